@@ -0,0 +1,133 @@
+//! `GET /admin/overview`: what the (future) admin UI shows on its front page,
+//! in one call.
+//!
+//! Activity covers the last `hours` (default 24, at most 30 days): runs by
+//! outcome and challenge submissions by status, the compile failure rate per
+//! language, the busiest submitters and the challenges whose submissions take
+//! longest to get a verdict. Alongside that, current table sizes and the
+//! queue: stream depths and, on NATS, each worker pool's consumer state.
+//! Workers don't register anywhere, so the consumers are the fleet view.
+
+use crate::auth::AdminUser;
+use crate::binary_transfer::TransferSnapshot;
+use crate::db::{self, BinaryStorage, ChallengeLatency, CompileFailureRate, StatusCount, TableSize, TopSubmitter};
+use crate::error::ApiError;
+use crate::queue::FleetStatus;
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const MAX_HOURS: i64 = 30 * 24;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct OverviewQuery {
+    #[serde(default = "default_hours")]
+    pub hours: i64,
+    /// Rows in the top submitters and slowest challenges lists
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_hours() -> i64 {
+    24
+}
+
+fn default_limit() -> i64 {
+    10
+}
+
+#[derive(Debug, Serialize)]
+pub struct Overview {
+    pub since: DateTime<Utc>,
+    pub jobs: JobCounts,
+    pub compile_failure_rates: Vec<CompileFailureRate>,
+    pub top_submitters: Vec<TopSubmitter>,
+    pub slowest_challenges: Vec<ChallengeLatency>,
+    pub storage: Vec<TableSize>,
+    pub binary_storage: BinaryStorage,
+    /// This replica's binary uploads and downloads since it started
+    pub binary_transfer: TransferSnapshot,
+    pub queue: QueueOverview,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobCounts {
+    /// `ok`, `error` or the limit that stopped the run
+    pub runs: Vec<StatusCount>,
+    pub challenge_submissions: Vec<StatusCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueOverview {
+    pub backend: Option<&'static str>,
+    pub healthy: bool,
+    pub queue_depth: Option<u64>,
+    pub compile_queue_depth: Option<u64>,
+    /// `None` on the local queue or while NATS is unreachable
+    pub fleet: Option<FleetStatus>,
+}
+
+pub async fn overview(
+    State(state): State<Arc<AppState>>,
+    AdminUser(_admin): AdminUser,
+    Query(query): Query<OverviewQuery>,
+) -> Result<Json<Overview>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let since = Utc::now() - Duration::hours(query.hours.clamp(1, MAX_HOURS));
+    let limit = query.limit.clamp(1, MAX_LIMIT);
+
+    let (runs, challenge_submissions, compile_failure_rates, top_submitters, slowest_challenges, storage, binary_storage) = tokio::try_join!(
+        db::count_run_outcomes(pool, since),
+        db::count_submission_statuses(pool, since),
+        db::compile_failure_rates(pool, since),
+        db::top_submitters(pool, since, limit),
+        db::slowest_challenges(pool, since, limit),
+        db::table_sizes(pool),
+        db::binary_storage(pool),
+    )?;
+
+    let queue = match state.queue.as_ref() {
+        Some(q) => {
+            let healthy = q.is_healthy();
+            QueueOverview {
+                backend: Some(q.backend()),
+                healthy,
+                queue_depth: q.get_queue_depth().await.ok(),
+                compile_queue_depth: q.get_compile_queue_depth().await.ok(),
+                fleet: if healthy { q.fleet_status().await.ok().flatten() } else { None },
+            }
+        }
+        None => QueueOverview {
+            backend: None,
+            healthy: false,
+            queue_depth: None,
+            compile_queue_depth: None,
+            fleet: None,
+        },
+    };
+
+    Ok(Json(Overview {
+        since,
+        jobs: JobCounts {
+            runs,
+            challenge_submissions,
+        },
+        compile_failure_rates,
+        top_submitters,
+        slowest_challenges,
+        storage,
+        binary_storage,
+        binary_transfer: state.binary_transfer.snapshot(),
+        queue,
+    }))
+}
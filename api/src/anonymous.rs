@@ -0,0 +1,143 @@
+//! Anonymous submission tokens for the public benchmark playground.
+//!
+//! `POST /tokens/anonymous` hands out a random token that is good for
+//! `ANONYMOUS_TOKEN_TTL_SECONDS`, `ANONYMOUS_TOKEN_MAX_SUBMISSIONS` runs and
+//! `ANONYMOUS_TOKEN_MAX_COMPILES` compiles. Issuing is throttled per client IP
+//! and, with `CAPTCHA_SECRET` set, needs a CAPTCHA token as the clanker
+//! verification does. Only the token's hash is stored.
+//!
+//! Callers send it as `X-Anonymous-Token`. With `ANONYMOUS_TOKEN_REQUIRED`
+//! set, `/submit` and `/compile` refuse callers that have neither a session
+//! nor a token; without it a token is optional but still counted when sent.
+
+use crate::audit;
+use crate::auth::{generate_session_token, hash_token};
+use crate::db::{self, AnonymousTokenUse, User};
+use crate::error::ApiError;
+use crate::stats;
+use crate::throttle;
+use crate::AppState;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub const TOKEN_HEADER: &str = "x-anonymous-token";
+
+/// What a token is spent on
+#[derive(Debug, Clone, Copy)]
+pub enum Usage {
+    Submit,
+    Compile,
+}
+
+impl Usage {
+    fn column(self) -> &'static str {
+        match self {
+            Usage::Submit => "submissions",
+            Usage::Compile => "compiles",
+        }
+    }
+
+    fn max(self, state: &AppState) -> u32 {
+        match self {
+            Usage::Submit => state.config.anonymous_token_max_submissions,
+            Usage::Compile => state.config.anonymous_token_max_compiles,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IssueTokenRequest {
+    /// Required when the server has `CAPTCHA_SECRET` set
+    #[serde(default)]
+    pub captcha_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssueTokenResponse {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+    pub max_submissions: u32,
+    pub max_compiles: u32,
+}
+
+pub async fn issue_token(
+    State(state): State<Arc<AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    request: Option<Json<IssueTokenRequest>>,
+) -> Result<Json<IssueTokenResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let ip = stats::client_ip(&state.config, &headers, peer.map(|ConnectInfo(addr)| addr));
+    state.anonymous_token_limiter.check(ip)?;
+    let Json(request) = request.unwrap_or_default();
+    throttle::verify_captcha(
+        &state.config,
+        pool,
+        None,
+        ip,
+        request.captcha_token.as_deref(),
+        "anonymous_token.captcha_failed",
+    )
+    .await?;
+
+    db::cleanup_expired_anonymous_tokens(pool).await?;
+    let token = generate_session_token();
+    let expires_at = Utc::now() + Duration::seconds(state.config.anonymous_token_ttl_seconds as i64);
+    db::create_anonymous_token(pool, &hash_token(&token), &ip.to_string(), expires_at).await?;
+    audit::record(pool, "anonymous_token.issued", None, Some(ip), json!({})).await;
+
+    Ok(Json(IssueTokenResponse {
+        token,
+        expires_at,
+        max_submissions: state.config.anonymous_token_max_submissions,
+        max_compiles: state.config.anonymous_token_max_compiles,
+    }))
+}
+
+/// Let a request through `/submit` or `/compile`: logged-in users always,
+/// others by spending their token (required with `ANONYMOUS_TOKEN_REQUIRED`)
+pub async fn admit(state: &AppState, user: Option<&User>, headers: &HeaderMap, usage: Usage) -> Result<(), ApiError> {
+    if user.is_some() {
+        return Ok(());
+    }
+    let token = headers
+        .get(TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+    let Some(token) = token else {
+        if state.config.anonymous_token_required {
+            return Err(ApiError::Unauthorized(
+                "Log in or send a token from POST /tokens/anonymous as X-Anonymous-Token".to_string(),
+            ));
+        }
+        return Ok(());
+    };
+
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+    let max = usage.max(state);
+    match db::use_anonymous_token(pool, &hash_token(token), usage.column(), max).await? {
+        AnonymousTokenUse::Counted => Ok(()),
+        AnonymousTokenUse::UsedUp(used) => Err(ApiError::AnonymousQuotaExceeded {
+            action: usage.column(),
+            used: used.max(0) as u64,
+            max: max as u64,
+        }),
+        AnonymousTokenUse::Invalid => Err(ApiError::Unauthorized("Anonymous token is unknown or expired".to_string())),
+    }
+}
@@ -0,0 +1,19 @@
+//! Append-only audit log (`audit_log` table) of security-relevant events.
+//!
+//! Events are named `<area>.<what>` (e.g. `clanker_verification.locked_out`)
+//! and carry the acting user, client IP and free-form JSON details. Writing
+//! an event never fails the request that triggered it.
+
+use crate::db;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use tracing::warn;
+use uuid::Uuid;
+
+pub async fn record(pool: &PgPool, event: &str, user_id: Option<&Uuid>, ip: Option<IpAddr>, details: Value) {
+    let ip = ip.map(|ip| ip.to_string());
+    if let Err(e) = db::insert_audit_event(pool, event, user_id, ip.as_deref(), &details).await {
+        warn!(event, error = %e, "Failed to write audit event");
+    }
+}
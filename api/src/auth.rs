@@ -1,9 +1,11 @@
 use crate::db::{self, PublicUser, User};
 use crate::error::ApiError;
+use crate::limits::InstructionLimits;
+use crate::{audit, countries, stats, throttle};
 use axum::{
     async_trait,
-    extract::{FromRequestParts, Query, State},
-    http::request::Parts,
+    extract::{ConnectInfo, FromRequestParts, Query, State},
+    http::{request::Parts, HeaderMap},
     response::Redirect,
     Json,
 };
@@ -12,6 +14,7 @@ use chrono::{Duration, Utc};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::info;
 
@@ -99,11 +102,30 @@ pub struct GitHubUser {
     pub followers: Option<i32>,
 }
 
-// ============ Auth Response Types ============
+const MAX_DISPLAY_NAME_CHARS: usize = 100;
+const MAX_BIO_CHARS: usize = 500;
+const MAX_HANDLE_CHARS: usize = 100;
+const MAX_AFFILIATION_CHARS: usize = 100;
+
+// ============ Auth Request/Response Types ============
+
+/// Fields to change; omitted ones stay as they are, and an empty `country`
+/// or `affiliation` clears it
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileRequest {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub twitter_handle: Option<String>,
+    /// ISO 3166-1 alpha-2, any case
+    pub country: Option<String>,
+    pub affiliation: Option<String>,
+}
 
 #[derive(Debug, Serialize)]
 pub struct AuthMeResponse {
     pub user: PublicUser,
+    /// Ceiling and default for this user's runs
+    pub instruction_limit: InstructionLimits,
 }
 
 #[derive(Debug, Serialize)]
@@ -144,10 +166,30 @@ impl FromRequestParts<Arc<crate::AppState>> for AuthenticatedUser {
             .await?
             .ok_or_else(|| ApiError::Unauthorized("User not found".to_string()))?;
 
+        // Tag the request's log lines (see request_id.rs)
+        tracing::Span::current().record("user_id", tracing::field::display(user.id));
+        state.api_calls.record(user.id);
+
         Ok(AuthenticatedUser(user))
     }
 }
 
+// Logged-in user listed in ADMIN_USERS
+pub struct AdminUser(pub User);
+
+#[async_trait]
+impl FromRequestParts<Arc<crate::AppState>> for AdminUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<crate::AppState>) -> Result<Self, Self::Rejection> {
+        let AuthenticatedUser(user) = AuthenticatedUser::from_request_parts(parts, state).await?;
+        if !state.config.admin_users.contains(&user.username) {
+            return Err(ApiError::Forbidden("Admin access required".to_string()));
+        }
+        Ok(AdminUser(user))
+    }
+}
+
 // Optional authenticated user (doesn't fail if not logged in)
 pub struct MaybeAuthenticatedUser(pub Option<User>);
 
@@ -295,13 +337,65 @@ pub async fn github_callback(
 }
 
 pub async fn auth_me(
+    State(state): State<Arc<crate::AppState>>,
     AuthenticatedUser(user): AuthenticatedUser,
 ) -> Json<AuthMeResponse> {
     Json(AuthMeResponse {
+        instruction_limit: InstructionLimits::for_user(&state.config, Some(&user)),
         user: user.into(),
     })
 }
 
+pub async fn update_profile(
+    State(state): State<Arc<crate::AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(req): Json<UpdateProfileRequest>,
+) -> Result<Json<AuthMeResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let trimmed = |value: &Option<String>, field: &str, max: usize| -> Result<Option<String>, ApiError> {
+        let value = value.as_deref().map(str::trim);
+        if value.is_some_and(|v| v.chars().count() > max) {
+            return Err(ApiError::InvalidField(format!("{} must be at most {} characters", field, max)));
+        }
+        Ok(value.map(str::to_string))
+    };
+    let display_name = trimmed(&req.display_name, "display_name", MAX_DISPLAY_NAME_CHARS)?;
+    let bio = trimmed(&req.bio, "bio", MAX_BIO_CHARS)?;
+    let twitter_handle = trimmed(&req.twitter_handle, "twitter_handle", MAX_HANDLE_CHARS)?;
+    let affiliation = trimmed(&req.affiliation, "affiliation", MAX_AFFILIATION_CHARS)?;
+    let country = match req.country.as_deref().map(str::trim) {
+        Some("") => Some(String::new()),
+        Some(code) => Some(countries::normalize(code).ok_or_else(|| {
+            ApiError::InvalidField(format!("'{}' is not an ISO 3166-1 alpha-2 country code", code))
+        })?),
+        None => None,
+    };
+
+    db::update_user_profile(
+        pool,
+        &user.id,
+        display_name.as_deref(),
+        bio.as_deref(),
+        twitter_handle.as_deref(),
+        country.as_deref(),
+        affiliation.as_deref(),
+    )
+    .await?;
+
+    let user = db::get_user_by_id(pool, &user.id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    Ok(Json(AuthMeResponse {
+        instruction_limit: InstructionLimits::for_user(&state.config, Some(&user)),
+        user: user.into(),
+    }))
+}
+
 pub async fn logout(
     State(state): State<Arc<crate::AppState>>,
     jar: CookieJar,
@@ -340,6 +434,8 @@ pub struct UserStats {
     pub challenges_completed: i64,
     pub total_entries: i64,
     pub first_places: i64,
+    /// Stars on the user's public runs
+    pub stars_received: i64,
     pub entries: Vec<db::LeaderboardEntry>,
 }
 
@@ -370,12 +466,15 @@ pub async fn get_user_profile(
     // TODO: Calculate first places by comparing with leaderboard
     let first_places = 0;
 
+    let stars_received = db::count_stars_received(pool, &user.id).await?;
+
     Ok(Json(UserProfileResponse {
         user: user.into(),
         stats: UserStats {
             challenges_completed,
             total_entries,
             first_places,
+            stars_received,
             entries,
         },
     }))
@@ -398,6 +497,8 @@ pub struct InitClankerVerificationResponse {
 pub async fn init_clanker_verification(
     State(state): State<Arc<crate::AppState>>,
     AuthenticatedUser(user): AuthenticatedUser,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(req): Json<InitClankerVerificationRequest>,
 ) -> Result<Json<InitClankerVerificationResponse>, ApiError> {
     let pool = state
@@ -405,6 +506,9 @@ pub async fn init_clanker_verification(
         .as_ref()
         .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
 
+    let ip = stats::client_ip(&state.config, &headers, peer.map(|ConnectInfo(addr)| addr));
+    throttle::guard(&state, pool, &user.id, ip, "init").await?;
+
     // Generate verification code
     let code = format!("ctf-clanker-{}", generate_session_token()[..12].to_string());
     let expires_at = Utc::now() + Duration::hours(1);
@@ -429,6 +533,8 @@ pub async fn init_clanker_verification(
         twitter = %req.twitter_handle,
         "Clanker verification initiated"
     );
+    let details = serde_json::json!({ "twitter_handle": req.twitter_handle });
+    audit::record(pool, "clanker_verification.initiated", Some(&user.id), Some(ip), details).await;
 
     Ok(Json(InitClankerVerificationResponse {
         code,
@@ -437,6 +543,15 @@ pub async fn init_clanker_verification(
     }))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CheckClankerVerificationRequest {
+    /// The code from the verification tweet
+    pub code: String,
+    /// Required when the server has `CAPTCHA_SECRET` set
+    #[serde(default)]
+    pub captcha_token: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct CheckClankerVerificationResponse {
     pub verified: bool,
@@ -447,17 +562,36 @@ pub struct CheckClankerVerificationResponse {
 pub async fn check_clanker_verification(
     State(state): State<Arc<crate::AppState>>,
     AuthenticatedUser(user): AuthenticatedUser,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<CheckClankerVerificationRequest>,
 ) -> Result<Json<CheckClankerVerificationResponse>, ApiError> {
     let pool = state
         .db
         .as_ref()
         .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
 
+    let ip = stats::client_ip(&state.config, &headers, peer.map(|ConnectInfo(addr)| addr));
+    throttle::guard(&state, pool, &user.id, ip, "check").await?;
+    throttle::verify_captcha(
+        &state.config,
+        pool,
+        Some(&user.id),
+        ip,
+        req.captcha_token.as_deref(),
+        "clanker_verification.captcha_failed",
+    )
+    .await?;
+
     // Get pending verification code
     let verification = db::get_verification_code(pool, &user.id)
         .await?
         .ok_or_else(|| ApiError::NotFound("No pending verification found. Please initiate verification first.".to_string()))?;
 
+    if req.code.trim() != verification.code {
+        return Err(throttle::wrong_code(&state.config, pool, &user.id, ip, &verification).await);
+    }
+
     // For now, we'll implement a simplified verification that doesn't actually check Twitter
     // In production, you'd use the Twitter API to search for the tweet
     // For demo purposes, we'll just mark it as verified if they've initiated the flow
@@ -468,6 +602,10 @@ pub async fn check_clanker_verification(
     // Update user type to clanker and verify
     db::set_user_type(pool, &user.id, "clanker", Some(&verification.twitter_handle)).await?;
     db::verify_user(pool, &user.id, "twitter_clanker").await?;
+    db::clear_verification_lockouts(pool, &user.id).await?;
+
+    let details = serde_json::json!({ "twitter_handle": verification.twitter_handle });
+    audit::record(pool, "clanker_verification.verified", Some(&user.id), Some(ip), details).await;
 
     info!(
         user_id = %user.id,
@@ -0,0 +1,179 @@
+//! SVG shields for READMEs: `GET /badge/:challenge_id/:username.svg` renders
+//! e.g. "portscan | 594 instr, rank #3", and
+//! `GET /badge/benchmarks/:benchmark_id/:language.svg` the fastest run of a
+//! benchmark in one language.
+//!
+//! Badges are small flat-style SVGs drawn here rather than through a shields
+//! service, sent with `Cache-Control` and an `ETag` so image proxies (GitHub's
+//! camo) and browsers can revalidate cheaply.
+
+use crate::db;
+use crate::error::ApiError;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+const COLOR_GOLD: &str = "#4c1";
+const COLOR_PODIUM: &str = "#97ca00";
+const COLOR_TOP_TEN: &str = "#dfb317";
+const COLOR_RANKED: &str = "#007ec6";
+const COLOR_NONE: &str = "#9f9f9f";
+
+const LABEL_COLOR: &str = "#555";
+/// Average advance of 11px Verdana, which is what shields.io badges use
+const CHAR_WIDTH: f64 = 6.5;
+const PADDING: f64 = 10.0;
+
+// ============ Rendering ============
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn text_width(text: &str) -> f64 {
+    (text.chars().count() as f64 * CHAR_WIDTH + PADDING).round()
+}
+
+/// Two-part flat badge: grey `label` on the left, `message` on `color`
+pub fn render(label: &str, message: &str, color: &str) -> String {
+    let (lw, mw) = (text_width(label), text_width(message));
+    let width = lw + mw;
+    let (label, message) = (escape(label), escape(message));
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}"><title>{label}: {message}</title><linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient><clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{lw}" height="20" fill="{LABEL_COLOR}"/><rect x="{lw}" width="{mw}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{lx}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{lx}" y="14">{label}</text><text x="{mx}" y="15" fill="#010101" fill-opacity=".3">{message}</text><text x="{mx}" y="14">{message}</text></g></svg>"##,
+        lx = lw / 2.0,
+        mx = lw + mw / 2.0,
+    )
+}
+
+/// 594, 12.3K, 4.5M, 1.2B
+pub fn format_instructions(n: i64) -> String {
+    let n = n as f64;
+    match n {
+        n if n < 10_000.0 => format!("{}", n),
+        n if n < 1e6 => format!("{:.1}K", n / 1e3),
+        n if n < 1e9 => format!("{:.1}M", n / 1e6),
+        n => format!("{:.1}B", n / 1e9),
+    }
+}
+
+fn rank_color(rank: i64) -> &'static str {
+    match rank {
+        1 => COLOR_GOLD,
+        2..=3 => COLOR_PODIUM,
+        4..=10 => COLOR_TOP_TEN,
+        _ => COLOR_RANKED,
+    }
+}
+
+/// `{name}.svg` path segment
+fn strip_svg(file: &str) -> Result<&str, ApiError> {
+    file.strip_suffix(".svg")
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| ApiError::NotFound(format!("badge {}", file)))
+}
+
+fn svg_response(headers: &HeaderMap, svg: String) -> Response {
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(svg.as_bytes())[..8]));
+    let cache = [
+        (header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL)),
+        (header::ETAG, HeaderValue::from_str(&etag).expect("hex etag is a valid header")),
+    ];
+
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag))
+    {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
+    }
+
+    (
+        cache,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("image/svg+xml; charset=utf-8"))],
+        svg,
+    )
+        .into_response()
+}
+
+// ============ Handlers ============
+
+#[derive(Debug, Deserialize)]
+pub struct BadgeQuery {
+    /// Only consider this language's leaderboard
+    pub language: Option<String>,
+}
+
+pub async fn challenge_badge(
+    State(state): State<Arc<crate::AppState>>,
+    Path((challenge_id, file)): Path<(String, String)>,
+    Query(query): Query<BadgeQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let username = strip_svg(&file)?;
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+    let user = db::get_user_by_username(pool, username)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("user {}", username)))?;
+
+    let label = match &query.language {
+        Some(language) => format!("{} ({})", challenge_id, language),
+        None => challenge_id.clone(),
+    };
+    let svg = match db::get_user_challenge_rank(pool, &challenge_id, &user.id, query.language.as_deref()).await? {
+        Some((_, instructions, rank)) => render(
+            &label,
+            &format!("{} instr, rank #{}", format_instructions(instructions), rank),
+            rank_color(rank),
+        ),
+        None => render(&label, "unsolved", COLOR_NONE),
+    };
+
+    Ok(svg_response(&headers, svg))
+}
+
+/// Fastest run of a benchmark in one language
+pub async fn benchmark_badge(
+    State(state): State<Arc<crate::AppState>>,
+    Path((benchmark_id, file)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let language = strip_svg(&file)?;
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let version = crate::current_benchmark_version(&benchmark_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Benchmark '{}' not found", benchmark_id)))?;
+    let instructions = db::get_min_instructions(pool, &benchmark_id, version)
+        .await?
+        .remove(language)
+        .ok_or_else(|| ApiError::NotFound(format!("no {} runs of benchmark {}", language, benchmark_id)))?;
+
+    let svg = render(
+        &format!("{} ({})", benchmark_id, language),
+        &format!("{} instr", format_instructions(instructions)),
+        COLOR_RANKED,
+    );
+
+    Ok(svg_response(&headers, svg))
+}
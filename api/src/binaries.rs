@@ -0,0 +1,83 @@
+//! Listing stored binaries: `GET /binaries`.
+//!
+//! Metadata only, never payloads, newest first with `limit`/`offset`. Filters
+//! are `language`, `since` (RFC 3339) and `user`. A binary belongs to every
+//! logged-in user whose compile job produced it (cache hits included), as
+//! recorded when the job is settled against the compile quota; anonymous
+//! builds belong to nobody.
+//!
+//! Users only see their own binaries (`user` left out, `me` or their own
+//! username). Admins see everything by default and can pick any user.
+
+use crate::auth::AuthenticatedUser;
+use crate::db::{self, BinarySummary};
+use crate::error::ApiError;
+use crate::queue::Language;
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct ListBinariesQuery {
+    pub language: Option<String>,
+    /// `me`, or a username (admins only for anyone else)
+    pub user: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+pub async fn list_binaries(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<ListBinariesQuery>,
+) -> Result<Json<Vec<BinarySummary>>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let is_admin = state.config.admin_users.contains(&user.username);
+    let owner = match query.user.as_deref().map(str::trim) {
+        None | Some("") if is_admin => None,
+        None | Some("") | Some("me") => Some(user.id),
+        Some(username) if username == user.username => Some(user.id),
+        Some(username) if is_admin => {
+            let owner = db::get_user_by_username(pool, username)
+                .await?
+                .ok_or_else(|| ApiError::NotFound(format!("User '{}' not found", username)))?;
+            Some(owner.id)
+        }
+        Some(_) => return Err(ApiError::Forbidden("Only admins can list other users' binaries".to_string())),
+    };
+
+    let language = match query.language.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+        Some(name) => Some(Language::from_str(name).ok_or_else(|| ApiError::InvalidLanguage(name.to_string()))?),
+        None => None,
+    };
+
+    let binaries = db::list_binaries(
+        pool,
+        language.map(|l| l.as_str()),
+        owner.as_ref(),
+        query.since,
+        query.limit.clamp(1, MAX_LIMIT),
+        query.offset.max(0),
+    )
+    .await?;
+
+    Ok(Json(binaries))
+}
@@ -0,0 +1,179 @@
+//! zstd on `PUT`/`GET /binaries/:id`, and optionally at rest.
+//!
+//! Workers upload with `Content-Encoding: zstd` (the decompressed size is
+//! held to `MAX_BINARY_SIZE`) and fetch with `Accept-Encoding: zstd`; other
+//! clients send and get raw bytes. With `BINARY_COMPRESSION_AT_REST=true`,
+//! binaries stored from then on are kept compressed in Postgres: a
+//! compressed upload is stored as sent and served as stored. Binaries that
+//! don't shrink, or are under 4 KiB, stay raw, and so do run output files.
+//! `db::get_binary` decompresses, so the rest of the API never sees the
+//! encoding.
+//!
+//! Bytes in and out are counted per replica since it started and show up in
+//! `GET /admin/overview` under `binary_transfer`.
+
+use crate::config::Config;
+use crate::db::{self, BinaryMetadata, StoredBinary};
+use crate::error::ApiError;
+use axum::http::{header, HeaderMap};
+use ctf_common::binary_encoding::{self, ZSTD};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Transfers in one direction
+#[derive(Default)]
+struct Counters {
+    transfers: AtomicU64,
+    compressed: AtomicU64,
+    raw_bytes: AtomicU64,
+    wire_bytes: AtomicU64,
+}
+
+impl Counters {
+    fn record(&self, raw: usize, wire: usize, compressed: bool) {
+        self.transfers.fetch_add(1, Ordering::Relaxed);
+        if compressed {
+            self.compressed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.raw_bytes.fetch_add(raw as u64, Ordering::Relaxed);
+        self.wire_bytes.fetch_add(wire as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> DirectionStats {
+        let raw_bytes = self.raw_bytes.load(Ordering::Relaxed);
+        let wire_bytes = self.wire_bytes.load(Ordering::Relaxed);
+        DirectionStats {
+            transfers: self.transfers.load(Ordering::Relaxed),
+            compressed: self.compressed.load(Ordering::Relaxed),
+            raw_bytes,
+            wire_bytes,
+            saved_bytes: raw_bytes.saturating_sub(wire_bytes),
+        }
+    }
+}
+
+/// Binary uploads and downloads since this replica started
+#[derive(Default)]
+pub struct TransferStats {
+    uploads: Counters,
+    downloads: Counters,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectionStats {
+    pub transfers: u64,
+    /// Of those, how many went over the wire zstd-compressed
+    pub compressed: u64,
+    /// Binary sizes
+    pub raw_bytes: u64,
+    /// Bodies as sent
+    pub wire_bytes: u64,
+    pub saved_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferSnapshot {
+    pub uploads: DirectionStats,
+    pub downloads: DirectionStats,
+}
+
+impl TransferStats {
+    pub fn snapshot(&self) -> TransferSnapshot {
+        TransferSnapshot {
+            uploads: self.uploads.snapshot(),
+            downloads: self.downloads.snapshot(),
+        }
+    }
+}
+
+/// An uploaded binary: its bytes, and the zstd body it came in, if any
+pub struct Upload {
+    pub data: Vec<u8>,
+    pub compressed: Option<Vec<u8>>,
+}
+
+/// Undo the upload's `Content-Encoding` and count it
+pub fn decode_upload(config: &Config, stats: &TransferStats, headers: &HeaderMap, body: &[u8]) -> Result<Upload, ApiError> {
+    let encoding = headers
+        .get(header::CONTENT_ENCODING)
+        .map(|v| v.to_str().unwrap_or("").trim().to_ascii_lowercase());
+
+    let upload = match encoding.as_deref() {
+        None | Some("" | "identity") => Upload {
+            data: body.to_vec(),
+            compressed: None,
+        },
+        Some(ZSTD) => {
+            let data = binary_encoding::decompress(body, config.max_binary_size).map_err(|e| {
+                ApiError::InvalidField(format!("zstd body doesn't decompress within MAX_BINARY_SIZE: {}", e))
+            })?;
+            Upload {
+                data,
+                compressed: Some(body.to_vec()),
+            }
+        }
+        Some(other) => {
+            return Err(ApiError::InvalidField(format!(
+                "Content-Encoding must be zstd or identity, got {}",
+                other
+            )))
+        }
+    };
+
+    stats.uploads.record(upload.data.len(), body.len(), upload.compressed.is_some());
+    Ok(upload)
+}
+
+/// Store a binary, compressed at rest when configured; `compressed` is the
+/// zstd body it was uploaded in, reused rather than compressing again
+pub async fn store(
+    pool: &PgPool,
+    config: &Config,
+    binary_id: &str,
+    data: &[u8],
+    compressed: Option<&[u8]>,
+    metadata: Option<&BinaryMetadata>,
+) -> Result<(), ApiError> {
+    if config.binary_compression_at_rest {
+        let recompressed;
+        let compressed = match compressed.filter(|c| c.len() < data.len()) {
+            Some(compressed) => Some(compressed),
+            None => {
+                recompressed = binary_encoding::compress(data, config.binary_zstd_level);
+                recompressed.as_deref()
+            }
+        };
+        if let Some(compressed) = compressed {
+            return db::store_encoded_binary(pool, binary_id, compressed, data.len(), Some(ZSTD), metadata).await;
+        }
+    }
+    db::store_binary(pool, binary_id, data, metadata).await
+}
+
+/// The body to answer a fetch with and whether it's zstd, counting it
+pub fn encode_download(
+    config: &Config,
+    stats: &TransferStats,
+    headers: &HeaderMap,
+    stored: StoredBinary,
+) -> Result<(Vec<u8>, bool), ApiError> {
+    let wants_zstd = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(binary_encoding::accepts_zstd);
+    let raw_size = stored.size as usize;
+
+    let (body, compressed) = match (stored.encoding.as_deref(), wants_zstd) {
+        (Some(ZSTD), true) => (stored.data, true),
+        (Some(ZSTD), false) => (stored.decompress()?, false),
+        (_, true) => match binary_encoding::compress(&stored.data, config.binary_zstd_level) {
+            Some(compressed) => (compressed, true),
+            None => (stored.data, false),
+        },
+        (_, false) => (stored.data, false),
+    };
+
+    stats.downloads.record(raw_size, body.len(), compressed);
+    Ok((body, compressed))
+}
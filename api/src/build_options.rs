@@ -0,0 +1,157 @@
+//! Structured build options: control over linking for the languages that
+//! link with binutils `ld` (asm directly, C and C++ through `-Wl,`).
+//!
+//! Linker flags come from an allowlist (entry point, section placement and
+//! merging, stripping, `-z` keywords), one argument per entry with the value
+//! attached (`--entry=_start`, `-znoexecstack`). A custom linker script goes
+//! in its own field and is passed as `-T`; scripts can't pull in other files
+//! (`INCLUDE`, `INPUT`, `GROUP`, `SEARCH_DIR`, `STARTUP`) or rename the output.
+//!
+//! The compile container gets them as `LINKER_FLAGS` (space separated) and
+//! `LINKER_SCRIPT` (a path under `/work`).
+
+use crate::error::ApiError;
+use crate::queue::Language;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_LINKER_FLAGS: usize = 16;
+pub const MAX_LINKER_SCRIPT_BYTES: usize = 16 * 1024;
+
+/// Flags taken as-is
+const EXACT_FLAGS: &[&str] = &[
+    "-N",
+    "--omagic",
+    "-n",
+    "--nmagic",
+    "-s",
+    "--strip-all",
+    "-S",
+    "--strip-debug",
+    "--gc-sections",
+    "--no-gc-sections",
+    "--build-id=none",
+    "--no-dynamic-linker",
+    "--relax",
+    "--no-relax",
+    "--no-warn-rwx-segments",
+    "--no-warn-execstack",
+];
+
+/// `-z` keywords, with or without a numeric value
+const Z_KEYWORDS: &[&str] = &[
+    "noexecstack",
+    "execstack",
+    "norelro",
+    "relro",
+    "separate-code",
+    "noseparate-code",
+    "max-page-size=",
+    "common-page-size=",
+];
+
+/// Linker script commands that read other files or redirect the output
+const FORBIDDEN_SCRIPT_COMMANDS: &[&str] = &["INCLUDE", "INPUT", "GROUP", "SEARCH_DIR", "STARTUP", "OUTPUT"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linker_flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linker_script: Option<String>,
+}
+
+impl BuildOptions {
+    pub fn is_empty(&self) -> bool {
+        self.linker_flags.is_empty() && self.linker_script.is_none()
+    }
+
+    /// Reject options the language can't honor and anything off the allowlist
+    pub fn validate(&self, language: Language) -> Result<(), ApiError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if !matches!(language, Language::Asm | Language::C | Language::Cpp) {
+            return Err(ApiError::InvalidField(format!(
+                "build options are only supported for asm, c and cpp, not {}",
+                language.as_str()
+            )));
+        }
+
+        if self.linker_flags.len() > MAX_LINKER_FLAGS {
+            return Err(ApiError::LimitExceeded {
+                limit: "linker_flags",
+                value: self.linker_flags.len() as u64,
+                max: MAX_LINKER_FLAGS as u64,
+            });
+        }
+        if let Some(flag) = self.linker_flags.iter().find(|f| !linker_flag_allowed(f)) {
+            return Err(ApiError::InvalidField(format!("linker flag '{}' is not allowed", flag)));
+        }
+
+        if let Some(script) = &self.linker_script {
+            check_linker_script(script)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_symbol(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+}
+
+fn is_address(s: &str) -> bool {
+    match s.strip_prefix("0x") {
+        Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+fn linker_flag_allowed(flag: &str) -> bool {
+    if EXACT_FLAGS.contains(&flag) {
+        return true;
+    }
+    if let Some(symbol) = flag.strip_prefix("--entry=").or_else(|| flag.strip_prefix("-e")) {
+        return is_symbol(symbol);
+    }
+    if let Some(address) = ["-Ttext=", "-Tdata=", "-Tbss=", "--image-base="]
+        .iter()
+        .find_map(|prefix| flag.strip_prefix(prefix))
+    {
+        return is_address(address);
+    }
+    if let Some(placement) = flag.strip_prefix("--section-start=") {
+        return placement
+            .split_once('=')
+            .is_some_and(|(section, address)| is_symbol(section) && is_address(address));
+    }
+    if let Some(keyword) = flag.strip_prefix("-z") {
+        return Z_KEYWORDS.iter().any(|allowed| match allowed.strip_suffix('=') {
+            Some(name) => keyword
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+                .is_some_and(is_address),
+            None => keyword == *allowed,
+        });
+    }
+    false
+}
+
+fn check_linker_script(script: &str) -> Result<(), ApiError> {
+    if script.len() > MAX_LINKER_SCRIPT_BYTES {
+        return Err(ApiError::LimitExceeded {
+            limit: "linker_script_bytes",
+            value: script.len() as u64,
+            max: MAX_LINKER_SCRIPT_BYTES as u64,
+        });
+    }
+    if script.trim().is_empty() {
+        return Err(ApiError::InvalidField("linker_script is empty".to_string()));
+    }
+    let forbidden = script
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .find(|word| FORBIDDEN_SCRIPT_COMMANDS.contains(word));
+    if let Some(command) = forbidden {
+        return Err(ApiError::InvalidField(format!("linker scripts can't use {}", command)));
+    }
+    Ok(())
+}
@@ -1,13 +1,32 @@
-use crate::auth::AuthenticatedUser;
-use crate::db::{self, Challenge, TestCase, VerifyMode};
+use crate::auth::{AdminUser, AuthenticatedUser, MaybeAuthenticatedUser};
+use crate::config::Config;
+use crate::countries;
+use crate::db::{
+    self, Challenge, ChallengeHint, ChallengeScoring, LanguageRestrictions, RunVisibility, TestCase, VerifyMode,
+};
 use crate::error::ApiError;
-use crate::queue::{CompileJob, CompileStatus, Job, JobStatus, Language, Optimization, QueueClient};
+use crate::events::{self, LeaderboardImproved, Record};
+use crate::form;
+use crate::limits;
+use crate::orgs;
+use crate::preview::{self, Mismatch, OutputDiff, Whitespace};
+use crate::provenance::{self, ChallengeSigner};
+use crate::secrets::EnvVarCipher;
+use crate::notifications::{self, Mailer, NotificationKind};
+use ctf_common::binary_checks::BinaryChecks;
+use ctf_common::diagnostics::ExecutionMode;
+use ctf_common::fixtures::{FixtureFile, FixtureSpec};
+use ctf_common::sandbox_options::SandboxOptions;
+use crate::queue::{CompileJob, Job, Language, Optimization, QueueClient};
 use axum::{
-    extract::{Multipart, Path, Query, State},
+    extract::{Path, Query, State},
     Json,
 };
-use chrono::Utc;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::{stream, StreamExt, TryStreamExt};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -15,6 +34,12 @@ use std::time::Duration;
 use tracing::{info, warn};
 use uuid::Uuid;
 
+/// Unfinished submissions older than this were abandoned (e.g. by a restart)
+/// and don't count as queued
+const QUEUE_STALE_AFTER_MINUTES: i64 = 30;
+/// Window of finished submissions the average processing time is taken over
+const PROCESSING_TIME_WINDOW_HOURS: i64 = 24;
+
 // ============ Response Types ============
 
 #[derive(Debug, Serialize)]
@@ -30,6 +55,13 @@ pub struct ChallengeInfo {
     pub category: String,
     pub difficulty: String,
     pub is_active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<i32>,
+    pub star_count: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime<Utc>>,
 }
 
 impl From<Challenge> for ChallengeInfo {
@@ -41,6 +73,10 @@ impl From<Challenge> for ChallengeInfo {
             category: c.category,
             difficulty: c.difficulty,
             is_active: c.is_active,
+            points: c.points,
+            star_count: c.star_count,
+            starts_at: c.starts_at,
+            ends_at: c.ends_at,
         }
     }
 }
@@ -57,6 +93,34 @@ pub struct ChallengeDetailResponse {
     pub test_cases: Vec<PublicTestCase>,
     pub verify_mode: String,
     pub baselines: Option<Vec<ChallengeBaseline>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary_checks: Option<BinaryChecks>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub languages: Option<LanguageRestrictions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scoring: Option<ChallengeScoring>,
+    pub solve_count: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<i32>,
+    pub star_count: i32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<PublicHint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Every test case is judged even after one fails
+    pub run_all_tests: bool,
+}
+
+/// A hint as shown to one user: the text only once they've unlocked it
+#[derive(Debug, Serialize)]
+pub struct PublicHint {
+    pub index: usize,
+    pub cost: i32,
+    pub unlocked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +137,11 @@ pub struct ChallengeBaseline {
 pub struct PublicTestCase {
     pub description: Option<String>,
     pub stdin: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Files to write to `/work/out`; their expected content is hidden too
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
     // expected_stdout is hidden to prevent cheating
 }
 
@@ -80,33 +149,92 @@ pub struct PublicTestCase {
 pub struct SubmitResponse {
     pub submission_id: Uuid,
     pub status: String,
+    pub ranked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmissionStatusQuery {
+    /// How failed results' diffs show whitespace
+    #[serde(default)]
+    pub whitespace: Whitespace,
 }
 
 #[derive(Debug, Serialize)]
 pub struct SubmissionStatusResponse {
     pub submission_id: Uuid,
     pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_of: Option<Uuid>,
+    /// Practice submissions (false) never count toward solves or leaderboards
+    pub ranked: bool,
     pub test_results: Option<Vec<TestResult>>,
     pub instructions: Option<i64>,
     pub error_message: Option<String>,
     pub completed_at: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct ChallengeQueueResponse {
+    pub challenge_id: String,
+    /// Submissions to the challenge still compiling or running
+    pub in_flight: usize,
+    /// In-flight submissions ahead of the caller's oldest one (all of them
+    /// if the caller has none)
+    pub ahead: usize,
+    /// Average time from submission to result over the last day
+    pub avg_processing_ms: Option<i64>,
+    /// Finished submissions that average is over
+    pub processed_last_day: i64,
+    /// The caller's own in-flight submissions, oldest first
+    pub mine: Vec<QueuedSubmission>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedSubmission {
+    pub submission_id: Uuid,
+    pub status: String,
+    /// In-flight submissions to the challenge created before this one
+    pub position: usize,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestResult {
     pub test_index: usize,
     pub passed: bool,
-    pub expected_preview: Option<String>, // First 50 chars of expected output
-    pub actual_preview: Option<String>,   // First 50 chars of actual output
+    pub expected_preview: Option<String>, // See preview.rs
+    pub actual_preview: Option<String>,
+    /// Where stdout first departs from the expected output, when it does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_mismatch: Option<Mismatch>,
+    /// Lines around the first mismatch, stored raw (see preview::Whitespace)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<OutputDiff>,
     pub error: Option<String>,
+    /// Not judged because an earlier test case failed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    /// Reused from an earlier submission of the same binary, not re-run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
 }
 
 // ============ Query Types ============
 
+#[derive(Debug, Deserialize)]
+pub struct ChallengeWindowRequest {
+    #[serde(default)]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LeaderboardQuery {
     pub language: Option<String>,
     pub user_type: Option<String>,
+    /// ISO 3166-1 alpha-2, any case
+    pub country: Option<String>,
     #[serde(default = "default_limit")]
     pub limit: i64,
 }
@@ -115,35 +243,106 @@ fn default_limit() -> i64 {
     100
 }
 
+/// A `country` filter as stored, or 400 for anything but an ISO code
+fn country_filter(country: Option<&str>) -> Result<Option<String>, ApiError> {
+    country
+        .map(|code| {
+            countries::normalize(code)
+                .ok_or_else(|| ApiError::InvalidField(format!("'{}' is not an ISO 3166-1 alpha-2 country code", code)))
+        })
+        .transpose()
+}
+
 // ============ Handlers ============
 
 pub async fn list_challenges(
     State(state): State<Arc<crate::AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
 ) -> Result<Json<ChallengeListResponse>, ApiError> {
     let pool = state
         .db
         .as_ref()
         .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
 
+    let is_admin = is_admin(&state, user.as_ref());
+    let now = Utc::now();
     let challenges = db::list_challenges(pool, true).await?;
 
     Ok(Json(ChallengeListResponse {
-        challenges: challenges.into_iter().map(|c| c.into()).collect(),
+        challenges: challenges
+            .into_iter()
+            .filter(|c| is_admin || c.has_opened(now))
+            .map(|c| c.into())
+            .collect(),
     }))
 }
 
+fn is_admin(state: &crate::AppState, user: Option<&db::User>) -> bool {
+    user.is_some_and(|u| state.config.admin_users.contains(&u.username))
+}
+
+/// The challenge, unless it hasn't opened yet and `user` isn't an admin
+async fn get_visible_challenge(
+    state: &crate::AppState,
+    pool: &PgPool,
+    challenge_id: &str,
+    user: Option<&db::User>,
+) -> Result<Challenge, ApiError> {
+    db::get_challenge(pool, challenge_id)
+        .await?
+        .filter(|c| c.has_opened(Utc::now()) || is_admin(state, user))
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.to_string()))
+}
+
+/// Submissions (and retries) are only taken while the challenge is open
+fn check_window(challenge: &Challenge) -> Result<(), ApiError> {
+    let now = Utc::now();
+    if let Some(starts_at) = challenge.starts_at.filter(|_| !challenge.has_opened(now)) {
+        return Err(ApiError::Forbidden(format!(
+            "challenge {} opens at {}",
+            challenge.id,
+            starts_at.to_rfc3339()
+        )));
+    }
+    if let Some(ends_at) = challenge.ends_at.filter(|_| challenge.has_closed(now)) {
+        return Err(ApiError::Forbidden(format!(
+            "challenge {} closed at {}",
+            challenge.id,
+            ends_at.to_rfc3339()
+        )));
+    }
+    Ok(())
+}
+
 pub async fn get_challenge(
     State(state): State<Arc<crate::AppState>>,
     Path(challenge_id): Path<String>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
 ) -> Result<Json<ChallengeDetailResponse>, ApiError> {
     let pool = state
         .db
         .as_ref()
         .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
 
-    let challenge = db::get_challenge(pool, &challenge_id)
-        .await?
-        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id))?;
+    let challenge = get_visible_challenge(&state, pool, &challenge_id, user.as_ref()).await?;
+
+    let unlocked = match &user {
+        Some(user) => db::get_unlocked_hints(pool, &challenge.id, &user.id).await?,
+        None => Vec::new(),
+    };
+    let hints = challenge_hints(&challenge)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, hint)| {
+            let is_unlocked = unlocked.contains(&(index as i32));
+            PublicHint {
+                index,
+                cost: hint.cost,
+                unlocked: is_unlocked,
+                text: is_unlocked.then_some(hint.text),
+            }
+        })
+        .collect();
 
     // Parse test cases but hide expected output
     let test_cases: Vec<TestCase> = serde_json::from_value(challenge.test_cases.clone())
@@ -154,11 +353,28 @@ pub async fn get_challenge(
         .map(|tc| PublicTestCase {
             description: tc.description,
             stdin: tc.stdin,
+            args: tc.args,
+            outputs: tc.expected_files.into_keys().collect(),
         })
         .collect();
 
-    // Parse baselines
+    let languages = language_restrictions(&challenge)?;
+
+    // Parse baselines, keeping only languages the challenge accepts
     let baselines: Option<Vec<ChallengeBaseline>> = challenge.baselines
+        .and_then(|v| serde_json::from_value::<Vec<ChallengeBaseline>>(v).ok())
+        .map(|baselines| {
+            baselines
+                .into_iter()
+                .filter(|b| match (&languages, Language::from_str(&b.language)) {
+                    (Some(restrictions), Some(language)) => restrictions.permits(language),
+                    _ => true,
+                })
+                .collect()
+        });
+    let binary_checks: Option<BinaryChecks> = challenge.binary_checks
+        .and_then(|v| serde_json::from_value(v).ok());
+    let scoring: Option<ChallengeScoring> = challenge.scoring
         .and_then(|v| serde_json::from_value(v).ok());
 
     Ok(Json(ChallengeDetailResponse {
@@ -172,14 +388,120 @@ pub async fn get_challenge(
         test_cases: public_test_cases,
         verify_mode: challenge.verify_mode,
         baselines,
+        binary_checks,
+        languages,
+        scoring,
+        solve_count: challenge.solve_count,
+        points: challenge.points,
+        star_count: challenge.star_count,
+        hints,
+        starts_at: challenge.starts_at,
+        ends_at: challenge.ends_at,
+        run_all_tests: challenge.run_all_tests,
+    }))
+}
+
+/// Set or clear a challenge's availability window (admins only)
+pub async fn set_challenge_window(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    AdminUser(admin): AdminUser,
+    Json(req): Json<ChallengeWindowRequest>,
+) -> Result<Json<ChallengeInfo>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    if let (Some(starts_at), Some(ends_at)) = (req.starts_at, req.ends_at) {
+        if ends_at <= starts_at {
+            return Err(ApiError::InvalidField("ends_at must be after starts_at".to_string()));
+        }
+    }
+    // Re-signed below, so only from content that still checks out
+    let current = db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+    provenance::check_challenge(&state, pool, &current).await?;
+    if !db::set_challenge_window(pool, &challenge_id, req.starts_at, req.ends_at).await? {
+        return Err(ApiError::ChallengeNotFound(challenge_id));
+    }
+
+    info!(
+        challenge_id = %challenge_id,
+        starts_at = ?req.starts_at,
+        ends_at = ?req.ends_at,
+        admin = %admin.username,
+        "Challenge window set"
+    );
+
+    let challenge = db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id))?;
+    if state.challenge_signer.is_enabled() {
+        provenance::record_import(pool, &state.challenge_signer, &state.env_cipher, &challenge, &admin.username)
+            .await?;
+    }
+    Ok(Json(challenge.into()))
+}
+
+/// Reveal hint `n` (0-based) to the caller, recording its cost against their
+/// score. Hints unlock in order; unlocking one again costs nothing.
+pub async fn unlock_hint(
+    State(state): State<Arc<crate::AppState>>,
+    Path((challenge_id, index)): Path<(String, usize)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<PublicHint>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let challenge = get_visible_challenge(&state, pool, &challenge_id, Some(&user)).await?;
+
+    let hint = challenge_hints(&challenge)?
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| ApiError::NotFound(format!("hint {} of challenge {}", index, challenge_id)))?;
+
+    let unlocked = db::get_unlocked_hints(pool, &challenge_id, &user.id).await?;
+    if index > 0 && !unlocked.contains(&(index as i32 - 1)) {
+        return Err(ApiError::InvalidField(format!("unlock hint {} first", index - 1)));
+    }
+
+    if !unlocked.contains(&(index as i32)) {
+        db::unlock_hint(pool, &challenge_id, &user.id, index as i32, hint.cost).await?;
+        info!(
+            user_id = %user.id,
+            challenge_id = %challenge_id,
+            hint = index,
+            cost = hint.cost,
+            "Hint unlocked"
+        );
+    }
+
+    Ok(Json(PublicHint {
+        index,
+        cost: hint.cost,
+        unlocked: true,
+        text: Some(hint.text),
     }))
 }
 
+fn challenge_hints(challenge: &Challenge) -> Result<Vec<ChallengeHint>, ApiError> {
+    challenge.hints
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .map_err(|e| ApiError::Internal(format!("Invalid challenge hints: {}", e)))
+}
+
 pub async fn submit_challenge(
     State(state): State<Arc<crate::AppState>>,
     Path(challenge_id): Path<String>,
     AuthenticatedUser(user): AuthenticatedUser,
-    mut multipart: Multipart,
+    body: form::Body,
 ) -> Result<Json<SubmitResponse>, ApiError> {
     let pool = state
         .db
@@ -195,65 +517,412 @@ pub async fn submit_challenge(
     let challenge = db::get_challenge(pool, &challenge_id)
         .await?
         .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+    check_window(&challenge)?;
+    provenance::check_challenge(&state, pool, &challenge).await?;
+    let challenge = open_env_vars(&state.env_cipher, challenge)?;
+
+    let mut form = form::Schema::new()
+        .text("source_code")
+        .required()
+        .max_size(state.config.max_source_size, |size, max| ApiError::SourceTooLarge { size, max })
+        .text("language")
+        .required()
+        .text("optimization")
+        .text("binary_id")
+        .text("source_sha256")
+        .text("ranked")
+        .read(body)
+        .await?;
+
+    let source_code = form.required_text("source_code")?;
+    let language = form.required_text("language")?;
+    let optimization = form.text("optimization");
+    let binary_id = form.text("binary_id");
+    let source_sha256 = form.text("source_sha256");
+    let ranked = form.flag("ranked")?.unwrap_or(true);
+    check_language_allowed(&challenge, &language)?;
+    check_org_policy(pool, &user, &language, &source_code, optimization.as_deref()).await?;
+    if let Some(parsed) = Language::from_str(&language) {
+        limits::check_source(&state.config, parsed, &source_code)?;
+    }
+    if ranked {
+        check_ranked_cooldown(&state.config, pool, &user, &challenge).await?;
+    }
 
-    // Parse multipart form
-    let mut source_code: Option<String> = None;
-    let mut language: Option<String> = None;
-    let mut optimization: Option<String> = None;
+    let prebuilt = match (binary_id, source_sha256) {
+        (Some(binary_id), Some(source_sha256)) => Some(
+            verify_prebuilt(
+                pool,
+                &user,
+                binary_id.trim(),
+                source_sha256.trim(),
+                &source_code,
+                &language,
+                optimization.as_deref(),
+            )
+            .await?,
+        ),
+        (Some(_), None) => return Err(ApiError::MissingField("source_sha256")),
+        (None, Some(_)) => return Err(ApiError::MissingField("binary_id")),
+        (None, None) => None,
+    };
+    if prebuilt.is_none() {
+        limits::check_compile_quota(&state.config, pool, queue, &user.id).await?;
+    }
 
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|e| ApiError::Internal(e.to_string()))?
-    {
-        let name = field.name().unwrap_or("").to_string();
-        match name.as_str() {
-            "source_code" => {
-                source_code = Some(
-                    field
-                        .text()
-                        .await
-                        .map_err(|e| ApiError::Internal(e.to_string()))?,
-                );
-            }
-            "language" => {
-                language = Some(
-                    field
-                        .text()
-                        .await
-                        .map_err(|e| ApiError::Internal(e.to_string()))?,
-                );
-            }
-            "optimization" => {
-                optimization = Some(
-                    field
-                        .text()
-                        .await
-                        .map_err(|e| ApiError::Internal(e.to_string()))?,
-                );
-            }
-            _ => {}
+    let submission_id = start_submission(
+        pool,
+        queue,
+        state.mailer.as_ref(),
+        &challenge,
+        &user,
+        &language,
+        &source_code,
+        optimization.as_deref(),
+        prebuilt.as_deref(),
+        None,
+        ranked,
+        JudgeOptions::from_config(&state.config),
+    )
+    .await?;
+
+    Ok(Json(SubmitResponse {
+        submission_id,
+        status: "pending".to_string(),
+        ranked,
+    }))
+}
+
+/// Re-run a previous submission's source against the challenge's current
+/// test cases, e.g. after a sandbox fix or a flaky failure. The new
+/// submission links back to the original via `retry_of`.
+pub async fn retry_submission(
+    State(state): State<Arc<crate::AppState>>,
+    Path((challenge_id, submission_id)): Path<(String, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<SubmitResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let original = db::get_challenge_submission(pool, &submission_id)
+        .await?
+        .ok_or_else(|| ApiError::SubmissionNotFound(submission_id.to_string()))?;
+
+    if original.user_id != user.id {
+        return Err(ApiError::Forbidden("You don't own this submission".to_string()));
+    }
+    if original.challenge_id != challenge_id {
+        return Err(ApiError::SubmissionNotFound(submission_id.to_string()));
+    }
+    if !matches!(original.status.as_str(), "passed" | "failed") {
+        return Err(ApiError::InvalidField(format!(
+            "submission {} is still {}",
+            submission_id, original.status
+        )));
+    }
+
+    let challenge = db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+    check_window(&challenge)?;
+    provenance::check_challenge(&state, pool, &challenge).await?;
+    let challenge = open_env_vars(&state.env_cipher, challenge)?;
+    check_language_allowed(&challenge, &original.language)?;
+    check_org_policy(pool, &user, &original.language, &original.source_code, original.optimization.as_deref()).await?;
+    if original.ranked {
+        check_ranked_cooldown(&state.config, pool, &user, &challenge).await?;
+    }
+    limits::check_compile_quota(&state.config, pool, queue, &user.id).await?;
+
+    let new_id = start_submission(
+        pool,
+        queue,
+        state.mailer.as_ref(),
+        &challenge,
+        &user,
+        &original.language,
+        &original.source_code,
+        original.optimization.as_deref(),
+        None,
+        Some(&original.id),
+        original.ranked,
+        // A retry is for a suspect result, so nothing is taken from the cache
+        JudgeOptions {
+            reuse_cached: false,
+            ..JudgeOptions::from_config(&state.config)
+        },
+    )
+    .await?;
+
+    Ok(Json(SubmitResponse {
+        submission_id: new_id,
+        status: "pending".to_string(),
+        ranked: original.ranked,
+    }))
+}
+
+/// A practice submission made on the user's behalf (a repo push, see
+/// repos.rs), judged before this returns. The checks of
+/// `POST /challenges/:id/submit` apply, the compile quota included.
+pub async fn submit_on_behalf(
+    state: &crate::AppState,
+    user: &db::User,
+    challenge_id: &str,
+    language: &str,
+    source_code: &str,
+    optimization: Option<&str>,
+) -> Result<db::ChallengeSubmission, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let challenge = db::get_challenge(pool, challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.to_string()))?;
+    check_window(&challenge)?;
+    provenance::check_challenge(state, pool, &challenge).await?;
+    let challenge = open_env_vars(&state.env_cipher, challenge)?;
+    check_language_allowed(&challenge, language)?;
+    check_org_policy(pool, user, language, source_code, optimization).await?;
+    if let Some(parsed) = Language::from_str(language) {
+        limits::check_source(&state.config, parsed, source_code)?;
+    }
+    limits::check_compile_quota(&state.config, pool, queue, &user.id).await?;
+
+    let submission_id = start_submission(
+        pool,
+        queue,
+        state.mailer.as_ref(),
+        &challenge,
+        user,
+        language,
+        source_code,
+        optimization,
+        None,
+        None,
+        false,
+        JudgeOptions::from_config(&state.config),
+    )
+    .await?;
+    db::get_challenge_submission(pool, &submission_id)
+        .await?
+        .ok_or_else(|| ApiError::SubmissionNotFound(submission_id.to_string()))
+}
+
+fn language_restrictions(challenge: &Challenge) -> Result<Option<LanguageRestrictions>, ApiError> {
+    challenge.languages
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| ApiError::Internal(format!("Invalid challenge languages: {}", e)))
+}
+
+/// Space out a user's ranked submissions to a challenge; practice runs are
+/// never held back
+async fn check_ranked_cooldown(
+    config: &Config,
+    pool: &PgPool,
+    user: &db::User,
+    challenge: &Challenge,
+) -> Result<(), ApiError> {
+    let cooldown = config.ranked_submission_cooldown_seconds;
+    if cooldown == 0 {
+        return Ok(());
+    }
+    if let Some(last) = db::last_ranked_submission_at(pool, &user.id, &challenge.id).await? {
+        let elapsed = (Utc::now() - last).num_seconds().max(0) as u64;
+        if elapsed < cooldown {
+            return Err(ApiError::RankedCooldown { retry_after: cooldown - elapsed });
         }
     }
+    Ok(())
+}
 
-    let source_code = source_code.ok_or(ApiError::MissingField("source_code"))?;
-    let language = language.ok_or(ApiError::MissingField("language"))?;
+/// Reject languages the challenge doesn't accept before anything is compiled
+fn check_language_allowed(challenge: &Challenge, language_str: &str) -> Result<(), ApiError> {
+    let language = Language::from_str(language_str)
+        .ok_or_else(|| ApiError::InvalidLanguage(language_str.to_string()))?;
+    if let Some(restrictions) = language_restrictions(challenge)? {
+        if !restrictions.permits(language) {
+            return Err(ApiError::InvalidField(format!(
+                "challenge {} does not accept {} submissions",
+                challenge.id,
+                language.as_str()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Hold the submitter to their organization's compile policy
+async fn check_org_policy(
+    pool: &PgPool,
+    user: &db::User,
+    language_str: &str,
+    source_code: &str,
+    optimization_str: Option<&str>,
+) -> Result<(), ApiError> {
+    let language = Language::from_str(language_str)
+        .ok_or_else(|| ApiError::InvalidLanguage(language_str.to_string()))?;
+    let job = challenge_compile_job(user, source_code, language, challenge_optimization(optimization_str));
+    orgs::enforce(pool, &job).await
+}
+
+/// Check that `binary_id` is what the challenge would compile from
+/// `source_code`, so the submission can skip compiling. The hash pins the
+/// source the caller means; the binary must have come out of a build of that
+/// source with the challenge's settings (default flags, image and toolchain).
+async fn verify_prebuilt(
+    pool: &PgPool,
+    user: &db::User,
+    binary_id: &str,
+    source_sha256: &str,
+    source_code: &str,
+    language_str: &str,
+    optimization_str: Option<&str>,
+) -> Result<String, ApiError> {
+    let digest = hex::encode(Sha256::digest(source_code.as_bytes()));
+    if !digest.eq_ignore_ascii_case(source_sha256) {
+        return Err(ApiError::InvalidField(
+            "source_sha256 does not match source_code".to_string(),
+        ));
+    }
 
-    // Create challenge submission
+    if db::get_binary_metadata(pool, binary_id).await?.is_none() {
+        return Err(ApiError::BinaryNotFound(binary_id.to_string()));
+    }
+
+    let language = Language::from_str(language_str)
+        .ok_or_else(|| ApiError::InvalidLanguage(language_str.to_string()))?;
+    let optimization = challenge_optimization(optimization_str);
+    let build_key = challenge_compile_job(user, source_code, language, optimization).cache_key();
+    if !db::is_binary_built_from(pool, binary_id, &build_key).await? {
+        return Err(ApiError::InvalidField(format!(
+            "binary {} was not built from this source as {} {} with default settings",
+            binary_id,
+            language.as_str(),
+            optimization.as_str()
+        )));
+    }
+
+    Ok(binary_id.to_string())
+}
+
+fn challenge_optimization(optimization_str: Option<&str>) -> Optimization {
+    optimization_str
+        .and_then(Optimization::from_str)
+        .unwrap_or(Optimization::Release)
+}
+
+/// The build a submission gets: the requested language and optimization,
+/// everything else default
+fn challenge_compile_job(
+    user: &db::User,
+    source_code: &str,
+    language: Language,
+    optimization: Optimization,
+) -> CompileJob {
+    CompileJob {
+        id: Uuid::new_v4(),
+        user_id: Some(user.id),
+        source_code: source_code.to_string(),
+        language,
+        optimization,
+        flags: HashMap::new(),
+        compiler_image: None,
+        emit_asm: false,
+        build_options: Default::default(),
+        toolchain: None,
+        retry_failed: false,
+        created_at: Utc::now(),
+    }
+}
+
+/// Swap sealed env vars for the plain map the sandbox job needs
+fn open_env_vars(cipher: &EnvVarCipher, mut challenge: Challenge) -> Result<Challenge, ApiError> {
+    if let Some(stored) = &challenge.env_vars {
+        challenge.env_vars = Some(serde_json::json!(cipher.open(stored)?));
+    }
+    Ok(challenge)
+}
+
+/// How a submission's test cases are run
+#[derive(Debug, Clone, Copy)]
+struct JudgeOptions {
+    /// Test cases run at once
+    test_parallelism: usize,
+    /// How long a judged test case is reused for the same user and binary
+    /// (0 = never cached)
+    test_cache_seconds: u64,
+    /// Take results from the cache; fresh results are cached either way
+    reuse_cached: bool,
+    /// Characters of output in each preview
+    preview_chars: usize,
+}
+
+impl JudgeOptions {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            test_parallelism: config.challenge_test_parallelism.max(1),
+            test_cache_seconds: config.challenge_test_cache_seconds,
+            reuse_cached: true,
+            preview_chars: config.challenge_preview_chars,
+        }
+    }
+}
+
+/// Record a submission and run it; processing failures end up on the
+/// submission's status rather than in the response. With `prebuilt` (a
+/// binary already checked by [`verify_prebuilt`]) the compile is skipped.
+/// Unranked (practice) submissions run the same way but never record a
+/// solve or touch the leaderboard.
+#[allow(clippy::too_many_arguments)]
+async fn start_submission(
+    pool: &PgPool,
+    queue: &QueueClient,
+    mailer: Option<&Mailer>,
+    challenge: &Challenge,
+    user: &db::User,
+    language: &str,
+    source_code: &str,
+    optimization: Option<&str>,
+    prebuilt: Option<&str>,
+    retry_of: Option<&Uuid>,
+    ranked: bool,
+    options: JudgeOptions,
+) -> Result<Uuid, ApiError> {
     let submission = db::create_challenge_submission(
         pool,
         &user.id,
-        &challenge_id,
-        &language,
-        &source_code,
+        &challenge.id,
+        language,
+        source_code,
+        optimization,
+        retry_of,
+        ranked,
     )
     .await?;
 
     info!(
         submission_id = %submission.id,
         user_id = %user.id,
-        challenge_id = %challenge_id,
+        challenge_id = %challenge.id,
         language = %language,
+        prebuilt = ?prebuilt,
+        retry_of = ?retry_of,
+        ranked,
         "Challenge submission created"
     );
 
@@ -264,12 +933,16 @@ pub async fn submit_challenge(
     if let Err(e) = process_challenge_submission(
         pool,
         queue,
+        mailer,
         submission_id,
-        &challenge,
-        &user,
-        &source_code,
-        &language,
-        optimization.as_deref(),
+        challenge,
+        user,
+        source_code,
+        language,
+        optimization,
+        prebuilt,
+        ranked,
+        options,
     )
     .await
     {
@@ -288,54 +961,105 @@ pub async fn submit_challenge(
             None,
             Some(&e.to_string()),
         )
-        .await;
+        .await;
+    }
+
+    if let Ok(Some(finished)) = db::get_challenge_submission(pool, &submission_id).await {
+        let title = format!("Submission {}: {}", finished.status, challenge.name);
+        let body = match (&finished.error_message, finished.instructions) {
+            (Some(error), _) => error.clone(),
+            (None, Some(instructions)) => format!("{} instructions ({})", instructions, language),
+            (None, None) => format!("{} submission {}", language, finished.status),
+        };
+        let link = format!("/challenges/{}", challenge.id);
+        if let Err(e) = notifications::notify(
+            pool,
+            mailer,
+            &user.id,
+            NotificationKind::SubmissionFinished,
+            &title,
+            &body,
+            Some(&link),
+        )
+        .await
+        {
+            warn!(submission_id = %submission_id, error = %e, "Failed to send submission notification");
+        }
     }
 
-    Ok(Json(SubmitResponse {
-        submission_id,
-        status: "pending".to_string(),
-    }))
+    Ok(submission_id)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_challenge_submission(
     pool: &PgPool,
     queue: &QueueClient,
+    mailer: Option<&Mailer>,
     submission_id: Uuid,
     challenge: &Challenge,
     user: &db::User,
     source_code: &str,
     language_str: &str,
     optimization_str: Option<&str>,
+    prebuilt: Option<&str>,
+    ranked: bool,
+    options: JudgeOptions,
 ) -> Result<(), ApiError> {
-    // Update status to compiling
-    db::update_challenge_submission_status(pool, &submission_id, "compiling", None, None, None, None).await?;
-
-    // Parse language
-    let language = Language::from_str(language_str)
-        .ok_or_else(|| ApiError::InvalidLanguage(language_str.to_string()))?;
-
-    let optimization = optimization_str
-        .and_then(|s| Optimization::from_str(s))
-        .unwrap_or(Optimization::Release);
-
-    // Submit compile job
-    let compile_job = CompileJob {
-        id: Uuid::new_v4(),
-        user_id: Some(user.id),
-        source_code: source_code.to_string(),
-        language,
-        optimization,
-        flags: HashMap::new(),
-        created_at: Utc::now(),
+    let binary_id = match prebuilt {
+        Some(binary_id) => binary_id.to_string(),
+        None => {
+            // Update status to compiling
+            db::update_challenge_submission_status(pool, &submission_id, "compiling", None, None, None, None).await?;
+
+            // Parse language
+            let language = Language::from_str(language_str)
+                .ok_or_else(|| ApiError::InvalidLanguage(language_str.to_string()))?;
+
+            // Submit compile job
+            let compile_job = challenge_compile_job(user, source_code, language, challenge_optimization(optimization_str));
+            let compile_job_id = compile_job.id;
+            queue.submit_compile_job(compile_job).await?;
+            let compile_wait = Duration::from_secs(120);
+            limits::reserve_compile(pool, &user.id, &compile_job_id, language, compile_wait).await?;
+
+            // Wait for compilation
+            let compile_result = queue.await_compile(&compile_job_id, compile_wait).await?;
+            limits::settle_compile(pool, &compile_job_id, &compile_result).await?;
+
+            compile_result.binary_id
+        }
     };
 
-    let compile_job_id = compile_job.id;
-    queue.submit_compile_job(compile_job).await?;
-
-    // Wait for compilation
-    let compile_result = wait_for_compile(&queue, compile_job_id, Duration::from_secs(120)).await?;
+    let binary_checks: Option<BinaryChecks> = challenge.binary_checks
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| ApiError::Internal(format!("Invalid challenge binary checks: {}", e)))?;
+    if let Some(checks) = binary_checks.filter(|c| !c.is_empty()) {
+        let binary = db::get_binary(pool, &binary_id)
+            .await?
+            .ok_or_else(|| ApiError::BinaryNotFound(binary_id.clone()))?;
+        if let Err(e) = checks.check(&binary) {
+            info!(
+                submission_id = %submission_id,
+                challenge_id = %challenge.id,
+                error = %e,
+                "Submission rejected by binary checks"
+            );
+            db::update_challenge_submission_status(
+                pool,
+                &submission_id,
+                "failed",
+                Some(&binary_id),
+                None,
+                None,
+                Some(&e.to_string()),
+            )
+            .await?;
+            return Ok(());
+        }
+    }
 
-    let binary_id = compile_result.binary_id;
     db::update_challenge_submission_status(pool, &submission_id, "running", Some(&binary_id), None, None, None).await?;
 
     // Parse test cases
@@ -349,84 +1073,90 @@ async fn process_challenge_submission(
         _ => VerifyMode::Exact,
     };
 
-    // Run each test case and collect results
-    let mut test_results = Vec::new();
-    let mut all_passed = true;
-    let mut total_instructions: i64 = 0;
-    let mut max_instructions: i64 = 0;
-    let mut final_run_id: Option<Uuid> = None;
-
     // Parse challenge env_vars if present
     let challenge_env_vars: std::collections::HashMap<String, String> = challenge.env_vars
         .as_ref()
         .and_then(|v| serde_json::from_value(v.clone()).ok())
         .unwrap_or_default();
 
-    for (i, test_case) in test_cases.iter().enumerate() {
-        // Submit execute job
-        let job = Job {
-            id: Uuid::new_v4(),
-            user_id: Some(user.id),
-            binary_id: binary_id.clone(),
-            instruction_limit: 1_000_000_000, // 1B instruction limit for challenges
-            stdin: test_case.stdin.as_bytes().to_vec(),
-            created_at: Utc::now(),
-            benchmark_id: Some(challenge.id.clone()),
-            network_enabled: challenge.network_enabled,
-            env_vars: challenge_env_vars.clone(),
-        };
-
-        let job_id = job.id;
-        queue.submit_job(job).await?;
-
-        // Wait for execution
-        let exec_result = match wait_for_execution(&queue, job_id, Duration::from_secs(30)).await {
-            Ok(result) => result,
-            Err(e) => {
-                test_results.push(TestResult {
-                    test_index: i,
-                    passed: false,
-                    expected_preview: Some(truncate_preview(&test_case.expected_stdout, 50)),
-                    actual_preview: None,
-                    error: Some(format!("Execution failed: {}", e)),
-                });
-                all_passed = false;
-                continue;
-            }
-        };
-
-        // Get the run from database (saved by worker)
-        if let Ok(Some(run)) = db::get_run_by_job_id(pool, &job_id).await {
-            final_run_id = Some(run.id);
-        }
-
-        // Check output
-        let actual_output = exec_result.stdout.clone();
-        let passed = verify_output(&actual_output, &test_case.expected_stdout, &verify_mode);
-
-        if !passed {
-            all_passed = false;
-        }
-
-        total_instructions += exec_result.instructions as i64;
-        if exec_result.instructions as i64 > max_instructions {
-            max_instructions = exec_result.instructions as i64;
+    let fixtures: Option<FixtureSpec> = challenge.fixtures
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| ApiError::Internal(format!("Invalid challenge fixtures: {}", e)))?;
+    let sandbox_options: Option<SandboxOptions> = challenge.sandbox_options
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| ApiError::Internal(format!("Invalid challenge sandbox options: {}", e)))?;
+
+    // Run the test cases, several at once; every job carries its own input,
+    // and results come back in test order. Unless the
+    // challenge wants every case judged, nothing more is started after the
+    // first failure.
+    let runs: Vec<_> = test_cases
+        .iter()
+        .enumerate()
+        .map(|(i, test_case)| {
+            let job = Job {
+                id: Uuid::new_v4(),
+                user_id: Some(user.id),
+                binary_id: binary_id.clone(),
+                instruction_limit: 1_000_000_000, // 1B instruction limit for challenges
+                stdin: test_case.stdin.as_bytes().to_vec(),
+                created_at: Utc::now(),
+                benchmark_id: Some(challenge.id.clone()),
+                benchmark_version: None,
+                network_enabled: challenge.network_enabled,
+                env_vars: challenge_env_vars.clone(),
+                args: test_case.args.clone(),
+                outputs: test_case.expected_files.keys().cloned().collect(),
+                core_dump: false,
+                mode: ExecutionMode::Normal,
+                fixtures: fixtures.clone(),
+                sandbox_options: sandbox_options.clone(),
+                sandbox_signature: None,
+                timeout_ms: None,
+                memory_limit_mb: challenge.memory_limit_mb.and_then(|m| u32::try_from(m).ok()),
+                // Challenge output can give the solution away
+                visibility: RunVisibility::Private,
+                challenge_id: Some(challenge.id.clone()),
+            };
+            run_test_case(pool, queue, i, test_case, job, &verify_mode, options)
+        })
+        .collect();
+    let mut pending = stream::iter(runs).buffered(options.test_parallelism);
+    let mut outcomes: Vec<TestOutcome> = Vec::with_capacity(test_cases.len());
+    while let Some(outcome) = pending.try_next().await? {
+        let failed = !outcome.result.passed;
+        outcomes.push(outcome);
+        if failed && !challenge.run_all_tests {
+            break;
         }
+    }
+    drop(pending);
 
+    let max_instructions = outcomes.iter().filter_map(|outcome| outcome.instructions).max().unwrap_or(0);
+    let final_run_id = outcomes.iter().rev().find_map(|outcome| outcome.run_id);
+    let counted = outcomes.iter().all(|outcome| outcome.counted);
+    let mut test_results: Vec<TestResult> = outcomes.into_iter().map(|outcome| outcome.result).collect();
+    for (i, test_case) in test_cases.iter().enumerate().skip(test_results.len()) {
         test_results.push(TestResult {
             test_index: i,
-            passed,
-            expected_preview: Some(truncate_preview(&test_case.expected_stdout, 50)),
-            actual_preview: Some(truncate_preview(&actual_output, 50)),
-            error: if exec_result.exit_code != 0 {
-                Some(format!("Exit code: {}", exec_result.exit_code))
-            } else {
-                None
-            },
+            passed: false,
+            expected_preview: Some(preview::preview(&test_case.expected_stdout, options.preview_chars)),
+            actual_preview: None,
+            first_mismatch: None,
+            diff: None,
+            error: None,
+            skipped: true,
+            cached: false,
         });
     }
+    let all_passed = test_results.iter().all(|result| result.passed);
 
-    // Update submission with results
+    // Update submission with results; an uncounted one has no total, which
+    // keeps it out of the digests' bests
     let status = if all_passed { "passed" } else { "failed" };
     let test_results_json = serde_json::to_value(&test_results)
         .map_err(|e| ApiError::Internal(format!("Failed to serialize test results: {}", e)))?;
@@ -437,15 +1167,36 @@ async fn process_challenge_submission(
         status,
         None,
         Some(&test_results_json),
-        Some(max_instructions),
+        Some(max_instructions).filter(|_| counted),
         None,
     )
     .await?;
 
-    // If all tests passed, update leaderboard
-    if all_passed {
-        if let Some(run_id) = final_run_id {
-            db::update_leaderboard_entry(
+    // If all tests passed, record the solve and update leaderboard
+    if all_passed && ranked {
+        let scoring: Option<ChallengeScoring> = challenge.scoring
+            .as_ref()
+            .and_then(|v| serde_json::from_value(v.clone()).ok());
+        if let Some(scoring) = scoring {
+            if db::record_challenge_solve(pool, &challenge.id, &user.id, &scoring).await? {
+                info!(
+                    user_id = %user.id,
+                    challenge_id = %challenge.id,
+                    "Challenge solved"
+                );
+            }
+        }
+
+        if !counted {
+            info!(
+                user_id = %user.id,
+                challenge_id = %challenge.id,
+                "Solve not ranked: a run wasn't fully counted"
+            );
+        }
+        if let Some(run_id) = final_run_id.filter(|_| counted) {
+            let previous_record = db::get_language_record(pool, &challenge.id, language_str).await?;
+            let (_, old_instructions) = db::update_leaderboard_entry(
                 pool,
                 &user.id,
                 &challenge.id,
@@ -464,12 +1215,205 @@ async fn process_challenge_submission(
                 instructions = max_instructions,
                 "Leaderboard entry updated"
             );
+
+            if old_instructions.is_none_or(|old| max_instructions < old) {
+                let event = LeaderboardImproved {
+                    id: Uuid::new_v4(),
+                    challenge_id: challenge.id.clone(),
+                    challenge_name: challenge.name.clone(),
+                    language: language_str.to_string(),
+                    user_id: user.id,
+                    username: user.username.clone(),
+                    old_instructions,
+                    new_instructions: max_instructions,
+                    run_id,
+                    previous_record: previous_record.map(|(user_id, instructions)| Record { user_id, instructions }),
+                    at: Utc::now(),
+                };
+                events::publish_leaderboard_improved(pool, queue, mailer, &event).await;
+            }
         }
     }
 
     Ok(())
 }
 
+/// One test case's verdict, plus what the leaderboard needs from its run
+struct TestOutcome {
+    result: TestResult,
+    instructions: Option<i64>,
+    run_id: Option<Uuid>,
+    /// False when the run hit a limit or exec'd past what the sandbox
+    /// counts; it can still pass, but can't rank
+    counted: bool,
+}
+
+/// Everything a test case's verdict depends on besides the binary, the
+/// challenge, the test index and the user, which key the cache
+fn test_hash(job: &Job, test_case: &TestCase, verify_mode: &VerifyMode) -> String {
+    let env_vars: std::collections::BTreeMap<&String, &String> = job.env_vars.iter().collect();
+    let content = serde_json::json!({
+        "stdin": test_case.stdin,
+        "args": job.args,
+        "env_vars": env_vars,
+        "instruction_limit": job.instruction_limit,
+        "network_enabled": job.network_enabled,
+        "fixtures": job.fixtures,
+        "sandbox_options": job.sandbox_options,
+        "memory_limit_mb": job.memory_limit_mb,
+        "expected_stdout": test_case.expected_stdout,
+        "expected_files": test_case.expected_files,
+        "verify_mode": verify_mode,
+    });
+    hex::encode(Sha256::digest(content.to_string().as_bytes()))
+}
+
+/// Run a test case's job and judge what it printed and left behind, or reuse
+/// the verdict the same user's identical binary got within the cache TTL
+async fn run_test_case(
+    pool: &PgPool,
+    queue: &QueueClient,
+    index: usize,
+    test_case: &TestCase,
+    job: Job,
+    verify_mode: &VerifyMode,
+    options: JudgeOptions,
+) -> Result<TestOutcome, ApiError> {
+    let cache_key = match (job.user_id, &job.challenge_id) {
+        (Some(user_id), Some(challenge_id)) if options.test_cache_seconds > 0 => Some((
+            user_id,
+            challenge_id.clone(),
+            job.binary_id.clone(),
+            test_hash(&job, test_case, verify_mode),
+        )),
+        _ => None,
+    };
+
+    if let Some((user_id, challenge_id, binary_id, test_hash)) = cache_key.as_ref().filter(|_| options.reuse_cached) {
+        let since = Utc::now() - chrono::Duration::seconds(options.test_cache_seconds as i64);
+        let cached =
+            db::get_cached_test_result(pool, binary_id, challenge_id, index as i32, user_id, test_hash, since).await?;
+        if let Some(cached) = cached {
+            return Ok(TestOutcome {
+                result: TestResult {
+                    test_index: index,
+                    passed: cached.passed,
+                    expected_preview: cached.expected_preview,
+                    actual_preview: cached.actual_preview,
+                    first_mismatch: cached.first_mismatch.and_then(|v| serde_json::from_value(v).ok()),
+                    diff: cached.diff.and_then(|v| serde_json::from_value(v).ok()),
+                    error: cached.error,
+                    skipped: false,
+                    cached: true,
+                },
+                instructions: Some(cached.instructions),
+                run_id: Some(cached.run_id),
+                counted: true,
+            });
+        }
+    }
+
+    let job_id = job.id;
+    queue.submit_job(job).await?;
+
+    // Wait for execution
+    let exec_result = match queue.await_job(&job_id, Duration::from_secs(30)).await {
+        Ok(result) => result,
+        Err(e) => {
+            return Ok(TestOutcome {
+                result: TestResult {
+                    test_index: index,
+                    passed: false,
+                    expected_preview: Some(preview::preview(&test_case.expected_stdout, options.preview_chars)),
+                    actual_preview: None,
+                    first_mismatch: None,
+                    diff: None,
+                    error: Some(format!("Execution failed: {}", e)),
+                    skipped: false,
+                    cached: false,
+                },
+                instructions: None,
+                run_id: None,
+                counted: true,
+            });
+        }
+    };
+
+    // Get the run from database (saved by worker)
+    let run_id = db::get_run_by_job_id(pool, &job_id).await.ok().flatten().map(|run| run.id);
+
+    // Check output (stdout is base64-encoded in ExecutionResult)
+    let actual_output = BASE64
+        .decode(&exec_result.stdout)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default();
+    let file_error = verify_output_files(pool, &exec_result, &test_case.expected_files, verify_mode).await?;
+    let stdout_matches = verify_output(&actual_output, &test_case.expected_stdout, verify_mode);
+    let passed = file_error.is_none() && stdout_matches;
+
+    // Point the previews at where stdout goes wrong
+    let first_mismatch = (!stdout_matches)
+        .then(|| preview::first_mismatch(&actual_output, &test_case.expected_stdout, verify_mode))
+        .flatten();
+    let diff = first_mismatch
+        .as_ref()
+        .map(|mismatch| OutputDiff::new(&test_case.expected_stdout, &actual_output, mismatch));
+    let (expected_preview, actual_preview) = match &first_mismatch {
+        Some(mismatch) => mismatch.previews(&test_case.expected_stdout, &actual_output, options.preview_chars),
+        None => (
+            preview::preview(&test_case.expected_stdout, options.preview_chars),
+            preview::preview(&actual_output, options.preview_chars),
+        ),
+    };
+
+    let result = TestResult {
+        test_index: index,
+        passed,
+        expected_preview: Some(expected_preview),
+        actual_preview: Some(actual_preview),
+        first_mismatch,
+        diff,
+        error: if let Some(signal) = &exec_result.crash_signal {
+            Some(format!("crashed ({})", signal))
+        } else if exec_result.exit_code != 0 {
+            Some(format!("Exit code: {}", exec_result.exit_code))
+        } else {
+            file_error
+        },
+        skipped: false,
+        cached: false,
+    };
+    let instructions = exec_result.instructions as i64;
+    let counted = !exec_result.limit_reached;
+
+    // Only results backed by a saved, fully counted run are cached, so a
+    // reused one can still land on the leaderboard
+    if let (Some((user_id, challenge_id, binary_id, test_hash)), Some(run_id), true) = (&cache_key, run_id, counted) {
+        let cached = db::CachedTestResult {
+            passed: result.passed,
+            expected_preview: result.expected_preview.clone(),
+            actual_preview: result.actual_preview.clone(),
+            first_mismatch: result.first_mismatch.and_then(|m| serde_json::to_value(m).ok()),
+            diff: result.diff.as_ref().and_then(|d| serde_json::to_value(d).ok()),
+            error: result.error.clone(),
+            instructions,
+            run_id,
+        };
+        if let Err(e) =
+            db::cache_test_result(pool, binary_id, challenge_id, index as i32, user_id, test_hash, &cached).await
+        {
+            warn!(job_id = %job_id, error = %e, "Failed to cache test result");
+        }
+    }
+
+    Ok(TestOutcome {
+        result,
+        instructions: Some(instructions),
+        run_id,
+        counted,
+    })
+}
+
 fn verify_output(actual: &str, expected: &str, mode: &VerifyMode) -> bool {
     match mode {
         VerifyMode::Exact => actual == expected,
@@ -488,81 +1432,32 @@ fn verify_output(actual: &str, expected: &str, mode: &VerifyMode) -> bool {
     }
 }
 
-fn truncate_preview(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
-        s.to_string()
-    } else {
-        format!("{}...", &s[..max_len])
-    }
-}
-
-async fn wait_for_compile(
-    queue: &QueueClient,
-    job_id: Uuid,
-    timeout: Duration,
-) -> Result<crate::queue::CompileResult, ApiError> {
-    let start = std::time::Instant::now();
-
-    loop {
-        if start.elapsed() > timeout {
-            return Err(ApiError::Timeout(timeout.as_secs()));
-        }
-
-        if let Some(metadata) = queue.get_compile_status(&job_id).await? {
-            match metadata.status {
-                CompileStatus::Completed => {
-                    if let Some(result) = queue.get_compile_result(&job_id).await? {
-                        return Ok(result);
-                    }
-                }
-                CompileStatus::Failed => {
-                    return Err(ApiError::CompileError(
-                        metadata.error.unwrap_or_else(|| "Compilation failed".to_string()),
-                    ));
-                }
-                _ => {}
-            }
-        }
-
-        tokio::time::sleep(Duration::from_millis(250)).await;
-    }
-}
-
-async fn wait_for_execution(
-    queue: &QueueClient,
-    job_id: Uuid,
-    timeout: Duration,
-) -> Result<crate::sandbox::ExecutionResult, ApiError> {
-    let start = std::time::Instant::now();
-
-    loop {
-        if start.elapsed() > timeout {
-            return Err(ApiError::Timeout(timeout.as_secs()));
-        }
-
-        if let Some(metadata) = queue.get_job_status(&job_id).await? {
-            match metadata.status {
-                JobStatus::Completed => {
-                    if let Some(result) = queue.get_job_result(&job_id).await? {
-                        return Ok(result);
-                    }
-                }
-                JobStatus::Failed => {
-                    return Err(ApiError::Internal(
-                        metadata.error.unwrap_or_else(|| "Execution failed".to_string()),
-                    ));
-                }
-                _ => {}
-            }
+/// Check the files a test case expects in `/work/out`; the first problem found
+async fn verify_output_files(
+    pool: &PgPool,
+    result: &crate::sandbox::ExecutionResult,
+    expected_files: &std::collections::BTreeMap<String, String>,
+    mode: &VerifyMode,
+) -> Result<Option<String>, ApiError> {
+    for (path, expected) in expected_files {
+        let name = ctf_common::outputs::file_name(path).unwrap_or(path);
+        let file = result.output_files.iter().find(|f| f.path == name);
+        let Some(binary_id) = file.and_then(|f| f.binary_id.as_ref()) else {
+            let reason = file.and_then(|f| f.error.as_deref()).unwrap_or("missing");
+            return Ok(Some(format!("Output file {}: {}", name, reason)));
+        };
+        let actual = db::get_binary(pool, binary_id).await?.unwrap_or_default();
+        if !verify_output(&String::from_utf8_lossy(&actual), expected, mode) {
+            return Ok(Some(format!("Output file {} does not match", name)));
         }
-
-        tokio::time::sleep(Duration::from_millis(100)).await;
     }
+    Ok(None)
 }
 
 pub async fn get_submission_status(
     State(state): State<Arc<crate::AppState>>,
     Path((challenge_id, submission_id)): Path<(String, Uuid)>,
+    Query(query): Query<SubmissionStatusQuery>,
     AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<Json<SubmissionStatusResponse>, ApiError> {
     let pool = state
@@ -584,13 +1479,18 @@ pub async fn get_submission_status(
         return Err(ApiError::SubmissionNotFound(submission_id.to_string()));
     }
 
-    let test_results: Option<Vec<TestResult>> = submission
+    let mut test_results: Option<Vec<TestResult>> = submission
         .test_results
         .and_then(|v| serde_json::from_value(v).ok());
+    for diff in test_results.iter_mut().flatten().filter_map(|result| result.diff.as_mut()) {
+        diff.render(query.whitespace);
+    }
 
     Ok(Json(SubmissionStatusResponse {
         submission_id: submission.id,
         status: submission.status,
+        retry_of: submission.retry_of,
+        ranked: submission.ranked,
         test_results,
         instructions: submission.instructions,
         error_message: submission.error_message,
@@ -598,6 +1498,52 @@ pub async fn get_submission_status(
     }))
 }
 
+/// Queue status for a challenge: what's in flight, how long results take,
+/// and where the caller's own submissions stand
+pub async fn get_challenge_queue(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+) -> Result<Json<ChallengeQueueResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let challenge = get_visible_challenge(&state, pool, &challenge_id, user.as_ref()).await?;
+
+    let now = Utc::now();
+    let (pending, (processed_last_day, avg_processing_ms)) = tokio::try_join!(
+        db::list_pending_challenge_submissions(
+            pool,
+            &challenge.id,
+            now - chrono::Duration::minutes(QUEUE_STALE_AFTER_MINUTES)
+        ),
+        db::challenge_processing_time(pool, &challenge.id, now - chrono::Duration::hours(PROCESSING_TIME_WINDOW_HOURS)),
+    )?;
+
+    let mine: Vec<QueuedSubmission> = pending
+        .iter()
+        .enumerate()
+        .filter(|(_, submission)| user.as_ref().is_some_and(|u| u.id == submission.user_id))
+        .map(|(position, submission)| QueuedSubmission {
+            submission_id: submission.id,
+            status: submission.status.clone(),
+            position,
+            created_at: submission.created_at,
+        })
+        .collect();
+
+    Ok(Json(ChallengeQueueResponse {
+        challenge_id: challenge.id,
+        in_flight: pending.len(),
+        ahead: mine.first().map_or(pending.len(), |oldest| oldest.position),
+        avg_processing_ms,
+        processed_last_day,
+        mine,
+    }))
+}
+
 pub async fn get_challenge_leaderboard(
     State(state): State<Arc<crate::AppState>>,
     Path(challenge_id): Path<String>,
@@ -618,6 +1564,7 @@ pub async fn get_challenge_leaderboard(
         &challenge_id,
         query.language.as_deref(),
         query.user_type.as_deref(),
+        country_filter(query.country.as_deref())?.as_deref(),
         query.limit.min(500),
     )
     .await?;
@@ -625,11 +1572,72 @@ pub async fn get_challenge_leaderboard(
     Ok(Json(leaderboard))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LanguageLeaderboardQuery {
+    pub user_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LanguageLeaderboardResponse {
+    pub challenge_id: String,
+    /// Fewest instructions in any language
+    pub best_instructions: Option<i64>,
+    /// One entry per language, best first
+    pub languages: Vec<LanguageLeaderboardEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LanguageLeaderboardEntry {
+    #[serde(flatten)]
+    pub best: db::LanguageBest,
+    /// Instructions over `best_instructions`
+    pub delta: i64,
+    /// Instructions as a multiple of `best_instructions`
+    pub ratio: f64,
+}
+
+/// Each language's best entry side by side, against the overall best
+pub async fn get_language_leaderboard(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    Query(query): Query<LanguageLeaderboardQuery>,
+) -> Result<Json<LanguageLeaderboardResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+
+    let bests = db::get_challenge_language_bests(pool, &challenge_id, query.user_type.as_deref()).await?;
+    let best_instructions = bests.iter().map(|best| best.instructions).min();
+    let languages = bests
+        .into_iter()
+        .map(|best| {
+            let min = best_instructions.unwrap_or(best.instructions);
+            LanguageLeaderboardEntry {
+                delta: best.instructions - min,
+                ratio: if min > 0 { best.instructions as f64 / min as f64 } else { 1.0 },
+                best,
+            }
+        })
+        .collect();
+
+    Ok(Json(LanguageLeaderboardResponse {
+        challenge_id,
+        best_instructions,
+        languages,
+    }))
+}
+
 // ============ Global Leaderboard ============
 
 #[derive(Debug, Deserialize)]
 pub struct GlobalLeaderboardQuery {
     pub user_type: Option<String>,
+    pub country: Option<String>,
     #[serde(default = "default_global_limit")]
     pub limit: i64,
 }
@@ -650,6 +1658,7 @@ pub async fn get_global_leaderboard(
     let leaderboard = db::get_global_leaderboard(
         pool,
         query.user_type.as_deref(),
+        country_filter(query.country.as_deref())?.as_deref(),
         query.limit.min(500),
     )
     .await?;
@@ -657,9 +1666,91 @@ pub async fn get_global_leaderboard(
     Ok(Json(leaderboard))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CountryLeaderboardQuery {
+    pub user_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountryStanding {
+    pub rank: usize,
+    pub country: String,
+    /// Players from the country on the global leaderboard
+    pub players: usize,
+    /// Sum of their global scores
+    pub total_score: i64,
+    pub average_score: i64,
+    /// The country's best-placed player
+    pub top_player: db::PublicUser,
+}
+
+/// The global leaderboard grouped by country, by total score; players
+/// without a country are left out
+pub async fn get_country_leaderboard(
+    State(state): State<Arc<crate::AppState>>,
+    Query(query): Query<CountryLeaderboardQuery>,
+) -> Result<Json<Vec<CountryStanding>>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    // Best first, so the first player seen from a country is its top player
+    let leaderboard = db::get_global_leaderboard(pool, query.user_type.as_deref(), None, i64::MAX).await?;
+
+    let mut standings: Vec<CountryStanding> = Vec::new();
+    for entry in leaderboard {
+        let Some(country) = entry.user.country.clone() else {
+            continue;
+        };
+        match standings.iter_mut().find(|s| s.country == country) {
+            Some(standing) => {
+                standing.players += 1;
+                standing.total_score += entry.total_score;
+            }
+            None => standings.push(CountryStanding {
+                rank: 0,
+                country,
+                players: 1,
+                total_score: entry.total_score,
+                average_score: 0,
+                top_player: entry.user,
+            }),
+        }
+    }
+    standings.sort_by(|a, b| b.total_score.cmp(&a.total_score).then_with(|| a.country.cmp(&b.country)));
+    for (i, standing) in standings.iter_mut().enumerate() {
+        standing.rank = i + 1;
+        standing.average_score = standing.total_score / standing.players as i64;
+    }
+
+    Ok(Json(standings))
+}
+
 // ============ Challenge Seeding ============
 
-pub async fn seed_challenges(pool: &PgPool) -> Result<(), ApiError> {
+/// `imported_by` for the built-in challenges
+const SEED_IMPORTER: &str = "seed";
+
+/// Static x86_64 setuid helper for the file-flag challenge (488 bytes):
+/// `open("/flag.txt")`, `read` up to 256 bytes, `write` them to stdout.
+/// Runs natively (not under QEMU) when the solution execs it, so the
+/// kernel honours the setuid bit.
+const READFLAG_HELPER: &str = "f0VMRgIBAQAAAAAAAAAAAAIAPgABAAAAeABAAAAAAABAAAAAAAAAAOgAAAAAAAAAAAAAAEAAOAABAEAABAADAAEAAAAHAAAAeAAAAAAAAAB4AEAAAAAAAHgAQAAAAAAAVQAAAAAAAABYEQAAAAAAAAgAAAAAAAAAuAIAAABIjT0/AAAAMfYPBYXAeCuJxzHASI01ORAAALoAAQAADwWJwrgBAAAAvwEAAABIjTUfEAAADwUx/+sFvwEAAAC4PAAAAA8FL2ZsYWcudHh0AAAuc2hzdHJ0YWIALnRleHQALmJzcwAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAALAAAAAQAAAAYAAAAAAAAAeABAAAAAAAB4AAAAAAAAAFUAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAAAAAAAEQAAAAgAAAADAAAAAAAAANAQQAAAAAAAzQAAAAAAAAAAAQAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAEAAAADAAAAAAAAAAAAAAAAAAAAAAAAAM0AAAAAAAAAFgAAAAAAAAAAAAAAAAAAAAEAAAAAAAAAAAAAAAAAAAA=";
+
+pub async fn seed_challenges(
+    pool: &PgPool,
+    mailer: Option<&Mailer>,
+    env_cipher: &EnvVarCipher,
+    signer: &ChallengeSigner,
+) -> Result<(), ApiError> {
+    let mut seeded = Vec::new();
+    let existing: std::collections::HashSet<String> = db::list_challenges(pool, false)
+        .await?
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+
     // Hello World challenge (simplest baseline)
     let hello_tests = serde_json::json!([
         {
@@ -726,22 +1817,22 @@ _start:
         }
     ]);
 
-    db::create_challenge(
+    seeded.push(db::create_challenge(
         pool,
-        "hello-world",
-        "Hello World",
-        "Print \"Hello, World!\" followed by a newline. The simplest challenge - establish your baseline instruction count.",
-        "intro",
-        "easy",
-        None,
-        "Print exactly: Hello, World!",
-        &hello_tests,
-        "exact",
-        false,
-        None,
-        Some(&hello_baselines),
+        &db::NewChallenge {
+            id: "hello-world",
+            name: "Hello World",
+            description: "Print \"Hello, World!\" followed by a newline. The simplest challenge - establish your baseline instruction count.",
+            category: "intro",
+            difficulty: "easy",
+            output_spec: "Print exactly: Hello, World!",
+            test_cases: hello_tests,
+            verify_mode: "exact",
+            baselines: Some(hello_baselines),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
 
     // Port Scanner challenge (needs network)
     let portscan_tests = serde_json::json!([
@@ -854,27 +1945,28 @@ itoa:
         }
     ]);
 
-    db::create_challenge(
+    seeded.push(db::create_challenge(
         pool,
-        "portscan",
-        "Port Scanner",
-        "Scan localhost (127.0.0.1) on ports 22, 80, 443. Print \"<port> open\" for each open port, one per line. If a port is closed, print nothing for it.",
-        "networking",
-        "medium",
-        None,
-        "Print \"<port> open\" for each open port (22, 80, 443), one per line. Order doesn't matter.",
-        &portscan_tests,
-        "sorted",
-        true,  // Network enabled for port scanning
-        None,
-        Some(&portscan_baselines),
+        &db::NewChallenge {
+            id: "portscan",
+            name: "Port Scanner",
+            description: "Scan localhost (127.0.0.1) on ports 22, 80, 443. Print \"<port> open\" for each open port, one per line. If a port is closed, print nothing for it.",
+            category: "networking",
+            difficulty: "medium",
+            output_spec: "Print \"<port> open\" for each open port (22, 80, 443), one per line. Order doesn't matter.",
+            test_cases: portscan_tests,
+            verify_mode: "sorted",
+            network_enabled: true,  // Network enabled for port scanning
+            baselines: Some(portscan_baselines),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
 
     // Env Leak challenge (needs env vars)
-    let env_vars = serde_json::json!({
+    let env_vars = env_cipher.seal(&serde_json::json!({
         "FLAG": "ctf{env_leak_flag_42}"
-    });
+    }))?;
     let env_tests = serde_json::json!([
         {
             "stdin": "",
@@ -968,22 +2060,27 @@ exit:
         }
     ]);
 
-    db::create_challenge(
+    // Flag challenges are pass/fail, so their value decays with solves instead
+    let flag_scoring = serde_json::json!({ "type": "decay", "initial": 500, "minimum": 100, "decay": 50 });
+
+    seeded.push(db::create_challenge(
         pool,
-        "env-leak",
-        "Env Leak",
-        "A flag is hidden in an environment variable called FLAG. Find and print it.",
-        "system",
-        "easy",
-        None,
-        "Print the value of the FLAG environment variable.",
-        &env_tests,
-        "exact",
-        false,
-        Some(&env_vars),  // Set FLAG env var
-        Some(&env_baselines),
+        &db::NewChallenge {
+            id: "env-leak",
+            name: "Env Leak",
+            description: "A flag is hidden in an environment variable called FLAG. Find and print it.",
+            category: "system",
+            difficulty: "easy",
+            output_spec: "Print the value of the FLAG environment variable.",
+            test_cases: env_tests,
+            verify_mode: "exact",
+            env_vars: Some(env_vars),  // Set FLAG env var
+            baselines: Some(env_baselines),
+            scoring: Some(flag_scoring.clone()),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
 
     // Base64 Decode challenge
     let b64_tests = serde_json::json!([
@@ -1071,22 +2168,23 @@ fn base64_decode(s: &str) -> Vec<u8> {
         }
     ]);
 
-    db::create_challenge(
+    seeded.push(db::create_challenge(
         pool,
-        "base64-decode",
-        "Base64 Decode",
-        "Decode a base64-encoded string from stdin and print the decoded output.",
-        "crypto",
-        "easy",
-        Some("Base64-encoded string"),
-        "Decoded plaintext",
-        &b64_tests,
-        "exact",
-        false,
-        None,
-        Some(&b64_baselines),
+        &db::NewChallenge {
+            id: "base64-decode",
+            name: "Base64 Decode",
+            description: "Decode a base64-encoded string from stdin and print the decoded output.",
+            category: "crypto",
+            difficulty: "easy",
+            input_spec: Some("Base64-encoded string"),
+            output_spec: "Decoded plaintext",
+            test_cases: b64_tests,
+            verify_mode: "exact",
+            baselines: Some(b64_baselines),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
 
     // XOR Decode challenge
     let xor_tests = serde_json::json!([
@@ -1174,22 +2272,23 @@ func main() {
         }
     ]);
 
-    db::create_challenge(
+    seeded.push(db::create_challenge(
         pool,
-        "xor-decode",
-        "XOR Decode",
-        "Decode a XOR-encrypted message. Input format: \"0xKEY hex_data\" where KEY is a single-byte hex key and hex_data is the encrypted message in hex (no spaces).",
-        "crypto",
-        "medium",
-        Some("XOR key and encrypted hex data"),
-        "Decrypted plaintext",
-        &xor_tests,
-        "exact",
-        false,
-        None,
-        Some(&xor_baselines),
+        &db::NewChallenge {
+            id: "xor-decode",
+            name: "XOR Decode",
+            description: "Decode a XOR-encrypted message. Input format: \"0xKEY hex_data\" where KEY is a single-byte hex key and hex_data is the encrypted message in hex (no spaces).",
+            category: "crypto",
+            difficulty: "medium",
+            input_spec: Some("XOR key and encrypted hex data"),
+            output_spec: "Decrypted plaintext",
+            test_cases: xor_tests,
+            verify_mode: "exact",
+            baselines: Some(xor_baselines),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
 
     // Crypto Chain challenge (multi-layer: base64 → reverse → xor → rot13)
     // Generate test data: "ctf{crypto_chain}" → ROT13 → XOR(key=0x42) → reverse → base64
@@ -1252,22 +2351,23 @@ print(result, end="")"#
         }
     ]);
 
-    db::create_challenge(
+    seeded.push(db::create_challenge(
         pool,
-        "crypto-chain",
-        "Crypto Chain",
-        "Decode a message encrypted with multiple layers: Base64 → Reverse bytes → XOR (key from first 4 bytes) → ROT13. Apply them in order to reveal the flag.",
-        "crypto",
-        "hard",
-        Some("Multi-layer encrypted blob"),
-        "Decrypted flag",
-        &crypto_chain_tests,
-        "exact",
-        false,
-        None,
-        Some(&crypto_chain_baselines),
+        &db::NewChallenge {
+            id: "crypto-chain",
+            name: "Crypto Chain",
+            description: "Decode a message encrypted with multiple layers: Base64 → Reverse bytes → XOR (key from first 4 bytes) → ROT13. Apply them in order to reveal the flag.",
+            category: "crypto",
+            difficulty: "hard",
+            input_spec: Some("Multi-layer encrypted blob"),
+            output_spec: "Decrypted flag",
+            test_cases: crypto_chain_tests,
+            verify_mode: "exact",
+            baselines: Some(crypto_chain_baselines),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
 
     // HTTP GET challenge (needs network)
     let http_tests = serde_json::json!([
@@ -1320,23 +2420,112 @@ func main() {
         }
     ]);
 
-    db::create_challenge(
+    seeded.push(db::create_challenge(
         pool,
-        "http-get",
-        "HTTP GET",
-        "Perform an HTTP GET request to http://127.0.0.1:8080/flag and print the response body. Implement HTTP/1.1 using raw TCP sockets.",
-        "networking",
-        "hard",
-        None,
-        "HTTP response body",
-        &http_tests,
-        "trimmed",
-        true,  // Network enabled for HTTP
-        None,
-        Some(&http_baselines),
+        &db::NewChallenge {
+            id: "http-get",
+            name: "HTTP GET",
+            description: "Perform an HTTP GET request to http://127.0.0.1:8080/flag and print the response body. Implement HTTP/1.1 using raw TCP sockets.",
+            category: "networking",
+            difficulty: "hard",
+            output_spec: "HTTP response body",
+            test_cases: http_tests,
+            verify_mode: "trimmed",
+            network_enabled: true,  // Network enabled for HTTP
+            baselines: Some(http_baselines),
+            ..Default::default()
+        },
     )
-    .await?;
+    .await?);
+
+    // File Flag challenge (needs filesystem fixtures)
+    let file_fixtures = serde_json::to_value(FixtureSpec {
+        files: vec![
+            FixtureFile {
+                path: "/flag.txt".to_string(),
+                content: "ctf{setuid_helpers_leak_too}\n".to_string(),
+                base64: false,
+                mode: "0400".to_string(),
+                uid: 0,
+                gid: 0,
+            },
+            FixtureFile {
+                path: "/usr/local/bin/readflag".to_string(),
+                content: READFLAG_HELPER.to_string(),
+                base64: true,
+                mode: "4755".to_string(),
+                uid: 0,
+                gid: 0,
+            },
+        ],
+        run_as: None,
+    })
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let file_sandbox_options = serde_json::json!({ "allow_setuid": true });
+    let file_tests = serde_json::json!([
+        {
+            "stdin": "",
+            "expected_stdout": "ctf{setuid_helpers_leak_too}\n",
+            "description": "Read the root-only /flag.txt"
+        }
+    ]);
+
+    let file_baselines = serde_json::json!([
+        {
+            "language": "c",
+            "name": "C (musl)",
+            "tier": "native",
+            "source_code": "#include <unistd.h>\nint main() {\n    execl(\"/usr/local/bin/readflag\", \"readflag\", (char *)0);\n    return 1;\n}"
+        },
+        {
+            "language": "rust",
+            "name": "Rust",
+            "tier": "native",
+            "source_code": "use std::os::unix::process::CommandExt;\nfn main() {\n    std::process::Command::new(\"/usr/local/bin/readflag\").exec();\n}"
+        },
+        {
+            "language": "python",
+            "name": "Python (Nuitka)",
+            "tier": "scripting",
+            "source_code": "import os\nos.execv('/usr/local/bin/readflag', ['readflag'])"
+        }
+    ]);
+
+    let file_hints = serde_json::json!([
+        { "text": "Look at the permission bits on /usr/local/bin/readflag.", "cost": 25 },
+        { "text": "A setuid binary runs as its owner. Exec it and let it print the flag for you.", "cost": 75 }
+    ]);
 
-    info!("Seeded 7 initial challenges");
+    seeded.push(db::create_challenge(
+        pool,
+        &db::NewChallenge {
+            id: "file-flag",
+            name: "File Flag",
+            description: "The flag is in /flag.txt, readable only by root, and you are not root. A setuid helper is installed at /usr/local/bin/readflag. Print the flag.",
+            category: "system",
+            difficulty: "easy",
+            output_spec: "The contents of /flag.txt.",
+            test_cases: file_tests,
+            verify_mode: "exact",
+            baselines: Some(file_baselines),
+            fixtures: Some(file_fixtures),
+            sandbox_options: Some(file_sandbox_options),
+            scoring: Some(flag_scoring),
+            hints: Some(file_hints),
+            ..Default::default()
+        },
+    )
+    .await?);
+
+    for challenge in &seeded {
+        provenance::record_import(pool, signer, env_cipher, challenge, SEED_IMPORTER).await?;
+    }
+    info!(signed = signer.is_enabled(), "Seeded {} initial challenges", seeded.len());
+
+    for challenge in db::list_challenges(pool, true).await? {
+        if !existing.contains(&challenge.id) {
+            notifications::notify_challenge_published(pool, mailer, &challenge).await?;
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,214 @@
+//! Notes and comments on runs, so the runs browser doubles as an
+//! optimization journal.
+//!
+//! A run's owner can annotate it whether it's public or private (their
+//! comments come back with `is_note: true`); anyone logged in can comment on
+//! a public run. Authors edit or delete their own comments, owners can
+//! delete anything on their runs, and admins hide or restore comments the
+//! way they moderate writeups.
+
+use crate::auth::{AdminUser, AuthenticatedUser, MaybeAuthenticatedUser};
+use crate::db::{self, Run, RunComment, RunCommentWithUser, RunVisibility, User};
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const MAX_COMMENT_BYTES: usize = 8 * 1024;
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    /// Markdown
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerateCommentRequest {
+    pub hidden: bool,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommentListResponse {
+    pub comments: Vec<RunCommentWithUser>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteCommentResponse {
+    pub deleted: bool,
+}
+
+// ============ Helpers ============
+
+fn is_admin(state: &AppState, user: &User) -> bool {
+    state.config.admin_users.contains(&user.username)
+}
+
+/// The run, if `user` may see it; private runs read as missing
+async fn visible_run(state: &AppState, pool: &PgPool, run_id: &Uuid, user: Option<&User>) -> Result<Run, ApiError> {
+    db::get_run(pool, run_id)
+        .await?
+        .filter(|run| crate::can_view_run(state, run, user))
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))
+}
+
+/// A comment on `run_id`; one on another run reads as missing
+async fn run_comment(pool: &PgPool, run_id: &Uuid, comment_id: &Uuid) -> Result<RunComment, ApiError> {
+    db::get_run_comment(pool, comment_id)
+        .await?
+        .filter(|c| c.run_id == *run_id)
+        .ok_or_else(|| ApiError::NotFound(format!("comment {}", comment_id)))
+}
+
+fn check_content(content: &str) -> Result<&str, ApiError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(ApiError::MissingField("content"));
+    }
+    if content.len() > MAX_COMMENT_BYTES {
+        return Err(ApiError::InvalidField(format!(
+            "comment is {} bytes (max {})",
+            content.len(),
+            MAX_COMMENT_BYTES
+        )));
+    }
+    Ok(content)
+}
+
+// ============ Handlers ============
+
+pub async fn list_comments(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<Uuid>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+) -> Result<Json<CommentListResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    visible_run(&state, pool, &run_id, user.as_ref()).await?;
+
+    let is_admin = user.as_ref().is_some_and(|u| is_admin(&state, u));
+    let author = user.as_ref().map(|u| &u.id);
+    let mut comments = db::list_run_comments(pool, &run_id, is_admin, author).await?;
+    if !is_admin {
+        // Moderation notes are for the author and admins
+        for c in comments.iter_mut().filter(|c| Some(&c.user.id) != author) {
+            c.moderation_note = None;
+        }
+    }
+
+    Ok(Json(CommentListResponse { comments }))
+}
+
+/// Annotate your own run, or comment on someone else's public one
+pub async fn post_comment(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(req): Json<CommentRequest>,
+) -> Result<Json<RunComment>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = visible_run(&state, pool, &run_id, Some(&user)).await?;
+    let is_owner = run.user_id == Some(user.id);
+    if !is_owner && run.visibility != RunVisibility::Public.as_str() {
+        return Err(ApiError::Forbidden("Only the owner can annotate a private run".to_string()));
+    }
+
+    let content = check_content(&req.content)?;
+    let comment = db::create_run_comment(pool, &run_id, &user.id, content).await?;
+
+    info!(
+        comment_id = %comment.id,
+        run_id = %run_id,
+        user_id = %user.id,
+        note = is_owner,
+        "Run comment posted"
+    );
+
+    Ok(Json(comment))
+}
+
+pub async fn update_comment(
+    State(state): State<Arc<AppState>>,
+    Path((run_id, comment_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(req): Json<CommentRequest>,
+) -> Result<Json<RunComment>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    visible_run(&state, pool, &run_id, Some(&user)).await?;
+    let comment = run_comment(pool, &run_id, &comment_id).await?;
+    if comment.user_id != user.id {
+        return Err(ApiError::Forbidden("Only the author can edit a comment".to_string()));
+    }
+
+    let content = check_content(&req.content)?;
+    Ok(Json(db::update_run_comment(pool, &comment_id, content).await?))
+}
+
+/// The author, the run's owner or an admin can delete a comment
+pub async fn delete_comment(
+    State(state): State<Arc<AppState>>,
+    Path((run_id, comment_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<DeleteCommentResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = visible_run(&state, pool, &run_id, Some(&user)).await?;
+    let comment = run_comment(pool, &run_id, &comment_id).await?;
+    if comment.user_id != user.id && run.user_id != Some(user.id) && !is_admin(&state, &user) {
+        return Err(ApiError::Forbidden("Only the author or the run's owner can delete a comment".to_string()));
+    }
+
+    db::delete_run_comment(pool, &comment_id).await?;
+    info!(comment_id = %comment_id, run_id = %run_id, user_id = %user.id, "Run comment deleted");
+
+    Ok(Json(DeleteCommentResponse { deleted: true }))
+}
+
+pub async fn moderate_comment(
+    State(state): State<Arc<AppState>>,
+    Path(comment_id): Path<Uuid>,
+    AdminUser(admin): AdminUser,
+    Json(req): Json<ModerateCommentRequest>,
+) -> Result<Json<RunComment>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let comment = db::moderate_run_comment(pool, &comment_id, req.hidden, req.note.as_deref())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("comment {}", comment_id)))?;
+
+    info!(
+        comment_id = %comment_id,
+        admin = %admin.username,
+        hidden = req.hidden,
+        "Run comment moderated"
+    );
+
+    Ok(Json(comment))
+}
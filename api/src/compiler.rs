@@ -0,0 +1,37 @@
+use crate::config::Config;
+use crate::queue::CompileJob;
+use ctf_common::compilation::{self, Build, LINKER_SCRIPT_FILE};
+use ctf_common::compile_protocol::{CompileFailure, CompileManifest};
+use sha2::{Digest, Sha256};
+
+pub use ctf_common::compilation::CompileOutput;
+
+pub fn compute_binary_id(binary: &[u8]) -> String {
+    format!("sha256-{}", hex::encode(Sha256::digest(binary)))
+}
+
+/// Compile a job in-process with the compiler image (mirrors the compile worker).
+pub async fn compile(job: &CompileJob, config: &Config) -> Result<CompileOutput, CompileFailure> {
+    #[cfg(feature = "fake-sandbox")]
+    if config.sandbox_fake {
+        return crate::fake_sandbox::compile(job);
+    }
+
+    let source_filename = format!("source.{}", job.language.source_extension());
+    let mut manifest = CompileManifest::new(job.language.as_str(), job.optimization.as_str(), &source_filename);
+    manifest.flags = job.flags.clone().into_iter().collect();
+    manifest.emit_asm = job.emit_asm;
+    manifest.toolchain = job.toolchain.clone();
+    manifest.linker_flags = job.build_options.linker_flags.clone();
+    manifest.linker_script = job.build_options.linker_script.as_ref().map(|_| LINKER_SCRIPT_FILE.to_string());
+
+    compilation::compile(Build {
+        image: job.compiler_image.as_deref().unwrap_or(&config.compiler_image),
+        manifest: &manifest,
+        source_code: &job.source_code,
+        linker_script: job.build_options.linker_script.as_deref(),
+        memory_limit_mb: config.compile_memory_limit_mb,
+        timeout_sec: config.compile_timeout_sec,
+    })
+    .await
+}
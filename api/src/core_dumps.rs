@@ -0,0 +1,97 @@
+//! Core dump downloads: `GET /runs/:id/core` hands a run's owner the core
+//! its binary left when it crashed, together with the binary itself, so the
+//! crash can be looked at in gdb.
+//!
+//! Cores are only kept for `/submit` jobs that set `core_dump` (logged-in
+//! callers only), and live in the binary store, so they are cleaned up with
+//! the binaries. See `ctf_common::core_dump` for how the sandbox produces them.
+
+use crate::auth::AuthenticatedUser;
+use crate::db;
+use crate::error::ApiError;
+use crate::repro::append;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use flate2::{write::GzEncoder, Compression};
+use std::io::Write;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn readme(run_id: &Uuid, has_binary: bool) -> String {
+    let mut lines = vec![
+        format!("# Core dump of run {}", run_id),
+        String::new(),
+        "- `core`: the guest core QEMU wrote when the binary crashed".to_string(),
+    ];
+    if has_binary {
+        lines.push("- `binary`: the exact binary that ran".to_string());
+        lines.push(String::new());
+        lines.push("Open it with `gdb binary core`.".to_string());
+    } else {
+        lines.push(String::new());
+        lines.push("The binary has been cleaned up on the server; load the core".to_string());
+        lines.push("against a rebuild of it with `gdb <binary> core`.".to_string());
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+pub async fn get_core_dump(
+    State(state): State<Arc<crate::AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(run_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = db::get_run(pool, &run_id)
+        .await?
+        .filter(|run| crate::can_view_run(&state, run, Some(&user)))
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))?;
+    let is_admin = state.config.admin_users.contains(&user.username);
+    if run.user_id != Some(user.id) && !is_admin {
+        return Err(ApiError::Forbidden("You don't own this run".to_string()));
+    }
+
+    let core_dump_id = run
+        .core_dump_id
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' has no core dump", run_id)))?;
+    let core = db::get_binary(pool, core_dump_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Core dump of run '{}' is no longer stored", run_id)))?;
+    let binary = db::get_binary(pool, &run.binary_id).await?;
+
+    let build = || -> std::io::Result<Vec<u8>> {
+        let prefix = format!("core-{}", run.id);
+        let mtime = run.created_at.timestamp().max(0) as u64;
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append(&mut tar, &prefix, "README.md", 0o644, mtime, readme(&run.id, binary.is_some()).as_bytes())?;
+        append(&mut tar, &prefix, "core", 0o644, mtime, &core)?;
+        if let Some(binary) = &binary {
+            append(&mut tar, &prefix, "binary", 0o755, mtime, binary)?;
+        }
+        let mut gz = tar.into_inner()?;
+        gz.flush()?;
+        gz.finish()
+    };
+    let archive = build().map_err(|e| ApiError::Internal(format!("Failed to build core dump archive: {}", e)))?;
+
+    let disposition = format!("attachment; filename=\"core-{}.tar.gz\"", run.id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/gzip")),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition).expect("uuid filename is a valid header"),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
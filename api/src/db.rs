@@ -1,9 +1,16 @@
 use crate::error::ApiError;
-use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use crate::queue::Language;
+use ctf_common::binary_cache::BinaryCacheStats;
+use ctf_common::binary_checks::BinaryChecks;
+use ctf_common::binary_encoding;
+use ctf_common::fixtures::FixtureSpec;
+use ctf_common::runtime::RuntimeManifest;
+use ctf_common::sandbox_options::SandboxOptions;
+use chrono::{DateTime, DurationRound, NaiveDate, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 // ============ User Types ============
@@ -37,6 +44,12 @@ pub struct User {
     pub user_type: String, // 'human' or 'clanker'
     pub clanker_twitter: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// ISO 3166-1 alpha-2, upper-case
+    #[sqlx(default)]
+    pub country: Option<String>,
+    /// School, company or team
+    #[sqlx(default)]
+    pub affiliation: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +63,10 @@ pub struct PublicUser {
     pub is_verified: bool,
     pub user_type: String,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub affiliation: Option<String>,
 }
 
 impl From<User> for PublicUser {
@@ -64,6 +81,44 @@ impl From<User> for PublicUser {
             is_verified: u.is_verified,
             user_type: u.user_type,
             created_at: u.created_at,
+            country: u.country,
+            affiliation: u.affiliation,
+        }
+    }
+}
+
+/// The users columns behind a `PublicUser` (all but the bio), for queries
+/// that join users: `u.id, u.username, u.avatar_url, u.display_name,
+/// u.twitter_handle, is_verified, user_type, u.created_at, u.country,
+/// u.affiliation`
+#[derive(sqlx::FromRow)]
+struct PublicUserRow {
+    id: Uuid,
+    username: String,
+    avatar_url: Option<String>,
+    display_name: Option<String>,
+    twitter_handle: Option<String>,
+    is_verified: bool,
+    user_type: String,
+    created_at: DateTime<Utc>,
+    country: Option<String>,
+    affiliation: Option<String>,
+}
+
+impl From<PublicUserRow> for PublicUser {
+    fn from(u: PublicUserRow) -> Self {
+        PublicUser {
+            id: u.id,
+            username: u.username,
+            avatar_url: u.avatar_url,
+            display_name: u.display_name,
+            bio: None,
+            twitter_handle: u.twitter_handle,
+            is_verified: u.is_verified,
+            user_type: u.user_type,
+            created_at: u.created_at,
+            country: u.country,
+            affiliation: u.affiliation,
         }
     }
 }
@@ -84,7 +139,14 @@ pub struct Session {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestCase {
     pub stdin: String,
+    /// Passed to the solution as its arguments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
     pub expected_stdout: String,
+    /// Files the solution must leave in `/work/out` (name to expected
+    /// content, compared like stdout)
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub expected_files: std::collections::BTreeMap<String, String>,
     pub description: Option<String>,
 }
 
@@ -117,9 +179,76 @@ pub struct Challenge {
     pub created_at: DateTime<Utc>,
     // Challenge execution options
     pub network_enabled: bool,
-    pub env_vars: Option<serde_json::Value>, // HashMap<String, String> as JSON
+    // HashMap<String, String> as JSON, or sealed by secrets.rs; never sent to clients
+    #[serde(skip_serializing)]
+    pub env_vars: Option<serde_json::Value>,
     // Baseline solutions per language
     pub baselines: Option<serde_json::Value>, // Vec<ChallengeBaseline> as JSON
+    // Files mounted into the sandbox (filesystem-flag challenges)
+    pub fixtures: Option<serde_json::Value>, // FixtureSpec as JSON
+    // Capabilities/helper/setuid for privilege-escalation challenges
+    pub sandbox_options: Option<serde_json::Value>, // SandboxOptions as JSON
+    // Container memory per test case (None = MEMORY_LIMIT_MB)
+    pub memory_limit_mb: Option<i32>,
+    // Size/linkage/section/symbol constraints on the compiled binary
+    pub binary_checks: Option<serde_json::Value>, // BinaryChecks as JSON
+    // Accepted languages (None = any)
+    pub languages: Option<serde_json::Value>, // LanguageRestrictions as JSON
+    // Decay scoring for flag challenges (None = instruction-count scoring)
+    pub scoring: Option<serde_json::Value>, // ChallengeScoring as JSON
+    pub solve_count: i32,
+    // Current value of a solve, kept in step with solve_count
+    pub points: Option<i32>,
+    // Writeups stay hidden until then (None = as soon as posted)
+    pub writeups_visible_at: Option<DateTime<Utc>>,
+    // Ordered hints, unlocked one at a time for a score penalty
+    pub hints: Option<serde_json::Value>, // Vec<ChallengeHint> as JSON
+    // Users who bookmarked it, kept in step with challenge_stars
+    #[sqlx(default)]
+    pub star_count: i32,
+    // Availability window; hidden from non-admins until starts_at, closed
+    // to submissions from ends_at (None = no bound)
+    #[sqlx(default)]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub ends_at: Option<DateTime<Utc>>,
+    // Judge every test case even after one fails (default: stop at the first)
+    #[sqlx(default)]
+    pub run_all_tests: bool,
+}
+
+impl Challenge {
+    pub fn has_opened(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at.is_none_or(|starts_at| now >= starts_at)
+    }
+
+    pub fn has_closed(&self, now: DateTime<Utc>) -> bool {
+        self.ends_at.is_some_and(|ends_at| now >= ends_at)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeHint {
+    pub text: String,
+    /// Subtracted from the user's global score once unlocked
+    #[serde(default)]
+    pub cost: i32,
+}
+
+/// Languages a challenge accepts, e.g. `{"allow": ["asm"]}` for an asm-only
+/// golf challenge. An empty `allow` list means every language not in `deny`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguageRestrictions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<Language>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<Language>,
+}
+
+impl LanguageRestrictions {
+    pub fn permits(&self, language: Language) -> bool {
+        (self.allow.is_empty() || self.allow.contains(&language)) && !self.deny.contains(&language)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -131,6 +260,62 @@ pub struct ChallengeBaseline {
     pub reference_instructions: Option<i64>,
 }
 
+/// How a flag challenge's value falls as it gets solved. Every solver holds
+/// the current value, so the first solvers lose points as others catch up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChallengeScoring {
+    /// From `initial` (first solve) down to `minimum`, reached after
+    /// `decay` further solves
+    Decay {
+        initial: i32,
+        minimum: i32,
+        decay: i32,
+        #[serde(default)]
+        curve: DecayCurve,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecayCurve {
+    /// Slow at first, then steep (CTFd's dynamic value)
+    #[default]
+    Quadratic,
+    Linear,
+}
+
+impl ChallengeScoring {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            ChallengeScoring::Decay { initial, minimum, decay, .. } => {
+                if minimum < 0 || minimum > initial {
+                    return Err("decay scoring needs 0 <= minimum <= initial".to_string());
+                }
+                if decay < 1 {
+                    return Err("decay must be at least 1".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Value of the challenge once it has `solves` solves
+    pub fn points(&self, solves: i64) -> i32 {
+        match *self {
+            ChallengeScoring::Decay { initial, minimum, decay, curve } => {
+                let progress = ((solves - 1).max(0) as f64 / decay as f64).min(1.0);
+                let fraction = match curve {
+                    DecayCurve::Quadratic => progress * progress,
+                    DecayCurve::Linear => progress,
+                };
+                let value = initial as f64 - (initial - minimum) as f64 * fraction;
+                (value.ceil() as i32).max(minimum)
+            }
+        }
+    }
+}
+
 // ============ Leaderboard Types ============
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -164,6 +349,11 @@ pub struct ChallengeSubmission {
     pub challenge_id: String,
     pub language: String,
     pub source_code: String,
+    pub optimization: Option<String>,
+    /// Submission this one re-ran, for retries
+    pub retry_of: Option<Uuid>,
+    /// Practice submissions (false) run fully but never touch solves or leaderboards
+    pub ranked: bool,
     pub binary_id: Option<String>,
     pub status: String, // 'pending', 'compiling', 'running', 'passed', 'failed'
     pub test_results: Option<serde_json::Value>,
@@ -173,6 +363,220 @@ pub struct ChallengeSubmission {
     pub completed_at: Option<DateTime<Utc>>,
 }
 
+/// A submission still compiling or running, for queue positions
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PendingSubmission {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A judged test case, keyed by binary, challenge, test index and user.
+/// `test_hash` covers everything else the verdict depends on.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedTestResult {
+    pub passed: bool,
+    pub expected_preview: Option<String>,
+    pub actual_preview: Option<String>,
+    pub first_mismatch: Option<serde_json::Value>, // preview::Mismatch as JSON
+    pub diff: Option<serde_json::Value>,           // preview::OutputDiff as JSON
+    pub error: Option<String>,
+    pub instructions: i64,
+    pub run_id: Uuid,
+}
+
+// ============ Writeup Types ============
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Writeup {
+    pub id: Uuid,
+    pub challenge_id: String,
+    pub user_id: Uuid,
+    pub content: String, // Markdown
+    pub hidden: bool,
+    pub moderation_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteupWithUser {
+    pub id: Uuid,
+    pub user: PublicUser,
+    pub content: String,
+    pub hidden: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderation_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct WriteupRow {
+    id: Uuid,
+    content: String,
+    hidden: bool,
+    moderation_note: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    user_id: Uuid,
+    username: String,
+    avatar_url: Option<String>,
+    display_name: Option<String>,
+    twitter_handle: Option<String>,
+    is_verified: bool,
+    user_type: String,
+    user_created_at: DateTime<Utc>,
+    country: Option<String>,
+    affiliation: Option<String>,
+}
+
+// ============ Run Comment Types ============
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RunComment {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub user_id: Uuid,
+    pub content: String, // Markdown
+    pub hidden: bool,
+    pub moderation_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunCommentWithUser {
+    pub id: Uuid,
+    pub user: PublicUser,
+    pub content: String,
+    /// Written by the run's owner
+    pub is_note: bool,
+    pub hidden: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderation_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct RunCommentRow {
+    id: Uuid,
+    content: String,
+    is_note: bool,
+    hidden: bool,
+    moderation_note: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    user_id: Uuid,
+    username: String,
+    avatar_url: Option<String>,
+    display_name: Option<String>,
+    twitter_handle: Option<String>,
+    is_verified: bool,
+    user_type: String,
+    user_created_at: DateTime<Utc>,
+    country: Option<String>,
+    affiliation: Option<String>,
+}
+
+// ============ Notification Types ============
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub kind: String, // 'record_beaten', 'submission_finished', 'challenge_published', 'tracked_solution'
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============ Tracked Solution Types ============
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TrackedSolution {
+    #[serde(skip_serializing)]
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub benchmark_id: String,
+    pub language: String,
+    pub optimization: Option<String>,
+    pub source_code: String,
+    pub next_run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One scheduled re-run; either `instructions` or `error` is set
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TrackedSolutionRun {
+    #[serde(skip_serializing)]
+    pub tracked_solution_id: Uuid,
+    pub run_id: Option<Uuid>,
+    pub benchmark_version: i32,
+    pub compiler_version: Option<String>,
+    pub instructions: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A file in a GitHub repo run on every push (repos.rs); exactly one of
+/// `benchmark_id` and `challenge_id` is set
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RepoLink {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    /// `owner/name`, lower-case
+    pub repo: String,
+    pub path: String,
+    /// Pushes to other branches are ignored; `None` means the default branch
+    pub branch: Option<String>,
+    pub language: String,
+    pub optimization: Option<String>,
+    pub benchmark_id: Option<String>,
+    pub challenge_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One push a link ran; either `instructions` or `error` is set
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RepoPush {
+    #[serde(skip_serializing)]
+    pub link_id: Uuid,
+    pub commit_sha: String,
+    pub run_id: Option<Uuid>,
+    pub submission_id: Option<Uuid>,
+    pub instructions: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============ Organization Types ============
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Organization {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+    /// An `orgs::OrgPolicy`; `{}` restricts nothing
+    pub policy: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct OrganizationMember {
+    pub username: String,
+    /// `admin` or `member`
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
 // ============ Verification Types ============
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -232,6 +636,12 @@ pub async fn run_migrations(pool: &PgPool) -> Result<(), ApiError> {
         .execute(pool).await.ok();
     sqlx::query(r#"ALTER TABLE users ADD COLUMN IF NOT EXISTS clanker_twitter VARCHAR(100)"#)
         .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE users ADD COLUMN IF NOT EXISTS country VARCHAR(2)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE users ADD COLUMN IF NOT EXISTS affiliation VARCHAR(100)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_users_country ON users(country) WHERE country IS NOT NULL"#)
+        .execute(pool).await.ok();
 
     // Create sessions table
     sqlx::query(
@@ -290,6 +700,133 @@ pub async fn run_migrations(pool: &PgPool) -> Result<(), ApiError> {
         .execute(pool).await.ok();
     sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS baselines JSONB"#)
         .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS fixtures JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS sandbox_options JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS memory_limit_mb INTEGER"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS binary_checks JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS languages JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS scoring JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS solve_count INTEGER DEFAULT 0"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS points INTEGER"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS star_count INTEGER NOT NULL DEFAULT 0"#)
+        .execute(pool).await.ok();
+
+    // One row per user per decay-scored challenge they have solved
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS challenge_solves (
+            challenge_id VARCHAR(100) NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            solved_at TIMESTAMPTZ DEFAULT NOW(),
+            PRIMARY KEY (challenge_id, user_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge_solves table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_challenge_solves_user ON challenge_solves(user_id)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS writeups_visible_at TIMESTAMPTZ"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS hints JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS starts_at TIMESTAMPTZ"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS ends_at TIMESTAMPTZ"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS run_all_tests BOOLEAN NOT NULL DEFAULT FALSE"#)
+        .execute(pool).await.ok();
+    // Provenance and content signature (provenance.rs)
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS imported_by VARCHAR(100)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS imported_at TIMESTAMPTZ"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS signature VARCHAR(64)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenges ADD COLUMN IF NOT EXISTS signing_key_id VARCHAR(16)"#)
+        .execute(pool).await.ok();
+
+    // Hints a user has paid for; cost is fixed at unlock time
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS hint_unlocks (
+            challenge_id VARCHAR(100) NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            hint_index INTEGER NOT NULL,
+            cost INTEGER NOT NULL DEFAULT 0,
+            unlocked_at TIMESTAMPTZ DEFAULT NOW(),
+            PRIMARY KEY (challenge_id, user_id, hint_index)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create hint_unlocks table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_hint_unlocks_user ON hint_unlocks(user_id)"#)
+        .execute(pool).await.ok();
+
+    // In-app notification inbox
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notifications (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            kind VARCHAR(50) NOT NULL,
+            title VARCHAR(200) NOT NULL,
+            body TEXT NOT NULL,
+            link VARCHAR(500),
+            read_at TIMESTAMPTZ,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create notifications table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_notifications_user ON notifications(user_id, created_at DESC)"#)
+        .execute(pool).await.ok();
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notification_preferences (
+            user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            preferences JSONB NOT NULL,
+            updated_at TIMESTAMPTZ DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create notification_preferences table: {}", e)))?;
+
+    // Solver writeups, one per user per challenge
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS writeups (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            challenge_id VARCHAR(100) NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            content TEXT NOT NULL,
+            hidden BOOLEAN NOT NULL DEFAULT FALSE,
+            moderation_note TEXT,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            updated_at TIMESTAMPTZ DEFAULT NOW(),
+            UNIQUE (challenge_id, user_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create writeups table: {}", e)))?;
 
     // Create leaderboard_entries table
     sqlx::query(
@@ -348,6 +885,45 @@ pub async fn run_migrations(pool: &PgPool) -> Result<(), ApiError> {
         .execute(pool).await.ok();
     sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_challenge_submissions_status ON challenge_submissions(status)"#)
         .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenge_submissions ADD COLUMN IF NOT EXISTS optimization VARCHAR(20)"#)
+        .execute(pool).await.ok();
+    sqlx::query(
+        r#"ALTER TABLE challenge_submissions ADD COLUMN IF NOT EXISTS retry_of UUID REFERENCES challenge_submissions(id) ON DELETE SET NULL"#,
+    )
+    .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenge_submissions ADD COLUMN IF NOT EXISTS ranked BOOLEAN NOT NULL DEFAULT TRUE"#)
+        .execute(pool).await.ok();
+
+    // Judged test cases, reused when the same user submits the same binary again
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS challenge_test_cache (
+            binary_id VARCHAR(100) NOT NULL,
+            challenge_id VARCHAR(100) NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+            test_index INTEGER NOT NULL,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            test_hash VARCHAR(64) NOT NULL,
+            passed BOOLEAN NOT NULL,
+            actual_preview TEXT,
+            error TEXT,
+            instructions BIGINT NOT NULL,
+            run_id UUID NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (binary_id, challenge_id, test_index, user_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge_test_cache table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_challenge_test_cache_created ON challenge_test_cache(created_at)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenge_test_cache ADD COLUMN IF NOT EXISTS expected_preview TEXT"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenge_test_cache ADD COLUMN IF NOT EXISTS first_mismatch JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE challenge_test_cache ADD COLUMN IF NOT EXISTS diff JSONB"#)
+        .execute(pool).await.ok();
 
     // Create verification_codes table (for clanker Twitter verification)
     sqlx::query(
@@ -416,14 +992,490 @@ pub async fn run_migrations(pool: &PgPool) -> Result<(), ApiError> {
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to create index: {}", e)))?;
 
-    // Create binaries table for compiled binary storage
-    create_binaries_table(pool).await?;
+    // What /submit ran the binary with, for reproduction kits
+    sqlx::query(r#"ALTER TABLE submissions ADD COLUMN IF NOT EXISTS stdin BYTEA"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE submissions ADD COLUMN IF NOT EXISTS env_vars JSONB"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE submissions ADD COLUMN IF NOT EXISTS timeout_ms BIGINT"#)
+        .execute(pool).await.ok();
 
-    // Create runs table for permanent run storage
-    create_runs_table(pool).await?;
+    // Precomputed aggregates behind the public stats API, one JSON row each
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS public_stats (
+            name VARCHAR(50) PRIMARY KEY,
+            data JSONB NOT NULL,
+            refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create public_stats table: {}", e)))?;
 
-    Ok(())
-}
+    // One generated digest per UTC day, see digest.rs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS digests (
+            day DATE PRIMARY KEY,
+            data JSONB NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create digests table: {}", e)))?;
+
+    // Compile time charged against each user's daily quota, see limits.rs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS compile_usage (
+            job_id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            compile_ms BIGINT NOT NULL,
+            settled BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create compile_usage table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_compile_usage_user ON compile_usage(user_id, created_at)"#)
+        .execute(pool).await.ok();
+    // CPU time the build used, from the compiler image's cgroup accounting
+    sqlx::query(r#"ALTER TABLE compile_usage ADD COLUMN IF NOT EXISTS cpu_ms BIGINT"#)
+        .execute(pool).await.ok();
+    // What the job built, which is how `GET /binaries` knows whose binaries are whose
+    sqlx::query(r#"ALTER TABLE compile_usage ADD COLUMN IF NOT EXISTS binary_id VARCHAR(100)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_compile_usage_binary ON compile_usage(binary_id, user_id)"#)
+        .execute(pool).await.ok();
+    // For the compile failure rate in `GET /admin/overview`
+    sqlx::query(r#"ALTER TABLE compile_usage ADD COLUMN IF NOT EXISTS language VARCHAR(20)"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"ALTER TABLE compile_usage ADD COLUMN IF NOT EXISTS failed BOOLEAN NOT NULL DEFAULT FALSE"#)
+        .execute(pool).await.ok();
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_compile_usage_created ON compile_usage(created_at)"#)
+        .execute(pool).await.ok();
+
+    // Per-user consumption by UTC day, see usage.rs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS usage_daily (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            day DATE NOT NULL,
+            api_calls BIGINT NOT NULL DEFAULT 0,
+            compiles BIGINT NOT NULL DEFAULT 0,
+            compile_cache_hits BIGINT NOT NULL DEFAULT 0,
+            compile_ms BIGINT NOT NULL DEFAULT 0,
+            runs BIGINT NOT NULL DEFAULT 0,
+            execute_ms BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (user_id, day)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create usage_daily table: {}", e)))?;
+
+    // Append-only record of security-relevant events, see audit.rs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id BIGSERIAL PRIMARY KEY,
+            event VARCHAR(64) NOT NULL,
+            user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+            ip VARCHAR(45),
+            details JSONB NOT NULL DEFAULT '{}',
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create audit_log table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_audit_log_event ON audit_log(event, created_at DESC)"#)
+        .execute(pool).await.ok();
+
+    // Clanker verification: wrong codes per code, and the lockouts they lead to (throttle.rs)
+    sqlx::query(r#"ALTER TABLE verification_codes ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0"#)
+        .execute(pool).await.ok();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS verification_lockouts (
+            user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            lockouts INTEGER NOT NULL DEFAULT 0,
+            locked_until TIMESTAMPTZ NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create verification_lockouts table: {}", e)))?;
+
+    // Create binaries table for compiled binary storage
+    create_binaries_table(pool).await?;
+
+    // Create runs table for permanent run storage
+    create_runs_table(pool).await?;
+
+    // Owner notes and public comments on runs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS run_comments (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            run_id UUID NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            content TEXT NOT NULL,
+            hidden BOOLEAN NOT NULL DEFAULT FALSE,
+            moderation_note TEXT,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create run_comments table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_run_comments_run ON run_comments(run_id, created_at)"#)
+        .execute(pool).await.ok();
+
+    // Per-user bookmarks ("stars") on runs and challenges
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS run_stars (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            run_id UUID NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (user_id, run_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create run_stars table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_run_stars_run ON run_stars(run_id)"#)
+        .execute(pool).await.ok();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS challenge_stars (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            challenge_id VARCHAR(100) NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (user_id, challenge_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge_stars table: {}", e)))?;
+
+    // Short-lived tokens for callers without a session (anonymous.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS anonymous_tokens (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            token_hash VARCHAR(64) NOT NULL UNIQUE,
+            ip VARCHAR(45),
+            submissions INTEGER NOT NULL DEFAULT 0,
+            compiles INTEGER NOT NULL DEFAULT 0,
+            expires_at TIMESTAMPTZ NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create anonymous_tokens table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_anonymous_tokens_expires ON anonymous_tokens(expires_at)"#)
+        .execute(pool).await.ok();
+
+    // Job statuses and results kept past JOB_TTL_SECONDS (pins.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS pinned_results (
+            job_id UUID PRIMARY KEY,
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            until_fetched BOOLEAN NOT NULL DEFAULT FALSE,
+            expires_at TIMESTAMPTZ,
+            metadata JSONB,
+            result JSONB,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create pinned_results table: {}", e)))?;
+
+    // Solutions re-run against current toolchains on a schedule (tracked.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tracked_solutions (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            benchmark_id VARCHAR(100) NOT NULL,
+            language VARCHAR(50) NOT NULL,
+            optimization VARCHAR(20),
+            source_code TEXT NOT NULL,
+            next_run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            UNIQUE (user_id, benchmark_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create tracked_solutions table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_tracked_solutions_next_run ON tracked_solutions(next_run_at)"#)
+        .execute(pool).await.ok();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tracked_solution_runs (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            tracked_solution_id UUID NOT NULL REFERENCES tracked_solutions(id) ON DELETE CASCADE,
+            run_id UUID REFERENCES runs(id) ON DELETE SET NULL,
+            benchmark_version INTEGER NOT NULL,
+            compiler_version TEXT,
+            instructions BIGINT,
+            error TEXT,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create tracked_solution_runs table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_tracked_solution_runs_solution ON tracked_solution_runs(tracked_solution_id, created_at)"#)
+        .execute(pool).await.ok();
+
+    // Repo files run on GitHub pushes (repos.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS repo_links (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            repo VARCHAR(200) NOT NULL,
+            path VARCHAR(500) NOT NULL,
+            branch VARCHAR(255),
+            language VARCHAR(50) NOT NULL,
+            optimization VARCHAR(20),
+            benchmark_id VARCHAR(100),
+            challenge_id VARCHAR(100) REFERENCES challenges(id) ON DELETE CASCADE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            UNIQUE (user_id, repo, path)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create repo_links table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_repo_links_repo ON repo_links(repo)"#)
+        .execute(pool).await.ok();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS repo_pushes (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            link_id UUID NOT NULL REFERENCES repo_links(id) ON DELETE CASCADE,
+            commit_sha VARCHAR(40) NOT NULL,
+            run_id UUID REFERENCES runs(id) ON DELETE SET NULL,
+            submission_id UUID REFERENCES challenge_submissions(id) ON DELETE SET NULL,
+            instructions BIGINT,
+            error TEXT,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create repo_pushes table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_repo_pushes_link ON repo_pushes(link_id, created_at)"#)
+        .execute(pool).await.ok();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS github_deliveries (
+            delivery_id VARCHAR(100) PRIMARY KEY,
+            received_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create github_deliveries table: {}", e)))?;
+
+    // Short links to run share pages (share.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS share_links (
+            code VARCHAR(16) PRIMARY KEY,
+            run_id UUID NOT NULL UNIQUE REFERENCES runs(id) ON DELETE CASCADE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create share_links table: {}", e)))?;
+
+    // Organizations and the compile policy their members are held to (orgs.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS organizations (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            slug VARCHAR(50) NOT NULL UNIQUE,
+            name VARCHAR(100) NOT NULL,
+            policy JSONB NOT NULL DEFAULT '{}',
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create organizations table: {}", e)))?;
+    // One organization per user, so there's never more than one policy to apply
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS organization_members (
+            user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            org_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+            role VARCHAR(10) NOT NULL DEFAULT 'member',
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create organization_members table: {}", e)))?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_organization_members_org ON organization_members(org_id)"#)
+        .execute(pool).await.ok();
+
+    // What the janitor removed, per table (janitor.rs)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS retention_stats (
+            table_name VARCHAR(64) NOT NULL,
+            action VARCHAR(16) NOT NULL,
+            last_pass_rows BIGINT NOT NULL DEFAULT 0,
+            total_rows BIGINT NOT NULL DEFAULT 0,
+            last_pass_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (table_name, action)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create retention_stats table: {}", e)))?;
+
+    // Execute workers run by third parties (workers.rs); only the token's hash is kept
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS external_workers (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(100) UNIQUE NOT NULL,
+            token_hash VARCHAR(64) UNIQUE NOT NULL,
+            created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            revoked_at TIMESTAMPTZ,
+            arch VARCHAR(32),
+            isolation VARCHAR(32),
+            version VARCHAR(64),
+            registered_at TIMESTAMPTZ,
+            last_seen_at TIMESTAMPTZ,
+            runs_reported BIGINT NOT NULL DEFAULT 0
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create external_workers table: {}", e)))?;
+    sqlx::query(r#"ALTER TABLE external_workers ADD COLUMN IF NOT EXISTS binary_cache JSONB"#)
+        .execute(pool).await.ok();
+    // Runs an external worker reported; never on a leaderboard
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS worker_id UUID REFERENCES external_workers(id) ON DELETE SET NULL"#)
+        .execute(pool).await.ok();
+    // Jobs the API published to SHARED_JOBS, the only ones an external worker
+    // may report on; worker_id is whichever one claimed it first
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS shared_jobs (
+            job_id UUID PRIMARY KEY,
+            binary_id VARCHAR(100) NOT NULL,
+            instruction_limit BIGINT NOT NULL,
+            user_id UUID,
+            worker_id UUID REFERENCES external_workers(id) ON DELETE SET NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create shared_jobs table: {}", e)))?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_shared_jobs_created ON shared_jobs(created_at)")
+        .execute(pool).await.ok();
+
+    // Migrations are idempotent and unnumbered; the build that last ran them
+    // is what pins the schema down
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_info (
+            id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+            api_version VARCHAR(50) NOT NULL,
+            git_sha VARCHAR(64),
+            migrated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create schema_info table: {}", e)))?;
+    sqlx::query(
+        r#"
+        INSERT INTO schema_info (id, api_version, git_sha, migrated_at)
+        VALUES (TRUE, $1, $2, NOW())
+        ON CONFLICT (id) DO UPDATE SET
+            api_version = EXCLUDED.api_version,
+            git_sha = EXCLUDED.git_sha,
+            migrated_at = EXCLUDED.migrated_at
+        "#,
+    )
+    .bind(crate::version::API_VERSION)
+    .bind(crate::version::git_sha())
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record schema info: {}", e)))?;
+
+    Ok(())
+}
+
+/// The build whose migrations the database last ran
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SchemaInfo {
+    pub api_version: String,
+    pub git_sha: Option<String>,
+    pub migrated_at: DateTime<Utc>,
+}
+
+pub async fn get_schema_info(pool: &PgPool) -> Result<Option<SchemaInfo>, ApiError> {
+    sqlx::query_as::<_, SchemaInfo>(
+        r#"
+        SELECT api_version, git_sha, migrated_at FROM schema_info WHERE id
+        "#,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get schema info: {}", e)))
+}
+
+pub async fn get_server_version(pool: &PgPool) -> Result<String, ApiError> {
+    let (version,): (String,) = sqlx::query_as(r#"SHOW server_version"#)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get server version: {}", e)))?;
+    Ok(version)
+}
 
 pub async fn check_rate_limit(
     pool: &PgPool,
@@ -464,17 +1516,24 @@ pub async fn record_submission(
     user_id: Option<&Uuid>,
     job_id: &Uuid,
     challenge_id: Option<&Uuid>,
+    stdin: &[u8],
+    env_vars: &HashMap<String, String>,
+    timeout_ms: Option<u64>,
 ) -> Result<Uuid, ApiError> {
+    let env_vars = serde_json::to_value(env_vars).unwrap_or_default();
     let result: (Uuid,) = sqlx::query_as(
         r#"
-        INSERT INTO submissions (user_id, job_id, challenge_id)
-        VALUES ($1, $2, $3)
+        INSERT INTO submissions (user_id, job_id, challenge_id, stdin, env_vars, timeout_ms)
+        VALUES ($1, $2, $3, $4, $5, $6)
         RETURNING id
         "#,
     )
     .bind(user_id)
     .bind(job_id)
     .bind(challenge_id)
+    .bind(stdin)
+    .bind(env_vars)
+    .bind(timeout_ms.map(|t| t as i64))
     .fetch_one(pool)
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to record submission: {}", e)))?;
@@ -513,6 +1572,18 @@ pub struct Submission {
     pub created_at: DateTime<Utc>,
 }
 
+/// Inputs `/submit` recorded for a job: (stdin, env_vars, timeout_ms)
+pub async fn get_submission_inputs(
+    pool: &PgPool,
+    job_id: &Uuid,
+) -> Result<Option<(Option<Vec<u8>>, Option<serde_json::Value>, Option<i64>)>, ApiError> {
+    sqlx::query_as("SELECT stdin, env_vars, timeout_ms FROM submissions WHERE job_id = $1 LIMIT 1")
+        .bind(job_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get submission inputs: {}", e)))
+}
+
 pub async fn get_submission_by_job_id(
     pool: &PgPool,
     job_id: &Uuid,
@@ -574,14 +1645,181 @@ pub async fn cleanup_old_rate_limits(pool: &PgPool) -> Result<u64, ApiError> {
     Ok(result.rows_affected())
 }
 
-// Binary storage functions
+// ============ Compile Usage ============
 
-pub async fn create_binaries_table(pool: &PgPool) -> Result<(), ApiError> {
+/// Charge `compile_ms` up front for a queued compile job; settled later
+pub async fn reserve_compile_usage(
+    pool: &PgPool,
+    user_id: &Uuid,
+    job_id: &Uuid,
+    language: &str,
+    compile_ms: i64,
+) -> Result<(), ApiError> {
     sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS binaries (
-            id VARCHAR(100) PRIMARY KEY,
-            data BYTEA NOT NULL,
+        INSERT INTO compile_usage (job_id, user_id, language, compile_ms)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (job_id) DO NOTHING
+        "#,
+    )
+    .bind(job_id)
+    .bind(user_id)
+    .bind(language)
+    .bind(compile_ms)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to reserve compile usage: {}", e)))?;
+    Ok(())
+}
+
+/// Replace a reservation with what the job actually cost (`None` keeps the
+/// reserved amount); the user and charge, unless it was already settled
+pub async fn settle_compile_usage(
+    pool: &PgPool,
+    job_id: &Uuid,
+    compile_ms: Option<i64>,
+    cpu_ms: Option<i64>,
+    binary_id: Option<&str>,
+) -> Result<Option<(Uuid, i64)>, ApiError> {
+    sqlx::query_as(
+        r#"
+        UPDATE compile_usage SET compile_ms = COALESCE($2, compile_ms), cpu_ms = $3, binary_id = $4, settled = TRUE
+        WHERE job_id = $1 AND NOT settled
+        RETURNING user_id, compile_ms
+        "#,
+    )
+    .bind(job_id)
+    .bind(compile_ms)
+    .bind(cpu_ms)
+    .bind(binary_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to settle compile usage: {}", e)))
+}
+
+/// Settle a job whose build failed, charging `compile_ms`; the user, unless
+/// it was already settled
+pub async fn settle_failed_compile_usage(pool: &PgPool, job_id: &Uuid, compile_ms: i64) -> Result<Option<Uuid>, ApiError> {
+    let row: Option<(Uuid,)> = sqlx::query_as(
+        r#"
+        UPDATE compile_usage SET compile_ms = $2, failed = TRUE, settled = TRUE
+        WHERE job_id = $1 AND NOT settled
+        RETURNING user_id
+        "#,
+    )
+    .bind(job_id)
+    .bind(compile_ms)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to settle compile usage: {}", e)))?;
+    Ok(row.map(|(user_id,)| user_id))
+}
+
+// ============ Usage Analytics ============
+
+/// Counts to add to a user's usage for today
+#[derive(Debug, Clone, Default)]
+pub struct UsageDelta {
+    pub api_calls: i64,
+    pub compiles: i64,
+    pub compile_cache_hits: i64,
+    pub compile_ms: i64,
+    pub runs: i64,
+    pub execute_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct UsageDay {
+    pub day: NaiveDate,
+    pub api_calls: i64,
+    pub compiles: i64,
+    pub compile_cache_hits: i64,
+    pub compile_ms: i64,
+    pub runs: i64,
+    pub execute_ms: i64,
+}
+
+pub async fn record_usage(pool: &PgPool, user_id: &Uuid, delta: &UsageDelta) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO usage_daily (user_id, day, api_calls, compiles, compile_cache_hits, compile_ms, runs, execute_ms)
+        VALUES ($1, (NOW() AT TIME ZONE 'UTC')::date, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (user_id, day) DO UPDATE SET
+            api_calls = usage_daily.api_calls + EXCLUDED.api_calls,
+            compiles = usage_daily.compiles + EXCLUDED.compiles,
+            compile_cache_hits = usage_daily.compile_cache_hits + EXCLUDED.compile_cache_hits,
+            compile_ms = usage_daily.compile_ms + EXCLUDED.compile_ms,
+            runs = usage_daily.runs + EXCLUDED.runs,
+            execute_ms = usage_daily.execute_ms + EXCLUDED.execute_ms
+        "#,
+    )
+    .bind(user_id)
+    .bind(delta.api_calls)
+    .bind(delta.compiles)
+    .bind(delta.compile_cache_hits)
+    .bind(delta.compile_ms)
+    .bind(delta.runs)
+    .bind(delta.execute_ms)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record usage: {}", e)))?;
+    Ok(())
+}
+
+/// A user's usage from `since` (UTC day) on, oldest first; days without any are left out
+pub async fn get_usage(pool: &PgPool, user_id: &Uuid, since: NaiveDate) -> Result<Vec<UsageDay>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT day, api_calls, compiles, compile_cache_hits, compile_ms, runs, execute_ms
+        FROM usage_daily
+        WHERE user_id = $1 AND day >= $2
+        ORDER BY day
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get usage: {}", e)))
+}
+
+pub async fn get_unsettled_compile_jobs(
+    pool: &PgPool,
+    user_id: &Uuid,
+    since: DateTime<Utc>,
+) -> Result<Vec<Uuid>, ApiError> {
+    let rows: Vec<(Uuid,)> = sqlx::query_as(
+        r#"SELECT job_id FROM compile_usage WHERE user_id = $1 AND created_at >= $2 AND NOT settled"#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get unsettled compile jobs: {}", e)))?;
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Settled plus reserved compile time since `since`
+pub async fn get_compile_ms_since(pool: &PgPool, user_id: &Uuid, since: DateTime<Utc>) -> Result<i64, ApiError> {
+    let (total,): (Option<i64>,) = sqlx::query_as(
+        r#"SELECT SUM(compile_ms)::BIGINT FROM compile_usage WHERE user_id = $1 AND created_at >= $2"#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get compile usage: {}", e)))?;
+    Ok(total.unwrap_or(0))
+}
+
+// Binary storage functions
+
+pub async fn create_binaries_table(pool: &PgPool) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS binaries (
+            id VARCHAR(100) PRIMARY KEY,
+            data BYTEA NOT NULL,
             size BIGINT NOT NULL,
             language VARCHAR(50),
             optimization VARCHAR(20),
@@ -616,6 +1854,32 @@ pub async fn create_binaries_table(pool: &PgPool) -> Result<(), ApiError> {
         .await
         .ok();
 
+    sqlx::query(r#"ALTER TABLE binaries ADD COLUMN IF NOT EXISTS compiler_image VARCHAR(300)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    sqlx::query(r#"ALTER TABLE binaries ADD COLUMN IF NOT EXISTS toolchain VARCHAR(50)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    sqlx::query(r#"ALTER TABLE binaries ADD COLUMN IF NOT EXISTS size_report JSONB"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    sqlx::query(r#"ALTER TABLE binaries ADD COLUMN IF NOT EXISTS runtime JSONB"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // 'zstd' when `data` is compressed at rest; `size` stays the uncompressed size
+    sqlx::query(r#"ALTER TABLE binaries ADD COLUMN IF NOT EXISTS encoding VARCHAR(16)"#)
+        .execute(pool)
+        .await
+        .ok();
+
     // Create index for cleanup
     sqlx::query(
         r#"
@@ -626,6 +1890,22 @@ pub async fn create_binaries_table(pool: &PgPool) -> Result<(), ApiError> {
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to create binaries index: {}", e)))?;
 
+    // Which compile cache keys produced each binary, so a challenge submission
+    // can reuse a binary built from the same source instead of recompiling
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS binary_builds (
+            binary_id VARCHAR(100) NOT NULL,
+            build_key VARCHAR(64) NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (binary_id, build_key)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create binary_builds table: {}", e)))?;
+
     Ok(())
 }
 
@@ -635,6 +1915,15 @@ pub struct BinaryMetadata {
     pub optimization: Option<String>,
     pub compiler_version: Option<String>,
     pub compile_flags: Option<serde_json::Value>,
+    /// Digest of the compiler image that built it, for bit-for-bit rebuilds
+    #[serde(default)]
+    pub compiler_image: Option<String>,
+    /// Set for languages with a toolchain choice, e.g. `nightly`
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    /// Interpreter a script bundle needs in the sandbox
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeManifest>,
 }
 
 pub async fn store_binary(
@@ -643,27 +1932,48 @@ pub async fn store_binary(
     data: &[u8],
     metadata: Option<&BinaryMetadata>,
 ) -> Result<(), ApiError> {
-    let size = data.len() as i64;
-    let (language, optimization, compiler_version, compile_flags) = metadata
+    store_encoded_binary(pool, id, data, data.len(), None, metadata).await
+}
+
+/// Store `data` as given: compressed with `encoding` (`zstd`) or raw, with
+/// `size` the binary's own size
+pub async fn store_encoded_binary(
+    pool: &PgPool,
+    id: &str,
+    data: &[u8],
+    size: usize,
+    encoding: Option<&str>,
+    metadata: Option<&BinaryMetadata>,
+) -> Result<(), ApiError> {
+    let size = size as i64;
+    let (language, optimization, compiler_version, compile_flags, compiler_image, toolchain) = metadata
         .map(|m| {
             (
                 m.language.as_deref(),
                 m.optimization.as_deref(),
                 m.compiler_version.as_deref(),
                 m.compile_flags.as_ref(),
+                m.compiler_image.as_deref(),
+                m.toolchain.as_deref(),
             )
         })
-        .unwrap_or((None, None, None, None));
+        .unwrap_or((None, None, None, None, None, None));
+    let runtime = metadata
+        .and_then(|m| m.runtime.as_ref())
+        .and_then(|r| serde_json::to_value(r).ok());
 
     sqlx::query(
         r#"
-        INSERT INTO binaries (id, data, size, language, optimization, compiler_version, compile_flags)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        INSERT INTO binaries (id, data, size, language, optimization, compiler_version, compile_flags, compiler_image, toolchain, runtime, encoding)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         ON CONFLICT (id) DO UPDATE SET
             language = COALESCE(EXCLUDED.language, binaries.language),
             optimization = COALESCE(EXCLUDED.optimization, binaries.optimization),
             compiler_version = COALESCE(EXCLUDED.compiler_version, binaries.compiler_version),
-            compile_flags = COALESCE(EXCLUDED.compile_flags, binaries.compile_flags)
+            compile_flags = COALESCE(EXCLUDED.compile_flags, binaries.compile_flags),
+            compiler_image = COALESCE(EXCLUDED.compiler_image, binaries.compiler_image),
+            toolchain = COALESCE(EXCLUDED.toolchain, binaries.toolchain),
+            runtime = COALESCE(EXCLUDED.runtime, binaries.runtime)
         "#,
     )
     .bind(id)
@@ -673,6 +1983,10 @@ pub async fn store_binary(
     .bind(optimization)
     .bind(compiler_version)
     .bind(compile_flags)
+    .bind(compiler_image)
+    .bind(toolchain)
+    .bind(runtime)
+    .bind(encoding)
     .execute(pool)
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to store binary: {}", e)))?;
@@ -680,27 +1994,81 @@ pub async fn store_binary(
     Ok(())
 }
 
+/// A binary as it sits in the table
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredBinary {
+    pub data: Vec<u8>,
+    /// `zstd` when `data` is compressed
+    pub encoding: Option<String>,
+    /// Uncompressed size
+    pub size: i64,
+}
+
+impl StoredBinary {
+    /// The binary's own bytes
+    pub fn decompress(self) -> Result<Vec<u8>, ApiError> {
+        match self.encoding.as_deref() {
+            None => Ok(self.data),
+            Some(binary_encoding::ZSTD) => binary_encoding::decompress(&self.data, self.size as usize)
+                .map_err(|e| ApiError::Internal(format!("Stored binary doesn't decompress: {}", e))),
+            Some(other) => Err(ApiError::Internal(format!("Unknown binary encoding: {}", other))),
+        }
+    }
+}
+
 pub async fn get_binary(pool: &PgPool, id: &str) -> Result<Option<Vec<u8>>, ApiError> {
-    let result: Option<(Vec<u8>,)> = sqlx::query_as(
+    get_stored_binary(pool, id).await?.map(StoredBinary::decompress).transpose()
+}
+
+/// The binary without decompressing it, for answering fetches that accept zstd
+pub async fn get_stored_binary(pool: &PgPool, id: &str) -> Result<Option<StoredBinary>, ApiError> {
+    sqlx::query_as(
         r#"
-        SELECT data FROM binaries WHERE id = $1
+        SELECT data, encoding, size FROM binaries WHERE id = $1
         "#,
     )
     .bind(id)
     .fetch_optional(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to get binary: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get binary: {}", e)))
+}
+
+/// How much the binaries table holds, and how much compression at rest saves
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct BinaryStorage {
+    pub binaries: i64,
+    /// Kept zstd-compressed
+    pub compressed: i64,
+    /// Uncompressed sizes
+    pub raw_bytes: i64,
+    /// `data` as stored, before Postgres' own TOAST compression
+    pub stored_bytes: i64,
+}
 
-    Ok(result.map(|(data,)| data))
+pub async fn binary_storage(pool: &PgPool) -> Result<BinaryStorage, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT
+            COUNT(*) AS binaries,
+            COUNT(*) FILTER (WHERE encoding IS NOT NULL) AS compressed,
+            COALESCE(SUM(size), 0)::BIGINT AS raw_bytes,
+            COALESCE(SUM(CASE WHEN encoding IS NULL THEN size ELSE octet_length(data) END), 0)::BIGINT AS stored_bytes
+        FROM binaries
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to measure binary storage: {}", e)))
 }
 
 pub async fn get_binary_metadata(
     pool: &PgPool,
     id: &str,
 ) -> Result<Option<BinaryMetadata>, ApiError> {
-    let result: Option<(Option<String>, Option<String>, Option<String>, Option<serde_json::Value>)> = sqlx::query_as(
+    #[allow(clippy::type_complexity)]
+    let result: Option<(Option<String>, Option<String>, Option<String>, Option<serde_json::Value>, Option<String>, Option<String>, Option<serde_json::Value>)> = sqlx::query_as(
         r#"
-        SELECT language, optimization, compiler_version, compile_flags FROM binaries WHERE id = $1
+        SELECT language, optimization, compiler_version, compile_flags, compiler_image, toolchain, runtime FROM binaries WHERE id = $1
         "#,
     )
     .bind(id)
@@ -708,14 +2076,134 @@ pub async fn get_binary_metadata(
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to get binary metadata: {}", e)))?;
 
-    Ok(result.map(|(language, optimization, compiler_version, compile_flags)| BinaryMetadata {
+    Ok(result.map(|(language, optimization, compiler_version, compile_flags, compiler_image, toolchain, runtime)| BinaryMetadata {
         language,
         optimization,
         compiler_version,
         compile_flags,
+        compiler_image,
+        toolchain,
+        runtime: runtime.and_then(|r| serde_json::from_value(r).ok()),
     }))
 }
 
+/// Remember that the build with compile cache key `build_key` produced `binary_id`
+pub async fn record_binary_build(pool: &PgPool, binary_id: &str, build_key: &str) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO binary_builds (binary_id, build_key)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(binary_id)
+    .bind(build_key)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record binary build: {}", e)))?;
+
+    Ok(())
+}
+
+/// Whether the build with compile cache key `build_key` produced `binary_id`
+pub async fn is_binary_built_from(pool: &PgPool, binary_id: &str, build_key: &str) -> Result<bool, ApiError> {
+    let found: Option<(i32,)> = sqlx::query_as(
+        r#"
+        SELECT 1 FROM binary_builds WHERE binary_id = $1 AND build_key = $2
+        "#,
+    )
+    .bind(binary_id)
+    .bind(build_key)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to look up binary build: {}", e)))?;
+
+    Ok(found.is_some())
+}
+
+/// Attach a size report (from `compile.sh`) to a stored binary; false if there is no such binary
+pub async fn store_size_report(pool: &PgPool, id: &str, report: &serde_json::Value) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE binaries SET size_report = $2 WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(report)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to store size report: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// `None` if there is no such binary, `Some(None)` if it has no size report
+pub async fn get_size_report(pool: &PgPool, id: &str) -> Result<Option<Option<serde_json::Value>>, ApiError> {
+    let result: Option<(Option<serde_json::Value>,)> = sqlx::query_as(
+        r#"
+        SELECT size_report FROM binaries WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get size report: {}", e)))?;
+
+    Ok(result.map(|(report,)| report))
+}
+
+/// A stored binary without its payload, as listed by `GET /binaries`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct BinarySummary {
+    pub id: String,
+    pub size: i64,
+    pub language: Option<String>,
+    pub optimization: Option<String>,
+    pub compiler_version: Option<String>,
+    pub compiler_image: Option<String>,
+    pub toolchain: Option<String>,
+    pub has_size_report: bool,
+    /// Saved runs that executed it; binaries nobody ran are the first to clean up
+    pub run_count: i64,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Newest binaries first, optionally only `language`, those built since
+/// `since`, or those `user_id` compiled
+pub async fn list_binaries(
+    pool: &PgPool,
+    language: Option<&str>,
+    user_id: Option<&Uuid>,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<BinarySummary>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT b.id, b.size, b.language, b.optimization, b.compiler_version, b.compiler_image, b.toolchain,
+               b.size_report IS NOT NULL AS has_size_report,
+               (SELECT COUNT(*) FROM runs r WHERE r.binary_id = b.id) AS run_count,
+               b.created_at
+        FROM binaries b
+        WHERE ($1::VARCHAR IS NULL OR b.language = $1)
+          AND ($2::UUID IS NULL OR EXISTS (
+              SELECT 1 FROM compile_usage u WHERE u.binary_id = b.id AND u.user_id = $2
+          ))
+          AND ($3::TIMESTAMPTZ IS NULL OR b.created_at >= $3)
+        ORDER BY b.created_at DESC, b.id
+        LIMIT $4 OFFSET $5
+        "#,
+    )
+    .bind(language)
+    .bind(user_id)
+    .bind(since)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list binaries: {}", e)))
+}
+
 pub async fn cleanup_old_binaries(pool: &PgPool, max_age_hours: i64) -> Result<u64, ApiError> {
     let cutoff = Utc::now() - TimeDelta::hours(max_age_hours);
 
@@ -824,6 +2312,122 @@ pub async fn create_runs_table(pool: &PgPool) -> Result<(), ApiError> {
         .await
         .ok();
 
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS toolchain VARCHAR(50)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Runs used to be labelled with the compile language (`deno`, `node`);
+    // relabel them as the language written plus its runtime
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS runtime VARCHAR(20)"#)
+        .execute(pool)
+        .await
+        .ok();
+    for language in Language::ALL {
+        let Some(runtime) = language.runtime() else {
+            continue;
+        };
+        sqlx::query(r#"UPDATE runs SET language = $1, runtime = $2 WHERE language = $3 AND runtime IS NULL"#)
+            .bind(language.canonical())
+            .bind(runtime)
+            .bind(language.as_str())
+            .execute(pool)
+            .await
+            .ok();
+    }
+
+    // Per-thread instruction/syscall split from the sandbox plugin
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS thread_breakdown JSONB"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // instructions | wall_clock | memory | exec, when limit_reached
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS limit_reached_reason VARCHAR(20)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Container memory limit the run had
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS memory_limit_mb INTEGER"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Image reference (digest when known) the sandbox ran
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS sandbox_image VARCHAR(200)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // argv the binary got after its name, when it got any
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS args JSONB"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Binary store ID of the crash's core, for runs that asked for one
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS core_dump_id VARCHAR(100)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // `diagnostics` for valgrind/ASan runs; NULL for normal ones
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS mode VARCHAR(20)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Benchmark definition version the run was measured against; runs from
+    // before versioning count as version 1
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS benchmark_version INTEGER"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Ownership and visibility; challenge runs are private by default
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES users(id) ON DELETE SET NULL"#)
+        .execute(pool)
+        .await
+        .ok();
+    let added_visibility = sqlx::query(r#"ALTER TABLE runs ADD COLUMN visibility VARCHAR(10) NOT NULL DEFAULT 'public'"#)
+        .execute(pool)
+        .await
+        .is_ok();
+    if added_visibility {
+        // One-off backfill: runs saved before this column existed were all
+        // listable, including challenge solutions and their output
+        sqlx::query(
+            r#"
+            UPDATE runs SET visibility = 'private'
+            WHERE benchmark_id IN (SELECT id FROM challenges)
+            "#,
+        )
+        .execute(pool)
+        .await
+        .ok();
+        sqlx::query(
+            r#"
+            UPDATE runs SET user_id = l.user_id
+            FROM leaderboard_entries l
+            WHERE l.run_id = runs.id AND runs.user_id IS NULL
+            "#,
+        )
+        .execute(pool)
+        .await
+        .ok();
+    }
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_runs_user ON runs(user_id, created_at DESC)"#)
+        .execute(pool)
+        .await
+        .ok();
+
+    // Bookmarks, counted here so listings don't have to join run_stars
+    sqlx::query(r#"ALTER TABLE runs ADD COLUMN IF NOT EXISTS star_count INTEGER NOT NULL DEFAULT 0"#)
+        .execute(pool)
+        .await
+        .ok();
+
     Ok(())
 }
 
@@ -835,8 +2439,14 @@ pub struct Run {
     pub binary_size: Option<i64>,
     pub source_code: Option<String>,
     pub language: Option<String>,
+    /// `bun`, `deno` or `node` for javascript/typescript
+    #[sqlx(default)]
+    pub runtime: Option<String>,
     pub optimization: Option<String>,
     pub compiler_version: Option<String>,
+    /// Set for languages with a toolchain choice, e.g. `nightly`
+    #[sqlx(default)]
+    pub toolchain: Option<String>,
     pub compile_time_ms: Option<i64>,
     pub compile_cached: Option<bool>,
     pub instructions: i64,
@@ -852,17 +2462,81 @@ pub struct Run {
     pub guest_mmap_peak: Option<i64>,
     pub guest_heap_bytes: Option<i64>,
     pub limit_reached: bool,
+    pub limit_reached_reason: Option<String>,
     pub exit_code: Option<i32>,
     pub execution_time_ms: Option<i64>,
     pub instruction_limit: Option<i64>,
+    pub memory_limit_mb: Option<i32>,
+    pub sandbox_image: Option<String>,
+    /// JSON array of the arguments passed to the binary; `None` for none
+    #[sqlx(default)]
+    pub args: Option<serde_json::Value>,
+    /// Binary store ID of the core, downloadable by the owner from
+    /// `/runs/:id/core`
+    #[sqlx(default)]
+    pub core_dump_id: Option<String>,
+    /// `diagnostics` when run under valgrind/ASan (no instruction count);
+    /// `None` for normal runs
+    #[sqlx(default)]
+    pub mode: Option<String>,
     pub syscalls: Option<i64>,
     pub syscall_breakdown: Option<serde_json::Value>,
+    pub thread_breakdown: Option<serde_json::Value>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
     pub benchmark_id: Option<String>,
+    /// Set for benchmark runs; `None` before versioning means version 1
+    #[sqlx(default)]
+    pub benchmark_version: Option<i32>,
     pub created_at: DateTime<Utc>,
     pub started_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
+    /// Who submitted the job, when known
+    pub user_id: Option<Uuid>,
+    /// `public` or `private`, see [`RunVisibility`]
+    pub visibility: String,
+    /// Users who bookmarked it, kept in step with `run_stars`
+    #[sqlx(default)]
+    pub star_count: i32,
+    /// The external worker that reported it; such runs are unverified
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<Uuid>,
+}
+
+impl Run {
+    /// Public runs are visible to anyone; private ones to their owner and admins
+    pub fn visible_to(&self, viewer: Option<&Uuid>, is_admin: bool) -> bool {
+        self.visibility != RunVisibility::Private.as_str()
+            || is_admin
+            || (viewer.is_some() && self.user_id.as_ref() == viewer)
+    }
+}
+
+/// Who can see a run and its stdout/stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunVisibility {
+    #[default]
+    Public,
+    Private,
+}
+
+impl RunVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunVisibility::Public => "public",
+            RunVisibility::Private => "private",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "public" => Some(RunVisibility::Public),
+            "private" => Some(RunVisibility::Private),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -874,6 +2548,8 @@ pub struct SaveRunRequest {
     pub language: Option<String>,
     pub optimization: Option<String>,
     pub compiler_version: Option<String>,
+    #[serde(default)]
+    pub toolchain: Option<String>,
     pub compile_time_ms: Option<i64>,
     pub compile_cached: Option<bool>,
     pub instructions: i64,
@@ -889,31 +2565,56 @@ pub struct SaveRunRequest {
     pub guest_mmap_peak: Option<i64>,
     pub guest_heap_bytes: Option<i64>,
     pub limit_reached: bool,
+    pub limit_reached_reason: Option<String>,
     pub exit_code: Option<i32>,
     pub execution_time_ms: Option<i64>,
     pub instruction_limit: Option<i64>,
+    pub memory_limit_mb: Option<i32>,
+    pub sandbox_image: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub core_dump_id: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
     pub syscalls: Option<i64>,
     pub syscall_breakdown: Option<serde_json::Value>,
+    pub thread_breakdown: Option<serde_json::Value>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
     pub benchmark_id: Option<String>,
+    #[serde(default)]
+    pub benchmark_version: Option<i32>,
     pub started_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+    #[serde(default)]
+    pub visibility: RunVisibility,
+    /// Set by the API for runs external workers report, never by workers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<Uuid>,
 }
 
 pub async fn save_run(pool: &PgPool, req: &SaveRunRequest) -> Result<Uuid, ApiError> {
-    let result: (Uuid,) = sqlx::query_as(
+    // `language` arrives as the compile language; store it as language + runtime
+    let compiled_as = req.language.as_deref().and_then(Language::from_str);
+    let language = compiled_as.map(|l| l.canonical().to_string()).or_else(|| req.language.clone());
+    let runtime = compiled_as.and_then(|l| l.runtime());
+
+    let result: (Uuid, bool) = sqlx::query_as(
         r#"
         INSERT INTO runs (
             job_id, binary_id, binary_size, source_code, language, optimization, compiler_version,
             compile_time_ms, compile_cached, instructions, memory_peak_kb,
             memory_rss_kb, memory_hwm_kb, memory_data_kb, memory_stack_kb,
             io_read_bytes, io_write_bytes, guest_mmap_bytes, guest_mmap_peak,
-            guest_heap_bytes, limit_reached, exit_code,
-            execution_time_ms, instruction_limit, syscalls, syscall_breakdown,
-            stdout, stderr, benchmark_id, started_at, completed_at
+            guest_heap_bytes, limit_reached, limit_reached_reason, exit_code,
+            execution_time_ms, instruction_limit, memory_limit_mb, syscalls, syscall_breakdown,
+            thread_breakdown, stdout, stderr, benchmark_id, started_at, completed_at, sandbox_image,
+            user_id, visibility, toolchain, runtime, args, core_dump_id, mode, benchmark_version, worker_id
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40, $41, $42, $43, $44)
         ON CONFLICT (job_id) DO UPDATE SET
             instructions = EXCLUDED.instructions,
             memory_peak_kb = EXCLUDED.memory_peak_kb,
@@ -927,21 +2628,26 @@ pub async fn save_run(pool: &PgPool, req: &SaveRunRequest) -> Result<Uuid, ApiEr
             guest_mmap_peak = EXCLUDED.guest_mmap_peak,
             guest_heap_bytes = EXCLUDED.guest_heap_bytes,
             limit_reached = EXCLUDED.limit_reached,
+            limit_reached_reason = EXCLUDED.limit_reached_reason,
             exit_code = EXCLUDED.exit_code,
             execution_time_ms = EXCLUDED.execution_time_ms,
             syscalls = EXCLUDED.syscalls,
             syscall_breakdown = EXCLUDED.syscall_breakdown,
+            thread_breakdown = EXCLUDED.thread_breakdown,
             stdout = EXCLUDED.stdout,
             stderr = EXCLUDED.stderr,
-            completed_at = EXCLUDED.completed_at
-        RETURNING id
+            completed_at = EXCLUDED.completed_at,
+            sandbox_image = COALESCE(EXCLUDED.sandbox_image, runs.sandbox_image),
+            core_dump_id = COALESCE(EXCLUDED.core_dump_id, runs.core_dump_id),
+            worker_id = EXCLUDED.worker_id
+        RETURNING id, (xmax = 0) as inserted
         "#,
     )
     .bind(&req.job_id)
     .bind(&req.binary_id)
     .bind(req.binary_size)
     .bind(&req.source_code)
-    .bind(&req.language)
+    .bind(&language)
     .bind(&req.optimization)
     .bind(&req.compiler_version)
     .bind(req.compile_time_ms)
@@ -958,33 +2664,59 @@ pub async fn save_run(pool: &PgPool, req: &SaveRunRequest) -> Result<Uuid, ApiEr
     .bind(req.guest_mmap_peak)
     .bind(req.guest_heap_bytes)
     .bind(req.limit_reached)
+    .bind(&req.limit_reached_reason)
     .bind(req.exit_code)
     .bind(req.execution_time_ms)
     .bind(req.instruction_limit)
+    .bind(req.memory_limit_mb)
     .bind(req.syscalls)
     .bind(&req.syscall_breakdown)
+    .bind(&req.thread_breakdown)
     .bind(&req.stdout)
     .bind(&req.stderr)
     .bind(&req.benchmark_id)
     .bind(req.started_at)
     .bind(req.completed_at)
+    .bind(&req.sandbox_image)
+    .bind(req.user_id)
+    .bind(req.visibility.as_str())
+    .bind(&req.toolchain)
+    .bind(runtime)
+    .bind((!req.args.is_empty()).then(|| serde_json::json!(req.args)))
+    .bind(&req.core_dump_id)
+    .bind(&req.mode)
+    .bind(req.benchmark_version)
+    .bind(req.worker_id)
     .fetch_one(pool)
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to save run: {}", e)))?;
 
-    Ok(result.0)
+    // Count the run once, however often its job is saved; analytics are
+    // best-effort and never fail the save
+    let (id, inserted) = result;
+    if let (true, Some(user_id)) = (inserted, req.user_id) {
+        let delta = UsageDelta {
+            runs: 1,
+            execute_ms: req.execution_time_ms.unwrap_or(0),
+            ..Default::default()
+        };
+        record_usage(pool, &user_id, &delta).await.ok();
+    }
+
+    Ok(id)
 }
 
 pub async fn get_run(pool: &PgPool, run_id: &Uuid) -> Result<Option<Run>, ApiError> {
     let result: Option<Run> = sqlx::query_as(
         r#"
-        SELECT id, job_id, binary_id, binary_size, source_code, language, optimization, compiler_version,
+        SELECT id, job_id, binary_id, binary_size, source_code, language, runtime, optimization, compiler_version, toolchain,
                compile_time_ms, compile_cached, instructions, memory_peak_kb,
                memory_rss_kb, memory_hwm_kb, memory_data_kb, memory_stack_kb,
                io_read_bytes, io_write_bytes, guest_mmap_bytes, guest_mmap_peak,
-               guest_heap_bytes, limit_reached, exit_code,
-               execution_time_ms, instruction_limit, syscalls, syscall_breakdown,
-               stdout, stderr, benchmark_id, created_at, started_at, completed_at
+               guest_heap_bytes, limit_reached, limit_reached_reason, exit_code,
+               execution_time_ms, instruction_limit, memory_limit_mb, sandbox_image, args, core_dump_id, mode, syscalls, syscall_breakdown,
+               thread_breakdown, stdout, stderr, benchmark_id, benchmark_version, created_at, started_at, completed_at,
+               user_id, visibility, star_count, worker_id
         FROM runs
         WHERE id = $1
         "#,
@@ -1000,13 +2732,14 @@ pub async fn get_run(pool: &PgPool, run_id: &Uuid) -> Result<Option<Run>, ApiErr
 pub async fn get_run_by_job_id(pool: &PgPool, job_id: &Uuid) -> Result<Option<Run>, ApiError> {
     let result: Option<Run> = sqlx::query_as(
         r#"
-        SELECT id, job_id, binary_id, binary_size, source_code, language, optimization, compiler_version,
+        SELECT id, job_id, binary_id, binary_size, source_code, language, runtime, optimization, compiler_version, toolchain,
                compile_time_ms, compile_cached, instructions, memory_peak_kb,
                memory_rss_kb, memory_hwm_kb, memory_data_kb, memory_stack_kb,
                io_read_bytes, io_write_bytes, guest_mmap_bytes, guest_mmap_peak,
-               guest_heap_bytes, limit_reached, exit_code,
-               execution_time_ms, instruction_limit, syscalls, syscall_breakdown,
-               stdout, stderr, benchmark_id, created_at, started_at, completed_at
+               guest_heap_bytes, limit_reached, limit_reached_reason, exit_code,
+               execution_time_ms, instruction_limit, memory_limit_mb, sandbox_image, args, core_dump_id, mode, syscalls, syscall_breakdown,
+               thread_breakdown, stdout, stderr, benchmark_id, benchmark_version, created_at, started_at, completed_at,
+               user_id, visibility, star_count, worker_id
         FROM runs
         WHERE job_id = $1
         "#,
@@ -1019,23 +2752,45 @@ pub async fn get_run_by_job_id(pool: &PgPool, job_id: &Uuid) -> Result<Option<Ru
     Ok(result)
 }
 
-pub async fn list_runs(pool: &PgPool, limit: i64, offset: i64) -> Result<Vec<Run>, ApiError> {
+/// Which of the given job IDs already have a `runs` row.
+pub async fn get_existing_run_job_ids(pool: &PgPool, job_ids: &[Uuid]) -> Result<HashSet<Uuid>, ApiError> {
+    let rows: Vec<(Uuid,)> = sqlx::query_as("SELECT job_id FROM runs WHERE job_id = ANY($1)")
+        .bind(job_ids)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to look up runs: {}", e)))?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Newest runs `viewer` may see: public ones, their own, or all for admins
+pub async fn list_runs(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+    viewer: Option<&Uuid>,
+    is_admin: bool,
+) -> Result<Vec<Run>, ApiError> {
     let results: Vec<Run> = sqlx::query_as(
         r#"
-        SELECT id, job_id, binary_id, binary_size, source_code, language, optimization, compiler_version,
+        SELECT id, job_id, binary_id, binary_size, source_code, language, runtime, optimization, compiler_version, toolchain,
                compile_time_ms, compile_cached, instructions, memory_peak_kb,
                memory_rss_kb, memory_hwm_kb, memory_data_kb, memory_stack_kb,
                io_read_bytes, io_write_bytes, guest_mmap_bytes, guest_mmap_peak,
-               guest_heap_bytes, limit_reached, exit_code,
-               execution_time_ms, instruction_limit, syscalls, syscall_breakdown,
-               stdout, stderr, benchmark_id, created_at, started_at, completed_at
+               guest_heap_bytes, limit_reached, limit_reached_reason, exit_code,
+               execution_time_ms, instruction_limit, memory_limit_mb, sandbox_image, args, core_dump_id, mode, syscalls, syscall_breakdown,
+               thread_breakdown, stdout, stderr, benchmark_id, benchmark_version, created_at, started_at, completed_at,
+               user_id, visibility, star_count
         FROM runs
+        WHERE visibility = 'public' OR user_id = $3 OR $4
         ORDER BY created_at DESC
         LIMIT $1 OFFSET $2
         "#,
     )
     .bind(limit)
     .bind(offset)
+    .bind(viewer)
+    .bind(is_admin)
     .fetch_all(pool)
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to list runs: {}", e)))?;
@@ -1043,19 +2798,103 @@ pub async fn list_runs(pool: &PgPool, limit: i64, offset: i64) -> Result<Vec<Run
     Ok(results)
 }
 
+pub async fn set_run_visibility(pool: &PgPool, run_id: &Uuid, visibility: RunVisibility) -> Result<(), ApiError> {
+    sqlx::query("UPDATE runs SET visibility = $2 WHERE id = $1")
+        .bind(run_id)
+        .bind(visibility.as_str())
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to update run visibility: {}", e)))?;
+    Ok(())
+}
+
+/// `user_id`'s runs matched by a purge: `$1` the user, `$2` an optional
+/// created-before cutoff and `$3` an optional benchmark id
+const PURGE_FILTER: &str = r#"user_id = $1
+      AND ($2::TIMESTAMPTZ IS NULL OR created_at < $2)
+      AND ($3::VARCHAR IS NULL OR benchmark_id = $3)"#;
+const ON_LEADERBOARD: &str = "EXISTS (SELECT 1 FROM leaderboard_entries l WHERE l.run_id = runs.id)";
+
+/// Counts a purge would touch: (deleted, anonymized)
+pub async fn count_purgeable_runs(
+    pool: &PgPool,
+    user_id: &Uuid,
+    before: Option<DateTime<Utc>>,
+    benchmark_id: Option<&str>,
+) -> Result<(i64, i64), ApiError> {
+    sqlx::query_as(&format!(
+        r#"
+        SELECT COUNT(*) FILTER (WHERE NOT {ON_LEADERBOARD}),
+               COUNT(*) FILTER (WHERE {ON_LEADERBOARD})
+        FROM runs
+        WHERE {PURGE_FILTER}
+        "#
+    ))
+    .bind(user_id)
+    .bind(before)
+    .bind(benchmark_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count runs to purge: {}", e)))
+}
+
+/// Delete `user_id`'s matching runs, except those a leaderboard entry points
+/// at: those are detached from the user and lose their output instead.
+/// Returns (deleted, anonymized)
+pub async fn purge_user_runs(
+    pool: &PgPool,
+    user_id: &Uuid,
+    before: Option<DateTime<Utc>>,
+    benchmark_id: Option<&str>,
+) -> Result<(u64, u64), ApiError> {
+    let db_err = |e: sqlx::Error| ApiError::DatabaseError(format!("Failed to purge runs: {}", e));
+    let mut tx = pool.begin().await.map_err(db_err)?;
+
+    let deleted = sqlx::query(&format!("DELETE FROM runs WHERE {PURGE_FILTER} AND NOT {ON_LEADERBOARD}"))
+        .bind(user_id)
+        .bind(before)
+        .bind(benchmark_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?
+        .rows_affected();
+
+    let anonymized = sqlx::query(&format!(
+        "UPDATE runs SET user_id = NULL, stdout = NULL, stderr = NULL WHERE {PURGE_FILTER} AND {ON_LEADERBOARD}"
+    ))
+    .bind(user_id)
+    .bind(before)
+    .bind(benchmark_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .rows_affected();
+
+    tx.commit().await.map_err(db_err)?;
+    Ok((deleted, anonymized))
+}
+
+/// Runs are stored as language + runtime; benchmark implementations are keyed
+/// by the language they compile as (`deno`, `node`, bun being the default)
+const RUN_COMPILE_LANGUAGE: &str = "COALESCE(NULLIF(runtime, 'bun'), language)";
+
 pub async fn get_min_instructions(
     pool: &PgPool,
     benchmark_id: &str,
+    version: u32,
 ) -> Result<HashMap<String, i64>, ApiError> {
-    let results: Vec<(String, i64)> = sqlx::query_as(
+    let results: Vec<(String, i64)> = sqlx::query_as(&format!(
         r#"
-        SELECT language, MIN(instructions) as min_instructions
+        SELECT {compiled_as} as language, MIN(instructions) as min_instructions
         FROM runs
-        WHERE benchmark_id = $1 AND language IS NOT NULL AND limit_reached = FALSE
-        GROUP BY language
+        WHERE benchmark_id = $1 AND COALESCE(benchmark_version, 1) = $2
+          AND language IS NOT NULL AND limit_reached = FALSE
+        GROUP BY 1
         "#,
-    )
+        compiled_as = RUN_COMPILE_LANGUAGE
+    ))
     .bind(benchmark_id)
+    .bind(version as i32)
     .fetch_all(pool)
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to get min instructions: {}", e)))?;
@@ -1063,6 +2902,189 @@ pub async fn get_min_instructions(
     Ok(results.into_iter().collect())
 }
 
+// ============ Public Stats Functions ============
+
+pub async fn count_runs(pool: &PgPool) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM runs")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to count runs: {}", e)))?;
+
+    Ok(count)
+}
+
+/// (day, language, runs) for the last `days` days, oldest first
+pub async fn count_runs_per_language_per_day(
+    pool: &PgPool,
+    days: i64,
+) -> Result<Vec<(NaiveDate, String, i64)>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT (created_at AT TIME ZONE 'UTC')::date as day, language, COUNT(*)
+        FROM runs
+        WHERE created_at >= date_trunc('day', NOW()) - make_interval(days => $1::int - 1)
+          AND language IS NOT NULL
+        GROUP BY day, language
+        ORDER BY day, language
+        "#,
+    )
+    .bind(days)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count runs per day: {}", e)))
+}
+
+/// Distinct signed-in users with a submission in the last `days` days
+pub async fn count_active_users(pool: &PgPool, days: i64) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(DISTINCT a.user_id)
+        FROM (
+            SELECT user_id FROM submissions WHERE created_at >= NOW() - make_interval(days => $1::int)
+            UNION
+            SELECT user_id FROM challenge_submissions WHERE created_at >= NOW() - make_interval(days => $1::int)
+        ) a
+        JOIN users u ON u.id = a.user_id
+        WHERE u.username <> 'anonymous'
+        "#,
+    )
+    .bind(days)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count active users: {}", e)))?;
+
+    Ok(count)
+}
+
+pub async fn store_public_stat(pool: &PgPool, name: &str, data: &serde_json::Value) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO public_stats (name, data, refreshed_at)
+        VALUES ($1, $2, NOW())
+        ON CONFLICT (name) DO UPDATE SET data = EXCLUDED.data, refreshed_at = EXCLUDED.refreshed_at
+        "#,
+    )
+    .bind(name)
+    .bind(data)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to store public stat: {}", e)))?;
+
+    Ok(())
+}
+
+pub async fn get_public_stat(
+    pool: &PgPool,
+    name: &str,
+) -> Result<Option<(serde_json::Value, DateTime<Utc>)>, ApiError> {
+    sqlx::query_as("SELECT data, refreshed_at FROM public_stats WHERE name = $1")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get public stat: {}", e)))
+}
+
+// ============ Digest Functions ============
+
+/// A day's best result for one benchmark or challenge in one language
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DigestEntry {
+    /// Benchmark or challenge id
+    pub target: String,
+    pub language: String,
+    pub instructions: i64,
+    /// Best before that day, if any
+    pub previous_best: Option<i64>,
+    /// Who set it (challenges only; benchmark runs are anonymous)
+    pub username: Option<String>,
+}
+
+fn day_bounds(day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = day.and_time(chrono::NaiveTime::MIN).and_utc();
+    (start, start + TimeDelta::days(1))
+}
+
+pub async fn get_benchmark_day_bests(pool: &PgPool, day: NaiveDate) -> Result<Vec<DigestEntry>, ApiError> {
+    let (start, end) = day_bounds(day);
+    sqlx::query_as(&format!(
+        r#"
+        WITH day_best AS (
+            SELECT benchmark_id, COALESCE(benchmark_version, 1) as version,
+                   {compiled_as} as language, MIN(instructions) as instructions
+            FROM runs
+            WHERE benchmark_id IS NOT NULL AND language IS NOT NULL AND limit_reached = FALSE
+              AND created_at >= $1 AND created_at < $2
+            GROUP BY benchmark_id, 2, 3
+        )
+        SELECT d.benchmark_id as target, d.language, d.instructions,
+               (SELECT MIN(r.instructions) FROM runs r
+                WHERE r.benchmark_id = d.benchmark_id AND COALESCE(r.benchmark_version, 1) = d.version
+                  AND {compiled_as} = d.language
+                  AND r.limit_reached = FALSE AND r.created_at < $1) as previous_best,
+               NULL::text as username
+        FROM day_best d
+        ORDER BY d.benchmark_id, d.instructions
+        "#,
+        compiled_as = RUN_COMPILE_LANGUAGE
+    ))
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get benchmark day bests: {}", e)))
+}
+
+pub async fn get_challenge_day_bests(pool: &PgPool, day: NaiveDate) -> Result<Vec<DigestEntry>, ApiError> {
+    let (start, end) = day_bounds(day);
+    sqlx::query_as(
+        r#"
+        WITH day_best AS (
+            SELECT DISTINCT ON (cs.challenge_id, cs.language)
+                   cs.challenge_id, cs.language, cs.instructions, u.username
+            FROM challenge_submissions cs
+            JOIN users u ON u.id = cs.user_id
+            WHERE cs.status = 'passed' AND cs.ranked AND cs.instructions IS NOT NULL
+              AND cs.completed_at >= $1 AND cs.completed_at < $2
+            ORDER BY cs.challenge_id, cs.language, cs.instructions, cs.completed_at
+        )
+        SELECT d.challenge_id as target, d.language, d.instructions,
+               (SELECT MIN(p.instructions) FROM challenge_submissions p
+                WHERE p.challenge_id = d.challenge_id AND p.language = d.language
+                  AND p.status = 'passed' AND p.ranked AND p.completed_at < $1) as previous_best,
+               d.username
+        FROM day_best d
+        ORDER BY d.challenge_id, d.instructions
+        "#,
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge day bests: {}", e)))
+}
+
+/// Store a digest unless that day already has one; true if this call stored it
+pub async fn insert_digest(pool: &PgPool, day: NaiveDate, data: &serde_json::Value) -> Result<bool, ApiError> {
+    let result = sqlx::query("INSERT INTO digests (day, data) VALUES ($1, $2) ON CONFLICT (day) DO NOTHING")
+        .bind(day)
+        .bind(data)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to store digest: {}", e)))?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_digest(pool: &PgPool, day: NaiveDate) -> Result<Option<serde_json::Value>, ApiError> {
+    let row: Option<(serde_json::Value,)> = sqlx::query_as("SELECT data FROM digests WHERE day = $1")
+        .bind(day)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get digest: {}", e)))?;
+
+    Ok(row.map(|(data,)| data))
+}
+
 // ============ User Functions ============
 
 pub async fn get_user_by_id(pool: &PgPool, user_id: &Uuid) -> Result<Option<User>, ApiError> {
@@ -1070,7 +3092,7 @@ pub async fn get_user_by_id(pool: &PgPool, user_id: &Uuid) -> Result<Option<User
         r#"
         SELECT id, username, github_id, github_login, avatar_url, display_name, bio,
                twitter_handle, COALESCE(is_verified, FALSE) as is_verified, verified_at, verification_method,
-               COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at
+               COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at, country, affiliation
         FROM users
         WHERE id = $1
         "#,
@@ -1088,7 +3110,7 @@ pub async fn get_user_by_username(pool: &PgPool, username: &str) -> Result<Optio
         r#"
         SELECT id, username, github_id, github_login, avatar_url, display_name, bio,
                twitter_handle, COALESCE(is_verified, FALSE) as is_verified, verified_at, verification_method,
-               COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at
+               COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at, country, affiliation
         FROM users
         WHERE username = $1
         "#,
@@ -1106,7 +3128,7 @@ pub async fn get_user_by_github_id(pool: &PgPool, github_id: i64) -> Result<Opti
         r#"
         SELECT id, username, github_id, github_login, avatar_url, display_name, bio,
                twitter_handle, COALESCE(is_verified, FALSE) as is_verified, verified_at, verification_method,
-               COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at
+               COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at, country, affiliation
         FROM users
         WHERE github_id = $1
         "#,
@@ -1141,7 +3163,7 @@ pub async fn create_or_update_user_from_github(
             display_name = COALESCE(EXCLUDED.display_name, users.display_name)
         RETURNING id, username, github_id, github_login, avatar_url, display_name, bio,
                   twitter_handle, COALESCE(is_verified, FALSE) as is_verified, verified_at, verification_method,
-                  COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at
+                  COALESCE(user_type, 'human') as user_type, clanker_twitter, created_at, country, affiliation
         "#,
     )
     .bind(&data.github_login) // username = github_login initially
@@ -1206,13 +3228,18 @@ pub async fn update_user_profile(
     display_name: Option<&str>,
     bio: Option<&str>,
     twitter_handle: Option<&str>,
+    country: Option<&str>,
+    affiliation: Option<&str>,
 ) -> Result<(), ApiError> {
+    // None leaves a field alone; for country and affiliation, "" clears it
     sqlx::query(
         r#"
         UPDATE users
         SET display_name = COALESCE($2, display_name),
             bio = COALESCE($3, bio),
-            twitter_handle = COALESCE($4, twitter_handle)
+            twitter_handle = COALESCE($4, twitter_handle),
+            country = CASE WHEN $5::text IS NULL THEN country ELSE NULLIF($5, '') END,
+            affiliation = CASE WHEN $6::text IS NULL THEN affiliation ELSE NULLIF($6, '') END
         WHERE id = $1
         "#,
     )
@@ -1220,6 +3247,8 @@ pub async fn update_user_profile(
     .bind(display_name)
     .bind(bio)
     .bind(twitter_handle)
+    .bind(country)
+    .bind(affiliation)
     .execute(pool)
     .await
     .map_err(|e| ApiError::DatabaseError(format!("Failed to update user profile: {}", e)))?;
@@ -1297,576 +3326,2922 @@ pub async fn cleanup_expired_sessions(pool: &PgPool) -> Result<u64, ApiError> {
     Ok(result.rows_affected())
 }
 
-// ============ Challenge Functions ============
+// ============ Anonymous Token Functions ============
 
-pub async fn get_challenge(pool: &PgPool, challenge_id: &str) -> Result<Option<Challenge>, ApiError> {
-    let result: Option<Challenge> = sqlx::query_as(
-        r#"
-        SELECT id, name, description, category, difficulty, input_spec, output_spec,
-               test_cases, verify_mode, is_active, created_at,
-               COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines
-        FROM challenges
-        WHERE id = $1
-        "#,
-    )
-    .bind(challenge_id)
+pub async fn create_anonymous_token(
+    pool: &PgPool,
+    token_hash: &str,
+    ip: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    sqlx::query(r#"INSERT INTO anonymous_tokens (token_hash, ip, expires_at) VALUES ($1, $2, $3)"#)
+        .bind(token_hash)
+        .bind(ip)
+        .bind(expires_at)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to create anonymous token: {}", e)))?;
+
+    Ok(())
+}
+
+pub enum AnonymousTokenUse {
+    Counted,
+    /// Already at the limit; holds the count
+    UsedUp(i32),
+    /// Unknown or expired
+    Invalid,
+}
+
+/// Count one use of a live token against `column` (`submissions` or
+/// `compiles`) unless it has reached `max`
+pub async fn use_anonymous_token(
+    pool: &PgPool,
+    token_hash: &str,
+    column: &'static str,
+    max: u32,
+) -> Result<AnonymousTokenUse, ApiError> {
+    let used: Option<(i32,)> = sqlx::query_as(&format!(
+        "UPDATE anonymous_tokens SET {column} = {column} + 1 \
+         WHERE token_hash = $1 AND expires_at > NOW() AND {column} < $2 RETURNING {column}"
+    ))
+    .bind(token_hash)
+    .bind(max as i32)
     .fetch_optional(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to use anonymous token: {}", e)))?;
+    if used.is_some() {
+        return Ok(AnonymousTokenUse::Counted);
+    }
 
-    Ok(result)
+    let current: Option<(i32,)> = sqlx::query_as(&format!(
+        "SELECT {column} FROM anonymous_tokens WHERE token_hash = $1 AND expires_at > NOW()"
+    ))
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get anonymous token: {}", e)))?;
+
+    Ok(match current {
+        Some((used,)) => AnonymousTokenUse::UsedUp(used),
+        None => AnonymousTokenUse::Invalid,
+    })
 }
 
-pub async fn list_challenges(pool: &PgPool, active_only: bool) -> Result<Vec<Challenge>, ApiError> {
-    let results: Vec<Challenge> = if active_only {
-        sqlx::query_as(
-            r#"
-            SELECT id, name, description, category, difficulty, input_spec, output_spec,
-                   test_cases, verify_mode, is_active, created_at,
-                   COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines
-            FROM challenges
-            WHERE is_active = TRUE
-            ORDER BY created_at ASC
-            "#,
-        )
-        .fetch_all(pool)
-        .await
-    } else {
-        sqlx::query_as(
-            r#"
-            SELECT id, name, description, category, difficulty, input_spec, output_spec,
-                   test_cases, verify_mode, is_active, created_at,
-                   COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines
-            FROM challenges
-            ORDER BY created_at ASC
-            "#,
-        )
-        .fetch_all(pool)
+pub async fn cleanup_expired_anonymous_tokens(pool: &PgPool) -> Result<u64, ApiError> {
+    let result = sqlx::query(r#"DELETE FROM anonymous_tokens WHERE expires_at < NOW()"#)
+        .execute(pool)
         .await
-    }
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to list challenges: {}", e)))?;
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to cleanup anonymous tokens: {}", e)))?;
 
-    Ok(results)
+    Ok(result.rows_affected())
 }
 
-pub async fn create_challenge(
-    pool: &PgPool,
-    id: &str,
-    name: &str,
-    description: &str,
-    category: &str,
-    difficulty: &str,
-    input_spec: Option<&str>,
-    output_spec: &str,
-    test_cases: &serde_json::Value,
-    verify_mode: &str,
-    network_enabled: bool,
-    env_vars: Option<&serde_json::Value>,
-    baselines: Option<&serde_json::Value>,
-) -> Result<Challenge, ApiError> {
-    let result: Challenge = sqlx::query_as(
-        r#"
-        INSERT INTO challenges (id, name, description, category, difficulty, input_spec, output_spec, test_cases, verify_mode, network_enabled, env_vars, baselines)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
-        ON CONFLICT (id) DO UPDATE SET
-            name = EXCLUDED.name,
-            description = EXCLUDED.description,
-            category = EXCLUDED.category,
-            difficulty = EXCLUDED.difficulty,
-            input_spec = EXCLUDED.input_spec,
-            output_spec = EXCLUDED.output_spec,
-            test_cases = EXCLUDED.test_cases,
-            verify_mode = EXCLUDED.verify_mode,
-            network_enabled = EXCLUDED.network_enabled,
-            env_vars = EXCLUDED.env_vars,
-            baselines = EXCLUDED.baselines
-        RETURNING id, name, description, category, difficulty, input_spec, output_spec,
-                  test_cases, verify_mode, is_active, created_at,
-                  COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines
-        "#,
-    )
-    .bind(id)
-    .bind(name)
-    .bind(description)
-    .bind(category)
-    .bind(difficulty)
-    .bind(input_spec)
-    .bind(output_spec)
-    .bind(test_cases)
-    .bind(verify_mode)
-    .bind(network_enabled)
-    .bind(env_vars)
-    .bind(baselines)
-    .fetch_one(pool)
-    .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge: {}", e)))?;
+// ============ Pinned Result Functions ============
 
-    Ok(result)
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PinnedResult {
+    pub job_id: Uuid,
+    pub until_fetched: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// `JobMetadata` once the job has finished and been mirrored
+    pub metadata: Option<serde_json::Value>,
+    /// `ExecutionResult`, for completed jobs
+    pub result: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
 }
 
-// ============ Challenge Submission Functions ============
+const PIN_IS_LIVE: &str = "(until_fetched OR expires_at > NOW())";
 
-pub async fn create_challenge_submission(
+/// Pin a job until its result is fetched and/or until `expires_at`; pinning
+/// again only ever extends a pin
+pub async fn pin_job(
     pool: &PgPool,
-    user_id: &Uuid,
-    challenge_id: &str,
-    language: &str,
-    source_code: &str,
-) -> Result<ChallengeSubmission, ApiError> {
-    let result: ChallengeSubmission = sqlx::query_as(
+    job_id: &Uuid,
+    user_id: Option<&Uuid>,
+    until_fetched: bool,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<PinnedResult, ApiError> {
+    let result: PinnedResult = sqlx::query_as(
         r#"
-        INSERT INTO challenge_submissions (user_id, challenge_id, language, source_code)
+        INSERT INTO pinned_results (job_id, user_id, until_fetched, expires_at)
         VALUES ($1, $2, $3, $4)
-        RETURNING id, user_id, challenge_id, language, source_code, binary_id, status,
-                  test_results, instructions, error_message, created_at, completed_at
+        ON CONFLICT (job_id) DO UPDATE SET
+            user_id = COALESCE(pinned_results.user_id, EXCLUDED.user_id),
+            until_fetched = pinned_results.until_fetched OR EXCLUDED.until_fetched,
+            expires_at = GREATEST(pinned_results.expires_at, EXCLUDED.expires_at)
+        RETURNING job_id, until_fetched, expires_at, metadata, result, created_at
         "#,
     )
+    .bind(job_id)
     .bind(user_id)
-    .bind(challenge_id)
-    .bind(language)
-    .bind(source_code)
+    .bind(until_fetched)
+    .bind(expires_at)
     .fetch_one(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge submission: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to pin job: {}", e)))?;
 
     Ok(result)
 }
 
-pub async fn get_challenge_submission(pool: &PgPool, submission_id: &Uuid) -> Result<Option<ChallengeSubmission>, ApiError> {
-    let result: Option<ChallengeSubmission> = sqlx::query_as(
-        r#"
-        SELECT id, user_id, challenge_id, language, source_code, binary_id, status,
-               test_results, instructions, error_message, created_at, completed_at
-        FROM challenge_submissions
-        WHERE id = $1
-        "#,
-    )
-    .bind(submission_id)
+pub async fn get_pinned_result(pool: &PgPool, job_id: &Uuid) -> Result<Option<PinnedResult>, ApiError> {
+    let result: Option<PinnedResult> = sqlx::query_as(&format!(
+        "SELECT job_id, until_fetched, expires_at, metadata, result, created_at \
+         FROM pinned_results WHERE job_id = $1 AND {PIN_IS_LIVE}"
+    ))
+    .bind(job_id)
     .fetch_optional(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge submission: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get pinned result: {}", e)))?;
 
     Ok(result)
 }
 
-pub async fn update_challenge_submission_status(
+/// Live pins whose job hasn't been mirrored yet, oldest first
+pub async fn list_unmirrored_pins(pool: &PgPool, limit: i64) -> Result<Vec<(Uuid, DateTime<Utc>)>, ApiError> {
+    let result: Vec<(Uuid, DateTime<Utc>)> = sqlx::query_as(&format!(
+        "SELECT job_id, created_at FROM pinned_results \
+         WHERE metadata IS NULL AND {PIN_IS_LIVE} ORDER BY created_at LIMIT $1"
+    ))
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list unmirrored pins: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn store_pinned_result(
     pool: &PgPool,
-    submission_id: &Uuid,
-    status: &str,
-    binary_id: Option<&str>,
-    test_results: Option<&serde_json::Value>,
-    instructions: Option<i64>,
-    error_message: Option<&str>,
+    job_id: &Uuid,
+    metadata: &serde_json::Value,
+    result: Option<&serde_json::Value>,
 ) -> Result<(), ApiError> {
-    let completed_at = if status == "passed" || status == "failed" {
-        Some(Utc::now())
-    } else {
-        None
-    };
+    sqlx::query(r#"UPDATE pinned_results SET metadata = $2, result = $3 WHERE job_id = $1"#)
+        .bind(job_id)
+        .bind(metadata)
+        .bind(result)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to store pinned result: {}", e)))?;
 
-    sqlx::query(
-        r#"
-        UPDATE challenge_submissions
-        SET status = $2,
-            binary_id = COALESCE($3, binary_id),
-            test_results = COALESCE($4, test_results),
-            instructions = COALESCE($5, instructions),
-            error_message = COALESCE($6, error_message),
-            completed_at = COALESCE($7, completed_at)
-        WHERE id = $1
-        "#,
-    )
-    .bind(submission_id)
-    .bind(status)
-    .bind(binary_id)
-    .bind(test_results)
-    .bind(instructions)
-    .bind(error_message)
-    .bind(completed_at)
-    .execute(pool)
-    .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to update challenge submission: {}", e)))?;
+    Ok(())
+}
+
+/// The result has been fetched; a pin that was only waiting for that lapses
+pub async fn release_fetched_pin(pool: &PgPool, job_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE pinned_results SET until_fetched = FALSE WHERE job_id = $1 AND until_fetched"#)
+        .bind(job_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to release pinned result: {}", e)))?;
 
     Ok(())
 }
 
-// ============ Leaderboard Functions ============
+pub async fn delete_pinned_result(pool: &PgPool, job_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"DELETE FROM pinned_results WHERE job_id = $1"#)
+        .bind(job_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to delete pinned result: {}", e)))?;
 
-pub async fn update_leaderboard_entry(
+    Ok(())
+}
+
+pub async fn cleanup_expired_pins(pool: &PgPool) -> Result<u64, ApiError> {
+    let result = sqlx::query(&format!("DELETE FROM pinned_results WHERE NOT {PIN_IS_LIVE}"))
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to cleanup pinned results: {}", e)))?;
+
+    Ok(result.rows_affected())
+}
+
+// ============ Tracked Solution Functions ============
+
+const TRACKED_SOLUTION_COLUMNS: &str =
+    "id, user_id, benchmark_id, language, optimization, source_code, next_run_at, created_at";
+
+/// Track `source_code` for the user's `benchmark_id`, replacing what was
+/// tracked before; it's re-run on the next pass
+pub async fn upsert_tracked_solution(
     pool: &PgPool,
     user_id: &Uuid,
-    challenge_id: &str,
+    benchmark_id: &str,
     language: &str,
-    instructions: i64,
-    run_id: &Uuid,
+    optimization: Option<&str>,
     source_code: &str,
-    is_verified: bool,
-) -> Result<LeaderboardEntry, ApiError> {
-    // Only update if this is a better score (lower instructions)
-    let result: LeaderboardEntry = sqlx::query_as(
+) -> Result<TrackedSolution, ApiError> {
+    sqlx::query_as(&format!(
         r#"
-        INSERT INTO leaderboard_entries (user_id, challenge_id, language, instructions, run_id, source_code, is_verified)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
-        ON CONFLICT (user_id, challenge_id, language) DO UPDATE SET
-            instructions = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
-                               THEN EXCLUDED.instructions
-                               ELSE leaderboard_entries.instructions END,
-            run_id = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
-                         THEN EXCLUDED.run_id
-                         ELSE leaderboard_entries.run_id END,
-            source_code = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
-                              THEN EXCLUDED.source_code
-                              ELSE leaderboard_entries.source_code END,
-            is_verified = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
-                              THEN EXCLUDED.is_verified
-                              ELSE leaderboard_entries.is_verified END,
-            created_at = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
-                             THEN NOW()
-                             ELSE leaderboard_entries.created_at END
-        RETURNING id, user_id, challenge_id, language, instructions, run_id, source_code, is_verified, created_at
-        "#,
-    )
+        INSERT INTO tracked_solutions (user_id, benchmark_id, language, optimization, source_code)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, benchmark_id) DO UPDATE SET
+            language = EXCLUDED.language,
+            optimization = EXCLUDED.optimization,
+            source_code = EXCLUDED.source_code,
+            next_run_at = NOW()
+        RETURNING {TRACKED_SOLUTION_COLUMNS}
+        "#
+    ))
     .bind(user_id)
-    .bind(challenge_id)
+    .bind(benchmark_id)
     .bind(language)
-    .bind(instructions)
-    .bind(run_id)
+    .bind(optimization)
     .bind(source_code)
-    .bind(is_verified)
     .fetch_one(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to update leaderboard entry: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to track solution: {}", e)))
+}
 
-    Ok(result)
+pub async fn get_tracked_solution(
+    pool: &PgPool,
+    user_id: &Uuid,
+    benchmark_id: &str,
+) -> Result<Option<TrackedSolution>, ApiError> {
+    sqlx::query_as(&format!(
+        "SELECT {TRACKED_SOLUTION_COLUMNS} FROM tracked_solutions WHERE user_id = $1 AND benchmark_id = $2"
+    ))
+    .bind(user_id)
+    .bind(benchmark_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get tracked solution: {}", e)))
 }
 
-pub async fn get_challenge_leaderboard(
+pub async fn list_tracked_solutions(pool: &PgPool, user_id: &Uuid) -> Result<Vec<TrackedSolution>, ApiError> {
+    sqlx::query_as(&format!(
+        "SELECT {TRACKED_SOLUTION_COLUMNS} FROM tracked_solutions WHERE user_id = $1 ORDER BY benchmark_id"
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list tracked solutions: {}", e)))
+}
+
+pub async fn count_tracked_solutions(pool: &PgPool, user_id: &Uuid) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tracked_solutions WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to count tracked solutions: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Also drops the solution's history
+pub async fn delete_tracked_solution(pool: &PgPool, user_id: &Uuid, benchmark_id: &str) -> Result<bool, ApiError> {
+    let result = sqlx::query("DELETE FROM tracked_solutions WHERE user_id = $1 AND benchmark_id = $2")
+        .bind(user_id)
+        .bind(benchmark_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to delete tracked solution: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Take up to `limit` solutions that are due and push them `interval_days`
+/// out; rows another replica is claiming are skipped
+pub async fn claim_due_tracked_solutions(
     pool: &PgPool,
-    challenge_id: &str,
-    language: Option<&str>,
-    user_type: Option<&str>,
+    interval_days: u64,
     limit: i64,
-) -> Result<Vec<LeaderboardEntryWithUser>, ApiError> {
-    let results: Vec<(i64, Uuid, String, Option<String>, Option<String>, Option<String>, bool, String, DateTime<Utc>, i64, String, DateTime<Utc>)> =
-        if let Some(lang) = language {
-            if let Some(utype) = user_type {
-                sqlx::query_as(
-                    r#"
-                    SELECT
-                        ROW_NUMBER() OVER (ORDER BY le.instructions ASC) as rank,
-                        u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
-                        COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
-                        le.instructions, le.language, le.created_at as submitted_at
-                    FROM leaderboard_entries le
-                    JOIN users u ON le.user_id = u.id
-                    WHERE le.challenge_id = $1 AND le.language = $2 AND COALESCE(u.user_type, 'human') = $4
-                    ORDER BY le.instructions ASC
-                    LIMIT $3
-                    "#,
-                )
-                .bind(challenge_id)
-                .bind(lang)
-                .bind(limit)
-                .bind(utype)
-                .fetch_all(pool)
-                .await
-            } else {
-                sqlx::query_as(
-                    r#"
-                    SELECT
-                        ROW_NUMBER() OVER (ORDER BY le.instructions ASC) as rank,
-                        u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
-                        COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
-                        le.instructions, le.language, le.created_at as submitted_at
-                    FROM leaderboard_entries le
-                    JOIN users u ON le.user_id = u.id
-                    WHERE le.challenge_id = $1 AND le.language = $2
-                    ORDER BY le.instructions ASC
-                    LIMIT $3
-                    "#,
-                )
-                .bind(challenge_id)
-                .bind(lang)
-                .bind(limit)
-                .fetch_all(pool)
-                .await
-            }
-        } else if let Some(utype) = user_type {
-            sqlx::query_as(
-                r#"
-                SELECT
-                    ROW_NUMBER() OVER (PARTITION BY le.language ORDER BY le.instructions ASC) as rank,
-                    u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
-                    COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
-                    le.instructions, le.language, le.created_at as submitted_at
-                FROM leaderboard_entries le
-                JOIN users u ON le.user_id = u.id
-                WHERE le.challenge_id = $1 AND COALESCE(u.user_type, 'human') = $3
-                ORDER BY le.language, le.instructions ASC
-                LIMIT $2
-                "#,
-            )
-            .bind(challenge_id)
-            .bind(limit)
-            .bind(utype)
-            .fetch_all(pool)
-            .await
-        } else {
-            sqlx::query_as(
-                r#"
-                SELECT
-                    ROW_NUMBER() OVER (PARTITION BY le.language ORDER BY le.instructions ASC) as rank,
-                    u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
-                    COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
-                    le.instructions, le.language, le.created_at as submitted_at
-                FROM leaderboard_entries le
-                JOIN users u ON le.user_id = u.id
-                WHERE le.challenge_id = $1
-                ORDER BY le.language, le.instructions ASC
-                LIMIT $2
-                "#,
-            )
-            .bind(challenge_id)
-            .bind(limit)
-            .fetch_all(pool)
-            .await
-        }
-        .map_err(|e| ApiError::DatabaseError(format!("Failed to get leaderboard: {}", e)))?;
+) -> Result<Vec<TrackedSolution>, ApiError> {
+    sqlx::query_as(&format!(
+        r#"
+        UPDATE tracked_solutions SET next_run_at = NOW() + make_interval(days => $1)
+        WHERE id IN (
+            SELECT id FROM tracked_solutions
+            WHERE next_run_at <= NOW()
+            ORDER BY next_run_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING {TRACKED_SOLUTION_COLUMNS}
+        "#
+    ))
+    .bind(interval_days as i32)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to claim tracked solutions: {}", e)))
+}
 
-    Ok(results
-        .into_iter()
-        .map(|(rank, id, username, avatar_url, display_name, twitter_handle, is_verified, user_type, created_at, instructions, language, submitted_at)| {
-            LeaderboardEntryWithUser {
-                rank,
-                user: PublicUser {
-                    id,
-                    username,
-                    avatar_url,
-                    display_name,
-                    bio: None,
-                    twitter_handle,
-                    is_verified,
-                    user_type,
-                    created_at,
-                },
-                instructions,
-                language,
-                submitted_at,
-            }
-        })
-        .collect())
+pub async fn record_tracked_solution_run(pool: &PgPool, run: &TrackedSolutionRun) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO tracked_solution_runs
+            (tracked_solution_id, run_id, benchmark_version, compiler_version, instructions, error, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(run.tracked_solution_id)
+    .bind(run.run_id)
+    .bind(run.benchmark_version)
+    .bind(&run.compiler_version)
+    .bind(run.instructions)
+    .bind(&run.error)
+    .bind(run.created_at)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record tracked solution run: {}", e)))?;
+
+    Ok(())
 }
 
-pub async fn get_user_challenge_stats(
+/// Newest first
+pub async fn list_tracked_solution_runs(
     pool: &PgPool,
-    user_id: &Uuid,
-) -> Result<Vec<LeaderboardEntry>, ApiError> {
-    let results: Vec<LeaderboardEntry> = sqlx::query_as(
+    tracked_solution_id: &Uuid,
+    limit: i64,
+) -> Result<Vec<TrackedSolutionRun>, ApiError> {
+    sqlx::query_as(
         r#"
-        SELECT id, user_id, challenge_id, language, instructions, run_id, source_code, is_verified, created_at
-        FROM leaderboard_entries
-        WHERE user_id = $1
-        ORDER BY challenge_id, language
+        SELECT tracked_solution_id, run_id, benchmark_version, compiler_version, instructions, error, created_at
+        FROM tracked_solution_runs
+        WHERE tracked_solution_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
         "#,
     )
-    .bind(user_id)
+    .bind(tracked_solution_id)
+    .bind(limit)
     .fetch_all(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to get user challenge stats: {}", e)))?;
-
-    Ok(results)
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list tracked solution runs: {}", e)))
 }
 
-// ============ Verification Code Functions ============
+// ============ Repo Link Functions ============
 
-pub async fn create_verification_code(
+const REPO_LINK_COLUMNS: &str =
+    "id, user_id, repo, path, branch, language, optimization, benchmark_id, challenge_id, created_at";
+
+/// Link `path` in `repo` for the user, replacing what the link ran before
+#[allow(clippy::too_many_arguments)]
+pub async fn upsert_repo_link(
     pool: &PgPool,
     user_id: &Uuid,
-    code: &str,
-    twitter_handle: &str,
-    expires_at: DateTime<Utc>,
-) -> Result<VerificationCode, ApiError> {
-    let result: VerificationCode = sqlx::query_as(
+    repo: &str,
+    path: &str,
+    branch: Option<&str>,
+    language: &str,
+    optimization: Option<&str>,
+    benchmark_id: Option<&str>,
+    challenge_id: Option<&str>,
+) -> Result<RepoLink, ApiError> {
+    sqlx::query_as(&format!(
         r#"
-        INSERT INTO verification_codes (user_id, code, twitter_handle, expires_at)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id, user_id, code, twitter_handle, expires_at, verified, created_at
-        "#,
-    )
+        INSERT INTO repo_links (user_id, repo, path, branch, language, optimization, benchmark_id, challenge_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (user_id, repo, path) DO UPDATE SET
+            branch = EXCLUDED.branch,
+            language = EXCLUDED.language,
+            optimization = EXCLUDED.optimization,
+            benchmark_id = EXCLUDED.benchmark_id,
+            challenge_id = EXCLUDED.challenge_id
+        RETURNING {REPO_LINK_COLUMNS}
+        "#
+    ))
     .bind(user_id)
-    .bind(code)
-    .bind(twitter_handle)
-    .bind(expires_at)
+    .bind(repo)
+    .bind(path)
+    .bind(branch)
+    .bind(language)
+    .bind(optimization)
+    .bind(benchmark_id)
+    .bind(challenge_id)
     .fetch_one(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to create verification code: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to link repo: {}", e)))
+}
 
-    Ok(result)
+pub async fn list_repo_links(pool: &PgPool, user_id: &Uuid) -> Result<Vec<RepoLink>, ApiError> {
+    sqlx::query_as(&format!(
+        "SELECT {REPO_LINK_COLUMNS} FROM repo_links WHERE user_id = $1 ORDER BY repo, path"
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list repo links: {}", e)))
 }
 
-pub async fn get_verification_code(pool: &PgPool, user_id: &Uuid) -> Result<Option<VerificationCode>, ApiError> {
-    let result: Option<VerificationCode> = sqlx::query_as(
+/// Every user's links to `repo` (lower-case `owner/name`)
+pub async fn list_repo_links_for_repo(pool: &PgPool, repo: &str) -> Result<Vec<RepoLink>, ApiError> {
+    sqlx::query_as(&format!("SELECT {REPO_LINK_COLUMNS} FROM repo_links WHERE repo = $1"))
+        .bind(repo)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to list repo links: {}", e)))
+}
+
+pub async fn count_repo_links(pool: &PgPool, user_id: &Uuid) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM repo_links WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to count repo links: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Also drops the link's push history
+pub async fn delete_repo_link(pool: &PgPool, user_id: &Uuid, link_id: &Uuid) -> Result<bool, ApiError> {
+    let result = sqlx::query("DELETE FROM repo_links WHERE id = $1 AND user_id = $2")
+        .bind(link_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to delete repo link: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn record_repo_push(pool: &PgPool, push: &RepoPush) -> Result<(), ApiError> {
+    sqlx::query(
         r#"
-        SELECT id, user_id, code, twitter_handle, expires_at, verified, created_at
-        FROM verification_codes
-        WHERE user_id = $1 AND expires_at > NOW() AND verified = FALSE
-        ORDER BY created_at DESC
-        LIMIT 1
+        INSERT INTO repo_pushes (link_id, commit_sha, run_id, submission_id, instructions, error, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
         "#,
     )
-    .bind(user_id)
-    .fetch_optional(pool)
+    .bind(push.link_id)
+    .bind(&push.commit_sha)
+    .bind(push.run_id)
+    .bind(push.submission_id)
+    .bind(push.instructions)
+    .bind(&push.error)
+    .bind(push.created_at)
+    .execute(pool)
     .await
-    .map_err(|e| ApiError::DatabaseError(format!("Failed to get verification code: {}", e)))?;
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record repo push: {}", e)))?;
 
-    Ok(result)
+    Ok(())
 }
 
-pub async fn mark_verification_code_used(pool: &PgPool, code_id: &Uuid) -> Result<(), ApiError> {
-    sqlx::query(r#"UPDATE verification_codes SET verified = TRUE WHERE id = $1"#)
-        .bind(code_id)
+/// Remember a webhook delivery; false if it was already seen
+pub async fn record_github_delivery(pool: &PgPool, delivery_id: &str) -> Result<bool, ApiError> {
+    let result = sqlx::query("INSERT INTO github_deliveries (delivery_id) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(delivery_id)
         .execute(pool)
         .await
-        .map_err(|e| ApiError::DatabaseError(format!("Failed to mark verification code used: {}", e)))?;
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to record GitHub delivery: {}", e)))?;
 
-    Ok(())
+    Ok(result.rows_affected() > 0)
 }
 
-// ============ Global Leaderboard ============
+/// GitHub redelivers for a few days at most
+pub async fn cleanup_old_github_deliveries(pool: &PgPool) -> Result<u64, ApiError> {
+    let cutoff = Utc::now() - TimeDelta::days(7);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GlobalLeaderboardEntry {
-    pub rank: i64,
-    pub user: PublicUser,
-    pub total_score: i64,
-    pub challenges_completed: i64,
-    pub first_places: i64,
+    let result = sqlx::query("DELETE FROM github_deliveries WHERE received_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to cleanup GitHub deliveries: {}", e)))?;
+
+    Ok(result.rows_affected())
 }
 
-pub async fn get_global_leaderboard(
-    pool: &PgPool,
-    user_type: Option<&str>,
-    limit: i64,
-) -> Result<Vec<GlobalLeaderboardEntry>, ApiError> {
-    // Score = sum of (best_in_language / user_instructions * 1000) for each entry
-    // Plus bonus for #1 positions
-    let results: Vec<(i64, Uuid, String, Option<String>, Option<String>, Option<String>, bool, String, DateTime<Utc>, i64, i64, i64)> =
-        if let Some(utype) = user_type {
-            sqlx::query_as(
-                r#"
-                WITH user_scores AS (
-                    SELECT
-                        le.user_id,
-                        COUNT(DISTINCT le.challenge_id) as challenges_completed,
-                        SUM(
-                            CASE
-                                WHEN le.instructions = (
-                                    SELECT MIN(le2.instructions)
-                                    FROM leaderboard_entries le2
-                                    WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
-                                ) THEN 1000
-                                ELSE (
-                                    SELECT MIN(le2.instructions)::float / le.instructions::float * 1000
-                                    FROM leaderboard_entries le2
-                                    WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
-                                )::bigint
-                            END
-                        ) as total_score,
-                        SUM(
-                            CASE WHEN le.instructions = (
-                                SELECT MIN(le2.instructions)
-                                FROM leaderboard_entries le2
-                                WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
-                            ) THEN 1 ELSE 0 END
-                        ) as first_places
-                    FROM leaderboard_entries le
-                    JOIN users u ON le.user_id = u.id
-                    WHERE COALESCE(u.user_type, 'human') = $2
-                    GROUP BY le.user_id
-                )
-                SELECT
-                    ROW_NUMBER() OVER (ORDER BY us.total_score DESC) as rank,
-                    u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
-                    COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
-                    us.total_score, us.challenges_completed, us.first_places
-                FROM user_scores us
-                JOIN users u ON us.user_id = u.id
-                ORDER BY us.total_score DESC
-                LIMIT $1
-                "#,
-            )
-            .bind(limit)
-            .bind(utype)
-            .fetch_all(pool)
-            .await
-        } else {
-            sqlx::query_as(
-                r#"
-                WITH user_scores AS (
-                    SELECT
-                        le.user_id,
-                        COUNT(DISTINCT le.challenge_id) as challenges_completed,
-                        SUM(
-                            CASE
-                                WHEN le.instructions = (
-                                    SELECT MIN(le2.instructions)
-                                    FROM leaderboard_entries le2
-                                    WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
-                                ) THEN 1000
-                                ELSE (
-                                    SELECT MIN(le2.instructions)::float / le.instructions::float * 1000
-                                    FROM leaderboard_entries le2
-                                    WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
-                                )::bigint
-                            END
-                        ) as total_score,
-                        SUM(
-                            CASE WHEN le.instructions = (
-                                SELECT MIN(le2.instructions)
-                                FROM leaderboard_entries le2
-                                WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
-                            ) THEN 1 ELSE 0 END
-                        ) as first_places
-                    FROM leaderboard_entries le
-                    GROUP BY le.user_id
-                )
-                SELECT
-                    ROW_NUMBER() OVER (ORDER BY us.total_score DESC) as rank,
-                    u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
-                    COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
-                    us.total_score, us.challenges_completed, us.first_places
-                FROM user_scores us
-                JOIN users u ON us.user_id = u.id
-                ORDER BY us.total_score DESC
-                LIMIT $1
-                "#,
-            )
-            .bind(limit)
-            .fetch_all(pool)
-            .await
-        }
-        .map_err(|e| ApiError::DatabaseError(format!("Failed to get global leaderboard: {}", e)))?;
+/// Newest first
+pub async fn list_repo_pushes(pool: &PgPool, link_id: &Uuid, limit: i64) -> Result<Vec<RepoPush>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT link_id, commit_sha, run_id, submission_id, instructions, error, created_at
+        FROM repo_pushes
+        WHERE link_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(link_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list repo pushes: {}", e)))
+}
 
-    Ok(results
-        .into_iter()
-        .map(|(rank, id, username, avatar_url, display_name, twitter_handle, is_verified, user_type, created_at, total_score, challenges_completed, first_places)| {
-            GlobalLeaderboardEntry {
-                rank,
-                user: PublicUser {
-                    id,
-                    username,
-                    avatar_url,
-                    display_name,
-                    bio: None,
-                    twitter_handle,
-                    is_verified,
-                    user_type,
-                    created_at,
-                },
-                total_score,
-                challenges_completed,
-                first_places,
-            }
-        })
-        .collect())
+// ============ Share Link Functions ============
+
+/// The run's short link code, created as `code` if it has none yet
+pub async fn get_or_create_share_link(pool: &PgPool, run_id: &Uuid, code: &str) -> Result<String, ApiError> {
+    let (code,): (String,) = sqlx::query_as(
+        r#"
+        WITH inserted AS (
+            INSERT INTO share_links (code, run_id) VALUES ($1, $2)
+            ON CONFLICT (run_id) DO NOTHING
+            RETURNING code
+        )
+        SELECT code FROM inserted
+        UNION ALL
+        SELECT code FROM share_links WHERE run_id = $2
+        LIMIT 1
+        "#,
+    )
+    .bind(code)
+    .bind(run_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create share link: {}", e)))?;
+
+    Ok(code)
+}
+
+pub async fn get_share_link_run(pool: &PgPool, code: &str) -> Result<Option<Uuid>, ApiError> {
+    let row: Option<(Uuid,)> = sqlx::query_as("SELECT run_id FROM share_links WHERE code = $1")
+        .bind(code)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get share link: {}", e)))?;
+
+    Ok(row.map(|(run_id,)| run_id))
+}
+
+/// Where a benchmark run places among public clean runs in its language at
+/// the same benchmark version: (rank, out of)
+pub async fn get_run_rank(pool: &PgPool, run: &Run) -> Result<Option<(i64, i64)>, ApiError> {
+    let (Some(benchmark_id), Some(language)) = (&run.benchmark_id, &run.language) else {
+        return Ok(None);
+    };
+    // Runs that don't count themselves aren't ranked
+    if run.limit_reached || run.exit_code != Some(0) || run.mode.is_some() {
+        return Ok(None);
+    }
+    let (better, total): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FILTER (WHERE instructions < $4), COUNT(*)
+        FROM runs
+        WHERE benchmark_id = $1 AND COALESCE(benchmark_version, 1) = $2 AND language = $3
+          AND limit_reached = FALSE AND exit_code = 0 AND mode IS NULL AND visibility = 'public'
+        "#,
+    )
+    .bind(benchmark_id)
+    .bind(run.benchmark_version.unwrap_or(1))
+    .bind(language)
+    .bind(run.instructions)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to rank run: {}", e)))?;
+
+    Ok(Some((better + 1, total.max(better + 1))))
+}
+
+// ============ Challenge Functions ============
+
+/// Every challenge's stored env vars, sealed or not
+pub async fn get_challenge_env_vars(pool: &PgPool) -> Result<Vec<(String, serde_json::Value)>, ApiError> {
+    sqlx::query_as("SELECT id, env_vars FROM challenges WHERE env_vars IS NOT NULL")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge env vars: {}", e)))
+}
+
+pub async fn set_challenge_env_vars(pool: &PgPool, challenge_id: &str, env_vars: &serde_json::Value) -> Result<(), ApiError> {
+    sqlx::query("UPDATE challenges SET env_vars = $2 WHERE id = $1")
+        .bind(challenge_id)
+        .bind(env_vars)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to update challenge env vars: {}", e)))?;
+    Ok(())
+}
+
+/// Who imported a challenge, when, and its content signature
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChallengeProvenance {
+    pub challenge_id: String,
+    pub imported_by: Option<String>,
+    pub imported_at: Option<DateTime<Utc>>,
+    pub signature: Option<String>,
+    pub signing_key_id: Option<String>,
+}
+
+/// Record an import; a `None` signature clears any older one
+pub async fn set_challenge_provenance(
+    pool: &PgPool,
+    challenge_id: &str,
+    imported_by: &str,
+    signature: Option<&str>,
+    signing_key_id: Option<&str>,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        UPDATE challenges
+        SET imported_by = $2, imported_at = NOW(), signature = $3, signing_key_id = $4
+        WHERE id = $1
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(imported_by)
+    .bind(signature)
+    .bind(signing_key_id)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record challenge provenance: {}", e)))?;
+    Ok(())
+}
+
+pub async fn get_challenge_provenance(pool: &PgPool, challenge_id: &str) -> Result<Option<ChallengeProvenance>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT id as challenge_id, imported_by, imported_at, signature, signing_key_id
+        FROM challenges
+        WHERE id = $1
+        "#,
+    )
+    .bind(challenge_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge provenance: {}", e)))
+}
+
+pub async fn list_challenge_provenance(pool: &PgPool) -> Result<Vec<ChallengeProvenance>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT id as challenge_id, imported_by, imported_at, signature, signing_key_id
+        FROM challenges
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list challenge provenance: {}", e)))
+}
+
+/// Set or clear a challenge's availability window; false if it doesn't exist
+pub async fn set_challenge_window(
+    pool: &PgPool,
+    challenge_id: &str,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+) -> Result<bool, ApiError> {
+    let result = sqlx::query("UPDATE challenges SET starts_at = $2, ends_at = $3 WHERE id = $1")
+        .bind(challenge_id)
+        .bind(starts_at)
+        .bind(ends_at)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to set challenge window: {}", e)))?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_challenge(pool: &PgPool, challenge_id: &str) -> Result<Option<Challenge>, ApiError> {
+    let result: Option<Challenge> = sqlx::query_as(
+        r#"
+        SELECT id, name, description, category, difficulty, input_spec, output_spec,
+               test_cases, verify_mode, is_active, created_at,
+               COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines, fixtures, sandbox_options, memory_limit_mb, binary_checks, languages,
+               scoring, COALESCE(solve_count, 0) as solve_count, points, writeups_visible_at, hints,
+               COALESCE(star_count, 0) as star_count, starts_at, ends_at, run_all_tests
+        FROM challenges
+        WHERE id = $1
+        "#,
+    )
+    .bind(challenge_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn list_challenges(pool: &PgPool, active_only: bool) -> Result<Vec<Challenge>, ApiError> {
+    let results: Vec<Challenge> = if active_only {
+        sqlx::query_as(
+            r#"
+            SELECT id, name, description, category, difficulty, input_spec, output_spec,
+                   test_cases, verify_mode, is_active, created_at,
+                   COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines, fixtures, sandbox_options, memory_limit_mb, binary_checks, languages,
+                   scoring, COALESCE(solve_count, 0) as solve_count, points, writeups_visible_at, hints,
+                   COALESCE(star_count, 0) as star_count, starts_at, ends_at, run_all_tests
+            FROM challenges
+            WHERE is_active = TRUE
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+    } else {
+        sqlx::query_as(
+            r#"
+            SELECT id, name, description, category, difficulty, input_spec, output_spec,
+                   test_cases, verify_mode, is_active, created_at,
+                   COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines, fixtures, sandbox_options, memory_limit_mb, binary_checks, languages,
+                   scoring, COALESCE(solve_count, 0) as solve_count, points, writeups_visible_at, hints,
+                   COALESCE(star_count, 0) as star_count, starts_at, ends_at, run_all_tests
+            FROM challenges
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+    }
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list challenges: {}", e)))?;
+
+    Ok(results)
+}
+
+/// A challenge as authored: what `create_challenge` inserts, or overwrites on
+/// an existing id. Everything past the texts and test cases is optional.
+#[derive(Debug, Default)]
+pub struct NewChallenge<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub description: &'a str,
+    pub category: &'a str,
+    pub difficulty: &'a str,
+    pub input_spec: Option<&'a str>,
+    pub output_spec: &'a str,
+    pub test_cases: serde_json::Value,
+    pub verify_mode: &'a str,
+    pub network_enabled: bool,
+    pub env_vars: Option<serde_json::Value>,
+    pub baselines: Option<serde_json::Value>,
+    pub fixtures: Option<serde_json::Value>,
+    pub sandbox_options: Option<serde_json::Value>,
+    pub memory_limit_mb: Option<i32>,
+    pub binary_checks: Option<serde_json::Value>,
+    pub languages: Option<serde_json::Value>,
+    pub scoring: Option<serde_json::Value>,
+    pub writeups_visible_at: Option<DateTime<Utc>>,
+    pub hints: Option<serde_json::Value>,
+    pub run_all_tests: bool,
+}
+
+pub async fn create_challenge(pool: &PgPool, challenge: &NewChallenge<'_>) -> Result<Challenge, ApiError> {
+    let NewChallenge {
+        id,
+        name,
+        description,
+        category,
+        difficulty,
+        input_spec,
+        output_spec,
+        ref test_cases,
+        verify_mode,
+        network_enabled,
+        ref env_vars,
+        ref baselines,
+        ref fixtures,
+        ref sandbox_options,
+        memory_limit_mb,
+        ref binary_checks,
+        ref languages,
+        ref scoring,
+        writeups_visible_at,
+        ref hints,
+        run_all_tests,
+    } = *challenge;
+
+    // Challenges are admin-authored; still refuse anything the worker would reject
+    let options: Option<SandboxOptions> = sandbox_options
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| ApiError::InvalidField(format!("sandbox_options: {}", e)))?;
+    if let Some(ref options) = options {
+        options.validate().map_err(|e| ApiError::InvalidField(e.to_string()))?;
+    }
+    if let Some(fixtures) = fixtures {
+        let spec: FixtureSpec = serde_json::from_value(fixtures.clone())
+            .map_err(|e| ApiError::InvalidField(format!("fixtures: {}", e)))?;
+        spec.validate(options.as_ref().is_some_and(|o| o.allow_setuid))
+            .map_err(|e| ApiError::InvalidField(e.to_string()))?;
+    }
+    if let Some(checks) = binary_checks {
+        let checks: BinaryChecks = serde_json::from_value(checks.clone())
+            .map_err(|e| ApiError::InvalidField(format!("binary_checks: {}", e)))?;
+        checks.validate().map_err(|e| ApiError::InvalidField(e.to_string()))?;
+    }
+    if let Some(languages) = languages {
+        let restrictions: LanguageRestrictions = serde_json::from_value(languages.clone())
+            .map_err(|e| ApiError::InvalidField(format!("languages: {}", e)))?;
+        if !restrictions.allow.is_empty() && restrictions.allow.iter().all(|l| restrictions.deny.contains(l)) {
+            return Err(ApiError::InvalidField("languages: every allowed language is denied".to_string()));
+        }
+    }
+    let parsed_scoring: Option<ChallengeScoring> = scoring
+        .as_ref()
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| ApiError::InvalidField(format!("scoring: {}", e)))?;
+    if let Some(ref scoring) = parsed_scoring {
+        scoring.validate().map_err(|e| ApiError::InvalidField(format!("scoring: {}", e)))?;
+    }
+    if let Some(hints) = hints {
+        let hints: Vec<ChallengeHint> = serde_json::from_value(hints.clone())
+            .map_err(|e| ApiError::InvalidField(format!("hints: {}", e)))?;
+        if hints.iter().any(|h| h.cost < 0) {
+            return Err(ApiError::InvalidField("hints: cost must not be negative".to_string()));
+        }
+    }
+
+    let result: Challenge = sqlx::query_as(
+        r#"
+        INSERT INTO challenges (id, name, description, category, difficulty, input_spec, output_spec, test_cases, verify_mode, network_enabled, env_vars, baselines, fixtures, sandbox_options, memory_limit_mb, binary_checks, languages, scoring, writeups_visible_at, hints, run_all_tests)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            description = EXCLUDED.description,
+            category = EXCLUDED.category,
+            difficulty = EXCLUDED.difficulty,
+            input_spec = EXCLUDED.input_spec,
+            output_spec = EXCLUDED.output_spec,
+            test_cases = EXCLUDED.test_cases,
+            verify_mode = EXCLUDED.verify_mode,
+            network_enabled = EXCLUDED.network_enabled,
+            env_vars = EXCLUDED.env_vars,
+            baselines = EXCLUDED.baselines,
+            fixtures = EXCLUDED.fixtures,
+            sandbox_options = EXCLUDED.sandbox_options,
+            memory_limit_mb = EXCLUDED.memory_limit_mb,
+            binary_checks = EXCLUDED.binary_checks,
+            languages = EXCLUDED.languages,
+            scoring = EXCLUDED.scoring,
+            writeups_visible_at = EXCLUDED.writeups_visible_at,
+            hints = EXCLUDED.hints,
+            run_all_tests = EXCLUDED.run_all_tests
+        RETURNING id, name, description, category, difficulty, input_spec, output_spec,
+                  test_cases, verify_mode, is_active, created_at,
+                  COALESCE(network_enabled, FALSE) as network_enabled, env_vars, baselines, fixtures, sandbox_options, memory_limit_mb, binary_checks, languages,
+                  scoring, COALESCE(solve_count, 0) as solve_count, points, writeups_visible_at, hints,
+                  COALESCE(star_count, 0) as star_count, starts_at, ends_at, run_all_tests
+        "#,
+    )
+    .bind(id)
+    .bind(name)
+    .bind(description)
+    .bind(category)
+    .bind(difficulty)
+    .bind(input_spec)
+    .bind(output_spec)
+    .bind(test_cases)
+    .bind(verify_mode)
+    .bind(network_enabled)
+    .bind(env_vars)
+    .bind(baselines)
+    .bind(fixtures)
+    .bind(sandbox_options)
+    .bind(memory_limit_mb)
+    .bind(binary_checks)
+    .bind(languages)
+    .bind(scoring)
+    .bind(writeups_visible_at)
+    .bind(hints)
+    .bind(run_all_tests)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge: {}", e)))?;
+
+    // The curve may have changed under existing solves
+    let mut result = result;
+    result.points = parsed_scoring.map(|s| s.points(result.solve_count as i64));
+    sqlx::query(r#"UPDATE challenges SET points = $2 WHERE id = $1"#)
+        .bind(id)
+        .bind(result.points)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to set challenge points: {}", e)))?;
+
+    Ok(result)
+}
+
+/// Record `user_id`'s first solve of a decay-scored challenge and recompute its
+/// value. Runs in one transaction with the challenge row locked, so concurrent
+/// solves can't both read the same count. Returns false for a repeat solve.
+pub async fn record_challenge_solve(
+    pool: &PgPool,
+    challenge_id: &str,
+    user_id: &Uuid,
+    scoring: &ChallengeScoring,
+) -> Result<bool, ApiError> {
+    let db_err = |e: sqlx::Error| ApiError::DatabaseError(format!("Failed to record challenge solve: {}", e));
+    let mut tx = pool.begin().await.map_err(db_err)?;
+
+    sqlx::query(r#"SELECT id FROM challenges WHERE id = $1 FOR UPDATE"#)
+        .bind(challenge_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+    let inserted = sqlx::query(
+        r#"INSERT INTO challenge_solves (challenge_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+    )
+    .bind(challenge_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .rows_affected()
+        > 0;
+
+    if inserted {
+        let (solves,): (i64,) = sqlx::query_as(r#"SELECT COUNT(*) FROM challenge_solves WHERE challenge_id = $1"#)
+            .bind(challenge_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(db_err)?;
+        sqlx::query(r#"UPDATE challenges SET solve_count = $2, points = $3 WHERE id = $1"#)
+            .bind(challenge_id)
+            .bind(solves as i32)
+            .bind(scoring.points(solves))
+            .execute(&mut *tx)
+            .await
+            .map_err(db_err)?;
+    }
+
+    tx.commit().await.map_err(db_err)?;
+    Ok(inserted)
+}
+
+// ============ Challenge Submission Functions ============
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_challenge_submission(
+    pool: &PgPool,
+    user_id: &Uuid,
+    challenge_id: &str,
+    language: &str,
+    source_code: &str,
+    optimization: Option<&str>,
+    retry_of: Option<&Uuid>,
+    ranked: bool,
+) -> Result<ChallengeSubmission, ApiError> {
+    let result: ChallengeSubmission = sqlx::query_as(
+        r#"
+        INSERT INTO challenge_submissions (user_id, challenge_id, language, source_code, optimization, retry_of, ranked)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, user_id, challenge_id, language, source_code, optimization, retry_of, ranked, binary_id, status,
+                  test_results, instructions, error_message, created_at, completed_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(challenge_id)
+    .bind(language)
+    .bind(source_code)
+    .bind(optimization)
+    .bind(retry_of)
+    .bind(ranked)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create challenge submission: {}", e)))?;
+
+    Ok(result)
+}
+
+/// When `user_id` last made a ranked submission to the challenge
+pub async fn last_ranked_submission_at(
+    pool: &PgPool,
+    user_id: &Uuid,
+    challenge_id: &str,
+) -> Result<Option<DateTime<Utc>>, ApiError> {
+    let (at,): (Option<DateTime<Utc>>,) = sqlx::query_as(
+        r#"
+        SELECT MAX(created_at) FROM challenge_submissions
+        WHERE user_id = $1 AND challenge_id = $2 AND ranked
+        "#,
+    )
+    .bind(user_id)
+    .bind(challenge_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get last ranked submission: {}", e)))?;
+
+    Ok(at)
+}
+
+pub async fn get_challenge_submission(pool: &PgPool, submission_id: &Uuid) -> Result<Option<ChallengeSubmission>, ApiError> {
+    let result: Option<ChallengeSubmission> = sqlx::query_as(
+        r#"
+        SELECT id, user_id, challenge_id, language, source_code, optimization, retry_of, ranked, binary_id, status,
+               test_results, instructions, error_message, created_at, completed_at
+        FROM challenge_submissions
+        WHERE id = $1
+        "#,
+    )
+    .bind(submission_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge submission: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn update_challenge_submission_status(
+    pool: &PgPool,
+    submission_id: &Uuid,
+    status: &str,
+    binary_id: Option<&str>,
+    test_results: Option<&serde_json::Value>,
+    instructions: Option<i64>,
+    error_message: Option<&str>,
+) -> Result<(), ApiError> {
+    let completed_at = if status == "passed" || status == "failed" {
+        Some(Utc::now())
+    } else {
+        None
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE challenge_submissions
+        SET status = $2,
+            binary_id = COALESCE($3, binary_id),
+            test_results = COALESCE($4, test_results),
+            instructions = COALESCE($5, instructions),
+            error_message = COALESCE($6, error_message),
+            completed_at = COALESCE($7, completed_at)
+        WHERE id = $1
+        "#,
+    )
+    .bind(submission_id)
+    .bind(status)
+    .bind(binary_id)
+    .bind(test_results)
+    .bind(instructions)
+    .bind(error_message)
+    .bind(completed_at)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to update challenge submission: {}", e)))?;
+
+    Ok(())
+}
+
+/// Unfinished submissions to the challenge created since `since`, oldest
+/// first (older ones were abandoned by an API restart)
+pub async fn list_pending_challenge_submissions(
+    pool: &PgPool,
+    challenge_id: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<PendingSubmission>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT id, user_id, COALESCE(status, 'pending') AS status, created_at
+        FROM challenge_submissions
+        WHERE challenge_id = $1 AND completed_at IS NULL AND created_at >= $2
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list pending submissions: {}", e)))
+}
+
+/// A test case result cached since `since` for this binary, test and user,
+/// as long as the test is unchanged and its run still exists
+#[allow(clippy::too_many_arguments)]
+pub async fn get_cached_test_result(
+    pool: &PgPool,
+    binary_id: &str,
+    challenge_id: &str,
+    test_index: i32,
+    user_id: &Uuid,
+    test_hash: &str,
+    since: DateTime<Utc>,
+) -> Result<Option<CachedTestResult>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT c.passed, c.expected_preview, c.actual_preview, c.first_mismatch, c.diff, c.error, c.instructions, c.run_id
+        FROM challenge_test_cache c
+        JOIN runs r ON r.id = c.run_id
+        WHERE c.binary_id = $1 AND c.challenge_id = $2 AND c.test_index = $3 AND c.user_id = $4
+          AND c.test_hash = $5 AND c.created_at >= $6
+        "#,
+    )
+    .bind(binary_id)
+    .bind(challenge_id)
+    .bind(test_index)
+    .bind(user_id)
+    .bind(test_hash)
+    .bind(since)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get cached test result: {}", e)))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn cache_test_result(
+    pool: &PgPool,
+    binary_id: &str,
+    challenge_id: &str,
+    test_index: i32,
+    user_id: &Uuid,
+    test_hash: &str,
+    result: &CachedTestResult,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO challenge_test_cache
+            (binary_id, challenge_id, test_index, user_id, test_hash, passed, expected_preview, actual_preview,
+             first_mismatch, diff, error, instructions, run_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        ON CONFLICT (binary_id, challenge_id, test_index, user_id) DO UPDATE SET
+            test_hash = EXCLUDED.test_hash,
+            passed = EXCLUDED.passed,
+            expected_preview = EXCLUDED.expected_preview,
+            actual_preview = EXCLUDED.actual_preview,
+            first_mismatch = EXCLUDED.first_mismatch,
+            diff = EXCLUDED.diff,
+            error = EXCLUDED.error,
+            instructions = EXCLUDED.instructions,
+            run_id = EXCLUDED.run_id,
+            created_at = NOW()
+        "#,
+    )
+    .bind(binary_id)
+    .bind(challenge_id)
+    .bind(test_index)
+    .bind(user_id)
+    .bind(test_hash)
+    .bind(result.passed)
+    .bind(&result.expected_preview)
+    .bind(&result.actual_preview)
+    .bind(&result.first_mismatch)
+    .bind(&result.diff)
+    .bind(&result.error)
+    .bind(result.instructions)
+    .bind(result.run_id)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to cache test result: {}", e)))?;
+
+    Ok(())
+}
+
+/// Drop cached test results older than `cutoff`
+pub async fn cleanup_expired_test_cache(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, ApiError> {
+    let result = sqlx::query(r#"DELETE FROM challenge_test_cache WHERE created_at < $1"#)
+        .bind(cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to clean up test cache: {}", e)))?;
+
+    Ok(result.rows_affected())
+}
+
+/// How many submissions to the challenge finished since `since`, and their
+/// average time from submission to result in milliseconds
+pub async fn challenge_processing_time(
+    pool: &PgPool,
+    challenge_id: &str,
+    since: DateTime<Utc>,
+) -> Result<(i64, Option<i64>), ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT COUNT(*),
+               (AVG(EXTRACT(EPOCH FROM completed_at - created_at)) * 1000)::BIGINT
+        FROM challenge_submissions
+        WHERE challenge_id = $1 AND completed_at >= $2
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(since)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get challenge processing time: {}", e)))
+}
+
+// ============ Leaderboard Functions ============
+
+/// Keep the user's best entry for the challenge and language. Also returns
+/// their previous best, `None` when this is their first entry.
+pub async fn update_leaderboard_entry(
+    pool: &PgPool,
+    user_id: &Uuid,
+    challenge_id: &str,
+    language: &str,
+    instructions: i64,
+    run_id: &Uuid,
+    source_code: &str,
+    is_verified: bool,
+) -> Result<(LeaderboardEntry, Option<i64>), ApiError> {
+    #[derive(sqlx::FromRow)]
+    struct Upserted {
+        #[sqlx(flatten)]
+        entry: LeaderboardEntry,
+        previous_instructions: Option<i64>,
+    }
+
+    // Only update if this is a better score (lower instructions)
+    let result: Upserted = sqlx::query_as(
+        r#"
+        WITH previous AS (
+            SELECT instructions FROM leaderboard_entries
+            WHERE user_id = $1 AND challenge_id = $2 AND language = $3
+        ), upserted AS (
+            INSERT INTO leaderboard_entries (user_id, challenge_id, language, instructions, run_id, source_code, is_verified)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (user_id, challenge_id, language) DO UPDATE SET
+                instructions = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
+                                   THEN EXCLUDED.instructions
+                                   ELSE leaderboard_entries.instructions END,
+                run_id = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
+                             THEN EXCLUDED.run_id
+                             ELSE leaderboard_entries.run_id END,
+                source_code = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
+                                  THEN EXCLUDED.source_code
+                                  ELSE leaderboard_entries.source_code END,
+                is_verified = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
+                                  THEN EXCLUDED.is_verified
+                                  ELSE leaderboard_entries.is_verified END,
+                created_at = CASE WHEN EXCLUDED.instructions < leaderboard_entries.instructions
+                                 THEN NOW()
+                                 ELSE leaderboard_entries.created_at END
+            RETURNING id, user_id, challenge_id, language, instructions, run_id, source_code, is_verified, created_at
+        )
+        SELECT upserted.*, (SELECT instructions FROM previous) AS previous_instructions
+        FROM upserted
+        "#,
+    )
+    .bind(user_id)
+    .bind(challenge_id)
+    .bind(language)
+    .bind(instructions)
+    .bind(run_id)
+    .bind(source_code)
+    .bind(is_verified)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to update leaderboard entry: {}", e)))?;
+
+    Ok((result.entry, result.previous_instructions))
+}
+
+/// Ranked within each language (a single ranking with `language` set)
+pub async fn get_challenge_leaderboard(
+    pool: &PgPool,
+    challenge_id: &str,
+    language: Option<&str>,
+    user_type: Option<&str>,
+    country: Option<&str>,
+    limit: i64,
+) -> Result<Vec<LeaderboardEntryWithUser>, ApiError> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        rank: i64,
+        #[sqlx(flatten)]
+        user: PublicUserRow,
+        instructions: i64,
+        language: String,
+        submitted_at: DateTime<Utc>,
+    }
+
+    let rows: Vec<Row> = sqlx::query_as(
+        r#"
+        SELECT
+            ROW_NUMBER() OVER (PARTITION BY le.language ORDER BY le.instructions ASC) as rank,
+            u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
+            COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
+            u.country, u.affiliation,
+            le.instructions, le.language, le.created_at as submitted_at
+        FROM leaderboard_entries le
+        JOIN users u ON le.user_id = u.id
+        WHERE le.challenge_id = $1
+          AND ($2::text IS NULL OR le.language = $2)
+          AND ($3::text IS NULL OR COALESCE(u.user_type, 'human') = $3)
+          AND ($4::text IS NULL OR u.country = $4)
+        ORDER BY le.language, le.instructions ASC
+        LIMIT $5
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(language)
+    .bind(user_type)
+    .bind(country)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get leaderboard: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| LeaderboardEntryWithUser {
+            rank: row.rank,
+            user: row.user.into(),
+            instructions: row.instructions,
+            language: row.language,
+            submitted_at: row.submitted_at,
+        })
+        .collect())
+}
+
+/// The best leaderboard entry in one language
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageBest {
+    pub language: String,
+    pub user: PublicUser,
+    pub instructions: i64,
+    pub submitted_at: DateTime<Utc>,
+    /// Leaderboard entries in the language
+    pub entries: i64,
+}
+
+/// Each language's best entry on a challenge, best first
+pub async fn get_challenge_language_bests(
+    pool: &PgPool,
+    challenge_id: &str,
+    user_type: Option<&str>,
+) -> Result<Vec<LanguageBest>, ApiError> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        language: String,
+        instructions: i64,
+        submitted_at: DateTime<Utc>,
+        entries: i64,
+        #[sqlx(flatten)]
+        user: PublicUserRow,
+    }
+
+    let rows: Vec<Row> = sqlx::query_as(
+        r#"
+        WITH best AS (
+            SELECT DISTINCT ON (le.language)
+                   le.language, le.instructions, le.created_at as submitted_at,
+                   COUNT(*) OVER (PARTITION BY le.language) as entries,
+                   u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
+                   COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
+                   u.country, u.affiliation
+            FROM leaderboard_entries le
+            JOIN users u ON le.user_id = u.id
+            WHERE le.challenge_id = $1 AND ($2::text IS NULL OR COALESCE(u.user_type, 'human') = $2)
+            ORDER BY le.language, le.instructions ASC, le.created_at ASC
+        )
+        SELECT * FROM best
+        ORDER BY instructions ASC, language
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(user_type)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get language bests: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| LanguageBest {
+            language: row.language,
+            user: row.user.into(),
+            instructions: row.instructions,
+            submitted_at: row.submitted_at,
+            entries: row.entries,
+        })
+        .collect())
+}
+
+/// A row of the embeddable leaderboard widget
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct EmbedLeaderboardEntry {
+    pub rank: i64,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub language: String,
+    pub instructions: i64,
+}
+
+/// Top `limit` users by instructions across languages (each user's best
+/// language only), or within one language
+pub async fn get_embed_leaderboard(
+    pool: &PgPool,
+    challenge_id: &str,
+    language: Option<&str>,
+    limit: i64,
+) -> Result<Vec<EmbedLeaderboardEntry>, ApiError> {
+    sqlx::query_as(
+        r#"
+        WITH best AS (
+            SELECT DISTINCT ON (le.user_id) le.user_id, le.language, le.instructions, le.created_at
+            FROM leaderboard_entries le
+            WHERE le.challenge_id = $1 AND ($2::text IS NULL OR le.language = $2)
+            ORDER BY le.user_id, le.instructions ASC, le.created_at ASC
+        )
+        SELECT ROW_NUMBER() OVER (ORDER BY b.instructions ASC, b.created_at ASC) as rank,
+               u.username, u.display_name, u.avatar_url, b.language, b.instructions
+        FROM best b
+        JOIN users u ON u.id = b.user_id
+        ORDER BY rank
+        LIMIT $3
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(language)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get embed leaderboard: {}", e)))
+}
+
+/// (language, instructions, rank) of the user's best-ranked leaderboard entry
+/// on a challenge, ranked within its language like the challenge leaderboard
+pub async fn get_user_challenge_rank(
+    pool: &PgPool,
+    challenge_id: &str,
+    user_id: &Uuid,
+    language: Option<&str>,
+) -> Result<Option<(String, i64, i64)>, ApiError> {
+    sqlx::query_as(
+        r#"
+        WITH ranked AS (
+            SELECT user_id, language, instructions,
+                   ROW_NUMBER() OVER (PARTITION BY language ORDER BY instructions ASC) as rank
+            FROM leaderboard_entries
+            WHERE challenge_id = $1
+        )
+        SELECT language, instructions, rank
+        FROM ranked
+        WHERE user_id = $2 AND ($3::text IS NULL OR language = $3)
+        ORDER BY rank, instructions
+        LIMIT 1
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(user_id)
+    .bind(language)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get user challenge rank: {}", e)))
+}
+
+pub async fn get_user_challenge_stats(
+    pool: &PgPool,
+    user_id: &Uuid,
+) -> Result<Vec<LeaderboardEntry>, ApiError> {
+    let results: Vec<LeaderboardEntry> = sqlx::query_as(
+        r#"
+        SELECT id, user_id, challenge_id, language, instructions, run_id, source_code, is_verified, created_at
+        FROM leaderboard_entries
+        WHERE user_id = $1
+        ORDER BY challenge_id, language
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get user challenge stats: {}", e)))?;
+
+    Ok(results)
+}
+
+// ============ Organization Functions ============
+
+const ORGANIZATION_COLUMNS: &str = "id, slug, name, policy, created_at";
+
+/// `None` if the slug is taken
+pub async fn create_organization(pool: &PgPool, slug: &str, name: &str) -> Result<Option<Organization>, ApiError> {
+    sqlx::query_as(&format!(
+        r#"
+        INSERT INTO organizations (slug, name) VALUES ($1, $2)
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING {ORGANIZATION_COLUMNS}
+        "#
+    ))
+    .bind(slug)
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create organization: {}", e)))
+}
+
+pub async fn get_organization(pool: &PgPool, slug: &str) -> Result<Option<Organization>, ApiError> {
+    sqlx::query_as(&format!("SELECT {ORGANIZATION_COLUMNS} FROM organizations WHERE slug = $1"))
+        .bind(slug)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to get organization: {}", e)))
+}
+
+/// The organization a user belongs to, with their role in it
+pub async fn get_user_organization(pool: &PgPool, user_id: &Uuid) -> Result<Option<(Organization, String)>, ApiError> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        #[sqlx(flatten)]
+        organization: Organization,
+        role: String,
+    }
+
+    let row: Option<Row> = sqlx::query_as(
+        r#"
+        SELECT o.id, o.slug, o.name, o.policy, o.created_at, m.role
+        FROM organization_members m
+        JOIN organizations o ON o.id = m.org_id
+        WHERE m.user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get user organization: {}", e)))?;
+
+    Ok(row.map(|r| (r.organization, r.role)))
+}
+
+pub async fn set_organization_policy(pool: &PgPool, org_id: &Uuid, policy: &serde_json::Value) -> Result<(), ApiError> {
+    sqlx::query("UPDATE organizations SET policy = $2 WHERE id = $1")
+        .bind(org_id)
+        .bind(policy)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to set organization policy: {}", e)))?;
+
+    Ok(())
+}
+
+pub async fn list_organization_members(pool: &PgPool, org_id: &Uuid) -> Result<Vec<OrganizationMember>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT u.username, m.role, m.created_at
+        FROM organization_members m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.org_id = $1
+        ORDER BY m.role, u.username
+        "#,
+    )
+    .bind(org_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list organization members: {}", e)))
+}
+
+/// Add a user to an organization or change their role there; `false` if they
+/// already belong to a different one
+pub async fn set_organization_member(pool: &PgPool, org_id: &Uuid, user_id: &Uuid, role: &str) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO organization_members (user_id, org_id, role) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE SET role = EXCLUDED.role
+        WHERE organization_members.org_id = EXCLUDED.org_id
+        "#,
+    )
+    .bind(user_id)
+    .bind(org_id)
+    .bind(role)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to set organization member: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_organization_member(pool: &PgPool, org_id: &Uuid, user_id: &Uuid) -> Result<bool, ApiError> {
+    let result = sqlx::query("DELETE FROM organization_members WHERE org_id = $1 AND user_id = $2")
+        .bind(org_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to remove organization member: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// ============ Verification Code Functions ============
+
+pub async fn create_verification_code(
+    pool: &PgPool,
+    user_id: &Uuid,
+    code: &str,
+    twitter_handle: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<VerificationCode, ApiError> {
+    let result: VerificationCode = sqlx::query_as(
+        r#"
+        INSERT INTO verification_codes (user_id, code, twitter_handle, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, user_id, code, twitter_handle, expires_at, verified, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(code)
+    .bind(twitter_handle)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create verification code: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn get_verification_code(pool: &PgPool, user_id: &Uuid) -> Result<Option<VerificationCode>, ApiError> {
+    let result: Option<VerificationCode> = sqlx::query_as(
+        r#"
+        SELECT id, user_id, code, twitter_handle, expires_at, verified, created_at
+        FROM verification_codes
+        WHERE user_id = $1 AND expires_at > NOW() AND verified = FALSE
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get verification code: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn mark_verification_code_used(pool: &PgPool, code_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE verification_codes SET verified = TRUE WHERE id = $1"#)
+        .bind(code_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to mark verification code used: {}", e)))?;
+
+    Ok(())
+}
+
+/// Count a wrong code against a pending verification; returns the attempts so far
+pub async fn record_verification_attempt(pool: &PgPool, code_id: &Uuid) -> Result<i32, ApiError> {
+    let (attempts,): (i32,) = sqlx::query_as(
+        r#"UPDATE verification_codes SET attempts = attempts + 1 WHERE id = $1 RETURNING attempts"#,
+    )
+    .bind(code_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record verification attempt: {}", e)))?;
+
+    Ok(attempts)
+}
+
+pub async fn expire_verification_code(pool: &PgPool, code_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE verification_codes SET expires_at = NOW() WHERE id = $1"#)
+        .bind(code_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to expire verification code: {}", e)))?;
+
+    Ok(())
+}
+
+pub async fn get_verification_locked_until(pool: &PgPool, user_id: &Uuid) -> Result<Option<DateTime<Utc>>, ApiError> {
+    let result: Option<(DateTime<Utc>,)> = sqlx::query_as(
+        r#"SELECT locked_until FROM verification_lockouts WHERE user_id = $1 AND locked_until > NOW()"#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get verification lockout: {}", e)))?;
+
+    Ok(result.map(|(until,)| until))
+}
+
+/// Lock a user out of verification for `base_seconds`, doubled for every
+/// earlier lockout and capped at `max_seconds`; returns when it ends
+pub async fn lock_verification(
+    pool: &PgPool,
+    user_id: &Uuid,
+    base_seconds: u64,
+    max_seconds: u64,
+) -> Result<DateTime<Utc>, ApiError> {
+    let (locked_until,): (DateTime<Utc>,) = sqlx::query_as(
+        r#"
+        INSERT INTO verification_lockouts (user_id, lockouts, locked_until)
+        VALUES ($1, 1, NOW() + make_interval(secs => LEAST($2, $3)))
+        ON CONFLICT (user_id) DO UPDATE SET
+            lockouts = verification_lockouts.lockouts + 1,
+            locked_until = NOW() + make_interval(secs => LEAST($2 * POWER(2, verification_lockouts.lockouts), $3))
+        RETURNING locked_until
+        "#,
+    )
+    .bind(user_id)
+    .bind(base_seconds as f64)
+    .bind(max_seconds as f64)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to lock verification: {}", e)))?;
+
+    Ok(locked_until)
+}
+
+pub async fn clear_verification_lockouts(pool: &PgPool, user_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"DELETE FROM verification_lockouts WHERE user_id = $1"#)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to clear verification lockouts: {}", e)))?;
+
+    Ok(())
+}
+
+// ============ Audit Log Functions ============
+
+pub async fn insert_audit_event(
+    pool: &PgPool,
+    event: &str,
+    user_id: Option<&Uuid>,
+    ip: Option<&str>,
+    details: &serde_json::Value,
+) -> Result<(), ApiError> {
+    sqlx::query(r#"INSERT INTO audit_log (event, user_id, ip, details) VALUES ($1, $2, $3, $4)"#)
+        .bind(event)
+        .bind(user_id)
+        .bind(ip)
+        .bind(details)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to write audit event: {}", e)))?;
+
+    Ok(())
+}
+
+// ============ Hint Functions ============
+
+/// Indexes of the hints `user_id` has unlocked for a challenge, ascending
+pub async fn get_unlocked_hints(pool: &PgPool, challenge_id: &str, user_id: &Uuid) -> Result<Vec<i32>, ApiError> {
+    let rows: Vec<(i32,)> = sqlx::query_as(
+        r#"SELECT hint_index FROM hint_unlocks WHERE challenge_id = $1 AND user_id = $2 ORDER BY hint_index"#,
+    )
+    .bind(challenge_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get unlocked hints: {}", e)))?;
+
+    Ok(rows.into_iter().map(|(i,)| i).collect())
+}
+
+/// Record an unlock; repeating one is a no-op and keeps the original cost
+pub async fn unlock_hint(
+    pool: &PgPool,
+    challenge_id: &str,
+    user_id: &Uuid,
+    hint_index: i32,
+    cost: i32,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO hint_unlocks (challenge_id, user_id, hint_index, cost)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(user_id)
+    .bind(hint_index)
+    .bind(cost)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to unlock hint: {}", e)))?;
+
+    Ok(())
+}
+
+// ============ Writeup Functions ============
+
+pub async fn has_solved_challenge(pool: &PgPool, user_id: &Uuid, challenge_id: &str) -> Result<bool, ApiError> {
+    let (solved,): (bool,) = sqlx::query_as(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM challenge_submissions
+            WHERE user_id = $1 AND challenge_id = $2 AND status = 'passed'
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(challenge_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to check challenge solve: {}", e)))?;
+
+    Ok(solved)
+}
+
+/// Create the user's writeup for a challenge, or replace its content.
+/// Moderation state survives edits.
+pub async fn upsert_writeup(
+    pool: &PgPool,
+    challenge_id: &str,
+    user_id: &Uuid,
+    content: &str,
+) -> Result<Writeup, ApiError> {
+    let result: Writeup = sqlx::query_as(
+        r#"
+        INSERT INTO writeups (challenge_id, user_id, content)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (challenge_id, user_id) DO UPDATE SET
+            content = EXCLUDED.content,
+            updated_at = NOW()
+        RETURNING id, challenge_id, user_id, content, hidden, moderation_note, created_at, updated_at
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(user_id)
+    .bind(content)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to save writeup: {}", e)))?;
+
+    Ok(result)
+}
+
+/// Writeups for a challenge, oldest first. Hidden ones are only included
+/// with `include_hidden`, or when they belong to `author`.
+pub async fn list_writeups(
+    pool: &PgPool,
+    challenge_id: &str,
+    include_hidden: bool,
+    author: Option<&Uuid>,
+) -> Result<Vec<WriteupWithUser>, ApiError> {
+    let rows: Vec<WriteupRow> = sqlx::query_as(
+        r#"
+        SELECT w.id, w.content, w.hidden, w.moderation_note, w.created_at, w.updated_at,
+               u.id as user_id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
+               COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type,
+               u.created_at as user_created_at, u.country, u.affiliation
+        FROM writeups w
+        JOIN users u ON w.user_id = u.id
+        WHERE w.challenge_id = $1 AND (NOT w.hidden OR $2 OR w.user_id = $3)
+        ORDER BY w.created_at ASC
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(include_hidden)
+    .bind(author)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list writeups: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| WriteupWithUser {
+            id: r.id,
+            user: PublicUser {
+                id: r.user_id,
+                username: r.username,
+                avatar_url: r.avatar_url,
+                display_name: r.display_name,
+                bio: None,
+                twitter_handle: r.twitter_handle,
+                is_verified: r.is_verified,
+                user_type: r.user_type,
+                created_at: r.user_created_at,
+                country: r.country,
+                affiliation: r.affiliation,
+            },
+            content: r.content,
+            hidden: r.hidden,
+            moderation_note: r.moderation_note,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+        })
+        .collect())
+}
+
+/// Hide or restore a writeup; returns None if it doesn't exist
+pub async fn moderate_writeup(
+    pool: &PgPool,
+    writeup_id: &Uuid,
+    hidden: bool,
+    note: Option<&str>,
+) -> Result<Option<Writeup>, ApiError> {
+    let result: Option<Writeup> = sqlx::query_as(
+        r#"
+        UPDATE writeups
+        SET hidden = $2, moderation_note = $3
+        WHERE id = $1
+        RETURNING id, challenge_id, user_id, content, hidden, moderation_note, created_at, updated_at
+        "#,
+    )
+    .bind(writeup_id)
+    .bind(hidden)
+    .bind(note)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to moderate writeup: {}", e)))?;
+
+    Ok(result)
+}
+
+// ============ Run Comment Functions ============
+
+pub async fn create_run_comment(
+    pool: &PgPool,
+    run_id: &Uuid,
+    user_id: &Uuid,
+    content: &str,
+) -> Result<RunComment, ApiError> {
+    let result: RunComment = sqlx::query_as(
+        r#"
+        INSERT INTO run_comments (run_id, user_id, content)
+        VALUES ($1, $2, $3)
+        RETURNING id, run_id, user_id, content, hidden, moderation_note, created_at, updated_at
+        "#,
+    )
+    .bind(run_id)
+    .bind(user_id)
+    .bind(content)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to save run comment: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn get_run_comment(pool: &PgPool, comment_id: &Uuid) -> Result<Option<RunComment>, ApiError> {
+    let result: Option<RunComment> = sqlx::query_as(
+        r#"
+        SELECT id, run_id, user_id, content, hidden, moderation_note, created_at, updated_at
+        FROM run_comments
+        WHERE id = $1
+        "#,
+    )
+    .bind(comment_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get run comment: {}", e)))?;
+
+    Ok(result)
+}
+
+/// Comments on a run, oldest first. Hidden ones are only included with
+/// `include_hidden`, or when they belong to `author`.
+pub async fn list_run_comments(
+    pool: &PgPool,
+    run_id: &Uuid,
+    include_hidden: bool,
+    author: Option<&Uuid>,
+) -> Result<Vec<RunCommentWithUser>, ApiError> {
+    let rows: Vec<RunCommentRow> = sqlx::query_as(
+        r#"
+        SELECT c.id, c.content, r.user_id IS NOT DISTINCT FROM c.user_id as is_note,
+               c.hidden, c.moderation_note, c.created_at, c.updated_at,
+               u.id as user_id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
+               COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type,
+               u.created_at as user_created_at, u.country, u.affiliation
+        FROM run_comments c
+        JOIN runs r ON c.run_id = r.id
+        JOIN users u ON c.user_id = u.id
+        WHERE c.run_id = $1 AND (NOT c.hidden OR $2 OR c.user_id = $3)
+        ORDER BY c.created_at ASC
+        "#,
+    )
+    .bind(run_id)
+    .bind(include_hidden)
+    .bind(author)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list run comments: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| RunCommentWithUser {
+            id: r.id,
+            user: PublicUser {
+                id: r.user_id,
+                username: r.username,
+                avatar_url: r.avatar_url,
+                display_name: r.display_name,
+                bio: None,
+                twitter_handle: r.twitter_handle,
+                is_verified: r.is_verified,
+                user_type: r.user_type,
+                created_at: r.user_created_at,
+                country: r.country,
+                affiliation: r.affiliation,
+            },
+            content: r.content,
+            is_note: r.is_note,
+            hidden: r.hidden,
+            moderation_note: r.moderation_note,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+        })
+        .collect())
+}
+
+/// Replace a comment's content; moderation state survives edits
+pub async fn update_run_comment(pool: &PgPool, comment_id: &Uuid, content: &str) -> Result<RunComment, ApiError> {
+    let result: RunComment = sqlx::query_as(
+        r#"
+        UPDATE run_comments
+        SET content = $2, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, run_id, user_id, content, hidden, moderation_note, created_at, updated_at
+        "#,
+    )
+    .bind(comment_id)
+    .bind(content)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to update run comment: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn delete_run_comment(pool: &PgPool, comment_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"DELETE FROM run_comments WHERE id = $1"#)
+        .bind(comment_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to delete run comment: {}", e)))?;
+
+    Ok(())
+}
+
+/// Hide or restore a run comment; returns None if it doesn't exist
+pub async fn moderate_run_comment(
+    pool: &PgPool,
+    comment_id: &Uuid,
+    hidden: bool,
+    note: Option<&str>,
+) -> Result<Option<RunComment>, ApiError> {
+    let result: Option<RunComment> = sqlx::query_as(
+        r#"
+        UPDATE run_comments
+        SET hidden = $2, moderation_note = $3
+        WHERE id = $1
+        RETURNING id, run_id, user_id, content, hidden, moderation_note, created_at, updated_at
+        "#,
+    )
+    .bind(comment_id)
+    .bind(hidden)
+    .bind(note)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to moderate run comment: {}", e)))?;
+
+    Ok(result)
+}
+
+// ============ Star Functions ============
+
+/// Bookmark a run; returns its star count. Starring twice is a no-op.
+pub async fn star_run(pool: &PgPool, user_id: &Uuid, run_id: &Uuid) -> Result<i32, ApiError> {
+    let (count,): (i32,) = sqlx::query_as(
+        r#"
+        WITH ins AS (
+            INSERT INTO run_stars (user_id, run_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            RETURNING 1
+        )
+        UPDATE runs SET star_count = star_count + (SELECT COUNT(*) FROM ins)
+        WHERE id = $2
+        RETURNING star_count
+        "#,
+    )
+    .bind(user_id)
+    .bind(run_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to star run: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Remove a run bookmark; returns its star count
+pub async fn unstar_run(pool: &PgPool, user_id: &Uuid, run_id: &Uuid) -> Result<i32, ApiError> {
+    let (count,): (i32,) = sqlx::query_as(
+        r#"
+        WITH del AS (
+            DELETE FROM run_stars WHERE user_id = $1 AND run_id = $2
+            RETURNING 1
+        )
+        UPDATE runs SET star_count = GREATEST(star_count - (SELECT COUNT(*) FROM del), 0)
+        WHERE id = $2
+        RETURNING star_count
+        "#,
+    )
+    .bind(user_id)
+    .bind(run_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to unstar run: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Bookmark a challenge; returns its star count. Starring twice is a no-op.
+pub async fn star_challenge(pool: &PgPool, user_id: &Uuid, challenge_id: &str) -> Result<i32, ApiError> {
+    let (count,): (i32,) = sqlx::query_as(
+        r#"
+        WITH ins AS (
+            INSERT INTO challenge_stars (user_id, challenge_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            RETURNING 1
+        )
+        UPDATE challenges SET star_count = star_count + (SELECT COUNT(*) FROM ins)
+        WHERE id = $2
+        RETURNING star_count
+        "#,
+    )
+    .bind(user_id)
+    .bind(challenge_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to star challenge: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Remove a challenge bookmark; returns its star count
+pub async fn unstar_challenge(pool: &PgPool, user_id: &Uuid, challenge_id: &str) -> Result<i32, ApiError> {
+    let (count,): (i32,) = sqlx::query_as(
+        r#"
+        WITH del AS (
+            DELETE FROM challenge_stars WHERE user_id = $1 AND challenge_id = $2
+            RETURNING 1
+        )
+        UPDATE challenges SET star_count = GREATEST(star_count - (SELECT COUNT(*) FROM del), 0)
+        WHERE id = $2
+        RETURNING star_count
+        "#,
+    )
+    .bind(user_id)
+    .bind(challenge_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to unstar challenge: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Runs `user_id` starred, most recently starred first, skipping any that
+/// have since gone private (unless they're the user's own, or `is_admin`)
+pub async fn list_starred_runs(pool: &PgPool, user_id: &Uuid, is_admin: bool) -> Result<Vec<Run>, ApiError> {
+    let results: Vec<Run> = sqlx::query_as(
+        r#"
+        SELECT r.id, r.job_id, r.binary_id, r.binary_size, r.source_code, r.language, r.runtime, r.optimization, r.compiler_version, r.toolchain,
+               r.compile_time_ms, r.compile_cached, r.instructions, r.memory_peak_kb,
+               r.memory_rss_kb, r.memory_hwm_kb, r.memory_data_kb, r.memory_stack_kb,
+               r.io_read_bytes, r.io_write_bytes, r.guest_mmap_bytes, r.guest_mmap_peak,
+               r.guest_heap_bytes, r.limit_reached, r.limit_reached_reason, r.exit_code,
+               r.execution_time_ms, r.instruction_limit, r.memory_limit_mb, r.sandbox_image, r.args, r.core_dump_id, r.mode, r.syscalls, r.syscall_breakdown,
+               r.thread_breakdown, r.stdout, r.stderr, r.benchmark_id, r.benchmark_version, r.created_at, r.started_at, r.completed_at,
+               r.user_id, r.visibility, r.star_count
+        FROM run_stars s
+        JOIN runs r ON r.id = s.run_id
+        WHERE s.user_id = $1 AND (r.visibility = 'public' OR r.user_id = $1 OR $2)
+        ORDER BY s.created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(is_admin)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list starred runs: {}", e)))?;
+
+    Ok(results)
+}
+
+/// Active challenges `user_id` starred, most recently starred first
+pub async fn list_starred_challenges(pool: &PgPool, user_id: &Uuid) -> Result<Vec<Challenge>, ApiError> {
+    let results: Vec<Challenge> = sqlx::query_as(
+        r#"
+        SELECT c.id, c.name, c.description, c.category, c.difficulty, c.input_spec, c.output_spec,
+               c.test_cases, c.verify_mode, c.is_active, c.created_at,
+               COALESCE(c.network_enabled, FALSE) as network_enabled, c.env_vars, c.baselines, c.fixtures, c.sandbox_options, c.memory_limit_mb, c.binary_checks, c.languages,
+               c.scoring, COALESCE(c.solve_count, 0) as solve_count, c.points, c.writeups_visible_at, c.hints,
+               COALESCE(c.star_count, 0) as star_count
+        FROM challenge_stars s
+        JOIN challenges c ON c.id = s.challenge_id
+        WHERE s.user_id = $1 AND c.is_active = TRUE
+        ORDER BY s.created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list starred challenges: {}", e)))?;
+
+    Ok(results)
+}
+
+/// Stars on `user_id`'s public runs
+pub async fn count_stars_received(pool: &PgPool, user_id: &Uuid) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as(
+        r#"SELECT COALESCE(SUM(star_count), 0)::BIGINT FROM runs WHERE user_id = $1 AND visibility = 'public'"#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count stars: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Stars on every public run and challenge
+pub async fn count_stars(pool: &PgPool) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT (SELECT COUNT(*) FROM run_stars s JOIN runs r ON r.id = s.run_id WHERE r.visibility = 'public')
+             + (SELECT COUNT(*) FROM challenge_stars)
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count stars: {}", e)))?;
+
+    Ok(count)
+}
+
+// ============ Notification Functions ============
+
+pub async fn create_notification(
+    pool: &PgPool,
+    user_id: &Uuid,
+    kind: &str,
+    title: &str,
+    body: &str,
+    link: Option<&str>,
+) -> Result<Notification, ApiError> {
+    let result: Notification = sqlx::query_as(
+        r#"
+        INSERT INTO notifications (user_id, kind, title, body, link)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, user_id, kind, title, body, link, read_at, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(kind)
+    .bind(title)
+    .bind(body)
+    .bind(link)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create notification: {}", e)))?;
+
+    Ok(result)
+}
+
+pub async fn list_notifications(
+    pool: &PgPool,
+    user_id: &Uuid,
+    unread_only: bool,
+    limit: i64,
+) -> Result<Vec<Notification>, ApiError> {
+    let results: Vec<Notification> = sqlx::query_as(
+        r#"
+        SELECT id, user_id, kind, title, body, link, read_at, created_at
+        FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list notifications: {}", e)))?;
+
+    Ok(results)
+}
+
+pub async fn count_unread_notifications(pool: &PgPool, user_id: &Uuid) -> Result<i64, ApiError> {
+    let (count,): (i64,) = sqlx::query_as(r#"SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL"#)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to count notifications: {}", e)))?;
+
+    Ok(count)
+}
+
+/// Mark one of `user_id`'s notifications read; false if it isn't theirs
+pub async fn mark_notification_read(pool: &PgPool, user_id: &Uuid, notification_id: &Uuid) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"UPDATE notifications SET read_at = COALESCE(read_at, NOW()) WHERE id = $1 AND user_id = $2"#,
+    )
+    .bind(notification_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to mark notification read: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn mark_all_notifications_read(pool: &PgPool, user_id: &Uuid) -> Result<u64, ApiError> {
+    let result = sqlx::query(r#"UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL"#)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to mark notifications read: {}", e)))?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn get_notification_preferences(pool: &PgPool, user_id: &Uuid) -> Result<Option<serde_json::Value>, ApiError> {
+    let result: Option<(serde_json::Value,)> =
+        sqlx::query_as(r#"SELECT preferences FROM notification_preferences WHERE user_id = $1"#)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| ApiError::DatabaseError(format!("Failed to get notification preferences: {}", e)))?;
+
+    Ok(result.map(|(p,)| p))
+}
+
+pub async fn set_notification_preferences(
+    pool: &PgPool,
+    user_id: &Uuid,
+    preferences: &serde_json::Value,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO notification_preferences (user_id, preferences)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET preferences = EXCLUDED.preferences, updated_at = NOW()
+        "#,
+    )
+    .bind(user_id)
+    .bind(preferences)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to set notification preferences: {}", e)))?;
+
+    Ok(())
+}
+
+pub async fn list_user_ids(pool: &PgPool) -> Result<Vec<Uuid>, ApiError> {
+    let rows: Vec<(Uuid,)> = sqlx::query_as(r#"SELECT id FROM users"#)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to list users: {}", e)))?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Current best (user, instructions) for a challenge in one language
+pub async fn get_language_record(
+    pool: &PgPool,
+    challenge_id: &str,
+    language: &str,
+) -> Result<Option<(Uuid, i64)>, ApiError> {
+    let result: Option<(Uuid, i64)> = sqlx::query_as(
+        r#"
+        SELECT user_id, instructions FROM leaderboard_entries
+        WHERE challenge_id = $1 AND language = $2
+        ORDER BY instructions ASC, created_at ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(language)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get language record: {}", e)))?;
+
+    Ok(result)
+}
+
+// ============ Global Leaderboard ============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalLeaderboardEntry {
+    pub rank: i64,
+    pub user: PublicUser,
+    pub total_score: i64,
+    pub challenges_completed: i64,
+    pub first_places: i64,
+}
+
+pub async fn get_global_leaderboard(
+    pool: &PgPool,
+    user_type: Option<&str>,
+    country: Option<&str>,
+    limit: i64,
+) -> Result<Vec<GlobalLeaderboardEntry>, ApiError> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        rank: i64,
+        #[sqlx(flatten)]
+        user: PublicUserRow,
+        total_score: i64,
+        challenges_completed: i64,
+        first_places: i64,
+    }
+
+    // Score = sum of (best_in_language / user_instructions * 1000) for each entry,
+    // plus the current value of each decay-scored (flag) challenge solved,
+    // minus the cost of every hint unlocked
+    let rows: Vec<Row> = sqlx::query_as(
+        r#"
+        WITH user_scores AS (
+            SELECT
+                le.user_id,
+                COUNT(DISTINCT le.challenge_id) as challenges_completed,
+                SUM(
+                    CASE
+                        WHEN c.scoring IS NOT NULL THEN 0
+                        WHEN le.instructions = (
+                            SELECT MIN(le2.instructions)
+                            FROM leaderboard_entries le2
+                            WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
+                        ) THEN 1000
+                        ELSE (
+                            SELECT MIN(le2.instructions)::float / le.instructions::float * 1000
+                            FROM leaderboard_entries le2
+                            WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
+                        )::bigint
+                    END
+                ) as total_score,
+                SUM(
+                    CASE WHEN c.scoring IS NULL AND le.instructions = (
+                        SELECT MIN(le2.instructions)
+                        FROM leaderboard_entries le2
+                        WHERE le2.challenge_id = le.challenge_id AND le2.language = le.language
+                    ) THEN 1 ELSE 0 END
+                ) as first_places
+            FROM leaderboard_entries le
+            JOIN challenges c ON c.id = le.challenge_id
+            JOIN users u ON le.user_id = u.id
+            WHERE ($2::text IS NULL OR COALESCE(u.user_type, 'human') = $2)
+              AND ($3::text IS NULL OR u.country = $3)
+            GROUP BY le.user_id
+        ),
+        solve_points AS (
+            SELECT cs.user_id, SUM(COALESCE(c.points, 0)) as points
+            FROM challenge_solves cs
+            JOIN challenges c ON c.id = cs.challenge_id
+            WHERE c.scoring IS NOT NULL
+            GROUP BY cs.user_id
+        ),
+        hint_penalties AS (
+            SELECT user_id, SUM(cost) as penalty
+            FROM hint_unlocks
+            GROUP BY user_id
+        )
+        SELECT
+            ROW_NUMBER() OVER (ORDER BY us.total_score + COALESCE(sp.points, 0) - COALESCE(hp.penalty, 0) DESC) as rank,
+            u.id, u.username, u.avatar_url, u.display_name, u.twitter_handle,
+            COALESCE(u.is_verified, FALSE) as is_verified, COALESCE(u.user_type, 'human') as user_type, u.created_at,
+            u.country, u.affiliation,
+            (us.total_score + COALESCE(sp.points, 0) - COALESCE(hp.penalty, 0))::bigint as total_score, us.challenges_completed, us.first_places
+        FROM user_scores us
+        JOIN users u ON us.user_id = u.id
+        LEFT JOIN solve_points sp ON sp.user_id = us.user_id
+        LEFT JOIN hint_penalties hp ON hp.user_id = us.user_id
+        ORDER BY total_score DESC
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .bind(user_type)
+    .bind(country)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get global leaderboard: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| GlobalLeaderboardEntry {
+            rank: row.rank,
+            user: row.user.into(),
+            total_score: row.total_score,
+            challenges_completed: row.challenges_completed,
+            first_places: row.first_places,
+        })
+        .collect())
+}
+
+// ============ Admin Overview Functions ============
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct StatusCount {
+    pub status: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompileFailureRate {
+    pub language: String,
+    /// Builds that finished, either way
+    pub finished: i64,
+    pub failed: i64,
+    pub failure_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TopSubmitter {
+    pub username: String,
+    pub submissions: i64,
+    pub passed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ChallengeLatency {
+    pub challenge_id: String,
+    pub submissions: i64,
+    /// Submission to verdict, compile included
+    pub avg_ms: i64,
+    pub p95_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TableSize {
+    pub table: String,
+    /// Table, indexes and TOAST
+    pub total_bytes: i64,
+    /// Planner estimate
+    pub live_rows: i64,
+}
+
+/// Runs since `since` by outcome: `ok`, `error` (non-zero exit) or the
+/// limit that stopped them
+pub async fn count_run_outcomes(pool: &PgPool, since: DateTime<Utc>) -> Result<Vec<StatusCount>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT CASE
+                   WHEN limit_reached THEN COALESCE(limit_reached_reason, 'limit')
+                   WHEN exit_code = 0 THEN 'ok'
+                   ELSE 'error'
+               END AS status,
+               COUNT(*) AS count
+        FROM runs
+        WHERE created_at >= $1
+        GROUP BY 1
+        ORDER BY count DESC
+        "#,
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count run outcomes: {}", e)))
+}
+
+pub async fn count_submission_statuses(pool: &PgPool, since: DateTime<Utc>) -> Result<Vec<StatusCount>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT COALESCE(status, 'pending') AS status, COUNT(*) AS count
+        FROM challenge_submissions
+        WHERE created_at >= $1
+        GROUP BY 1
+        ORDER BY count DESC
+        "#,
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count submission statuses: {}", e)))
+}
+
+/// Failure rate per language of logged-in users' compiles since `since`
+/// (anonymous compiles aren't recorded)
+pub async fn compile_failure_rates(pool: &PgPool, since: DateTime<Utc>) -> Result<Vec<CompileFailureRate>, ApiError> {
+    let rows: Vec<(String, i64, i64)> = sqlx::query_as(
+        r#"
+        SELECT COALESCE(language, 'unknown') AS language,
+               COUNT(*) FILTER (WHERE failed OR binary_id IS NOT NULL) AS finished,
+               COUNT(*) FILTER (WHERE failed) AS failed
+        FROM compile_usage
+        WHERE created_at >= $1 AND settled
+        GROUP BY 1
+        ORDER BY failed DESC, finished DESC
+        "#,
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get compile failure rates: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|(_, finished, _)| *finished > 0)
+        .map(|(language, finished, failed)| CompileFailureRate {
+            language,
+            finished,
+            failed,
+            failure_rate: failed as f64 / finished as f64,
+        })
+        .collect())
+}
+
+pub async fn top_submitters(pool: &PgPool, since: DateTime<Utc>, limit: i64) -> Result<Vec<TopSubmitter>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT u.username,
+               COUNT(*) AS submissions,
+               COUNT(*) FILTER (WHERE s.status = 'passed') AS passed
+        FROM challenge_submissions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.created_at >= $1
+        GROUP BY u.username
+        ORDER BY submissions DESC, u.username
+        LIMIT $2
+        "#,
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get top submitters: {}", e)))
+}
+
+/// Challenges whose finished submissions took longest (by p95) since `since`
+pub async fn slowest_challenges(pool: &PgPool, since: DateTime<Utc>, limit: i64) -> Result<Vec<ChallengeLatency>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT challenge_id,
+               COUNT(*) AS submissions,
+               (AVG(EXTRACT(EPOCH FROM completed_at - created_at)) * 1000)::BIGINT AS avg_ms,
+               (PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM completed_at - created_at)) * 1000)::BIGINT AS p95_ms
+        FROM challenge_submissions
+        WHERE created_at >= $1 AND completed_at IS NOT NULL
+        GROUP BY challenge_id
+        ORDER BY p95_ms DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get slowest challenges: {}", e)))
+}
+
+/// Every table's on-disk size, largest first
+pub async fn table_sizes(pool: &PgPool) -> Result<Vec<TableSize>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT relname::TEXT AS "table",
+               pg_total_relation_size(relid) AS total_bytes,
+               n_live_tup AS live_rows
+        FROM pg_stat_user_tables
+        ORDER BY total_bytes DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get table sizes: {}", e)))
+}
+
+// ============ Retention Functions ============
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RetentionStat {
+    pub table_name: String,
+    /// `deleted` or `trimmed`
+    pub action: String,
+    pub last_pass_rows: i64,
+    /// Since the table was created, across every replica
+    pub total_rows: i64,
+    pub last_pass_at: DateTime<Utc>,
+}
+
+/// Delete up to `limit` runs created before `cutoff`, keeping those a
+/// leaderboard entry points at
+pub async fn delete_expired_runs(pool: &PgPool, cutoff: DateTime<Utc>, limit: i64) -> Result<u64, ApiError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM runs WHERE id IN (
+            SELECT r.id FROM runs r
+            WHERE r.created_at < $1
+              AND NOT EXISTS (SELECT 1 FROM leaderboard_entries l WHERE l.run_id = r.id)
+            LIMIT $2
+        )
+        "#,
+    )
+    .bind(cutoff)
+    .bind(limit)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to delete expired runs: {}", e)))?;
+
+    Ok(result.rows_affected())
+}
+
+/// Drop stdout and stderr from up to `limit` runs created before `cutoff`
+pub async fn trim_expired_run_output(pool: &PgPool, cutoff: DateTime<Utc>, limit: i64) -> Result<u64, ApiError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE runs SET stdout = NULL, stderr = NULL WHERE id IN (
+            SELECT id FROM runs
+            WHERE created_at < $1 AND (stdout IS NOT NULL OR stderr IS NOT NULL)
+            LIMIT $2
+        )
+        "#,
+    )
+    .bind(cutoff)
+    .bind(limit)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to trim run output: {}", e)))?;
+
+    Ok(result.rows_affected())
+}
+
+/// Record what a janitor pass did to `table_name`
+pub async fn record_retention_pass(pool: &PgPool, table_name: &str, action: &str, rows: u64) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO retention_stats (table_name, action, last_pass_rows, total_rows, last_pass_at)
+        VALUES ($1, $2, $3, $3, NOW())
+        ON CONFLICT (table_name, action) DO UPDATE SET
+            last_pass_rows = EXCLUDED.last_pass_rows,
+            total_rows = retention_stats.total_rows + EXCLUDED.last_pass_rows,
+            last_pass_at = EXCLUDED.last_pass_at
+        "#,
+    )
+    .bind(table_name)
+    .bind(action)
+    .bind(rows as i64)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record retention pass: {}", e)))?;
+
+    Ok(())
+}
+
+pub async fn list_retention_stats(pool: &PgPool) -> Result<Vec<RetentionStat>, ApiError> {
+    sqlx::query_as(
+        r#"
+        SELECT table_name, action, last_pass_rows, total_rows, last_pass_at
+        FROM retention_stats
+        ORDER BY table_name, action
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list retention stats: {}", e)))
+}
+
+// ============ External Worker Functions ============
+
+/// An external worker as admins see it; the token's hash never leaves the table
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ExternalWorker {
+    pub id: Uuid,
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Declared at registration; `None` until the worker first registers
+    pub arch: Option<String>,
+    pub isolation: Option<String>,
+    pub version: Option<String>,
+    pub registered_at: Option<DateTime<Utc>>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub runs_reported: i64,
+    /// `BinaryCacheStats` from the last heartbeat; `None` without a cache
+    pub binary_cache: Option<serde_json::Value>,
+}
+
+const EXTERNAL_WORKER_COLUMNS: &str = "id, name, created_by, created_at, revoked_at, arch, isolation, version, \
+                                       registered_at, last_seen_at, runs_reported, binary_cache";
+
+/// `None` if the name is taken
+pub async fn create_external_worker(
+    pool: &PgPool,
+    name: &str,
+    token_hash: &str,
+    created_by: &Uuid,
+) -> Result<Option<ExternalWorker>, ApiError> {
+    sqlx::query_as(&format!(
+        r#"
+        INSERT INTO external_workers (name, token_hash, created_by)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING {EXTERNAL_WORKER_COLUMNS}
+        "#
+    ))
+    .bind(name)
+    .bind(token_hash)
+    .bind(created_by)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to create external worker: {}", e)))
+}
+
+/// The worker holding this token, unless it was revoked
+pub async fn get_external_worker_by_token_hash(
+    pool: &PgPool,
+    token_hash: &str,
+) -> Result<Option<ExternalWorker>, ApiError> {
+    sqlx::query_as(&format!(
+        "SELECT {EXTERNAL_WORKER_COLUMNS} FROM external_workers WHERE token_hash = $1 AND revoked_at IS NULL"
+    ))
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to get external worker: {}", e)))
+}
+
+/// Record what the worker declared; registering again doubles as a heartbeat
+pub async fn register_external_worker(
+    pool: &PgPool,
+    worker_id: &Uuid,
+    arch: &str,
+    isolation: &str,
+    version: Option<&str>,
+    binary_cache: Option<&BinaryCacheStats>,
+) -> Result<ExternalWorker, ApiError> {
+    sqlx::query_as(&format!(
+        r#"
+        UPDATE external_workers
+        SET arch = $2, isolation = $3, version = $4, binary_cache = $5,
+            registered_at = COALESCE(registered_at, NOW()), last_seen_at = NOW()
+        WHERE id = $1
+        RETURNING {EXTERNAL_WORKER_COLUMNS}
+        "#
+    ))
+    .bind(worker_id)
+    .bind(arch)
+    .bind(isolation)
+    .bind(version)
+    .bind(binary_cache.and_then(|stats| serde_json::to_value(stats).ok()))
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to register external worker: {}", e)))
+}
+
+pub async fn list_external_workers(pool: &PgPool) -> Result<Vec<ExternalWorker>, ApiError> {
+    sqlx::query_as(&format!(
+        "SELECT {EXTERNAL_WORKER_COLUMNS} FROM external_workers ORDER BY created_at"
+    ))
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to list external workers: {}", e)))
+}
+
+/// Whether there was an active worker to revoke
+pub async fn revoke_external_worker(pool: &PgPool, worker_id: &Uuid) -> Result<bool, ApiError> {
+    let result = sqlx::query("UPDATE external_workers SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL")
+        .bind(worker_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to revoke external worker: {}", e)))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn count_external_worker_run(pool: &PgPool, worker_id: &Uuid) -> Result<(), ApiError> {
+    sqlx::query(
+        "UPDATE external_workers SET runs_reported = runs_reported + 1, last_seen_at = NOW() WHERE id = $1",
+    )
+    .bind(worker_id)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to count external worker run: {}", e)))?;
+
+    Ok(())
+}
+
+/// A job as the API shared it; what an external worker reports about it is
+/// checked against this, never against anything in NATS
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SharedJob {
+    pub job_id: Uuid,
+    pub binary_id: String,
+    pub instruction_limit: i64,
+    pub user_id: Option<Uuid>,
+    pub worker_id: Option<Uuid>,
+}
+
+pub async fn record_shared_job(
+    pool: &PgPool,
+    job_id: &Uuid,
+    binary_id: &str,
+    instruction_limit: u64,
+    user_id: Option<&Uuid>,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO shared_jobs (job_id, binary_id, instruction_limit, user_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (job_id) DO NOTHING
+        "#,
+    )
+    .bind(job_id)
+    .bind(binary_id)
+    .bind(instruction_limit as i64)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to record shared job: {}", e)))?;
+
+    Ok(())
+}
+
+/// The shared job, claimed for `worker_id` unless another worker reported
+/// on it first; `None` if it was never shared or belongs to another worker
+pub async fn claim_shared_job(
+    pool: &PgPool,
+    job_id: &Uuid,
+    worker_id: &Uuid,
+) -> Result<Option<SharedJob>, ApiError> {
+    sqlx::query_as(
+        r#"
+        UPDATE shared_jobs SET worker_id = $2
+        WHERE job_id = $1 AND (worker_id IS NULL OR worker_id = $2)
+        RETURNING job_id, binary_id, instruction_limit, user_id, worker_id
+        "#,
+    )
+    .bind(job_id)
+    .bind(worker_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::DatabaseError(format!("Failed to claim shared job: {}", e)))
+}
+
+/// Drop records of jobs shared before `cutoff`, long gone from the queue
+pub async fn cleanup_old_shared_jobs(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, ApiError> {
+    let result = sqlx::query("DELETE FROM shared_jobs WHERE created_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to clean up shared jobs: {}", e)))?;
+
+    Ok(result.rows_affected())
 }
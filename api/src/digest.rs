@@ -0,0 +1,200 @@
+//! Daily "fastest run" digests.
+//!
+//! Once per UTC day a background task summarises the previous day: the best
+//! result per benchmark/challenge and language, which of those are new
+//! records, and the biggest improvements over the previous best. The digest
+//! is stored in `digests` (so `GET /digest/:date` is a single lookup) and,
+//! when `DISCORD_WEBHOOK_URL` is set, posted to Discord. Only the replica
+//! whose insert wins posts, so running several APIs doesn't duplicate it.
+
+use crate::db::{self, DigestEntry};
+use crate::error::ApiError;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{NaiveDate, TimeDelta, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How often the task checks whether yesterday's digest exists
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+pub const MAX_IMPROVEMENTS: usize = 10;
+/// Discord rejects messages longer than this
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub day: NaiveDate,
+    pub benchmarks: Vec<DigestEntry>,
+    pub challenges: Vec<DigestEntry>,
+    /// New records with the largest relative drop in instructions
+    pub improvements: Vec<Improvement>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Improvement {
+    /// `benchmark` or `challenge`
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub entry: DigestEntry,
+    /// Percent fewer instructions than the previous best
+    pub percent: f64,
+}
+
+fn is_record(entry: &DigestEntry) -> bool {
+    entry.previous_best.is_none_or(|prev| entry.instructions < prev)
+}
+
+impl Digest {
+    pub fn is_empty(&self) -> bool {
+        self.benchmarks.is_empty() && self.challenges.is_empty()
+    }
+
+    fn summary(&self) -> String {
+        let mut lines = vec![format!("**Daily digest for {}**", self.day)];
+        let records: Vec<_> = self
+            .benchmarks
+            .iter()
+            .map(|e| ("benchmark", e))
+            .chain(self.challenges.iter().map(|e| ("challenge", e)))
+            .filter(|(_, e)| is_record(e))
+            .collect();
+        if !records.is_empty() {
+            lines.push(String::new());
+            lines.push("New records:".to_string());
+            for (kind, e) in records {
+                let by = e.username.as_ref().map(|u| format!(" by {}", u)).unwrap_or_default();
+                let was = e.previous_best.map(|p| format!(" (was {})", p)).unwrap_or_default();
+                lines.push(format!(
+                    "- {} {} ({}): {} instructions{}{}",
+                    kind, e.target, e.language, e.instructions, was, by
+                ));
+            }
+        }
+        if let Some(top) = self.improvements.first() {
+            lines.push(String::new());
+            lines.push(format!(
+                "Biggest improvement: {} ({}) {:.1}% fewer instructions",
+                top.entry.target, top.entry.language, top.percent
+            ));
+        }
+
+        let mut content = String::new();
+        for line in lines {
+            if content.len() + line.len() + 1 > DISCORD_MAX_CONTENT {
+                break;
+            }
+            content.push_str(&line);
+            content.push('\n');
+        }
+        content
+    }
+}
+
+pub async fn build(pool: &PgPool, day: NaiveDate) -> Result<Digest, ApiError> {
+    let benchmarks = db::get_benchmark_day_bests(pool, day).await?;
+    let challenges = db::get_challenge_day_bests(pool, day).await?;
+
+    let mut improvements: Vec<Improvement> = benchmarks
+        .iter()
+        .map(|e| ("benchmark", e))
+        .chain(challenges.iter().map(|e| ("challenge", e)))
+        .filter_map(|(kind, e)| {
+            let prev = e.previous_best.filter(|&p| p > e.instructions)?;
+            Some(Improvement {
+                kind,
+                entry: e.clone(),
+                percent: (prev - e.instructions) as f64 * 100.0 / prev as f64,
+            })
+        })
+        .collect();
+    improvements.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+    improvements.truncate(MAX_IMPROVEMENTS);
+
+    Ok(Digest {
+        day,
+        benchmarks,
+        challenges,
+        improvements,
+    })
+}
+
+// ============ Scheduling ============
+
+pub fn spawn(pool: PgPool, discord_webhook_url: Option<String>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let yesterday = Utc::now().date_naive() - TimeDelta::days(1);
+            if let Err(e) = generate(&pool, yesterday, discord_webhook_url.as_deref()).await {
+                error!(day = %yesterday, "Digest generation failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Build and store `day`'s digest if it doesn't exist yet, then post it
+pub async fn generate(pool: &PgPool, day: NaiveDate, discord_webhook_url: Option<&str>) -> Result<(), ApiError> {
+    if db::get_digest(pool, day).await?.is_some() {
+        return Ok(());
+    }
+
+    let digest = build(pool, day).await?;
+    let data = serde_json::to_value(&digest)
+        .map_err(|e| ApiError::Internal(format!("Failed to serialize digest: {}", e)))?;
+    if !db::insert_digest(pool, day, &data).await? {
+        return Ok(()); // another replica got there first
+    }
+
+    info!(
+        day = %day,
+        benchmarks = digest.benchmarks.len(),
+        challenges = digest.challenges.len(),
+        "Daily digest generated"
+    );
+
+    if let (Some(url), false) = (discord_webhook_url, digest.is_empty()) {
+        if let Err(e) = post_to_discord(url, &digest.summary()).await {
+            warn!(day = %day, "Failed to post digest to Discord: {}", e);
+        }
+    }
+    Ok(())
+}
+
+async fn post_to_discord(url: &str, content: &str) -> Result<(), reqwest::Error> {
+    reqwest::Client::new()
+        .post(url)
+        .json(&json!({ "content": content }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// ============ Handlers ============
+
+/// `date` is `YYYY-MM-DD` (UTC)
+pub async fn get_digest(
+    State(state): State<Arc<crate::AppState>>,
+    Path(date): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let day = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|_| ApiError::InvalidField(format!("date must be YYYY-MM-DD, got {}", date)))?;
+
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let digest = db::get_digest(pool, day)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("digest for {}", day)))?;
+
+    Ok(Json(digest))
+}
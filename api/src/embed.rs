@@ -0,0 +1,205 @@
+//! Embeddable challenge leaderboards for blogs and event pages.
+//!
+//! `GET /embed/challenges/:id/leaderboard` returns a self-contained HTML page
+//! (inline CSS, no scripts) meant for an `<iframe>`; the `.json` variant
+//! returns the same rows for sites that render their own. Both use the public
+//! CORS policy (any origin by default), can be framed, and are cacheable for
+//! a minute.
+
+use crate::badge::escape;
+use crate::db::{self, EmbedLeaderboardEntry};
+use crate::error::ApiError;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderValue},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const CACHE_CONTROL: &str = "public, max-age=60";
+pub const MAX_EMBED_LIMIT: i64 = 50;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// (background, text, muted, border, accent)
+    fn palette(self) -> (&'static str, &'static str, &'static str, &'static str, &'static str) {
+        match self {
+            Theme::Light => ("#ffffff", "#1f2328", "#656d76", "#d0d7de", "#0969da"),
+            Theme::Dark => ("#0d1117", "#e6edf3", "#7d8590", "#30363d", "#4493f8"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedQuery {
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub theme: Theme,
+    pub language: Option<String>,
+}
+
+fn default_limit() -> i64 {
+    10
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedLeaderboardResponse {
+    pub challenge_id: String,
+    pub challenge_name: String,
+    pub language: Option<String>,
+    pub entries: Vec<EmbedLeaderboardEntry>,
+    /// Full leaderboard on the site
+    pub url: String,
+}
+
+/// Embed routes; mounted with the public CORS policy and without frame-deny
+pub fn router() -> Router<Arc<crate::AppState>> {
+    Router::new()
+        .route("/embed/challenges/:id/leaderboard", get(leaderboard_html))
+        .route("/embed/challenges/:id/leaderboard.json", get(leaderboard_json))
+}
+
+pub(crate) fn frontend_url() -> String {
+    std::env::var("FRONTEND_URL")
+        .ok()
+        .and_then(|s| s.split(',').next().map(|u| u.trim().trim_end_matches('/').to_string()))
+        .unwrap_or_else(|| "http://localhost:8080".to_string())
+}
+
+async fn load(
+    state: &crate::AppState,
+    challenge_id: &str,
+    query: &EmbedQuery,
+) -> Result<EmbedLeaderboardResponse, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let challenge = db::get_challenge(pool, challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.to_string()))?;
+
+    let entries = db::get_embed_leaderboard(
+        pool,
+        challenge_id,
+        query.language.as_deref(),
+        query.limit.clamp(1, MAX_EMBED_LIMIT),
+    )
+    .await?;
+
+    Ok(EmbedLeaderboardResponse {
+        url: format!("{}/challenges/{}", frontend_url(), challenge.id),
+        challenge_id: challenge.id,
+        challenge_name: challenge.name,
+        language: query.language.clone(),
+        entries,
+    })
+}
+
+fn cached(response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    response
+}
+
+/// 1234567 -> "1,234,567"
+pub(crate) fn group_digits(n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    if n < 0 {
+        out.insert(0, '-');
+    }
+    out
+}
+
+fn render(board: &EmbedLeaderboardResponse, theme: Theme) -> String {
+    let (bg, fg, muted, border, accent) = theme.palette();
+    let title = match &board.language {
+        Some(language) => format!("{} ({})", board.challenge_name, language),
+        None => board.challenge_name.clone(),
+    };
+
+    let rows: String = if board.entries.is_empty() {
+        r#"<tr><td colspan="4" class="empty">No entries yet</td></tr>"#.to_string()
+    } else {
+        board
+            .entries
+            .iter()
+            .map(|e| {
+                format!(
+                    r#"<tr><td class="rank">{}</td><td>{}</td><td class="muted">{}</td><td class="num">{}</td></tr>"#,
+                    e.rank,
+                    escape(e.display_name.as_deref().unwrap_or(&e.username)),
+                    escape(&e.language),
+                    group_digits(e.instructions),
+                )
+            })
+            .collect()
+    };
+
+    format!(
+        r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} leaderboard</title>
+<style>
+body{{margin:0;padding:12px;background:{bg};color:{fg};font:14px/1.4 -apple-system,BlinkMacSystemFont,"Segoe UI",Helvetica,Arial,sans-serif}}
+h1{{font-size:15px;margin:0 0 8px}}
+table{{width:100%;border-collapse:collapse}}
+th,td{{padding:4px 6px;border-bottom:1px solid {border};text-align:left}}
+th{{font-weight:600;color:{muted};font-size:12px}}
+.num{{text-align:right;font-variant-numeric:tabular-nums}}
+.rank{{width:2em;color:{muted}}}
+.muted{{color:{muted}}}
+.empty{{text-align:center;color:{muted};padding:12px}}
+a{{color:{accent};text-decoration:none;font-size:12px}}
+</style></head>
+<body><h1>{title}</h1>
+<table><thead><tr><th>#</th><th>User</th><th>Language</th><th class="num">Instructions</th></tr></thead>
+<tbody>{rows}</tbody></table>
+<p><a href="{url}" target="_blank" rel="noopener">Full leaderboard on CTF Arena &rarr;</a></p>
+</body></html>
+"#,
+        title = escape(&title),
+        url = escape(&board.url),
+    )
+}
+
+// ============ Handlers ============
+
+async fn leaderboard_html(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    Query(query): Query<EmbedQuery>,
+) -> Result<Response, ApiError> {
+    let board = load(&state, &challenge_id, &query).await?;
+    Ok(cached(Html(render(&board, query.theme))))
+}
+
+async fn leaderboard_json(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    Query(query): Query<EmbedQuery>,
+) -> Result<Response, ApiError> {
+    let board = load(&state, &challenge_id, &query).await?;
+    Ok(cached(Json(board)))
+}
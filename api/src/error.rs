@@ -1,8 +1,18 @@
+//! Every error the API returns, catalogued.
+//!
+//! Each [`ApiError`] variant has an [`ErrorKind`]: a stable snake_case code,
+//! the HTTP status, whether the same request can succeed if retried as is,
+//! and a one-line description. Error bodies carry `code` and `retryable`
+//! next to `error` (the message, which may change), and `GET /errors` lists
+//! the whole catalog so clients can switch on codes instead of messages.
+
 use axum::{
-    http::StatusCode,
+    extract::multipart::MultipartError,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use serde::Serialize;
 use serde_json::json;
 use thiserror::Error;
 
@@ -38,6 +48,44 @@ pub enum ApiError {
     #[error("Rate limit exceeded, try again later")]
     RateLimited,
 
+    #[error("Queue full: {queue} queue has {depth} pending jobs (max {max}), retry in {retry_after}s")]
+    QueueFull {
+        queue: &'static str,
+        depth: u64,
+        max: u64,
+        retry_after: u64,
+    },
+
+    #[error("Limit exceeded: {limit} is {value} (max {max})")]
+    LimitExceeded {
+        limit: &'static str,
+        value: u64,
+        max: u64,
+    },
+
+    #[error("Daily compile quota used up: {used_seconds}s of {limit_seconds}s, resets in {retry_after}s")]
+    CompileQuotaExceeded {
+        used_seconds: u64,
+        limit_seconds: u64,
+        retry_after: u64,
+    },
+
+    #[error("Anonymous token used up: {used} of {max} {action}, log in or get a new token")]
+    AnonymousQuotaExceeded {
+        action: &'static str,
+        used: u64,
+        max: u64,
+    },
+
+    #[error("Too many failed attempts, try again in {retry_after}s")]
+    LockedOut { retry_after: u64 },
+
+    #[error("Ranked submission cooldown: retry in {retry_after}s, or submit with ranked=false to practice")]
+    RankedCooldown { retry_after: u64 },
+
+    #[error("Direct execution is disabled: this server runs jobs on workers only, and no queue is connected")]
+    WorkersOnly,
+
     #[error("Queue error: {0}")]
     QueueError(String),
 
@@ -82,41 +130,232 @@ pub enum ApiError {
 
     #[error("Challenge verification failed: {0}")]
     VerificationFailed(String),
+
+    #[error("Invalid multipart body: {0}")]
+    Multipart(#[from] MultipartError),
+}
+
+/// A catalog entry: what a class of error means and how to react to it
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorKind {
+    pub code: &'static str,
+    pub status: StatusCode,
+    /// The same request may succeed later (after `Retry-After`, if sent)
+    pub retryable: bool,
+    pub description: &'static str,
+}
+
+impl ErrorKind {
+    const fn new(code: &'static str, status: StatusCode, retryable: bool, description: &'static str) -> Self {
+        Self { code, status, retryable, description }
+    }
+}
+
+const MISSING_FIELD: ErrorKind = ErrorKind::new("missing_field", StatusCode::BAD_REQUEST, false, "A required request field was not sent");
+const INVALID_FIELD: ErrorKind = ErrorKind::new("invalid_field", StatusCode::BAD_REQUEST, false, "A field was sent with a value the endpoint does not accept");
+const BINARY_TOO_LARGE: ErrorKind = ErrorKind::new("binary_too_large", StatusCode::PAYLOAD_TOO_LARGE, false, "The uploaded binary is over MAX_BINARY_SIZE");
+const INSTRUCTION_LIMIT_TOO_HIGH: ErrorKind = ErrorKind::new("instruction_limit_too_high", StatusCode::BAD_REQUEST, false, "The requested instruction limit is over the allowed maximum");
+const SANDBOX_FAILED: ErrorKind = ErrorKind::new("sandbox_failed", StatusCode::BAD_GATEWAY, true, "The sandbox failed to start or run the job");
+const TIMEOUT: ErrorKind = ErrorKind::new("timeout", StatusCode::GATEWAY_TIMEOUT, true, "Waiting for a job or compile took longer than allowed");
+const SERVER_BUSY: ErrorKind = ErrorKind::new("server_busy", StatusCode::SERVICE_UNAVAILABLE, true, "Every execution slot is busy");
+const JOB_NOT_FOUND: ErrorKind = ErrorKind::new("job_not_found", StatusCode::NOT_FOUND, false, "No job with that ID (or it expired)");
+const JOB_NOT_READY: ErrorKind = ErrorKind::new("job_not_ready", StatusCode::ACCEPTED, true, "The job has not finished; poll again");
+const RATE_LIMITED: ErrorKind = ErrorKind::new("rate_limited", StatusCode::TOO_MANY_REQUESTS, true, "The caller sent too many requests");
+const QUEUE_FULL: ErrorKind = ErrorKind::new("queue_full", StatusCode::TOO_MANY_REQUESTS, true, "The job or compile queue is at its depth limit");
+const LIMIT_EXCEEDED: ErrorKind = ErrorKind::new("limit_exceeded", StatusCode::PAYLOAD_TOO_LARGE, false, "A request is over one of the size or count limits");
+const COMPILE_QUOTA_EXCEEDED: ErrorKind = ErrorKind::new("compile_quota_exceeded", StatusCode::TOO_MANY_REQUESTS, true, "The daily compile-time quota is used up");
+const ANONYMOUS_QUOTA_EXCEEDED: ErrorKind = ErrorKind::new("anonymous_quota_exceeded", StatusCode::TOO_MANY_REQUESTS, false, "The anonymous token has no uses left");
+const LOCKED_OUT: ErrorKind = ErrorKind::new("locked_out", StatusCode::TOO_MANY_REQUESTS, true, "Too many failed verification attempts");
+const RANKED_COOLDOWN: ErrorKind = ErrorKind::new("ranked_cooldown", StatusCode::TOO_MANY_REQUESTS, true, "A ranked submission came sooner than the cooldown allows");
+const QUEUE_UNAVAILABLE: ErrorKind = ErrorKind::new("queue_unavailable", StatusCode::SERVICE_UNAVAILABLE, true, "The job queue is unreachable or failed");
+const WORKERS_ONLY: ErrorKind = ErrorKind::new("workers_only", StatusCode::SERVICE_UNAVAILABLE, false, "Direct execution is disabled and no worker queue is connected");
+const DATABASE_ERROR: ErrorKind = ErrorKind::new("database_error", StatusCode::INTERNAL_SERVER_ERROR, false, "The database is unreachable or a query failed");
+const INTERNAL: ErrorKind = ErrorKind::new("internal", StatusCode::INTERNAL_SERVER_ERROR, false, "Anything else that went wrong on the server");
+const COMPILE_FAILED: ErrorKind = ErrorKind::new("compile_failed", StatusCode::BAD_REQUEST, false, "The source did not compile");
+const BINARY_NOT_FOUND: ErrorKind = ErrorKind::new("binary_not_found", StatusCode::NOT_FOUND, false, "No binary with that ID (or it expired)");
+const COMPILE_JOB_NOT_FOUND: ErrorKind = ErrorKind::new("compile_job_not_found", StatusCode::NOT_FOUND, false, "No compile job with that ID (or it expired)");
+const COMPILE_JOB_NOT_READY: ErrorKind = ErrorKind::new("compile_job_not_ready", StatusCode::ACCEPTED, true, "The compile has not finished; poll again");
+const SOURCE_TOO_LARGE: ErrorKind = ErrorKind::new("source_too_large", StatusCode::PAYLOAD_TOO_LARGE, false, "The source is over MAX_SOURCE_SIZE");
+const INVALID_LANGUAGE: ErrorKind = ErrorKind::new("invalid_language", StatusCode::BAD_REQUEST, false, "The language is not one the compiler supports");
+const NOT_FOUND: ErrorKind = ErrorKind::new("not_found", StatusCode::NOT_FOUND, false, "The resource does not exist or is not visible to the caller");
+const UNAUTHORIZED: ErrorKind = ErrorKind::new("unauthorized", StatusCode::UNAUTHORIZED, false, "No valid session or token");
+const FORBIDDEN: ErrorKind = ErrorKind::new("forbidden", StatusCode::FORBIDDEN, false, "Authenticated, but not allowed to do this");
+const CHALLENGE_NOT_FOUND: ErrorKind = ErrorKind::new("challenge_not_found", StatusCode::NOT_FOUND, false, "No challenge with that ID, or it has not opened");
+const SUBMISSION_NOT_FOUND: ErrorKind = ErrorKind::new("submission_not_found", StatusCode::NOT_FOUND, false, "No submission with that ID for this challenge");
+const VERIFICATION_FAILED: ErrorKind = ErrorKind::new("verification_failed", StatusCode::BAD_REQUEST, false, "A verification code or proof did not check out");
+const INVALID_MULTIPART: ErrorKind = ErrorKind::new("invalid_multipart", StatusCode::BAD_REQUEST, false, "The multipart body could not be read (413 when it is over the size limit)");
+
+/// Every kind, in the order `GET /errors` lists them
+pub const CATALOG: &[ErrorKind] = &[
+    MISSING_FIELD,
+    INVALID_FIELD,
+    INVALID_LANGUAGE,
+    INVALID_MULTIPART,
+    INSTRUCTION_LIMIT_TOO_HIGH,
+    COMPILE_FAILED,
+    VERIFICATION_FAILED,
+    UNAUTHORIZED,
+    FORBIDDEN,
+    NOT_FOUND,
+    JOB_NOT_FOUND,
+    BINARY_NOT_FOUND,
+    COMPILE_JOB_NOT_FOUND,
+    CHALLENGE_NOT_FOUND,
+    SUBMISSION_NOT_FOUND,
+    BINARY_TOO_LARGE,
+    SOURCE_TOO_LARGE,
+    LIMIT_EXCEEDED,
+    JOB_NOT_READY,
+    COMPILE_JOB_NOT_READY,
+    RATE_LIMITED,
+    QUEUE_FULL,
+    COMPILE_QUOTA_EXCEEDED,
+    ANONYMOUS_QUOTA_EXCEEDED,
+    LOCKED_OUT,
+    RANKED_COOLDOWN,
+    INTERNAL,
+    DATABASE_ERROR,
+    SANDBOX_FAILED,
+    SERVER_BUSY,
+    QUEUE_UNAVAILABLE,
+    WORKERS_ONLY,
+    TIMEOUT,
+];
+
+impl ApiError {
+    pub fn kind(&self) -> &'static ErrorKind {
+        match self {
+            ApiError::MissingField(_) => &MISSING_FIELD,
+            ApiError::InvalidField(_) => &INVALID_FIELD,
+            ApiError::BinaryTooLarge { .. } => &BINARY_TOO_LARGE,
+            ApiError::InstructionLimitTooHigh { .. } => &INSTRUCTION_LIMIT_TOO_HIGH,
+            ApiError::DockerError(_) => &SANDBOX_FAILED,
+            ApiError::Timeout(_) => &TIMEOUT,
+            ApiError::TooManyRequests => &SERVER_BUSY,
+            ApiError::JobNotFound(_) => &JOB_NOT_FOUND,
+            ApiError::JobNotReady => &JOB_NOT_READY,
+            ApiError::RateLimited => &RATE_LIMITED,
+            ApiError::QueueFull { .. } => &QUEUE_FULL,
+            ApiError::LimitExceeded { .. } => &LIMIT_EXCEEDED,
+            ApiError::CompileQuotaExceeded { .. } => &COMPILE_QUOTA_EXCEEDED,
+            ApiError::AnonymousQuotaExceeded { .. } => &ANONYMOUS_QUOTA_EXCEEDED,
+            ApiError::LockedOut { .. } => &LOCKED_OUT,
+            ApiError::RankedCooldown { .. } => &RANKED_COOLDOWN,
+            ApiError::QueueError(_) => &QUEUE_UNAVAILABLE,
+            ApiError::WorkersOnly => &WORKERS_ONLY,
+            ApiError::DatabaseError(_) => &DATABASE_ERROR,
+            ApiError::Internal(_) => &INTERNAL,
+            ApiError::CompileError(_) => &COMPILE_FAILED,
+            ApiError::BinaryNotFound(_) => &BINARY_NOT_FOUND,
+            ApiError::CompileJobNotFound(_) => &COMPILE_JOB_NOT_FOUND,
+            ApiError::CompileJobNotReady => &COMPILE_JOB_NOT_READY,
+            ApiError::SourceTooLarge { .. } => &SOURCE_TOO_LARGE,
+            ApiError::InvalidLanguage(_) => &INVALID_LANGUAGE,
+            ApiError::NotFound(_) => &NOT_FOUND,
+            ApiError::Unauthorized(_) => &UNAUTHORIZED,
+            ApiError::Forbidden(_) => &FORBIDDEN,
+            ApiError::ChallengeNotFound(_) => &CHALLENGE_NOT_FOUND,
+            ApiError::SubmissionNotFound(_) => &SUBMISSION_NOT_FOUND,
+            ApiError::VerificationFailed(_) => &VERIFICATION_FAILED,
+            ApiError::Multipart(_) => &INVALID_MULTIPART,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind().code
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            // axum knows whether the body was malformed or over the limit
+            ApiError::Multipart(e) => e.status(),
+            _ => self.kind().status,
+        }
+    }
+
+    pub fn retryable(&self) -> bool {
+        self.kind().retryable
+    }
+
+    /// Seconds until a retry can succeed, sent as `Retry-After`
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            ApiError::QueueFull { retry_after, .. }
+            | ApiError::CompileQuotaExceeded { retry_after, .. }
+            | ApiError::LockedOut { retry_after }
+            | ApiError::RankedCooldown { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// Variant-specific fields added to the body
+    fn details(&self) -> serde_json::Value {
+        match self {
+            ApiError::QueueFull { queue, depth, max, retry_after } => json!({
+                "queue": queue,
+                "queue_depth": depth,
+                "max_queue_depth": max,
+                "retry_after": retry_after,
+            }),
+            ApiError::LimitExceeded { limit, value, max } => json!({
+                "limit": limit,
+                "value": value,
+                "max": max,
+            }),
+            ApiError::CompileQuotaExceeded { used_seconds, limit_seconds, retry_after } => json!({
+                "used_seconds": used_seconds,
+                "limit_seconds": limit_seconds,
+                "retry_after": retry_after,
+            }),
+            ApiError::AnonymousQuotaExceeded { action, used, max } => json!({
+                "action": action,
+                "used": used,
+                "max": max,
+            }),
+            ApiError::LockedOut { retry_after } | ApiError::RankedCooldown { retry_after } => json!({
+                "retry_after": retry_after,
+            }),
+            _ => json!({}),
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            ApiError::MissingField(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::InvalidField(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::BinaryTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
-            ApiError::InstructionLimitTooHigh { .. } => {
-                (StatusCode::BAD_REQUEST, self.to_string())
-            }
-            ApiError::DockerError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::Timeout(_) => (StatusCode::GATEWAY_TIMEOUT, self.to_string()),
-            ApiError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
-            ApiError::JobNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::JobNotReady => (StatusCode::ACCEPTED, self.to_string()),
-            ApiError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
-            ApiError::QueueError(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
-            ApiError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::CompileError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::BinaryNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::CompileJobNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::CompileJobNotReady => (StatusCode::ACCEPTED, self.to_string()),
-            ApiError::SourceTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
-            ApiError::InvalidLanguage(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
-            ApiError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
-            ApiError::ChallengeNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::SubmissionNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::VerificationFailed(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-        };
-
-        let body = Json(json!({ "error": message }));
-        (status, body).into_response()
+        let mut body = self.details();
+        body["error"] = json!(self.to_string());
+        body["code"] = json!(self.code());
+        body["retryable"] = json!(self.retryable());
+
+        let mut response = (self.status(), Json(body)).into_response();
+        if let Some(retry_after) = self.retry_after() {
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+        }
+        response
     }
 }
+
+// ============ Handlers ============
+
+#[derive(Debug, Serialize)]
+pub struct ErrorCatalogEntry {
+    pub code: &'static str,
+    pub status: u16,
+    pub retryable: bool,
+    pub description: &'static str,
+}
+
+/// The error catalog, for client authors
+pub async fn list_errors() -> Json<Vec<ErrorCatalogEntry>> {
+    Json(
+        CATALOG
+            .iter()
+            .map(|kind| ErrorCatalogEntry {
+                code: kind.code,
+                status: kind.status.as_u16(),
+                retryable: kind.retryable,
+                description: kind.description,
+            })
+            .collect(),
+    )
+}
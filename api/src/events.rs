@@ -0,0 +1,163 @@
+//! Leaderboard events.
+//!
+//! Whenever a submission improves a user's leaderboard entry the API
+//! publishes a [`LeaderboardImproved`] as JSON to `events.leaderboard` on the
+//! `EVENTS` stream. The stream keeps events for a week whether or not they
+//! were acked, so every subsystem that reacts to leaderboard changes reads it
+//! with a durable consumer of its own (see `NatsQueue::event_consumer`)
+//! instead of re-querying `leaderboard_entries`. Replicas of the API share the
+//! consumer, so each event is handled once.
+//!
+//! The notification sender (`notifications`) is the first such consumer: it
+//! tells the previous record holder that their record was beaten. With the
+//! in-process queue there is no stream and events are handled right away, as
+//! they are when publishing fails.
+
+use crate::error::ApiError;
+use crate::notifications::{self, Mailer, NotificationKind};
+use crate::queue::{NatsQueue, QueueClient};
+use async_nats::jetstream::{self, AckKind};
+use chrono::{DateTime, Utc};
+use ctf_common::topology::{LEADERBOARD_EVENTS_SUBJECT, NOTIFICATIONS_CONSUMER};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Pause before re-creating the consumer after it failed
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A user's best for a challenge and language went down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderboardImproved {
+    pub id: Uuid,
+    pub challenge_id: String,
+    pub challenge_name: String,
+    pub language: String,
+    pub user_id: Uuid,
+    pub username: String,
+    /// The user's previous best; `None` for their first entry
+    pub old_instructions: Option<i64>,
+    pub new_instructions: i64,
+    pub run_id: Uuid,
+    /// The language's record just before this entry, by anyone
+    pub previous_record: Option<Record>,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub user_id: Uuid,
+    pub instructions: i64,
+}
+
+impl LeaderboardImproved {
+    /// Whether this entry took the language record from someone else
+    pub fn beaten_record(&self) -> Option<&Record> {
+        self.previous_record
+            .as_ref()
+            .filter(|r| r.user_id != self.user_id && self.new_instructions < r.instructions)
+    }
+}
+
+/// Publish `event`, or handle it here when there is no stream to publish to
+pub async fn publish_leaderboard_improved(
+    pool: &PgPool,
+    queue: &QueueClient,
+    mailer: Option<&Mailer>,
+    event: &LeaderboardImproved,
+) {
+    if let QueueClient::Nats(q) = queue {
+        let published = match serde_json::to_vec(event) {
+            Ok(payload) => q.publish_event(LEADERBOARD_EVENTS_SUBJECT, &event.id, payload).await,
+            Err(e) => Err(ApiError::Internal(format!("Failed to serialize event: {}", e))),
+        };
+        match published {
+            Ok(()) => return,
+            Err(e) => warn!(event_id = %event.id, error = %e, "Failed to publish leaderboard event, handling it inline"),
+        }
+    }
+
+    if let Err(e) = notify_record_beaten(pool, mailer, event).await {
+        warn!(event_id = %event.id, error = %e, "Failed to handle leaderboard event");
+    }
+}
+
+/// Tell the previous holder when their record was beaten
+async fn notify_record_beaten(
+    pool: &PgPool,
+    mailer: Option<&Mailer>,
+    event: &LeaderboardImproved,
+) -> Result<(), ApiError> {
+    let Some(record) = event.beaten_record() else {
+        return Ok(());
+    };
+    let title = format!("Your {} record on {} was beaten", event.language, event.challenge_name);
+    let body = format!(
+        "{} got it down to {} instructions (yours: {})",
+        event.username, event.new_instructions, record.instructions
+    );
+    let link = format!("/challenges/{}/leaderboard", event.challenge_id);
+    notifications::notify(
+        pool,
+        mailer,
+        &record.user_id,
+        NotificationKind::RecordBeaten,
+        &title,
+        &body,
+        Some(&link),
+    )
+    .await
+}
+
+/// Run the notification sender's consumer for as long as the API runs
+pub fn spawn(queue: Arc<NatsQueue>, pool: PgPool, mailer: Option<Mailer>) {
+    tokio::spawn(async move {
+        loop {
+            match queue.event_consumer(NOTIFICATIONS_CONSUMER, LEADERBOARD_EVENTS_SUBJECT).await {
+                Ok(consumer) => {
+                    info!(consumer = NOTIFICATIONS_CONSUMER, "Following leaderboard events");
+                    if let Err(e) = consume(&consumer, &pool, mailer.as_ref()).await {
+                        warn!(consumer = NOTIFICATIONS_CONSUMER, "Leaderboard event consumer stopped: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to create leaderboard event consumer: {}", e),
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    });
+}
+
+async fn consume(
+    consumer: &jetstream::consumer::PullConsumer,
+    pool: &PgPool,
+    mailer: Option<&Mailer>,
+) -> Result<(), String> {
+    let mut messages = consumer.messages().await.map_err(|e| e.to_string())?;
+
+    while let Some(message) = messages.next().await {
+        let message = message.map_err(|e| e.to_string())?;
+        let ack = match serde_json::from_slice::<LeaderboardImproved>(&message.payload) {
+            Ok(event) => match notify_record_beaten(pool, mailer, &event).await {
+                Ok(()) => AckKind::Ack,
+                Err(e) => {
+                    warn!(event_id = %event.id, error = %e, "Failed to handle leaderboard event, retrying");
+                    AckKind::Nak(Some(RETRY_DELAY))
+                }
+            },
+            // Nothing a retry would fix
+            Err(e) => {
+                warn!(error = %e, "Dropping unreadable leaderboard event");
+                AckKind::Term
+            }
+        };
+        if let Err(e) = message.ack_with(ack).await {
+            warn!(error = %e, "Failed to ack leaderboard event");
+        }
+    }
+
+    Ok(())
+}
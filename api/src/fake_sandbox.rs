@@ -0,0 +1,133 @@
+//! Dockerless stand-ins for the sandbox and compiler, enabled with `SANDBOX_FAKE=1`.
+//!
+//! Only compiled with the `fake-sandbox` feature so release images can never
+//! run it. Results are derived from the SHA-256 of the binary, so the same
+//! input always produces the same stats. Test sources can steer the output
+//! with markers (each terminated by a newline):
+//!
+//! - `FAKE_STDOUT=<text>` - stdout (`\n` escapes are expanded)
+//! - `FAKE_EXIT=<code>` - exit code
+//! - `FAKE_COMPILE_ERROR` - compilation fails
+//! - `FAKE_CRASH` - the executing worker panics (failure injection)
+
+use crate::compiler::CompileOutput;
+use crate::queue::CompileJob;
+use crate::sandbox::{ExecutionResult, LimitReason};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ctf_common::compile_protocol::{CompileFailure, ResourceUsage};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+const FAKE_BINARY_MAGIC: &[u8] = b"FAKEBIN\n";
+
+fn marker<'a>(binary: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let needle = format!("{}=", name);
+    let start = binary
+        .windows(needle.len())
+        .position(|w| w == needle.as_bytes())?
+        + needle.len();
+    let rest = &binary[start..];
+    let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+fn hash_u64(hash: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(hash[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn compile(job: &CompileJob) -> Result<CompileOutput, CompileFailure> {
+    if job.source_code.contains("FAKE_COMPILE_ERROR") {
+        return Err(CompileFailure::Rejected(
+            "Compilation failed (exit 1)\nstdout: \nstderr: fake compile error".to_string(),
+        ));
+    }
+
+    let mut binary = FAKE_BINARY_MAGIC.to_vec();
+    binary.extend_from_slice(job.language.as_str().as_bytes());
+    binary.push(b'\n');
+    binary.extend_from_slice(job.source_code.as_bytes());
+
+    let file_bytes = binary.len();
+    Ok(CompileOutput {
+        binary,
+        compiler_version: Some("fake".to_string()),
+        compile_flags: None,
+        compiler_image: None,
+        asm: job.emit_asm.then(|| format!("; fake assembly for {}\n", job.language.as_str())),
+        size_report: Some(serde_json::json!({
+            "tool": "fake",
+            "file_bytes": file_bytes,
+            "sections": [{ "name": ".text", "size": file_bytes }],
+            "symbol_count": 0,
+            "symbols": [],
+        })),
+        toolchain: job.toolchain.clone(),
+        runtime: None,
+        diagnostics: Vec::new(),
+        resources: Some(ResourceUsage {
+            cpu_seconds: 0.01,
+            peak_memory_bytes: 8 * 1024 * 1024,
+            ..Default::default()
+        }),
+    })
+}
+
+pub fn execute(binary: &[u8], instruction_limit: u64, stdin: &[u8]) -> ExecutionResult {
+    if binary.windows(10).any(|w| w == b"FAKE_CRASH") {
+        panic!("fake sandbox crash requested");
+    }
+
+    let hash = Sha256::digest(binary);
+
+    let wanted = 10_000 + hash_u64(&hash, 0) % 1_000_000;
+    let limit_reached = wanted > instruction_limit;
+    let instructions = wanted.min(instruction_limit);
+
+    let stdout = marker(binary, "FAKE_STDOUT")
+        .map(|s| String::from_utf8_lossy(s).replace("\\n", "\n"))
+        .unwrap_or_default();
+    let exit_code = marker(binary, "FAKE_EXIT")
+        .and_then(|s| std::str::from_utf8(s).ok())
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0);
+
+    let memory_peak_kb = 1024 + hash_u64(&hash, 8) % 4096;
+    let syscalls = 10 + hash_u64(&hash, 16) % 90;
+    let mut syscall_breakdown = HashMap::new();
+    syscall_breakdown.insert("write".to_string(), syscalls / 2);
+    syscall_breakdown.insert("exit_group".to_string(), 1);
+    syscall_breakdown.insert("mmap".to_string(), syscalls - syscalls / 2 - 1);
+
+    ExecutionResult {
+        instructions,
+        memory_peak_kb,
+        memory_rss_kb: memory_peak_kb,
+        memory_hwm_kb: memory_peak_kb,
+        memory_data_kb: memory_peak_kb / 2,
+        memory_stack_kb: 132,
+        io_read_bytes: stdin.len() as u64,
+        io_write_bytes: stdout.len() as u64,
+        guest_mmap_bytes: hash_u64(&hash, 24) % (1024 * 1024),
+        guest_mmap_peak: hash_u64(&hash, 24) % (1024 * 1024),
+        guest_heap_bytes: 0,
+        limit_reached,
+        limit_reached_reason: limit_reached.then_some(LimitReason::Instructions),
+        oom_killed: false,
+        crash_signal: None,
+        memory_limit_mb: None,
+        sandbox_image: None,
+        exit_code,
+        stdout: BASE64.encode(stdout.as_bytes()),
+        stderr: String::new(),
+        execution_time_ms: 1 + instructions / 100_000,
+        syscalls,
+        syscall_breakdown,
+        process_count: 1,
+        processes: Vec::new(),
+        thread_count: 1,
+        threads: Vec::new(),
+        output_files: Vec::new(),
+        core_dump: None,
+        diagnostics: None,
+    }
+}
@@ -0,0 +1,357 @@
+//! Request forms, multipart or JSON, read against a declared schema.
+//!
+//! Handlers list the fields they take (text or bytes, required or not, a
+//! size cap, accepted content types) and get back a [`Form`] to pull typed
+//! values from, so every endpoint answers a bad form the same way:
+//! `missing_field` for an absent required field, `invalid_field` for a
+//! duplicate, a wrong content type or a value that doesn't parse, and the
+//! field's own error once it's over its cap. Unknown fields are ignored.
+//!
+//! A part without a `Content-Type` (the default for `curl -F name=value`) is
+//! always accepted; one with a type must match the field's list, if it has one.
+//!
+//! The same fields can come as a JSON object instead, with the request's
+//! `Content-Type: application/json` (see [`Body`]). Strings, numbers and
+//! booleans stand for the text of a field, objects and arrays for the JSON
+//! text of JSON-valued fields, and `null` for leaving it out. Bytes fields
+//! marked [`Schema::base64`] (uploaded binaries) are base64 strings; other
+//! bytes fields, like `stdin`, are plain strings.
+
+use crate::error::ApiError;
+use axum::{
+    async_trait,
+    extract::{FromRequest, Multipart, Request},
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::warn;
+
+/// Content types accepted for uploaded executables
+pub const BINARY_TYPES: &[&str] = &[
+    "application/octet-stream",
+    "application/x-executable",
+    "application/x-elf",
+    "application/x-sharedlib",
+];
+
+/// Content types accepted for JSON-valued fields
+pub const JSON_TYPES: &[&str] = &["application/json", "text/plain"];
+
+/// Builds the error for a field over its cap from its size and the cap
+type TooLarge = fn(usize, usize) -> ApiError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// UTF-8, like `Field::text`
+    Text,
+    Bytes,
+}
+
+struct FieldSpec {
+    name: &'static str,
+    kind: Kind,
+    required: bool,
+    /// Cap in bytes and the error for going over it
+    max_size: Option<(usize, TooLarge)>,
+    content_types: &'static [&'static str],
+    /// Base64 in JSON bodies (bytes fields only)
+    base64: bool,
+}
+
+impl FieldSpec {
+    fn check_content_type(&self, content_type: Option<&str>) -> Result<(), ApiError> {
+        let Some(content_type) = content_type else {
+            return Ok(());
+        };
+        if self.content_types.is_empty() {
+            return Ok(());
+        }
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        if self.content_types.iter().any(|t| t.eq_ignore_ascii_case(essence)) {
+            return Ok(());
+        }
+        Err(ApiError::InvalidField(format!(
+            "{} must be sent as {} (got {})",
+            self.name,
+            self.content_types.join(" or "),
+            essence
+        )))
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), ApiError> {
+        match self.max_size {
+            Some((max, too_large)) if size > max => Err(too_large(size, max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn value(&self, data: Vec<u8>) -> Result<Value, ApiError> {
+        match self.kind {
+            Kind::Bytes => Ok(Value::Bytes(data)),
+            Kind::Text => String::from_utf8(data)
+                .map(Value::Text)
+                .map_err(|_| ApiError::InvalidField(format!("{} must be UTF-8 text", self.name))),
+        }
+    }
+}
+
+/// A form request body: multipart, or a JSON object when the request says
+/// `Content-Type: application/json`
+pub enum Body {
+    Multipart(Multipart),
+    Json(serde_json::Map<String, serde_json::Value>),
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for Body {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Response> {
+        let is_json = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/json"));
+        if !is_json {
+            return Multipart::from_request(req, state)
+                .await
+                .map(Body::Multipart)
+                .map_err(IntoResponse::into_response);
+        }
+
+        let Json(value) = Json::<serde_json::Value>::from_request(req, state)
+            .await
+            .map_err(|e| ApiError::InvalidField(format!("JSON body: {}", e.body_text())).into_response())?;
+        match value {
+            serde_json::Value::Object(object) => Ok(Body::Json(object)),
+            _ => Err(ApiError::InvalidField("JSON body must be an object".to_string()).into_response()),
+        }
+    }
+}
+
+/// The fields a handler takes; built per request since caps come from config
+#[derive(Default)]
+pub struct Schema {
+    fields: Vec<FieldSpec>,
+    /// Free-form fields taken as text by name prefix, e.g. `flag_`
+    prefixes: Vec<&'static str>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(self, name: &'static str) -> Self {
+        self.field(name, Kind::Text)
+    }
+
+    pub fn bytes(self, name: &'static str) -> Self {
+        self.field(name, Kind::Bytes)
+    }
+
+    /// Accept any text field whose name starts with `prefix`
+    pub fn prefix(mut self, prefix: &'static str) -> Self {
+        self.prefixes.push(prefix);
+        self
+    }
+
+    /// Make the last field required
+    pub fn required(mut self) -> Self {
+        self.last().required = true;
+        self
+    }
+
+    /// Cap the last field at `max` bytes, answering with `too_large(size, max)` past it
+    pub fn max_size(mut self, max: usize, too_large: TooLarge) -> Self {
+        self.last().max_size = Some((max, too_large));
+        self
+    }
+
+    /// Content types the last field may be sent with
+    pub fn content_types(mut self, types: &'static [&'static str]) -> Self {
+        self.last().content_types = types;
+        self
+    }
+
+    /// Take the last (bytes) field as base64 in JSON bodies
+    pub fn base64(mut self) -> Self {
+        self.last().base64 = true;
+        self
+    }
+
+    fn field(mut self, name: &'static str, kind: Kind) -> Self {
+        self.fields.push(FieldSpec {
+            name,
+            kind,
+            required: false,
+            max_size: None,
+            content_types: &[],
+            base64: false,
+        });
+        self
+    }
+
+    fn last(&mut self) -> &mut FieldSpec {
+        self.fields.last_mut().expect("schema modifier before any field")
+    }
+
+    pub async fn read(&self, body: Body) -> Result<Form, ApiError> {
+        let form = match body {
+            Body::Multipart(mut multipart) => self.read_multipart(&mut multipart).await?,
+            Body::Json(object) => self.read_json(object)?,
+        };
+
+        if let Some(missing) = self.fields.iter().find(|f| f.required && !form.values.contains_key(f.name)) {
+            return Err(ApiError::MissingField(missing.name));
+        }
+        Ok(form)
+    }
+
+    async fn read_multipart(&self, multipart: &mut Multipart) -> Result<Form, ApiError> {
+        let mut form = Form::default();
+
+        while let Some(mut field) = multipart.next_field().await? {
+            let name = field.name().unwrap_or("").to_string();
+            let Some(spec) = self.fields.iter().find(|f| f.name == name) else {
+                if self.prefixes.iter().any(|p| name.starts_with(p)) {
+                    let value = field.text().await?;
+                    form.prefixed.push((name, value));
+                } else {
+                    warn!("Unknown field: {}", name);
+                }
+                continue;
+            };
+
+            if form.values.contains_key(spec.name) {
+                return Err(ApiError::InvalidField(format!("{} was sent more than once", spec.name)));
+            }
+            spec.check_content_type(field.content_type())?;
+
+            // Read chunk by chunk so an oversized upload is never held whole;
+            // the rest is still counted to report its size
+            let mut data = Vec::new();
+            let mut size = 0;
+            while let Some(chunk) = field.chunk().await? {
+                size += chunk.len();
+                if spec.max_size.is_none_or(|(max, _)| size <= max) {
+                    data.extend_from_slice(&chunk);
+                }
+            }
+            spec.check_size(size)?;
+            form.values.insert(spec.name, spec.value(data)?);
+        }
+        Ok(form)
+    }
+
+    fn read_json(&self, object: serde_json::Map<String, serde_json::Value>) -> Result<Form, ApiError> {
+        let mut form = Form::default();
+
+        for (name, value) in object {
+            let text = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::String(text) => text,
+                value => value.to_string(),
+            };
+            let Some(spec) = self.fields.iter().find(|f| f.name == name) else {
+                if self.prefixes.iter().any(|p| name.starts_with(p)) {
+                    form.prefixed.push((name, text));
+                } else {
+                    warn!("Unknown field: {}", name);
+                }
+                continue;
+            };
+
+            let data = if spec.base64 {
+                BASE64
+                    .decode(text.trim())
+                    .map_err(|_| ApiError::InvalidField(format!("{} must be base64", spec.name)))?
+            } else {
+                text.into_bytes()
+            };
+            spec.check_size(data.len())?;
+            form.values.insert(spec.name, spec.value(data)?);
+        }
+        Ok(form)
+    }
+}
+
+enum Value {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// A form read by [`Schema::read`]; each value can be taken once
+#[derive(Default)]
+pub struct Form {
+    values: HashMap<&'static str, Value>,
+    prefixed: Vec<(String, String)>,
+}
+
+impl Form {
+    pub fn text(&mut self, name: &str) -> Option<String> {
+        match self.values.remove(name)? {
+            Value::Text(text) => Some(text),
+            Value::Bytes(data) => Some(String::from_utf8_lossy(&data).into_owned()),
+        }
+    }
+
+    pub fn bytes(&mut self, name: &str) -> Option<Vec<u8>> {
+        match self.values.remove(name)? {
+            Value::Text(text) => Some(text.into_bytes()),
+            Value::Bytes(data) => Some(data),
+        }
+    }
+
+    /// A field the schema marks required (or `missing_field` if it doesn't)
+    pub fn required_text(&mut self, name: &'static str) -> Result<String, ApiError> {
+        self.text(name).ok_or(ApiError::MissingField(name))
+    }
+
+    pub fn number<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, ApiError> {
+        self.text(name)
+            .map(|text| {
+                text.trim()
+                    .parse()
+                    .map_err(|_| ApiError::InvalidField(format!("{} must be a number", name)))
+            })
+            .transpose()
+    }
+
+    /// `true`/`1` or `false`/`0`
+    pub fn flag(&mut self, name: &str) -> Result<Option<bool>, ApiError> {
+        self.text(name)
+            .map(|text| match text.trim() {
+                "true" | "1" => Ok(true),
+                "false" | "0" => Ok(false),
+                _ => Err(ApiError::InvalidField(format!("{} must be true or false", name))),
+            })
+            .transpose()
+    }
+
+    pub fn json<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<T>, ApiError> {
+        self.text(name)
+            .map(|text| {
+                serde_json::from_str(&text)
+                    .map_err(|e| ApiError::InvalidField(format!("{} must be valid JSON: {}", name, e)))
+            })
+            .transpose()
+    }
+
+    /// Fields matched by `Schema::prefix(prefix)`, with the prefix stripped
+    pub fn prefixed(&mut self, prefix: &str) -> Vec<(String, String)> {
+        let (matched, rest) = std::mem::take(&mut self.prefixed)
+            .into_iter()
+            .partition(|(name, _)| name.starts_with(prefix));
+        self.prefixed = rest;
+        matched
+            .into_iter()
+            .map(|(name, value)| (name[prefix.len()..].to_string(), value))
+            .collect()
+    }
+}
@@ -0,0 +1,296 @@
+//! Compiling a GitHub gist without copying it into the editor.
+//!
+//! `POST /import/gist` takes a gist URL (or bare id), looks the gist up
+//! through the GitHub API at `GIST_API_URL`, picks one of its files and
+//! queues it as a compile job exactly like `/compile`: the same backpressure,
+//! anonymous-token, source and quota checks apply. The language comes from
+//! the file's extension unless the request names one.
+//!
+//! Each import makes a request to GitHub on the server's behalf, so callers
+//! are admitted (and an anonymous token spent) before the gist is fetched,
+//! and imports are limited per client IP
+//! (`GIST_IMPORT_RATE_LIMIT_PER_MINUTE`).
+//!
+//! Only the API response is fetched, and it is read at most
+//! [`MAX_RESPONSE_BYTES`] deep. Files GitHub truncates in that response are
+//! over a megabyte, so they're refused as too large instead of being fetched
+//! from their raw URL.
+
+use crate::auth::MaybeAuthenticatedUser;
+use crate::build_options::BuildOptions;
+use crate::error::ApiError;
+use crate::queue::{CompileJob, Language, Optimization};
+use crate::{anonymous, check_backpressure, enqueue_compile, limits, resolve_language, stats, AppState, CompileSubmitResponse};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+/// Gist API responses are cut off here, whatever the gist holds
+const MAX_RESPONSE_BYTES: usize = 8 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Extensions that aren't some language's [`Language::source_extension`]
+const EXTRA_EXTENSIONS: &[(&str, Language)] = &[
+    ("cc", Language::Cpp),
+    ("cxx", Language::Cpp),
+    ("asm", Language::Asm),
+    ("f", Language::Fortran),
+    ("f95", Language::Fortran),
+    ("exs", Language::Elixir),
+    ("mjs", Language::Javascript),
+];
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct ImportGistRequest {
+    /// `https://gist.github.com/<user>/<id>` or just the id
+    pub url: String,
+    /// Which file to build; needed when several have a known language
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Overrides the language inferred from the file's extension
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub runtime: Option<String>,
+    #[serde(default)]
+    pub optimization: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportGistResponse {
+    #[serde(flatten)]
+    compile: CompileSubmitResponse,
+    gist_id: String,
+    file: String,
+    /// The imported source, for loading into the editor
+    source_code: String,
+}
+
+/// The subset of GitHub's gist object we read
+#[derive(Deserialize)]
+struct Gist {
+    files: BTreeMap<String, GistFile>,
+}
+
+#[derive(Deserialize)]
+struct GistFile {
+    size: usize,
+    #[serde(default)]
+    truncated: bool,
+    content: Option<String>,
+}
+
+// ============ Handlers ============
+
+pub async fn import_gist(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<ImportGistRequest>,
+) -> Result<Json<ImportGistResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+    check_backpressure(
+        &state,
+        "compile",
+        queue.get_compile_queue_depth().await,
+        state.config.max_compile_queue_depth,
+    )?;
+
+    let gist_id = parse_gist_id(&req.url)
+        .ok_or_else(|| ApiError::InvalidField(format!("'{}' is not a gist URL", req.url.trim())))?;
+    let ip = stats::client_ip(&state.config, &headers, peer.map(|ConnectInfo(addr)| addr));
+    state.gist_import_limiter.check(ip)?;
+    anonymous::admit(&state, user.as_ref(), &headers, anonymous::Usage::Compile).await?;
+    let gist = fetch_gist(&state.config.gist_api_url, gist_id).await?;
+    let (name, file, inferred) = pick_file(gist, req.file.as_deref())?;
+
+    if file.truncated || file.size > state.config.max_source_size {
+        return Err(ApiError::SourceTooLarge {
+            size: file.size,
+            max: state.config.max_source_size,
+        });
+    }
+    let source_code = file
+        .content
+        .ok_or_else(|| ApiError::Internal(format!("Gist file '{}' came back without content", name)))?;
+
+    let language_name = req
+        .language
+        .as_deref()
+        .or(inferred.as_ref().map(Language::as_str))
+        .ok_or(ApiError::MissingField("language"))?;
+    let language = resolve_language(language_name, req.runtime.as_deref())?;
+    let warnings: Vec<String> = req.language.as_deref().and_then(Language::deprecation).into_iter().collect();
+    limits::check_source(&state.config, language, &source_code)?;
+
+    let optimization = req
+        .optimization
+        .as_deref()
+        .and_then(Optimization::from_str)
+        .unwrap_or(Optimization::Release);
+    let job = CompileJob {
+        id: Uuid::new_v4(),
+        user_id: user.map(|u| u.id),
+        source_code: source_code.clone(),
+        language,
+        optimization,
+        flags: HashMap::new(),
+        compiler_image: None,
+        emit_asm: false,
+        build_options: BuildOptions::default(),
+        toolchain: None,
+        retry_failed: false,
+        created_at: Utc::now(),
+    };
+    let job_id = job.id;
+    let position = enqueue_compile(&state, queue, job).await?;
+
+    info!(
+        compile_job_id = %job_id,
+        gist_id = %gist_id,
+        file = %name,
+        language = ?language,
+        "Gist imported"
+    );
+
+    Ok(Json(ImportGistResponse {
+        compile: CompileSubmitResponse {
+            compile_job_id: job_id,
+            status: "queued",
+            position,
+            language: language.canonical(),
+            runtime: language.runtime(),
+            warnings,
+        },
+        gist_id: gist_id.to_string(),
+        file: name,
+        source_code,
+    }))
+}
+
+// ============ Helpers ============
+
+/// The gist id in a `gist.github.com` URL, or `url` itself if it is one
+fn parse_gist_id(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let path = match url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        Some(rest) => rest.strip_prefix("gist.github.com/")?,
+        None => url,
+    };
+    // <user>/<id>, <id>, maybe followed by /<revision>, #file-... or ?query
+    let path = path.split(['#', '?']).next()?;
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let first = segments.next()?;
+    let id = match segments.next() {
+        Some(second) if is_gist_id(second) => second,
+        _ => first,
+    };
+    is_gist_id(id).then_some(id)
+}
+
+/// Gist ids are hex: 20 characters for old gists, 32 for newer ones
+fn is_gist_id(s: &str) -> bool {
+    (20..=40).contains(&s.len()) && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+async fn fetch_gist(api_url: &str, gist_id: &str) -> Result<Gist, ApiError> {
+    let mut response = reqwest::Client::new()
+        .get(format!("{}/gists/{}", api_url.trim_end_matches('/'), gist_id))
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "CTF-Arena")
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| ApiError::Internal(format!("GitHub unreachable: {}", e)))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ApiError::NotFound(format!("Gist '{}'", gist_id)));
+    }
+    if !response.status().is_success() {
+        return Err(ApiError::Internal(format!(
+            "GitHub answered {} for gist '{}'",
+            response.status(),
+            gist_id
+        )));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to read gist: {}", e)))?
+    {
+        if body.len() + chunk.len() > MAX_RESPONSE_BYTES {
+            return Err(ApiError::InvalidField(format!("gist '{}' is too large to import", gist_id)));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&body).map_err(|e| ApiError::Internal(format!("Unexpected gist response: {}", e)))
+}
+
+/// The file to build, by name if one was asked for, else the only one with a
+/// language we can tell from its extension; alongside it, that language
+fn pick_file(gist: Gist, requested: Option<&str>) -> Result<(String, GistFile, Option<Language>), ApiError> {
+    let mut files = gist.files;
+    if let Some(requested) = requested {
+        let (name, file) = files
+            .remove_entry(requested)
+            .ok_or_else(|| ApiError::InvalidField(format!("the gist has no file '{}'", requested)))?;
+        let language = language_for(&name);
+        return Ok((name, file, language));
+    }
+
+    let mut candidates: Vec<(String, GistFile, Option<Language>)> = files
+        .into_iter()
+        .map(|(name, file)| {
+            let language = language_for(&name);
+            (name, file, language)
+        })
+        .filter(|(_, _, language)| language.is_some())
+        .collect();
+    match candidates.len() {
+        1 => Ok(candidates.remove(0)),
+        0 => Err(ApiError::InvalidField(
+            "no file in the gist has a known source extension, pass file and language".to_string(),
+        )),
+        _ => Err(ApiError::InvalidField(format!(
+            "the gist has several source files ({}), pass file",
+            candidates.iter().map(|(name, _, _)| name.as_str()).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+/// The language a file name's extension suggests; JavaScript and TypeScript
+/// map to the languages, not to a runtime
+fn language_for(name: &str) -> Option<Language> {
+    let (_, extension) = name.rsplit_once('.')?;
+    Language::ALL
+        .iter()
+        .copied()
+        .filter(|l| !matches!(l, Language::Bun | Language::Deno | Language::Node))
+        .find(|l| l.source_extension().eq_ignore_ascii_case(extension))
+        .or_else(|| {
+            EXTRA_EXTENSIONS
+                .iter()
+                .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+                .map(|(_, language)| *language)
+        })
+}
@@ -0,0 +1,161 @@
+//! Data retention, so self-hosted databases stop growing without bound.
+//!
+//! Every `JANITOR_INTERVAL_SECONDS` a background task deletes runs older than
+//! `RUN_RETENTION_DAYS` (runs a leaderboard entry points at are kept for as
+//! long as the entry exists), strips stdout and stderr from runs older than
+//! `RUN_OUTPUT_RETENTION_DAYS`, and clears expired sessions, rate-limit
+//! rows, challenge test results cached past `CHALLENGE_TEST_CACHE_SECONDS`
+//! records of jobs shared with external workers past `JOB_TTL_SECONDS` and
+//! GitHub webhook delivery IDs older than a week.
+//! Work is done in batches so a first pass over a large backlog never holds
+//! long locks on `runs`.
+//!
+//! Each pass records how many rows it removed per table in
+//! `retention_stats`; `GET /admin/retention` reports those next to every
+//! table's current size. Every replica runs the task; the statements are
+//! idempotent, so they only share the work.
+
+use crate::auth::AdminUser;
+use crate::db::{self, RetentionStat, TableSize};
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{extract::State, Json};
+use chrono::{TimeDelta, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Rows per delete/update statement
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RetentionPolicy {
+    /// 0 disables the janitor
+    pub interval_seconds: u64,
+    /// 0 keeps runs forever
+    pub run_retention_days: u64,
+    /// 0 keeps run output forever
+    pub run_output_retention_days: u64,
+    /// Cached challenge test results older than this are dropped
+    pub challenge_test_cache_seconds: u64,
+    /// Shared-job records are dropped once their job has left NATS
+    pub job_ttl_seconds: u64,
+}
+
+impl RetentionPolicy {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self {
+            interval_seconds: config.janitor_interval_seconds,
+            run_retention_days: config.run_retention_days,
+            run_output_retention_days: config.run_output_retention_days,
+            challenge_test_cache_seconds: config.challenge_test_cache_seconds,
+            job_ttl_seconds: config.job_ttl_seconds,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionResponse {
+    pub policy: RetentionPolicy,
+    /// Rows removed per table, last pass and in total
+    pub removed: Vec<RetentionStat>,
+    pub tables: Vec<TableSize>,
+}
+
+pub fn spawn(pool: PgPool, policy: RetentionPolicy) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(policy.interval_seconds));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = run_pass(&pool, policy).await {
+                error!("Janitor pass failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Apply the retention policy once
+pub async fn run_pass(pool: &PgPool, policy: RetentionPolicy) -> Result<(), ApiError> {
+    let now = Utc::now();
+
+    let deleted_runs = if policy.run_retention_days > 0 {
+        let cutoff = now - TimeDelta::days(policy.run_retention_days as i64);
+        let rows = in_batches(|| db::delete_expired_runs(pool, cutoff, BATCH_SIZE)).await?;
+        db::record_retention_pass(pool, "runs", "deleted", rows).await?;
+        rows
+    } else {
+        0
+    };
+
+    let trimmed_runs = if policy.run_output_retention_days > 0 {
+        let cutoff = now - TimeDelta::days(policy.run_output_retention_days as i64);
+        let rows = in_batches(|| db::trim_expired_run_output(pool, cutoff, BATCH_SIZE)).await?;
+        db::record_retention_pass(pool, "runs", "trimmed", rows).await?;
+        rows
+    } else {
+        0
+    };
+
+    let sessions = db::cleanup_expired_sessions(pool).await?;
+    db::record_retention_pass(pool, "sessions", "deleted", sessions).await?;
+    let rate_limits = db::cleanup_old_rate_limits(pool).await?;
+    db::record_retention_pass(pool, "rate_limits", "deleted", rate_limits).await?;
+    let test_cache_cutoff = now - TimeDelta::seconds(policy.challenge_test_cache_seconds as i64);
+    let cached_tests = db::cleanup_expired_test_cache(pool, test_cache_cutoff).await?;
+    db::record_retention_pass(pool, "challenge_test_cache", "deleted", cached_tests).await?;
+    let shared_jobs_cutoff = now - TimeDelta::seconds(policy.job_ttl_seconds as i64);
+    let shared_jobs = db::cleanup_old_shared_jobs(pool, shared_jobs_cutoff).await?;
+    db::record_retention_pass(pool, "shared_jobs", "deleted", shared_jobs).await?;
+    let github_deliveries = db::cleanup_old_github_deliveries(pool).await?;
+    db::record_retention_pass(pool, "github_deliveries", "deleted", github_deliveries).await?;
+
+    info!(
+        deleted_runs,
+        trimmed_runs,
+        sessions,
+        rate_limits,
+        cached_tests,
+        shared_jobs,
+        github_deliveries,
+        "Janitor pass finished"
+    );
+    Ok(())
+}
+
+/// Repeat `batch` until it touches fewer than [`BATCH_SIZE`] rows
+async fn in_batches<F, Fut>(mut batch: F) -> Result<u64, ApiError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<u64, ApiError>>,
+{
+    let mut total = 0;
+    loop {
+        let rows = batch().await?;
+        total += rows;
+        if rows < BATCH_SIZE as u64 {
+            return Ok(total);
+        }
+    }
+}
+
+// ============ Handlers ============
+
+pub async fn retention(
+    State(state): State<Arc<AppState>>,
+    AdminUser(_admin): AdminUser,
+) -> Result<Json<RetentionResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let (removed, tables) = tokio::try_join!(db::list_retention_stats(pool), db::table_sizes(pool))?;
+
+    Ok(Json(RetentionResponse {
+        policy: RetentionPolicy::from_config(&state.config),
+        removed,
+        tables,
+    }))
+}
@@ -0,0 +1,153 @@
+//! The deprecated synchronous `POST /execute`, kept for old clients.
+//!
+//! With a queue the binary is stored and run on the workers like `/submit`,
+//! and the request waits for the result. Without one it runs in this
+//! process through Docker, unless `DIRECT_EXECUTION=false`.
+//!
+//! Compiled in by the `legacy-execute` feature (on by default); build with
+//! `--no-default-features` to drop the route along with its Docker path.
+
+use crate::auth::MaybeAuthenticatedUser;
+use crate::binary_transfer;
+use crate::db::{RunVisibility, User};
+use crate::error::ApiError;
+use crate::queue::Job;
+use crate::{form, limits, parse_memory_limit_mb, parse_timeout_ms, sandbox, AppState};
+use axum::{
+    extract::State,
+    routing::post,
+    Json, Router,
+};
+use chrono::Utc;
+use ctf_common::diagnostics::ExecutionMode;
+use sha2::Digest;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/execute", post(execute))
+}
+
+/// The form `/execute` takes (multipart or JSON)
+struct ExecuteRequest {
+    binary: Vec<u8>,
+    instruction_limit: u64,
+    timeout_ms: Option<u64>,
+    memory_limit_mb: Option<u32>,
+    stdin: Vec<u8>,
+}
+
+impl ExecuteRequest {
+    async fn parse(state: &AppState, user: Option<&User>, body: form::Body) -> Result<Self, ApiError> {
+        let instruction_limits = limits::InstructionLimits::for_user(&state.config, user);
+        let mut form = form::Schema::new()
+            .bytes("binary")
+            .required()
+            .max_size(state.config.max_binary_size, |size, max| ApiError::BinaryTooLarge { size, max })
+            .content_types(form::BINARY_TYPES)
+            .base64()
+            .text("instruction_limit")
+            .text("timeout_ms")
+            .text("memory_limit_mb")
+            .bytes("stdin")
+            .read(body)
+            .await?;
+
+        let instruction_limit = form.number("instruction_limit")?.map(|l| instruction_limits.check(l)).transpose()?;
+
+        Ok(Self {
+            binary: form.bytes("binary").ok_or(ApiError::MissingField("binary"))?,
+            instruction_limit: instruction_limit.unwrap_or(instruction_limits.default),
+            timeout_ms: form.text("timeout_ms").map(|t| parse_timeout_ms(state, &t)).transpose()?,
+            memory_limit_mb: form.text("memory_limit_mb").map(|t| parse_memory_limit_mb(state, &t)).transpose()?,
+            stdin: form.bytes("stdin").unwrap_or_default(),
+        })
+    }
+}
+
+async fn execute(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    body: form::Body,
+) -> Result<Json<sandbox::ExecutionResult>, ApiError> {
+    let request = ExecuteRequest::parse(&state, user.as_ref(), body).await?;
+
+    if state.queue.is_some() {
+        return execute_queued(&state, request).await.map(Json);
+    }
+    if !state.config.direct_execution {
+        return Err(ApiError::WorkersOnly);
+    }
+    execute_direct(&state, request).await.map(Json)
+}
+
+/// Store the binary and run it on the workers, waiting for the result
+async fn execute_queued(state: &AppState, request: ExecuteRequest) -> Result<sandbox::ExecutionResult, ApiError> {
+    let pool = state.db.as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+    let binary_id = format!("sha256-{}", hex::encode(sha2::Sha256::digest(&request.binary)));
+    binary_transfer::store(pool, &state.config, &binary_id, &request.binary, None, None).await?;
+
+    let queue = state.queue.as_ref().unwrap();
+
+    let job = Job {
+        id: Uuid::new_v4(),
+        user_id: None,
+        binary_id,
+        instruction_limit: request.instruction_limit,
+        stdin: request.stdin,
+        created_at: Utc::now(),
+        benchmark_id: None,
+        benchmark_version: None,
+        network_enabled: false,
+        env_vars: HashMap::new(),
+        args: Vec::new(),
+        outputs: Vec::new(),
+        core_dump: false,
+        mode: ExecutionMode::Normal,
+        fixtures: None,
+        sandbox_options: None,
+        sandbox_signature: None,
+        timeout_ms: request.timeout_ms,
+        memory_limit_mb: request.memory_limit_mb,
+        visibility: RunVisibility::Public,
+        challenge_id: None,
+    };
+    let job_id = job.id;
+    queue.submit_job(job).await?;
+
+    queue
+        .await_job(&job_id, Duration::from_secs(state.config.timeout_sec))
+        .await
+}
+
+/// Run the binary in this process (the original behavior)
+async fn execute_direct(state: &AppState, request: ExecuteRequest) -> Result<sandbox::ExecutionResult, ApiError> {
+    // Acquire semaphore permit for concurrency control
+    let _permit = state
+        .semaphore
+        .try_acquire()
+        .map_err(|_| ApiError::TooManyRequests)?;
+
+    info!(
+        binary_size = request.binary.len(),
+        instruction_limit = request.instruction_limit,
+        "Executing binary"
+    );
+
+    let limits = sandbox::limits(request.instruction_limit, request.timeout_ms, request.memory_limit_mb, &state.config);
+    let (result, _) = sandbox::execute(request.binary, limits, request.stdin, &[], false, &HashMap::new(), Default::default(), &state.config).await?;
+
+    info!(
+        instructions = result.instructions,
+        memory_kb = result.memory_peak_kb,
+        exit_code = result.exit_code,
+        time_ms = result.execution_time_ms,
+        "Execution complete"
+    );
+
+    Ok(result)
+}
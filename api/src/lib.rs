@@ -0,0 +1,1848 @@
+// The fake sandbox judges nothing; it's for the integration tests' debug builds
+#[cfg(all(feature = "fake-sandbox", not(debug_assertions)))]
+compile_error!("the fake-sandbox feature must not be enabled in release builds");
+
+pub mod admin;
+pub mod anonymous;
+pub mod audit;
+pub mod auth;
+pub mod badge;
+pub mod binaries;
+pub mod binary_transfer;
+pub mod build_options;
+pub mod challenges;
+pub mod comments;
+pub mod compiler;
+pub mod config;
+pub mod core_dumps;
+pub mod countries;
+pub mod db;
+pub mod digest;
+pub mod embed;
+pub mod error;
+pub mod events;
+pub mod form;
+#[cfg(feature = "fake-sandbox")]
+pub mod fake_sandbox;
+pub mod gist;
+pub mod janitor;
+#[cfg(feature = "legacy-execute")]
+pub mod legacy;
+pub mod limits;
+pub mod local_queue;
+pub mod manifest;
+pub mod notifications;
+pub mod orgs;
+pub mod pins;
+pub mod preview;
+pub mod provenance;
+pub mod queue;
+pub mod reconciler;
+pub mod repro;
+pub mod request_id;
+pub mod sandbox;
+pub mod repos;
+pub mod secrets;
+pub mod security;
+pub mod share;
+pub mod stars;
+pub mod stats;
+pub mod throttle;
+pub mod toolchains;
+pub mod tracked;
+pub mod usage;
+pub mod version;
+pub mod workers;
+pub mod writeups;
+
+use auth::{AuthenticatedUser, MaybeAuthenticatedUser};
+use axum::{
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
+    Json, Router,
+};
+use build_options::BuildOptions;
+use db::{BinaryMetadata, Run, RunVisibility, SaveRunRequest};
+use chrono::{DateTime, Utc};
+use config::Config;
+use ctf_common::binary_encoding;
+use ctf_common::diagnostics::ExecutionMode;
+use ctf_common::runtime::RuntimeManifest;
+use error::ApiError;
+use queue::{CompileJob, CompileStatus, Job, JobStatus, Language, Optimization, QueueClient};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use security::HeaderPolicy;
+use sha2::Digest;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub struct AppState {
+    pub config: Config,
+    pub semaphore: Semaphore,
+    pub queue: Option<QueueClient>,
+    pub db: Option<PgPool>,
+    pub auth_config: Option<auth::AuthConfig>,
+    pub mailer: Option<notifications::Mailer>,
+    pub env_cipher: secrets::EnvVarCipher,
+    pub challenge_signer: provenance::ChallengeSigner,
+    /// Set when `GITHUB_APP_*` and `GITHUB_WEBHOOK_SECRET` are (repos.rs)
+    pub github_app: Option<repos::GitHubApp>,
+    pub public_stats_limiter: stats::IpRateLimiter,
+    pub verification_limiter: stats::IpRateLimiter,
+    pub anonymous_token_limiter: stats::IpRateLimiter,
+    pub gist_import_limiter: stats::IpRateLimiter,
+    pub api_calls: usage::ApiCallCounter,
+    pub binary_transfer: binary_transfer::TransferStats,
+}
+
+// ============ Benchmark Types ============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BenchmarkDef {
+    id: String,
+    /// Bump whenever stdin, env_vars or the expected output change; runs
+    /// record the version they ran against and stats are kept per version
+    version: u32,
+    name: String,
+    description: String,
+    implementations: Vec<BenchmarkImpl>,
+    #[serde(default)]
+    env_vars: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    stdin: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BenchmarkImpl {
+    language: String,
+    name: String,
+    file: String,
+    tier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference_instructions: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BenchmarkImplWithSource {
+    language: String,
+    name: String,
+    file: String,
+    tier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference_instructions: Option<u64>,
+    source_code: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BenchmarkWithSource {
+    id: String,
+    version: u32,
+    name: String,
+    description: String,
+    implementations: Vec<BenchmarkImplWithSource>,
+    env_vars: std::collections::HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdin: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BenchmarkQuery {
+    #[serde(default)]
+    include_source: bool,
+}
+
+/// The version runs of a benchmark are recorded and compared under;
+/// `None` for unknown benchmarks (and challenge IDs)
+pub(crate) fn current_benchmark_version(id: &str) -> Option<u32> {
+    find_benchmark(id).map(|b| b.version)
+}
+
+/// Every benchmark with the version its stats are currently kept under
+pub(crate) fn benchmark_versions() -> Vec<(String, u32)> {
+    get_benchmarks_config().into_iter().map(|b| (b.id, b.version)).collect()
+}
+
+/// A benchmark's definition, for jobs the API builds itself
+pub(crate) fn find_benchmark(id: &str) -> Option<BenchmarkDef> {
+    get_benchmarks_config().into_iter().find(|b| b.id == id)
+}
+
+fn get_benchmarks_config() -> Vec<BenchmarkDef> {
+    vec![
+        // Hello World benchmark
+        BenchmarkDef {
+            id: "hello-world".to_string(),
+            version: 1,
+            name: "Hello World".to_string(),
+            description: "Print \"Hello, World!\" followed by a newline. The simplest benchmark.".to_string(),
+            env_vars: std::collections::HashMap::new(),
+            stdin: None,
+            implementations: vec![
+                BenchmarkImpl { language: "asm".to_string(), name: "Assembly".to_string(), file: "hello.S".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "c".to_string(), name: "C (musl)".to_string(), file: "hello.c".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "cpp".to_string(), name: "C++".to_string(), file: "hello.cpp".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "rust".to_string(), name: "Rust".to_string(), file: "hello.rs".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "go".to_string(), name: "Go".to_string(), file: "hello.go".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "zig".to_string(), name: "Zig".to_string(), file: "hello.zig".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "nim".to_string(), name: "Nim".to_string(), file: "hello.nim".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "pascal".to_string(), name: "Pascal".to_string(), file: "hello.pas".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "swift".to_string(), name: "Swift".to_string(), file: "hello.swift".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "ocaml".to_string(), name: "OCaml".to_string(), file: "hello.ml".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "haskell".to_string(), name: "Haskell".to_string(), file: "hello.hs".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "fortran".to_string(), name: "Fortran".to_string(), file: "hello.f90".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "d".to_string(), name: "D (LDC)".to_string(), file: "hello.d".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "crystal".to_string(), name: "Crystal".to_string(), file: "hello.cr".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "v".to_string(), name: "V".to_string(), file: "hello.v".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "ada".to_string(), name: "Ada (GNAT)".to_string(), file: "hello.adb".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "java".to_string(), name: "Java (GraalVM)".to_string(), file: "hello.java".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "kotlin".to_string(), name: "Kotlin (GraalVM)".to_string(), file: "hello.kt".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "scala".to_string(), name: "Scala (GraalVM)".to_string(), file: "hello.scala".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "clojure".to_string(), name: "Clojure (GraalVM)".to_string(), file: "hello.clj".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "csharp".to_string(), name: "C# (.NET AOT)".to_string(), file: "hello.cs".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "python".to_string(), name: "Python".to_string(), file: "hello.py".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "javascript".to_string(), name: "JavaScript (Bun)".to_string(), file: "hello.js".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "typescript".to_string(), name: "TypeScript (Bun)".to_string(), file: "hello.ts".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "node".to_string(), name: "JavaScript (Node)".to_string(), file: "hello.js".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "deno".to_string(), name: "TypeScript (Deno)".to_string(), file: "hello.ts".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "lua".to_string(), name: "Lua".to_string(), file: "hello.lua".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "perl".to_string(), name: "Perl".to_string(), file: "hello.pl".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "racket".to_string(), name: "Racket".to_string(), file: "hello.rkt".to_string(), tier: "special".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "erlang".to_string(), name: "Erlang".to_string(), file: "hello.erl".to_string(), tier: "special".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "elixir".to_string(), name: "Elixir".to_string(), file: "hello.exs".to_string(), tier: "special".to_string(), reference_instructions: None },
+            ],
+        },
+        // Env Leak benchmark
+        BenchmarkDef {
+            id: "env-leak".to_string(),
+            version: 1,
+            name: "Environment Variable Leak".to_string(),
+            description: "Read the FLAG environment variable and print its value.".to_string(),
+            env_vars: [("FLAG".to_string(), "CTF{env_leak_test}".to_string())].into_iter().collect(),
+            stdin: None,
+            implementations: vec![
+                BenchmarkImpl { language: "asm".to_string(), name: "Assembly".to_string(), file: "envleak.S".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "c".to_string(), name: "C (musl)".to_string(), file: "envleak.c".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "cpp".to_string(), name: "C++".to_string(), file: "envleak.cpp".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "rust".to_string(), name: "Rust".to_string(), file: "envleak.rs".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "go".to_string(), name: "Go".to_string(), file: "envleak.go".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "zig".to_string(), name: "Zig".to_string(), file: "envleak.zig".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "nim".to_string(), name: "Nim".to_string(), file: "envleak.nim".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "pascal".to_string(), name: "Pascal".to_string(), file: "envleak.pas".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "swift".to_string(), name: "Swift".to_string(), file: "envleak.swift".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "ocaml".to_string(), name: "OCaml".to_string(), file: "envleak.ml".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "haskell".to_string(), name: "Haskell".to_string(), file: "envleak.hs".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "java".to_string(), name: "Java (GraalVM)".to_string(), file: "envleak.java".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "kotlin".to_string(), name: "Kotlin (GraalVM)".to_string(), file: "envleak.kt".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "scala".to_string(), name: "Scala (GraalVM)".to_string(), file: "envleak.scala".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "clojure".to_string(), name: "Clojure (GraalVM)".to_string(), file: "envleak.clj".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "csharp".to_string(), name: "C# (.NET AOT)".to_string(), file: "envleak.cs".to_string(), tier: "managed".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "python".to_string(), name: "Python".to_string(), file: "envleak.py".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "javascript".to_string(), name: "JavaScript (Bun)".to_string(), file: "envleak.js".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "typescript".to_string(), name: "TypeScript (Bun)".to_string(), file: "envleak.ts".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "node".to_string(), name: "JavaScript (Node)".to_string(), file: "envleak.js".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "deno".to_string(), name: "TypeScript (Deno)".to_string(), file: "envleak_deno.ts".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "lua".to_string(), name: "Lua".to_string(), file: "envleak.lua".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "perl".to_string(), name: "Perl".to_string(), file: "envleak.pl".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+            ],
+        },
+        // Base64 Decode benchmark
+        BenchmarkDef {
+            id: "base64-decode".to_string(),
+            version: 1,
+            name: "Base64 Decode".to_string(),
+            description: "Decode a base64-encoded string from stdin and print the decoded output.".to_string(),
+            env_vars: std::collections::HashMap::new(),
+            stdin: Some("SGVsbG8sIFdvcmxkIQ==".to_string()), // "Hello, World!" in base64
+            implementations: vec![
+                BenchmarkImpl { language: "c".to_string(), name: "C (musl)".to_string(), file: "base64.c".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "rust".to_string(), name: "Rust".to_string(), file: "base64.rs".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "go".to_string(), name: "Go".to_string(), file: "base64.go".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "zig".to_string(), name: "Zig".to_string(), file: "base64.zig".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "nim".to_string(), name: "Nim".to_string(), file: "base64.nim".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "python".to_string(), name: "Python".to_string(), file: "base64.py".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "javascript".to_string(), name: "JavaScript (Bun)".to_string(), file: "base64.js".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "node".to_string(), name: "JavaScript (Node)".to_string(), file: "base64.js".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "lua".to_string(), name: "Lua".to_string(), file: "base64.lua".to_string(), tier: "scripting".to_string(), reference_instructions: None },
+            ],
+        },
+        // Port Scanner benchmark
+        BenchmarkDef {
+            id: "portscan".to_string(),
+            version: 1,
+            name: "Port Scanner".to_string(),
+            description: "Scan localhost (127.0.0.1) on ports 22, 80, 443. Print \"<port> open\" for each open port.".to_string(),
+            env_vars: std::collections::HashMap::new(),
+            stdin: None,
+            implementations: vec![
+                BenchmarkImpl { language: "asm".to_string(), name: "Assembly (minimal)".to_string(), file: "portscan_min.S".to_string(), tier: "native".to_string(), reference_instructions: Some(51) },
+                BenchmarkImpl { language: "asm".to_string(), name: "Assembly (optimized)".to_string(), file: "portscan_opt.S".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "asm".to_string(), name: "Assembly (with function)".to_string(), file: "portscan_asm.S".to_string(), tier: "native".to_string(), reference_instructions: None },
+                BenchmarkImpl { language: "c".to_string(), name: "C (musl)".to_string(), file: "portscan.c".to_string(), tier: "native".to_string(), reference_instructions: Some(2008) },
+                BenchmarkImpl { language: "c".to_string(), name: "C (raw syscalls)".to_string(), file: "portscan_raw.c".to_string(), tier: "native".to_string(), reference_instructions: Some(1525) },
+                BenchmarkImpl { language: "cpp".to_string(), name: "C++".to_string(), file: "portscan.cpp".to_string(), tier: "native".to_string(), reference_instructions: Some(535110) },
+                BenchmarkImpl { language: "rust".to_string(), name: "Rust".to_string(), file: "portscan.rs".to_string(), tier: "native".to_string(), reference_instructions: Some(24530) },
+                BenchmarkImpl { language: "go".to_string(), name: "Go".to_string(), file: "portscan.go".to_string(), tier: "native".to_string(), reference_instructions: Some(1120285) },
+                BenchmarkImpl { language: "zig".to_string(), name: "Zig".to_string(), file: "portscan.zig".to_string(), tier: "native".to_string(), reference_instructions: Some(594) },
+                BenchmarkImpl { language: "nim".to_string(), name: "Nim".to_string(), file: "portscan.nim".to_string(), tier: "native".to_string(), reference_instructions: Some(23580) },
+                BenchmarkImpl { language: "pascal".to_string(), name: "Pascal".to_string(), file: "portscan.pas".to_string(), tier: "native".to_string(), reference_instructions: Some(30375) },
+                BenchmarkImpl { language: "swift".to_string(), name: "Swift".to_string(), file: "portscan.swift".to_string(), tier: "native".to_string(), reference_instructions: Some(45503) },
+                BenchmarkImpl { language: "ocaml".to_string(), name: "OCaml".to_string(), file: "portscan.ml".to_string(), tier: "native".to_string(), reference_instructions: Some(295270) },
+                BenchmarkImpl { language: "haskell".to_string(), name: "Haskell".to_string(), file: "portscan.hs".to_string(), tier: "native".to_string(), reference_instructions: Some(558511) },
+                BenchmarkImpl { language: "java".to_string(), name: "Java (GraalVM)".to_string(), file: "portscan.java".to_string(), tier: "managed".to_string(), reference_instructions: Some(1134302) },
+                BenchmarkImpl { language: "kotlin".to_string(), name: "Kotlin (GraalVM)".to_string(), file: "portscan.kt".to_string(), tier: "managed".to_string(), reference_instructions: Some(1219305) },
+                BenchmarkImpl { language: "scala".to_string(), name: "Scala (GraalVM)".to_string(), file: "portscan.scala".to_string(), tier: "managed".to_string(), reference_instructions: Some(922764) },
+                BenchmarkImpl { language: "clojure".to_string(), name: "Clojure (GraalVM)".to_string(), file: "portscan.clj".to_string(), tier: "managed".to_string(), reference_instructions: Some(1147912) },
+                BenchmarkImpl { language: "csharp".to_string(), name: "C# (.NET AOT)".to_string(), file: "portscan.cs".to_string(), tier: "managed".to_string(), reference_instructions: Some(3203528) },
+                BenchmarkImpl { language: "python".to_string(), name: "Python".to_string(), file: "portscan.py".to_string(), tier: "scripting".to_string(), reference_instructions: Some(376920746) },
+                BenchmarkImpl { language: "javascript".to_string(), name: "JavaScript (Bun)".to_string(), file: "portscan.js".to_string(), tier: "scripting".to_string(), reference_instructions: Some(17653512) },
+                BenchmarkImpl { language: "typescript".to_string(), name: "TypeScript (Bun)".to_string(), file: "portscan.ts".to_string(), tier: "scripting".to_string(), reference_instructions: Some(17653512) },
+                BenchmarkImpl { language: "node".to_string(), name: "JavaScript (Node)".to_string(), file: "portscan.js".to_string(), tier: "scripting".to_string(), reference_instructions: Some(176107839) },
+                BenchmarkImpl { language: "deno".to_string(), name: "TypeScript (Deno)".to_string(), file: "portscan_deno.ts".to_string(), tier: "scripting".to_string(), reference_instructions: Some(130547222) },
+                BenchmarkImpl { language: "lua".to_string(), name: "Lua".to_string(), file: "portscan.lua".to_string(), tier: "scripting".to_string(), reference_instructions: Some(410182) },
+            ],
+        },
+    ]
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    docker_available: bool,
+    // False on workers-only deployments (DIRECT_EXECUTION=false)
+    direct_execution: bool,
+    nats_connected: bool,
+    db_connected: bool,
+    queue_backend: Option<&'static str>,
+    queue_healthy: bool,
+    queue_depth: Option<u64>,
+    compile_queue_depth: Option<u64>,
+    // True while /submit or /compile would be rejected with 429
+    queue_saturated: bool,
+}
+
+#[derive(Serialize)]
+struct SubmitResponse {
+    job_id: Uuid,
+    status: &'static str,
+    position: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    job_id: Uuid,
+    status: String,
+    position: Option<u64>,
+    created_at: Option<String>,
+    started_at: Option<String>,
+    completed_at: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct QueueStatsResponse {
+    queue_length: u64,
+    active_jobs: u64,
+    workers_online: u64,
+}
+
+// ============ Compile Response Types ============
+
+#[derive(Serialize)]
+struct CompileSubmitResponse {
+    compile_job_id: Uuid,
+    status: &'static str,
+    position: Option<u64>,
+    /// The language as written and the runtime it's built for
+    language: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtime: Option<&'static str>,
+    /// Deprecated names used in the request
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CompileStatusResponse {
+    compile_job_id: Uuid,
+    status: String,
+    position: Option<u64>,
+    created_at: Option<String>,
+    started_at: Option<String>,
+    completed_at: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CompileResultResponse {
+    binary_id: String,
+    binary_size: usize,
+    compile_time_ms: u64,
+    cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_cache: Option<queue::BuildCacheUsage>,
+    /// Compiler warnings, for images that report them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<ctf_common::compile_protocol::Diagnostic>,
+    /// CPU time and peak memory, for images that report them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<ctf_common::compile_protocol::ResourceUsage>,
+}
+
+async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    // Workers-only APIs have no Docker to ask
+    let docker_available = state.config.direct_execution && sandbox::check_docker().await;
+    let queue_backend = state.queue.as_ref().map(|q| q.backend());
+    // Degraded while NATS is reconnecting (circuit breaker open)
+    let queue_healthy = state.queue.as_ref().is_some_and(|q| q.is_healthy());
+    let nats_connected = queue_backend == Some("nats") && queue_healthy;
+    let db_connected = if let Some(ref pool) = state.db {
+        sqlx::query("SELECT 1")
+            .execute(pool)
+            .await
+            .is_ok()
+    } else {
+        false
+    };
+
+    let (queue_depth, compile_queue_depth) = match state.queue.as_ref() {
+        Some(q) if queue_healthy => (q.get_queue_depth().await.ok(), q.get_compile_queue_depth().await.ok()),
+        _ => (None, None),
+    };
+    let queue_saturated = over_limit(queue_depth, state.config.max_queue_depth)
+        || over_limit(compile_queue_depth, state.config.max_compile_queue_depth);
+
+    let status = if queue_backend.is_some() && !queue_healthy {
+        "degraded"
+    } else if docker_available || nats_connected {
+        "ok"
+    } else {
+        "degraded"
+    };
+
+    Json(HealthResponse {
+        status,
+        docker_available,
+        direct_execution: state.config.direct_execution,
+        nats_connected,
+        db_connected,
+        queue_backend,
+        queue_healthy,
+        queue_depth,
+        compile_queue_depth,
+        queue_saturated,
+    })
+}
+
+fn over_limit(depth: Option<u64>, max: u64) -> bool {
+    max > 0 && depth.is_some_and(|d| d >= max)
+}
+
+/// Reject new work with 429 once a stream holds `max` pending jobs (0 = unlimited).
+/// A failed depth lookup lets the request through; the submit itself will surface it.
+fn check_backpressure(state: &AppState, queue: &'static str, depth: Result<u64, ApiError>, max: u64) -> Result<(), ApiError> {
+    match depth {
+        Ok(depth) if over_limit(Some(depth), max) => {
+            warn!(queue, depth, max, "Queue saturated, rejecting submission");
+            Err(ApiError::QueueFull {
+                queue,
+                depth,
+                max,
+                retry_after: state.config.queue_retry_after_seconds,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Per-job container memory, at most `MAX_MEMORY_LIMIT_MB`
+fn parse_memory_limit_mb(state: &AppState, text: &str) -> Result<u32, ApiError> {
+    let (min, max) = (sandbox::MIN_MEMORY_LIMIT_MB, state.config.max_memory_limit_mb);
+    match text.parse::<u32>() {
+        Ok(mb) if (min..=max).contains(&mb) => Ok(mb),
+        Ok(_) => Err(ApiError::InvalidField(format!("memory_limit_mb must be between {} and {}", min, max))),
+        Err(_) => Err(ApiError::InvalidField("memory_limit_mb must be a number".into())),
+    }
+}
+
+/// Per-job wall-clock limit, at most `TIMEOUT_SEC`
+fn parse_timeout_ms(state: &AppState, text: &str) -> Result<u64, ApiError> {
+    let max = state.config.timeout_sec * 1000;
+    match text.parse::<u64>() {
+        Ok(ms) if (1..=max).contains(&ms) => Ok(ms),
+        Ok(_) => Err(ApiError::InvalidField(format!("timeout_ms must be between 1 and {}", max))),
+        Err(_) => Err(ApiError::InvalidField("timeout_ms must be a number".into())),
+    }
+}
+
+async fn submit(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    headers: HeaderMap,
+    body: form::Body,
+) -> Result<Json<SubmitResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+    check_backpressure(&state, "execute", queue.get_queue_depth().await, state.config.max_queue_depth)?;
+    anonymous::admit(&state, user.as_ref(), &headers, anonymous::Usage::Submit).await?;
+    let instruction_limits = limits::InstructionLimits::for_user(&state.config, user.as_ref());
+
+    let mut form = form::Schema::new()
+        .bytes("binary")
+        .max_size(state.config.max_binary_size, |size, max| ApiError::BinaryTooLarge { size, max })
+        .content_types(form::BINARY_TYPES)
+        .base64()
+        .text("binary_id")
+        .text("instruction_limit")
+        .text("timeout_ms")
+        .text("memory_limit_mb")
+        .bytes("stdin")
+        .text("benchmark_id")
+        .text("env_vars")
+        .content_types(form::JSON_TYPES)
+        .text("args")
+        .content_types(form::JSON_TYPES)
+        .text("outputs")
+        .content_types(form::JSON_TYPES)
+        .text("core_dump")
+        .text("mode")
+        .text("visibility")
+        .read(body)
+        .await?;
+
+    let binary = form.bytes("binary");
+    let binary_id = form.text("binary_id");
+    let instruction_limit = form.number("instruction_limit")?.map(|l| instruction_limits.check(l)).transpose()?;
+    let timeout_ms = form.text("timeout_ms").map(|t| parse_timeout_ms(&state, &t)).transpose()?;
+    let memory_limit_mb = form.text("memory_limit_mb").map(|t| parse_memory_limit_mb(&state, &t)).transpose()?;
+    let stdin = form.bytes("stdin").unwrap_or_default();
+    let benchmark_id = form.text("benchmark_id");
+    let env_vars: std::collections::HashMap<String, String> = form.json("env_vars")?.unwrap_or_default();
+    limits::check_env_vars(&env_vars)?;
+    let args: Vec<String> = form.json("args")?.unwrap_or_default();
+    limits::check_args(&args)?;
+    let outputs: Vec<String> = form.json("outputs")?.unwrap_or_default();
+    ctf_common::outputs::validate(&outputs).map_err(|e| ApiError::InvalidField(e.to_string()))?;
+    let core_dump = form.flag("core_dump")?.unwrap_or(false);
+    let mode = match form.text("mode") {
+        Some(text) => ExecutionMode::parse(text.trim())
+            .ok_or_else(|| ApiError::InvalidField("mode must be normal or diagnostics".into()))?,
+        None => ExecutionMode::Normal,
+    };
+    let visibility = match form.text("visibility") {
+        Some(text) => RunVisibility::parse(text.trim())
+            .ok_or_else(|| ApiError::InvalidField("visibility must be public or private".into()))?,
+        None => RunVisibility::Public,
+    };
+
+    // Resolve binary_id (store binary in PostgreSQL if uploaded directly)
+    let binary_id_str = if let Some(bid) = binary_id {
+        // Verify the binary exists
+        if let Some(ref pool) = state.db {
+            if db::get_binary(pool, &bid).await?.is_none() {
+                return Err(ApiError::BinaryNotFound(bid));
+            }
+        }
+        bid
+    } else if let Some(bin) = binary {
+        // Store the binary and get its ID
+        let pool = state.db.as_ref()
+            .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+        let bid = format!("sha256-{}", hex::encode(sha2::Sha256::digest(&bin)));
+        binary_transfer::store(pool, &state.config, &bid, &bin, None, None).await?;
+        bid
+    } else {
+        return Err(ApiError::MissingField("binary or binary_id"));
+    };
+    let instruction_limit = instruction_limit.unwrap_or(instruction_limits.default);
+
+    // A private run without an owner would be visible to nobody
+    let user_id = user.map(|u| u.id);
+    if visibility == RunVisibility::Private && user_id.is_none() {
+        return Err(ApiError::Unauthorized("Log in to submit private runs".to_string()));
+    }
+    // Only the owner can download the core, so there has to be one
+    if core_dump && user_id.is_none() {
+        return Err(ApiError::Unauthorized("Log in to request a core dump".to_string()));
+    }
+    // Diagnostics runs count no instructions, so they stay off leaderboards
+    if mode == ExecutionMode::Diagnostics {
+        if benchmark_id.is_some() {
+            return Err(ApiError::InvalidField("benchmark_id can't be used with mode=diagnostics".into()));
+        }
+        if core_dump {
+            return Err(ApiError::InvalidField("core_dump can't be used with mode=diagnostics".into()));
+        }
+    }
+
+    // Create job with binary_id reference (not the full binary data)
+    let job = Job {
+        id: Uuid::new_v4(),
+        user_id,
+        binary_id: binary_id_str,
+        instruction_limit,
+        stdin,
+        created_at: Utc::now(),
+        benchmark_version: benchmark_id.as_deref().and_then(current_benchmark_version),
+        benchmark_id,
+        network_enabled: false,
+        env_vars,
+        args,
+        outputs,
+        core_dump,
+        mode,
+        fixtures: None,
+        sandbox_options: None,
+        sandbox_signature: None,
+        timeout_ms,
+        memory_limit_mb,
+        visibility,
+        challenge_id: None,
+    };
+
+    let job_id = job.id;
+
+    // Record submission in database
+    if let Some(ref pool) = state.db {
+        let _ = db::record_submission(pool, user_id.as_ref(), &job_id, None, &job.stdin, &job.env_vars, job.timeout_ms)
+            .await;
+        // Logged-in users get their result even if they come back after the job TTL
+        if let Some(user_id) = &user_id {
+            pins::pin_until_fetched(pool, &job_id, user_id).await;
+        }
+    }
+
+    // Submit to queue
+    queue.submit_job(job).await?;
+
+    // Get queue position
+    let position = queue.get_queue_depth().await.ok();
+
+    info!(job_id = %job_id, "Job submitted to queue");
+
+    Ok(Json(SubmitResponse {
+        job_id,
+        status: "queued",
+        position,
+    }))
+}
+
+/// Longest `/status/:job_id?wait=` holds a request
+const MAX_STATUS_WAIT_SECONDS: u64 = 60;
+
+#[derive(Deserialize)]
+struct StatusQuery {
+    /// Seconds to hold the request until the status changes (long-poll)
+    wait: Option<u64>,
+    /// The status the client last saw; defaults to the current one
+    status: Option<JobStatus>,
+}
+
+async fn status(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<Uuid>,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<StatusResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let mut metadata = match queue.get_job_status(&job_id).await? {
+        Some(metadata) => metadata,
+        None => pins::lookup(&state, &job_id)
+            .await?
+            .map(|(metadata, _)| metadata)
+            .ok_or_else(|| ApiError::JobNotFound(job_id.to_string()))?,
+    };
+
+    // Long-poll: hold on until the status moves off what the client has seen
+    let seen = query.status.unwrap_or(metadata.status);
+    let finished = matches!(metadata.status, JobStatus::Completed | JobStatus::Failed);
+    if let Some(wait) = query.wait.filter(|w| *w > 0 && !finished && metadata.status == seen) {
+        let wait = Duration::from_secs(wait.min(MAX_STATUS_WAIT_SECONDS));
+        if let Some(updated) = queue.watch_job_status(&job_id, seen, wait).await? {
+            metadata = updated;
+        }
+    }
+
+    // Get approximate position for pending jobs
+    let position = if metadata.status == JobStatus::Pending {
+        queue.get_queue_depth().await.ok()
+    } else {
+        None
+    };
+
+    Ok(Json(StatusResponse {
+        job_id,
+        status: format!("{:?}", metadata.status).to_lowercase(),
+        position,
+        created_at: Some(metadata.created_at.to_rfc3339()),
+        started_at: metadata.started_at.map(|t| t.to_rfc3339()),
+        completed_at: metadata.completed_at.map(|t| t.to_rfc3339()),
+        error: metadata.error,
+    }))
+}
+
+async fn result(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<sandbox::ExecutionResult>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    // Check job status first, then any pinned copy of a job the queue has forgotten
+    let (metadata, pinned_result) = match queue.get_job_status(&job_id).await? {
+        Some(metadata) => (metadata, None),
+        None => pins::lookup(&state, &job_id)
+            .await?
+            .ok_or_else(|| ApiError::JobNotFound(job_id.to_string()))?,
+    };
+
+    match metadata.status {
+        JobStatus::Completed => {
+            let result = match pinned_result {
+                Some(result) => result,
+                None => queue.get_job_result(&job_id).await?.ok_or(ApiError::JobNotReady)?,
+            };
+            pins::fetched(&state, &job_id).await;
+            Ok(Json(result))
+        }
+        JobStatus::Failed => Err(ApiError::Internal(
+            metadata.error.unwrap_or_else(|| "Job failed".to_string()),
+        )),
+        _ => Err(ApiError::JobNotReady),
+    }
+}
+
+/// One of the job's collected `/work/out` files
+async fn result_file(
+    State(state): State<Arc<AppState>>,
+    Path((job_id, name)): Path<(Uuid, String)>,
+) -> Result<axum::body::Bytes, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let result = match queue.get_job_result(&job_id).await? {
+        Some(result) => result,
+        None => pins::lookup(&state, &job_id)
+            .await?
+            .and_then(|(_, result)| result)
+            .ok_or_else(|| ApiError::JobNotFound(job_id.to_string()))?,
+    };
+    let binary_id = result
+        .output_files
+        .iter()
+        .find(|f| f.path == name)
+        .and_then(|f| f.binary_id.clone())
+        .ok_or_else(|| ApiError::NotFound(format!("Output file '{}' not collected", name)))?;
+    let data = db::get_binary(pool, &binary_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Output file '{}' no longer stored", name)))?;
+
+    Ok(axum::body::Bytes::from(data))
+}
+
+async fn queue_stats(State(state): State<Arc<AppState>>) -> Result<Json<QueueStatsResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let queue_length = queue.get_queue_depth().await.unwrap_or(0);
+
+    // TODO: track active jobs and workers in the queue client
+    Ok(Json(QueueStatsResponse {
+        queue_length,
+        active_jobs: 0,
+        workers_online: 0,
+    }))
+}
+
+// ============ Compile Endpoints ============
+
+async fn compile(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    headers: HeaderMap,
+    body: form::Body,
+) -> Result<Json<CompileSubmitResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+    check_backpressure(
+        &state,
+        "compile",
+        queue.get_compile_queue_depth().await,
+        state.config.max_compile_queue_depth,
+    )?;
+    anonymous::admit(&state, user.as_ref(), &headers, anonymous::Usage::Compile).await?;
+
+    let mut form = form::Schema::new()
+        .text("source_code")
+        .required()
+        .max_size(state.config.max_source_size, |size, max| ApiError::SourceTooLarge { size, max })
+        .text("language")
+        .required()
+        // bun, deno or node for javascript/typescript
+        .text("runtime")
+        .text("optimization")
+        // JSON object: {"nostd": "true", "lto": "thin"}
+        .text("flags")
+        .content_types(form::JSON_TYPES)
+        // Also individual flag fields: flag_nostd=true, flag_lto=thin
+        .prefix("flag_")
+        // Pin a build to an allowlisted image digest
+        .text("compiler_image")
+        .text("emit_asm")
+        // Rebuild instead of returning a recent identical failure
+        .text("retry_failed")
+        // {"linker_flags": ["--entry=_start", "-N"]}; a script here is overridden by linker_script
+        .text("build_options")
+        .content_types(form::JSON_TYPES)
+        .text("linker_script")
+        // Resolved against the language once all fields are in
+        .text("toolchain")
+        .read(body)
+        .await?;
+
+    let source_code = form.required_text("source_code")?;
+    let language_name = form.required_text("language")?;
+    let runtime = form.text("runtime");
+    let optimization = form
+        .text("optimization")
+        .and_then(|text| Optimization::from_str(&text))
+        .unwrap_or(Optimization::Release);
+    let mut flags: std::collections::HashMap<String, String> = form.json("flags")?.unwrap_or_default();
+    flags.extend(form.prefixed("flag_"));
+    let compiler_image = match form.text("compiler_image") {
+        Some(text) => Some(
+            state
+                .config
+                .allowed_compiler_image(text.trim())
+                .ok_or_else(|| {
+                    ApiError::InvalidField(format!("compiler_image '{}' is not in COMPILER_IMAGE_ALLOWLIST", text.trim()))
+                })?
+                .to_string(),
+        ),
+        None => None,
+    };
+    let emit_asm = form.flag("emit_asm")?.unwrap_or(false);
+    let retry_failed = form.flag("retry_failed")?.unwrap_or(false);
+    let mut build_options: BuildOptions = form.json("build_options")?.unwrap_or_default();
+    if let Some(script) = form.text("linker_script") {
+        build_options.linker_script = Some(script);
+    }
+    let toolchain = form.text("toolchain");
+
+    let language = resolve_language(&language_name, runtime.as_deref())?;
+    let warnings: Vec<String> = Language::deprecation(&language_name).into_iter().collect();
+    limits::check_source(&state.config, language, &source_code)?;
+    limits::check_flags(&state.config, &flags)?;
+    build_options.validate(language)?;
+    let toolchain = match toolchain {
+        Some(requested) => toolchains::resolve(language, &requested)?,
+        None => None,
+    };
+
+    // Create compile job
+    let job = CompileJob {
+        id: Uuid::new_v4(),
+        user_id: user.map(|u| u.id),
+        source_code,
+        language,
+        optimization,
+        flags,
+        compiler_image,
+        emit_asm,
+        build_options,
+        toolchain,
+        retry_failed,
+        created_at: Utc::now(),
+    };
+    let job_id = job.id;
+    let position = enqueue_compile(&state, queue, job).await?;
+
+    info!(
+        compile_job_id = %job_id,
+        language = ?language,
+        optimization = ?optimization,
+        "Compile job submitted"
+    );
+
+    Ok(Json(CompileSubmitResponse {
+        compile_job_id: job_id,
+        status: "queued",
+        position,
+        language: language.canonical(),
+        runtime: language.runtime(),
+        warnings,
+    }))
+}
+
+/// Hold logged-in users to their organization's policy and compile quota,
+/// queue `job` and record its usage; the compile queue depth after submitting
+async fn enqueue_compile(state: &AppState, queue: &QueueClient, job: CompileJob) -> Result<Option<u64>, ApiError> {
+    if let Some(pool) = &state.db {
+        orgs::enforce(pool, &job).await?;
+    }
+
+    // Logged-in users are held to a daily compile-minute quota
+    if let (Some(user_id), Some(pool)) = (&job.user_id, &state.db) {
+        limits::check_compile_quota(&state.config, pool, queue, user_id).await?;
+    }
+
+    // Check compile cache first
+    if let Ok(Some(cached_result)) = queue.check_compile_cache(&job).await {
+        info!(
+            binary_id = %cached_result.binary_id,
+            "Compile cache hit"
+        );
+        // For cache hits, we could return immediately but the client expects a job_id
+        // So we still create a job but it will complete instantly via cache
+    }
+
+    let job_id = job.id;
+    let user_id = job.user_id;
+    let language = job.language;
+
+    // Submit to queue
+    queue.submit_compile_job(job).await?;
+
+    if let (Some(user_id), Some(pool)) = (&user_id, &state.db) {
+        let timeout = Duration::from_secs(state.config.compile_timeout_sec);
+        if let Err(e) = limits::reserve_compile(pool, user_id, &job_id, language, timeout).await {
+            warn!(compile_job_id = %job_id, error = %e, "Failed to record compile usage");
+        }
+    }
+
+    // Get queue position
+    Ok(queue.get_compile_queue_depth().await.ok())
+}
+
+/// The language to compile with for a `language` field plus an optional `runtime`
+fn resolve_language(name: &str, runtime: Option<&str>) -> Result<Language, ApiError> {
+    let language = Language::from_str(name).ok_or_else(|| ApiError::InvalidLanguage(name.to_string()))?;
+    Language::resolve(name, runtime).ok_or_else(|| {
+        ApiError::InvalidField(format!(
+            "runtime '{}' isn't available for {}",
+            runtime.unwrap_or_default().trim(),
+            language.canonical()
+        ))
+    })
+}
+
+async fn compile_status(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<CompileStatusResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let metadata = queue
+        .get_compile_status(&job_id)
+        .await?
+        .ok_or_else(|| ApiError::CompileJobNotFound(job_id.to_string()))?;
+
+    // Get approximate position for pending jobs
+    let position = if metadata.status == CompileStatus::Pending {
+        queue.get_compile_queue_depth().await.ok()
+    } else {
+        None
+    };
+
+    Ok(Json(CompileStatusResponse {
+        compile_job_id: job_id,
+        status: format!("{:?}", metadata.status).to_lowercase(),
+        position,
+        created_at: Some(metadata.created_at.to_rfc3339()),
+        started_at: metadata.started_at.map(|t| t.to_rfc3339()),
+        completed_at: metadata.completed_at.map(|t| t.to_rfc3339()),
+        error: metadata.error,
+    }))
+}
+
+async fn compile_result(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<CompileResultResponse>, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    // Check job status first
+    let metadata = queue
+        .get_compile_status(&job_id)
+        .await?
+        .ok_or_else(|| ApiError::CompileJobNotFound(job_id.to_string()))?;
+
+    match metadata.status {
+        CompileStatus::Completed => {
+            let result = queue
+                .get_compile_result(&job_id)
+                .await?
+                .ok_or(ApiError::CompileJobNotReady)?;
+            if let Some(ref pool) = state.db {
+                let _ = limits::settle_compile(pool, &job_id, &result).await;
+            }
+            Ok(Json(CompileResultResponse {
+                binary_id: result.binary_id,
+                binary_size: result.binary_size,
+                compile_time_ms: result.compile_time_ms,
+                cached: result.cached,
+                build_cache: result.build_cache,
+                diagnostics: result.diagnostics,
+                resources: result.resources,
+            }))
+        }
+        CompileStatus::Failed => {
+            if let Some(ref pool) = state.db {
+                let _ = limits::settle_failed_compile(pool, &job_id, &metadata).await;
+            }
+            Err(ApiError::CompileError(
+                metadata.error.unwrap_or_else(|| "Compilation failed".to_string()),
+            ))
+        }
+        _ => Err(ApiError::CompileJobNotReady),
+    }
+}
+
+/// Assembly from a job compiled with `emit_asm`
+async fn compile_asm(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<Uuid>,
+) -> Result<String, ApiError> {
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let metadata = queue
+        .get_compile_status(&job_id)
+        .await?
+        .ok_or_else(|| ApiError::CompileJobNotFound(job_id.to_string()))?;
+
+    match metadata.status {
+        CompileStatus::Completed => queue.get_compile_asm(&job_id).await?.ok_or_else(|| {
+            ApiError::NotFound(format!("No assembly for compile job '{}' (compile with emit_asm=true)", job_id))
+        }),
+        CompileStatus::Failed => Err(ApiError::CompileError(
+            metadata.error.unwrap_or_else(|| "Compilation failed".to_string()),
+        )),
+        _ => Err(ApiError::CompileJobNotReady),
+    }
+}
+
+// ============ Benchmark Endpoints ============
+
+async fn list_benchmarks() -> Json<Vec<BenchmarkDef>> {
+    Json(get_benchmarks_config())
+}
+
+async fn get_benchmark(
+    Path(id): Path<String>,
+    Query(query): Query<BenchmarkQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let benchmarks = get_benchmarks_config();
+    let benchmark = benchmarks
+        .into_iter()
+        .find(|b| b.id == id)
+        .ok_or_else(|| ApiError::NotFound(format!("Benchmark '{}' not found", id)))?;
+
+    if query.include_source {
+        let tests_dir = std::path::Path::new("/app/tests");
+        let fallback_dir = std::path::Path::new("../sandbox/tests");
+
+        let base_dir = if tests_dir.exists() { tests_dir } else { fallback_dir };
+
+        let implementations: Vec<BenchmarkImplWithSource> = benchmark
+            .implementations
+            .into_iter()
+            .filter_map(|impl_| {
+                let file_path = base_dir.join(&impl_.file);
+                let source_code = std::fs::read_to_string(&file_path).ok()?;
+                Some(BenchmarkImplWithSource {
+                    language: impl_.language,
+                    name: impl_.name,
+                    file: impl_.file,
+                    tier: impl_.tier,
+                    reference_instructions: impl_.reference_instructions,
+                    source_code,
+                })
+            })
+            .collect();
+
+        let result = BenchmarkWithSource {
+            id: benchmark.id,
+            version: benchmark.version,
+            name: benchmark.name,
+            description: benchmark.description,
+            implementations,
+            env_vars: benchmark.env_vars,
+            stdin: benchmark.stdin,
+        };
+        Ok(Json(serde_json::to_value(result).unwrap()))
+    } else {
+        Ok(Json(serde_json::to_value(benchmark).unwrap()))
+    }
+}
+
+async fn get_benchmark_source(
+    Path((id, file)): Path<(String, String)>,
+) -> Result<String, ApiError> {
+    let benchmarks = get_benchmarks_config();
+    let benchmark = benchmarks
+        .iter()
+        .find(|b| b.id == id)
+        .ok_or_else(|| ApiError::NotFound(format!("Benchmark '{}' not found", id)))?;
+
+    // Verify the file belongs to this benchmark
+    if !benchmark.implementations.iter().any(|i| i.file == file) {
+        return Err(ApiError::NotFound(format!("File '{}' not in benchmark '{}'", file, id)));
+    }
+
+    let tests_dir = std::path::Path::new("/app/tests");
+    let fallback_dir = std::path::Path::new("../sandbox/tests");
+    let base_dir = if tests_dir.exists() { tests_dir } else { fallback_dir };
+
+    let file_path = base_dir.join(&file);
+    std::fs::read_to_string(&file_path)
+        .map_err(|_| ApiError::NotFound(format!("File '{}' not found", file)))
+}
+
+// ============ Benchmark Stats Endpoint ============
+
+#[derive(Serialize)]
+struct BenchmarkStatsResponse {
+    /// The benchmark version these minimums are for
+    version: u32,
+    min_instructions: std::collections::HashMap<String, i64>,
+}
+
+#[derive(Deserialize)]
+struct BenchmarkStatsQuery {
+    /// Defaults to the benchmark's current version
+    version: Option<u32>,
+}
+
+async fn get_benchmark_stats(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<BenchmarkStatsQuery>,
+) -> Result<Json<BenchmarkStatsResponse>, ApiError> {
+    // Verify benchmark exists
+    let current_version = current_benchmark_version(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("Benchmark '{}' not found", id)))?;
+    let version = query.version.unwrap_or(current_version);
+    if version == 0 || version > current_version {
+        return Err(ApiError::InvalidField(format!(
+            "version must be between 1 and {} for benchmark '{}'",
+            current_version, id
+        )));
+    }
+
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let min_instructions = db::get_min_instructions(pool, &id, version).await?;
+
+    Ok(Json(BenchmarkStatsResponse { version, min_instructions }))
+}
+
+// ============ Runs Endpoints ============
+
+#[derive(Serialize)]
+struct SaveRunResponse {
+    id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct ListRunsQuery {
+    #[serde(default = "default_limit")]
+    limit: i64,
+    #[serde(default)]
+    offset: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+async fn save_run(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SaveRunRequest>,
+) -> Result<Json<SaveRunResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let id = db::save_run(pool, &req).await?;
+
+    info!(run_id = %id, job_id = %req.job_id, "Run saved");
+
+    Ok(Json(SaveRunResponse { id }))
+}
+
+/// Whether `user` may see `run`; private runs read as missing to everyone else
+pub(crate) fn can_view_run(state: &AppState, run: &Run, user: Option<&db::User>) -> bool {
+    let is_admin = user.is_some_and(|u| state.config.admin_users.contains(&u.username));
+    run.visible_to(user.map(|u| &u.id), is_admin)
+}
+
+async fn get_run(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<Run>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = db::get_run(pool, &run_id)
+        .await?
+        .filter(|run| can_view_run(&state, run, user.as_ref()))
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))?;
+
+    Ok(Json(run))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateRunRequest {
+    visibility: RunVisibility,
+}
+
+/// Owners (and admins) can make a run public or private
+async fn update_run(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(run_id): Path<Uuid>,
+    Json(req): Json<UpdateRunRequest>,
+) -> Result<Json<Run>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let mut run = db::get_run(pool, &run_id)
+        .await?
+        .filter(|run| can_view_run(&state, run, Some(&user)))
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))?;
+
+    let is_admin = state.config.admin_users.contains(&user.username);
+    if run.user_id != Some(user.id) && !is_admin {
+        return Err(ApiError::Forbidden("You don't own this run".to_string()));
+    }
+
+    db::set_run_visibility(pool, &run_id, req.visibility).await?;
+    run.visibility = req.visibility.as_str().to_string();
+
+    info!(run_id = %run_id, user_id = %user.id, visibility = %run.visibility, "Run visibility changed");
+
+    Ok(Json(run))
+}
+
+#[derive(Debug, Deserialize)]
+struct PurgeRunsQuery {
+    /// Only runs created before this
+    before: Option<DateTime<Utc>>,
+    benchmark: Option<String>,
+    /// Count what would be affected without changing anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct PurgeRunsResponse {
+    deleted: u64,
+    /// Runs a leaderboard entry points at are kept, detached from you and
+    /// without their output
+    anonymized: u64,
+    dry_run: bool,
+}
+
+async fn purge_my_runs(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<PurgeRunsQuery>,
+) -> Result<Json<PurgeRunsResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let benchmark = query.benchmark.as_deref();
+    let (deleted, anonymized) = if query.dry_run {
+        let (deleted, anonymized) = db::count_purgeable_runs(pool, &user.id, query.before, benchmark).await?;
+        (deleted as u64, anonymized as u64)
+    } else {
+        let purged = db::purge_user_runs(pool, &user.id, query.before, benchmark).await?;
+        info!(
+            user_id = %user.id,
+            before = ?query.before,
+            benchmark = ?benchmark,
+            deleted = purged.0,
+            anonymized = purged.1,
+            "Runs purged"
+        );
+        purged
+    };
+
+    Ok(Json(PurgeRunsResponse {
+        deleted,
+        anonymized,
+        dry_run: query.dry_run,
+    }))
+}
+
+async fn get_run_by_job(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<Run>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = db::get_run_by_job_id(pool, &job_id)
+        .await?
+        .filter(|run| can_view_run(&state, run, user.as_ref()))
+        .ok_or_else(|| ApiError::NotFound(format!("Run for job '{}' not found", job_id)))?;
+
+    Ok(Json(run))
+}
+
+async fn list_runs(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    Query(query): Query<ListRunsQuery>,
+) -> Result<Json<Vec<Run>>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let limit = query.limit.clamp(1, 100); // Cap at 100, minimum 1
+    let is_admin = user.as_ref().is_some_and(|u| state.config.admin_users.contains(&u.username));
+    let runs = db::list_runs(pool, limit, query.offset, user.as_ref().map(|u| &u.id), is_admin).await?;
+
+    Ok(Json(runs))
+}
+
+// Binary storage endpoints (for compile-worker and execute-worker)
+
+#[derive(Serialize)]
+struct StoreBinaryResponse {
+    success: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoreBinaryQuery {
+    language: Option<String>,
+    optimization: Option<String>,
+    compiler_version: Option<String>,
+    compile_flags: Option<String>, // JSON string
+    compiler_image: Option<String>,
+    toolchain: Option<String>,
+    /// Compile cache key of the build that produced it
+    build_key: Option<String>,
+    /// JSON `RuntimeManifest`, for script bundles
+    runtime: Option<String>,
+}
+
+async fn store_binary(
+    State(state): State<Arc<AppState>>,
+    Path(binary_id): Path<String>,
+    Query(query): Query<StoreBinaryQuery>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<StoreBinaryResponse>, ApiError> {
+    let upload = binary_transfer::decode_upload(&state.config, &state.binary_transfer, &headers, &body)?;
+    info!(
+        binary_id = %binary_id,
+        body_size = body.len(),
+        binary_size = upload.data.len(),
+        language = ?query.language,
+        optimization = ?query.optimization,
+        "Storing binary"
+    );
+
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let compile_flags = query.compile_flags
+        .as_ref()
+        .and_then(|s| serde_json::from_str(s).ok());
+
+    let runtime = query
+        .runtime
+        .as_deref()
+        .map(RuntimeManifest::parse)
+        .transpose()
+        .map_err(ApiError::InvalidField)?;
+
+    let metadata = BinaryMetadata {
+        language: query.language,
+        optimization: query.optimization,
+        compiler_version: query.compiler_version,
+        compile_flags,
+        compiler_image: query.compiler_image,
+        toolchain: query.toolchain,
+        runtime,
+    };
+
+    let stored = binary_transfer::store(
+        pool,
+        &state.config,
+        &binary_id,
+        &upload.data,
+        upload.compressed.as_deref(),
+        Some(&metadata),
+    )
+    .await;
+    if let Err(e) = stored {
+        tracing::error!(binary_id = %binary_id, body_size = body.len(), error = %e, "Failed to store binary");
+        return Err(e);
+    }
+    if let Some(build_key) = &query.build_key {
+        db::record_binary_build(pool, &binary_id, build_key).await?;
+    }
+
+    info!(binary_id = %binary_id, "Binary stored successfully");
+    Ok(Json(StoreBinaryResponse { success: true }))
+}
+
+/// The binary, zstd-compressed for clients that send `Accept-Encoding: zstd`
+pub(crate) async fn get_binary(
+    State(state): State<Arc<AppState>>,
+    Path(binary_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let stored = db::get_stored_binary(pool, &binary_id)
+        .await?
+        .ok_or_else(|| ApiError::BinaryNotFound(binary_id))?;
+    let (body, compressed) = binary_transfer::encode_download(&state.config, &state.binary_transfer, &headers, stored)?;
+
+    let mut response = axum::body::Bytes::from(body).into_response();
+    response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    if compressed {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static(binary_encoding::ZSTD));
+    }
+    Ok(response)
+}
+
+pub(crate) async fn get_binary_metadata(
+    State(state): State<Arc<AppState>>,
+    Path(binary_id): Path<String>,
+) -> Result<Json<BinaryMetadata>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let metadata = db::get_binary_metadata(pool, &binary_id)
+        .await?
+        .ok_or_else(|| ApiError::BinaryNotFound(binary_id))?;
+
+    Ok(Json(metadata))
+}
+
+async fn store_size_report(
+    State(state): State<Arc<AppState>>,
+    Path(binary_id): Path<String>,
+    Json(report): Json<serde_json::Value>,
+) -> Result<Json<StoreBinaryResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    if !report.is_object() {
+        return Err(ApiError::InvalidField("size report must be a JSON object".to_string()));
+    }
+    if !db::store_size_report(pool, &binary_id, &report).await? {
+        return Err(ApiError::BinaryNotFound(binary_id));
+    }
+
+    Ok(Json(StoreBinaryResponse { success: true }))
+}
+
+/// Section and largest-symbol sizes, for binaries binutils could read
+async fn get_size_report(
+    State(state): State<Arc<AppState>>,
+    Path(binary_id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let report = db::get_size_report(pool, &binary_id)
+        .await?
+        .ok_or_else(|| ApiError::BinaryNotFound(binary_id.clone()))?
+        .ok_or_else(|| ApiError::NotFound(format!("No size report for binary '{}'", binary_id)))?;
+
+    Ok(Json(report))
+}
+
+/// Connect to PostgreSQL and the queue backend, returning the shared state for the router.
+pub async fn init_state(config: Config) -> Arc<AppState> {
+    #[cfg(feature = "fake-sandbox")]
+    if config.sandbox_fake {
+        warn!("SANDBOX_FAKE=1: executions and compiles return synthetic results, do not use in production");
+    }
+    #[cfg(not(feature = "fake-sandbox"))]
+    if std::env::var("SANDBOX_FAKE").is_ok() {
+        warn!("SANDBOX_FAKE is ignored: built without the fake-sandbox feature");
+    }
+
+    // Email notifications (optional - requires SMTP_URL)
+    let mailer = notifications::Mailer::from_env();
+    if mailer.is_some() {
+        info!("SMTP configured for email notifications");
+    }
+
+    // Challenge env vars (flags) are encrypted at rest when a key is set
+    let env_cipher = secrets::EnvVarCipher::from_config(&config).unwrap_or_else(|e| panic!("{}", e));
+    if !env_cipher.is_enabled() {
+        warn!("CHALLENGE_ENV_KEY not set: challenge env vars (flags) are stored in plaintext");
+    }
+    let challenge_signer = provenance::ChallengeSigner::from_config(&config);
+    if !challenge_signer.is_enabled() {
+        warn!("CHALLENGE_SIGNING_KEY not set: challenge content is not signed");
+    }
+    let github_app = repos::GitHubApp::from_config(&config).unwrap_or_else(|e| panic!("{}", e));
+    if github_app.is_some() {
+        info!("GitHub App configured: linked repos run on push");
+    }
+
+    // Try to connect to PostgreSQL (optional)
+    let db = match db::create_pool(&config.database_url).await {
+        Ok(pool) => {
+            if let Err(e) = db::run_migrations(&pool).await {
+                warn!("Failed to run migrations: {}", e);
+            } else {
+                info!("Connected to PostgreSQL and ran migrations");
+            }
+            // Try to seed challenges
+            if let Err(e) = challenges::seed_challenges(&pool, mailer.as_ref(), &env_cipher, &challenge_signer).await {
+                warn!("Failed to seed challenges: {}", e);
+            }
+            match secrets::seal_stored(&pool, &env_cipher).await {
+                Ok(0) => {}
+                Ok(n) => info!(challenges = n, "Encrypted plaintext challenge env vars"),
+                Err(e) => warn!("Failed to encrypt challenge env vars: {}", e),
+            }
+            Some(pool)
+        }
+        Err(e) => {
+            warn!("Failed to connect to PostgreSQL: {}. Submissions won't be persisted.", e);
+            None
+        }
+    };
+
+    // The in-process queue runs jobs through Docker like /execute does
+    let queue_mode = if !config.direct_execution && config.queue_mode != "nats" {
+        info!("DIRECT_EXECUTION=false: using NATS workers only (QUEUE_MODE={} ignored)", config.queue_mode);
+        "nats"
+    } else {
+        config.queue_mode.as_str()
+    };
+
+    // Try to connect to NATS, falling back to the in-process queue (QUEUE_MODE=auto)
+    let nats_queue = if queue_mode == "local" {
+        None
+    } else {
+        match QueueClient::connect(&config, db.clone()).await {
+            Ok(q) => {
+                info!("Connected to NATS at {}", config.nats_url);
+                Some(q)
+            }
+            Err(e) => {
+                warn!("Failed to connect to NATS: {}", e);
+                None
+            }
+        }
+    };
+
+    let queue = match (nats_queue, &db) {
+        (Some(q), _) => Some(q),
+        (None, Some(pool)) if queue_mode != "nats" => {
+            info!("Using in-process queue with local workers");
+            Some(QueueClient::local(&config, pool.clone()))
+        }
+        (None, _) if config.direct_execution => {
+            warn!("No queue available. Running in direct mode (/execute only).");
+            None
+        }
+        (None, _) => {
+            warn!("No queue available and DIRECT_EXECUTION=false: job endpoints fail until restarted with NATS reachable");
+            None
+        }
+    };
+
+    // Backfill runs that executed on NATS workers but never reached Postgres
+    if let (Some(QueueClient::Nats(q)), Some(pool)) = (&queue, &db) {
+        if config.reconcile_interval_seconds > 0 {
+            reconciler::spawn(q.clone(), pool.clone(), Duration::from_secs(config.reconcile_interval_seconds));
+        }
+    }
+
+    // Notify on leaderboard events published by any replica
+    if let (Some(QueueClient::Nats(q)), Some(pool)) = (&queue, &db) {
+        events::spawn(q.clone(), pool.clone(), mailer.clone());
+    }
+
+    // Precompute the aggregates behind /public/stats*
+    if let Some(pool) = &db {
+        if config.stats_refresh_seconds > 0 {
+            stats::spawn(pool.clone(), Duration::from_secs(config.stats_refresh_seconds));
+        }
+    }
+
+    // Enforce the data-retention policy
+    if let Some(pool) = &db {
+        if config.janitor_interval_seconds > 0 {
+            janitor::spawn(pool.clone(), janitor::RetentionPolicy::from_config(&config));
+        }
+    }
+
+    // Summarise each finished day into a digest
+    if let Some(pool) = &db {
+        digest::spawn(pool.clone(), config.discord_webhook_url.clone());
+    }
+
+    // Initialize auth config (optional - requires GitHub OAuth credentials)
+    let auth_config = auth::AuthConfig::from_env();
+    if auth_config.is_some() {
+        info!("GitHub OAuth configured");
+    } else {
+        warn!("GitHub OAuth not configured. Set GITHUB_CLIENT_ID and GITHUB_CLIENT_SECRET to enable.");
+    }
+
+    let state = Arc::new(AppState {
+        semaphore: Semaphore::new(config.max_concurrent),
+        public_stats_limiter: stats::IpRateLimiter::new(config.public_stats_rate_limit_per_minute),
+        verification_limiter: stats::IpRateLimiter::new(config.verification_rate_limit_per_minute),
+        anonymous_token_limiter: stats::IpRateLimiter::new(config.anonymous_token_rate_limit_per_minute),
+        gist_import_limiter: stats::IpRateLimiter::new(config.gist_import_rate_limit_per_minute),
+        api_calls: usage::ApiCallCounter::default(),
+        binary_transfer: binary_transfer::TransferStats::default(),
+        config,
+        queue,
+        db,
+        auth_config,
+        mailer,
+        env_cipher,
+        challenge_signer,
+        github_app,
+    });
+
+    // Copy pinned jobs out of the queue as they finish
+    if state.db.is_some() && state.queue.is_some() {
+        pins::spawn(state.clone());
+    }
+
+    // Write per-user API call counts to usage_daily
+    if state.db.is_some() {
+        usage::spawn(state.clone());
+    }
+
+    // Re-run tracked solutions against current toolchains
+    if state.db.is_some() && state.queue.is_some() && state.config.tracked_rerun_days > 0 {
+        tracked::spawn(state.clone(), state.config.tracked_rerun_days);
+    }
+
+    state
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    let config = &state.config;
+    let deny_framing = HeaderPolicy::new(config, true);
+
+    // Frontend routes: credentialed CORS for CORS_APP_ORIGINS
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/version", get(version::version))
+        .route("/submit", post(submit))
+        .route("/tokens/anonymous", post(anonymous::issue_token))
+        .route("/status/:job_id", get(status))
+        .route("/result/:job_id", get(result))
+        .route("/result/:job_id/files/:name", get(result_file))
+        .route("/jobs/:job_id/pin", post(pins::pin_job))
+        .route("/queue/stats", get(queue_stats))
+        // Compile endpoints
+        .route("/compile", post(compile))
+        .route("/languages", get(toolchains::list_languages))
+        .route("/compile/status/:job_id", get(compile_status))
+        .route("/compile/result/:job_id", get(compile_result))
+        .route("/compile/:job_id/asm", get(compile_asm))
+        .route("/import/gist", post(gist::import_gist))
+        .route("/binaries", get(binaries::list_binaries))
+        .route("/binaries/:binary_id/metadata", get(get_binary_metadata))
+        .route("/binaries/:binary_id/size-report", get(get_size_report))
+        // Benchmark endpoints
+        .route("/benchmarks", get(list_benchmarks))
+        .route("/benchmarks/:id", get(get_benchmark))
+        .route("/benchmarks/:id/source/:file", get(get_benchmark_source))
+        .route("/benchmarks/:id/stats", get(get_benchmark_stats))
+        // Runs endpoints (permanent storage)
+        .route("/runs", get(list_runs))
+        .route("/runs/:id", get(get_run).patch(update_run))
+        .route("/runs/:id/repro.tar.gz", get(repro::get_repro))
+        .route("/runs/:id/core", get(core_dumps::get_core_dump))
+        .route("/runs/:id/comments", get(comments::list_comments).post(comments::post_comment))
+        .route(
+            "/runs/:id/comments/:comment_id",
+            patch(comments::update_comment).delete(comments::delete_comment),
+        )
+        .route("/runs/:id/star", put(stars::star_run).delete(stars::unstar_run))
+        .route("/runs/:id/share", post(share::create_share_link))
+        .route("/runs/job/:job_id", get(get_run_by_job))
+        // Auth endpoints
+        .route("/auth/github", get(auth::github_login))
+        .route("/auth/github/callback", get(auth::github_callback))
+        .route("/auth/me", get(auth::auth_me).patch(auth::update_profile))
+        .route("/auth/me/analytics", get(usage::get_analytics))
+        .route("/auth/logout", post(auth::logout))
+        // Clanker verification endpoints
+        .route("/verification/clanker", post(auth::init_clanker_verification))
+        .route("/verification/clanker/check", post(auth::check_clanker_verification))
+        // User profile endpoint
+        .route("/users/:username", get(auth::get_user_profile))
+        // Challenge endpoints
+        .route("/challenges", get(challenges::list_challenges))
+        .route("/challenges/:id", get(challenges::get_challenge))
+        .route("/challenges/:id/submit", post(challenges::submit_challenge))
+        .route("/challenges/:id/submission/:submission_id", get(challenges::get_submission_status))
+        .route("/challenges/:id/submissions/:submission_id/retry", post(challenges::retry_submission))
+        .route("/challenges/:id/leaderboard", get(challenges::get_challenge_leaderboard))
+        .route("/challenges/:id/leaderboard/languages", get(challenges::get_language_leaderboard))
+        .route("/challenges/:id/queue", get(challenges::get_challenge_queue))
+        .route("/challenges/:id/hints/:n/unlock", post(challenges::unlock_hint))
+        .route("/challenges/:id/writeups", get(writeups::list_writeups).post(writeups::submit_writeup))
+        .route("/challenges/:id/star", put(stars::star_challenge).delete(stars::unstar_challenge))
+        // Bookmarks
+        .route("/my/stars", get(stars::my_stars))
+        // Purge your own runs (leaderboard runs are anonymized instead)
+        .route("/my/runs", delete(purge_my_runs))
+        // Tracked solutions, re-run every TRACKED_RERUN_DAYS
+        .route("/my/tracked", get(tracked::list_tracked))
+        .route(
+            "/my/tracked/:benchmark_id",
+            get(tracked::get_tracked).put(tracked::track).delete(tracked::untrack),
+        )
+        // GitHub repo files run on every push, results posted as commit statuses
+        .route("/my/repos", get(repos::list_links).post(repos::link))
+        .route("/my/repos/:id", delete(repos::unlink))
+        .route("/integrations/github/webhook", post(repos::webhook))
+        // Organizations and their compile policies
+        .route("/orgs/:slug", get(orgs::get_org))
+        .route("/orgs/:slug/policy", put(orgs::set_policy))
+        .route(
+            "/orgs/:slug/members/:username",
+            put(orgs::set_member).delete(orgs::remove_member),
+        )
+        // Moderation
+        .route("/admin/overview", get(admin::overview))
+        .route("/admin/retention", get(janitor::retention))
+        .route("/admin/challenges", get(provenance::list_challenge_provenance))
+        .route("/admin/challenges/:id/window", put(challenges::set_challenge_window))
+        .route("/admin/workers", get(workers::list_workers).post(workers::create_worker))
+        .route("/admin/workers/:worker_id", delete(workers::revoke_worker))
+        .route("/admin/orgs", post(orgs::create_org))
+        .route("/admin/writeups/:writeup_id", patch(writeups::moderate_writeup))
+        .route("/admin/run-comments/:comment_id", patch(comments::moderate_comment))
+        // Notifications
+        .route("/notifications", get(notifications::list_notifications))
+        .route("/notifications/read-all", post(notifications::mark_all_read))
+        .route("/notifications/:id/read", post(notifications::mark_read))
+        .route(
+            "/notifications/preferences",
+            get(notifications::get_preferences).put(notifications::set_preferences),
+        )
+        // Global leaderboard
+        .route("/leaderboard", get(challenges::get_global_leaderboard))
+        .route("/leaderboard/countries", get(challenges::get_country_leaderboard));
+    // Deprecated synchronous execution
+    #[cfg(feature = "legacy-execute")]
+    let app = app.merge(legacy::router());
+    let app = app.layer(security::app_cors(config));
+
+    // Anonymous GET routes for third-party sites: CORS_PUBLIC_ORIGINS, no cookies
+    let public = Router::new()
+        // README badges
+        .route("/badge/benchmarks/:benchmark_id/:file", get(badge::benchmark_badge))
+        .route("/badge/:challenge_id/:file", get(badge::challenge_badge))
+        // Daily digest
+        .route("/digest/:date", get(digest::get_digest))
+        // Public stats for third-party dashboards
+        .merge(stats::router())
+        // Measurement environment for local reproduction
+        .merge(manifest::router())
+        // Error codes, statuses and retry hints for client authors
+        .route("/errors", get(error::list_errors))
+        // Run share pages for link previews
+        .merge(share::router())
+        .layer(security::public_cors(config));
+
+    // Worker-facing routes: no CORS, browsers never call these cross-origin
+    let internal = Router::new()
+        .route("/binaries/:binary_id", put(store_binary).get(get_binary))
+        .route("/binaries/:binary_id/size-report", put(store_size_report))
+        .route("/runs", post(save_run))
+        // External workers, token-authenticated
+        .merge(workers::router());
+
+    // Leaderboard widgets for iframes, so no frame-deny
+    let embeds = embed::router()
+        .layer(security::public_cors(config))
+        .layer(middleware::from_fn_with_state(
+            HeaderPolicy::new(config, false),
+            security::security_headers,
+        ));
+
+    Router::new()
+        .merge(app)
+        .merge(public)
+        .merge(internal)
+        .layer(middleware::from_fn_with_state(deny_framing, security::security_headers))
+        .merge(embeds)
+        .layer(DefaultBodyLimit::max(state.config.max_binary_size + 1024 * 1024))
+        .layer(middleware::from_fn(request_id::request_span))
+        .with_state(state)
+}
+
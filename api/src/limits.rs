@@ -0,0 +1,479 @@
+//! Compile-time resource guards.
+//!
+//! `MAX_SOURCE_SIZE` only bounds bytes. These bound what a compile request
+//! can cost: source lines per language, the number and length of compile
+//! flags, entries in a multi-file source archive, and compile minutes per
+//! logged-in user per UTC day. Violations come back as structured errors
+//! (`ApiError::LimitExceeded`, `ApiError::CompileQuotaExceeded`).
+//!
+//! Compile time is reserved when a job is queued (the full compile timeout)
+//! and settled to what it really took once the job finishes, so a burst of
+//! queued jobs can't overshoot the quota. Settling also records the CPU time
+//! the build used, where the compiler image reports it.
+//!
+//! Execution is bounded by the caller's [`Standing`]: unverified (and
+//! anonymous) callers can't ask for more instructions than
+//! `UNVERIFIED_MAX_INSTRUCTION_LIMIT`, verified users than
+//! `VERIFIED_MAX_INSTRUCTION_LIMIT`, and admins than `MAX_INSTRUCTION_LIMIT`.
+//! A job's argv is capped at [`MAX_ARGS`] arguments of [`MAX_ARG_LENGTH`]
+//! bytes each.
+//!
+//! A job's environment goes to `docker run -e`, so [`check_env_vars`] only
+//! accepts `[A-Za-z_][A-Za-z0-9_]*` names, refuses the ones the loader, QEMU
+//! and the sandbox entrypoint read ([`RESERVED_ENV_VARS`],
+//! [`RESERVED_ENV_PREFIXES`]), and caps their number and size.
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::db::{self, User};
+use crate::queue::{CompileMetadata, CompileResult, CompileStatus, Language, QueueClient};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Longest flag name or value accepted
+pub const MAX_FLAG_LENGTH: usize = 256;
+
+/// Most arguments a job can pass to its binary
+pub const MAX_ARGS: usize = 64;
+
+/// Longest argument accepted
+pub const MAX_ARG_LENGTH: usize = 4096;
+
+/// Most environment variables a job can set
+pub const MAX_ENV_VARS: usize = 32;
+
+/// Longest environment variable name accepted
+pub const MAX_ENV_KEY_LENGTH: usize = 64;
+
+/// Longest environment variable value accepted
+pub const MAX_ENV_VALUE_LENGTH: usize = 4096;
+
+/// Variables the sandbox sets or reads itself; a job setting them could
+/// change what gets measured or how the sandbox runs
+pub const RESERVED_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "SHELL",
+    "IFS",
+    "ENV",
+    "BASH_ENV",
+    "TMPDIR",
+    "LIMIT",
+    "TIMEOUT",
+    "TIMEOUT_MS",
+    "RUNTIME_ENTRYPOINT",
+    "CORE_DUMP",
+    "PROGRAM",
+    "ARGS_FILE",
+    "EXIT_CODE",
+    "DROP",
+];
+
+/// Prefixes of variables the dynamic loader, libc, QEMU and the sandbox read
+pub const RESERVED_ENV_PREFIXES: &[&str] = &["LD_", "QEMU_", "SANDBOX_", "BASH_FUNC_", "GLIBC_", "MALLOC_"];
+
+/// Byte and per-language line caps on a source file
+pub fn check_source(config: &Config, language: Language, source: &str) -> Result<(), ApiError> {
+    if source.len() > config.max_source_size {
+        return Err(ApiError::SourceTooLarge {
+            size: source.len(),
+            max: config.max_source_size,
+        });
+    }
+
+    let lines = source.lines().count();
+    let max = config.max_source_lines_for(language.as_str());
+    if lines > max {
+        return Err(ApiError::LimitExceeded {
+            limit: "source_lines",
+            value: lines as u64,
+            max: max as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Cap the number of compile flags and the length of each
+pub fn check_flags(config: &Config, flags: &HashMap<String, String>) -> Result<(), ApiError> {
+    if flags.len() > config.max_compile_flags {
+        return Err(ApiError::LimitExceeded {
+            limit: "compile_flags",
+            value: flags.len() as u64,
+            max: config.max_compile_flags as u64,
+        });
+    }
+
+    let longest = flags.iter().map(|(k, v)| k.len().max(v.len())).max().unwrap_or(0);
+    if longest > MAX_FLAG_LENGTH {
+        return Err(ApiError::LimitExceeded {
+            limit: "compile_flag_length",
+            value: longest as u64,
+            max: MAX_FLAG_LENGTH as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Cap a job's argv; NUL bytes can't be passed through `execve` at all
+pub fn check_args(args: &[String]) -> Result<(), ApiError> {
+    if args.len() > MAX_ARGS {
+        return Err(ApiError::LimitExceeded {
+            limit: "args",
+            value: args.len() as u64,
+            max: MAX_ARGS as u64,
+        });
+    }
+
+    let longest = args.iter().map(String::len).max().unwrap_or(0);
+    if longest > MAX_ARG_LENGTH {
+        return Err(ApiError::LimitExceeded {
+            limit: "arg_length",
+            value: longest as u64,
+            max: MAX_ARG_LENGTH as u64,
+        });
+    }
+    if args.iter().any(|a| a.contains('\0')) {
+        return Err(ApiError::InvalidField("args must not contain NUL bytes".into()));
+    }
+    Ok(())
+}
+
+/// Names, reserved variables and size caps for a job's environment
+pub fn check_env_vars(env_vars: &HashMap<String, String>) -> Result<(), ApiError> {
+    if env_vars.len() > MAX_ENV_VARS {
+        return Err(ApiError::LimitExceeded {
+            limit: "env_vars",
+            value: env_vars.len() as u64,
+            max: MAX_ENV_VARS as u64,
+        });
+    }
+
+    for (key, value) in env_vars {
+        if key.len() > MAX_ENV_KEY_LENGTH {
+            return Err(ApiError::LimitExceeded {
+                limit: "env_var_name_length",
+                value: key.len() as u64,
+                max: MAX_ENV_KEY_LENGTH as u64,
+            });
+        }
+        let mut chars = key.chars();
+        let well_formed = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !well_formed {
+            return Err(ApiError::InvalidField(format!(
+                "env_vars: '{}' isn't a valid name (letters, digits and underscores, not starting with a digit)",
+                key.escape_debug()
+            )));
+        }
+        let upper = key.to_ascii_uppercase();
+        if RESERVED_ENV_VARS.contains(&upper.as_str())
+            || RESERVED_ENV_PREFIXES.iter().any(|prefix| upper.starts_with(prefix))
+        {
+            return Err(ApiError::InvalidField(format!("env_vars: {} is reserved by the sandbox", key)));
+        }
+        if value.len() > MAX_ENV_VALUE_LENGTH {
+            return Err(ApiError::LimitExceeded {
+                limit: "env_var_length",
+                value: value.len() as u64,
+                max: MAX_ENV_VALUE_LENGTH as u64,
+            });
+        }
+        if value.contains('\0') {
+            return Err(ApiError::InvalidField(format!("env_vars: {} must not contain NUL bytes", key)));
+        }
+    }
+    Ok(())
+}
+
+/// Cap the number of files in a multi-file source archive
+pub fn check_archive_entries(config: &Config, entries: usize) -> Result<(), ApiError> {
+    if entries > config.max_archive_entries {
+        return Err(ApiError::LimitExceeded {
+            limit: "archive_entries",
+            value: entries as u64,
+            max: config.max_archive_entries as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Sets the highest instruction_limit a caller may request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Standing {
+    Unverified,
+    Verified,
+    Admin,
+}
+
+impl Standing {
+    /// Anonymous callers count as unverified
+    pub fn of(config: &Config, user: Option<&User>) -> Self {
+        match user {
+            Some(user) if config.admin_users.contains(&user.username) => Standing::Admin,
+            Some(user) if user.is_verified => Standing::Verified,
+            _ => Standing::Unverified,
+        }
+    }
+}
+
+/// Instruction limits for one standing; returned by `/auth/me`
+#[derive(Debug, Clone, Serialize)]
+pub struct InstructionLimits {
+    pub standing: Standing,
+    /// Used when a run doesn't set instruction_limit
+    pub default: u64,
+    pub max: u64,
+}
+
+impl InstructionLimits {
+    pub fn for_standing(config: &Config, standing: Standing) -> Self {
+        let max = match standing {
+            Standing::Unverified => config.unverified_max_instruction_limit,
+            Standing::Verified => config.verified_max_instruction_limit,
+            Standing::Admin => config.max_instruction_limit,
+        }
+        .min(config.max_instruction_limit);
+        Self {
+            standing,
+            default: config.default_instruction_limit.min(max),
+            max,
+        }
+    }
+
+    pub fn for_user(config: &Config, user: Option<&User>) -> Self {
+        Self::for_standing(config, Standing::of(config, user))
+    }
+
+    /// A requested instruction_limit, if this standing may have it
+    pub fn check(&self, limit: u64) -> Result<u64, ApiError> {
+        if limit > self.max {
+            return Err(ApiError::InstructionLimitTooHigh { limit, max: self.max });
+        }
+        Ok(limit)
+    }
+}
+
+fn start_of_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    now.date_naive().and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc()
+}
+
+/// Reject a new compile once `user_id` has used its compile minutes for today
+pub async fn check_compile_quota(
+    config: &Config,
+    pool: &PgPool,
+    queue: &QueueClient,
+    user_id: &Uuid,
+) -> Result<(), ApiError> {
+    if config.compile_minutes_per_day == 0 {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let since = start_of_day(now);
+    settle_finished(pool, queue, user_id, since).await?;
+
+    let used_ms = db::get_compile_ms_since(pool, user_id, since).await?.max(0) as u64;
+    let limit_ms = config.compile_minutes_per_day * 60_000;
+    if used_ms >= limit_ms {
+        let reset = since + Duration::days(1);
+        return Err(ApiError::CompileQuotaExceeded {
+            used_seconds: used_ms / 1000,
+            limit_seconds: limit_ms / 1000,
+            retry_after: (reset - now).num_seconds().max(1) as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Reserve the longest a just-queued job may take; recorded even without a quota
+pub async fn reserve_compile(
+    pool: &PgPool,
+    user_id: &Uuid,
+    job_id: &Uuid,
+    language: Language,
+    timeout: std::time::Duration,
+) -> Result<(), ApiError> {
+    db::reserve_compile_usage(pool, user_id, job_id, language.as_str(), timeout.as_millis() as i64).await
+}
+
+/// Charge what a finished job took; cache hits are free
+pub async fn settle_compile(pool: &PgPool, job_id: &Uuid, result: &CompileResult) -> Result<(), ApiError> {
+    let binary_id = Some(result.binary_id.as_str());
+    let settled = if result.cached {
+        db::settle_compile_usage(pool, job_id, Some(0), Some(0), binary_id).await?
+    } else {
+        let cpu_ms = result.resources.as_ref().map(|r| (r.cpu_seconds * 1000.0) as i64);
+        db::settle_compile_usage(pool, job_id, Some(result.compile_time_ms as i64), cpu_ms, binary_id).await?
+    };
+    if let Some((user_id, compile_ms)) = settled {
+        record_compile(pool, &user_id, compile_ms, result.cached).await;
+    }
+    Ok(())
+}
+
+/// Charge a failed job the time it spent building
+pub async fn settle_failed_compile(pool: &PgPool, job_id: &Uuid, metadata: &CompileMetadata) -> Result<(), ApiError> {
+    let compile_ms = match (metadata.started_at, metadata.completed_at) {
+        (Some(started), Some(completed)) => (completed - started).num_milliseconds().max(0),
+        _ => 0,
+    };
+    if let Some(user_id) = db::settle_failed_compile_usage(pool, job_id, compile_ms).await? {
+        record_compile(pool, &user_id, compile_ms, false).await;
+    }
+    Ok(())
+}
+
+/// Add a settled compile to the user's usage analytics (see usage.rs)
+async fn record_compile(pool: &PgPool, user_id: &Uuid, compile_ms: i64, cached: bool) {
+    let delta = db::UsageDelta {
+        compiles: 1,
+        compile_cache_hits: cached as i64,
+        compile_ms,
+        ..Default::default()
+    };
+    if let Err(e) = db::record_usage(pool, user_id, &delta).await {
+        warn!(user_id = %user_id, error = %e, "Failed to record compile usage");
+    }
+}
+
+/// Settle reservations for jobs that finished without anyone fetching their result
+async fn settle_finished(pool: &PgPool, queue: &QueueClient, user_id: &Uuid, since: DateTime<Utc>) -> Result<(), ApiError> {
+    for job_id in db::get_unsettled_compile_jobs(pool, user_id, since).await? {
+        let metadata = match queue.get_compile_status(&job_id).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!(compile_job_id = %job_id, error = %e, "Failed to check compile job for quota");
+                continue;
+            }
+        };
+
+        match metadata {
+            Some(metadata) if metadata.status == CompileStatus::Completed => {
+                if let Ok(Some(result)) = queue.get_compile_result(&job_id).await {
+                    settle_compile(pool, &job_id, &result).await?;
+                }
+            }
+            Some(metadata) if metadata.status == CompileStatus::Failed => {
+                settle_failed_compile(pool, &job_id, &metadata).await?;
+            }
+            // Still queued or compiling: the reservation stands
+            Some(_) => {}
+            // Expired from the queue before we saw it finish; the reservation stays charged
+            None => {
+                if let Some((user_id, compile_ms)) = db::settle_compile_usage(pool, &job_id, None, None, None).await? {
+                    record_compile(pool, &user_id, compile_ms, false).await;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn rejected_as_invalid(pairs: &[(&str, &str)]) -> bool {
+        matches!(check_env_vars(&env(pairs)), Err(ApiError::InvalidField(_)))
+    }
+
+    fn exceeded(env_vars: &HashMap<String, String>) -> Option<(&'static str, u64, u64)> {
+        match check_env_vars(env_vars) {
+            Err(ApiError::LimitExceeded { limit, value, max }) => Some((limit, value, max)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn accepts_ordinary_variables() {
+        assert!(check_env_vars(&HashMap::new()).is_ok());
+        assert!(check_env_vars(&env(&[("FOO", "bar"), ("_x1", ""), ("RUST_LOG", "debug")])).is_ok());
+    }
+
+    #[test]
+    fn rejects_reserved_names_in_any_case() {
+        for name in RESERVED_ENV_VARS {
+            assert!(rejected_as_invalid(&[(name, "x")]), "{} accepted", name);
+            assert!(rejected_as_invalid(&[(&name.to_ascii_lowercase(), "x")]), "{} accepted", name);
+        }
+        // Only the exact name is reserved
+        assert!(check_env_vars(&env(&[("PATHS", "x"), ("MY_HOME", "x")])).is_ok());
+    }
+
+    #[test]
+    fn rejects_reserved_prefixes_in_any_case() {
+        for prefix in RESERVED_ENV_PREFIXES {
+            let name = format!("{}PRELOAD", prefix);
+            assert!(rejected_as_invalid(&[(&name, "x")]), "{} accepted", name);
+            assert!(rejected_as_invalid(&[(&name.to_ascii_lowercase(), "x")]), "{} accepted", name);
+        }
+        assert!(check_env_vars(&env(&[("XLD_PRELOAD", "x"), ("SANDBOX", "x")])).is_ok());
+    }
+
+    #[test]
+    fn covers_the_prefixes_the_sandbox_refuses() {
+        for prefix in ctf_common::sandbox_options::RESERVED_ENV_PREFIXES {
+            let name = format!("{}RUN_AS", prefix);
+            assert!(rejected_as_invalid(&[(&name, "x")]), "{} accepted", name);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        for name in ["", "1ABC", "A-B", "A=B", "A B", "Ä", "A\0B"] {
+            assert!(rejected_as_invalid(&[(name, "x")]), "{:?} accepted", name);
+        }
+    }
+
+    #[test]
+    fn rejects_nul_in_values() {
+        assert!(rejected_as_invalid(&[("FOO", "a\0b")]));
+        assert!(rejected_as_invalid(&[("FOO", "\0")]));
+    }
+
+    #[test]
+    fn caps_the_number_of_variables() {
+        let mut env_vars: HashMap<String, String> =
+            (0..MAX_ENV_VARS).map(|i| (format!("VAR_{}", i), String::new())).collect();
+        assert!(check_env_vars(&env_vars).is_ok());
+        env_vars.insert("ONE_MORE".to_string(), String::new());
+        assert_eq!(
+            exceeded(&env_vars),
+            Some(("env_vars", MAX_ENV_VARS as u64 + 1, MAX_ENV_VARS as u64))
+        );
+    }
+
+    #[test]
+    fn caps_name_and_value_length() {
+        let name = "A".repeat(MAX_ENV_KEY_LENGTH);
+        assert!(check_env_vars(&env(&[(&name, "x")])).is_ok());
+        let long_name = "A".repeat(MAX_ENV_KEY_LENGTH + 1);
+        assert_eq!(
+            exceeded(&env(&[(&long_name, "x")])),
+            Some(("env_var_name_length", MAX_ENV_KEY_LENGTH as u64 + 1, MAX_ENV_KEY_LENGTH as u64))
+        );
+
+        let value = "v".repeat(MAX_ENV_VALUE_LENGTH);
+        assert!(check_env_vars(&env(&[("FOO", &value)])).is_ok());
+        let long_value = "v".repeat(MAX_ENV_VALUE_LENGTH + 1);
+        assert_eq!(
+            exceeded(&env(&[("FOO", &long_value)])),
+            Some(("env_var_length", MAX_ENV_VALUE_LENGTH as u64 + 1, MAX_ENV_VALUE_LENGTH as u64))
+        );
+    }
+
+    #[test]
+    fn value_length_counts_bytes() {
+        let value = "é".repeat(MAX_ENV_VALUE_LENGTH / 2 + 1);
+        assert_eq!(exceeded(&env(&[("FOO", &value)])).map(|(limit, _, _)| limit), Some("env_var_length"));
+    }
+}
@@ -0,0 +1,452 @@
+//! In-process queue used when NATS is unavailable (local development).
+//!
+//! Jobs go through tokio channels to local workers that run the same docker
+//! commands as the execute/compile workers. Status and results live in memory
+//! and expire after `JOB_TTL_SECONDS`; binaries and runs still go to PostgreSQL.
+
+use crate::binary_transfer;
+use crate::compiler;
+use crate::config::Config;
+use crate::db::{self, BinaryMetadata};
+use crate::error::ApiError;
+use crate::queue::{
+    run_request, CompileJob, CompileMetadata, CompileResult, CompileStatus, Job, JobMetadata, JobStatus,
+};
+use crate::sandbox::{self, ExecutionResult};
+use chrono::{DateTime, Utc};
+use ctf_common::compile_protocol::CompileFailure;
+use ctf_common::sandbox_options::Isolation;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify, RwLock, Semaphore};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Default)]
+struct LocalState {
+    jobs: RwLock<HashMap<Uuid, JobMetadata>>,
+    /// Woken on every job status change
+    job_updates: Notify,
+    results: RwLock<HashMap<Uuid, ExecutionResult>>,
+    compiles: RwLock<HashMap<Uuid, CompileMetadata>>,
+    compile_updates: Notify,
+    compile_results: RwLock<HashMap<Uuid, CompileResult>>,
+    compile_cache: RwLock<HashMap<String, CompileResult>>,
+    /// Builds the compiler rejected, by cache key, for `COMPILE_FAILURE_CACHE_SECONDS`
+    compile_failures: RwLock<HashMap<String, (String, DateTime<Utc>)>>,
+    /// `emit_asm` output by job, and by cache key for later hits
+    compile_asm: RwLock<HashMap<Uuid, String>>,
+    compile_cache_asm: RwLock<HashMap<String, String>>,
+    jobs_pending: AtomicU64,
+    compiles_pending: AtomicU64,
+}
+
+pub struct LocalQueue {
+    jobs_tx: mpsc::UnboundedSender<Job>,
+    compiles_tx: mpsc::UnboundedSender<CompileJob>,
+    state: Arc<LocalState>,
+}
+
+impl LocalQueue {
+    /// Spawn the local execute/compile workers and return a handle to submit to them.
+    pub fn start(config: &Config, pool: PgPool) -> Self {
+        let state = Arc::new(LocalState::default());
+        let (jobs_tx, jobs_rx) = mpsc::unbounded_channel();
+        let (compiles_tx, compiles_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(run_execute_worker(jobs_rx, state.clone(), config.clone(), pool.clone()));
+        tokio::spawn(run_compile_worker(compiles_rx, state.clone(), config.clone(), pool));
+        tokio::spawn(run_expiry(state.clone(), Duration::from_secs(config.job_ttl_seconds)));
+
+        info!(
+            "Local queue started ({} concurrent executions)",
+            config.max_concurrent
+        );
+
+        Self {
+            jobs_tx,
+            compiles_tx,
+            state,
+        }
+    }
+
+    pub async fn submit_job(&self, job: Job) -> Result<(), ApiError> {
+        let metadata = JobMetadata::pending(&job);
+        self.state.jobs.write().await.insert(job.id, metadata);
+
+        self.state.jobs_pending.fetch_add(1, Ordering::Relaxed);
+        self.jobs_tx
+            .send(job)
+            .map_err(|_| ApiError::QueueError("Local execute worker stopped".to_string()))
+    }
+
+    pub async fn get_job_status(&self, job_id: &Uuid) -> Result<Option<JobMetadata>, ApiError> {
+        Ok(self.state.jobs.read().await.get(job_id).cloned())
+    }
+
+    pub async fn watch_job_status(
+        &self,
+        job_id: &Uuid,
+        from: JobStatus,
+        timeout: Duration,
+    ) -> Result<Option<JobMetadata>, ApiError> {
+        let moved = |m: &JobMetadata| m.status != from;
+        Ok(watch(&self.state.jobs, &self.state.job_updates, job_id, timeout, moved).await)
+    }
+
+    pub async fn get_job_result(&self, job_id: &Uuid) -> Result<Option<ExecutionResult>, ApiError> {
+        Ok(self.state.results.read().await.get(job_id).cloned())
+    }
+
+    pub fn get_queue_depth(&self) -> u64 {
+        self.state.jobs_pending.load(Ordering::Relaxed)
+    }
+
+    pub async fn submit_compile_job(&self, job: CompileJob) -> Result<(), ApiError> {
+        let metadata = CompileMetadata {
+            status: CompileStatus::Pending,
+            created_at: job.created_at,
+            started_at: None,
+            completed_at: None,
+            error: None,
+            position: None,
+        };
+        self.state.compiles.write().await.insert(job.id, metadata);
+
+        self.state.compiles_pending.fetch_add(1, Ordering::Relaxed);
+        self.compiles_tx
+            .send(job)
+            .map_err(|_| ApiError::QueueError("Local compile worker stopped".to_string()))
+    }
+
+    pub async fn get_compile_status(&self, job_id: &Uuid) -> Result<Option<CompileMetadata>, ApiError> {
+        Ok(self.state.compiles.read().await.get(job_id).cloned())
+    }
+
+    pub async fn watch_compile_status(
+        &self,
+        job_id: &Uuid,
+        from: CompileStatus,
+        timeout: Duration,
+    ) -> Result<Option<CompileMetadata>, ApiError> {
+        let moved = |m: &CompileMetadata| m.status != from;
+        Ok(watch(&self.state.compiles, &self.state.compile_updates, job_id, timeout, moved).await)
+    }
+
+    pub async fn get_compile_result(&self, job_id: &Uuid) -> Result<Option<CompileResult>, ApiError> {
+        Ok(self.state.compile_results.read().await.get(job_id).cloned())
+    }
+
+    pub async fn get_compile_asm(&self, job_id: &Uuid) -> Result<Option<String>, ApiError> {
+        Ok(self.state.compile_asm.read().await.get(job_id).cloned())
+    }
+
+    pub fn get_compile_queue_depth(&self) -> u64 {
+        self.state.compiles_pending.load(Ordering::Relaxed)
+    }
+
+    pub async fn check_compile_cache(&self, job: &CompileJob) -> Result<Option<CompileResult>, ApiError> {
+        let cache_key = job.cache_key();
+        Ok(self.state.compile_cache.read().await.get(&cache_key).cloned())
+    }
+}
+
+// ============ Local Workers ============
+
+async fn set_job_status(state: &LocalState, job_id: &Uuid, status: JobStatus, error: Option<String>) {
+    if let Some(metadata) = state.jobs.write().await.get_mut(job_id) {
+        metadata.status = status;
+        match status {
+            JobStatus::Running => metadata.started_at = Some(Utc::now()),
+            JobStatus::Completed | JobStatus::Failed => {
+                metadata.completed_at = Some(Utc::now());
+                metadata.error = error;
+            }
+            _ => {}
+        }
+    }
+    state.job_updates.notify_waiters();
+}
+
+async fn set_compile_status(state: &LocalState, job_id: &Uuid, status: CompileStatus, error: Option<String>) {
+    if let Some(metadata) = state.compiles.write().await.get_mut(job_id) {
+        metadata.status = status;
+        match status {
+            CompileStatus::Compiling => metadata.started_at = Some(Utc::now()),
+            CompileStatus::Completed | CompileStatus::Failed => {
+                metadata.completed_at = Some(Utc::now());
+                metadata.error = error;
+            }
+            _ => {}
+        }
+    }
+    state.compile_updates.notify_waiters();
+}
+
+/// Wait up to `timeout` for `moved` to hold for a job's status; the status
+/// at that point, `None` if the job is unknown or has expired
+async fn watch<T: Clone>(
+    statuses: &RwLock<HashMap<Uuid, T>>,
+    updates: &Notify,
+    job_id: &Uuid,
+    timeout: Duration,
+    moved: impl Fn(&T) -> bool,
+) -> Option<T> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        // Registered before the read, so a change in between still wakes us
+        let updated = updates.notified();
+        let current = statuses.read().await.get(job_id).cloned();
+        match current {
+            Some(status) if !moved(&status) => {
+                if tokio::time::timeout_at(deadline, updated).await.is_err() {
+                    return Some(status);
+                }
+            }
+            other => return other,
+        }
+    }
+}
+
+async fn run_execute_worker(
+    mut rx: mpsc::UnboundedReceiver<Job>,
+    state: Arc<LocalState>,
+    config: Config,
+    pool: PgPool,
+) {
+    let config = Arc::new(config);
+    let permits = Arc::new(Semaphore::new(config.max_concurrent.max(1)));
+
+    while let Some(job) = rx.recv().await {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            break;
+        };
+        state.jobs_pending.fetch_sub(1, Ordering::Relaxed);
+
+        let state = state.clone();
+        let config = config.clone();
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let job_id = job.id;
+            let run = tokio::spawn({
+                let state = state.clone();
+                async move { execute_job(&job, &state, &config, &pool).await }
+            });
+            // A panicking job must not be left "running" forever
+            if run.await.is_err() {
+                error!(job_id = %job_id, "Local execute worker crashed");
+                set_job_status(&state, &job_id, JobStatus::Failed, Some("Worker crashed while running job".to_string())).await;
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn execute_job(job: &Job, state: &LocalState, config: &Config, pool: &PgPool) {
+    info!(job_id = %job.id, binary_id = %job.binary_id, "Processing job locally");
+
+    let binary = match db::get_binary(pool, &job.binary_id).await {
+        Ok(Some(b)) => b,
+        Ok(None) => {
+            set_job_status(state, &job.id, JobStatus::Failed, Some(format!("Binary not found: {}", job.binary_id))).await;
+            return;
+        }
+        Err(e) => {
+            set_job_status(state, &job.id, JobStatus::Failed, Some(format!("Failed to fetch binary: {}", e))).await;
+            return;
+        }
+    };
+    let metadata = db::get_binary_metadata(pool, &job.binary_id).await.ok().flatten();
+    let binary_size = binary.len();
+
+    set_job_status(state, &job.id, JobStatus::Running, None).await;
+
+    match sandbox::execute(
+        binary,
+        sandbox::limits(job.instruction_limit, job.timeout_ms, job.memory_limit_mb, config),
+        job.stdin.clone(),
+        &job.args,
+        job.network_enabled,
+        &job.env_vars,
+        Isolation {
+            fixtures: job.fixtures.as_ref(),
+            options: job.sandbox_options.as_ref(),
+            outputs: &job.outputs,
+            core_dump: job.core_dump,
+            mode: job.mode,
+            runtime: metadata.as_ref().and_then(|m| m.runtime.as_ref()),
+        },
+        config,
+    )
+    .await
+    {
+        Ok((mut result, collected)) => {
+            sandbox::store_outputs(pool, &mut result, collected).await;
+            info!(
+                job_id = %job.id,
+                instructions = result.instructions,
+                exit_code = result.exit_code,
+                time_ms = result.execution_time_ms,
+                "Job completed"
+            );
+
+            if let Err(e) = db::save_run(pool, &run_request(job, Some(binary_size), metadata.as_ref(), &result, None, Utc::now())).await {
+                error!("Failed to persist run to database: {}", e);
+            }
+
+            state.results.write().await.insert(job.id, result);
+            set_job_status(state, &job.id, JobStatus::Completed, None).await;
+        }
+        Err(e) => {
+            error!(job_id = %job.id, error = %e, "Job failed");
+            set_job_status(state, &job.id, JobStatus::Failed, Some(e.to_string())).await;
+        }
+    }
+}
+
+async fn run_compile_worker(
+    mut rx: mpsc::UnboundedReceiver<CompileJob>,
+    state: Arc<LocalState>,
+    config: Config,
+    pool: PgPool,
+) {
+    // Compiles are heavy (up to COMPILE_MEMORY_LIMIT_MB each), so run them one at a time
+    let config = Arc::new(config);
+
+    while let Some(job) = rx.recv().await {
+        state.compiles_pending.fetch_sub(1, Ordering::Relaxed);
+
+        let job_id = job.id;
+        let run = tokio::spawn({
+            let state = state.clone();
+            let config = config.clone();
+            let pool = pool.clone();
+            async move { compile_job(&job, &state, &config, &pool).await }
+        });
+        if run.await.is_err() {
+            error!(job_id = %job_id, "Local compile worker crashed");
+            set_compile_status(&state, &job_id, CompileStatus::Failed, Some("Worker crashed while compiling".to_string())).await;
+        }
+    }
+}
+
+async fn compile_job(job: &CompileJob, state: &LocalState, config: &Config, pool: &PgPool) {
+    info!(
+        job_id = %job.id,
+        language = ?job.language,
+        optimization = ?job.optimization,
+        "Processing compile job locally"
+    );
+
+    let start = Instant::now();
+    let cache_key = job.cache_key();
+
+    // A job that wants assembly only hits if that was kept too
+    let cached_asm = state.compile_cache_asm.read().await.get(&cache_key).cloned();
+    let cached = state.compile_cache.read().await.get(&cache_key).cloned();
+    if let Some(mut cached_result) = cached.filter(|_| !job.emit_asm || cached_asm.is_some()) {
+        cached_result.cached = true;
+        if let (true, Some(asm)) = (job.emit_asm, cached_asm) {
+            state.compile_asm.write().await.insert(job.id, asm);
+        }
+        state.compile_results.write().await.insert(job.id, cached_result);
+        set_compile_status(state, &job.id, CompileStatus::Completed, None).await;
+        return;
+    }
+
+    let failure_ttl = chrono::Duration::seconds(config.compile_failure_cache_seconds as i64);
+    let cached_failure = state.compile_failures.read().await.get(&cache_key).cloned();
+    let cached_failure = cached_failure.filter(|(_, at)| !job.retry_failed && Utc::now() - *at < failure_ttl);
+    if let Some((error, failed_at)) = cached_failure {
+        let error = format!(
+            "{}\n(an identical build failed {}s ago; resubmit with retry_failed=true to build again)",
+            error,
+            (Utc::now() - failed_at).num_seconds()
+        );
+        set_compile_status(state, &job.id, CompileStatus::Failed, Some(error)).await;
+        return;
+    }
+
+    set_compile_status(state, &job.id, CompileStatus::Compiling, None).await;
+
+    let output = match compiler::compile(job, config).await {
+        Ok(output) => output,
+        Err(failure) => {
+            warn!(job_id = %job.id, error = %failure.message(), "Compilation failed");
+            if let CompileFailure::Rejected(error) = &failure {
+                let mut failures = state.compile_failures.write().await;
+                failures.retain(|_, (_, at)| Utc::now() - *at < failure_ttl);
+                failures.insert(cache_key, (error.clone(), Utc::now()));
+            }
+            set_compile_status(state, &job.id, CompileStatus::Failed, Some(failure.into_message())).await;
+            return;
+        }
+    };
+
+    let compile_time_ms = start.elapsed().as_millis() as u64;
+    let binary_id = compiler::compute_binary_id(&output.binary);
+    let metadata = BinaryMetadata {
+        language: Some(job.language.as_str().to_string()),
+        optimization: Some(job.optimization.as_str().to_string()),
+        compiler_version: output.compiler_version,
+        compile_flags: output.compile_flags,
+        compiler_image: output.compiler_image,
+        toolchain: output.toolchain,
+        runtime: output.runtime,
+    };
+
+    if let Err(e) = binary_transfer::store(pool, config, &binary_id, &output.binary, None, Some(&metadata)).await {
+        error!(job_id = %job.id, error = %e, "Failed to store compile result");
+        set_compile_status(state, &job.id, CompileStatus::Failed, Some(e.to_string())).await;
+        return;
+    }
+    if let Err(e) = db::record_binary_build(pool, &binary_id, &cache_key).await {
+        warn!(job_id = %job.id, error = %e, "Failed to record binary build");
+    }
+    if let Some(report) = &output.size_report {
+        if let Err(e) = db::store_size_report(pool, &binary_id, report).await {
+            warn!(job_id = %job.id, error = %e, "Failed to store size report");
+        }
+    }
+
+    let result = CompileResult {
+        binary_id,
+        binary_size: output.binary.len(),
+        compile_time_ms,
+        cached: false,
+        build_cache: None,
+        diagnostics: output.diagnostics,
+        resources: output.resources,
+    };
+    if let Some(asm) = output.asm {
+        state.compile_cache_asm.write().await.insert(cache_key.clone(), asm.clone());
+        state.compile_asm.write().await.insert(job.id, asm);
+    }
+    state.compile_cache.write().await.insert(cache_key, result.clone());
+    state.compile_results.write().await.insert(job.id, result);
+    set_compile_status(state, &job.id, CompileStatus::Completed, None).await;
+}
+
+/// Drop finished entries older than the job TTL, mirroring the KV bucket max_age.
+async fn run_expiry(state: Arc<LocalState>, ttl: Duration) {
+    let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::hours(1));
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+        let cutoff = Utc::now() - ttl;
+
+        let mut jobs = state.jobs.write().await;
+        jobs.retain(|_, m| m.created_at >= cutoff);
+        state.results.write().await.retain(|id, _| jobs.contains_key(id));
+        drop(jobs);
+
+        let mut compiles = state.compiles.write().await;
+        compiles.retain(|_, m| m.created_at >= cutoff);
+        state.compile_results.write().await.retain(|id, _| compiles.contains_key(id));
+        state.compile_asm.write().await.retain(|id, _| compiles.contains_key(id));
+    }
+}
@@ -0,0 +1,249 @@
+//! The measurement environment, published so third parties can reproduce
+//! instruction counts locally.
+//!
+//! `GET /sandbox/manifest` describes the sandbox and compiler images (tag and
+//! resolved digest), the container flags and limits runs get, and how the
+//! QEMU plugin turns execution into an instruction count.
+//! `GET /sandbox/docker-compose.yml` renders the same thing as a compose file
+//! that compiles `./main.<ext>` and runs the result.
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::queue::{Language, Optimization};
+use crate::sandbox::{resolve_image_digest, FILESYSTEM_ARGS, MIN_MEMORY_LIMIT_MB};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Plugin arguments `sandbox/entrypoint.sh` passes to `sandbox.so`
+const PLUGIN_ARGS: &str = "limit=$LIMIT,binary=/work/binary,from_start=on";
+/// `syscall_cost` isn't passed by the entrypoint, so syscalls add nothing
+const SYSCALL_COST: u64 = 0;
+
+static DIGESTS: OnceCell<(Option<String>, Option<String>)> = OnceCell::const_new();
+
+#[derive(Debug, Serialize)]
+pub struct SandboxManifest {
+    pub api_version: &'static str,
+    pub sandbox: ImageInfo,
+    pub compiler: ImageInfo,
+    /// Pinned compiler images `/compile` accepts as `compiler_image`
+    pub compiler_image_allowlist: Vec<String>,
+    /// `docker run` flags every execution gets, besides limits and env
+    pub docker_run_args: Vec<String>,
+    pub limits: ManifestLimits,
+    pub cost_model: CostModel,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageInfo {
+    /// Configured tag
+    pub image: String,
+    /// `repo@sha256:...` or image ID; `None` if this API can't inspect it
+    pub digest: Option<String>,
+}
+
+impl ImageInfo {
+    /// Digest when known, so a local pull gets the identical image
+    fn reference(&self) -> &str {
+        self.digest.as_deref().unwrap_or(&self.image)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestLimits {
+    pub default_instruction_limit: u64,
+    pub max_instruction_limit: u64,
+    pub memory_limit_mb: u32,
+    pub min_memory_limit_mb: u32,
+    pub max_memory_limit_mb: u32,
+    pub timeout_ms: u64,
+    pub compile_timeout_sec: u64,
+    pub compile_memory_limit_mb: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CostModel {
+    /// What one unit of `instructions` is
+    pub unit: &'static str,
+    pub emulator: &'static str,
+    pub plugin: &'static str,
+    pub plugin_args: &'static str,
+    /// Extra instructions charged per syscall
+    pub syscall_cost: u64,
+    pub description: &'static str,
+}
+
+/// Digests of the configured sandbox and compiler images, resolved once
+pub async fn image_digests(config: &Config) -> (Option<String>, Option<String>) {
+    DIGESTS
+        .get_or_init(|| async {
+            (
+                resolve_image_digest(&config.sandbox_image).await,
+                resolve_image_digest(&config.compiler_image).await,
+            )
+        })
+        .await
+        .clone()
+}
+
+pub async fn build(config: &Config) -> SandboxManifest {
+    let (sandbox_digest, compiler_digest) = image_digests(config).await;
+
+    SandboxManifest {
+        api_version: crate::version::API_VERSION,
+        sandbox: ImageInfo {
+            image: config.sandbox_image.clone(),
+            digest: sandbox_digest,
+        },
+        compiler: ImageInfo {
+            image: config.compiler_image.clone(),
+            digest: compiler_digest,
+        },
+        compiler_image_allowlist: config.compiler_image_allowlist.clone(),
+        docker_run_args: ["-i", "--network=none"]
+            .into_iter()
+            .chain(FILESYSTEM_ARGS)
+            .chain(["-v", "<binary>:/work/binary:ro"])
+            .map(str::to_string)
+            .collect(),
+        limits: ManifestLimits {
+            default_instruction_limit: config.default_instruction_limit,
+            max_instruction_limit: config.max_instruction_limit,
+            memory_limit_mb: config.memory_limit_mb,
+            min_memory_limit_mb: MIN_MEMORY_LIMIT_MB,
+            max_memory_limit_mb: config.max_memory_limit_mb,
+            timeout_ms: config.timeout_sec * 1000,
+            compile_timeout_sec: config.compile_timeout_sec,
+            compile_memory_limit_mb: config.compile_memory_limit_mb,
+        },
+        cost_model: CostModel {
+            unit: "guest_instruction",
+            emulator: "qemu-x86_64 -cpu max",
+            plugin: "/plugin/sandbox.so",
+            plugin_args: PLUGIN_ARGS,
+            syscall_cost: SYSCALL_COST,
+            description: "Every guest user-space x86-64 instruction executed, counted per translated block \
+                from the first instruction (dynamic loader and runtime startup included). Kernel work done \
+                on the guest's behalf is not counted. LIMIT and TIMEOUT_MS are read from the container \
+                environment.",
+        },
+    }
+}
+
+/// Compose file with a `compiler` service that builds `./main.<ext>` into
+/// `./binary` and a `sandbox` service that runs it like the arena does
+pub fn compose(manifest: &SandboxManifest, language: Language, optimization: Optimization) -> String {
+    let source = format!("main.{}", language.source_extension());
+    let tmpfs: String = FILESYSTEM_ARGS
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("--tmpfs="))
+        .map(|mount| format!("      - {}\n", mount))
+        .collect();
+    let limits = &manifest.limits;
+
+    format!(
+        r#"# Generated from the CTF Arena sandbox manifest (API {api_version}).
+# Put your source in ./{source}, then:
+#   docker compose run --rm compiler
+#   docker compose run --rm -T sandbox < input.txt
+# The instruction count is the last line on stderr.
+services:
+  compiler:
+    image: {compiler_image}
+    mem_limit: {compile_memory}m
+    memswap_limit: {compile_memory}m
+    tmpfs:
+      - /tmp:rw,exec,nosuid,size=512m
+    environment:
+      LANGUAGE: "{language}"
+      OPTIMIZATION: "{optimization}"
+      SOURCE_FILE: "{source}"
+      OUTPUT_FILE: "binary"
+    volumes:
+      - ./:/work:rw
+
+  sandbox:
+    image: {sandbox_image}
+    stdin_open: true
+    network_mode: none
+    read_only: true
+    mem_limit: {memory}m
+    memswap_limit: {memory}m
+    tmpfs:
+{tmpfs}    environment:
+      LIMIT: "{instructions}"
+      TIMEOUT_MS: "{timeout_ms}"
+    volumes:
+      - ./binary:/work/binary:ro
+"#,
+        api_version = manifest.api_version,
+        compiler_image = manifest.compiler.reference(),
+        compile_memory = limits.compile_memory_limit_mb,
+        language = language.as_str(),
+        optimization = optimization.as_str(),
+        sandbox_image = manifest.sandbox.reference(),
+        memory = limits.memory_limit_mb,
+        instructions = limits.default_instruction_limit,
+        timeout_ms = limits.timeout_ms,
+    )
+}
+
+// ============ Handlers ============
+
+/// Manifest routes; mounted with the public CORS policy
+pub fn router() -> Router<Arc<crate::AppState>> {
+    Router::new()
+        .route("/sandbox/manifest", get(get_manifest))
+        .route("/sandbox/docker-compose.yml", get(get_compose))
+}
+
+async fn get_manifest(State(state): State<Arc<crate::AppState>>) -> Response {
+    let mut response = Json(build(&state.config).await).into_response();
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComposeQuery {
+    /// Language the compiler service builds (default c)
+    pub language: Option<String>,
+    /// debug, release or size (default release)
+    pub optimization: Option<String>,
+}
+
+async fn get_compose(
+    State(state): State<Arc<crate::AppState>>,
+    Query(query): Query<ComposeQuery>,
+) -> Result<Response, ApiError> {
+    let language = match query.language.as_deref() {
+        Some(name) => Language::from_str(name).ok_or_else(|| ApiError::InvalidLanguage(name.to_string()))?,
+        None => Language::C,
+    };
+    let optimization = match query.optimization.as_deref() {
+        Some(name) => Optimization::from_str(name)
+            .ok_or_else(|| ApiError::InvalidField(format!("unknown optimization '{}'", name)))?,
+        None => Optimization::Release,
+    };
+
+    let manifest = build(&state.config).await;
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/yaml; charset=utf-8")),
+            (header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL)),
+        ],
+        compose(&manifest, language, optimization),
+    )
+        .into_response())
+}
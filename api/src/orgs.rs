@@ -0,0 +1,294 @@
+//! Organizations and the compile policy they hold their members to.
+//!
+//! A site admin creates an organization (`POST /admin/orgs`); its admins then
+//! manage members and set a policy restricting the languages, optimization
+//! levels and compile flags members may use, e.g. a class that only allows
+//! C at `debug`. A user belongs to at most one organization.
+//!
+//! The policy is checked wherever a member's code gets compiled (`/compile`,
+//! `/import/gist`, challenge submissions, tracking a solution, repo pushes),
+//! before anything is queued, and `GET /languages` only lists what the
+//! caller's organization allows. Builds the server starts on a member's
+//! behalf (tracked solution re-runs) are checked too, so tightening the
+//! policy can't be sidestepped by code tracked before it.
+
+use crate::auth::{AdminUser, AuthenticatedUser};
+use crate::db::{self, Organization, OrganizationMember, User};
+use crate::error::ApiError;
+use crate::queue::{CompileJob, Language, Optimization};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+
+const MAX_SLUG_LENGTH: usize = 50;
+const MAX_NAME_LENGTH: usize = 100;
+
+/// What members may compile; a missing list allows everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrgPolicy {
+    /// Language ids or names (`c`, `javascript`); a name covers its runtimes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optimizations: Option<Vec<Optimization>>,
+    /// Compile flag names (`lto`, `nostd`); empty forbids flags altogether
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<Vec<String>>,
+    /// Linker flags and scripts (`build_options`)
+    #[serde(default = "default_true")]
+    pub build_options: bool,
+    /// Toolchains other than the default, and pinned compiler images
+    #[serde(default = "default_true")]
+    pub toolchains: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl OrgPolicy {
+    fn parse(org: &Organization) -> Result<Self, ApiError> {
+        serde_json::from_value(org.policy.clone())
+            .map_err(|e| ApiError::Internal(format!("Invalid policy for organization {}: {}", org.slug, e)))
+    }
+
+    pub fn allows_language(&self, language: Language) -> bool {
+        self.languages.as_ref().is_none_or(|allowed| {
+            allowed
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(language.as_str()) || name.eq_ignore_ascii_case(language.canonical()))
+        })
+    }
+
+    pub fn allows_optimization(&self, optimization: Optimization) -> bool {
+        self.optimizations.as_ref().is_none_or(|allowed| allowed.contains(&optimization))
+    }
+
+    /// Why `job` is outside the policy, if it is
+    fn violation(&self, job: &CompileJob) -> Option<String> {
+        if !self.allows_language(job.language) {
+            return Some(format!("{} isn't allowed", job.language.as_str()));
+        }
+        if !self.allows_optimization(job.optimization) {
+            return Some(format!("optimization '{}' isn't allowed", job.optimization.as_str()));
+        }
+        if let Some(allowed) = &self.flags {
+            if let Some(flag) = job.flags.keys().find(|flag| !allowed.contains(flag)) {
+                return Some(format!("flag '{}' isn't allowed", flag));
+            }
+        }
+        if !self.build_options && job.build_options != Default::default() {
+            return Some("build options aren't allowed".to_string());
+        }
+        if !self.toolchains && (job.toolchain.is_some() || job.compiler_image.is_some()) {
+            return Some("only the default toolchain and compiler image are allowed".to_string());
+        }
+        None
+    }
+
+    fn validate(&self) -> Result<(), ApiError> {
+        for name in self.languages.iter().flatten() {
+            if Language::from_str(name).is_none() {
+                return Err(ApiError::InvalidLanguage(name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The policy of the organization `user_id` belongs to, if any
+pub async fn policy_for(pool: &PgPool, user_id: &uuid::Uuid) -> Result<Option<(String, OrgPolicy)>, ApiError> {
+    match db::get_user_organization(pool, user_id).await? {
+        Some((org, _)) => Ok(Some((org.slug.clone(), OrgPolicy::parse(&org)?))),
+        None => Ok(None),
+    }
+}
+
+/// Reject a job its user's organization doesn't allow
+pub async fn enforce(pool: &PgPool, job: &CompileJob) -> Result<(), ApiError> {
+    let Some(user_id) = &job.user_id else {
+        return Ok(());
+    };
+    if let Some((slug, policy)) = policy_for(pool, user_id).await? {
+        if let Some(reason) = policy.violation(job) {
+            return Err(ApiError::Forbidden(format!("Organization {} policy: {}", slug, reason)));
+        }
+    }
+    Ok(())
+}
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrgRequest {
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMemberRequest {
+    #[serde(default = "default_role")]
+    pub role: String,
+}
+
+fn default_role() -> String {
+    "member".to_string()
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrgResponse {
+    pub slug: String,
+    pub name: String,
+    pub policy: OrgPolicy,
+    pub members: Vec<OrganizationMember>,
+}
+
+// ============ Handlers ============
+
+fn pool(state: &AppState) -> Result<&PgPool, ApiError> {
+    state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))
+}
+
+/// The organization at `slug` if `user` may manage it: its admins and site admins
+async fn managed_org(state: &AppState, pool: &PgPool, slug: &str, user: &User) -> Result<Organization, ApiError> {
+    let org = db::get_organization(pool, slug)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Organization '{}' not found", slug)))?;
+    if state.config.admin_users.contains(&user.username) {
+        return Ok(org);
+    }
+    match db::get_user_organization(pool, &user.id).await? {
+        Some((own, role)) if own.id == org.id && role == "admin" => Ok(org),
+        Some((own, _)) if own.id == org.id => Err(ApiError::Forbidden("Organization admin access required".to_string())),
+        _ => Err(ApiError::NotFound(format!("Organization '{}' not found", slug))),
+    }
+}
+
+async fn respond(pool: &PgPool, org: Organization) -> Result<Json<OrgResponse>, ApiError> {
+    let policy = OrgPolicy::parse(&org)?;
+    let members = db::list_organization_members(pool, &org.id).await?;
+    Ok(Json(OrgResponse {
+        slug: org.slug,
+        name: org.name,
+        policy,
+        members,
+    }))
+}
+
+pub async fn create_org(
+    State(state): State<Arc<AppState>>,
+    AdminUser(admin): AdminUser,
+    Json(req): Json<CreateOrgRequest>,
+) -> Result<Json<OrgResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let slug = req.slug.trim().to_lowercase();
+    if slug.is_empty()
+        || slug.len() > MAX_SLUG_LENGTH
+        || !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(ApiError::InvalidField(format!(
+            "slug must be 1-{} letters, digits or dashes",
+            MAX_SLUG_LENGTH
+        )));
+    }
+    let name = req.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiError::InvalidField(format!("name must be 1-{} characters", MAX_NAME_LENGTH)));
+    }
+
+    let org = db::create_organization(pool, &slug, name)
+        .await?
+        .ok_or_else(|| ApiError::InvalidField(format!("organization '{}' already exists", slug)))?;
+
+    info!(slug = %org.slug, admin = %admin.username, "Organization created");
+
+    respond(pool, org).await
+}
+
+/// Members see their organization and its policy; admins can manage it
+pub async fn get_org(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(slug): Path<String>,
+) -> Result<Json<OrgResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let org = match managed_org(&state, pool, &slug, &user).await {
+        Err(ApiError::Forbidden(_)) => db::get_organization(pool, &slug)
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("Organization '{}' not found", slug)))?,
+        other => other?,
+    };
+    respond(pool, org).await
+}
+
+pub async fn set_policy(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(policy): Json<OrgPolicy>,
+) -> Result<Json<OrgResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let mut org = managed_org(&state, pool, &slug, &user).await?;
+    policy.validate()?;
+
+    org.policy = serde_json::to_value(&policy)
+        .map_err(|e| ApiError::Internal(format!("Failed to serialize policy: {}", e)))?;
+    db::set_organization_policy(pool, &org.id, &org.policy).await?;
+
+    info!(slug = %org.slug, user_id = %user.id, policy = %org.policy, "Organization policy changed");
+
+    respond(pool, org).await
+}
+
+pub async fn set_member(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((slug, username)): Path<(String, String)>,
+    Json(req): Json<SetMemberRequest>,
+) -> Result<Json<OrgResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let org = managed_org(&state, pool, &slug, &user).await?;
+    if !matches!(req.role.as_str(), "admin" | "member") {
+        return Err(ApiError::InvalidField("role must be admin or member".to_string()));
+    }
+    let member = db::get_user_by_username(pool, &username)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("User '{}' not found", username)))?;
+
+    if !db::set_organization_member(pool, &org.id, &member.id, &req.role).await? {
+        return Err(ApiError::InvalidField(format!("{} already belongs to another organization", username)));
+    }
+
+    info!(slug = %org.slug, member = %username, role = %req.role, "Organization member set");
+
+    respond(pool, org).await
+}
+
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((slug, username)): Path<(String, String)>,
+) -> Result<Json<OrgResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let org = managed_org(&state, pool, &slug, &user).await?;
+    let member = db::get_user_by_username(pool, &username)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("User '{}' not found", username)))?;
+
+    if !db::remove_organization_member(pool, &org.id, &member.id).await? {
+        return Err(ApiError::NotFound(format!("{} is not a member of {}", username, org.slug)));
+    }
+
+    info!(slug = %org.slug, member = %username, "Organization member removed");
+
+    respond(pool, org).await
+}
@@ -0,0 +1,174 @@
+//! Keeping job results past `JOB_TTL_SECONDS`.
+//!
+//! Job statuses and results live in NATS KV (or the local queue's memory) for
+//! `JOB_TTL_SECONDS` whether or not anyone fetched them. A pinned job has its
+//! status and result mirrored into `pinned_results`, and `/status/:job_id`
+//! and `/result/:job_id` fall back to that copy once the queue has forgotten
+//! the job.
+//!
+//! Jobs that logged-in users submit through `/submit` are pinned until their
+//! result is first fetched. `POST /jobs/:id/pin` pins any job the queue still
+//! knows for `PINNED_RESULT_TTL_SECONDS` from now; pinning again extends it.
+//! A background pass every [`SYNC_INTERVAL`] mirrors pinned jobs as they
+//! finish and drops pins that have lapsed.
+
+use crate::auth::MaybeAuthenticatedUser;
+use crate::db::{self, PinnedResult};
+use crate::error::ApiError;
+use crate::queue::{JobMetadata, JobStatus, QueueClient};
+use crate::sandbox::ExecutionResult;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How often finished pinned jobs are mirrored; well under the job TTL
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Pins looked at per pass
+const SYNC_BATCH: i64 = 500;
+
+#[derive(Debug, Serialize)]
+pub struct PinResponse {
+    pub job_id: Uuid,
+    /// Kept at least until the result is first fetched
+    pub until_fetched: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The status and result are already copied out of the queue
+    pub mirrored: bool,
+}
+
+pub async fn pin_job(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<PinResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+
+    let metadata = queue.get_job_status(&job_id).await?;
+    if metadata.is_none() && db::get_pinned_result(pool, &job_id).await?.is_none() {
+        return Err(ApiError::JobNotFound(job_id.to_string()));
+    }
+
+    let expires_at = Utc::now() + ChronoDuration::seconds(state.config.pinned_result_ttl_seconds as i64);
+    let pin = db::pin_job(pool, &job_id, user.as_ref().map(|u| &u.id), false, Some(expires_at)).await?;
+    let mut mirrored = pin.metadata.is_some();
+    if let Some(metadata) = metadata.filter(|m| !mirrored && is_finished(m)) {
+        mirror(pool, queue, &job_id, &metadata).await?;
+        mirrored = true;
+    }
+
+    info!(job_id = %job_id, expires_at = %expires_at, "Job pinned");
+
+    Ok(Json(PinResponse {
+        job_id,
+        until_fetched: pin.until_fetched,
+        expires_at: pin.expires_at,
+        mirrored,
+    }))
+}
+
+/// Pin a job until its result is first fetched, for logged-in submitters
+pub async fn pin_until_fetched(pool: &PgPool, job_id: &Uuid, user_id: &Uuid) {
+    if let Err(e) = db::pin_job(pool, job_id, Some(user_id), true, None).await {
+        warn!(job_id = %job_id, error = %e, "Failed to pin job");
+    }
+}
+
+/// The mirrored status and result of a pinned job
+pub async fn lookup(state: &AppState, job_id: &Uuid) -> Result<Option<(JobMetadata, Option<ExecutionResult>)>, ApiError> {
+    let Some(pool) = state.db.as_ref() else {
+        return Ok(None);
+    };
+    let Some(PinnedResult {
+        metadata: Some(metadata),
+        result,
+        ..
+    }) = db::get_pinned_result(pool, job_id).await?
+    else {
+        return Ok(None);
+    };
+
+    let metadata: JobMetadata = serde_json::from_value(metadata)
+        .map_err(|e| ApiError::Internal(format!("Failed to parse pinned job metadata: {}", e)))?;
+    let result = result
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| ApiError::Internal(format!("Failed to parse pinned job result: {}", e)))?;
+    Ok(Some((metadata, result)))
+}
+
+/// A result was handed out; pins that only waited for that lapse
+pub async fn fetched(state: &AppState, job_id: &Uuid) {
+    if let Some(pool) = state.db.as_ref() {
+        if let Err(e) = db::release_fetched_pin(pool, job_id).await {
+            warn!(job_id = %job_id, error = %e, "Failed to release pinned result");
+        }
+    }
+}
+
+fn is_finished(metadata: &JobMetadata) -> bool {
+    matches!(metadata.status, JobStatus::Completed | JobStatus::Failed)
+}
+
+/// Copy a finished job's status and result out of the queue
+async fn mirror(pool: &PgPool, queue: &QueueClient, job_id: &Uuid, metadata: &JobMetadata) -> Result<(), ApiError> {
+    let result = match metadata.status {
+        JobStatus::Completed => queue.get_job_result(job_id).await?,
+        _ => None,
+    };
+    let metadata = serde_json::to_value(metadata)
+        .map_err(|e| ApiError::Internal(format!("Failed to serialize job metadata: {}", e)))?;
+    let result = result
+        .map(|r| serde_json::to_value(&r))
+        .transpose()
+        .map_err(|e| ApiError::Internal(format!("Failed to serialize job result: {}", e)))?;
+    db::store_pinned_result(pool, job_id, &metadata, result.as_ref()).await
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let (Some(pool), Some(queue)) = (state.db.as_ref(), state.queue.as_ref()) else {
+                return;
+            };
+            if let Err(e) = sync(pool, queue, state.config.job_ttl_seconds).await {
+                warn!("Pinned result sync failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Mirror pinned jobs that have finished since the last pass and drop lapsed pins
+async fn sync(pool: &PgPool, queue: &QueueClient, job_ttl_seconds: u64) -> Result<(), ApiError> {
+    db::cleanup_expired_pins(pool).await?;
+
+    let job_ttl = ChronoDuration::seconds(job_ttl_seconds as i64);
+    for (job_id, pinned_at) in db::list_unmirrored_pins(pool, SYNC_BATCH).await? {
+        match queue.get_job_status(&job_id).await? {
+            Some(metadata) if is_finished(&metadata) => mirror(pool, queue, &job_id, &metadata).await?,
+            Some(_) => {}
+            // Expired before a pass saw it finish; nothing left to keep
+            None if Utc::now() - pinned_at > job_ttl => db::delete_pinned_result(pool, &job_id).await?,
+            None => {}
+        }
+    }
+    Ok(())
+}
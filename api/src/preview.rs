@@ -0,0 +1,357 @@
+//! Previews of expected and actual output in challenge test results.
+//!
+//! Previews are cut by characters, never bytes, so multi-byte UTF-8 can't
+//! split. Control characters and backslashes are escaped (`\n`, `\t`,
+//! `\u{1b}`, `\\`), so a missing trailing newline or a stray `\r` is visible.
+//! When output fails, [`first_mismatch`] finds where it departs from the
+//! expected output, and both previews are windowed around that point rather
+//! than always starting at the beginning.
+//!
+//! Failures also carry an [`OutputDiff`]: the lines around the first
+//! mismatch from both outputs. Those are stored as printed and rendered per
+//! request in one of the [`Whitespace`] modes.
+
+use crate::db::VerifyMode;
+use serde::{Deserialize, Serialize};
+
+/// Preview length when `CHALLENGE_PREVIEW_CHARS` isn't set
+pub const DEFAULT_PREVIEW_CHARS: usize = 50;
+/// Characters kept before a mismatch when the preview skips ahead to it
+const CONTEXT_CHARS: usize = 10;
+/// Lines shown before and after the first differing line in a diff
+const DIFF_CONTEXT_LINES: usize = 3;
+/// Characters kept of each line in a diff
+const DIFF_LINE_CHARS: usize = 200;
+
+/// Where actual output first departs from the expected output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mismatch {
+    /// 1-based line
+    pub line: usize,
+    /// 1-based character within the line (after trimming, in trimmed mode)
+    pub column: usize,
+    /// Character offsets the previews focus on
+    #[serde(skip)]
+    expected_at: usize,
+    #[serde(skip)]
+    actual_at: usize,
+}
+
+impl Mismatch {
+    /// Previews of `expected` and `actual` windowed around the mismatch
+    pub fn previews(&self, expected: &str, actual: &str, max_chars: usize) -> (String, String) {
+        (
+            preview_at(expected, self.expected_at, max_chars),
+            preview_at(actual, self.actual_at, max_chars),
+        )
+    }
+}
+
+/// How diff lines show whitespace and control characters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Whitespace {
+    /// As printed
+    Raw,
+    /// Control characters and backslashes escaped, as in previews
+    #[default]
+    Escaped,
+    /// Also spaces as `·` and tabs as `→`, with `↵` for each newline
+    Visible,
+}
+
+/// Lines around the first mismatch, from both outputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputDiff {
+    /// 1-based line where the outputs first differ
+    pub line: usize,
+    pub expected: Vec<DiffLine>,
+    pub actual: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffLine {
+    /// 1-based line number
+    pub number: usize,
+    pub text: String,
+    /// Whether the line ended in a newline
+    pub newline: bool,
+    /// Whether `text` was cut at `DIFF_LINE_CHARS` characters
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl OutputDiff {
+    /// The lines of `expected` and `actual` around `mismatch`
+    pub fn new(expected: &str, actual: &str, mismatch: &Mismatch) -> Self {
+        let first = mismatch.line.saturating_sub(DIFF_CONTEXT_LINES).max(1);
+        let last = mismatch.line + DIFF_CONTEXT_LINES;
+        Self {
+            line: mismatch.line,
+            expected: diff_lines(expected, first, last),
+            actual: diff_lines(actual, first, last),
+        }
+    }
+
+    /// Rewrite every line's text for `mode`; texts start out raw
+    pub fn render(&mut self, mode: Whitespace) {
+        for line in self.expected.iter_mut().chain(self.actual.iter_mut()) {
+            line.text = render_line(&line.text, line.newline && !line.truncated, mode);
+        }
+    }
+}
+
+/// Lines `first..=last` (1-based) of `s`, as printed
+fn diff_lines(s: &str, first: usize, last: usize) -> Vec<DiffLine> {
+    s.split_inclusive('\n')
+        .enumerate()
+        .skip(first - 1)
+        .take(last + 1 - first)
+        .map(|(i, line)| {
+            let newline = line.ends_with('\n');
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            let truncated = line.chars().nth(DIFF_LINE_CHARS).is_some();
+            DiffLine {
+                number: i + 1,
+                text: line.chars().take(DIFF_LINE_CHARS).collect(),
+                newline,
+                truncated,
+            }
+        })
+        .collect()
+}
+
+fn render_line(text: &str, newline: bool, mode: Whitespace) -> String {
+    match mode {
+        Whitespace::Raw => text.to_string(),
+        Whitespace::Escaped => {
+            let mut out = String::with_capacity(text.len());
+            for c in text.chars() {
+                push_escaped(&mut out, c);
+            }
+            out
+        }
+        Whitespace::Visible => {
+            let mut out = String::with_capacity(text.len());
+            for c in text.chars() {
+                match c {
+                    ' ' => out.push('·'),
+                    '\t' => out.push('→'),
+                    c => push_escaped(&mut out, c),
+                }
+            }
+            if newline {
+                out.push('↵');
+            }
+            out
+        }
+    }
+}
+
+/// Up to `max_chars` characters from the start of `s`
+pub fn preview(s: &str, max_chars: usize) -> String {
+    preview_at(s, 0, max_chars)
+}
+
+/// Up to `max_chars` characters of `s` showing the character at `focus`,
+/// with `...` wherever something was cut
+fn preview_at(s: &str, focus: usize, max_chars: usize) -> String {
+    let max_chars = max_chars.max(1);
+    let start = if focus < max_chars { 0 } else { focus.saturating_sub(CONTEXT_CHARS) };
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push_str("...");
+    }
+    let mut chars = s.chars().skip(start);
+    for c in chars.by_ref().take(max_chars) {
+        push_escaped(&mut out, c);
+    }
+    if chars.next().is_some() {
+        out.push_str("...");
+    }
+    out
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\\' => out.push_str("\\\\"),
+        c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        c => out.push(c),
+    }
+}
+
+/// Where `actual` first differs from `expected` as `mode` compares them.
+/// `None` when they match, and for sorted mode, where lines have no fixed
+/// position to point at.
+pub fn first_mismatch(actual: &str, expected: &str, mode: &VerifyMode) -> Option<Mismatch> {
+    match mode {
+        VerifyMode::Exact => exact_mismatch(actual, expected),
+        VerifyMode::Trimmed => trimmed_mismatch(actual, expected),
+        VerifyMode::Sorted => None,
+    }
+}
+
+fn exact_mismatch(actual: &str, expected: &str) -> Option<Mismatch> {
+    let (mut line, mut column) = (1, 1);
+    let mut expected_chars = expected.chars();
+    let mut actual_chars = actual.chars();
+    let mut at = 0;
+    loop {
+        match (expected_chars.next(), actual_chars.next()) {
+            (None, None) => return None,
+            (e, a) if e == a => {
+                if e == Some('\n') {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+                at += 1;
+            }
+            _ => {
+                return Some(Mismatch {
+                    line,
+                    column,
+                    expected_at: at,
+                    actual_at: at,
+                })
+            }
+        }
+    }
+}
+
+fn trimmed_mismatch(actual: &str, expected: &str) -> Option<Mismatch> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut index = 0;
+    loop {
+        let (e, a) = (expected_lines.next(), actual_lines.next());
+        if e.is_none() && a.is_none() {
+            return None;
+        }
+        let (e_raw, a_raw) = (e.unwrap_or(""), a.unwrap_or(""));
+        let (e_trimmed, a_trimmed) = (e_raw.trim(), a_raw.trim());
+        if e.is_none() || a.is_none() || e_trimmed != a_trimmed {
+            let column = e_trimmed
+                .chars()
+                .zip(a_trimmed.chars())
+                .take_while(|(x, y)| x == y)
+                .count();
+            let indent = |raw: &str| raw.chars().take_while(|c| c.is_whitespace()).count();
+            return Some(Mismatch {
+                line: index + 1,
+                column: column + 1,
+                expected_at: line_start(expected, index) + indent(e_raw) + column,
+                actual_at: line_start(actual, index) + indent(a_raw) + column,
+            });
+        }
+        index += 1;
+    }
+}
+
+/// Character offset where line `index` (0-based) starts, or the end of `s`
+fn line_start(s: &str, index: usize) -> usize {
+    if index == 0 {
+        return 0;
+    }
+    s.chars()
+        .enumerate()
+        .filter(|(_, c)| *c == '\n')
+        .nth(index - 1)
+        .map_or_else(|| s.chars().count(), |(i, _)| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(mismatch: Mismatch) -> (usize, usize, usize, usize) {
+        (mismatch.line, mismatch.column, mismatch.expected_at, mismatch.actual_at)
+    }
+
+    #[test]
+    fn preview_cuts_multibyte_text_by_characters() {
+        assert_eq!(preview("héllo wörld", 5), "héllo...");
+        assert_eq!(preview("日本語テキスト", 2), "日本...");
+        assert_eq!(preview("日本語", 3), "日本語");
+    }
+
+    #[test]
+    fn preview_keeps_emoji_at_the_cut_whole() {
+        assert_eq!(preview("ab😀cd", 3), "ab😀...");
+        assert_eq!(preview("ab😀", 3), "ab😀");
+        assert_eq!(preview("ab😀", 2), "ab...");
+    }
+
+    #[test]
+    fn preview_escapes_without_counting_the_escape() {
+        assert_eq!(preview("a\n😀\t", 3), "a\\n😀...");
+        assert_eq!(preview("\\\u{1b}", 5), "\\\\\\u{1b}");
+    }
+
+    #[test]
+    fn preview_at_windows_around_the_focus() {
+        let s = format!("{}X{}", "é".repeat(20), "ü".repeat(20));
+        assert_eq!(preview_at(&s, 20, 11), format!("...{}X...", "é".repeat(10)));
+        assert_eq!(preview_at(&s, 30, 25), format!("...X{}", "ü".repeat(20)));
+        // A focus inside the first window doesn't skip ahead
+        assert_eq!(preview_at(&s, 4, 5), format!("{}...", "é".repeat(5)));
+    }
+
+    #[test]
+    fn preview_at_handles_a_focus_past_the_end() {
+        assert_eq!(preview_at("😀😀😀", 20, 2), "...");
+        assert_eq!(preview_at("", 0, 5), "");
+    }
+
+    #[test]
+    fn exact_mismatch_counts_characters_not_bytes() {
+        assert_eq!(exact_mismatch("héllo", "hello").map(at), Some((1, 2, 1, 1)));
+        assert_eq!(exact_mismatch("😀😀y", "😀😀x").map(at), Some((1, 3, 2, 2)));
+    }
+
+    #[test]
+    fn exact_mismatch_tracks_lines() {
+        assert_eq!(exact_mismatch("ab\nce", "ab\ncd").map(at), Some((2, 2, 4, 4)));
+        assert_eq!(exact_mismatch("ab", "ab\n").map(at), Some((1, 3, 2, 2)));
+        assert_eq!(exact_mismatch("ab\n", "ab").map(at), Some((1, 3, 2, 2)));
+    }
+
+    #[test]
+    fn exact_mismatch_is_none_for_equal_output() {
+        assert_eq!(exact_mismatch("日本\n😀", "日本\n😀"), None);
+        assert_eq!(exact_mismatch("", ""), None);
+    }
+
+    #[test]
+    fn trimmed_mismatch_ignores_surrounding_whitespace() {
+        assert_eq!(trimmed_mismatch("foo  \n\tbar", "  foo\nbar"), None);
+        assert_eq!(trimmed_mismatch("a  \n", "a"), None);
+    }
+
+    #[test]
+    fn trimmed_mismatch_offsets_skip_indentation() {
+        assert_eq!(trimmed_mismatch("foo  \nbaz", "  foo\nbar").map(at), Some((2, 3, 8, 8)));
+        assert_eq!(trimmed_mismatch("x\n\tñc", "x\n  ñb").map(at), Some((2, 2, 5, 4)));
+    }
+
+    #[test]
+    fn trimmed_mismatch_points_at_a_missing_line() {
+        assert_eq!(trimmed_mismatch("a", "a\nb").map(at), Some((2, 1, 2, 1)));
+        assert_eq!(trimmed_mismatch("😀\nb", "😀").map(at), Some((2, 1, 1, 2)));
+    }
+
+    #[test]
+    fn previews_focus_on_the_mismatch() {
+        let expected = format!("{}ab", "é".repeat(30));
+        let actual = format!("{}ac", "é".repeat(30));
+        let mismatch = first_mismatch(&actual, &expected, &VerifyMode::Exact).unwrap();
+        let (e, a) = mismatch.previews(&expected, &actual, 12);
+        assert_eq!(e, format!("...{}ab", "é".repeat(9)));
+        assert_eq!(a, format!("...{}ac", "é".repeat(9)));
+    }
+}
@@ -0,0 +1,253 @@
+//! Signed challenge content, so edits made straight in the database show up.
+//!
+//! With `CHALLENGE_SIGNING_KEY` set, importing a challenge (seeding, at API
+//! startup) HMAC-signs the whole challenge row except its counters (solve
+//! count, points, stars): test cases, verify mode, baselines, env vars,
+//! fixtures, sandbox options, network, memory limit, binary checks,
+//! languages, scoring, hints, the availability window and the descriptive
+//! fields. The signature is stored with who imported it, when, and the ID of
+//! the key (the first 16 hex digits of its SHA-256). Env vars are signed in
+//! plaintext, so sealing them or rotating `CHALLENGE_ENV_KEY` doesn't
+//! invalidate anything. An admin changing the window re-signs the challenge,
+//! after checking the signature it had.
+//!
+//! While a key is set, submissions are only judged against a challenge whose
+//! signature is valid under that key: changed content, a missing signature
+//! and one made with another key are all refused. `GET /admin/challenges`
+//! shows every challenge's provenance and signature status.
+
+use crate::auth::AdminUser;
+use crate::config::Config;
+use crate::db::{self, Challenge, ChallengeProvenance};
+use crate::error::ApiError;
+use crate::secrets::EnvVarCipher;
+use crate::AppState;
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(Clone)]
+pub struct ChallengeSigner {
+    key: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Valid,
+    /// The content changed since it was signed
+    Invalid,
+    /// Imported before signing was enabled
+    Unsigned,
+    /// Signed with a key other than the current one
+    OtherKey,
+    /// No `CHALLENGE_SIGNING_KEY` to check against
+    Unverifiable,
+}
+
+impl ChallengeSigner {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            key: config.challenge_signing_key.as_ref().map(|key| key.as_bytes().to_vec()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Identifies the key without revealing it
+    pub fn key_id(&self) -> Option<String> {
+        self.key
+            .as_ref()
+            .map(|key| hex::encode(Sha256::digest(key))[..16].to_string())
+    }
+
+    /// Hex HMAC of the challenge's signed content; `None` without a key
+    pub fn sign(&self, cipher: &EnvVarCipher, challenge: &Challenge) -> Result<Option<String>, ApiError> {
+        let Some(key) = &self.key else {
+            return Ok(None);
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(&signed_content(cipher, challenge)?);
+        Ok(Some(hex::encode(mac.finalize().into_bytes())))
+    }
+
+    pub fn verify(
+        &self,
+        cipher: &EnvVarCipher,
+        challenge: &Challenge,
+        provenance: Option<&ChallengeProvenance>,
+    ) -> Result<SignatureStatus, ApiError> {
+        let Some((signature, key_id)) = provenance.and_then(|p| p.signature.as_ref().zip(p.signing_key_id.as_ref()))
+        else {
+            return Ok(SignatureStatus::Unsigned);
+        };
+        let Some(current_key_id) = self.key_id() else {
+            return Ok(SignatureStatus::Unverifiable);
+        };
+        if *key_id != current_key_id {
+            return Ok(SignatureStatus::OtherKey);
+        }
+
+        let Ok(signature) = hex::decode(signature) else {
+            return Ok(SignatureStatus::Invalid);
+        };
+        let key = self.key.as_ref().expect("key_id implies a key");
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(&signed_content(cipher, challenge)?);
+        Ok(match mac.verify_slice(&signature) {
+            Ok(()) => SignatureStatus::Valid,
+            Err(_) => SignatureStatus::Invalid,
+        })
+    }
+}
+
+/// The signed fields as canonical JSON (object keys sorted at every level,
+/// whatever order Postgres hands JSONB back in)
+fn signed_content(cipher: &EnvVarCipher, challenge: &Challenge) -> Result<Vec<u8>, ApiError> {
+    let env_vars = challenge
+        .env_vars
+        .as_ref()
+        .map(|stored| cipher.open(stored))
+        .transpose()?;
+    let content = json!({
+        "id": challenge.id,
+        "name": challenge.name,
+        "description": challenge.description,
+        "category": challenge.category,
+        "difficulty": challenge.difficulty,
+        "input_spec": challenge.input_spec,
+        "output_spec": challenge.output_spec,
+        "test_cases": challenge.test_cases,
+        "verify_mode": challenge.verify_mode,
+        "is_active": challenge.is_active,
+        "network_enabled": challenge.network_enabled,
+        "baselines": challenge.baselines,
+        "env_vars": env_vars,
+        "fixtures": challenge.fixtures,
+        "sandbox_options": challenge.sandbox_options,
+        "memory_limit_mb": challenge.memory_limit_mb,
+        "binary_checks": challenge.binary_checks,
+        "languages": challenge.languages,
+        "scoring": challenge.scoring,
+        "writeups_visible_at": challenge.writeups_visible_at,
+        "hints": challenge.hints,
+        "starts_at": challenge.starts_at,
+        "ends_at": challenge.ends_at,
+        "run_all_tests": challenge.run_all_tests,
+    });
+    serde_json::to_vec(&canonical(&content)).map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let mut sorted = Map::new();
+            for key in keys {
+                sorted.insert(key.clone(), canonical(&map[key]));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonical).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Sign `challenge` as just imported (or edited) by `imported_by`
+pub async fn record_import(
+    pool: &PgPool,
+    signer: &ChallengeSigner,
+    cipher: &EnvVarCipher,
+    challenge: &Challenge,
+    imported_by: &str,
+) -> Result<(), ApiError> {
+    let signature = signer.sign(cipher, challenge)?;
+    let key_id = signature.as_ref().and_then(|_| signer.key_id());
+    db::set_challenge_provenance(pool, &challenge.id, imported_by, signature.as_deref(), key_id.as_deref()).await
+}
+
+/// Refuse to judge against content without a valid signature under the
+/// current key
+pub async fn check_challenge(state: &AppState, pool: &PgPool, challenge: &Challenge) -> Result<(), ApiError> {
+    if !state.challenge_signer.is_enabled() {
+        return Ok(());
+    }
+    let provenance = db::get_challenge_provenance(pool, &challenge.id).await?;
+    let status = state
+        .challenge_signer
+        .verify(&state.env_cipher, challenge, provenance.as_ref())?;
+    let problem = match status {
+        SignatureStatus::Valid => return Ok(()),
+        SignatureStatus::Invalid => "does not match its signature",
+        SignatureStatus::Unsigned => "is not signed",
+        SignatureStatus::OtherKey => "is signed with another key",
+        SignatureStatus::Unverifiable => "can't be verified",
+    };
+    error!(challenge_id = %challenge.id, "Challenge content {}", problem);
+    Err(ApiError::Internal(format!(
+        "Challenge '{}' failed its integrity check",
+        challenge.id
+    )))
+}
+
+// ============ Handlers ============
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeProvenanceEntry {
+    pub id: String,
+    pub name: String,
+    pub is_active: bool,
+    pub imported_by: Option<String>,
+    pub imported_at: Option<DateTime<Utc>>,
+    pub signing_key_id: Option<String>,
+    pub signature_status: SignatureStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeProvenanceResponse {
+    /// ID of `CHALLENGE_SIGNING_KEY`, if set
+    pub signing_key_id: Option<String>,
+    pub challenges: Vec<ChallengeProvenanceEntry>,
+}
+
+pub async fn list_challenge_provenance(
+    State(state): State<Arc<AppState>>,
+    AdminUser(_admin): AdminUser,
+) -> Result<Json<ChallengeProvenanceResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let (challenges, provenance) =
+        tokio::try_join!(db::list_challenges(pool, false), db::list_challenge_provenance(pool))?;
+
+    let mut entries = Vec::with_capacity(challenges.len());
+    for challenge in challenges {
+        let record = provenance.iter().find(|p| p.challenge_id == challenge.id);
+        let signature_status = state.challenge_signer.verify(&state.env_cipher, &challenge, record)?;
+        entries.push(ChallengeProvenanceEntry {
+            imported_by: record.and_then(|p| p.imported_by.clone()),
+            imported_at: record.and_then(|p| p.imported_at),
+            signing_key_id: record.and_then(|p| p.signing_key_id.clone()),
+            signature_status,
+            id: challenge.id,
+            name: challenge.name,
+            is_active: challenge.is_active,
+        });
+    }
+
+    Ok(Json(ChallengeProvenanceResponse {
+        signing_key_id: state.challenge_signer.key_id(),
+        challenges: entries,
+    }))
+}
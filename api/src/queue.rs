@@ -1,22 +1,31 @@
+use crate::build_options::BuildOptions;
+use crate::config::Config;
+use crate::db::{self, BinaryMetadata, RunVisibility, SaveRunRequest};
 use crate::error::ApiError;
+use crate::local_queue::LocalQueue;
 use crate::sandbox::ExecutionResult;
-use async_nats::jetstream::{self, kv::Store, stream::Stream};
+use async_nats::jetstream;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use futures::TryStreamExt;
+use ctf_common::compile_protocol::{Diagnostic, ResourceUsage};
+use ctf_common::diagnostics::ExecutionMode;
+use ctf_common::fixtures::FixtureSpec;
+use ctf_common::sandbox_options::SandboxOptions;
+use ctf_common::topology::{
+    Topology, TopologyConfig, COMPILES_SUBJECT, COMPILE_WORKER_CONSUMER, JOBS_SUBJECT, SHARED_JOBS_SUBJECT,
+    SHARED_WORKER_CONSUMER, WORKER_CONSUMER,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
 use uuid::Uuid;
 
-const JOBS_STREAM: &str = "JOBS";
-const JOBS_KV: &str = "jobs";
-const RESULTS_KV: &str = "results";
-const COMPILES_STREAM: &str = "COMPILES";
-const COMPILES_KV: &str = "compiles";
-const BINARIES_KV: &str = "binaries";
-const COMPILE_CACHE_KV: &str = "compile_cache";
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
     pub id: Uuid,
@@ -27,11 +36,64 @@ pub struct Job {
     pub created_at: DateTime<Utc>,
     #[serde(default)]
     pub benchmark_id: Option<String>,
+    /// The benchmark's version at submission, see `BenchmarkDef::version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark_version: Option<u32>,
     // Challenge-specific execution options
     #[serde(default)]
     pub network_enabled: bool,
     #[serde(default)]
     pub env_vars: std::collections::HashMap<String, String>,
+    /// argv after the program name, checked by `limits::check_args`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Files to collect from `/work/out` afterwards, see `ctf_common::outputs`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+    /// Keep the core if the binary crashes; only for logged-in submitters
+    #[serde(default)]
+    pub core_dump: bool,
+    /// `diagnostics` runs under valgrind/ASan and counts no instructions
+    #[serde(default, skip_serializing_if = "ExecutionMode::is_normal")]
+    pub mode: ExecutionMode,
+    // Files bind-mounted into the sandbox (filesystem-flag challenges)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixtures: Option<FixtureSpec>,
+    // Privilege-escalation challenges only; signed by NatsQueue::submit_job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_options: Option<SandboxOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_signature: Option<String>,
+    /// Wall-clock limit; `None` means `TIMEOUT_SEC`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Container memory limit; `None` means `MEMORY_LIMIT_MB`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u32>,
+    /// Who may see the resulting run; owned by `user_id`
+    #[serde(default)]
+    pub visibility: RunVisibility,
+    /// Set for challenge test runs; workers scrub env values from their stderr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge_id: Option<String>,
+}
+
+impl Job {
+    /// Whether an external worker may run this job: nothing secret goes in
+    /// (challenge flags, env, fixtures, privileged options, private runs)
+    /// and nothing it reports could place on a leaderboard
+    pub fn is_shareable(&self) -> bool {
+        self.challenge_id.is_none()
+            && self.benchmark_id.is_none()
+            && self.env_vars.is_empty()
+            && self.fixtures.is_none()
+            && self.sandbox_options.is_none()
+            && !self.network_enabled
+            && self.outputs.is_empty()
+            && !self.core_dump
+            && self.mode.is_normal()
+            && self.visibility == RunVisibility::Public
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -50,149 +112,112 @@ pub struct JobMetadata {
     pub started_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
     pub error: Option<String>,
+    // Enough of the job to rebuild its run if the worker never persisted it
+    #[serde(default)]
+    pub binary_id: Option<String>,
+    #[serde(default)]
+    pub instruction_limit: Option<u64>,
+    #[serde(default)]
+    pub benchmark_id: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+    #[serde(default)]
+    pub visibility: RunVisibility,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "ExecutionMode::is_normal")]
+    pub mode: ExecutionMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark_version: Option<u32>,
+    /// Published for external workers too (see workers.rs)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shared: bool,
+    /// Set by the in-house worker that took a shared job
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub in_house: bool,
 }
 
-// ============ Compile Types ============
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum Language {
-    // Tier 1: Native compilation
-    C,
-    Cpp,
-    Rust,
-    Go,
-    Zig,
-    Asm,
-    Nim,
-    Pascal,
-    Ocaml,
-    Swift,
-    Haskell,
-    Csharp,
-    // Tier 2: JVM -> Native (GraalVM)
-    Java,
-    Kotlin,
-    Scala,
-    Clojure,
-    // Tier 3: Scripting -> Bundle
-    Python,
-    Javascript,
-    Typescript,
-    Bun,
-    Deno,
-    Node,
-    Lua,
-    Perl,
-    Php,
-    Tcl,
-    // Tier 4: Special runtimes
-    Erlang,
-    Elixir,
-    Racket,
-    Wasm,
-}
-
-impl Language {
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s.to_lowercase().as_str() {
-            "c" => Some(Language::C),
-            "cpp" | "c++" => Some(Language::Cpp),
-            "rust" => Some(Language::Rust),
-            "go" | "golang" => Some(Language::Go),
-            "zig" => Some(Language::Zig),
-            "asm" | "assembly" => Some(Language::Asm),
-            "nim" => Some(Language::Nim),
-            "pascal" => Some(Language::Pascal),
-            "ocaml" => Some(Language::Ocaml),
-            "swift" => Some(Language::Swift),
-            "haskell" => Some(Language::Haskell),
-            "csharp" | "c#" => Some(Language::Csharp),
-            "java" => Some(Language::Java),
-            "kotlin" => Some(Language::Kotlin),
-            "scala" => Some(Language::Scala),
-            "clojure" => Some(Language::Clojure),
-            "python" => Some(Language::Python),
-            "javascript" | "js" => Some(Language::Javascript),
-            "typescript" | "ts" => Some(Language::Typescript),
-            "bun" => Some(Language::Bun),
-            "deno" => Some(Language::Deno),
-            "node" | "nodejs" => Some(Language::Node),
-            "lua" => Some(Language::Lua),
-            "perl" => Some(Language::Perl),
-            "php" => Some(Language::Php),
-            "tcl" => Some(Language::Tcl),
-            "erlang" => Some(Language::Erlang),
-            "elixir" => Some(Language::Elixir),
-            "racket" => Some(Language::Racket),
-            "wasm" | "wat" => Some(Language::Wasm),
-            _ => None,
-        }
-    }
-
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Language::C => "c",
-            Language::Cpp => "cpp",
-            Language::Rust => "rust",
-            Language::Go => "go",
-            Language::Zig => "zig",
-            Language::Asm => "asm",
-            Language::Nim => "nim",
-            Language::Pascal => "pascal",
-            Language::Ocaml => "ocaml",
-            Language::Swift => "swift",
-            Language::Haskell => "haskell",
-            Language::Csharp => "csharp",
-            Language::Java => "java",
-            Language::Kotlin => "kotlin",
-            Language::Scala => "scala",
-            Language::Clojure => "clojure",
-            Language::Python => "python",
-            Language::Javascript => "javascript",
-            Language::Typescript => "typescript",
-            Language::Bun => "bun",
-            Language::Deno => "deno",
-            Language::Node => "node",
-            Language::Lua => "lua",
-            Language::Perl => "perl",
-            Language::Php => "php",
-            Language::Tcl => "tcl",
-            Language::Erlang => "erlang",
-            Language::Elixir => "elixir",
-            Language::Racket => "racket",
-            Language::Wasm => "wasm",
+impl JobMetadata {
+    pub fn pending(job: &Job) -> Self {
+        Self {
+            status: JobStatus::Pending,
+            created_at: job.created_at,
+            started_at: None,
+            completed_at: None,
+            error: None,
+            binary_id: Some(job.binary_id.clone()),
+            instruction_limit: Some(job.instruction_limit),
+            benchmark_id: job.benchmark_id.clone(),
+            user_id: job.user_id,
+            visibility: job.visibility,
+            args: job.args.clone(),
+            mode: job.mode,
+            benchmark_version: job.benchmark_version,
+            shared: false,
+            in_house: false,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
-#[serde(rename_all = "lowercase")]
-pub enum Optimization {
-    Debug,
-    #[default]
-    Release,
-    Size,
+/// Build the `runs` row for an executed job.
+pub(crate) fn run_request(
+    job: &Job,
+    binary_size: Option<usize>,
+    metadata: Option<&BinaryMetadata>,
+    result: &ExecutionResult,
+    started_at: Option<DateTime<Utc>>,
+    completed_at: DateTime<Utc>,
+) -> SaveRunRequest {
+    SaveRunRequest {
+        job_id: job.id,
+        binary_id: job.binary_id.clone(),
+        binary_size: binary_size.map(|s| s as i64),
+        source_code: None,
+        language: metadata.and_then(|m| m.language.clone()),
+        optimization: metadata.and_then(|m| m.optimization.clone()),
+        compiler_version: metadata.and_then(|m| m.compiler_version.clone()),
+        toolchain: metadata.and_then(|m| m.toolchain.clone()),
+        compile_time_ms: None,
+        compile_cached: None,
+        instructions: result.instructions as i64,
+        memory_peak_kb: Some(result.memory_peak_kb as i64),
+        memory_rss_kb: Some(result.memory_rss_kb as i64),
+        memory_hwm_kb: Some(result.memory_hwm_kb as i64),
+        memory_data_kb: Some(result.memory_data_kb as i64),
+        memory_stack_kb: Some(result.memory_stack_kb as i64),
+        io_read_bytes: Some(result.io_read_bytes as i64),
+        io_write_bytes: Some(result.io_write_bytes as i64),
+        guest_mmap_bytes: Some(result.guest_mmap_bytes as i64),
+        guest_mmap_peak: Some(result.guest_mmap_peak as i64),
+        guest_heap_bytes: Some(result.guest_heap_bytes as i64),
+        limit_reached: result.limit_reached,
+        exit_code: Some(result.exit_code),
+        execution_time_ms: Some(result.execution_time_ms as i64),
+        instruction_limit: Some(job.instruction_limit as i64),
+        syscalls: Some(result.syscalls as i64),
+        syscall_breakdown: Some(serde_json::to_value(&result.syscall_breakdown).unwrap_or_default()),
+        thread_breakdown: Some(serde_json::to_value(&result.threads).unwrap_or_default()),
+        limit_reached_reason: result.limit_reached_reason.map(|r| r.as_str().to_string()),
+        memory_limit_mb: result.memory_limit_mb.map(|m| m as i32),
+        sandbox_image: result.sandbox_image.clone(),
+        args: job.args.clone(),
+        core_dump_id: result.core_dump.as_ref().and_then(|c| c.binary_id.clone()),
+        mode: (!job.mode.is_normal()).then(|| job.mode.as_str().to_string()),
+        stdout: Some(result.stdout.clone()),
+        stderr: Some(result.stderr.clone()),
+        benchmark_id: job.benchmark_id.clone(),
+        benchmark_version: job.benchmark_version.map(|v| v as i32),
+        started_at,
+        completed_at: Some(completed_at),
+        user_id: job.user_id,
+        visibility: job.visibility,
+        worker_id: None,
+    }
 }
 
-impl Optimization {
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s.to_lowercase().as_str() {
-            "debug" => Some(Optimization::Debug),
-            "release" => Some(Optimization::Release),
-            "size" => Some(Optimization::Size),
-            _ => None,
-        }
-    }
+// ============ Compile Types ============
 
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Optimization::Debug => "debug",
-            Optimization::Release => "release",
-            Optimization::Size => "size",
-        }
-    }
-}
+pub use ctf_common::language::{Language, Optimization};
 
 use std::collections::HashMap;
 
@@ -205,9 +230,39 @@ pub struct CompileJob {
     pub optimization: Optimization,
     #[serde(default)]
     pub flags: HashMap<String, String>,
+    /// Allowlisted compiler image to build with instead of `COMPILER_IMAGE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiler_image: Option<String>,
+    /// Also produce assembly, served by `GET /compile/:job_id/asm`
+    #[serde(default)]
+    pub emit_asm: bool,
+    /// Linker flags and script, validated by `BuildOptions::validate`
+    #[serde(default, skip_serializing_if = "BuildOptions::is_empty")]
+    pub build_options: BuildOptions,
+    /// Non-default toolchain (see toolchains.rs); `None` builds with the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// Build even if an identical job failed moments ago (for flaky infra)
+    #[serde(default)]
+    pub retry_failed: bool,
     pub created_at: DateTime<Utc>,
 }
 
+impl CompileJob {
+    /// Key into the compile cache; the compile worker computes the same
+    pub fn cache_key(&self) -> String {
+        compute_cache_key(
+            &self.source_code,
+            self.language,
+            self.optimization,
+            &self.flags,
+            self.compiler_image.as_deref(),
+            &self.build_options,
+            self.toolchain.as_deref(),
+        )
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CompileStatus {
@@ -233,9 +288,67 @@ pub struct CompileResult {
     pub binary_size: usize,
     pub compile_time_ms: u64,
     pub cached: bool,
+    /// Set by compile workers with persistent build caches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_cache: Option<BuildCacheUsage>,
+    /// Warnings the compiler image reported
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    /// CPU time and peak memory of the build, with the ceilings it had
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+}
+
+/// How a build used the compile worker's persistent toolchain cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildCacheUsage {
+    /// `sccache`, `go` or `jvm`
+    pub kind: String,
+    /// The cache had entries before this build
+    pub warm: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hits: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub misses: Option<u64>,
+    pub size_bytes: u64,
+}
+
+/// The worker pools as their JetStream consumers see them; only external
+/// workers also register with the API (`GET /admin/workers`)
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetStatus {
+    /// `None` until a worker has created its consumer
+    pub workers: Option<ConsumerStats>,
+    /// Shareable jobs, taken by in-house and external workers alike
+    pub shared_workers: Option<ConsumerStats>,
+    pub compile_workers: Option<ConsumerStats>,
+    /// Runs waiting in the spool for the API to take them
+    pub spooled_runs: Option<u64>,
 }
 
-fn compute_cache_key(source: &str, language: Language, optimization: Optimization, flags: &HashMap<String, String>) -> String {
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsumerStats {
+    /// Jobs not yet handed to a worker
+    pub pending: u64,
+    /// Handed out and not yet acked: jobs workers are on
+    pub in_flight: usize,
+    /// Open pull requests, roughly the number of idle workers
+    pub waiting_pulls: usize,
+    /// In-flight jobs delivered more than once
+    pub redelivered: usize,
+    /// When a worker was last handed a job
+    pub last_delivery: Option<DateTime<Utc>>,
+}
+
+fn compute_cache_key(
+    source: &str,
+    language: Language,
+    optimization: Optimization,
+    flags: &HashMap<String, String>,
+    compiler_image: Option<&str>,
+    build_options: &BuildOptions,
+    toolchain: Option<&str>,
+) -> String {
     let mut hasher = Sha256::new();
     hasher.update(source.as_bytes());
     hasher.update(language.as_str().as_bytes());
@@ -249,205 +362,390 @@ fn compute_cache_key(source: &str, language: Language, optimization: Optimizatio
         hasher.update(v.as_bytes());
         hasher.update(b";");
     }
+    // Only pinned builds hash the image, so existing cache keys stay valid
+    if let Some(image) = compiler_image {
+        hasher.update(b"image=");
+        hasher.update(image.as_bytes());
+    }
+    if !build_options.is_empty() {
+        hasher.update(b"build_options=");
+        hasher.update(serde_json::to_vec(build_options).unwrap_or_default());
+    }
+    if let Some(toolchain) = toolchain {
+        hasher.update(b"toolchain=");
+        hasher.update(toolchain.as_bytes());
+    }
     hex::encode(hasher.finalize())
 }
 
-pub struct QueueClient {
+// ============ NATS Resilience ============
+
+/// Consecutive queue failures before the circuit breaker opens
+const BREAKER_FAILURE_THRESHOLD: u32 = 3;
+/// Delay before the first recovery attempt; doubles per failed attempt
+const BREAKER_BASE_COOLDOWN: Duration = Duration::from_secs(1);
+const BREAKER_MAX_COOLDOWN: Duration = Duration::from_secs(30);
+/// Upper bound for the client's reconnect backoff
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Fails queue calls fast while NATS is unreachable instead of letting every
+/// request wait out the NATS request timeout. A background task re-creates
+/// the topology once the cooldown elapses and closes the breaker on success.
+struct CircuitBreaker {
+    failures: AtomicU32,
+    state: std::sync::Mutex<BreakerState>,
+}
+
+struct BreakerState {
+    open: bool,
+    cooldown: Duration,
+    retry_at: Instant,
+}
+
+impl CircuitBreaker {
+    fn new() -> Self {
+        Self {
+            failures: AtomicU32::new(0),
+            state: std::sync::Mutex::new(BreakerState {
+                open: false,
+                cooldown: BREAKER_BASE_COOLDOWN,
+                retry_at: Instant::now(),
+            }),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.state.lock().unwrap().open
+    }
+
+    fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        if state.open {
+            info!("NATS queue recovered, closing circuit breaker");
+        }
+        state.open = false;
+        state.cooldown = BREAKER_BASE_COOLDOWN;
+    }
+
+    fn record_failure(&self) {
+        if self.failures.fetch_add(1, Ordering::Relaxed) + 1 >= BREAKER_FAILURE_THRESHOLD {
+            self.trip();
+        }
+    }
+
+    fn trip(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.open {
+            warn!("NATS queue unavailable, opening circuit breaker");
+            state.open = true;
+            state.retry_at = Instant::now() + state.cooldown;
+        }
+    }
+
+    /// Allow the next recovery attempt immediately (e.g. after a reconnect).
+    fn retry_now(&self) {
+        self.state.lock().unwrap().retry_at = Instant::now();
+    }
+
+    fn recovery_due(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.open && Instant::now() >= state.retry_at
+    }
+
+    fn recovery_failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.cooldown = (state.cooldown * 2).min(BREAKER_MAX_COOLDOWN);
+        state.retry_at = Instant::now() + state.cooldown;
+    }
+}
+
+fn unavailable() -> ApiError {
+    ApiError::QueueError("Queue unavailable: reconnecting to NATS".to_string())
+}
+
+pub struct NatsQueue {
+    client: async_nats::Client,
     jetstream: jetstream::Context,
-    jobs_stream: Arc<RwLock<Stream>>,
-    jobs_kv: Store,
-    results_kv: Store,
-    compiles_stream: Arc<RwLock<Stream>>,
-    compiles_kv: Store,
-    binaries_kv: Store,
-    compile_cache_kv: Store,
+    topology_config: TopologyConfig,
+    topology: RwLock<Arc<Topology>>,
+    breaker: Arc<CircuitBreaker>,
+    /// Serializes topology re-creation; holds the time of the last success
+    last_recovery: Mutex<Option<Instant>>,
+    /// Signs sandbox options so workers only honour them from the API
+    sandbox_options_secret: Option<String>,
+    /// `EXTERNAL_WORKERS`: publish shareable jobs where external workers can take them
+    share_jobs: bool,
+    /// Where shared jobs are recorded for workers.rs to check reports
+    /// against; nothing is shared without it
+    pool: Option<PgPool>,
 }
 
-impl QueueClient {
-    pub async fn connect(nats_url: &str, job_ttl_seconds: u64, binary_ttl_seconds: u64) -> Result<Self, ApiError> {
+impl NatsQueue {
+    async fn connect(config: &Config, pool: Option<PgPool>) -> Result<Arc<Self>, ApiError> {
+        let breaker = Arc::new(CircuitBreaker::new());
+        let events = breaker.clone();
+
         // Use longer request timeout for large binary operations
         let nats_options = async_nats::ConnectOptions::new()
-            .request_timeout(Some(std::time::Duration::from_secs(120)));
+            .request_timeout(Some(std::time::Duration::from_secs(120)))
+            .max_reconnects(None)
+            .reconnect_delay_callback(|attempts| {
+                (Duration::from_millis(100) * 2u32.saturating_pow(attempts.min(16) as u32)).min(RECONNECT_MAX_DELAY)
+            })
+            .event_callback(move |event| {
+                let breaker = events.clone();
+                async move {
+                    match event {
+                        async_nats::Event::Disconnected => {
+                            warn!("Disconnected from NATS, reconnecting");
+                            breaker.trip();
+                        }
+                        async_nats::Event::Connected => {
+                            // Streams may be gone if the server restarted without persistence
+                            info!("Reconnected to NATS");
+                            breaker.retry_now();
+                        }
+                        other => warn!("NATS event: {}", other),
+                    }
+                }
+            });
 
-        let client = nats_options.connect(nats_url)
+        let client = nats_options.connect(&config.nats_url)
             .await
             .map_err(|e| ApiError::QueueError(format!("Failed to connect to NATS: {}", e)))?;
 
-        let jetstream = jetstream::new(client);
-
-        // Create or get the JOBS stream (work queue pattern)
-        let jobs_stream = jetstream
-            .get_or_create_stream(jetstream::stream::Config {
-                name: JOBS_STREAM.to_string(),
-                subjects: vec!["jobs.submit".to_string()],
-                retention: jetstream::stream::RetentionPolicy::WorkQueue,
-                max_age: Duration::from_secs(job_ttl_seconds),
-                storage: jetstream::stream::StorageType::File,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create JOBS stream: {}", e)))?;
-
-        // Create or get the jobs KV bucket for status tracking
-        let jobs_kv = jetstream
-            .create_key_value(jetstream::kv::Config {
-                bucket: JOBS_KV.to_string(),
-                max_age: Duration::from_secs(job_ttl_seconds),
-                storage: jetstream::stream::StorageType::File,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create jobs KV: {}", e)))?;
-
-        // Create or get the results KV bucket
-        let results_kv = jetstream
-            .create_key_value(jetstream::kv::Config {
-                bucket: RESULTS_KV.to_string(),
-                max_age: Duration::from_secs(job_ttl_seconds),
-                storage: jetstream::stream::StorageType::File,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create results KV: {}", e)))?;
-
-        // Create or get the COMPILES stream (work queue pattern)
-        let compiles_stream = jetstream
-            .get_or_create_stream(jetstream::stream::Config {
-                name: COMPILES_STREAM.to_string(),
-                subjects: vec!["compiles.submit".to_string()],
-                retention: jetstream::stream::RetentionPolicy::WorkQueue,
-                max_age: Duration::from_secs(job_ttl_seconds),
-                storage: jetstream::stream::StorageType::File,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create COMPILES stream: {}", e)))?;
-
-        // Create or get the compiles KV bucket for status tracking
-        let compiles_kv = jetstream
-            .create_key_value(jetstream::kv::Config {
-                bucket: COMPILES_KV.to_string(),
-                max_age: Duration::from_secs(job_ttl_seconds),
-                storage: jetstream::stream::StorageType::File,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create compiles KV: {}", e)))?;
-
-        // Create or get the binaries KV bucket for compiled binary storage
-        // max_value_size allows storing large binaries (up to 100MB)
-        // Use memory storage for faster writes (binaries are ephemeral anyway)
-        let binaries_kv = jetstream
-            .create_key_value(jetstream::kv::Config {
-                bucket: BINARIES_KV.to_string(),
-                max_age: Duration::from_secs(binary_ttl_seconds),
-                max_value_size: 100 * 1024 * 1024, // 100MB max binary size
-                storage: jetstream::stream::StorageType::Memory,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create binaries KV: {}", e)))?;
-
-        // Create or get the compile_cache KV bucket for source hash -> binary_id mapping
-        let compile_cache_kv = jetstream
-            .create_key_value(jetstream::kv::Config {
-                bucket: COMPILE_CACHE_KV.to_string(),
-                max_age: Duration::from_secs(binary_ttl_seconds),
-                storage: jetstream::stream::StorageType::File,
-                ..Default::default()
-            })
+        let jetstream = jetstream::new(client.clone());
+
+        // Create or verify every stream and KV bucket against the shared topology
+        let topology_config = TopologyConfig::new(config.job_ttl_seconds, config.binary_ttl_seconds);
+        let topology = Topology::ensure(&jetstream, &topology_config)
             .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to create compile_cache KV: {}", e)))?;
+            .map_err(|e| ApiError::QueueError(e.to_string()))?;
 
-        Ok(Self {
+        let queue = Arc::new(Self {
+            client,
             jetstream,
-            jobs_stream: Arc::new(RwLock::new(jobs_stream)),
-            jobs_kv,
-            results_kv,
-            compiles_stream: Arc::new(RwLock::new(compiles_stream)),
-            compiles_kv,
-            binaries_kv,
-            compile_cache_kv,
-        })
+            topology_config,
+            topology: RwLock::new(Arc::new(topology)),
+            breaker,
+            last_recovery: Mutex::new(None),
+            sandbox_options_secret: config.sandbox_options_secret.clone(),
+            share_jobs: config.external_workers,
+            pool,
+        });
+
+        tokio::spawn(run_recovery(Arc::downgrade(&queue)));
+
+        Ok(queue)
     }
 
-    pub async fn submit_job(&self, job: Job) -> Result<(), ApiError> {
-        let job_id = job.id.to_string();
+    /// False while disconnected or while the circuit breaker is open.
+    pub fn is_healthy(&self) -> bool {
+        self.client.connection_state() == async_nats::connection::State::Connected && !self.breaker.is_open()
+    }
 
-        // Store initial job metadata
-        let metadata = JobMetadata {
-            status: JobStatus::Pending,
-            created_at: job.created_at,
-            started_at: None,
-            completed_at: None,
-            error: None,
-        };
+    /// Version of the NATS server this client last connected to
+    pub fn server_version(&self) -> String {
+        self.client.server_info().version
+    }
+
+    /// Re-create any streams/buckets lost in a NATS restart and swap in fresh handles.
+    async fn recover(&self) -> Result<(), ApiError> {
+        let mut last_recovery = self.last_recovery.lock().await;
+
+        // Another request just recovered while we waited for the lock
+        if last_recovery.is_some_and(|t| t.elapsed() < BREAKER_BASE_COOLDOWN) {
+            return Ok(());
+        }
 
-        self.jobs_kv
-            .put(
-                &job_id,
-                serde_json::to_vec(&metadata)
-                    .map_err(|e| ApiError::Internal(e.to_string()))?
-                    .into(),
-            )
+        if self.client.connection_state() != async_nats::connection::State::Connected {
+            return Err(unavailable());
+        }
+
+        let topology = Topology::ensure(&self.jetstream, &self.topology_config)
             .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to store job metadata: {}", e)))?;
+            .map_err(|e| ApiError::QueueError(e.to_string()))?;
+        *self.topology.write().await = Arc::new(topology);
+        *last_recovery = Some(Instant::now());
 
-        // Publish job to the work queue
-        let payload = serde_json::to_vec(&job).map_err(|e| ApiError::Internal(e.to_string()))?;
+        info!("Re-created NATS streams and KV buckets");
+        Ok(())
+    }
+
+    /// Run a queue operation with fresh handles, tracking failures for the
+    /// circuit breaker. A queue error triggers one topology re-creation and retry.
+    async fn call<T, F, Fut>(&self, op: F) -> Result<T, ApiError>
+    where
+        F: Fn(Arc<Topology>) -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        if self.breaker.is_open() {
+            return Err(unavailable());
+        }
+
+        let topology = self.topology.read().await.clone();
+        let result = match op(topology).await {
+            Err(ApiError::QueueError(e)) => {
+                warn!("Queue operation failed, re-creating topology: {}", e);
+                match self.recover().await {
+                    Ok(()) => op(self.topology.read().await.clone()).await,
+                    Err(_) => Err(ApiError::QueueError(e)),
+                }
+            }
+            result => result,
+        };
+
+        match &result {
+            Err(ApiError::QueueError(_)) => self.breaker.record_failure(),
+            _ => self.breaker.record_success(),
+        }
+        result
+    }
+
+    /// Publish with the job ID as `Nats-Msg-Id` so a retried publish is deduplicated.
+    async fn publish(&self, subject: &'static str, id: &Uuid, payload: Vec<u8>) -> Result<(), ApiError> {
+        let publish = jetstream::context::Publish::build()
+            .payload(payload.into())
+            .message_id(id.to_string());
 
         self.jetstream
-            .publish("jobs.submit", payload.into())
+            .send_publish(subject, publish)
             .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to publish job: {}", e)))?
+            .map_err(|e| ApiError::QueueError(format!("Failed to publish to {}: {}", subject, e)))?
             .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to confirm job publish: {}", e)))?;
+            .map_err(|e| ApiError::QueueError(format!("Failed to confirm publish to {}: {}", subject, e)))?;
 
         Ok(())
     }
 
+    pub async fn submit_job(&self, mut job: Job) -> Result<(), ApiError> {
+        let job_id = job.id.to_string();
+
+        if let Some(ref options) = job.sandbox_options {
+            let secret = self.sandbox_options_secret.as_ref().ok_or_else(|| {
+                ApiError::Forbidden("Privileged sandbox options need SANDBOX_OPTIONS_SECRET".to_string())
+            })?;
+            job.sandbox_signature =
+                Some(options.sign(secret.as_bytes(), &job_id, &job.binary_id, job.fixtures.as_ref()));
+        }
+
+        // Store initial job metadata
+        let mut metadata = JobMetadata::pending(&job);
+        let shared_with = self.pool.as_ref().filter(|_| self.share_jobs && job.is_shareable());
+        if let Some(pool) = shared_with {
+            db::record_shared_job(pool, &job.id, &job.binary_id, job.instruction_limit, job.user_id.as_ref()).await?;
+        }
+        metadata.shared = shared_with.is_some();
+        let subject = if metadata.shared { SHARED_JOBS_SUBJECT } else { JOBS_SUBJECT };
+        let metadata = serde_json::to_vec(&metadata).map_err(|e| ApiError::Internal(e.to_string()))?;
+        let payload = serde_json::to_vec(&job).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        self.call(|t| {
+            let (job_id, metadata, payload) = (&job_id, metadata.clone(), payload.clone());
+            async move {
+                t.jobs_kv
+                    .put(job_id, metadata.into())
+                    .await
+                    .map_err(|e| ApiError::QueueError(format!("Failed to store job metadata: {}", e)))?;
+
+                // Publish job to the work queue
+                self.publish(subject, &job.id, payload).await
+            }
+        })
+        .await
+    }
+
     pub async fn get_job_status(&self, job_id: &Uuid) -> Result<Option<JobMetadata>, ApiError> {
         let key = job_id.to_string();
 
-        match self.jobs_kv.get(&key).await {
-            Ok(Some(entry)) => {
-                let metadata: JobMetadata = serde_json::from_slice(&entry)
-                    .map_err(|e| ApiError::Internal(format!("Failed to parse job metadata: {}", e)))?;
-                Ok(Some(metadata))
-            }
-            Ok(None) => Ok(None),
-            Err(e) => Err(ApiError::QueueError(format!(
-                "Failed to get job status: {}",
-                e
-            ))),
-        }
+        let entry = self
+            .call(|t| {
+                let key = &key;
+                async move {
+                    t.jobs_kv
+                        .get(key)
+                        .await
+                        .map_err(|e| ApiError::QueueError(format!("Failed to get job status: {}", e)))
+                }
+            })
+            .await?;
+
+        entry.map(|entry| parse_job_metadata(&entry)).transpose()
+    }
+
+    /// Wait up to `timeout` for the job's status to move off `from`, watching
+    /// its KV entry. Returns the status at that point: still `from` if nothing
+    /// happened, `None` if the job is unknown or gone.
+    pub async fn watch_job_status(
+        &self,
+        job_id: &Uuid,
+        from: JobStatus,
+        timeout: Duration,
+    ) -> Result<Option<JobMetadata>, ApiError> {
+        let key = job_id.to_string();
+        let deadline = tokio::time::Instant::now() + timeout;
+        self.call(|t| {
+            let key = &key;
+            async move { watch_entry(&t.jobs_kv, key, deadline, |m: &JobMetadata| m.status != from).await }
+        })
+        .await
     }
 
     pub async fn get_job_result(&self, job_id: &Uuid) -> Result<Option<ExecutionResult>, ApiError> {
         let key = job_id.to_string();
 
-        match self.results_kv.get(&key).await {
-            Ok(Some(entry)) => {
-                let result: ExecutionResult = serde_json::from_slice(&entry)
-                    .map_err(|e| ApiError::Internal(format!("Failed to parse job result: {}", e)))?;
-                Ok(Some(result))
-            }
-            Ok(None) => Ok(None),
-            Err(e) => Err(ApiError::QueueError(format!(
-                "Failed to get job result: {}",
-                e
-            ))),
-        }
+        let entry = self
+            .call(|t| {
+                let key = &key;
+                async move {
+                    t.results_kv
+                        .get(key)
+                        .await
+                        .map_err(|e| ApiError::QueueError(format!("Failed to get job result: {}", e)))
+                }
+            })
+            .await?;
+
+        entry
+            .map(|entry| {
+                serde_json::from_slice(&entry)
+                    .map_err(|e| ApiError::Internal(format!("Failed to parse job result: {}", e)))
+            })
+            .transpose()
     }
 
-    pub async fn get_queue_depth(&self) -> Result<u64, ApiError> {
-        let mut stream = self.jobs_stream.write().await;
-        let info = stream
-            .info()
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to get stream info: {}", e)))?;
+    /// IDs of every job with a stored result (still within the results TTL).
+    pub async fn list_result_ids(&self) -> Result<Vec<Uuid>, ApiError> {
+        self.call(|t| async move {
+            let keys = t
+                .results_kv
+                .keys()
+                .await
+                .map_err(|e| ApiError::QueueError(format!("Failed to list job results: {}", e)))?;
+            let keys: Vec<String> = keys
+                .try_collect()
+                .await
+                .map_err(|e| ApiError::QueueError(format!("Failed to list job results: {}", e)))?;
+            Ok(keys.iter().filter_map(|k| k.parse().ok()).collect())
+        })
+        .await
+    }
 
-        Ok(info.state.messages)
+    pub async fn get_queue_depth(&self) -> Result<u64, ApiError> {
+        self.call(|t| async move {
+            let mut depth = 0;
+            for stream in [&t.jobs_stream, &t.shared_jobs_stream] {
+                let mut stream = stream.clone();
+                let info = stream
+                    .info()
+                    .await
+                    .map_err(|e| ApiError::QueueError(format!("Failed to get stream info: {}", e)))?;
+                depth += info.state.messages;
+            }
+            Ok(depth)
+        })
+        .await
     }
 
     pub async fn update_job_status(
@@ -476,17 +774,18 @@ impl QueueClient {
             _ => {}
         }
 
-        self.jobs_kv
-            .put(
-                &key,
-                serde_json::to_vec(&metadata)
-                    .map_err(|e| ApiError::Internal(e.to_string()))?
-                    .into(),
-            )
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to update job status: {}", e)))?;
-
-        Ok(())
+        let value = serde_json::to_vec(&metadata).map_err(|e| ApiError::Internal(e.to_string()))?;
+        self.call(|t| {
+            let (key, value) = (&key, value.clone());
+            async move {
+                t.jobs_kv
+                    .put(key, value.into())
+                    .await
+                    .map_err(|e| ApiError::QueueError(format!("Failed to update job status: {}", e)))?;
+                Ok(())
+            }
+        })
+        .await
     }
 
     pub async fn store_job_result(
@@ -495,18 +794,19 @@ impl QueueClient {
         result: &ExecutionResult,
     ) -> Result<(), ApiError> {
         let key = job_id.to_string();
-
-        self.results_kv
-            .put(
-                &key,
-                serde_json::to_vec(result)
-                    .map_err(|e| ApiError::Internal(e.to_string()))?
-                    .into(),
-            )
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to store job result: {}", e)))?;
-
-        Ok(())
+        let value = serde_json::to_vec(result).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        self.call(|t| {
+            let (key, value) = (&key, value.clone());
+            async move {
+                t.results_kv
+                    .put(key, value.into())
+                    .await
+                    .map_err(|e| ApiError::QueueError(format!("Failed to store job result: {}", e)))?;
+                Ok(())
+            }
+        })
+        .await
     }
 
     // ============ Compile Methods ============
@@ -523,110 +823,504 @@ impl QueueClient {
             error: None,
             position: None,
         };
-
-        self.compiles_kv
-            .put(
-                &job_id,
-                serde_json::to_vec(&metadata)
-                    .map_err(|e| ApiError::Internal(e.to_string()))?
-                    .into(),
-            )
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to store compile metadata: {}", e)))?;
-
-        // Publish job to the work queue
+        let metadata = serde_json::to_vec(&metadata).map_err(|e| ApiError::Internal(e.to_string()))?;
         let payload = serde_json::to_vec(&job).map_err(|e| ApiError::Internal(e.to_string()))?;
 
-        self.jetstream
-            .publish("compiles.submit", payload.into())
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to publish compile job: {}", e)))?
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to confirm compile job publish: {}", e)))?;
+        self.call(|t| {
+            let (job_id, metadata, payload) = (&job_id, metadata.clone(), payload.clone());
+            async move {
+                t.compiles_kv
+                    .put(job_id, metadata.into())
+                    .await
+                    .map_err(|e| ApiError::QueueError(format!("Failed to store compile metadata: {}", e)))?;
 
-        Ok(())
+                // Publish job to the work queue
+                self.publish(COMPILES_SUBJECT, &job.id, payload).await
+            }
+        })
+        .await
     }
 
     pub async fn get_compile_status(&self, job_id: &Uuid) -> Result<Option<CompileMetadata>, ApiError> {
         let key = job_id.to_string();
 
-        match self.compiles_kv.get(&key).await {
-            Ok(Some(entry)) => {
-                let metadata: CompileMetadata = serde_json::from_slice(&entry)
-                    .map_err(|e| ApiError::Internal(format!("Failed to parse compile metadata: {}", e)))?;
-                Ok(Some(metadata))
-            }
-            Ok(None) => Ok(None),
-            Err(e) => Err(ApiError::QueueError(format!(
-                "Failed to get compile status: {}",
-                e
-            ))),
-        }
+        let entry = self
+            .call(|t| {
+                let key = &key;
+                async move {
+                    t.compiles_kv
+                        .get(key)
+                        .await
+                        .map_err(|e| ApiError::QueueError(format!("Failed to get compile status: {}", e)))
+                }
+            })
+            .await?;
+
+        entry
+            .map(|entry| {
+                serde_json::from_slice(&entry)
+                    .map_err(|e| ApiError::Internal(format!("Failed to parse compile metadata: {}", e)))
+            })
+            .transpose()
+    }
+
+    /// Like `watch_job_status`, for a compile job
+    pub async fn watch_compile_status(
+        &self,
+        job_id: &Uuid,
+        from: CompileStatus,
+        timeout: Duration,
+    ) -> Result<Option<CompileMetadata>, ApiError> {
+        let key = job_id.to_string();
+        let deadline = tokio::time::Instant::now() + timeout;
+        self.call(|t| {
+            let key = &key;
+            async move { watch_entry(&t.compiles_kv, key, deadline, |m: &CompileMetadata| m.status != from).await }
+        })
+        .await
     }
 
     pub async fn get_compile_result(&self, job_id: &Uuid) -> Result<Option<CompileResult>, ApiError> {
         let result_key = format!("{}_result", job_id);
 
-        match self.compiles_kv.get(&result_key).await {
-            Ok(Some(entry)) => {
-                let result: CompileResult = serde_json::from_slice(&entry)
-                    .map_err(|e| ApiError::Internal(format!("Failed to parse compile result: {}", e)))?;
-                Ok(Some(result))
-            }
-            Ok(None) => Ok(None),
-            Err(e) => Err(ApiError::QueueError(format!(
-                "Failed to get compile result: {}",
-                e
-            ))),
-        }
+        let entry = self
+            .call(|t| {
+                let key = &result_key;
+                async move {
+                    t.compiles_kv
+                        .get(key)
+                        .await
+                        .map_err(|e| ApiError::QueueError(format!("Failed to get compile result: {}", e)))
+                }
+            })
+            .await?;
+
+        entry
+            .map(|entry| {
+                serde_json::from_slice(&entry)
+                    .map_err(|e| ApiError::Internal(format!("Failed to parse compile result: {}", e)))
+            })
+            .transpose()
     }
 
-    pub async fn get_compile_queue_depth(&self) -> Result<u64, ApiError> {
-        let mut stream = self.compiles_stream.write().await;
-        let info = stream
-            .info()
-            .await
-            .map_err(|e| ApiError::QueueError(format!("Failed to get compile stream info: {}", e)))?;
+    /// Assembly kept for an `emit_asm` compile job
+    pub async fn get_compile_asm(&self, job_id: &Uuid) -> Result<Option<String>, ApiError> {
+        let asm_key = format!("{}_asm", job_id);
+
+        let entry = self
+            .call(|t| {
+                let key = &asm_key;
+                async move {
+                    t.compiles_kv
+                        .get(key)
+                        .await
+                        .map_err(|e| ApiError::QueueError(format!("Failed to get compile assembly: {}", e)))
+                }
+            })
+            .await?;
 
-        Ok(info.state.messages)
+        Ok(entry.map(|entry| String::from_utf8_lossy(&entry).into_owned()))
     }
 
-    pub async fn get_binary(&self, binary_id: &str) -> Result<Option<Vec<u8>>, ApiError> {
-        match self.binaries_kv.get(binary_id).await {
-            Ok(Some(entry)) => Ok(Some(entry.to_vec())),
-            Ok(None) => Ok(None),
-            Err(e) => Err(ApiError::QueueError(format!(
-                "Failed to get binary: {}",
-                e
-            ))),
+    pub async fn get_compile_queue_depth(&self) -> Result<u64, ApiError> {
+        self.call(|t| async move {
+            let mut stream = t.compiles_stream.clone();
+            let info = stream
+                .info()
+                .await
+                .map_err(|e| ApiError::QueueError(format!("Failed to get compile stream info: {}", e)))?;
+            Ok(info.state.messages)
+        })
+        .await
+    }
+
+    /// Consumer state of both worker pools. Read directly rather than through
+    /// `call`: a consumer no worker has created yet isn't a broken topology.
+    pub async fn fleet_status(&self) -> Result<FleetStatus, ApiError> {
+        if self.breaker.is_open() {
+            return Err(unavailable());
         }
+        let topology = self.topology.read().await.clone();
+        let mut spool = topology.runs_spool_stream.clone();
+        Ok(FleetStatus {
+            workers: consumer_stats(&topology.jobs_stream, WORKER_CONSUMER).await,
+            shared_workers: consumer_stats(&topology.shared_jobs_stream, SHARED_WORKER_CONSUMER).await,
+            compile_workers: consumer_stats(&topology.compiles_stream, COMPILE_WORKER_CONSUMER).await,
+            spooled_runs: spool.info().await.ok().map(|info| info.state.messages),
+        })
+    }
+
+    /// Publish an event onto the events stream under `id`
+    pub async fn publish_event(&self, subject: &'static str, id: &Uuid, payload: Vec<u8>) -> Result<(), ApiError> {
+        self.call(|_| self.publish(subject, id, payload.clone())).await
     }
 
-    pub async fn check_compile_cache(
+    /// The durable consumer `name` on the events stream, seeing only `subject`.
+    /// Events it fails to handle are retried a few times, then left behind.
+    pub async fn event_consumer(
         &self,
-        source: &str,
-        language: Language,
-        optimization: Optimization,
-        flags: &HashMap<String, String>,
-    ) -> Result<Option<CompileResult>, ApiError> {
-        let cache_key = compute_cache_key(source, language, optimization, flags);
-
-        match self.compile_cache_kv.get(&cache_key).await {
-            Ok(Some(entry)) => {
+        name: &str,
+        subject: &str,
+    ) -> Result<jetstream::consumer::PullConsumer, ApiError> {
+        self.call(|t| async move {
+            t.events_stream
+                .get_or_create_consumer(
+                    name,
+                    jetstream::consumer::pull::Config {
+                        durable_name: Some(name.to_string()),
+                        filter_subject: subject.to_string(),
+                        ack_policy: jetstream::consumer::AckPolicy::Explicit,
+                        max_deliver: 5,
+                        ack_wait: Duration::from_secs(30),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .map_err(|e| ApiError::QueueError(format!("Failed to create event consumer {}: {}", name, e)))
+        })
+        .await
+    }
+
+    pub async fn get_binary(&self, binary_id: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        self.call(|t| async move {
+            t.binaries_kv
+                .get(binary_id)
+                .await
+                .map(|entry| entry.map(|e| e.to_vec()))
+                .map_err(|e| ApiError::QueueError(format!("Failed to get binary: {}", e)))
+        })
+        .await
+    }
+
+    pub async fn check_compile_cache(&self, job: &CompileJob) -> Result<Option<CompileResult>, ApiError> {
+        let cache_key = job.cache_key();
+
+        self.call(|t| {
+            let cache_key = &cache_key;
+            async move {
+                let entry = t
+                    .compile_cache_kv
+                    .get(cache_key)
+                    .await
+                    .map_err(|e| ApiError::QueueError(format!("Failed to check compile cache: {}", e)))?;
+                let Some(entry) = entry else {
+                    return Ok(None);
+                };
+
                 let result: CompileResult = serde_json::from_slice(&entry)
                     .map_err(|e| ApiError::Internal(format!("Failed to parse cache entry: {}", e)))?;
 
                 // Verify the binary still exists
-                match self.binaries_kv.get(&result.binary_id).await {
+                match t.binaries_kv.get(&result.binary_id).await {
                     Ok(Some(_)) => Ok(Some(result)),
                     _ => Ok(None), // Binary expired, cache miss
                 }
             }
-            Ok(None) => Ok(None),
-            Err(e) => Err(ApiError::QueueError(format!(
-                "Failed to check compile cache: {}",
-                e
-            ))),
+        })
+        .await
+    }
+}
+
+/// Background recovery: once the breaker cooldown elapses, re-create the
+/// topology and close the breaker, backing off after each failed attempt.
+async fn run_recovery(queue: Weak<NatsQueue>) {
+    loop {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+
+        let Some(queue) = queue.upgrade() else {
+            return;
+        };
+        if !queue.breaker.recovery_due() {
+            continue;
+        }
+
+        match queue.recover().await {
+            Ok(()) => queue.breaker.record_success(),
+            Err(e) => {
+                warn!("NATS recovery attempt failed: {}", e);
+                queue.breaker.recovery_failed();
+            }
+        }
+    }
+}
+
+fn parse_job_metadata(raw: &[u8]) -> Result<JobMetadata, ApiError> {
+    serde_json::from_slice(raw).map_err(|e| ApiError::Internal(format!("Failed to parse job metadata: {}", e)))
+}
+
+/// Read `key` and follow its updates until `moved` holds or `deadline`
+/// passes. The value at that point; `None` if the key is missing or deleted.
+async fn watch_entry<T: DeserializeOwned>(
+    store: &jetstream::kv::Store,
+    key: &str,
+    deadline: tokio::time::Instant,
+    moved: impl Fn(&T) -> bool,
+) -> Result<Option<T>, ApiError> {
+    let parse = |raw: &[u8]| {
+        serde_json::from_slice::<T>(raw).map_err(|e| ApiError::Internal(format!("Failed to parse {}: {}", key, e)))
+    };
+
+    // Watch before reading so no update falls in between
+    let mut updates = store
+        .watch(key)
+        .await
+        .map_err(|e| ApiError::QueueError(format!("Failed to watch {}: {}", key, e)))?;
+    let entry = store
+        .get(key)
+        .await
+        .map_err(|e| ApiError::QueueError(format!("Failed to get {}: {}", key, e)))?;
+    let Some(entry) = entry else {
+        return Ok(None);
+    };
+
+    let mut value = parse(&entry)?;
+    while !moved(&value) {
+        let entry = match tokio::time::timeout_at(deadline, updates.try_next()).await {
+            Ok(Ok(Some(entry))) => entry,
+            Ok(Err(e)) => return Err(ApiError::QueueError(format!("Failed to watch {}: {}", key, e))),
+            // Timed out, or the watch ended
+            Ok(Ok(None)) | Err(_) => break,
+        };
+        if entry.operation != jetstream::kv::Operation::Put {
+            return Ok(None);
+        }
+        value = parse(&entry.value)?;
+    }
+    Ok(Some(value))
+}
+
+async fn consumer_stats(stream: &jetstream::stream::Stream, name: &str) -> Option<ConsumerStats> {
+    let info = stream.consumer_info(name).await.ok()?;
+    Some(ConsumerStats {
+        pending: info.num_pending,
+        in_flight: info.num_ack_pending,
+        waiting_pulls: info.num_waiting,
+        redelivered: info.num_redelivered,
+        last_delivery: info
+            .delivered
+            .last_active
+            .and_then(|t| DateTime::from_timestamp(t.unix_timestamp(), t.nanosecond())),
+    })
+}
+
+// ============ Queue Client ============
+
+/// How long `await_job` waits before looking again at a job that isn't
+/// there yet or whose result hasn't landed
+const AWAIT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Job queue backed by NATS JetStream, or by in-process workers when NATS is unavailable.
+pub enum QueueClient {
+    Nats(Arc<NatsQueue>),
+    Local(LocalQueue),
+}
+
+impl QueueClient {
+    pub async fn connect(config: &Config, pool: Option<PgPool>) -> Result<Self, ApiError> {
+        NatsQueue::connect(config, pool)
+            .await
+            .map(QueueClient::Nats)
+    }
+
+    pub fn local(config: &Config, pool: PgPool) -> Self {
+        QueueClient::Local(LocalQueue::start(config, pool))
+    }
+
+    pub fn backend(&self) -> &'static str {
+        match self {
+            QueueClient::Nats(_) => "nats",
+            QueueClient::Local(_) => "local",
+        }
+    }
+
+    /// False while the NATS connection is down or its circuit breaker is open.
+    pub fn is_healthy(&self) -> bool {
+        match self {
+            QueueClient::Nats(q) => q.is_healthy(),
+            QueueClient::Local(_) => true,
+        }
+    }
+
+    /// Worker consumer state; `None` for the local queue, whose workers are in-process
+    pub async fn fleet_status(&self) -> Result<Option<FleetStatus>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.fleet_status().await.map(Some),
+            QueueClient::Local(_) => Ok(None),
+        }
+    }
+
+    /// NATS server version; `None` for the local queue
+    pub fn server_version(&self) -> Option<String> {
+        match self {
+            QueueClient::Nats(q) => Some(q.server_version()),
+            QueueClient::Local(_) => None,
+        }
+    }
+
+    pub async fn submit_job(&self, job: Job) -> Result<(), ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.submit_job(job).await,
+            QueueClient::Local(q) => q.submit_job(job).await,
+        }
+    }
+
+    pub async fn get_job_status(&self, job_id: &Uuid) -> Result<Option<JobMetadata>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_job_status(job_id).await,
+            QueueClient::Local(q) => q.get_job_status(job_id).await,
+        }
+    }
+
+    /// Wait up to `timeout` for the job's status to move off `from`
+    pub async fn watch_job_status(
+        &self,
+        job_id: &Uuid,
+        from: JobStatus,
+        timeout: Duration,
+    ) -> Result<Option<JobMetadata>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.watch_job_status(job_id, from, timeout).await,
+            QueueClient::Local(q) => q.watch_job_status(job_id, from, timeout).await,
+        }
+    }
+
+    pub async fn get_job_result(&self, job_id: &Uuid) -> Result<Option<ExecutionResult>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_job_result(job_id).await,
+            QueueClient::Local(q) => q.get_job_result(job_id).await,
+        }
+    }
+
+    pub async fn get_queue_depth(&self) -> Result<u64, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_queue_depth().await,
+            QueueClient::Local(q) => Ok(q.get_queue_depth()),
+        }
+    }
+
+    pub async fn submit_compile_job(&self, job: CompileJob) -> Result<(), ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.submit_compile_job(job).await,
+            QueueClient::Local(q) => q.submit_compile_job(job).await,
+        }
+    }
+
+    pub async fn get_compile_status(&self, job_id: &Uuid) -> Result<Option<CompileMetadata>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_compile_status(job_id).await,
+            QueueClient::Local(q) => q.get_compile_status(job_id).await,
+        }
+    }
+
+    pub async fn watch_compile_status(
+        &self,
+        job_id: &Uuid,
+        from: CompileStatus,
+        timeout: Duration,
+    ) -> Result<Option<CompileMetadata>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.watch_compile_status(job_id, from, timeout).await,
+            QueueClient::Local(q) => q.watch_compile_status(job_id, from, timeout).await,
+        }
+    }
+
+    /// Wait for a job to finish and return its result, following its status
+    /// with `watch_job_status` rather than polling
+    pub async fn await_job(&self, job_id: &Uuid, timeout: Duration) -> Result<ExecutionResult, ApiError> {
+        let deadline = Instant::now() + timeout;
+        let mut metadata = self.get_job_status(job_id).await?;
+        loop {
+            match &metadata {
+                Some(m) if m.status == JobStatus::Completed => {
+                    if let Some(result) = self.get_job_result(job_id).await? {
+                        return Ok(result);
+                    }
+                }
+                Some(m) if m.status == JobStatus::Failed => {
+                    return Err(ApiError::Internal(
+                        m.error.clone().unwrap_or_else(|| "Execution failed".to_string()),
+                    ));
+                }
+                _ => {}
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ApiError::Timeout(timeout.as_secs()));
+            }
+            metadata = match metadata.map(|m| m.status) {
+                Some(status) if status != JobStatus::Completed => {
+                    self.watch_job_status(job_id, status, remaining).await?
+                }
+                // Not there yet, or completed with the result still on its way
+                _ => {
+                    tokio::time::sleep(AWAIT_RETRY_INTERVAL.min(remaining)).await;
+                    self.get_job_status(job_id).await?
+                }
+            };
+        }
+    }
+
+    /// `await_job` for a compile job
+    pub async fn await_compile(&self, job_id: &Uuid, timeout: Duration) -> Result<CompileResult, ApiError> {
+        let deadline = Instant::now() + timeout;
+        let mut metadata = self.get_compile_status(job_id).await?;
+        loop {
+            match &metadata {
+                Some(m) if m.status == CompileStatus::Completed => {
+                    if let Some(result) = self.get_compile_result(job_id).await? {
+                        return Ok(result);
+                    }
+                }
+                Some(m) if m.status == CompileStatus::Failed => {
+                    return Err(ApiError::CompileError(
+                        m.error.clone().unwrap_or_else(|| "Compilation failed".to_string()),
+                    ));
+                }
+                _ => {}
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ApiError::Timeout(timeout.as_secs()));
+            }
+            metadata = match metadata.map(|m| m.status) {
+                Some(status) if status != CompileStatus::Completed => {
+                    self.watch_compile_status(job_id, status, remaining).await?
+                }
+                _ => {
+                    tokio::time::sleep(AWAIT_RETRY_INTERVAL.min(remaining)).await;
+                    self.get_compile_status(job_id).await?
+                }
+            };
+        }
+    }
+
+    pub async fn get_compile_result(&self, job_id: &Uuid) -> Result<Option<CompileResult>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_compile_result(job_id).await,
+            QueueClient::Local(q) => q.get_compile_result(job_id).await,
+        }
+    }
+
+    pub async fn get_compile_asm(&self, job_id: &Uuid) -> Result<Option<String>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_compile_asm(job_id).await,
+            QueueClient::Local(q) => q.get_compile_asm(job_id).await,
+        }
+    }
+
+    pub async fn get_compile_queue_depth(&self) -> Result<u64, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.get_compile_queue_depth().await,
+            QueueClient::Local(q) => Ok(q.get_compile_queue_depth()),
+        }
+    }
+
+    pub async fn check_compile_cache(&self, job: &CompileJob) -> Result<Option<CompileResult>, ApiError> {
+        match self {
+            QueueClient::Nats(q) => q.check_compile_cache(job).await,
+            QueueClient::Local(q) => q.check_compile_cache(job).await,
         }
     }
 }
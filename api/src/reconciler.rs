@@ -0,0 +1,142 @@
+//! Backfills `runs` rows for jobs that completed in NATS but never reached
+//! PostgreSQL (e.g. the worker's persist failed and its spool expired).
+//!
+//! Every `RECONCILE_INTERVAL_SECONDS` the results KV is compared against the
+//! `runs` table; each missing run is rebuilt from the job metadata and result
+//! and saved with `db::save_run`, which is idempotent on `job_id`.
+
+use crate::db;
+use crate::error::ApiError;
+use crate::queue::{run_request, Job, JobStatus, NatsQueue};
+use ctf_common::logging::Sampler;
+use chrono::{Duration as ChronoDuration, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Leave recently finished jobs to the worker and its spool
+pub const GRACE_PERIOD: Duration = Duration::from_secs(120);
+
+/// Clean passes are the norm; only some are logged
+static UP_TO_DATE: Sampler = Sampler::new();
+
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub scanned: usize,
+    pub missing: usize,
+    pub backfilled: usize,
+    pub skipped: usize,
+}
+
+pub fn spawn(queue: Arc<NatsQueue>, pool: PgPool, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // skip the immediate first tick, let workers settle after startup
+
+        loop {
+            ticker.tick().await;
+            match reconcile(&queue, &pool, GRACE_PERIOD).await {
+                Ok(report) if report.missing > 0 => warn!(
+                    scanned = report.scanned,
+                    missing = report.missing,
+                    backfilled = report.backfilled,
+                    skipped = report.skipped,
+                    "Run reconciliation found results missing from Postgres"
+                ),
+                Ok(report) => {
+                    if let Some(suppressed) = UP_TO_DATE.sample() {
+                        info!(scanned = report.scanned, suppressed, "Run reconciliation: Postgres up to date");
+                    }
+                }
+                Err(e) => error!("Run reconciliation failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Backfill results that finished more than `grace` ago and have no `runs` row.
+pub async fn reconcile(queue: &NatsQueue, pool: &PgPool, grace: Duration) -> Result<ReconcileReport, ApiError> {
+    let job_ids = queue.list_result_ids().await?;
+    let existing = db::get_existing_run_job_ids(pool, &job_ids).await?;
+
+    let mut report = ReconcileReport {
+        scanned: job_ids.len(),
+        ..Default::default()
+    };
+
+    for job_id in job_ids.iter().filter(|id| !existing.contains(id)) {
+        match backfill(queue, pool, job_id, grace).await {
+            Ok(true) => {
+                report.missing += 1;
+                report.backfilled += 1;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                report.missing += 1;
+                report.skipped += 1;
+                warn!(job_id = %job_id, "Cannot backfill run: {}", e);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns `Ok(false)` when the job is not (yet) eligible for backfilling.
+async fn backfill(queue: &NatsQueue, pool: &PgPool, job_id: &Uuid, grace: Duration) -> Result<bool, ApiError> {
+    let Some(metadata) = queue.get_job_status(job_id).await? else {
+        return Err(ApiError::Internal("job metadata expired".to_string()));
+    };
+
+    // An external worker may have run it; only its own report counts
+    if metadata.shared {
+        return Ok(false);
+    }
+
+    let cutoff = Utc::now() - ChronoDuration::from_std(grace).unwrap_or_default();
+    let completed_at = match (metadata.status, metadata.completed_at) {
+        (JobStatus::Completed, Some(at)) if at <= cutoff => at,
+        _ => return Ok(false),
+    };
+
+    let (Some(binary_id), Some(instruction_limit)) = (metadata.binary_id, metadata.instruction_limit) else {
+        return Err(ApiError::Internal("job metadata predates reconciliation fields".to_string()));
+    };
+
+    let Some(result) = queue.get_job_result(job_id).await? else {
+        return Ok(false);
+    };
+
+    let binary_metadata = db::get_binary_metadata(pool, &binary_id).await?;
+    let job = Job {
+        id: *job_id,
+        user_id: metadata.user_id,
+        binary_id,
+        instruction_limit,
+        stdin: Vec::new(),
+        created_at: metadata.created_at,
+        benchmark_id: metadata.benchmark_id,
+        benchmark_version: metadata.benchmark_version,
+        network_enabled: false,
+        env_vars: Default::default(),
+        args: metadata.args,
+        outputs: Vec::new(),
+        core_dump: false,
+        mode: metadata.mode,
+        fixtures: None,
+        sandbox_options: None,
+        sandbox_signature: None,
+        timeout_ms: None,
+        memory_limit_mb: None,
+        visibility: metadata.visibility,
+        challenge_id: None,
+    };
+
+    let run = run_request(&job, None, binary_metadata.as_ref(), &result, metadata.started_at, completed_at);
+    db::save_run(pool, &run).await?;
+
+    warn!(job_id = %job_id, binary_id = %job.binary_id, "Backfilled run missing from Postgres");
+    Ok(true)
+}
@@ -0,0 +1,660 @@
+//! GitHub repos as a source of benchmark and challenge runs.
+//!
+//! A user links a file in one of their repos with `POST /my/repos`, naming
+//! the benchmark or challenge it solves. When GitHub delivers a push to
+//! `POST /integrations/github/webhook` for that repo's branch, the file is
+//! fetched at the pushed commit, built and run: against the benchmark like a
+//! tracked solution re-run (tracked.rs), or as a practice submission to the
+//! challenge. The instruction count, or what went wrong, is posted back as a
+//! `ctf-arena/<target>` commit status and kept in the link's push history.
+//!
+//! It runs as a GitHub App installed on the repos: `GITHUB_APP_ID` and
+//! `GITHUB_APP_PRIVATE_KEY` sign the app's JWTs, which buy an installation
+//! token per push, and `GITHUB_WEBHOOK_SECRET` checks each delivery's
+//! `X-Hub-Signature-256`. Without all three, linking and the webhook are
+//! refused. A repo can only be linked by the user whose GitHub login owns
+//! it. Both kinds of push count against the owner's compile quota and are
+//! held to their organization's policy, like any submission. A delivery is
+//! acted on once, by its `X-GitHub-Delivery` ID, so redeliveries and
+//! replays don't run anything again.
+
+use crate::auth::AuthenticatedUser;
+use crate::challenges;
+use crate::config::Config;
+use crate::db::{self, RepoLink, RepoPush};
+use crate::error::ApiError;
+use crate::queue::{Language, Optimization};
+use crate::tracked::{self, Solution};
+use crate::AppState;
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Repo links one user can hold
+pub const MAX_REPO_LINKS_PER_USER: u64 = 10;
+/// Pushes returned with a link, newest first
+const MAX_HISTORY: i64 = 20;
+/// Linked files are read at most this deep, whatever `MAX_SOURCE_SIZE` is
+const MAX_FILE_BYTES: usize = 1024 * 1024;
+const GITHUB_TIMEOUT: Duration = Duration::from_secs(10);
+/// GitHub caps status descriptions at 140 characters
+const MAX_DESCRIPTION: usize = 140;
+
+// ============ GitHub App ============
+
+/// The configured GitHub App, set when all of its settings are
+pub struct GitHubApp {
+    app_id: String,
+    key: RsaKeyPair,
+    webhook_secret: Vec<u8>,
+    api_url: String,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct InstallationToken {
+    token: String,
+}
+
+impl GitHubApp {
+    /// `None` unless the app ID, key and webhook secret are all set; an
+    /// unreadable key is an error
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        let (Some(app_id), Some(pem), Some(secret)) = (
+            &config.github_app_id,
+            &config.github_app_private_key,
+            &config.github_webhook_secret,
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            app_id: app_id.clone(),
+            key: parse_private_key(pem)?,
+            webhook_secret: secret.as_bytes().to_vec(),
+            api_url: config.github_api_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }))
+    }
+
+    /// Whether `signature` (`X-Hub-Signature-256`) is the body's HMAC
+    fn verify(&self, signature: &str, body: &[u8]) -> bool {
+        let Some(expected) = signature.strip_prefix("sha256=").and_then(|s| hex::decode(s).ok()) else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.webhook_secret).expect("HMAC accepts any key length");
+        mac.update(body);
+        mac.verify_slice(&expected).is_ok()
+    }
+
+    /// An RS256 JWT identifying the app, good for ten minutes
+    fn jwt(&self) -> Result<String, ApiError> {
+        let now = Utc::now().timestamp();
+        let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"RS256","typ":"JWT"}"#);
+        // Backdated for clock drift, as GitHub suggests
+        let claims = serde_json::json!({ "iat": now - 60, "exp": now + 540, "iss": self.app_id });
+        let message = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+
+        let mut signature = vec![0u8; self.key.public().modulus_len()];
+        self.key
+            .sign(&RSA_PKCS1_SHA256, &SystemRandom::new(), message.as_bytes(), &mut signature)
+            .map_err(|_| ApiError::Internal("Failed to sign GitHub App JWT".to_string()))?;
+        Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+    }
+
+    async fn installation_token(&self, installation_id: u64) -> Result<String, ApiError> {
+        let response = self
+            .http
+            .post(format!("{}/app/installations/{}/access_tokens", self.api_url, installation_id))
+            .bearer_auth(self.jwt()?)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "CTF-Arena")
+            .timeout(GITHUB_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| ApiError::Internal(format!("GitHub unreachable: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(ApiError::Internal(format!(
+                "GitHub answered {} for an installation token",
+                response.status()
+            )));
+        }
+        let token: InstallationToken = response
+            .json()
+            .await
+            .map_err(|e| ApiError::Internal(format!("Unexpected installation token response: {}", e)))?;
+        Ok(token.token)
+    }
+
+    /// `path` in `repo` as of `sha`
+    async fn fetch_file(&self, token: &str, repo: &str, path: &str, sha: &str) -> Result<String, ApiError> {
+        let mut url = reqwest::Url::parse(&self.api_url)
+            .map_err(|e| ApiError::Internal(format!("Bad GITHUB_API_URL: {}", e)))?;
+        // Each segment percent-encoded, so the path can't leave the contents route
+        url.path_segments_mut()
+            .map_err(|_| ApiError::Internal("Bad GITHUB_API_URL".to_string()))?
+            .pop_if_empty()
+            .push("repos")
+            .extend(repo.split('/'))
+            .push("contents")
+            .extend(path.trim_start_matches('/').split('/'));
+        let mut response = self
+            .http
+            .get(url)
+            .query(&[("ref", sha)])
+            .bearer_auth(token)
+            .header("Accept", "application/vnd.github.raw")
+            .header("User-Agent", "CTF-Arena")
+            .timeout(GITHUB_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| ApiError::Internal(format!("GitHub unreachable: {}", e)))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(ApiError::NotFound(format!("'{}' at {}", path, short_sha(sha))));
+        }
+        if !response.status().is_success() {
+            return Err(ApiError::Internal(format!("GitHub answered {} for '{}'", response.status(), path)));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to read '{}': {}", path, e)))?
+        {
+            if body.len() + chunk.len() > MAX_FILE_BYTES {
+                return Err(ApiError::SourceTooLarge {
+                    size: body.len() + chunk.len(),
+                    max: MAX_FILE_BYTES,
+                });
+            }
+            body.extend_from_slice(&chunk);
+        }
+        String::from_utf8(body).map_err(|_| ApiError::InvalidField(format!("'{}' is not UTF-8", path)))
+    }
+
+    async fn post_status(&self, token: &str, repo: &str, sha: &str, status: &CommitStatus) {
+        let result = self
+            .http
+            .post(format!("{}/repos/{}/statuses/{}", self.api_url, repo, sha))
+            .bearer_auth(token)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "CTF-Arena")
+            .timeout(GITHUB_TIMEOUT)
+            .json(status)
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => warn!(repo, sha, status = %response.status(), "GitHub refused a commit status"),
+            Err(e) => warn!(repo, sha, error = %e, "Failed to post commit status"),
+        }
+    }
+}
+
+/// A PEM RSA key, PKCS#1 (what GitHub hands out) or PKCS#8; `\n` escapes
+/// are taken as newlines so the key fits in one env line
+fn parse_private_key(pem: &str) -> Result<RsaKeyPair, String> {
+    let pem = pem.replace("\\n", "\n");
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("-----"))
+        .collect();
+    let der = BASE64
+        .decode(body)
+        .map_err(|e| format!("GITHUB_APP_PRIVATE_KEY is not a PEM key: {}", e))?;
+    let parsed = if pem.contains("BEGIN RSA PRIVATE KEY") {
+        RsaKeyPair::from_der(&der)
+    } else {
+        RsaKeyPair::from_pkcs8(&der)
+    };
+    parsed.map_err(|e| format!("GITHUB_APP_PRIVATE_KEY is not a usable RSA key: {}", e))
+}
+
+#[derive(Serialize)]
+struct CommitStatus {
+    state: &'static str,
+    description: String,
+    context: String,
+    target_url: String,
+}
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct LinkRepoRequest {
+    /// `owner/name`; the owner must be the caller's GitHub login
+    pub repo: String,
+    /// The source file, from the repo root
+    pub path: String,
+    /// Defaults to the repo's default branch
+    #[serde(default)]
+    pub branch: Option<String>,
+    pub language: String,
+    #[serde(default)]
+    pub optimization: Option<String>,
+    /// Exactly one of these
+    #[serde(default)]
+    pub benchmark_id: Option<String>,
+    #[serde(default)]
+    pub challenge_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepoLinkInfo {
+    #[serde(flatten)]
+    pub link: RepoLink,
+    /// Newest first
+    pub history: Vec<RepoPush>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepoLinksResponse {
+    pub repos: Vec<RepoLinkInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnlinkRepoResponse {
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+    /// Links the delivery started runs for
+    pub queued: usize,
+}
+
+/// The parts of a push event we read
+#[derive(Deserialize)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: String,
+    #[serde(default)]
+    deleted: bool,
+    repository: Repository,
+    installation: Option<Installation>,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    full_name: String,
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+// ============ Handlers ============
+
+fn github_app(state: &AppState) -> Result<&GitHubApp, ApiError> {
+    state
+        .github_app
+        .as_ref()
+        .ok_or_else(|| ApiError::Forbidden("GitHub integration is not configured".to_string()))
+}
+
+/// The caller's repo links, each with its latest push
+pub async fn list_links(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<RepoLinksResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let mut repos = Vec::new();
+    for link in db::list_repo_links(pool, &user.id).await? {
+        let history = db::list_repo_pushes(pool, &link.id, MAX_HISTORY).await?;
+        repos.push(RepoLinkInfo { link, history });
+    }
+
+    Ok(Json(RepoLinksResponse { repos }))
+}
+
+/// Link a file in one of the caller's repos, or change what an existing
+/// link to it runs
+pub async fn link(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(req): Json<LinkRepoRequest>,
+) -> Result<Json<RepoLinkInfo>, ApiError> {
+    github_app(&state)?;
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let repo = req.repo.trim().trim_end_matches(".git").to_ascii_lowercase();
+    let Some((owner, name)) = repo.split_once('/').filter(|(o, n)| !o.is_empty() && !n.is_empty() && !n.contains('/'))
+    else {
+        return Err(ApiError::InvalidField(format!("repo must be owner/name, got '{}'", req.repo)));
+    };
+    let login = user
+        .github_login
+        .as_deref()
+        .ok_or_else(|| ApiError::Forbidden("Sign in with GitHub to link repos".to_string()))?;
+    if !owner.eq_ignore_ascii_case(login) {
+        return Err(ApiError::Forbidden(format!("{}/{} isn't one of your repos", owner, name)));
+    }
+
+    let path = req.path.trim().trim_start_matches('/');
+    if path.is_empty() {
+        return Err(ApiError::MissingField("path"));
+    }
+    check_path(path)?;
+    let branch = req.branch.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    let language = Language::from_str(&req.language).ok_or_else(|| ApiError::InvalidLanguage(req.language.clone()))?;
+    let optimization = req
+        .optimization
+        .as_deref()
+        .map(|o| {
+            Optimization::from_str(o).ok_or_else(|| ApiError::InvalidField(format!("unknown optimization: {}", o)))
+        })
+        .transpose()?;
+
+    match (req.benchmark_id.as_deref(), req.challenge_id.as_deref()) {
+        (Some(benchmark_id), None) => {
+            if crate::find_benchmark(benchmark_id).is_none() {
+                return Err(ApiError::NotFound(format!("Benchmark '{}' not found", benchmark_id)));
+            }
+        }
+        (None, Some(challenge_id)) => {
+            if db::get_challenge(pool, challenge_id).await?.is_none() {
+                return Err(ApiError::ChallengeNotFound(challenge_id.to_string()));
+            }
+        }
+        _ => {
+            return Err(ApiError::InvalidField(
+                "give exactly one of benchmark_id and challenge_id".to_string(),
+            ))
+        }
+    }
+
+    let existing = db::list_repo_links(pool, &user.id).await?;
+    if !existing.iter().any(|l| l.repo == repo && l.path == path) {
+        let count = existing.len() as u64;
+        if count >= MAX_REPO_LINKS_PER_USER {
+            return Err(ApiError::LimitExceeded {
+                limit: "repo_links",
+                value: count + 1,
+                max: MAX_REPO_LINKS_PER_USER,
+            });
+        }
+    }
+
+    let link = db::upsert_repo_link(
+        pool,
+        &user.id,
+        &repo,
+        path,
+        branch,
+        language.as_str(),
+        optimization.map(|o| o.as_str()),
+        req.benchmark_id.as_deref(),
+        req.challenge_id.as_deref(),
+    )
+    .await?;
+    info!(user_id = %user.id, repo = %link.repo, path = %link.path, "Repo linked");
+
+    let history = db::list_repo_pushes(pool, &link.id, MAX_HISTORY).await?;
+    Ok(Json(RepoLinkInfo { link, history }))
+}
+
+pub async fn unlink(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(link_id): Path<Uuid>,
+) -> Result<Json<UnlinkRepoResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let deleted = db::delete_repo_link(pool, &user.id, &link_id).await?;
+    Ok(Json(UnlinkRepoResponse { deleted }))
+}
+
+/// GitHub's deliveries; pushes are answered right away and run in the
+/// background
+pub async fn webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<WebhookResponse>, ApiError> {
+    let app = github_app(&state)?;
+    let signature = headers
+        .get("x-hub-signature-256")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !app.verify(signature, &body) {
+        return Err(ApiError::Unauthorized("Bad webhook signature".to_string()));
+    }
+
+    let event = headers.get("x-github-event").and_then(|v| v.to_str().ok()).unwrap_or("");
+    if event != "push" {
+        // `ping` on setup, and whatever else the app was subscribed to
+        return Ok(Json(WebhookResponse { queued: 0 }));
+    }
+    let delivery = headers
+        .get("x-github-delivery")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .ok_or(ApiError::MissingField("X-GitHub-Delivery"))?;
+    let push: PushEvent =
+        serde_json::from_slice(&body).map_err(|e| ApiError::InvalidField(format!("Unexpected push event: {}", e)))?;
+    let (Some(branch), Some(installation)) = (push.git_ref.strip_prefix("refs/heads/"), push.installation) else {
+        return Ok(Json(WebhookResponse { queued: 0 }));
+    };
+    if push.deleted || push.after.bytes().all(|b| b == b'0') {
+        return Ok(Json(WebhookResponse { queued: 0 }));
+    }
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let repo = push.repository.full_name.to_ascii_lowercase();
+    let links: Vec<RepoLink> = db::list_repo_links_for_repo(pool, &repo)
+        .await?
+        .into_iter()
+        .filter(|l| l.branch.as_deref().unwrap_or(&push.repository.default_branch) == branch)
+        .collect();
+    if !db::record_github_delivery(pool, delivery).await? {
+        info!(repo = %repo, delivery, "Push already delivered");
+        return Ok(Json(WebhookResponse { queued: 0 }));
+    }
+    let queued = links.len();
+    info!(repo = %repo, branch, sha = %push.after, links = queued, "Push received");
+
+    for link in links {
+        let state = state.clone();
+        let (full_name, sha) = (push.repository.full_name.clone(), push.after.clone());
+        tokio::spawn(async move {
+            run_push(&state, installation.id, &full_name, &sha, link).await;
+        });
+    }
+
+    Ok(Json(WebhookResponse { queued }))
+}
+
+// ============ Push runs ============
+
+/// What the link runs against, for the status context and its link
+fn target(link: &RepoLink) -> (String, String) {
+    let frontend = crate::embed::frontend_url();
+    match (&link.benchmark_id, &link.challenge_id) {
+        (_, Some(challenge_id)) => (challenge_id.clone(), format!("{}/challenges/{}", frontend, challenge_id)),
+        (Some(benchmark_id), None) => (benchmark_id.clone(), format!("{}/benchmarks", frontend)),
+        (None, None) => (String::new(), frontend),
+    }
+}
+
+async fn run_push(state: &AppState, installation_id: u64, repo: &str, sha: &str, link: RepoLink) {
+    let (Some(app), Some(pool)) = (state.github_app.as_ref(), state.db.as_ref()) else {
+        return;
+    };
+    let token = match app.installation_token(installation_id).await {
+        Ok(token) => token,
+        Err(e) => {
+            warn!(repo, installation_id, error = %e, "No installation token for push");
+            return;
+        }
+    };
+
+    let (name, target_url) = target(&link);
+    let status = |state: &'static str, description: String| CommitStatus {
+        state,
+        description: description.chars().take(MAX_DESCRIPTION).collect(),
+        context: format!("ctf-arena/{}", name),
+        target_url: target_url.clone(),
+    };
+    app.post_status(&token, repo, sha, &status("pending", format!("Running {}", link.path)))
+        .await;
+
+    let mut push = RepoPush {
+        link_id: link.id,
+        commit_sha: sha.to_string(),
+        run_id: None,
+        submission_id: None,
+        instructions: None,
+        error: None,
+        created_at: Utc::now(),
+    };
+    if let Err(e) = run_link(state, pool, app, &token, repo, sha, &link, &mut push).await {
+        push.error = Some(e.to_string());
+    }
+
+    let (state_name, description) = match (push.instructions, &push.error) {
+        (Some(instructions), None) => ("success", format!("{} instructions", instructions)),
+        (_, Some(error)) => ("failure", error.clone()),
+        (None, None) => ("error", "No instruction count".to_string()),
+    };
+    app.post_status(&token, repo, sha, &status(state_name, description)).await;
+
+    if let Err(e) = db::record_repo_push(pool, &push).await {
+        warn!(link_id = %link.id, error = %e, "Failed to record repo push");
+    }
+    info!(
+        repo,
+        sha,
+        path = %link.path,
+        target = %name,
+        instructions = ?push.instructions,
+        error = ?push.error,
+        "Repo push run"
+    );
+}
+
+/// Fetch, build and run the linked file at `sha`, filling in `push`
+#[allow(clippy::too_many_arguments)]
+async fn run_link(
+    state: &AppState,
+    pool: &sqlx::PgPool,
+    app: &GitHubApp,
+    token: &str,
+    repo: &str,
+    sha: &str,
+    link: &RepoLink,
+    push: &mut RepoPush,
+) -> Result<(), ApiError> {
+    let source_code = app.fetch_file(token, repo, &link.path, sha).await?;
+    let user = db::get_user_by_id(pool, &link.user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Link owner".to_string()))?;
+
+    if let Some(challenge_id) = &link.challenge_id {
+        let submission = challenges::submit_on_behalf(
+            state,
+            &user,
+            challenge_id,
+            &link.language,
+            &source_code,
+            link.optimization.as_deref(),
+        )
+        .await?;
+        push.submission_id = Some(submission.id);
+        push.instructions = submission.instructions;
+        if submission.status != "passed" {
+            push.error = Some(submission.error_message.unwrap_or_else(|| format!("Submission {}", submission.status)));
+        }
+        return Ok(());
+    }
+
+    let benchmark_id = link.benchmark_id.as_deref().unwrap_or_default();
+    let benchmark = crate::find_benchmark(benchmark_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Benchmark '{}' no longer exists", benchmark_id)))?;
+    let queue = state
+        .queue
+        .as_ref()
+        .ok_or_else(|| ApiError::QueueError("Queue not available".to_string()))?;
+    if let Some(language) = Language::from_str(&link.language) {
+        crate::limits::check_source(&state.config, language, &source_code)?;
+    }
+
+    let solution = Solution {
+        user_id: link.user_id,
+        language: &link.language,
+        optimization: link.optimization.as_deref(),
+        source_code: &source_code,
+        charged: true,
+    };
+    let outcome = tracked::run_solution(state, pool, queue, &solution, &benchmark).await;
+    push.run_id = outcome.run_id;
+    push.instructions = outcome.instructions;
+    push.error = outcome.error;
+    Ok(())
+}
+
+/// A file's path in the repo: `/`-separated names, none of them empty, `.`
+/// or `..`, and nothing GitHub would read as a query, fragment or escape
+fn check_path(path: &str) -> Result<(), ApiError> {
+    if path.contains(['?', '#', '%']) {
+        return Err(ApiError::InvalidField(format!("path can't contain '?', '#' or '%', got '{}'", path)));
+    }
+    if path.split('/').any(|segment| matches!(segment, "" | "." | "..")) {
+        return Err(ApiError::InvalidField(format!("path must be a file in the repo, got '{}'", path)));
+    }
+    Ok(())
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_file_paths() {
+        assert!(check_path("main.rs").is_ok());
+        assert!(check_path("src/bin/solve.c").is_ok());
+        assert!(check_path(".github/solution.py").is_ok());
+    }
+
+    #[test]
+    fn rejects_paths_that_leave_the_contents_route() {
+        for path in ["../../user", "src/../../x", "./main.rs", "src//main.rs", "src/", "a?ref=main", "a#b", "%2e%2e/x"] {
+            assert!(matches!(check_path(path), Err(ApiError::InvalidField(_))), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Reproduction kits: `GET /runs/:id/repro.tar.gz` bundles everything needed
+//! to re-run a recorded run outside the arena.
+//!
+//! The archive is assembled on request from what is already stored: the run
+//! row (source, limits, sandbox image digest, expected result), the binary and
+//! its compile metadata if it hasn't been cleaned up yet, and the stdin/env/args
+//! the job ran with. Inputs come from the `/submit` record, or from the benchmark
+//! definition for benchmark runs; challenge inputs (which carry flags) are
+//! never included.
+
+use crate::auth::MaybeAuthenticatedUser;
+use crate::config::Config;
+use crate::db::{self, Run};
+use crate::error::ApiError;
+use crate::queue::Language;
+use crate::sandbox::{self, Limits};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// What the binary was run with, as far as the server knows
+#[derive(Debug, Default)]
+pub struct Inputs {
+    pub stdin: Vec<u8>,
+    pub env_vars: BTreeMap<String, String>,
+    pub args: Vec<String>,
+    pub timeout_ms: Option<u64>,
+    /// Whether stdin/env were actually recorded rather than assumed empty
+    pub known: bool,
+}
+
+async fn load_inputs(pool: &sqlx::PgPool, run: &Run) -> Result<Inputs, ApiError> {
+    if let Some((stdin, env_vars, timeout_ms)) = db::get_submission_inputs(pool, &run.job_id).await? {
+        if stdin.is_some() || env_vars.is_some() {
+            return Ok(Inputs {
+                stdin: stdin.unwrap_or_default(),
+                env_vars: env_vars.and_then(|v| serde_json::from_value(v).ok()).unwrap_or_default(),
+                args: run.args.clone().and_then(|v| serde_json::from_value(v).ok()).unwrap_or_default(),
+                timeout_ms: timeout_ms.map(|t| t as u64),
+                known: true,
+            });
+        }
+    }
+
+    let benchmark = run
+        .benchmark_id
+        .as_ref()
+        .and_then(|id| crate::get_benchmarks_config().into_iter().find(|b| &b.id == id));
+    Ok(match benchmark {
+        Some(benchmark) => Inputs {
+            stdin: benchmark.stdin.unwrap_or_default().into_bytes(),
+            env_vars: benchmark.env_vars.into_iter().collect(),
+            args: Vec::new(),
+            timeout_ms: None,
+            known: true,
+        },
+        None => Inputs::default(),
+    })
+}
+
+/// Single-quote for POSIX sh
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn reproduce_script(run: &Run, limits: &Limits, image: &str, inputs: &Inputs, source_file: Option<&str>) -> String {
+    let env_args: String = inputs
+        .env_vars
+        .iter()
+        .map(|(k, v)| format!("  -e {} \\\n", shell_quote(&format!("{}={}", k, v))))
+        .collect();
+    let args: String = inputs.args.iter().map(|a| format!(" {}", shell_quote(a))).collect();
+    // The arena's own flags, so the script can't drift from the sandbox
+    let filesystem_args: String = sandbox::FILESYSTEM_ARGS.iter().map(|a| format!("  {} \\\n", a)).collect();
+    let rebuild = match source_file {
+        Some(file) => format!("rebuild it from {} (see compile.json) and save it as ./binary", file),
+        None => "it can't be rebuilt from this kit".to_string(),
+    };
+
+    format!(
+        r#"#!/bin/sh
+# Re-run {run_id} in the sandbox image it originally ran in, with the same
+# limits, environment, arguments and stdin. Set SANDBOX_IMAGE to override the image.
+# Compare the "instructions" line on stderr with run.json.
+set -eu
+cd "$(dirname "$0")"
+
+IMAGE="${{SANDBOX_IMAGE:-{image}}}"
+
+if [ ! -f binary ]; then
+  echo "binary is no longer stored on the server; {rebuild}" >&2
+  exit 1
+fi
+
+exec docker run --rm -i \
+  --memory={memory}m --memory-swap={memory}m \
+  --network=none \
+{filesystem_args}{env_args}  -e LIMIT={instructions} \
+  -e TIMEOUT_MS={timeout_ms} \
+  -v "$PWD/binary:/work/binary:ro" \
+  "$IMAGE"{args} < stdin
+"#,
+        run_id = run.id,
+        image = image,
+        memory = limits.memory_mb,
+        instructions = limits.instructions,
+        timeout_ms = limits.timeout_ms,
+    )
+}
+
+fn readme(run: &Run, inputs: &Inputs, has_binary: bool, source_file: Option<&str>) -> String {
+    let mut lines = vec![
+        format!("# Reproduction kit for run {}", run.id),
+        String::new(),
+        "Run `./reproduce.sh` (needs Docker). It starts the same sandbox image with the".to_string(),
+        "same instruction, time and memory limits and feeds `stdin` to the binary.".to_string(),
+        String::new(),
+        "- `run.json`: limits, arguments, sandbox image and the result to compare against".to_string(),
+        "- `env.json`: environment variables the binary ran with".to_string(),
+        "- `stdin`: what was piped to the binary".to_string(),
+    ];
+    if has_binary {
+        lines.push("- `binary`: the exact binary that ran".to_string());
+    }
+    if let Some(file) = source_file {
+        lines.push(format!("- `{}`: source as submitted", file));
+        lines.push("- `compile.json`: language, optimization, compiler version, flags and image digest".to_string());
+    }
+    if !has_binary {
+        lines.push(String::new());
+        lines.push("The binary has been cleaned up on the server, so it has to be rebuilt".to_string());
+        lines.push("before `reproduce.sh` can run.".to_string());
+    }
+    if !inputs.known {
+        lines.push(String::new());
+        lines.push("This run's stdin and environment weren't recorded; both are left empty.".to_string());
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+pub(crate) fn append(
+    tar: &mut tar::Builder<GzEncoder<Vec<u8>>>,
+    prefix: &str,
+    name: &str,
+    mode: u32,
+    mtime: u64,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    tar.append_data(&mut header, format!("{}/{}", prefix, name), data)
+}
+
+/// Build the `.tar.gz`; everything sits under a `repro-<run id>/` directory
+pub fn build_archive(
+    run: &Run,
+    inputs: &Inputs,
+    binary: Option<&[u8]>,
+    binary_metadata: Option<&db::BinaryMetadata>,
+    config: &Config,
+) -> std::io::Result<Vec<u8>> {
+    let limits = sandbox::limits(
+        run.instruction_limit.map(|l| l as u64).unwrap_or(config.default_instruction_limit),
+        inputs.timeout_ms,
+        run.memory_limit_mb.map(|m| m as u32),
+        config,
+    );
+    let image = run.sandbox_image.as_deref().unwrap_or(&config.sandbox_image);
+    let source_file = run.source_code.as_ref().map(|_| {
+        let extension = run
+            .language
+            .as_deref()
+            .and_then(|language| Language::resolve(language, run.runtime.as_deref()))
+            .map(|l| l.source_extension())
+            .unwrap_or("txt");
+        format!("source.{}", extension)
+    });
+
+    let run_json = json!({
+        "run_id": run.id,
+        "job_id": run.job_id,
+        "binary_id": run.binary_id,
+        "benchmark_id": run.benchmark_id,
+        "sandbox_image": image,
+        "args": inputs.args,
+        "limits": {
+            "instructions": limits.instructions,
+            "timeout_ms": limits.timeout_ms,
+            "memory_mb": limits.memory_mb,
+        },
+        "expected": {
+            "instructions": run.instructions,
+            "exit_code": run.exit_code,
+            "limit_reached": run.limit_reached,
+            "limit_reached_reason": run.limit_reached_reason,
+        },
+        "created_at": run.created_at,
+    });
+    let compile_json = json!({
+        "language": run.language,
+        "runtime": run.runtime,
+        "optimization": run.optimization,
+        "compiler_version": run
+            .compiler_version
+            .clone()
+            .or_else(|| binary_metadata.and_then(|m| m.compiler_version.clone())),
+        "compile_flags": binary_metadata.and_then(|m| m.compile_flags.clone()),
+        "compiler_image": binary_metadata.and_then(|m| m.compiler_image.clone()),
+        "toolchain": run
+            .toolchain
+            .clone()
+            .or_else(|| binary_metadata.and_then(|m| m.toolchain.clone())),
+    });
+
+    let prefix = format!("repro-{}", run.id);
+    let mtime = run.created_at.timestamp().max(0) as u64;
+    let to_bytes = |v: &serde_json::Value| serde_json::to_vec_pretty(v).unwrap_or_default();
+
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    append(&mut tar, &prefix, "README.md", 0o644, mtime, readme(run, inputs, binary.is_some(), source_file.as_deref()).as_bytes())?;
+    append(
+        &mut tar,
+        &prefix,
+        "reproduce.sh",
+        0o755,
+        mtime,
+        reproduce_script(run, &limits, image, inputs, source_file.as_deref()).as_bytes(),
+    )?;
+    append(&mut tar, &prefix, "run.json", 0o644, mtime, &to_bytes(&run_json))?;
+    append(&mut tar, &prefix, "env.json", 0o644, mtime, &to_bytes(&json!(inputs.env_vars)))?;
+    append(&mut tar, &prefix, "stdin", 0o644, mtime, &inputs.stdin)?;
+    if let (Some(file), Some(source)) = (&source_file, &run.source_code) {
+        append(&mut tar, &prefix, file, 0o644, mtime, source.as_bytes())?;
+        append(&mut tar, &prefix, "compile.json", 0o644, mtime, &to_bytes(&compile_json))?;
+    }
+    if let Some(binary) = binary {
+        append(&mut tar, &prefix, "binary", 0o755, mtime, binary)?;
+    }
+
+    let mut gz = tar.into_inner()?;
+    gz.flush()?;
+    gz.finish()
+}
+
+// ============ Handlers ============
+
+pub async fn get_repro(
+    State(state): State<Arc<crate::AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+    Path(run_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = db::get_run(pool, &run_id)
+        .await?
+        .filter(|run| crate::can_view_run(&state, run, user.as_ref()))
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))?;
+    let inputs = load_inputs(pool, &run).await?;
+    let binary = db::get_binary(pool, &run.binary_id).await?;
+    let binary_metadata = db::get_binary_metadata(pool, &run.binary_id).await?;
+
+    let archive = build_archive(&run, &inputs, binary.as_deref(), binary_metadata.as_ref(), &state.config)
+        .map_err(|e| ApiError::Internal(format!("Failed to build reproduction kit: {}", e)))?;
+
+    let disposition = format!("attachment; filename=\"repro-{}.tar.gz\"", run.id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/gzip")),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition).expect("uuid filename is a valid header"),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
@@ -0,0 +1,49 @@
+//! Request IDs for log correlation.
+//!
+//! Every request runs in a `request` span with `request_id`, `method`,
+//! `path` and, once the session is looked up, `user_id`, so each line logged
+//! while handling it carries them. The ID is returned in `x-request-id`. A
+//! caller's own `x-request-id` (up to 64 letters, digits, `-` and `_`) is
+//! kept, so an ID can be followed from a proxy or client into these logs.
+
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field, info_span, Instrument};
+use uuid::Uuid;
+
+pub const HEADER: &str = "x-request-id";
+
+const MAX_LEN: usize = 64;
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+pub async fn request_span(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        user_id = field::Empty,
+    );
+    let mut response = next.run(req).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
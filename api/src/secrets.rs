@@ -0,0 +1,111 @@
+//! Challenge env vars (usually the flag) encrypted at rest.
+//!
+//! With `CHALLENGE_ENV_KEY` set (32 bytes, base64), `challenges.env_vars`
+//! holds `{"sealed": "<base64 nonce || AES-256-GCM ciphertext>"}` instead of
+//! the plain map. The map is only opened to build sandbox jobs; it never
+//! appears in API responses (`Challenge::env_vars` isn't serialized). Without
+//! a key, values stay plaintext and the API warns at startup.
+
+use crate::config::Config;
+use crate::db;
+use crate::error::ApiError;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+const SEALED_FIELD: &str = "sealed";
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone)]
+pub struct EnvVarCipher {
+    cipher: Option<Aes256Gcm>,
+}
+
+impl EnvVarCipher {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let Some(encoded) = config.challenge_env_key.as_deref() else {
+            return Ok(Self { cipher: None });
+        };
+        let key = BASE64
+            .decode(encoded.trim())
+            .map_err(|e| format!("CHALLENGE_ENV_KEY is not base64: {}", e))?;
+        if key.len() != 32 {
+            return Err(format!("CHALLENGE_ENV_KEY must decode to 32 bytes, got {}", key.len()));
+        }
+        Ok(Self {
+            cipher: Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Encrypt an env var map for storage; returned unchanged without a key
+    pub fn seal(&self, env_vars: &Value) -> Result<Value, ApiError> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(env_vars.clone());
+        };
+        if is_sealed(env_vars) {
+            return Ok(env_vars.clone());
+        }
+
+        let plaintext = serde_json::to_vec(env_vars).map_err(|e| ApiError::Internal(e.to_string()))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| ApiError::Internal("Failed to encrypt challenge env vars".to_string()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(json!({ SEALED_FIELD: BASE64.encode(payload) }))
+    }
+
+    /// The env var map as stored, decrypting it if sealed
+    pub fn open(&self, stored: &Value) -> Result<HashMap<String, String>, ApiError> {
+        let Some(sealed) = stored.get(SEALED_FIELD).and_then(Value::as_str) else {
+            return serde_json::from_value(stored.clone())
+                .map_err(|e| ApiError::Internal(format!("Invalid challenge env vars: {}", e)));
+        };
+        let cipher = self
+            .cipher
+            .as_ref()
+            .ok_or_else(|| ApiError::Internal("Challenge env vars are sealed but CHALLENGE_ENV_KEY is unset".to_string()))?;
+
+        let payload = BASE64
+            .decode(sealed)
+            .map_err(|_| ApiError::Internal("Sealed challenge env vars are corrupt".to_string()))?;
+        if payload.len() < NONCE_LEN {
+            return Err(ApiError::Internal("Sealed challenge env vars are corrupt".to_string()));
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| ApiError::Internal("Failed to decrypt challenge env vars (wrong CHALLENGE_ENV_KEY?)".to_string()))?;
+        serde_json::from_slice(&plaintext).map_err(|e| ApiError::Internal(format!("Invalid challenge env vars: {}", e)))
+    }
+}
+
+pub fn is_sealed(stored: &Value) -> bool {
+    stored.get(SEALED_FIELD).is_some_and(Value::is_string)
+}
+
+/// Encrypt any challenge env vars still stored in plaintext; returns how many
+pub async fn seal_stored(pool: &PgPool, cipher: &EnvVarCipher) -> Result<u64, ApiError> {
+    if !cipher.is_enabled() {
+        return Ok(0);
+    }
+
+    let mut sealed = 0;
+    for (challenge_id, env_vars) in db::get_challenge_env_vars(pool).await? {
+        if is_sealed(&env_vars) {
+            continue;
+        }
+        db::set_challenge_env_vars(pool, &challenge_id, &cipher.seal(&env_vars)?).await?;
+        sealed += 1;
+    }
+    Ok(sealed)
+}
@@ -0,0 +1,89 @@
+//! CORS policies per route group and standard security response headers.
+//!
+//! Routes fall into three groups (see `crate::router`):
+//! - app: the web frontend, credentialed CORS for `CORS_APP_ORIGINS`
+//! - public: badges, stats, digests, embeds and the sandbox manifest, GET-only
+//!   CORS without cookies for `CORS_PUBLIC_ORIGINS` (any origin by default)
+//! - internal: endpoints only workers call (binary storage, saving runs), no
+//!   CORS at all so browsers can't reach them cross-origin
+//!
+//! Every response gets `X-Content-Type-Options: nosniff`, HSTS unless
+//! `HSTS_MAX_AGE_SECONDS=0`, and `X-Frame-Options: DENY` except on the embed
+//! routes, which exist to be framed.
+
+use crate::config::Config;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// Credentialed CORS for the frontend; with credentials, origins can't be a wildcard
+pub fn app_cors(config: &Config) -> CorsLayer {
+    let origins: Vec<HeaderValue> = config
+        .cors_app_origins
+        .iter()
+        .filter_map(|origin| origin.parse().ok())
+        .collect();
+
+    CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_origin(origins)
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::AUTHORIZATION,
+            header::COOKIE,
+            HeaderName::from_static(crate::anonymous::TOKEN_HEADER),
+        ])
+        .allow_credentials(true)
+}
+
+/// GET-only, no cookies; any origin unless `CORS_PUBLIC_ORIGINS` narrows it
+pub fn public_cors(config: &Config) -> CorsLayer {
+    let origin = if config.cors_public_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(config.cors_public_origins.iter().filter_map(|origin| origin.parse().ok()))
+    };
+
+    CorsLayer::new().allow_origin(origin).allow_methods([Method::GET])
+}
+
+/// Which headers [`security_headers`] adds for a route group
+#[derive(Debug, Clone)]
+pub struct HeaderPolicy {
+    hsts: Option<HeaderValue>,
+    deny_framing: bool,
+}
+
+impl HeaderPolicy {
+    pub fn new(config: &Config, deny_framing: bool) -> Self {
+        let hsts = (config.hsts_max_age_seconds > 0).then(|| {
+            HeaderValue::from_str(&format!("max-age={}; includeSubDomains", config.hsts_max_age_seconds))
+                .expect("numeric HSTS value is a valid header")
+        });
+        Self { hsts, deny_framing }
+    }
+}
+
+/// Middleware adding the policy's headers, leaving any a handler set alone
+pub async fn security_headers(State(policy): State<HeaderPolicy>, request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    headers
+        .entry(header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    if let Some(hsts) = policy.hsts {
+        headers.entry(header::STRICT_TRANSPORT_SECURITY).or_insert(hsts);
+    }
+    if policy.deny_framing {
+        headers
+            .entry(header::X_FRAME_OPTIONS)
+            .or_insert(HeaderValue::from_static("DENY"));
+    }
+    response
+}
@@ -0,0 +1,255 @@
+//! Share pages for runs, with OpenGraph tags for link previews.
+//!
+//! `GET /share/runs/:id` returns a small self-contained HTML page whose
+//! `og:*` and `twitter:*` tags summarize the run (language, instructions,
+//! rank among public runs of the same benchmark), so pasting the link into
+//! chat or social sites unfurls into a card. `?format=json` returns the same
+//! summary for a frontend server that renders its own page.
+//!
+//! `POST /runs/:id/share` hands out a short `/s/:code` link that redirects to
+//! the share page; a run keeps one code, so asking again returns the same
+//! link. Only public runs can be shared; private ones read as missing.
+
+use crate::badge::escape;
+use crate::db::{self, Run, RunVisibility};
+use crate::embed::{frontend_url, group_digits};
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderValue},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+    Json, Router,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const CACHE_CONTROL: &str = "public, max-age=300";
+const CODE_LENGTH: usize = 8;
+const CODE_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Html,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareQuery {
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub run_id: Uuid,
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    pub instructions: i64,
+    pub benchmark_id: Option<String>,
+    pub benchmark_name: Option<String>,
+    /// Among public clean runs of the benchmark in this language
+    pub rank: Option<i64>,
+    pub ranked_runs: Option<i64>,
+    pub username: Option<String>,
+    /// The OpenGraph title and description the HTML page uses
+    pub title: String,
+    pub description: String,
+    /// This share page, and where its link sends people
+    pub share_url: String,
+    pub site_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareLinkResponse {
+    pub code: String,
+    pub short_url: String,
+    pub share_url: String,
+}
+
+/// Share pages and short-link redirects; mounted with the public CORS policy
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/share/runs/:id", get(share_run))
+        .route("/s/:code", get(follow_short_link))
+}
+
+/// Where the share routes are reachable from outside (`SHARE_BASE_URL`)
+fn base_url(state: &AppState) -> String {
+    match &state.config.share_base_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => format!("{}/api", frontend_url()),
+    }
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+async fn load_public_run(pool: &PgPool, run_id: &Uuid) -> Result<Run, ApiError> {
+    db::get_run(pool, run_id)
+        .await?
+        .filter(|run| run.visibility == RunVisibility::Public.as_str())
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))
+}
+
+async fn summarize(state: &AppState, pool: &PgPool, run: Run) -> Result<RunSummary, ApiError> {
+    let rank = db::get_run_rank(pool, &run).await?;
+    let username = match &run.user_id {
+        Some(user_id) => db::get_user_by_id(pool, user_id).await?.map(|u| u.username),
+        None => None,
+    };
+    let benchmark = run.benchmark_id.as_deref().and_then(crate::find_benchmark);
+
+    let language = match (&run.language, &run.runtime) {
+        (Some(language), Some(runtime)) => format!("{} ({})", language, runtime),
+        (Some(language), None) => language.clone(),
+        (None, _) => "Unknown language".to_string(),
+    };
+    let title = match &benchmark {
+        Some(b) => format!("{}: {} instructions in {}", b.name, group_digits(run.instructions), language),
+        None => format!("{} instructions in {}", group_digits(run.instructions), language),
+    };
+    let mut description = match rank {
+        Some((rank, of)) => format!("Ranked #{} of {} public {} runs.", rank, of, language),
+        None => format!("A {} run on CTF Arena.", language),
+    };
+    if let Some(username) = &username {
+        description.push_str(&format!(" Submitted by {}.", username));
+    }
+
+    let site_url = match &benchmark {
+        Some(_) => format!("{}/benchmarks", frontend_url()),
+        None => frontend_url(),
+    };
+
+    Ok(RunSummary {
+        run_id: run.id,
+        share_url: format!("{}/share/runs/{}", base_url(state), run.id),
+        language: run.language,
+        runtime: run.runtime,
+        instructions: run.instructions,
+        benchmark_id: run.benchmark_id,
+        benchmark_name: benchmark.map(|b| b.name),
+        rank: rank.map(|(rank, _)| rank),
+        ranked_runs: rank.map(|(_, of)| of),
+        username,
+        title,
+        description,
+        site_url,
+    })
+}
+
+fn render(summary: &RunSummary) -> String {
+    let rank = match (summary.rank, summary.ranked_runs) {
+        (Some(rank), Some(of)) => format!(r#"<p class="muted">Rank #{} of {}</p>"#, rank, of),
+        _ => String::new(),
+    };
+
+    format!(
+        r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<meta name="description" content="{description}">
+<meta property="og:type" content="website">
+<meta property="og:site_name" content="CTF Arena">
+<meta property="og:title" content="{title}">
+<meta property="og:description" content="{description}">
+<meta property="og:url" content="{share_url}">
+<meta name="twitter:card" content="summary">
+<meta name="twitter:title" content="{title}">
+<meta name="twitter:description" content="{description}">
+<link rel="canonical" href="{share_url}">
+<style>
+body{{margin:0;padding:24px;background:#0d1117;color:#e6edf3;font:15px/1.5 -apple-system,BlinkMacSystemFont,"Segoe UI",Helvetica,Arial,sans-serif}}
+h1{{font-size:18px;margin:0 0 4px}}
+.num{{font-size:32px;font-weight:600;font-variant-numeric:tabular-nums;margin:8px 0}}
+.muted{{color:#7d8590;margin:4px 0}}
+a{{color:#4493f8;text-decoration:none}}
+</style></head>
+<body><h1>{title}</h1>
+<p class="num">{instructions} instructions</p>
+{rank}<p class="muted">{description}</p>
+<p><a href="{site_url}">Try to beat it on CTF Arena &rarr;</a></p>
+</body></html>
+"#,
+        title = escape(&summary.title),
+        description = escape(&summary.description),
+        share_url = escape(&summary.share_url),
+        site_url = escape(&summary.site_url),
+        instructions = group_digits(summary.instructions),
+    )
+}
+
+// ============ Handlers ============
+
+async fn share_run(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<Uuid>,
+    Query(query): Query<ShareQuery>,
+) -> Result<Response, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run = load_public_run(pool, &run_id).await?;
+    let summary = summarize(&state, pool, run).await?;
+    let mut response = match query.format {
+        Format::Html => Html(render(&summary)).into_response(),
+        Format::Json => Json(summary).into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    Ok(response)
+}
+
+async fn follow_short_link(
+    State(state): State<Arc<AppState>>,
+    Path(code): Path<String>,
+) -> Result<Redirect, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let run_id = db::get_share_link_run(pool, &code)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Share link '{}' not found", code)))?;
+    Ok(Redirect::to(&format!("{}/share/runs/{}", base_url(&state), run_id)))
+}
+
+/// Short link for a public run; the same one every time
+pub async fn create_share_link(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<ShareLinkResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    load_public_run(pool, &run_id).await?;
+    let code = db::get_or_create_share_link(pool, &run_id, &generate_code()).await?;
+
+    info!(run_id = %run_id, code = %code, "Share link issued");
+
+    let base = base_url(&state);
+    Ok(Json(ShareLinkResponse {
+        short_url: format!("{}/s/{}", base, code),
+        share_url: format!("{}/share/runs/{}", base, run_id),
+        code,
+    }))
+}
@@ -0,0 +1,137 @@
+//! Stars: per-user bookmarks on runs and challenges.
+//!
+//! Starring is idempotent (`PUT` to star, `DELETE` to unstar) and only works
+//! on runs the caller can see. Each run and challenge carries a `star_count`;
+//! `/my/stars` lists what the caller has bookmarked, dropping runs that have
+//! since gone private.
+
+use crate::auth::AuthenticatedUser;
+use crate::challenges::ChallengeInfo;
+use crate::db::{self, Run};
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// ============ Response Types ============
+
+#[derive(Debug, Serialize)]
+pub struct StarResponse {
+    pub starred: bool,
+    pub star_count: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MyStarsResponse {
+    pub runs: Vec<Run>,
+    pub challenges: Vec<ChallengeInfo>,
+}
+
+// ============ Handlers ============
+
+pub async fn star_run(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<StarResponse>, ApiError> {
+    set_run_star(&state, &run_id, &user, true).await
+}
+
+pub async fn unstar_run(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<StarResponse>, ApiError> {
+    set_run_star(&state, &run_id, &user, false).await
+}
+
+async fn set_run_star(
+    state: &AppState,
+    run_id: &Uuid,
+    user: &db::User,
+    starred: bool,
+) -> Result<Json<StarResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    db::get_run(pool, run_id)
+        .await?
+        .filter(|run| crate::can_view_run(state, run, Some(user)))
+        .ok_or_else(|| ApiError::NotFound(format!("Run '{}' not found", run_id)))?;
+
+    let star_count = if starred {
+        db::star_run(pool, &user.id, run_id).await?
+    } else {
+        db::unstar_run(pool, &user.id, run_id).await?
+    };
+
+    Ok(Json(StarResponse { starred, star_count }))
+}
+
+pub async fn star_challenge(
+    State(state): State<Arc<AppState>>,
+    Path(challenge_id): Path<String>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<StarResponse>, ApiError> {
+    set_challenge_star(&state, challenge_id, &user, true).await
+}
+
+pub async fn unstar_challenge(
+    State(state): State<Arc<AppState>>,
+    Path(challenge_id): Path<String>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<StarResponse>, ApiError> {
+    set_challenge_star(&state, challenge_id, &user, false).await
+}
+
+async fn set_challenge_star(
+    state: &AppState,
+    challenge_id: String,
+    user: &db::User,
+    starred: bool,
+) -> Result<Json<StarResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    if db::get_challenge(pool, &challenge_id).await?.is_none() {
+        return Err(ApiError::ChallengeNotFound(challenge_id));
+    }
+
+    let star_count = if starred {
+        db::star_challenge(pool, &user.id, &challenge_id).await?
+    } else {
+        db::unstar_challenge(pool, &user.id, &challenge_id).await?
+    };
+
+    Ok(Json(StarResponse { starred, star_count }))
+}
+
+/// The caller's bookmarks, most recently starred first
+pub async fn my_stars(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<MyStarsResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let is_admin = state.config.admin_users.contains(&user.username);
+    let runs = db::list_starred_runs(pool, &user.id, is_admin).await?;
+    let challenges = db::list_starred_challenges(pool, &user.id)
+        .await?
+        .into_iter()
+        .map(ChallengeInfo::from)
+        .collect();
+
+    Ok(Json(MyStarsResponse { runs, challenges }))
+}
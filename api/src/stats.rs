@@ -0,0 +1,331 @@
+//! Coarse public numbers for third-party dashboards and the landing page.
+//!
+//! A background task recomputes every aggregate into the `public_stats` table
+//! each `STATS_REFRESH_SECONDS`; the unauthenticated `/public/stats*`
+//! endpoints only ever read that table, so a burst of dashboard traffic never
+//! scans `runs`. Responses carry `Cache-Control` and are rate limited per
+//! client IP.
+//!
+//! The language shootout (`/benchmarks/composite`) is refreshed the same way:
+//! per benchmark, each language's best run is divided by the best in any
+//! language, and a language's score is the geometric mean of those ratios.
+
+use crate::config::Config;
+use crate::db;
+use crate::error::ApiError;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Days covered by the runs-per-day series
+pub const RUNS_PER_DAY_WINDOW: i64 = 30;
+/// "This week" for active users
+pub const ACTIVE_USER_DAYS: i64 = 7;
+/// Benchmark whose per-language minimum is published
+pub const HELLO_WORLD_BENCHMARK: &str = "hello-world";
+
+const SUMMARY: &str = "summary";
+const RUNS_PER_DAY: &str = "runs_per_day";
+const HELLO_WORLD: &str = "hello_world";
+const COMPOSITE: &str = "composite";
+
+const CACHE_CONTROL: &str = "public, max-age=60";
+/// Forget idle clients once the limiter tracks this many addresses
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub total_runs: i64,
+    pub active_users_this_week: i64,
+    /// Stars on public runs and challenges
+    pub total_stars: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunsPerDay {
+    pub days: Vec<DayCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayCount {
+    pub day: NaiveDate,
+    pub total: i64,
+    pub languages: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HelloWorld {
+    /// Fewest instructions per language among runs that didn't hit a limit
+    pub languages: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Composite {
+    /// Benchmarks with any results at their current version
+    pub benchmarks: Vec<String>,
+    /// Most benchmarks covered first, then best score
+    pub languages: Vec<CompositeScore>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompositeScore {
+    pub language: String,
+    /// 1.0 when the language is the fastest on every benchmark it ran
+    pub score: f64,
+    /// How many of `benchmarks` the score covers
+    pub benchmarks: usize,
+}
+
+/// Score every language over the per-benchmark minimums
+pub fn composite(per_benchmark: Vec<(String, HashMap<String, i64>)>) -> Composite {
+    let mut benchmarks = Vec::new();
+    // language -> (sum of ln(ratio), benchmarks)
+    let mut logs: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+    for (benchmark_id, min_instructions) in per_benchmark {
+        let Some(best) = min_instructions.values().copied().filter(|&n| n > 0).min() else {
+            continue;
+        };
+        benchmarks.push(benchmark_id);
+        for (language, instructions) in min_instructions.into_iter().filter(|(_, n)| *n > 0) {
+            let entry = logs.entry(language).or_insert((0.0, 0));
+            entry.0 += (instructions as f64 / best as f64).ln();
+            entry.1 += 1;
+        }
+    }
+
+    let mut languages: Vec<CompositeScore> = logs
+        .into_iter()
+        .map(|(language, (sum, count))| CompositeScore {
+            language,
+            score: (sum / count as f64).exp(),
+            benchmarks: count,
+        })
+        .collect();
+    languages.sort_by(|a, b| b.benchmarks.cmp(&a.benchmarks).then(a.score.total_cmp(&b.score)));
+    Composite { benchmarks, languages }
+}
+
+// ============ Refresh ============
+
+pub fn spawn(pool: PgPool, interval: Duration) {
+    tokio::spawn(async move {
+        // First tick fires immediately so the endpoints have data after startup
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match refresh(&pool).await {
+                Ok(()) => info!("Public stats refreshed"),
+                Err(e) => error!("Public stats refresh failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Recompute every aggregate and store it in `public_stats`
+pub async fn refresh(pool: &PgPool) -> Result<(), ApiError> {
+    let summary = Summary {
+        total_runs: db::count_runs(pool).await?,
+        active_users_this_week: db::count_active_users(pool, ACTIVE_USER_DAYS).await?,
+        total_stars: db::count_stars(pool).await?,
+    };
+
+    let mut days: BTreeMap<NaiveDate, DayCount> = BTreeMap::new();
+    for (day, language, count) in db::count_runs_per_language_per_day(pool, RUNS_PER_DAY_WINDOW).await? {
+        let entry = days.entry(day).or_insert_with(|| DayCount {
+            day,
+            total: 0,
+            languages: BTreeMap::new(),
+        });
+        entry.total += count;
+        entry.languages.insert(language, count);
+    }
+    let runs_per_day = RunsPerDay {
+        days: days.into_values().collect(),
+    };
+
+    let hello_world_version = crate::current_benchmark_version(HELLO_WORLD_BENCHMARK).unwrap_or(1);
+    let hello_world = HelloWorld {
+        languages: db::get_min_instructions(pool, HELLO_WORLD_BENCHMARK, hello_world_version)
+            .await?
+            .into_iter()
+            .collect(),
+    };
+
+    let mut per_benchmark = Vec::new();
+    for (benchmark_id, version) in crate::benchmark_versions() {
+        let min_instructions = db::get_min_instructions(pool, &benchmark_id, version).await?;
+        per_benchmark.push((benchmark_id, min_instructions));
+    }
+    let composite = composite(per_benchmark);
+
+    store(pool, SUMMARY, &summary).await?;
+    store(pool, RUNS_PER_DAY, &runs_per_day).await?;
+    store(pool, HELLO_WORLD, &hello_world).await?;
+    store(pool, COMPOSITE, &composite).await?;
+    Ok(())
+}
+
+async fn store<T: Serialize>(pool: &PgPool, name: &str, value: &T) -> Result<(), ApiError> {
+    let data = serde_json::to_value(value)
+        .map_err(|e| ApiError::Internal(format!("Failed to serialize {} stats: {}", name, e)))?;
+    db::store_public_stat(pool, name, &data).await
+}
+
+// ============ Rate Limiting ============
+
+/// Fixed one-minute window per client IP, kept in memory (stats requests
+/// are anonymous, so the per-user `rate_limits` table doesn't apply; also
+/// throttles clanker verification)
+pub struct IpRateLimiter {
+    limit_per_minute: u32,
+    windows: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl IpRateLimiter {
+    /// `limit_per_minute` of 0 disables the limit
+    pub fn new(limit_per_minute: u32) -> Self {
+        Self {
+            limit_per_minute,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn check(&self, ip: IpAddr) -> Result<(), ApiError> {
+        match self.excess(ip) {
+            0 => Ok(()),
+            _ => Err(ApiError::RateLimited),
+        }
+    }
+
+    /// Count a request from `ip`; how far past the limit its window now is (0 = allowed)
+    pub fn excess(&self, ip: IpAddr) -> u32 {
+        if self.limit_per_minute == 0 {
+            return 0;
+        }
+        let now = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= MAX_TRACKED_CLIENTS {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < minute);
+        }
+
+        let (start, count) = windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= minute {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        count.saturating_sub(self.limit_per_minute)
+    }
+}
+
+/// The socket peer, or with `TRUST_PROXY_HEADERS` the address our own proxy
+/// saw (last `X-Forwarded-For` hop). Without a proxy in front the header is
+/// entirely client-supplied, and earlier hops always are.
+pub(crate) fn client_ip(config: &Config, headers: &HeaderMap, peer: Option<SocketAddr>) -> IpAddr {
+    let forwarded = || {
+        headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok())
+    };
+    config
+        .trust_proxy_headers
+        .then(forwarded)
+        .flatten()
+        .or(peer.map(|p| p.ip()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+// ============ Handlers ============
+
+/// Public stats routes; mounted with the public CORS policy
+pub fn router() -> Router<Arc<crate::AppState>> {
+    Router::new()
+        .route("/public/stats", get(get_summary))
+        .route("/public/stats/runs-per-day", get(get_runs_per_day))
+        .route("/public/stats/hello-world", get(get_hello_world))
+        .route("/benchmarks/composite", get(get_composite))
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    data: serde_json::Value,
+    refreshed_at: DateTime<Utc>,
+}
+
+async fn serve_stat(
+    state: &crate::AppState,
+    headers: &HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    name: &str,
+) -> Result<Response, ApiError> {
+    state
+        .public_stats_limiter
+        .check(client_ip(&state.config, headers, peer.map(|ConnectInfo(addr)| addr)))?;
+
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let (data, refreshed_at) = db::get_public_stat(pool, name)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Stats have not been computed yet".to_string()))?;
+
+    let mut response = Json(StatsResponse { data, refreshed_at }).into_response();
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    Ok(response)
+}
+
+/// Total runs and active users this week
+async fn get_summary(
+    State(state): State<Arc<crate::AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    serve_stat(&state, &headers, peer, SUMMARY).await
+}
+
+/// Runs per language per UTC day over the last 30 days
+async fn get_runs_per_day(
+    State(state): State<Arc<crate::AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    serve_stat(&state, &headers, peer, RUNS_PER_DAY).await
+}
+
+/// Fastest hello-world per language
+async fn get_hello_world(
+    State(state): State<Arc<crate::AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    serve_stat(&state, &headers, peer, HELLO_WORLD).await
+}
+
+/// Language shootout across all benchmarks
+async fn get_composite(
+    State(state): State<Arc<crate::AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    serve_stat(&state, &headers, peer, COMPOSITE).await
+}
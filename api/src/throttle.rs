@@ -0,0 +1,132 @@
+//! Abuse guards for the clanker verification endpoints.
+//!
+//! Both endpoints are throttled per client IP and refuse users who are
+//! locked out. A pending code takes up to `VERIFICATION_MAX_ATTEMPTS` wrong
+//! guesses; the last one burns it and locks the user out, for
+//! `VERIFICATION_LOCKOUT_BASE_SECONDS` the first time and twice as long each
+//! time after (up to a day) until they verify. With `CAPTCHA_SECRET` set,
+//! checks also need a CAPTCHA token. Every rejection goes to the audit log.
+
+use crate::audit;
+use crate::config::Config;
+use crate::db::{self, VerificationCode};
+use crate::error::ApiError;
+use crate::AppState;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longest a lockout gets, however many came before it
+const MAX_LOCKOUT_SECONDS: u64 = 24 * 60 * 60;
+
+/// Turnstile's siteverify endpoint; hCaptcha and reCAPTCHA take the same request
+pub const DEFAULT_CAPTCHA_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// Per-IP throttle and lockout check, run before anything else
+pub async fn guard(state: &AppState, pool: &PgPool, user_id: &Uuid, ip: IpAddr, endpoint: &str) -> Result<(), ApiError> {
+    let excess = state.verification_limiter.excess(ip);
+    if excess > 0 {
+        // Only the first rejection per window, so a flood can't flood the audit log
+        if excess == 1 {
+            let details = json!({ "endpoint": endpoint });
+            audit::record(pool, "clanker_verification.rate_limited", Some(user_id), Some(ip), details).await;
+        }
+        return Err(ApiError::RateLimited);
+    }
+
+    if let Some(locked_until) = db::get_verification_locked_until(pool, user_id).await? {
+        return Err(ApiError::LockedOut {
+            retry_after: (locked_until - Utc::now()).num_seconds().max(1) as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Count a wrong code; past the attempt limit the code is burned and the user locked out
+pub async fn wrong_code(
+    config: &Config,
+    pool: &PgPool,
+    user_id: &Uuid,
+    ip: IpAddr,
+    verification: &VerificationCode,
+) -> ApiError {
+    let attempts = match db::record_verification_attempt(pool, &verification.id).await {
+        Ok(attempts) => attempts.max(0) as u32,
+        Err(e) => return e,
+    };
+    let details = json!({ "twitter_handle": verification.twitter_handle, "attempts": attempts });
+    audit::record(pool, "clanker_verification.wrong_code", Some(user_id), Some(ip), details).await;
+
+    if attempts < config.verification_max_attempts {
+        let left = config.verification_max_attempts - attempts;
+        return ApiError::Forbidden(format!("Verification code does not match ({} attempts left)", left));
+    }
+
+    let locked_until = match lock_out(config, pool, verification).await {
+        Ok(locked_until) => locked_until,
+        Err(e) => return e,
+    };
+    let retry_after = (locked_until - Utc::now()).num_seconds().max(1) as u64;
+    let details = json!({ "twitter_handle": verification.twitter_handle, "locked_seconds": retry_after });
+    audit::record(pool, "clanker_verification.locked_out", Some(user_id), Some(ip), details).await;
+    ApiError::LockedOut { retry_after }
+}
+
+async fn lock_out(
+    config: &Config,
+    pool: &PgPool,
+    verification: &VerificationCode,
+) -> Result<chrono::DateTime<Utc>, ApiError> {
+    db::expire_verification_code(pool, &verification.id).await?;
+    db::lock_verification(
+        pool,
+        &verification.user_id,
+        config.verification_lockout_base_seconds,
+        MAX_LOCKOUT_SECONDS,
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct SiteverifyResponse {
+    success: bool,
+}
+
+/// Check a CAPTCHA token with the provider; a no-op without `CAPTCHA_SECRET`.
+/// A failed check is audited as `event`.
+pub async fn verify_captcha(
+    config: &Config,
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    ip: IpAddr,
+    token: Option<&str>,
+    event: &str,
+) -> Result<(), ApiError> {
+    let Some(secret) = config.captcha_secret.as_deref() else {
+        return Ok(());
+    };
+    let token = token.filter(|t| !t.is_empty()).ok_or(ApiError::MissingField("captcha_token"))?;
+
+    let remote_ip = ip.to_string();
+    let response = reqwest::Client::new()
+        .post(&config.captcha_verify_url)
+        .form(&[("secret", secret), ("response", token), ("remoteip", remote_ip.as_str())])
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| ApiError::Internal(format!("CAPTCHA provider unreachable: {}", e)))?;
+    let verified = response
+        .json::<SiteverifyResponse>()
+        .await
+        .map_err(|e| ApiError::Internal(format!("Unexpected CAPTCHA provider response: {}", e)))?;
+
+    if !verified.success {
+        audit::record(pool, event, user_id, Some(ip), json!({})).await;
+        return Err(ApiError::Forbidden("CAPTCHA check failed".to_string()));
+    }
+    Ok(())
+}
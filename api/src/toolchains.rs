@@ -0,0 +1,149 @@
+//! Toolchain versions a compile job can pick, where the compiler image has
+//! more than one installed (rust stable and nightly, Python 3.12 and 3.11
+//! for Nuitka).
+//!
+//! `/compile` takes an optional `toolchain`; asking for the default is the
+//! same as leaving it out, so those builds share compile-cache entries. The
+//! container gets it as `TOOLCHAIN` and records what it used, which ends up
+//! in the binary's metadata and in its runs. `GET /languages` lists every
+//! language with its toolchains, and for the JavaScript family its runtime;
+//! members of an organization only see what its policy allows.
+
+use crate::auth::MaybeAuthenticatedUser;
+use crate::error::ApiError;
+use crate::orgs::{self, OrgPolicy};
+use crate::queue::Language;
+use crate::AppState;
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Toolchain {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// First entry is the default; must match what `compiler/Dockerfile` installs
+const TOOLCHAINS: &[(Language, &[Toolchain])] = &[
+    (
+        Language::Rust,
+        &[
+            Toolchain { name: "stable", description: "Latest stable Rust" },
+            Toolchain { name: "nightly", description: "Nightly Rust, for unstable features" },
+        ],
+    ),
+    (
+        Language::Python,
+        &[
+            Toolchain { name: "3.12", description: "CPython 3.12 with Nuitka" },
+            Toolchain { name: "3.11", description: "CPython 3.11 with Nuitka" },
+        ],
+    ),
+];
+
+pub fn for_language(language: Language) -> &'static [Toolchain] {
+    TOOLCHAINS
+        .iter()
+        .find(|(l, _)| *l == language)
+        .map(|(_, toolchains)| *toolchains)
+        .unwrap_or(&[])
+}
+
+/// The toolchain to put on a compile job: `None` for the default
+pub fn resolve(language: Language, requested: &str) -> Result<Option<String>, ApiError> {
+    let requested = requested.trim();
+    if requested.is_empty() {
+        return Ok(None);
+    }
+
+    let toolchains = for_language(language);
+    let Some(index) = toolchains.iter().position(|t| t.name == requested) else {
+        let available: Vec<&str> = toolchains.iter().map(|t| t.name).collect();
+        return Err(ApiError::InvalidField(if available.is_empty() {
+            format!("{} has no toolchain choices", language.as_str())
+        } else {
+            format!(
+                "toolchain '{}' isn't available for {} ({})",
+                requested,
+                language.as_str(),
+                available.join(", ")
+            )
+        }));
+    };
+    Ok((index > 0).then(|| requested.to_string()))
+}
+
+// ============ Response Types ============
+
+#[derive(Debug, Serialize)]
+pub struct LanguagesResponse {
+    pub languages: Vec<LanguageInfo>,
+    /// Set when the caller's organization restricts what they may compile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyInfo {
+    pub organization: String,
+    #[serde(flatten)]
+    pub policy: OrgPolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LanguageInfo {
+    pub id: &'static str,
+    /// The language as written; differs from `id` for the runtime-named ids
+    pub language: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    pub extension: &'static str,
+    /// Empty when the image has only one
+    pub toolchains: Vec<ToolchainInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolchainInfo {
+    #[serde(flatten)]
+    pub toolchain: Toolchain,
+    pub default: bool,
+}
+
+// ============ Handlers ============
+
+pub async fn list_languages(
+    State(state): State<Arc<AppState>>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+) -> Result<Json<LanguagesResponse>, ApiError> {
+    let policy = match (&user, &state.db) {
+        (Some(user), Some(pool)) => orgs::policy_for(pool, &user.id).await?,
+        _ => None,
+    };
+
+    let languages = Language::ALL
+        .iter()
+        .filter(|language| policy.as_ref().is_none_or(|(_, p)| p.allows_language(**language)))
+        .map(|language| LanguageInfo {
+            id: language.as_str(),
+            language: language.canonical(),
+            runtime: language.runtime(),
+            deprecated: Language::deprecation(language.as_str()),
+            extension: language.source_extension(),
+            toolchains: for_language(*language)
+                .iter()
+                .enumerate()
+                .map(|(i, toolchain)| ToolchainInfo {
+                    toolchain: *toolchain,
+                    default: i == 0,
+                })
+                .collect(),
+        })
+        .collect();
+
+    let policy = policy.map(|(organization, policy)| PolicyInfo { organization, policy });
+
+    Ok(Json(LanguagesResponse { languages, policy }))
+}
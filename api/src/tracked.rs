@@ -0,0 +1,466 @@
+//! Tracked solutions: CI for golfed benchmark solutions.
+//!
+//! A user registers one source per benchmark with `PUT /my/tracked/:id`.
+//! Every `TRACKED_RERUN_DAYS` a background pass recompiles each tracked
+//! solution with the current default toolchain, runs it against the
+//! benchmark's current stdin and env, and appends the instruction count (or
+//! what went wrong) to its history. When the count moves from the previous
+//! re-run at the same benchmark version, or the solution stops building or
+//! running, the owner gets a `tracked_solution` notification. Tracking and
+//! every re-run are held to the owner's organization policy (orgs.rs), so a
+//! solution the policy stops allowing fails its next re-run.
+//!
+//! Re-runs are the server's doing, so they don't count against the user's
+//! compile quota (repo pushes, which share the build and run, do); the runs
+//! they leave are private. Solutions are claimed with
+//! `FOR UPDATE SKIP LOCKED`, so several API replicas don't re-run one twice.
+
+use crate::auth::AuthenticatedUser;
+use crate::db::{self, RunVisibility, TrackedSolution, TrackedSolutionRun};
+use crate::error::ApiError;
+use crate::limits;
+use crate::notifications::{self, NotificationKind};
+use crate::orgs;
+use crate::queue::{CompileJob, Job, Language, Optimization, QueueClient};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use ctf_common::diagnostics::ExecutionMode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// How often the task looks for solutions that are due
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+/// Solutions re-run per pass; the rest wait for the next one
+const RERUN_BATCH: i64 = 20;
+pub const MAX_TRACKED_PER_USER: u64 = 20;
+/// Re-runs returned with a solution, newest first (a year of weekly ones)
+const MAX_HISTORY: i64 = 52;
+const COMPILE_WAIT: Duration = Duration::from_secs(120);
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct TrackRequest {
+    pub source_code: String,
+    pub language: String,
+    #[serde(default)]
+    pub optimization: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackedSolutionInfo {
+    #[serde(flatten)]
+    pub solution: TrackedSolution,
+    /// Newest first
+    pub history: Vec<TrackedSolutionRun>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackedListResponse {
+    pub tracked: Vec<TrackedSolutionInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UntrackResponse {
+    pub deleted: bool,
+}
+
+// ============ Handlers ============
+
+async fn with_history(pool: &PgPool, solution: TrackedSolution, limit: i64) -> Result<TrackedSolutionInfo, ApiError> {
+    let history = db::list_tracked_solution_runs(pool, &solution.id, limit).await?;
+    Ok(TrackedSolutionInfo { solution, history })
+}
+
+/// The caller's tracked solutions, each with its latest re-run
+pub async fn list_tracked(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<TrackedListResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let mut tracked = Vec::new();
+    for solution in db::list_tracked_solutions(pool, &user.id).await? {
+        tracked.push(with_history(pool, solution, 1).await?);
+    }
+
+    Ok(Json(TrackedListResponse { tracked }))
+}
+
+pub async fn get_tracked(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(benchmark_id): Path<String>,
+) -> Result<Json<TrackedSolutionInfo>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let solution = db::get_tracked_solution(pool, &user.id, &benchmark_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No tracked solution for benchmark '{}'", benchmark_id)))?;
+
+    Ok(Json(with_history(pool, solution, MAX_HISTORY).await?))
+}
+
+/// Track a solution, or replace the tracked one; a different source,
+/// language or optimization starts a fresh history
+pub async fn track(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(benchmark_id): Path<String>,
+    Json(req): Json<TrackRequest>,
+) -> Result<Json<TrackedSolutionInfo>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    if crate::find_benchmark(&benchmark_id).is_none() {
+        return Err(ApiError::NotFound(format!("Benchmark '{}' not found", benchmark_id)));
+    }
+    if req.source_code.trim().is_empty() {
+        return Err(ApiError::MissingField("source_code"));
+    }
+    let language = Language::from_str(&req.language).ok_or_else(|| ApiError::InvalidLanguage(req.language.clone()))?;
+    limits::check_source(&state.config, language, &req.source_code)?;
+    let optimization = req
+        .optimization
+        .as_deref()
+        .map(|o| {
+            Optimization::from_str(o).ok_or_else(|| ApiError::InvalidField(format!("unknown optimization: {}", o)))
+        })
+        .transpose()?;
+    let source = Solution {
+        user_id: user.id,
+        language: language.as_str(),
+        optimization: optimization.map(|o| o.as_str()),
+        source_code: &req.source_code,
+        charged: false,
+    };
+    orgs::enforce(pool, &compile_job(&source, language)).await?;
+
+    match db::get_tracked_solution(pool, &user.id, &benchmark_id).await? {
+        Some(existing)
+            if existing.source_code == req.source_code
+                && existing.language == language.as_str()
+                && existing.optimization.as_deref() == optimization.map(|o| o.as_str()) =>
+        {
+            return Ok(Json(with_history(pool, existing, MAX_HISTORY).await?));
+        }
+        Some(_) => {
+            db::delete_tracked_solution(pool, &user.id, &benchmark_id).await?;
+        }
+        None => {
+            let count = db::count_tracked_solutions(pool, &user.id).await? as u64;
+            if count >= MAX_TRACKED_PER_USER {
+                return Err(ApiError::LimitExceeded {
+                    limit: "tracked_solutions",
+                    value: count + 1,
+                    max: MAX_TRACKED_PER_USER,
+                });
+            }
+        }
+    }
+
+    let solution = db::upsert_tracked_solution(
+        pool,
+        &user.id,
+        &benchmark_id,
+        language.as_str(),
+        optimization.map(|o| o.as_str()),
+        &req.source_code,
+    )
+    .await?;
+    info!(user_id = %user.id, benchmark_id = %benchmark_id, language = language.as_str(), "Solution tracked");
+
+    Ok(Json(TrackedSolutionInfo {
+        solution,
+        history: Vec::new(),
+    }))
+}
+
+pub async fn untrack(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(benchmark_id): Path<String>,
+) -> Result<Json<UntrackResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let deleted = db::delete_tracked_solution(pool, &user.id, &benchmark_id).await?;
+    Ok(Json(UntrackResponse { deleted }))
+}
+
+// ============ Scheduling ============
+
+pub fn spawn(state: Arc<AppState>, interval_days: u64) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let (Some(pool), Some(queue)) = (state.db.as_ref(), state.queue.as_ref()) else {
+                return;
+            };
+            if let Err(e) = rerun_due(&state, pool, queue, interval_days).await {
+                error!("Tracked solution re-runs failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Re-run the solutions that are due, one batch at a time
+async fn rerun_due(state: &AppState, pool: &PgPool, queue: &QueueClient, interval_days: u64) -> Result<(), ApiError> {
+    loop {
+        let due = db::claim_due_tracked_solutions(pool, interval_days, RERUN_BATCH).await?;
+        if due.is_empty() {
+            return Ok(());
+        }
+        for solution in &due {
+            if let Err(e) = rerun(state, pool, queue, solution).await {
+                warn!(
+                    user_id = %solution.user_id,
+                    benchmark_id = %solution.benchmark_id,
+                    error = %e,
+                    "Tracked solution re-run not recorded"
+                );
+            }
+        }
+    }
+}
+
+/// Compile and run one solution, record the outcome and tell the owner if
+/// it changed
+async fn rerun(
+    state: &AppState,
+    pool: &PgPool,
+    queue: &QueueClient,
+    solution: &TrackedSolution,
+) -> Result<(), ApiError> {
+    let benchmark = crate::find_benchmark(&solution.benchmark_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Benchmark '{}' no longer exists", solution.benchmark_id)))?;
+    let previous = db::list_tracked_solution_runs(pool, &solution.id, 1).await?.pop();
+
+    let source = Solution {
+        user_id: solution.user_id,
+        language: &solution.language,
+        optimization: solution.optimization.as_deref(),
+        source_code: &solution.source_code,
+        charged: false,
+    };
+    let outcome = run_solution(state, pool, queue, &source, &benchmark).await;
+    let run = TrackedSolutionRun {
+        tracked_solution_id: solution.id,
+        run_id: outcome.run_id,
+        benchmark_version: benchmark.version as i32,
+        compiler_version: outcome.compiler_version,
+        instructions: outcome.instructions,
+        error: outcome.error,
+        created_at: Utc::now(),
+    };
+    db::record_tracked_solution_run(pool, &run).await?;
+    info!(
+        user_id = %solution.user_id,
+        benchmark_id = %solution.benchmark_id,
+        instructions = ?run.instructions,
+        error = ?run.error,
+        "Tracked solution re-run"
+    );
+
+    if let Some((title, body)) = describe_change(solution, previous.as_ref(), &run) {
+        notifications::notify(
+            pool,
+            state.mailer.as_ref(),
+            &solution.user_id,
+            NotificationKind::TrackedSolution,
+            &title,
+            &body,
+            Some("/benchmarks"),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// A source to build and run against a benchmark on its owner's behalf
+pub struct Solution<'a> {
+    pub user_id: Uuid,
+    pub language: &'a str,
+    pub optimization: Option<&'a str>,
+    pub source_code: &'a str,
+    /// Charged to the owner's compile quota, as for a repo push; re-runs are
+    /// the server's doing
+    pub charged: bool,
+}
+
+/// What building and running a solution came to; either `instructions` or
+/// `error` is set
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub run_id: Option<Uuid>,
+    pub compiler_version: Option<String>,
+    pub instructions: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Compile `solution` with the default toolchain and run it against
+/// `benchmark`'s stdin and env as a private run. Also used for repo pushes
+/// (repos.rs), which are `charged`.
+pub(crate) async fn run_solution(
+    state: &AppState,
+    pool: &PgPool,
+    queue: &QueueClient,
+    solution: &Solution<'_>,
+    benchmark: &crate::BenchmarkDef,
+) -> Outcome {
+    let mut outcome = Outcome::default();
+    if let Err(e) = execute(state, pool, queue, solution, benchmark, &mut outcome).await {
+        outcome.error = Some(e.to_string());
+    }
+    outcome
+}
+
+/// The build of `solution`: default flags, image and toolchain
+fn compile_job(solution: &Solution<'_>, language: Language) -> CompileJob {
+    CompileJob {
+        id: Uuid::new_v4(),
+        user_id: Some(solution.user_id),
+        source_code: solution.source_code.to_string(),
+        language,
+        optimization: solution
+            .optimization
+            .and_then(Optimization::from_str)
+            .unwrap_or(Optimization::Release),
+        flags: HashMap::new(),
+        compiler_image: None,
+        emit_asm: false,
+        build_options: Default::default(),
+        toolchain: None,
+        retry_failed: false,
+        created_at: Utc::now(),
+    }
+}
+
+/// Fill in `outcome` from a fresh build and execution; errors are what it
+/// records as its `error`
+async fn execute(
+    state: &AppState,
+    pool: &PgPool,
+    queue: &QueueClient,
+    solution: &Solution<'_>,
+    benchmark: &crate::BenchmarkDef,
+    run: &mut Outcome,
+) -> Result<(), ApiError> {
+    let language =
+        Language::from_str(solution.language).ok_or_else(|| ApiError::InvalidLanguage(solution.language.to_string()))?;
+    let compile_job = compile_job(solution, language);
+    orgs::enforce(pool, &compile_job).await?;
+    if solution.charged {
+        limits::check_compile_quota(&state.config, pool, queue, &solution.user_id).await?;
+    }
+    let compile_job_id = compile_job.id;
+    queue.submit_compile_job(compile_job).await?;
+    if solution.charged {
+        limits::reserve_compile(pool, &solution.user_id, &compile_job_id, language, COMPILE_WAIT).await?;
+    }
+    let compiled = queue.await_compile(&compile_job_id, COMPILE_WAIT).await?;
+    if solution.charged {
+        limits::settle_compile(pool, &compile_job_id, &compiled).await?;
+    }
+    run.compiler_version = db::get_binary_metadata(pool, &compiled.binary_id)
+        .await?
+        .and_then(|m| m.compiler_version);
+
+    let job = Job {
+        id: Uuid::new_v4(),
+        user_id: Some(solution.user_id),
+        binary_id: compiled.binary_id,
+        instruction_limit: state.config.max_instruction_limit,
+        stdin: benchmark.stdin.clone().unwrap_or_default().into_bytes(),
+        created_at: Utc::now(),
+        benchmark_id: Some(benchmark.id.clone()),
+        benchmark_version: Some(benchmark.version),
+        network_enabled: false,
+        env_vars: benchmark.env_vars.clone(),
+        args: Vec::new(),
+        outputs: Vec::new(),
+        core_dump: false,
+        mode: ExecutionMode::Normal,
+        fixtures: None,
+        sandbox_options: None,
+        sandbox_signature: None,
+        timeout_ms: None,
+        memory_limit_mb: None,
+        visibility: RunVisibility::Private,
+        challenge_id: None,
+    };
+    let job_id = job.id;
+    queue.submit_job(job).await?;
+    // Queue time on top of the run's own wall-clock limit
+    let result = queue
+        .await_job(&job_id, Duration::from_secs(state.config.timeout_sec + 60))
+        .await?;
+    run.run_id = db::get_run_by_job_id(pool, &job_id).await?.map(|r| r.id);
+
+    if let Some(reason) = result.limit_reached_reason {
+        run.error = Some(format!("limit reached ({})", reason.as_str()));
+    } else if let Some(signal) = &result.crash_signal {
+        run.error = Some(format!("crashed ({})", signal));
+    } else if result.exit_code != 0 {
+        run.error = Some(format!("Exit code: {}", result.exit_code));
+    } else {
+        run.instructions = Some(result.instructions as i64);
+    }
+    Ok(())
+}
+
+/// Title and body of the notification for `run`, if there is news: the
+/// count moved, or the solution started or stopped failing. The first
+/// re-run and re-runs against a new benchmark version only set a baseline.
+fn describe_change(
+    solution: &TrackedSolution,
+    previous: Option<&TrackedSolutionRun>,
+    run: &TrackedSolutionRun,
+) -> Option<(String, String)> {
+    let previous = previous.filter(|p| p.benchmark_version == run.benchmark_version)?;
+    let target = format!("{} ({})", solution.benchmark_id, solution.language);
+    let compiler = match (&previous.compiler_version, &run.compiler_version) {
+        (Some(old), Some(new)) if old != new => format!(" Compiler: {} -> {}.", old, new),
+        (_, Some(new)) => format!(" Compiler: {}.", new),
+        _ => String::new(),
+    };
+
+    match (previous.instructions, run.instructions, &run.error) {
+        (Some(old), Some(new), _) if old != new => {
+            let percent = (new - old) as f64 * 100.0 / old as f64;
+            Some((
+                format!("Tracked {}: {:+.1}% instructions", target, percent),
+                format!("{} -> {} instructions.{}", old, new, compiler),
+            ))
+        }
+        (Some(_), None, Some(error)) => Some((
+            format!("Tracked {} no longer runs", target),
+            format!("{}.{}", error, compiler),
+        )),
+        (None, Some(new), _) => Some((
+            format!("Tracked {} runs again", target),
+            format!("{} instructions.{}", new, compiler),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,161 @@
+//! Per-user usage analytics, ahead of any quota built on them.
+//!
+//! `usage_daily` keeps one row per user and UTC day with API calls, compiles
+//! (time charged, and how many were compile-cache hits) and runs (count and
+//! execution time). Compiles are added when their quota reservation settles
+//! (see limits.rs) and runs when they're first saved, so redelivered jobs
+//! count once. API calls are every request that resolved a logged-in user;
+//! they're counted in memory and written every `FLUSH_INTERVAL`, so a replica
+//! going down loses at most that much.
+//!
+//! `GET /auth/me/analytics?period=30d` returns the caller's totals and daily
+//! series for the last 1-365 days (today included).
+
+use crate::auth::AuthenticatedUser;
+use crate::db::{self, UsageDay, UsageDelta};
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+const MAX_PERIOD_DAYS: i64 = 365;
+
+/// API calls per user since the last flush
+#[derive(Default)]
+pub struct ApiCallCounter {
+    pending: Mutex<HashMap<Uuid, i64>>,
+}
+
+impl ApiCallCounter {
+    pub fn record(&self, user_id: Uuid) {
+        *self.pending.lock().unwrap().entry(user_id).or_default() += 1;
+    }
+
+    fn take(&self) -> HashMap<Uuid, i64> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    /// Put back counts that couldn't be written
+    fn restore(&self, user_id: Uuid, calls: i64) {
+        *self.pending.lock().unwrap().entry(user_id).or_default() += calls;
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(pool) = state.db.as_ref() else {
+                return;
+            };
+            flush(pool, &state.api_calls).await;
+        }
+    });
+}
+
+async fn flush(pool: &PgPool, counter: &ApiCallCounter) {
+    for (user_id, api_calls) in counter.take() {
+        let delta = UsageDelta {
+            api_calls,
+            ..Default::default()
+        };
+        if let Err(e) = db::record_usage(pool, &user_id, &delta).await {
+            warn!(user_id = %user_id, error = %e, "Failed to record API calls");
+            counter.restore(user_id, api_calls);
+        }
+    }
+}
+
+// ============ Handlers ============
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    /// `<days>d`, e.g. `7d` or `30d`
+    pub period: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyticsResponse {
+    pub period_days: i64,
+    /// First UTC day covered
+    pub since: NaiveDate,
+    pub totals: UsageTotals,
+    /// Days with any usage, oldest first
+    pub daily: Vec<UsageDay>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UsageTotals {
+    pub api_calls: i64,
+    pub compiles: i64,
+    pub compile_cache_hits: i64,
+    /// Share of compiles answered from the compile cache; `None` without compiles
+    pub compile_cache_hit_rate: Option<f64>,
+    pub compile_minutes: f64,
+    pub runs: i64,
+    pub execute_seconds: f64,
+}
+
+fn parse_period(period: Option<&str>) -> Result<i64, ApiError> {
+    let Some(period) = period else {
+        return Ok(DEFAULT_PERIOD_DAYS);
+    };
+    period
+        .trim()
+        .strip_suffix('d')
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| (1..=MAX_PERIOD_DAYS).contains(days))
+        .ok_or_else(|| {
+            ApiError::InvalidField(format!("period must be 1d-{}d, e.g. 30d", MAX_PERIOD_DAYS))
+        })
+}
+
+pub async fn get_analytics(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<AnalyticsResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let period_days = parse_period(query.period.as_deref())?;
+    let since = Utc::now().date_naive() - chrono::Duration::days(period_days - 1);
+    let daily = db::get_usage(pool, &user.id, since).await?;
+
+    let mut totals = UsageTotals::default();
+    let (mut compile_ms, mut execute_ms) = (0, 0);
+    for day in &daily {
+        totals.api_calls += day.api_calls;
+        totals.compiles += day.compiles;
+        totals.compile_cache_hits += day.compile_cache_hits;
+        totals.runs += day.runs;
+        compile_ms += day.compile_ms;
+        execute_ms += day.execute_ms;
+    }
+    totals.compile_cache_hit_rate =
+        (totals.compiles > 0).then(|| totals.compile_cache_hits as f64 / totals.compiles as f64);
+    totals.compile_minutes = compile_ms as f64 / 60_000.0;
+    totals.execute_seconds = execute_ms as f64 / 1000.0;
+
+    Ok(Json(AnalyticsResponse {
+        period_days,
+        since,
+        totals,
+        daily,
+    }))
+}
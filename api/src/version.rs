@@ -0,0 +1,91 @@
+//! `GET /version`: exactly what is running, for operators and bug reports.
+//!
+//! The API build (crate version, git SHA, profile, features), the sandbox
+//! and compiler images (tag and resolved digest), the NATS and Postgres
+//! server versions, and the build whose migrations the database last ran.
+//! The git SHA is baked in at compile time from `GIT_SHA`
+//! (`docker build --build-arg GIT_SHA=$(git rev-parse HEAD)`).
+
+use crate::db::{self, SchemaInfo};
+use crate::manifest::{self, ImageInfo};
+use crate::AppState;
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::Arc;
+
+pub const API_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Unset (or empty) for builds outside the Dockerfile
+pub fn git_sha() -> Option<&'static str> {
+    option_env!("GIT_SHA").filter(|sha| !sha.is_empty())
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionResponse {
+    pub api: BuildInfo,
+    pub sandbox: ImageInfo,
+    pub compiler: ImageInfo,
+    pub queue_backend: Option<&'static str>,
+    /// `None` without NATS
+    pub nats_version: Option<String>,
+    /// `None` without a database
+    pub postgres_version: Option<String>,
+    /// The build that last migrated the database; a mismatch with `api`
+    /// means another API version ran migrations since
+    pub schema: Option<SchemaInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: Option<&'static str>,
+    /// `release` or `debug`
+    pub profile: &'static str,
+    /// Cargo features compiled in
+    pub features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        let mut features = Vec::new();
+        if cfg!(feature = "fake-sandbox") {
+            features.push("fake-sandbox");
+        }
+        if cfg!(feature = "legacy-execute") {
+            features.push("legacy-execute");
+        }
+        Self {
+            version: API_VERSION,
+            git_sha: git_sha(),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+            features,
+        }
+    }
+}
+
+pub async fn version(State(state): State<Arc<AppState>>) -> Json<VersionResponse> {
+    let (sandbox_digest, compiler_digest) = manifest::image_digests(&state.config).await;
+
+    let (postgres_version, schema) = match state.db.as_ref() {
+        Some(pool) => (
+            db::get_server_version(pool).await.ok(),
+            db::get_schema_info(pool).await.ok().flatten(),
+        ),
+        None => (None, None),
+    };
+
+    Json(VersionResponse {
+        api: BuildInfo::current(),
+        sandbox: ImageInfo {
+            image: state.config.sandbox_image.clone(),
+            digest: sandbox_digest,
+        },
+        compiler: ImageInfo {
+            image: state.config.compiler_image.clone(),
+            digest: compiler_digest,
+        },
+        queue_backend: state.queue.as_ref().map(|q| q.backend()),
+        nats_version: state.queue.as_ref().and_then(|q| q.server_version()),
+        postgres_version,
+        schema,
+    })
+}
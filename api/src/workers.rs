@@ -0,0 +1,488 @@
+//! Execute workers run by trusted third parties.
+//!
+//! An admin creates a worker with `POST /admin/workers` and hands its
+//! one-time token to the operator. The worker registers with
+//! `POST /workers/register`, declaring its architecture and isolation level,
+//! and gets back where to consume from: the `SHARED_JOBS` stream, which only
+//! carries jobs with nothing secret in them (see [`Job::is_shareable`]). With
+//! `EXTERNAL_WORKERS` unset nothing is published there and in-house workers
+//! take every job as before.
+//!
+//! Registration also issues the worker's NATS credentials: a user JWT for
+//! the public nkey it sent, signed with `EXTERNAL_WORKER_NATS_ACCOUNT_SEED`,
+//! that may only pull from and ack on the shared consumer and receive on
+//! the worker's own inbox, so it sees only the jobs delivered to it. It has
+//! no KV access and expires after [`NATS_CREDENTIALS_TTL`]; each heartbeat
+//! issues a fresh one.
+//!
+//! External workers never see the internal routes. They fetch binaries and
+//! report job status, results and runs through the token-authenticated
+//! `/workers/*` routes, which only accept reports on a job recorded in
+//! `shared_jobs` when the API published it (and claimed by the first worker
+//! to report on it, unless an in-house worker took it), take its owner from
+//! that record rather than the report, and strip any benchmark, so nothing
+//! an external worker measures is verified or can place on a leaderboard. Re-registering is the
+//! heartbeat; revoking a worker (`DELETE /admin/workers/:id`) cuts it off at
+//! its next request and off NATS once its credentials expire.
+//!
+//! [`Job::is_shareable`]: crate::queue::Job::is_shareable
+
+use crate::auth::{generate_session_token, hash_token, AdminUser};
+use crate::db::{self, BinaryMetadata, ExternalWorker, RunVisibility, SaveRunRequest, SharedJob};
+use crate::error::ApiError;
+use crate::queue::{JobStatus, NatsQueue, QueueClient};
+use crate::sandbox::ExecutionResult;
+use crate::AppState;
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, State},
+    http::{header, request::Parts, HeaderMap},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use ctf_common::binary_cache::BinaryCacheStats;
+use ctf_common::topology::{SHARED_JOBS_STREAM, SHARED_WORKER_CONSUMER};
+use nkeys::{KeyPair, KeyPairType};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+/// Architectures the sandbox images are built for
+const ARCHES: &[&str] = &["x86_64", "aarch64"];
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_VERSION_LENGTH: usize = 64;
+/// How often workers should re-register
+const HEARTBEAT_SECONDS: u64 = 60;
+/// How long one registration's NATS credentials last; heartbeats renew them
+/// long before
+pub const NATS_CREDENTIALS_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// How a worker separates jobs from its host, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkerIsolation {
+    /// Plain Docker, as the in-house workers run
+    Container,
+    /// Containers under gVisor
+    Gvisor,
+    /// A VM per job or per worker
+    Vm,
+}
+
+impl WorkerIsolation {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "container" => Some(WorkerIsolation::Container),
+            "gvisor" => Some(WorkerIsolation::Gvisor),
+            "vm" => Some(WorkerIsolation::Vm),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkerIsolation::Container => "container",
+            WorkerIsolation::Gvisor => "gvisor",
+            WorkerIsolation::Vm => "vm",
+        }
+    }
+}
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/workers/register", post(register))
+        .route("/workers/binaries/:binary_id", get(get_binary))
+        .route("/workers/binaries/:binary_id/metadata", get(get_binary_metadata))
+        .route("/workers/jobs/:job_id/status", post(report_status))
+        .route("/workers/jobs/:job_id/result", post(report_result))
+        .route("/workers/runs", post(report_run))
+}
+
+fn pool(state: &AppState) -> Result<&PgPool, ApiError> {
+    state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))
+}
+
+/// Only NATS queues share jobs
+fn nats_queue(state: &AppState) -> Result<&NatsQueue, ApiError> {
+    match state.queue.as_ref() {
+        Some(QueueClient::Nats(queue)) => Ok(queue),
+        _ => Err(ApiError::QueueError("NATS queue not available".to_string())),
+    }
+}
+
+// ============ Worker Extractor ============
+
+/// An active external worker, from `Authorization: Bearer <token>`
+pub struct WorkerAuth(pub ExternalWorker);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for WorkerAuth {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let pool = state
+            .db
+            .as_ref()
+            .ok_or_else(|| ApiError::Unauthorized("Database not available".to_string()))?;
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| ApiError::Unauthorized("No worker token".to_string()))?;
+
+        let worker = db::get_external_worker_by_token_hash(pool, &hash_token(token.trim()))
+            .await?
+            .ok_or_else(|| ApiError::Unauthorized("Invalid or revoked worker token".to_string()))?;
+
+        Ok(WorkerAuth(worker))
+    }
+}
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWorkerRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateWorkerResponse {
+    pub worker: ExternalWorker,
+    /// Shown once; only its hash is stored
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    pub arch: String,
+    pub isolation: WorkerIsolation,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Sent with every heartbeat by workers with a binary cache
+    #[serde(default)]
+    pub binary_cache: Option<BinaryCacheStats>,
+    /// User nkey (`U...`) the worker holds the seed of; its NATS
+    /// credentials are issued for this key
+    pub nats_public_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobStatusReport {
+    /// `running`, `completed` (after its result) or `failed`
+    pub status: JobStatus,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevokeWorkerResponse {
+    pub revoked: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegisterResponse {
+    pub worker_id: Uuid,
+    pub name: String,
+    /// `EXTERNAL_WORKER_NATS_URL`, if the arena hands one out
+    pub nats_url: Option<String>,
+    pub stream: &'static str,
+    pub consumer: &'static str,
+    pub heartbeat_seconds: u64,
+    /// User JWT for `nats_public_key`, valid for [`NATS_CREDENTIALS_TTL`]
+    pub nats_jwt: String,
+    /// The only subjects the credentials may subscribe to
+    pub inbox_prefix: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportJobResponse {
+    pub job_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportRunResponse {
+    pub id: Uuid,
+}
+
+// ============ Admin Handlers ============
+
+pub async fn create_worker(
+    State(state): State<Arc<AppState>>,
+    AdminUser(admin): AdminUser,
+    Json(req): Json<CreateWorkerRequest>,
+) -> Result<Json<CreateWorkerResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let name = req.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiError::InvalidField(format!("name must be 1-{} characters", MAX_NAME_LENGTH)));
+    }
+
+    let token = generate_session_token();
+    let worker = db::create_external_worker(pool, name, &hash_token(&token), &admin.id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidField(format!("worker '{}' already exists", name)))?;
+
+    info!(worker_id = %worker.id, name = %worker.name, admin = %admin.username, "External worker created");
+
+    Ok(Json(CreateWorkerResponse { worker, token }))
+}
+
+pub async fn list_workers(
+    State(state): State<Arc<AppState>>,
+    AdminUser(_admin): AdminUser,
+) -> Result<Json<Vec<ExternalWorker>>, ApiError> {
+    Ok(Json(db::list_external_workers(pool(&state)?).await?))
+}
+
+pub async fn revoke_worker(
+    State(state): State<Arc<AppState>>,
+    AdminUser(admin): AdminUser,
+    Path(worker_id): Path<Uuid>,
+) -> Result<Json<RevokeWorkerResponse>, ApiError> {
+    if !db::revoke_external_worker(pool(&state)?, &worker_id).await? {
+        return Err(ApiError::NotFound(format!("Worker '{}' not found", worker_id)));
+    }
+
+    info!(worker_id = %worker_id, admin = %admin.username, "External worker revoked");
+
+    Ok(Json(RevokeWorkerResponse { revoked: true }))
+}
+
+// ============ Worker Handlers ============
+
+async fn register(
+    State(state): State<Arc<AppState>>,
+    WorkerAuth(worker): WorkerAuth,
+    Json(req): Json<RegisterRequest>,
+) -> Result<Json<RegisterResponse>, ApiError> {
+    if !state.config.external_workers {
+        return Err(ApiError::Forbidden("External workers are disabled".to_string()));
+    }
+    if !ARCHES.contains(&req.arch.as_str()) {
+        return Err(ApiError::InvalidField(format!("arch must be one of {}", ARCHES.join(", "))));
+    }
+    let min_isolation = state.config.external_worker_min_isolation;
+    if req.isolation < min_isolation {
+        return Err(ApiError::Forbidden(format!(
+            "isolation '{}' is below this arena's minimum, '{}'",
+            req.isolation.as_str(),
+            min_isolation.as_str()
+        )));
+    }
+    let version = req.version.as_deref().map(str::trim).filter(|v| !v.is_empty());
+    if version.is_some_and(|v| v.len() > MAX_VERSION_LENGTH) {
+        return Err(ApiError::InvalidField(format!(
+            "version must be at most {} characters",
+            MAX_VERSION_LENGTH
+        )));
+    }
+    if !KeyPair::from_public_key(&req.nats_public_key).is_ok_and(|key| key.key_pair_type() == KeyPairType::User) {
+        return Err(ApiError::InvalidField("nats_public_key must be a user nkey".to_string()));
+    }
+    let account_seed = state.config.external_worker_nats_account_seed.as_deref().ok_or_else(|| {
+        ApiError::Forbidden("This arena issues no NATS credentials (EXTERNAL_WORKER_NATS_ACCOUNT_SEED unset)".to_string())
+    })?;
+
+    let first = worker.registered_at.is_none();
+    let worker = db::register_external_worker(
+        pool(&state)?,
+        &worker.id,
+        &req.arch,
+        req.isolation.as_str(),
+        version,
+        req.binary_cache.as_ref(),
+    )
+    .await?;
+    if first {
+        info!(worker_id = %worker.id, name = %worker.name, arch = %req.arch, isolation = req.isolation.as_str(), "External worker registered");
+    }
+
+    let nats_jwt = issue_nats_jwt(account_seed, &worker, &req.nats_public_key)?;
+
+    Ok(Json(RegisterResponse {
+        worker_id: worker.id,
+        inbox_prefix: inbox_prefix(&worker.id),
+        name: worker.name,
+        nats_url: state.config.external_worker_nats_url.clone(),
+        stream: SHARED_JOBS_STREAM,
+        consumer: SHARED_WORKER_CONSUMER,
+        heartbeat_seconds: HEARTBEAT_SECONDS,
+        nats_jwt,
+    }))
+}
+
+async fn get_binary(
+    state: State<Arc<AppState>>,
+    WorkerAuth(_worker): WorkerAuth,
+    binary_id: Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    crate::get_binary(state, binary_id, headers).await
+}
+
+async fn get_binary_metadata(
+    state: State<Arc<AppState>>,
+    WorkerAuth(_worker): WorkerAuth,
+    binary_id: Path<String>,
+) -> Result<Json<BinaryMetadata>, ApiError> {
+    crate::get_binary_metadata(state, binary_id).await
+}
+
+/// The shared job `job_id`, claimed for this worker: reports on jobs the API
+/// never shared, or that another worker already reported on, are refused
+async fn claim_job(pool: &PgPool, worker: &ExternalWorker, job_id: &Uuid) -> Result<SharedJob, ApiError> {
+    if worker.registered_at.is_none() {
+        return Err(ApiError::Forbidden("Register before reporting on jobs".to_string()));
+    }
+    db::claim_shared_job(pool, job_id, &worker.id)
+        .await?
+        .ok_or_else(|| ApiError::Forbidden(format!("Job '{}' was not shared with this worker", job_id)))
+}
+
+/// In-house workers take shared jobs too, off the same consumer; once one
+/// has, its status and result stand and external reports can't overwrite them
+async fn refuse_in_house(queue: &NatsQueue, job_id: &Uuid) -> Result<(), ApiError> {
+    if queue.get_job_status(job_id).await?.is_some_and(|job| job.in_house) {
+        return Err(ApiError::Forbidden(format!("Job '{}' was taken by an in-house worker", job_id)));
+    }
+    Ok(())
+}
+
+/// A shared job moved on; the API writes the status for the worker, which
+/// has no KV access
+async fn report_status(
+    State(state): State<Arc<AppState>>,
+    WorkerAuth(worker): WorkerAuth,
+    Path(job_id): Path<Uuid>,
+    Json(req): Json<JobStatusReport>,
+) -> Result<Json<ReportJobResponse>, ApiError> {
+    if req.status == JobStatus::Pending {
+        return Err(ApiError::InvalidField("status must be running, completed or failed".to_string()));
+    }
+    claim_job(pool(&state)?, &worker, &job_id).await?;
+
+    let queue = nats_queue(&state)?;
+    refuse_in_house(queue, &job_id).await?;
+    queue.update_job_status(&job_id, req.status, req.error).await?;
+
+    Ok(Json(ReportJobResponse { job_id }))
+}
+
+/// A shared job's result, stored for `GET /result/:job_id`
+async fn report_result(
+    State(state): State<Arc<AppState>>,
+    WorkerAuth(worker): WorkerAuth,
+    Path(job_id): Path<Uuid>,
+    Json(result): Json<ExecutionResult>,
+) -> Result<Json<ReportJobResponse>, ApiError> {
+    claim_job(pool(&state)?, &worker, &job_id).await?;
+
+    let queue = nats_queue(&state)?;
+    refuse_in_house(queue, &job_id).await?;
+    queue.store_job_result(&job_id, &result).await?;
+
+    Ok(Json(ReportJobResponse { job_id }))
+}
+
+/// A run the worker measured, saved as unverified: who it belongs to and
+/// what it ran come from the job as the API shared it
+async fn report_run(
+    State(state): State<Arc<AppState>>,
+    WorkerAuth(worker): WorkerAuth,
+    Json(mut req): Json<SaveRunRequest>,
+) -> Result<Json<ReportRunResponse>, ApiError> {
+    let pool = pool(&state)?;
+    let job = claim_job(pool, &worker, &req.job_id).await?;
+    refuse_in_house(nats_queue(&state)?, &req.job_id).await?;
+
+    // A verified run of the job stands; reports can't overwrite it
+    if db::get_run_by_job_id(pool, &req.job_id)
+        .await?
+        .is_some_and(|run| run.worker_id.is_none())
+    {
+        return Err(ApiError::Forbidden(format!("Job '{}' already has a run", req.job_id)));
+    }
+
+    req.binary_id = job.binary_id;
+    req.instruction_limit = Some(job.instruction_limit);
+    req.mode = None;
+    req.user_id = job.user_id;
+    req.visibility = RunVisibility::Public;
+    req.benchmark_id = None;
+    req.benchmark_version = None;
+    req.core_dump_id = None;
+    req.worker_id = Some(worker.id);
+
+    let id = db::save_run(pool, &req).await?;
+    db::count_external_worker_run(pool, &worker.id).await?;
+
+    info!(run_id = %id, job_id = %req.job_id, worker = %worker.name, "External run saved");
+
+    Ok(Json(ReportRunResponse { id }))
+}
+
+// ============ NATS Credentials ============
+
+/// Prefix of the worker's inboxes, the only subjects it may subscribe to
+fn inbox_prefix(worker_id: &Uuid) -> String {
+    format!("_INBOX_{}", worker_id.simple())
+}
+
+/// A NATS user JWT for `user_key`, signed with the account seed. It may pull
+/// from and ack on the shared consumer and receive on the worker's inbox, so
+/// it only sees the jobs delivered to it; there is no KV, message reads or
+/// other stream in it, so status and results go through the API.
+fn issue_nats_jwt(account_seed: &str, worker: &ExternalWorker, user_key: &str) -> Result<String, ApiError> {
+    let account = KeyPair::from_seed(account_seed)
+        .ok()
+        .filter(|key| key.key_pair_type() == KeyPairType::Account)
+        .ok_or_else(|| ApiError::Internal("EXTERNAL_WORKER_NATS_ACCOUNT_SEED is not an account seed".to_string()))?;
+
+    let (stream, consumer) = (SHARED_JOBS_STREAM, SHARED_WORKER_CONSUMER);
+    let now = Utc::now().timestamp();
+    let mut claims = serde_json::json!({
+        "iat": now,
+        "exp": now + NATS_CREDENTIALS_TTL.as_secs() as i64,
+        "iss": account.public_key(),
+        "name": worker.name,
+        "sub": user_key,
+        "nats": {
+            "pub": {
+                "allow": [
+                    format!("$JS.API.STREAM.INFO.{}", stream),
+                    format!("$JS.API.CONSUMER.INFO.{}.{}", stream, consumer),
+                    format!("$JS.API.CONSUMER.MSG.NEXT.{}.{}", stream, consumer),
+                    format!("$JS.ACK.{}.{}.>", stream, consumer),
+                ],
+            },
+            "sub": { "allow": [format!("{}.>", inbox_prefix(&worker.id))] },
+            "subs": -1,
+            "data": -1,
+            "payload": -1,
+            "type": "user",
+            "version": 2,
+        },
+    });
+    // Like nats-jwt, the ID is a hash of the claims without it
+    claims["jti"] = hex::encode(Sha256::digest(claims.to_string())).into();
+
+    let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ed25519-nkey"}"#);
+    let signing_input = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+    let signature = account
+        .sign(signing_input.as_bytes())
+        .map_err(|e| ApiError::Internal(format!("Failed to sign NATS credentials: {}", e)))?;
+
+    Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature)))
+}
@@ -0,0 +1,146 @@
+use crate::auth::{AdminUser, AuthenticatedUser, MaybeAuthenticatedUser};
+use crate::db::{self, Writeup, WriteupWithUser};
+use crate::error::ApiError;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const MAX_WRITEUP_BYTES: usize = 64 * 1024;
+
+// ============ Request/Response Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct WriteupRequest {
+    /// Markdown
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerateWriteupRequest {
+    pub hidden: bool,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WriteupListResponse {
+    pub writeups: Vec<WriteupWithUser>,
+    /// Set while writeups are still embargoed; only the caller's own (and,
+    /// for admins, every) writeup is listed until then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visible_at: Option<String>,
+}
+
+// ============ Handlers ============
+
+/// Post or replace the caller's writeup; only solvers may write one
+pub async fn submit_writeup(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(req): Json<WriteupRequest>,
+) -> Result<Json<Writeup>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+
+    let content = req.content.trim();
+    if content.is_empty() {
+        return Err(ApiError::MissingField("content"));
+    }
+    if content.len() > MAX_WRITEUP_BYTES {
+        return Err(ApiError::InvalidField(format!(
+            "writeup is {} bytes (max {})",
+            content.len(),
+            MAX_WRITEUP_BYTES
+        )));
+    }
+
+    if !db::has_solved_challenge(pool, &user.id, &challenge_id).await? {
+        return Err(ApiError::Forbidden("Solve the challenge before posting a writeup".to_string()));
+    }
+
+    let writeup = db::upsert_writeup(pool, &challenge_id, &user.id, content).await?;
+
+    info!(
+        writeup_id = %writeup.id,
+        user_id = %user.id,
+        challenge_id = %challenge_id,
+        "Writeup saved"
+    );
+
+    Ok(Json(writeup))
+}
+
+pub async fn list_writeups(
+    State(state): State<Arc<crate::AppState>>,
+    Path(challenge_id): Path<String>,
+    MaybeAuthenticatedUser(user): MaybeAuthenticatedUser,
+) -> Result<Json<WriteupListResponse>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let challenge = db::get_challenge(pool, &challenge_id)
+        .await?
+        .ok_or_else(|| ApiError::ChallengeNotFound(challenge_id.clone()))?;
+
+    let is_admin = user
+        .as_ref()
+        .is_some_and(|u| state.config.admin_users.contains(&u.username));
+    let author = user.as_ref().map(|u| &u.id);
+    let embargo = challenge.writeups_visible_at.filter(|t| *t > Utc::now());
+
+    let mut writeups = db::list_writeups(pool, &challenge_id, is_admin, author).await?;
+    if embargo.is_some() && !is_admin {
+        writeups.retain(|w| Some(&w.user.id) == author);
+    }
+    if !is_admin {
+        // Moderation notes are for the author and admins
+        for w in writeups.iter_mut().filter(|w| Some(&w.user.id) != author) {
+            w.moderation_note = None;
+        }
+    }
+
+    Ok(Json(WriteupListResponse {
+        writeups,
+        visible_at: embargo.map(|t| t.to_rfc3339()),
+    }))
+}
+
+pub async fn moderate_writeup(
+    State(state): State<Arc<crate::AppState>>,
+    Path(writeup_id): Path<Uuid>,
+    AdminUser(admin): AdminUser,
+    Json(req): Json<ModerateWriteupRequest>,
+) -> Result<Json<Writeup>, ApiError> {
+    let pool = state
+        .db
+        .as_ref()
+        .ok_or_else(|| ApiError::DatabaseError("Database not available".to_string()))?;
+
+    let writeup = db::moderate_writeup(pool, &writeup_id, req.hidden, req.note.as_deref())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("writeup {}", writeup_id)))?;
+
+    info!(
+        writeup_id = %writeup_id,
+        admin = %admin.username,
+        hidden = req.hidden,
+        "Writeup moderated"
+    );
+
+    Ok(Json(writeup))
+}
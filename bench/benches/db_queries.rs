@@ -0,0 +1,36 @@
+//! Criterion benches for the hot DB queries.
+//!
+//! Seeds rows, so point it at a scratch database:
+//! `BENCH_DATABASE_URL=postgres://... cargo bench -p ctf-bench`
+//! Without `BENCH_DATABASE_URL` the benches are skipped.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ctf_bench::{run_query, seed_fixture, HOT_QUERIES};
+use ctf_sandbox_api::db;
+
+fn hot_queries(c: &mut Criterion) {
+    let Ok(database_url) = std::env::var("BENCH_DATABASE_URL") else {
+        eprintln!("BENCH_DATABASE_URL not set, skipping DB benches");
+        return;
+    };
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (pool, fixture) = runtime.block_on(async {
+        let pool = db::create_pool(&database_url).await.expect("Failed to connect");
+        db::run_migrations(&pool).await.expect("Failed to run migrations");
+        let fixture = seed_fixture(&pool).await.expect("Failed to seed fixture");
+        (pool, fixture)
+    });
+
+    let mut group = c.benchmark_group("db");
+    for name in HOT_QUERIES {
+        group.bench_function(*name, |b| {
+            b.to_async(&runtime)
+                .iter(|| async { run_query(&pool, &fixture, name).await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hot_queries);
+criterion_main!(benches);
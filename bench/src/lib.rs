@@ -0,0 +1,531 @@
+//! Load generator and performance budgets for the API.
+//!
+//! Point it at an API running with `SANDBOX_FAKE=1` so the numbers measure
+//! the queue, workers and database rather than QEMU. The hot DB queries are
+//! shared with the criterion benches in `benches/db_queries.rs`.
+
+use ctf_sandbox_api::db::{self, BinaryMetadata, CreateUserFromGitHub, SaveRunRequest};
+use ctf_sandbox_api::error::ApiError;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// ============ Config ============
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pub api_url: String,
+    /// Number of compile+execute round trips
+    pub requests: usize,
+    pub concurrency: usize,
+    /// Share of requests (0-100) that reuse a previous source to exercise the compile cache
+    pub cache_hit_percent: usize,
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl LoadConfig {
+    pub fn from_env() -> Self {
+        Self {
+            api_url: env::var("BENCH_API_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            requests: env::var("BENCH_REQUESTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(200),
+            concurrency: env::var("BENCH_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(16),
+            cache_hit_percent: env::var("BENCH_CACHE_HIT_PERCENT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(25),
+            timeout: Duration::from_secs(
+                env::var("BENCH_TIMEOUT_SEC")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(120),
+            ),
+            poll_interval: Duration::from_millis(
+                env::var("BENCH_POLL_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(20),
+            ),
+        }
+    }
+}
+
+/// Upper bounds a run must stay within (unset = not enforced).
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub compile_p99_ms: Option<f64>,
+    pub execute_p99_ms: Option<f64>,
+    pub submit_p99_ms: Option<f64>,
+    pub query_p99_ms: Option<f64>,
+    pub min_throughput: Option<f64>,
+    /// Allowed slowdown against a baseline report, in percent
+    pub regression_percent: f64,
+}
+
+impl Budget {
+    pub fn from_env() -> Self {
+        let ms = |key: &str| env::var(key).ok().and_then(|s| s.parse().ok());
+        Self {
+            compile_p99_ms: ms("BENCH_BUDGET_COMPILE_P99_MS"),
+            execute_p99_ms: ms("BENCH_BUDGET_EXECUTE_P99_MS"),
+            submit_p99_ms: ms("BENCH_BUDGET_SUBMIT_P99_MS"),
+            query_p99_ms: ms("BENCH_BUDGET_QUERY_P99_MS"),
+            min_throughput: ms("BENCH_BUDGET_MIN_THROUGHPUT"),
+            regression_percent: ms("BENCH_REGRESSION_PERCENT").unwrap_or(20.0),
+        }
+    }
+}
+
+// ============ Stats ============
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &mut [Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+
+        let total: Duration = samples.iter().sum();
+        Self {
+            count: samples.len(),
+            mean_ms: as_ms(total) / samples.len() as f64,
+            p50_ms: as_ms(percentile(samples, 50.0)),
+            p99_ms: as_ms(percentile(samples, 99.0)),
+            max_ms: as_ms(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// Nearest-rank percentile of already sorted samples.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn as_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoadReport {
+    pub requests: usize,
+    pub concurrency: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub wall_time_ms: f64,
+    /// Completed executions per second
+    pub throughput: f64,
+    pub peak_queue_depth: u64,
+    /// POST /compile and POST /submit response times
+    pub submit: LatencyStats,
+    /// POST /compile until the compile job completes
+    pub compile: LatencyStats,
+    /// POST /submit until the job completes
+    pub execute: LatencyStats,
+    #[serde(default)]
+    pub queries: BTreeMap<String, LatencyStats>,
+}
+
+// ============ Load Generator ============
+
+#[derive(Default)]
+struct Sample {
+    submit: Vec<Duration>,
+    compile: Option<Duration>,
+    execute: Option<Duration>,
+}
+
+/// Flood the API with compile+execute round trips and collect latencies.
+pub async fn run_load(config: &LoadConfig) -> LoadReport {
+    let client = reqwest::Client::new();
+    let peak_depth = Arc::new(AtomicU64::new(0));
+    let run_id = Uuid::new_v4();
+
+    let sampler = tokio::spawn(sample_queue_depth(client.clone(), config.clone(), peak_depth.clone()));
+
+    let start = Instant::now();
+    let samples: Vec<Result<Sample, String>> = stream::iter(0..config.requests)
+        .map(|i| {
+            let client = client.clone();
+            let source = synthetic_source(run_id, i, config.cache_hit_percent);
+            async move { round_trip(&client, config, &source).await }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await;
+    let wall_time = start.elapsed();
+    sampler.abort();
+
+    let mut submit = Vec::new();
+    let mut compile = Vec::new();
+    let mut execute = Vec::new();
+    let mut failed = 0;
+    for sample in samples {
+        match sample {
+            Ok(s) => {
+                submit.extend(s.submit);
+                compile.extend(s.compile);
+                execute.extend(s.execute);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("request failed: {}", e);
+            }
+        }
+    }
+
+    LoadReport {
+        requests: config.requests,
+        concurrency: config.concurrency,
+        completed: execute.len(),
+        failed,
+        wall_time_ms: as_ms(wall_time),
+        throughput: execute.len() as f64 / wall_time.as_secs_f64().max(f64::EPSILON),
+        peak_queue_depth: peak_depth.load(Ordering::Relaxed),
+        submit: LatencyStats::from_samples(&mut submit),
+        compile: LatencyStats::from_samples(&mut compile),
+        execute: LatencyStats::from_samples(&mut execute),
+        queries: BTreeMap::new(),
+    }
+}
+
+/// Unique sources miss the compile cache; every Nth request reuses source 0.
+fn synthetic_source(run_id: Uuid, i: usize, cache_hit_percent: usize) -> String {
+    let variant = if cache_hit_percent > 0 && i.is_multiple_of(100 / cache_hit_percent.min(100)) {
+        0
+    } else {
+        i
+    };
+    format!(
+        "#include <stdio.h>\n// bench {} {}\n// FAKE_STDOUT=bench\\n\nint main() {{ puts(\"bench\"); return 0; }}\n",
+        run_id, variant
+    )
+}
+
+async fn round_trip(client: &reqwest::Client, config: &LoadConfig, source: &str) -> Result<Sample, String> {
+    let mut sample = Sample::default();
+
+    let form = reqwest::multipart::Form::new()
+        .text("source_code", source.to_string())
+        .text("language", "c");
+    let start = Instant::now();
+    let body = post_form(client, &format!("{}/compile", config.api_url), form).await?;
+    sample.submit.push(start.elapsed());
+
+    let compile_id = body["compile_job_id"].as_str().ok_or("missing compile_job_id")?;
+    poll(client, config, &format!("{}/compile/status/{}", config.api_url, compile_id)).await?;
+    sample.compile = Some(start.elapsed());
+
+    let result: Value = client
+        .get(format!("{}/compile/result/{}", config.api_url, compile_id))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    let binary_id = result["binary_id"].as_str().ok_or("missing binary_id")?;
+
+    let form = reqwest::multipart::Form::new().text("binary_id", binary_id.to_string());
+    let start = Instant::now();
+    let body = post_form(client, &format!("{}/submit", config.api_url), form).await?;
+    sample.submit.push(start.elapsed());
+
+    let job_id = body["job_id"].as_str().ok_or("missing job_id")?;
+    poll(client, config, &format!("{}/status/{}", config.api_url, job_id)).await?;
+    sample.execute = Some(start.elapsed());
+
+    Ok(sample)
+}
+
+async fn post_form(client: &reqwest::Client, url: &str, form: reqwest::multipart::Form) -> Result<Value, String> {
+    let resp = client.post(url).multipart(form).send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        return Err(format!("{} {}: {}", url, status, resp.text().await.unwrap_or_default()));
+    }
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+async fn poll(client: &reqwest::Client, config: &LoadConfig, url: &str) -> Result<(), String> {
+    let deadline = Instant::now() + config.timeout;
+    loop {
+        let body: Value = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        match body["status"].as_str() {
+            Some("completed") => return Ok(()),
+            Some("failed") => return Err(format!("{} failed: {}", url, body["error"])),
+            _ => {}
+        }
+        if Instant::now() > deadline {
+            return Err(format!("{} timed out after {:?}", url, config.timeout));
+        }
+        tokio::time::sleep(config.poll_interval).await;
+    }
+}
+
+async fn sample_queue_depth(client: reqwest::Client, config: LoadConfig, peak: Arc<AtomicU64>) {
+    let url = format!("{}/queue/stats", config.api_url);
+    loop {
+        if let Ok(resp) = client.get(&url).send().await {
+            if let Ok(body) = resp.json::<Value>().await {
+                peak.fetch_max(body["queue_length"].as_u64().unwrap_or(0), Ordering::Relaxed);
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+// ============ Hot DB Queries ============
+
+/// Queries on the request path of /status, /binaries, /runs, auth and leaderboards.
+pub const HOT_QUERIES: &[&str] = &[
+    "get_binary",
+    "get_binary_metadata",
+    "get_run_by_job_id",
+    "list_runs",
+    "get_session_by_token_hash",
+    "get_challenge_leaderboard",
+    "get_global_leaderboard",
+];
+
+/// Rows the hot queries read; seeded once per run.
+pub struct Fixture {
+    pub binary_id: String,
+    pub job_id: Uuid,
+    pub token_hash: String,
+    pub challenge_id: String,
+}
+
+pub async fn seed_fixture(pool: &PgPool) -> Result<Fixture, ApiError> {
+    let binary = vec![0u8; 64 * 1024];
+    let binary_id = format!("sha256-bench-{}", Uuid::new_v4().simple());
+    let metadata = BinaryMetadata {
+        language: Some("c".to_string()),
+        optimization: Some("release".to_string()),
+        compiler_version: Some("bench".to_string()),
+        compile_flags: None,
+        compiler_image: None,
+        toolchain: None,
+        runtime: None,
+    };
+    db::store_binary(pool, &binary_id, &binary, Some(&metadata)).await?;
+
+    let user = db::create_or_update_user_from_github(
+        pool,
+        &CreateUserFromGitHub {
+            github_id: -((Uuid::new_v4().as_u128() & 0x7fff_ffff) as i64),
+            github_login: format!("bench-{}", Uuid::new_v4().simple()),
+            avatar_url: None,
+            display_name: None,
+        },
+    )
+    .await?;
+    let token_hash = format!("bench-{}", Uuid::new_v4().simple());
+    db::create_session(pool, &user.id, &token_hash, chrono::Utc::now() + chrono::Duration::hours(1)).await?;
+
+    let job_id = Uuid::new_v4();
+    let run: SaveRunRequest = serde_json::from_value(json!({
+        "job_id": job_id,
+        "binary_id": binary_id,
+        "binary_size": binary.len(),
+        "language": "c",
+        "instructions": 12_345,
+        "limit_reached": false,
+        "exit_code": 0,
+        "benchmark_id": "hello-world",
+    }))
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let run_id = db::save_run(pool, &run).await?;
+
+    let challenge_id = "hello-world".to_string();
+    db::update_leaderboard_entry(pool, &user.id, &challenge_id, "c", 12_345, &run_id, "", false).await?;
+
+    Ok(Fixture {
+        binary_id,
+        job_id,
+        token_hash,
+        challenge_id,
+    })
+}
+
+pub async fn run_query(pool: &PgPool, fixture: &Fixture, name: &str) -> Result<(), ApiError> {
+    match name {
+        "get_binary" => db::get_binary(pool, &fixture.binary_id).await.map(drop),
+        "get_binary_metadata" => db::get_binary_metadata(pool, &fixture.binary_id).await.map(drop),
+        "get_run_by_job_id" => db::get_run_by_job_id(pool, &fixture.job_id).await.map(drop),
+        "list_runs" => db::list_runs(pool, 50, 0, None, false).await.map(drop),
+        "get_session_by_token_hash" => db::get_session_by_token_hash(pool, &fixture.token_hash).await.map(drop),
+        "get_challenge_leaderboard" => db::get_challenge_leaderboard(pool, &fixture.challenge_id, None, None, None, 100)
+            .await
+            .map(drop),
+        "get_global_leaderboard" => db::get_global_leaderboard(pool, None, None, 100).await.map(drop),
+        _ => Err(ApiError::Internal(format!("Unknown query: {}", name))),
+    }
+}
+
+pub async fn measure_queries(
+    pool: &PgPool,
+    fixture: &Fixture,
+    iterations: usize,
+) -> Result<BTreeMap<String, LatencyStats>, ApiError> {
+    let mut stats = BTreeMap::new();
+    for name in HOT_QUERIES {
+        let mut samples = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let start = Instant::now();
+            run_query(pool, fixture, name).await?;
+            samples.push(start.elapsed());
+        }
+        stats.insert(name.to_string(), LatencyStats::from_samples(&mut samples));
+    }
+    Ok(stats)
+}
+
+// ============ Budgets ============
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub metric: String,
+    pub value: f64,
+    pub limit: f64,
+    pub ok: bool,
+}
+
+impl Finding {
+    fn at_most(metric: impl Into<String>, value: f64, limit: f64) -> Self {
+        Self {
+            metric: metric.into(),
+            value,
+            limit,
+            ok: value <= limit,
+        }
+    }
+
+    fn at_least(metric: impl Into<String>, value: f64, limit: f64) -> Self {
+        Self {
+            metric: metric.into(),
+            value,
+            limit,
+            ok: value >= limit,
+        }
+    }
+}
+
+/// Compare a report against absolute budgets and, if given, a baseline report.
+pub fn check(report: &LoadReport, budget: &Budget, baseline: Option<&LoadReport>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if report.failed > 0 {
+        findings.push(Finding::at_most("failed requests", report.failed as f64, 0.0));
+    }
+
+    let latencies = [
+        ("submit p99 ms", report.submit.p99_ms, budget.submit_p99_ms),
+        ("compile p99 ms", report.compile.p99_ms, budget.compile_p99_ms),
+        ("execute p99 ms", report.execute.p99_ms, budget.execute_p99_ms),
+    ];
+    for (metric, value, limit) in latencies {
+        if let Some(limit) = limit {
+            findings.push(Finding::at_most(format!("{} (budget)", metric), value, limit));
+        }
+    }
+    if let Some(limit) = budget.query_p99_ms {
+        for (name, stats) in &report.queries {
+            findings.push(Finding::at_most(format!("{} p99 ms (budget)", name), stats.p99_ms, limit));
+        }
+    }
+    if let Some(limit) = budget.min_throughput {
+        findings.push(Finding::at_least("throughput/s (budget)", report.throughput, limit));
+    }
+
+    if let Some(base) = baseline {
+        let slack = 1.0 + budget.regression_percent / 100.0;
+        let mut compare = |metric: &str, value: f64, base: f64| {
+            if base > 0.0 {
+                findings.push(Finding::at_most(format!("{} (baseline)", metric), value, base * slack));
+            }
+        };
+        compare("submit p99 ms", report.submit.p99_ms, base.submit.p99_ms);
+        compare("compile p99 ms", report.compile.p99_ms, base.compile.p99_ms);
+        compare("execute p99 ms", report.execute.p99_ms, base.execute.p99_ms);
+        for (name, stats) in &report.queries {
+            if let Some(base_stats) = base.queries.get(name) {
+                compare(&format!("{} p99 ms", name), stats.p99_ms, base_stats.p99_ms);
+            }
+        }
+        if base.throughput > 0.0 {
+            findings.push(Finding::at_least(
+                "throughput/s (baseline)",
+                report.throughput,
+                base.throughput / slack,
+            ));
+        }
+    }
+
+    findings
+}
+
+pub fn print_report(report: &LoadReport, findings: &[Finding]) {
+    println!(
+        "{} requests @ concurrency {}: {} completed, {} failed in {:.0}ms ({:.1} jobs/s, peak queue depth {})",
+        report.requests,
+        report.concurrency,
+        report.completed,
+        report.failed,
+        report.wall_time_ms,
+        report.throughput,
+        report.peak_queue_depth
+    );
+    println!();
+    println!("{:<28} {:>8} {:>10} {:>10} {:>10} {:>10}", "latency", "count", "mean", "p50", "p99", "max");
+    let rows = [("submit", &report.submit), ("compile", &report.compile), ("execute", &report.execute)];
+    for (name, stats) in rows.into_iter().chain(report.queries.iter().map(|(k, v)| (k.as_str(), v))) {
+        println!(
+            "{:<28} {:>8} {:>8.2}ms {:>8.2}ms {:>8.2}ms {:>8.2}ms",
+            name, stats.count, stats.mean_ms, stats.p50_ms, stats.p99_ms, stats.max_ms
+        );
+    }
+
+    if findings.is_empty() {
+        return;
+    }
+    println!();
+    for f in findings {
+        println!(
+            "{} {:<44} {:>10.2} (limit {:.2})",
+            if f.ok { "ok  " } else { "FAIL" },
+            f.metric,
+            f.value,
+            f.limit
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use ctf_bench::{check, measure_queries, print_report, run_load, seed_fixture, Budget, LoadConfig, LoadReport};
+use std::env;
+use std::process::ExitCode;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let config = LoadConfig::from_env();
+    let budget = Budget::from_env();
+
+    println!(
+        "Flooding {} with {} compile+execute round trips (concurrency {})",
+        config.api_url, config.requests, config.concurrency
+    );
+    let mut report = run_load(&config).await;
+
+    // Hot DB queries (seeds rows, so only point this at a scratch database)
+    if let Ok(database_url) = env::var("BENCH_DATABASE_URL") {
+        let iterations = env::var("BENCH_QUERY_ITERATIONS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(200);
+        let result = async {
+            let pool = ctf_sandbox_api::db::create_pool(&database_url).await?;
+            let fixture = seed_fixture(&pool).await?;
+            measure_queries(&pool, &fixture, iterations).await
+        }
+        .await;
+        match result {
+            Ok(queries) => report.queries = queries,
+            Err(e) => eprintln!("Skipping DB queries: {}", e),
+        }
+    }
+
+    let baseline: Option<LoadReport> = match env::var("BENCH_BASELINE") {
+        Ok(path) => match std::fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(b) => Some(b),
+                Err(e) => {
+                    eprintln!("Ignoring baseline {}: {}", path, e);
+                    None
+                }
+            },
+            Err(e) => {
+                eprintln!("Ignoring baseline {}: {}", path, e);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    let findings = check(&report, &budget, baseline.as_ref());
+    println!();
+    print_report(&report, &findings);
+
+    if let Ok(path) = env::var("BENCH_OUTPUT") {
+        match serde_json::to_vec_pretty(&report).map(|json| std::fs::write(&path, json)) {
+            Ok(Ok(())) => println!("\nReport written to {}", path),
+            Ok(Err(e)) => eprintln!("Failed to write {}: {}", path, e),
+            Err(e) => eprintln!("Failed to serialize report: {}", e),
+        }
+    }
+
+    if findings.iter().any(|f| !f.ok) {
+        println!("\nPerformance budget exceeded");
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
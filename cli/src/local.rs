@@ -0,0 +1,329 @@
+//! The local runner.
+//!
+//! Images and limits come from an arena's `GET /sandbox/manifest` when
+//! `--api` (or `CTF_API_URL`) names one, with images pinned by digest so
+//! instruction counts match its workers; otherwise from `SANDBOX_IMAGE`,
+//! `COMPILER_IMAGE` and the other variables the API reads, with its
+//! defaults. Compiling and running go through `ctf_common::compilation` and
+//! `ctf_common::execution`, which the API's local mode uses too, so
+//! containers get the flags and limits the arena gives them.
+//!
+//! Only Docker is needed, so the runner builds natively on Linux, macOS and
+//! Windows. The images are linux/amd64; Docker Desktop runs them under
+//! emulation on ARM machines.
+
+use ctf_common::compilation::{self, Build};
+use ctf_common::compile_protocol::CompileManifest;
+use ctf_common::diagnostics::ExecutionMode;
+use ctf_common::execution::{self, Container, Limits};
+use ctf_common::language::{Language, Optimization};
+use ctf_common::runtime::RuntimeImages;
+use ctf_common::sandbox_options::is_reserved_env;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
+
+/// The images are only published for this platform
+const PLATFORM: &str = "linux/amd64";
+
+/// The parts of `GET /sandbox/manifest` the runner needs
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    sandbox: ImageInfo,
+    compiler: ImageInfo,
+    limits: ManifestLimits,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageInfo {
+    image: String,
+    digest: Option<String>,
+}
+
+impl ImageInfo {
+    /// `repo@sha256:...` when the arena knows it; a bare image ID can't be pulled
+    fn reference(self) -> String {
+        self.digest.filter(|digest| digest.contains('@')).unwrap_or(self.image)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestLimits {
+    default_instruction_limit: u64,
+    memory_limit_mb: u32,
+    max_memory_limit_mb: u32,
+    timeout_ms: u64,
+    compile_timeout_sec: u64,
+    compile_memory_limit_mb: u32,
+}
+
+/// Images and limits for local runs, named like the API's `Config` fields
+#[derive(Debug)]
+struct Settings {
+    sandbox_image: String,
+    compiler_image: String,
+    diagnostics_image: String,
+    runtime_images: RuntimeImages,
+    default_instruction_limit: u64,
+    memory_limit_mb: u32,
+    max_memory_limit_mb: u32,
+    timeout_ms: u64,
+    compile_timeout_sec: u64,
+    compile_memory_limit_mb: u32,
+}
+
+impl Settings {
+    /// The variables the API reads, with its defaults
+    fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            env::var(name).ok().and_then(|s| s.parse().ok()).unwrap_or(default)
+        }
+        Self {
+            sandbox_image: env::var("SANDBOX_IMAGE").unwrap_or_else(|_| "sandbox".to_string()),
+            compiler_image: env::var("COMPILER_IMAGE").unwrap_or_else(|_| "compiler".to_string()),
+            diagnostics_image: env::var("DIAGNOSTICS_IMAGE").unwrap_or_else(|_| "sandbox-diagnostics".to_string()),
+            runtime_images: RuntimeImages::from_env(),
+            default_instruction_limit: var("DEFAULT_INSTRUCTION_LIMIT", 10_000_000),
+            memory_limit_mb: var("MEMORY_LIMIT_MB", 256),
+            max_memory_limit_mb: var("MAX_MEMORY_LIMIT_MB", 2048),
+            timeout_ms: var("TIMEOUT_SEC", 30) * 1000,
+            compile_timeout_sec: var("COMPILE_TIMEOUT_SEC", 120),
+            compile_memory_limit_mb: var("COMPILE_MEMORY_LIMIT_MB", 4096),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RunOptions {
+    path: Option<String>,
+    language: Option<Language>,
+    optimization: Optimization,
+    toolchain: Option<String>,
+    stdin: Option<String>,
+    limit: Option<u64>,
+    timeout_ms: Option<u64>,
+    memory_mb: Option<u32>,
+    args: Vec<String>,
+    env_vars: HashMap<String, String>,
+    mode: ExecutionMode,
+    api: Option<String>,
+}
+
+impl RunOptions {
+    fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut options = RunOptions::default();
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            if !arg.starts_with("--") {
+                if options.path.replace(arg.to_string()).is_some() {
+                    return Err("only one file can be run at a time".to_string());
+                }
+                continue;
+            }
+            let value = *args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+            match arg {
+                "--language" => {
+                    options.language =
+                        Some(Language::from_str(value).ok_or_else(|| format!("unknown language '{}'", value))?)
+                }
+                "--optimization" => {
+                    options.optimization = Optimization::from_str(value)
+                        .ok_or_else(|| "optimization must be debug, release or size".to_string())?
+                }
+                "--toolchain" => options.toolchain = Some(value.to_string()),
+                "--stdin" => options.stdin = Some(value.to_string()),
+                "--limit" => options.limit = Some(number(arg, value)?),
+                "--timeout-ms" => options.timeout_ms = Some(number(arg, value)?),
+                "--memory-mb" => options.memory_mb = Some(number(arg, value)?),
+                "--arg" => options.args.push(value.to_string()),
+                "--env" => {
+                    let (key, val) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("--env takes KEY=VALUE, got '{}'", value))?;
+                    options.env_vars.insert(key.to_string(), val.to_string());
+                }
+                "--mode" => {
+                    options.mode = ExecutionMode::parse(value)
+                        .ok_or_else(|| "mode must be normal or diagnostics".to_string())?
+                }
+                "--api" => options.api = Some(value.to_string()),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} must be a number", option))
+}
+
+/// `--api`, else `CTF_API_URL`
+fn api_url(api: Option<String>) -> Option<String> {
+    api.or_else(|| env::var("CTF_API_URL").ok())
+        .map(|url| url.trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+}
+
+/// Settings from the environment, with the images and limits of the arena
+/// at `api`, if any
+async fn load_settings(api: Option<String>) -> Result<Settings, String> {
+    let mut settings = Settings::from_env();
+    let Some(api) = api_url(api) else {
+        return Ok(settings);
+    };
+
+    let url = format!("{}/sandbox/manifest", api);
+    let manifest: Manifest = reqwest::get(&url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("failed to fetch {}: {}", url, e))?
+        .json()
+        .await
+        .map_err(|e| format!("unreadable manifest from {}: {}", url, e))?;
+
+    settings.sandbox_image = manifest.sandbox.reference();
+    settings.compiler_image = manifest.compiler.reference();
+    settings.default_instruction_limit = manifest.limits.default_instruction_limit;
+    settings.memory_limit_mb = manifest.limits.memory_limit_mb;
+    settings.max_memory_limit_mb = manifest.limits.max_memory_limit_mb;
+    settings.timeout_ms = manifest.limits.timeout_ms;
+    settings.compile_timeout_sec = manifest.limits.compile_timeout_sec;
+    settings.compile_memory_limit_mb = manifest.limits.compile_memory_limit_mb;
+    Ok(settings)
+}
+
+/// `ctf local pull`: fetch the sandbox and compiler images
+pub async fn pull(args: &[&str]) -> Result<ExitCode, String> {
+    let api = match args {
+        [] => None,
+        ["--api", url] => Some(url.to_string()),
+        _ => return Err("usage: ctf local pull [--api URL]".to_string()),
+    };
+    let settings = load_settings(api).await?;
+
+    for image in [&settings.sandbox_image, &settings.compiler_image] {
+        eprintln!("Pulling {}", image);
+        let status = tokio::process::Command::new("docker")
+            .args(["pull", "--platform", PLATFORM, image])
+            .status()
+            .await
+            .map_err(|e| format!("failed to run docker: {}", e))?;
+        if !status.success() {
+            return Err(format!("docker pull {} failed", image));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `ctf local run`: compile a source file (or take a binary as is), run it in
+/// the sandbox and print the result
+pub async fn run(args: &[&str]) -> Result<ExitCode, String> {
+    let options = RunOptions::parse(args)?;
+    let path = options
+        .path
+        .clone()
+        .ok_or_else(|| "usage: ctf local run <source or binary> [options]".to_string())?;
+    // Names the entrypoint, the loader or QEMU act on, refused like the arena does
+    if let Some(key) = options.env_vars.keys().find(|key| is_reserved_env(key)) {
+        return Err(format!("env var {} is reserved for the sandbox", key));
+    }
+
+    let settings = load_settings(options.api.clone()).await?;
+
+    let stdin = match options.stdin.as_deref() {
+        None => Vec::new(),
+        Some("-") => {
+            let mut buf = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut buf)
+                .map_err(|e| format!("failed to read stdin: {}", e))?;
+            buf
+        }
+        Some(file) => std::fs::read(file).map_err(|e| format!("failed to read {}: {}", file, e))?,
+    };
+
+    let language = options.language.or_else(|| {
+        let extension = Path::new(&path).extension()?.to_str()?;
+        Language::ALL
+            .iter()
+            .copied()
+            .find(|language| language.source_extension() == extension)
+    });
+
+    let (binary, runtime) = match language {
+        Some(language) => {
+            let source_code =
+                std::fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+            let source_file = format!("source.{}", language.source_extension());
+            let mut manifest = CompileManifest::new(language.as_str(), options.optimization.as_str(), &source_file);
+            manifest.toolchain = options.toolchain.clone();
+
+            eprintln!("Compiling {} with {}", path, settings.compiler_image);
+            let build = Build {
+                image: &settings.compiler_image,
+                manifest: &manifest,
+                source_code: &source_code,
+                linker_script: None,
+                memory_limit_mb: settings.compile_memory_limit_mb,
+                timeout_sec: settings.compile_timeout_sec,
+            };
+            let output = match compilation::compile(build).await {
+                Ok(output) => output,
+                Err(failure) => {
+                    eprintln!("{}", failure.message());
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
+            for diagnostic in &output.diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+            (output.binary, output.runtime)
+        }
+        None => (
+            std::fs::read(&path).map_err(|e| format!("failed to read {}: {}", path, e))?,
+            None,
+        ),
+    };
+
+    let image = match (options.mode, &runtime) {
+        (ExecutionMode::Normal, None) => settings.sandbox_image.as_str(),
+        (ExecutionMode::Normal, Some(runtime)) => settings.runtime_images.image_for(&runtime.runtime),
+        (ExecutionMode::Diagnostics, None) => &settings.diagnostics_image,
+        (ExecutionMode::Diagnostics, Some(runtime)) => {
+            return Err(format!("diagnostics mode isn't available for {} programs", runtime.runtime))
+        }
+    };
+    let limits = Limits::bounded(
+        options.limit.unwrap_or(settings.default_instruction_limit),
+        options.timeout_ms,
+        options.memory_mb,
+        settings.timeout_ms,
+        settings.memory_limit_mb,
+        settings.max_memory_limit_mb,
+    );
+    let binary = execution::write_binary(&binary).map_err(|e| format!("failed to write the binary: {}", e))?;
+
+    eprintln!("Running in {} (local run, not eligible for leaderboards)", image);
+    let result = execution::run(Container {
+        image,
+        binary: binary.path(),
+        limits,
+        stdin: &stdin,
+        args: &options.args,
+        network_enabled: false,
+        env_vars: &options.env_vars,
+        docker_args: &[],
+        mode: options.mode,
+        runtime: runtime.as_ref(),
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let json = serde_json::to_string_pretty(&result).map_err(|e| format!("failed to serialize result: {}", e))?;
+    println!("{}", json);
+    Ok(ExitCode::SUCCESS)
+}
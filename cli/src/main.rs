@@ -0,0 +1,56 @@
+//! `ctf`, the arena's command-line client.
+//!
+//! `ctf local` is the local runner: it pulls the arena's sandbox and compiler
+//! images and compiles and runs programs on this machine with Docker,
+//! printing the same `ExecutionResult` JSON `GET /result/:job_id` returns.
+//! Nothing is uploaded, so local runs never count for a leaderboard.
+
+mod local;
+
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+  ctf local pull [--api URL]
+  ctf local run <source or binary> [options]
+
+Run options:
+  --language NAME        Compile as this language (default: from the file extension;
+                         files without a known extension run as binaries)
+  --optimization LEVEL   debug, release (default) or size
+  --toolchain NAME       Non-default toolchain
+  --stdin FILE           Feed FILE to the program (- for this process's stdin)
+  --limit N              Instruction limit
+  --timeout-ms N         Wall-clock limit
+  --memory-mb N          Memory limit
+  --arg VALUE            Argument for the program (repeatable)
+  --env KEY=VALUE        Environment variable for the program (repeatable)
+  --mode MODE            normal (default) or diagnostics
+
+  --api URL              Arena whose images and limits to use (default: CTF_API_URL;
+                         without one, SANDBOX_IMAGE / COMPILER_IMAGE and the API's defaults)
+";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["local", "pull", rest @ ..] => local::pull(rest).await,
+        ["local", "run", rest @ ..] => local::run(rest).await,
+        [] | ["help" | "-h" | "--help"] => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(format!("unknown command\n\n{}", USAGE)),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("ctf: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
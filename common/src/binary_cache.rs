@@ -0,0 +1,22 @@
+//! What an execute worker's on-disk binary cache reports with its heartbeat
+//! (`POST /workers/register`), shown by `GET /admin/workers`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BinaryCacheStats {
+    /// Binaries on disk and their total size
+    pub entries: u64,
+    pub bytes: u64,
+    /// `BINARY_CACHE_MAX_MB` in bytes
+    pub max_bytes: u64,
+    /// Since the worker started
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Files or fetches that didn't match their binary ID, dropped
+    pub invalid: u64,
+    /// Binaries fetched ahead of their job while another ran
+    #[serde(default)]
+    pub prefetched: u64,
+}
@@ -0,0 +1,111 @@
+//! Post-compile constraints on the binary itself.
+//!
+//! Some challenges judge the artifact rather than (or as well as) its output:
+//! a size budget, a fully static link, no `.data` section, no call into
+//! `printf`. A challenge carries [`BinaryChecks`] and the API inspects the
+//! compiled ELF with [`BinaryChecks::check`] before any test case runs; a
+//! challenge with checks and no test cases is compile-only.
+
+use goblin::elf::Elf;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const MAX_FORBIDDEN_NAMES: usize = 64;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BinaryChecks {
+    /// Upper bound on the file size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_binary_size: Option<u64>,
+    /// No interpreter and no shared library dependencies
+    #[serde(default)]
+    pub require_static: bool,
+    /// Section names that must not appear, e.g. `[".data", ".rodata"]`
+    #[serde(default)]
+    pub forbidden_sections: Vec<String>,
+    /// Symbols that must not be defined or imported, e.g. `["printf"]`
+    #[serde(default)]
+    pub forbidden_symbols: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum BinaryCheckError {
+    #[error("invalid binary checks: {0}")]
+    Invalid(String),
+
+    #[error("binary is not a valid ELF file: {0}")]
+    NotElf(String),
+
+    #[error("binary check failed: {}", .0.join("; "))]
+    Violations(Vec<String>),
+}
+
+impl BinaryChecks {
+    pub fn is_empty(&self) -> bool {
+        self.max_binary_size.is_none()
+            && !self.require_static
+            && self.forbidden_sections.is_empty()
+            && self.forbidden_symbols.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), BinaryCheckError> {
+        if self.forbidden_sections.len() + self.forbidden_symbols.len() > MAX_FORBIDDEN_NAMES {
+            return Err(BinaryCheckError::Invalid(format!(
+                "at most {} forbidden sections and symbols",
+                MAX_FORBIDDEN_NAMES
+            )));
+        }
+        if self.forbidden_sections.iter().chain(&self.forbidden_symbols).any(|n| n.is_empty()) {
+            return Err(BinaryCheckError::Invalid("forbidden names must not be empty".to_string()));
+        }
+        if self.max_binary_size == Some(0) {
+            return Err(BinaryCheckError::Invalid("max_binary_size must be positive".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Inspect `binary` and report every violated constraint at once
+    pub fn check(&self, binary: &[u8]) -> Result<(), BinaryCheckError> {
+        let mut violations = Vec::new();
+        if let Some(max) = self.max_binary_size {
+            if binary.len() as u64 > max {
+                violations.push(format!("binary is {} bytes (max {})", binary.len(), max));
+            }
+        }
+
+        let needs_elf = self.require_static || !self.forbidden_sections.is_empty() || !self.forbidden_symbols.is_empty();
+        if needs_elf {
+            let elf = Elf::parse(binary).map_err(|e| BinaryCheckError::NotElf(e.to_string()))?;
+
+            // static-pie still has a dynamic section, but no interpreter or DT_NEEDED
+            if self.require_static && (elf.interpreter.is_some() || !elf.libraries.is_empty()) {
+                let why = elf.interpreter.map(|i| format!(" (interpreter {})", i)).unwrap_or_default();
+                violations.push(format!("binary is dynamically linked{}", why));
+            }
+
+            for name in &self.forbidden_sections {
+                let present = elf
+                    .section_headers
+                    .iter()
+                    .any(|sh| elf.shdr_strtab.get_at(sh.sh_name) == Some(name.as_str()));
+                if present {
+                    violations.push(format!("forbidden section {}", name));
+                }
+            }
+
+            for name in &self.forbidden_symbols {
+                let in_symtab = elf.syms.iter().any(|s| elf.strtab.get_at(s.st_name) == Some(name.as_str()));
+                let in_dynsym = elf.dynsyms.iter().any(|s| elf.dynstrtab.get_at(s.st_name) == Some(name.as_str()));
+                if in_symtab || in_dynsym {
+                    violations.push(format!("forbidden symbol {}", name));
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(BinaryCheckError::Violations(violations))
+        }
+    }
+}
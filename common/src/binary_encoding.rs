@@ -0,0 +1,55 @@
+//! zstd for binaries moving between the workers and the API
+//! (`PUT`/`GET /binaries/:id` with `Content-Encoding: zstd`), and for
+//! binaries the API keeps compressed at rest.
+
+use std::io::{self, Read};
+
+pub const ZSTD: &str = "zstd";
+
+/// Below this, a round through zstd isn't worth it
+pub const MIN_COMPRESS_SIZE: usize = 4096;
+
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Ceiling for a binary the workers fetch; the API holds uploads to its own
+/// `MAX_BINARY_SIZE`, well under this
+pub const MAX_FETCH_SIZE: usize = 1 << 30;
+
+/// `data` compressed at `level`, or `None` when it's small or doesn't shrink
+pub fn compress(data: &[u8], level: i32) -> Option<Vec<u8>> {
+    if data.len() < MIN_COMPRESS_SIZE {
+        return None;
+    }
+    let compressed = zstd::bulk::compress(data, level).ok()?;
+    (compressed.len() < data.len()).then_some(compressed)
+}
+
+/// Decompress `data`, refusing to produce more than `max_size` bytes
+pub fn decompress(data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    zstd::stream::read::Decoder::new(data)?
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut out)?;
+    if out.len() > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("decompresses to more than {} bytes", max_size),
+        ));
+    }
+    Ok(out)
+}
+
+/// Whether an `Accept-Encoding` value allows zstd (and doesn't refuse it with `q=0`)
+pub fn accepts_zstd(accept_encoding: &str) -> bool {
+    accept_encoding.split(',').any(|part| {
+        let mut params = part.split(';');
+        let coding = params.next().unwrap_or("").trim();
+        let refused = params.any(|p| {
+            p.trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        });
+        coding.eq_ignore_ascii_case(ZSTD) && !refused
+    })
+}
@@ -0,0 +1,228 @@
+//! Building a source file with the compiler image on this machine, the way
+//! the API's local mode and the `ctf` CLI do. The compile worker has its own
+//! loop around the same [`crate::compile_protocol`].
+
+use crate::compile_protocol::{
+    self, CompileFailure, CompileManifest, CompileReport, Diagnostic, ResourceUsage, MANIFEST_FILE, RESULT_FILE,
+};
+use crate::execution::resolve_image_digest;
+use crate::runtime::RuntimeManifest;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+pub struct CompileOutput {
+    pub binary: Vec<u8>,
+    pub compiler_version: Option<String>,
+    pub compile_flags: Option<serde_json::Value>,
+    /// Digest of the image that ran the build
+    pub compiler_image: Option<String>,
+    /// With `emit_asm`, truncated to `MAX_ASM_BYTES`
+    pub asm: Option<String>,
+    /// Section and symbol sizes, for binaries binutils can read
+    pub size_report: Option<serde_json::Value>,
+    /// Toolchain the container built with, for languages that have a choice
+    pub toolchain: Option<String>,
+    /// From the image's `result.json`; empty for images that predate it
+    pub diagnostics: Vec<Diagnostic>,
+    /// Likewise; `None` too where the image can't read its cgroup
+    pub resources: Option<ResourceUsage>,
+    /// Interpreter the binary needs in the sandbox, for script bundles
+    pub runtime: Option<RuntimeManifest>,
+}
+
+/// Where a job's custom linker script goes in the work directory
+pub const LINKER_SCRIPT_FILE: &str = "linker.ld";
+
+/// Assembly past this is cut off (the compile worker keeps it in one KV value)
+const MAX_ASM_BYTES: usize = 768 * 1024;
+
+/// One build: the manifest's inputs plus what the container gets besides
+pub struct Build<'a> {
+    pub image: &'a str,
+    pub manifest: &'a CompileManifest,
+    pub source_code: &'a str,
+    /// Written to the manifest's `linker_script` file
+    pub linker_script: Option<&'a str>,
+    pub memory_limit_mb: u32,
+    pub timeout_sec: u64,
+}
+
+pub async fn compile(build: Build<'_>) -> Result<CompileOutput, CompileFailure> {
+    let Build { image, manifest, .. } = build;
+
+    // Create temp directory for compilation
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let work_dir = temp_dir.path();
+
+    tokio::fs::write(work_dir.join(&manifest.source_file), build.source_code)
+        .await
+        .map_err(|e| format!("Failed to write source: {}", e))?;
+
+    // Build docker command
+    let mut cmd = Command::new("docker");
+    cmd.args([
+        "run",
+        "--rm",
+        &format!("--memory={}m", build.memory_limit_mb),
+        &format!("--memory-swap={}m", build.memory_limit_mb),
+        "--tmpfs=/tmp:rw,exec,nosuid,size=512m",
+        "-v",
+        &format!("{}:/work:rw", work_dir.display()),
+        "-e",
+        &format!("LANGUAGE={}", manifest.language),
+        "-e",
+        &format!("OPTIMIZATION={}", manifest.optimization),
+        "-e",
+        &format!("SOURCE_FILE={}", manifest.source_file),
+        "-e",
+        &format!("OUTPUT_FILE={}", manifest.outputs.binary),
+    ]);
+
+    if manifest.emit_asm {
+        cmd.args(["-e", "EMIT_ASM=1"]);
+    }
+
+    if let Some(toolchain) = &manifest.toolchain {
+        cmd.args(["-e", &format!("TOOLCHAIN={}", toolchain)]);
+    }
+
+    // Build options were validated when the job was submitted
+    if !manifest.linker_flags.is_empty() {
+        cmd.args(["-e", &format!("LINKER_FLAGS={}", manifest.linker_flags.join(" "))]);
+    }
+    if let (Some(file), Some(script)) = (&manifest.linker_script, build.linker_script) {
+        tokio::fs::write(work_dir.join(file), script)
+            .await
+            .map_err(|e| format!("Failed to write linker script: {}", e))?;
+        cmd.args(["-e", &format!("LINKER_SCRIPT=/work/{}", file)]);
+    }
+
+    // Pass flags as environment variables (FLAG_<name>=<value>)
+    for (key, value) in &manifest.flags {
+        let safe_key: String = key.chars()
+            .filter(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        if !safe_key.is_empty() {
+            cmd.args(["-e", &format!("FLAG_{}={}", safe_key.to_uppercase(), value)]);
+        }
+    }
+
+    if !manifest.flags.is_empty() {
+        let flags_json = serde_json::to_string(&manifest.flags).unwrap_or_default();
+        cmd.args(["-e", &format!("FLAGS_JSON={}", flags_json)]);
+    }
+
+    // The same inputs as a versioned manifest, for images that read it
+    let manifest_json = serde_json::to_vec_pretty(manifest).map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    tokio::fs::write(work_dir.join(MANIFEST_FILE), manifest_json)
+        .await
+        .map_err(|e| format!("Failed to write manifest: {}", e))?;
+    let outputs = &manifest.outputs;
+
+    cmd.arg(image);
+
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+
+    let child = cmd.spawn().map_err(|e| format!("Failed to spawn docker: {}", e))?;
+
+    // Wait with timeout
+    let result = tokio::time::timeout(
+        Duration::from_secs(build.timeout_sec),
+        child.wait_with_output(),
+    )
+    .await;
+
+    let output = match result {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("Compilation failed: {}", e).into()),
+        Err(_) => return Err(format!("Compilation timed out after {} seconds", build.timeout_sec).into()),
+    };
+
+    let report = match tokio::fs::read(work_dir.join(RESULT_FILE)).await {
+        Ok(raw) => Some(CompileReport::parse(&raw)?),
+        Err(_) => None,
+    };
+
+    if !output.status.success() {
+        return Err(CompileFailure::Rejected(compile_protocol::failure_message(
+            output.status.code().unwrap_or(-1),
+            report.as_ref(),
+            build.memory_limit_mb,
+            &String::from_utf8_lossy(&output.stdout),
+            &String::from_utf8_lossy(&output.stderr),
+        )));
+    }
+
+    // Read compiled binary
+    let binary = tokio::fs::read(work_dir.join(&outputs.binary))
+        .await
+        .map_err(|e| format!("Failed to read compiled binary: {}", e))?;
+
+    if binary.is_empty() {
+        return Err("Compilation produced empty binary".to_string().into());
+    }
+
+    let compiler_version = tokio::fs::read_to_string(work_dir.join(&outputs.compiler_version))
+        .await
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let compile_flags = tokio::fs::read_to_string(work_dir.join(&outputs.compile_flags))
+        .await
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok());
+
+    let toolchain = tokio::fs::read_to_string(work_dir.join(&outputs.toolchain))
+        .await
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let size_report = tokio::fs::read_to_string(work_dir.join(&outputs.size_report))
+        .await
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok());
+
+    let runtime = match tokio::fs::read_to_string(work_dir.join(&outputs.runtime)).await {
+        Ok(raw) => Some(RuntimeManifest::parse(&raw).map_err(|e| format!("Bad {} from the compiler image: {}", outputs.runtime, e))?),
+        Err(_) => None,
+    };
+
+    let asm = if manifest.emit_asm {
+        tokio::fs::read(work_dir.join(&outputs.asm))
+            .await
+            .ok()
+            .filter(|asm| !asm.is_empty())
+            .map(|asm| truncate_asm(&asm))
+    } else {
+        None
+    };
+
+    Ok(CompileOutput {
+        binary,
+        compiler_version,
+        compile_flags,
+        compiler_image: Some(resolve_image_digest(image).await.unwrap_or_else(|| image.to_string())),
+        asm,
+        size_report,
+        toolchain,
+        runtime,
+        resources: report.as_ref().and_then(|r| r.resources.clone()).map(|usage| ResourceUsage {
+            memory_limit_mb: Some(build.memory_limit_mb),
+            ..usage
+        }),
+        diagnostics: report.map(|r| r.diagnostics).unwrap_or_default(),
+    })
+}
+
+fn truncate_asm(asm: &[u8]) -> String {
+    if asm.len() <= MAX_ASM_BYTES {
+        return String::from_utf8_lossy(asm).into_owned();
+    }
+    let mut text = String::from_utf8_lossy(&asm[..MAX_ASM_BYTES]).into_owned();
+    text.push_str(&format!("\n; ... truncated ({} of {} bytes shown)\n", MAX_ASM_BYTES, asm.len()));
+    text
+}
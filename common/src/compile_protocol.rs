@@ -0,0 +1,261 @@
+//! The contract between the compile workers and the compiler image.
+//!
+//! Before starting the container the worker writes a [`CompileManifest`] to
+//! `/work/manifest.json`: the inputs, the flags and the file names it expects
+//! back. `compile.sh` builds from it (falling back to the older `LANGUAGE`,
+//! `OPTIMIZATION`, `FLAG_*` env vars, which workers still pass) and finishes
+//! by writing a [`CompileReport`] to `/work/result.json`: success, exit code,
+//! which outputs it produced, the compiler diagnostics it could parse and
+//! what the build cost according to the container's cgroup.
+//!
+//! Both carry `schema_version`. The image refuses a manifest newer than it
+//! understands and the worker refuses a report that isn't [`SCHEMA_VERSION`],
+//! so images and workers can be rolled out separately; an image that writes
+//! no report is an older one and is read the old way.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Written by the worker into `/work`
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Written by `compile.sh` into `/work`
+pub const RESULT_FILE: &str = "result.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileManifest {
+    pub schema_version: u32,
+    pub language: String,
+    /// `debug`, `release` or `size`
+    pub optimization: String,
+    /// Under `/work`
+    pub source_file: String,
+    /// Language-specific flags, exported to the build script as `FLAG_<NAME>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub flags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub emit_asm: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linker_flags: Vec<String>,
+    /// Under `/work`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linker_script: Option<String>,
+    /// `sccache`, `go` or `jvm` when a cache is mounted at `/cache`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_cache: Option<String>,
+    #[serde(default)]
+    pub outputs: Outputs,
+}
+
+/// File names under `/work` the build writes its results to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Outputs {
+    pub binary: String,
+    pub compiler_version: String,
+    pub compile_flags: String,
+    pub toolchain: String,
+    pub asm: String,
+    pub size_report: String,
+    pub build_cache_stats: String,
+    /// A [`crate::runtime::RuntimeManifest`], for bundles that need an interpreter
+    #[serde(default = "default_runtime_output")]
+    pub runtime: String,
+}
+
+fn default_runtime_output() -> String {
+    "runtime.json".to_string()
+}
+
+impl Default for Outputs {
+    fn default() -> Self {
+        Self {
+            binary: "output".to_string(),
+            compiler_version: "compiler_version.txt".to_string(),
+            compile_flags: "compile_flags.json".to_string(),
+            toolchain: "toolchain.txt".to_string(),
+            asm: "output.asm".to_string(),
+            size_report: "size_report.json".to_string(),
+            build_cache_stats: "build_cache_stats.json".to_string(),
+            runtime: default_runtime_output(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileReport {
+    pub schema_version: u32,
+    pub success: bool,
+    /// The build script's exit code
+    pub exit_code: i32,
+    /// Keys of [`Outputs`] that were written, e.g. `["binary", "compiler_version"]`
+    #[serde(default)]
+    pub produced: Vec<String>,
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    /// Missing where the image can't read its cgroup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+}
+
+/// What a build cost, from the compile container's cgroup (v2, or v1's
+/// `cpuacct` and `memory` controllers)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// User plus system time of every process in the build
+    pub cpu_seconds: f64,
+    /// High-water mark of the container's memory, page cache included
+    pub peak_memory_bytes: u64,
+    /// The kernel killed a process for going over the memory limit
+    #[serde(default)]
+    pub oom_killed: bool,
+    /// Ceilings the build ran under; filled in by the worker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>,
+}
+
+/// A compiler message in `file:line:column: level: message` form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// `error`, `warning` or `note`
+    pub level: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            if let Some(column) = self.column {
+                write!(f, "{}:", column)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.level, self.message)
+    }
+}
+
+impl CompileManifest {
+    pub fn new(language: &str, optimization: &str, source_file: &str) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            language: language.to_string(),
+            optimization: optimization.to_string(),
+            source_file: source_file.to_string(),
+            flags: BTreeMap::new(),
+            emit_asm: false,
+            toolchain: None,
+            linker_flags: Vec::new(),
+            linker_script: None,
+            build_cache: None,
+            outputs: Outputs::default(),
+        }
+    }
+}
+
+impl CompileReport {
+    /// Parse `result.json`, refusing a protocol version this build doesn't speak
+    pub fn parse(raw: &[u8]) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            schema_version: u32,
+        }
+
+        let Versioned { schema_version } = serde_json::from_slice(raw)
+            .map_err(|e| format!("Unreadable {} from the compiler image: {}", RESULT_FILE, e))?;
+        if schema_version != SCHEMA_VERSION {
+            return Err(format!(
+                "Compiler image speaks compile protocol v{}, this worker v{}",
+                schema_version, SCHEMA_VERSION
+            ));
+        }
+        serde_json::from_slice(raw)
+            .map_err(|e| format!("Unreadable {} from the compiler image: {}", RESULT_FILE, e))
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.level == "error")
+    }
+}
+
+/// Why a build produced no binary
+#[derive(Debug, Clone)]
+pub enum CompileFailure {
+    /// The build ran and failed, so the same inputs will fail again; these are
+    /// worth caching for a little while
+    Rejected(String),
+    /// Timeouts, Docker and I/O trouble: worth retrying
+    Infra(String),
+}
+
+impl CompileFailure {
+    pub fn message(&self) -> &str {
+        match self {
+            CompileFailure::Rejected(message) | CompileFailure::Infra(message) => message,
+        }
+    }
+
+    pub fn into_message(self) -> String {
+        match self {
+            CompileFailure::Rejected(message) | CompileFailure::Infra(message) => message,
+        }
+    }
+}
+
+/// Setup errors along the way are infra
+impl From<String> for CompileFailure {
+    fn from(message: String) -> Self {
+        CompileFailure::Infra(message)
+    }
+}
+
+/// The error for a failed build: the parsed errors first when the image
+/// reported any, then everything the container printed. `memory_limit_mb` is
+/// the ceiling the build had, named when it was killed for going over it.
+pub fn failure_message(
+    exit_code: i32,
+    report: Option<&CompileReport>,
+    memory_limit_mb: u32,
+    stdout: &str,
+    stderr: &str,
+) -> String {
+    let resources = report.and_then(|r| r.resources.as_ref());
+    // The shell itself being killed leaves no report, only docker's 137
+    let oom_killed = match resources {
+        Some(resources) => resources.oom_killed,
+        None => exit_code == 137,
+    };
+    let mut message = if oom_killed {
+        format!("Compilation ran out of memory (limit {} MB, exit {})\n", memory_limit_mb, exit_code)
+    } else {
+        format!("Compilation failed (exit {})\n", exit_code)
+    };
+    if let Some(resources) = resources {
+        message.push_str(&format!(
+            "cpu: {:.2}s, peak memory: {} MB\n",
+            resources.cpu_seconds,
+            resources.peak_memory_bytes / (1024 * 1024)
+        ));
+    }
+    let errors: Vec<String> = report.into_iter().flat_map(|r| r.errors()).map(|d| d.to_string()).collect();
+    if !errors.is_empty() {
+        message.push_str(&errors.join("\n"));
+        message.push('\n');
+    }
+    message.push_str(&format!("stdout: {}\nstderr: {}", stdout, stderr));
+    message
+}
@@ -0,0 +1,54 @@
+//! Core dumps of crashed runs, for debugging.
+//!
+//! When the guest dies on a fault signal, QEMU writes its core as
+//! `qemu_<binary>_<time>_<pid>.core` in its working directory, provided
+//! `RLIMIT_CORE` leaves room for it. A job with `core_dump` set gets an empty
+//! host temp dir bind-mounted at [`CORE_DIR`] and the core limit raised to
+//! [`MAX_CORE_BYTES`]; the entrypoint starts QEMU from there. Once the
+//! container is gone, [`CoreDir::collect`] reads the core back the way output
+//! files are read, so callers store and list it the same way.
+
+use crate::file_mode;
+use crate::outputs::{CollectedOutput, OutputError};
+use tempfile::TempDir;
+
+/// Where QEMU runs, and so writes the core, in debug runs
+pub const CORE_DIR: &str = "/work/core";
+pub const MAX_CORE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Host side of `/work/core`; keep it alive until the core is collected
+pub struct CoreDir {
+    dir: TempDir,
+}
+
+impl CoreDir {
+    pub fn create() -> Result<Self, OutputError> {
+        let dir = tempfile::Builder::new().prefix("ctf-core-").tempdir()?;
+        // Writable by whichever user the binary runs as
+        file_mode::set(dir.path(), 0o777)?;
+        Ok(Self { dir })
+    }
+
+    pub fn docker_args(&self) -> Vec<String> {
+        vec![
+            "-v".to_string(),
+            format!("{}:{}:rw", self.dir.path().display(), CORE_DIR),
+            format!("--ulimit=core={}", MAX_CORE_BYTES),
+            "-e".to_string(),
+            "CORE_DUMP=1".to_string(),
+        ]
+    }
+
+    /// The core QEMU left, if the run crashed; call once the container has exited
+    pub fn collect(&self) -> Option<CollectedOutput> {
+        let mut names: Vec<String> = std::fs::read_dir(self.dir.path())
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.ends_with(".core"))
+            .collect();
+        // One per crashed process; past the fixed prefix, names sort by crash time
+        names.sort();
+        let name = names.first()?;
+        Some(CollectedOutput::read(name, &self.dir.path().join(name), MAX_CORE_BYTES))
+    }
+}
@@ -0,0 +1,241 @@
+//! Diagnostics mode: running a binary under valgrind or AddressSanitizer.
+//!
+//! A job with `mode: diagnostics` runs in `DIAGNOSTICS_IMAGE` instead of the
+//! instruction-counting sandbox. That image runs the binary natively, under
+//! valgrind's memcheck or, for binaries built with `-fsanitize=address`, with
+//! ASan's own runtime, and appends the tool's log to stderr after a
+//! [`REPORT_MARKER`] line. [`take_report`] cuts that log off stderr and parses
+//! it into a [`DiagnosticsReport`]. No instructions are counted, so these runs
+//! never carry a benchmark.
+
+use serde::{Deserialize, Serialize};
+
+/// Printed on stderr, followed by the tool name, before the tool's log
+pub const REPORT_MARKER: &str = "__CTF_DIAGNOSTICS__";
+/// Errors listed in a report; `error_count` still counts all of them
+pub const MAX_REPORTED_ERRORS: usize = 50;
+/// Stack frames kept per error
+pub const MAX_FRAMES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionMode {
+    /// Under QEMU with the instruction-counting plugin
+    #[default]
+    Normal,
+    /// Natively under valgrind or ASan, see [`crate::diagnostics`]
+    Diagnostics,
+}
+
+impl ExecutionMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "normal" => Some(ExecutionMode::Normal),
+            "diagnostics" => Some(ExecutionMode::Diagnostics),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExecutionMode::Normal => "normal",
+            ExecutionMode::Diagnostics => "diagnostics",
+        }
+    }
+
+    pub fn is_normal(&self) -> bool {
+        *self == ExecutionMode::Normal
+    }
+}
+
+/// One memory error the tool reported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryError {
+    /// e.g. `invalid_read`, `uninitialised`, `leak` (valgrind) or ASan's own
+    /// name such as `heap-buffer-overflow`
+    pub kind: String,
+    /// The tool's headline for the error
+    pub message: String,
+    /// Innermost first, as the tool prints them (`main (t.c:6)`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    /// `valgrind` or `asan`
+    pub tool: String,
+    pub errors: Vec<MemoryError>,
+    /// All errors reported, including those past [`MAX_REPORTED_ERRORS`]
+    pub error_count: u64,
+    /// Bytes definitely leaked at exit
+    pub leaked_bytes: u64,
+}
+
+/// Cut the tool's log off the end of `stderr` and parse it; `None` when the
+/// entrypoint didn't print one
+pub fn take_report(stderr: &mut Vec<u8>) -> Option<DiagnosticsReport> {
+    let marker = format!("\n{} ", REPORT_MARKER);
+    let start = stderr.windows(marker.len()).rposition(|w| w == marker.as_bytes())?;
+    let log = String::from_utf8_lossy(&stderr[start + marker.len()..]).into_owned();
+    stderr.truncate(start);
+
+    let (tool, log) = log.split_once('\n').unwrap_or((&log, ""));
+    Some(match tool.trim() {
+        "asan" => parse_asan(log),
+        _ => parse_valgrind(log),
+    })
+}
+
+/// The first number in `s`, allowing valgrind's thousands separators
+fn leading_number(s: &str) -> Option<u64> {
+    let digits: String = s
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == ',')
+        .filter(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+fn valgrind_kind(headline: &str) -> Option<&'static str> {
+    let kind = if headline.starts_with("Invalid read") {
+        "invalid_read"
+    } else if headline.starts_with("Invalid write") {
+        "invalid_write"
+    } else if headline.starts_with("Invalid free") || headline.starts_with("Mismatched free") {
+        "invalid_free"
+    } else if headline.contains("uninitialised") {
+        "uninitialised"
+    } else if headline.starts_with("Source and destination overlap") {
+        "overlap"
+    } else if headline.contains("are definitely lost") || headline.contains("are possibly lost") {
+        "leak"
+    } else if headline.contains("fishy value") {
+        "invalid_argument"
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
+/// memcheck's log: blocks of `==PID== ` lines separated by empty ones
+pub fn parse_valgrind(log: &str) -> DiagnosticsReport {
+    let mut report = DiagnosticsReport {
+        tool: "valgrind".to_string(),
+        errors: Vec::new(),
+        error_count: 0,
+        leaked_bytes: 0,
+    };
+    let mut summary_count = None;
+    let mut current: Option<MemoryError> = None;
+    let mut in_stack = false;
+
+    let lines = log.lines().map(|line| match line.strip_prefix("==") {
+        Some(rest) => rest.split_once("== ").map(|(_, l)| l).unwrap_or(""),
+        None => line,
+    });
+    for line in lines.chain(std::iter::once("")) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            if let Some(error) = current.take() {
+                report.error_count += 1;
+                if report.errors.len() < MAX_REPORTED_ERRORS {
+                    report.errors.push(error);
+                }
+            }
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix("definitely lost:") {
+            report.leaked_bytes = leading_number(rest).unwrap_or(0);
+        } else if let Some(rest) = trimmed.strip_prefix("ERROR SUMMARY:") {
+            summary_count = leading_number(rest);
+        }
+
+        match &mut current {
+            None if !line.starts_with(' ') => {
+                current = valgrind_kind(trimmed).map(|kind| MemoryError {
+                    kind: kind.to_string(),
+                    message: trimmed.to_string(),
+                    frames: Vec::new(),
+                });
+                in_stack = true;
+            }
+            // Only the first stack: where it happened, not where the block came from
+            Some(error) if in_stack => match trimmed.strip_prefix("at ").or_else(|| trimmed.strip_prefix("by ")) {
+                Some(frame) if error.frames.len() < MAX_FRAMES => {
+                    let frame = frame.split_once(": ").map(|(_, f)| f).unwrap_or(frame);
+                    error.frames.push(frame.to_string());
+                }
+                Some(_) => {}
+                None => in_stack = false,
+            },
+            _ => {}
+        }
+    }
+
+    // The summary doesn't count leaks as errors unless asked to
+    report.error_count = summary_count.unwrap_or(0).max(report.error_count);
+    report
+}
+
+/// ASan's and LeakSanitizer's reports
+pub fn parse_asan(log: &str) -> DiagnosticsReport {
+    let mut report = DiagnosticsReport {
+        tool: "asan".to_string(),
+        errors: Vec::new(),
+        error_count: 0,
+        leaked_bytes: 0,
+    };
+    let mut current: Option<MemoryError> = None;
+
+    for line in log.lines().chain(std::iter::once("")) {
+        let trimmed = line.trim();
+        let headline = match trimmed.split_once("ERROR: AddressSanitizer: ") {
+            Some((_, rest)) => Some((rest.split_whitespace().next().unwrap_or("unknown").to_string(), rest)),
+            None if trimmed.starts_with("Direct leak of") || trimmed.starts_with("Indirect leak of") => {
+                if trimmed.starts_with("Direct") {
+                    report.leaked_bytes += trimmed
+                        .strip_prefix("Direct leak of")
+                        .and_then(leading_number)
+                        .unwrap_or(0);
+                }
+                Some(("leak".to_string(), trimmed))
+            }
+            None => None,
+        };
+
+        if let Some((kind, message)) = headline {
+            if let Some(error) = current.take() {
+                report.errors.push(error);
+            }
+            report.error_count += 1;
+            if report.errors.len() < MAX_REPORTED_ERRORS {
+                current = Some(MemoryError {
+                    kind,
+                    message: message.to_string(),
+                    frames: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let Some(error) = current.as_mut() else {
+            continue;
+        };
+        // `#0 0x4c3b1e in main /src/t.c:6:10`
+        if let Some(frame) = trimmed.strip_prefix('#') {
+            if error.frames.len() < MAX_FRAMES {
+                let frame = frame.split_once(" in ").map(|(_, f)| f).unwrap_or(frame);
+                error.frames.push(frame.to_string());
+            }
+        } else if trimmed.is_empty() && !error.frames.is_empty() {
+            // The first stack ends; later ones say where memory was allocated
+            report.errors.push(current.take().expect("checked above"));
+        }
+    }
+    if let Some(error) = current {
+        report.errors.push(error);
+    }
+    report
+}
@@ -0,0 +1,490 @@
+//! Running a binary in the sandbox image with `docker run`, the way the
+//! API's local mode and the `ctf` CLI do, and the [`ExecutionResult`] a run
+//! produces. Execute workers have their own loop around the same image.
+
+use crate::diagnostics::{self, DiagnosticsReport, ExecutionMode};
+use crate::file_mode;
+use crate::outputs::OutputFile;
+use crate::runtime::{self, RuntimeManifest};
+use crate::{redact, signals};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+static STATS_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\n(\{[^\n]+\})\n?$").unwrap());
+
+#[derive(Debug, Default, Deserialize)]
+struct PluginStats {
+    instructions: u64,
+    memory_peak_kb: u64,
+    #[serde(default)]
+    memory_rss_kb: u64,
+    #[serde(default)]
+    memory_hwm_kb: u64,
+    #[serde(default)]
+    memory_data_kb: u64,
+    #[serde(default)]
+    memory_stack_kb: u64,
+    #[serde(default)]
+    io_read_bytes: u64,
+    #[serde(default)]
+    io_write_bytes: u64,
+    // Guest memory (actual binary allocations)
+    #[serde(default)]
+    guest_mmap_bytes: u64,
+    #[serde(default)]
+    guest_mmap_peak: u64,
+    #[serde(default)]
+    guest_heap_bytes: u64,
+    limit_reached: bool,
+    #[serde(default)]
+    syscalls: u64,
+    #[serde(default)]
+    syscall_breakdown: std::collections::HashMap<String, u64>,
+    #[serde(default)]
+    process_count: u64,
+    #[serde(default)]
+    processes: Vec<ProcessStats>,
+    #[serde(default)]
+    thread_count: u64,
+    #[serde(default)]
+    threads: Vec<ThreadStats>,
+}
+
+/// One guest process of a run. Forked children get their own entry; threads
+/// are counted in their process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessStats {
+    pub pid: i32,
+    pub ppid: i32,
+    pub instructions: u64,
+    /// False if it was still running when the root process exited, in which
+    /// case its count stops there
+    #[serde(default)]
+    pub exited: bool,
+    /// Replaced itself via execve; the new program ran natively, uncounted
+    #[serde(default)]
+    pub exec: bool,
+}
+
+/// One guest thread (the main thread included). A thread burning instructions
+/// with next to no syscalls while others sit in `futex` is usually spinning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadStats {
+    pub pid: i32,
+    pub tid: i32,
+    pub instructions: u64,
+    #[serde(default)]
+    pub syscalls: u64,
+    #[serde(default)]
+    pub syscall_breakdown: std::collections::HashMap<String, u64>,
+}
+
+/// Which limit stopped a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitReason {
+    Instructions,
+    WallClock,
+    Memory,
+    /// Not a limit: a process exec'd a program QEMU doesn't follow (or the
+    /// runtime always does), so the count is short and the run can't rank
+    Exec,
+}
+
+impl LimitReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LimitReason::Instructions => "instructions",
+            LimitReason::WallClock => "wall_clock",
+            LimitReason::Memory => "memory",
+            LimitReason::Exec => "exec",
+        }
+    }
+}
+
+/// Smallest container QEMU and the plugin reliably start in
+pub const MIN_MEMORY_LIMIT_MB: u32 = 32;
+
+
+/// Per-run limits, already bounded (see [`Limits::bounded`])
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub instructions: u64,
+    pub timeout_ms: u64,
+    pub memory_mb: u32,
+}
+
+impl Limits {
+    /// A job's requested `timeout_ms` capped at `max_timeout_ms` and
+    /// `memory_limit_mb` capped at `max_memory_mb`; unset means
+    /// `max_timeout_ms` and `default_memory_mb`
+    pub fn bounded(
+        instructions: u64,
+        timeout_ms: Option<u64>,
+        memory_limit_mb: Option<u32>,
+        max_timeout_ms: u64,
+        default_memory_mb: u32,
+        max_memory_mb: u32,
+    ) -> Self {
+        Self {
+            instructions,
+            timeout_ms: timeout_ms.unwrap_or(max_timeout_ms).min(max_timeout_ms),
+            memory_mb: memory_limit_mb
+                .unwrap_or(default_memory_mb)
+                .clamp(MIN_MEMORY_LIMIT_MB, max_memory_mb.max(MIN_MEMORY_LIMIT_MB)),
+        }
+    }
+}
+
+/// Read-only root with small writable scratch space; published in the
+/// sandbox manifest so local runs match
+pub const FILESYSTEM_ARGS: [&str; 3] = [
+    "--read-only",
+    "--tmpfs=/tmp:rw,exec,nosuid,size=64m",
+    "--tmpfs=/var:rw,nosuid,size=16m",
+];
+
+/// Past the in-container `timeout`, the host gives up on the container
+const WALL_CLOCK_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Exit code of coreutils `timeout` when the entrypoint's wall-clock limit fires
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    pub instructions: u64,
+    pub memory_peak_kb: u64,
+    #[serde(default)]
+    pub memory_rss_kb: u64,
+    #[serde(default)]
+    pub memory_hwm_kb: u64,
+    #[serde(default)]
+    pub memory_data_kb: u64,
+    #[serde(default)]
+    pub memory_stack_kb: u64,
+    #[serde(default)]
+    pub io_read_bytes: u64,
+    #[serde(default)]
+    pub io_write_bytes: u64,
+    // Guest memory (actual binary allocations)
+    #[serde(default)]
+    pub guest_mmap_bytes: u64,
+    #[serde(default)]
+    pub guest_mmap_peak: u64,
+    #[serde(default)]
+    pub guest_heap_bytes: u64,
+    pub limit_reached: bool,
+    /// Set whenever `limit_reached` is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_reached_reason: Option<LimitReason>,
+    /// Killed by the container's memory limit
+    #[serde(default)]
+    pub oom_killed: bool,
+    /// `SIGSEGV`, `SIGILL`, `SIGFPE`, ... when the program crashed rather
+    /// than exiting non-zero; see [`crate::signals`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash_signal: Option<String>,
+    /// Memory limit the run had, in MB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u32>,
+    /// Sandbox image the run used, pinned by digest when the image has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_image: Option<String>,
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub execution_time_ms: u64,
+    #[serde(default)]
+    pub syscalls: u64,
+    #[serde(default)]
+    pub syscall_breakdown: std::collections::HashMap<String, u64>,
+    /// Guest processes measured; 1 unless the program forks
+    #[serde(default)]
+    pub process_count: u64,
+    /// Per-process split of `instructions`, root process first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<ProcessStats>,
+    /// Guest threads across all processes; `threads` lists the first 128
+    #[serde(default)]
+    pub thread_count: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub threads: Vec<ThreadStats>,
+    /// Files the job declared under `/work/out`, in the binary store
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output_files: Vec<OutputFile>,
+    /// The crash's core, in the binary store, when the job asked for one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_dump: Option<OutputFile>,
+    /// valgrind/ASan findings; only in diagnostics mode, which counts no
+    /// instructions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<DiagnosticsReport>,
+}
+
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error("{0}")]
+    Docker(String),
+
+    /// The container outlived its wall-clock limit plus grace, in seconds
+    #[error("execution timed out after {0} seconds")]
+    Timeout(u64),
+}
+
+/// The binary in an executable temp file, to mount at `/work/binary`; the
+/// file goes away when dropped
+pub fn write_binary(binary: &[u8]) -> std::io::Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+    file.write_all(binary)?;
+    file.as_file().sync_all()?;
+    file_mode::set(file.path(), 0o755)?;
+    Ok(file)
+}
+
+/// One run of a binary already written with [`write_binary`]
+pub struct Container<'a> {
+    pub image: &'a str,
+    pub binary: &'a Path,
+    pub limits: Limits,
+    pub stdin: &'a [u8],
+    /// Handed to the entrypoint, which passes them on to the binary
+    pub args: &'a [String],
+    pub network_enabled: bool,
+    /// Checked by the caller; the limits are set after them so they can't
+    /// be overridden
+    pub env_vars: &'a HashMap<String, String>,
+    /// From [`crate::sandbox_options::Prepared::docker_args`]
+    pub docker_args: &'a [String],
+    pub mode: ExecutionMode,
+    pub runtime: Option<&'a RuntimeManifest>,
+}
+
+pub async fn run(container: Container<'_>) -> Result<ExecutionResult, RunError> {
+    let Container { image, limits, mode, runtime, .. } = container;
+    let start = Instant::now();
+
+    // Build docker command
+    let mut cmd = Command::new("docker");
+    // Named and removed by hand rather than --rm, so the exit state can be
+    // inspected for OOM kills and a runaway container can be killed
+    let container_name = format!("ctf-run-{}", Uuid::new_v4());
+    cmd.args([
+        "run",
+        "--name",
+        &container_name,
+        "-i",
+        &format!("--memory={}m", limits.memory_mb),
+        &format!("--memory-swap={}m", limits.memory_mb),
+    ]);
+
+    // Only disable network if not explicitly enabled
+    if !container.network_enabled {
+        cmd.arg("--network=none");
+    }
+
+    cmd.args(FILESYSTEM_ARGS);
+
+    // Pass environment variables from challenge
+    for (key, value) in container.env_vars {
+        cmd.arg("-e");
+        cmd.arg(format!("{}={}", key, value));
+    }
+
+    // After the user's variables so they can't override the limits
+    cmd.args([
+        "-e",
+        &format!("LIMIT={}", limits.instructions),
+        "-e",
+        &format!("TIMEOUT_MS={}", limits.timeout_ms),
+        "-e",
+        &runtime::entrypoint_env(runtime),
+    ]);
+
+    cmd.args(container.docker_args);
+
+    cmd.args([
+        "-v",
+        &format!("{}:/work/binary:ro", container.binary.display()),
+        image,
+    ]);
+    cmd.args(container.args);
+
+    cmd.stdin(std::process::Stdio::piped());
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+
+    // Env values carry challenge flags; never log them
+    let std_cmd = cmd.as_std();
+    debug!(
+        command = %redact::command_line(std_cmd.get_program(), std_cmd.get_args()),
+        "Starting sandbox container"
+    );
+
+    let mut child = cmd.spawn().map_err(|e| RunError::Docker(e.to_string()))?;
+
+    // Write stdin if provided
+    if !container.stdin.is_empty() {
+        if let Some(mut child_stdin) = child.stdin.take() {
+            let _ = child_stdin.write_all(container.stdin).await;
+        }
+    } else {
+        // Close stdin
+        drop(child.stdin.take());
+    }
+
+    // The entrypoint enforces timeout_ms itself so the plugin still reports
+    // stats; this only catches a container that doesn't stop
+    let host_timeout = std::time::Duration::from_millis(limits.timeout_ms) + WALL_CLOCK_GRACE;
+    let result = tokio::time::timeout(host_timeout, child.wait_with_output()).await;
+
+    let execution_time_ms = start.elapsed().as_millis() as u64;
+    let oom_killed = container_oom_killed(&container_name).await;
+    remove_container(&container_name).await;
+
+    let output = match result {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(RunError::Docker(e.to_string())),
+        Err(_) => return Err(RunError::Timeout(host_timeout.as_secs())),
+    };
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    // Parse plugin stats from stderr
+    let mut stderr = output.stderr;
+    let diagnostics = match mode {
+        ExecutionMode::Normal => None,
+        ExecutionMode::Diagnostics => diagnostics::take_report(&mut stderr),
+    };
+    let captures = STATS_REGEX.captures(&stderr);
+    let has_stats = captures.is_some();
+    let stats = if let Some(captures) = captures {
+        let json_match = captures.get(1).unwrap();
+        let stats: PluginStats = serde_json::from_slice(json_match.as_bytes()).unwrap_or_default();
+        // Remove stats JSON from stderr
+        stderr.truncate(json_match.start() - 1); // -1 for the leading \n
+        stats
+    } else {
+        PluginStats::default()
+    };
+
+    // Docker's own OOMKilled flag; if the inspect failed, a SIGKILL that
+    // stopped QEMU before its atexit hook printed stats is the best sign
+    let oom_killed = oom_killed.unwrap_or(exit_code == 137 && !has_stats);
+    let limit_reached_reason = if stats.limit_reached {
+        Some(LimitReason::Instructions)
+    } else if oom_killed {
+        Some(LimitReason::Memory)
+    } else if exit_code == TIMEOUT_EXIT_CODE && execution_time_ms >= limits.timeout_ms {
+        Some(LimitReason::WallClock)
+    } else if stats.processes.iter().any(|p| p.exec) || runtime.is_some_and(|r| !r.is_counted()) {
+        Some(LimitReason::Exec)
+    } else {
+        None
+    };
+
+    Ok(ExecutionResult {
+        instructions: stats.instructions,
+        memory_peak_kb: stats.memory_peak_kb,
+        memory_rss_kb: stats.memory_rss_kb,
+        memory_hwm_kb: stats.memory_hwm_kb,
+        memory_data_kb: stats.memory_data_kb,
+        memory_stack_kb: stats.memory_stack_kb,
+        io_read_bytes: stats.io_read_bytes,
+        io_write_bytes: stats.io_write_bytes,
+        guest_mmap_bytes: stats.guest_mmap_bytes,
+        guest_mmap_peak: stats.guest_mmap_peak,
+        guest_heap_bytes: stats.guest_heap_bytes,
+        limit_reached: limit_reached_reason.is_some(),
+        crash_signal: signals::crash_signal(exit_code)
+            .filter(|_| limit_reached_reason.is_none_or(|r| r == LimitReason::Exec))
+            .map(str::to_string),
+        limit_reached_reason,
+        oom_killed,
+        memory_limit_mb: Some(limits.memory_mb),
+        sandbox_image: Some(image_reference(image).await),
+        exit_code,
+        stdout: BASE64.encode(&output.stdout),
+        stderr: BASE64.encode(&stderr),
+        execution_time_ms,
+        syscalls: stats.syscalls,
+        syscall_breakdown: stats.syscall_breakdown,
+        process_count: stats.process_count,
+        processes: stats.processes,
+        thread_count: stats.thread_count,
+        threads: stats.threads,
+        output_files: Vec::new(),
+        core_dump: None,
+        diagnostics,
+    })
+}
+
+/// Resolved digests by configured image name
+static IMAGE_REFERENCES: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+/// `repo@sha256:...` for a pulled image, or the image ID for a local build;
+/// `None` if Docker can't inspect it
+pub async fn resolve_image_digest(image: &str) -> Option<String> {
+    let output = Command::new("docker")
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{if .RepoDigests}}{{index .RepoDigests 0}}{{else}}{{.Id}}{{end}}",
+            image,
+        ])
+        .output()
+        .await
+        .ok()?;
+    let digest = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !digest.is_empty()).then_some(digest)
+}
+
+/// The resolved digest, or just the configured name if Docker can't tell;
+/// resolved once per image per process
+async fn image_reference(image: &str) -> String {
+    if let Some(reference) = IMAGE_REFERENCES.lock().await.get(image) {
+        return reference.clone();
+    }
+    let reference = resolve_image_digest(image).await.unwrap_or_else(|| image.to_string());
+    IMAGE_REFERENCES.lock().await.insert(image.to_string(), reference.clone());
+    reference
+}
+
+/// `State.OOMKilled` of a stopped container; `None` if it can't be inspected
+async fn container_oom_killed(name: &str) -> Option<bool> {
+    let output = Command::new("docker")
+        .args(["inspect", "--format", "{{.State.OOMKilled}}", name])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Force-remove a sandbox container, killing it if it's still running
+async fn remove_container(name: &str) {
+    let removed = Command::new("docker")
+        .args(["rm", "-f", name])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await;
+    if !removed.is_ok_and(|s| s.success()) {
+        warn!(container = name, "Failed to remove sandbox container");
+    }
+}
@@ -0,0 +1,34 @@
+//! Unix modes and owners for files mounted into sandbox containers.
+//!
+//! The API and the workers run on Linux. Elsewhere (the `ctf` CLI on
+//! Windows) there are no modes to set, and Docker Desktop mounts host files
+//! readable and executable by every container user, so [`set`] does nothing;
+//! owners can't be faked, so [`chown`] fails.
+
+use std::io;
+use std::path::Path;
+
+pub fn set(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+pub fn chown(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::chown(path, Some(uid), Some(gid))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, uid, gid);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "file owners need a Unix host"))
+    }
+}
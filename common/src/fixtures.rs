@@ -0,0 +1,194 @@
+//! Filesystem fixtures for challenges whose flag lives in a file.
+//!
+//! A challenge carries a [`FixtureSpec`] (e.g. a root-only `/flag.txt` plus a
+//! setuid `/usr/local/bin/readflag` helper). [`materialize`] writes the files
+//! to a host temp dir with the requested owner/mode; each is then bind-mounted
+//! read-only at its path and the binary runs as an unprivileged user, so the
+//! permissions actually apply (see [`crate::sandbox_options::Isolation`]).
+//! Setuid/setgid bits need `allow_setuid` in the challenge's sandbox options.
+
+use crate::file_mode;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
+use tempfile::TempDir;
+use thiserror::Error;
+
+pub const MAX_FIXTURE_FILES: usize = 16;
+pub const MAX_FIXTURE_BYTES: usize = 4 * 1024 * 1024;
+
+/// User the sandboxed binary runs as when a challenge has fixtures
+pub const DEFAULT_RUN_AS: &str = "1000:1000";
+
+/// Mount points the sandbox itself needs; fixtures may not shadow them
+const RESERVED_PREFIXES: &[&str] = &["/work", "/plugin", "/tmp", "/var", "/proc", "/sys", "/dev", "/entrypoint.sh"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FixtureSpec {
+    #[serde(default)]
+    pub files: Vec<FixtureFile>,
+    /// `uid:gid` for the sandboxed process (default [`DEFAULT_RUN_AS`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureFile {
+    /// Absolute path inside the sandbox, e.g. `/flag.txt`
+    pub path: String,
+    pub content: String,
+    /// `content` is base64 (helper binaries) rather than UTF-8 text
+    #[serde(default)]
+    pub base64: bool,
+    /// Octal permission bits, e.g. `"0400"` or `"4755"` for a setuid helper
+    #[serde(default = "default_mode")]
+    pub mode: String,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+}
+
+fn default_mode() -> String {
+    "0644".to_string()
+}
+
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error("invalid fixture {path}: {reason}")]
+    Invalid { path: String, reason: String },
+
+    #[error("too many fixture files: {0} (max {MAX_FIXTURE_FILES})")]
+    TooManyFiles(usize),
+
+    #[error("fixtures too large: {0} bytes (max {MAX_FIXTURE_BYTES})")]
+    TooLarge(usize),
+
+    #[error("failed to materialize {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl FixtureSpec {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn run_as(&self) -> &str {
+        self.run_as.as_deref().unwrap_or(DEFAULT_RUN_AS)
+    }
+
+    /// Check paths, modes and sizes without touching the filesystem
+    pub fn validate(&self, allow_setuid: bool) -> Result<(), FixtureError> {
+        if self.files.len() > MAX_FIXTURE_FILES {
+            return Err(FixtureError::TooManyFiles(self.files.len()));
+        }
+        let mut total = 0;
+        for (i, file) in self.files.iter().enumerate() {
+            let invalid = |reason: &str| FixtureError::Invalid {
+                path: file.path.clone(),
+                reason: reason.to_string(),
+            };
+            let path = Path::new(&file.path);
+            if !path.is_absolute() || path.components().any(|c| !matches!(c, Component::RootDir | Component::Normal(_))) {
+                return Err(invalid("path must be absolute and normalized"));
+            }
+            if path == Path::new("/") || RESERVED_PREFIXES.iter().any(|p| path.starts_with(p)) {
+                return Err(invalid("path is reserved by the sandbox"));
+            }
+            if self.files[..i].iter().any(|f| f.path == file.path) {
+                return Err(invalid("duplicate path"));
+            }
+            if file.parse_mode()? & 0o6000 != 0 && !allow_setuid {
+                return Err(invalid("setuid/setgid bits need allow_setuid in the sandbox options"));
+            }
+            total += file.bytes()?.len();
+        }
+        if total > MAX_FIXTURE_BYTES {
+            return Err(FixtureError::TooLarge(total));
+        }
+        if let Some(run_as) = &self.run_as {
+            let valid = run_as
+                .split_once(':')
+                .is_some_and(|(u, g)| u.parse::<u32>().is_ok() && g.parse::<u32>().is_ok());
+            if !valid {
+                return Err(FixtureError::Invalid {
+                    path: "run_as".to_string(),
+                    reason: format!("expected uid:gid, got {}", run_as),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FixtureFile {
+    fn parse_mode(&self) -> Result<u32, FixtureError> {
+        u32::from_str_radix(&self.mode, 8)
+            .ok()
+            .filter(|m| *m <= 0o7777)
+            .ok_or_else(|| FixtureError::Invalid {
+                path: self.path.clone(),
+                reason: format!("mode {} is not octal permission bits", self.mode),
+            })
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>, FixtureError> {
+        if self.base64 {
+            BASE64.decode(&self.content).map_err(|e| FixtureError::Invalid {
+                path: self.path.clone(),
+                reason: format!("bad base64: {}", e),
+            })
+        } else {
+            Ok(self.content.clone().into_bytes())
+        }
+    }
+}
+
+/// Fixture files written to the host; removed when dropped, so keep it alive
+/// until the container exits.
+pub struct Materialized {
+    _dir: TempDir,
+    mounts: Vec<(String, String)>,
+}
+
+impl Materialized {
+    /// One read-only bind mount per file
+    pub fn docker_args(&self) -> Vec<String> {
+        let mut args = Vec::with_capacity(self.mounts.len() * 2);
+        for (host, target) in &self.mounts {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}:ro", host, target));
+        }
+        args
+    }
+}
+
+/// Write the fixture files with their owner and mode. Setting a root owner
+/// needs the caller to run as root (the worker container does).
+pub fn materialize(spec: &FixtureSpec, allow_setuid: bool) -> Result<Materialized, FixtureError> {
+    spec.validate(allow_setuid)?;
+    let io = |path: &str| {
+        let path = path.to_string();
+        move |source| FixtureError::Io { path, source }
+    };
+
+    let dir = tempfile::Builder::new().prefix("ctf-fixtures-").tempdir().map_err(io("tempdir"))?;
+    // The sandbox user has to traverse the dir to reach the mounts' sources
+    file_mode::set(dir.path(), 0o755).map_err(io("tempdir"))?;
+
+    let mut mounts = Vec::with_capacity(spec.files.len());
+    for (i, file) in spec.files.iter().enumerate() {
+        let host_path = dir.path().join(i.to_string());
+        std::fs::write(&host_path, file.bytes()?).map_err(io(&file.path))?;
+        file_mode::chown(&host_path, file.uid, file.gid).map_err(io(&file.path))?;
+        // After chown, which clears setuid/setgid bits
+        file_mode::set(&host_path, file.parse_mode()?).map_err(io(&file.path))?;
+        mounts.push((host_path.display().to_string(), file.path.clone()));
+    }
+
+    Ok(Materialized { _dir: dir, mounts })
+}
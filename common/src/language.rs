@@ -0,0 +1,302 @@
+//! Languages the compiler image builds and its optimization levels, shared
+//! by the API and the `ctf` CLI.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    // Tier 1: Native compilation
+    C,
+    Cpp,
+    Rust,
+    Go,
+    Zig,
+    Asm,
+    Nim,
+    Pascal,
+    Ocaml,
+    Swift,
+    Haskell,
+    Csharp,
+    Fortran,
+    D,
+    Crystal,
+    V,
+    Ada,
+    // Tier 2: JVM -> Native (GraalVM)
+    Java,
+    Kotlin,
+    Scala,
+    Clojure,
+    // Tier 3: Scripting -> Bundle
+    Python,
+    Javascript,
+    Typescript,
+    Bun,
+    Deno,
+    Node,
+    Lua,
+    Perl,
+    Php,
+    Tcl,
+    // Tier 4: Special runtimes
+    Erlang,
+    Elixir,
+    Racket,
+    Wasm,
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[
+        Language::C,
+        Language::Cpp,
+        Language::Rust,
+        Language::Go,
+        Language::Zig,
+        Language::Asm,
+        Language::Nim,
+        Language::Pascal,
+        Language::Ocaml,
+        Language::Swift,
+        Language::Haskell,
+        Language::Csharp,
+        Language::Fortran,
+        Language::D,
+        Language::Crystal,
+        Language::V,
+        Language::Ada,
+        Language::Java,
+        Language::Kotlin,
+        Language::Scala,
+        Language::Clojure,
+        Language::Python,
+        Language::Javascript,
+        Language::Typescript,
+        Language::Bun,
+        Language::Deno,
+        Language::Node,
+        Language::Lua,
+        Language::Perl,
+        Language::Php,
+        Language::Tcl,
+        Language::Erlang,
+        Language::Elixir,
+        Language::Racket,
+        Language::Wasm,
+    ];
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        // `typescript+deno`: a language and its runtime in one name
+        if let Some((language, runtime)) = s
+            .split_once('+')
+            .filter(|(_, runtime)| !runtime.is_empty() && runtime.chars().all(|c| c.is_ascii_alphabetic()))
+        {
+            return Language::resolve(language, Some(runtime));
+        }
+        match s.to_lowercase().as_str() {
+            "c" => Some(Language::C),
+            "cpp" | "c++" => Some(Language::Cpp),
+            "rust" => Some(Language::Rust),
+            "go" | "golang" => Some(Language::Go),
+            "zig" => Some(Language::Zig),
+            "asm" | "assembly" => Some(Language::Asm),
+            "nim" => Some(Language::Nim),
+            "pascal" => Some(Language::Pascal),
+            "ocaml" => Some(Language::Ocaml),
+            "swift" => Some(Language::Swift),
+            "haskell" => Some(Language::Haskell),
+            "csharp" | "c#" => Some(Language::Csharp),
+            "fortran" | "f90" => Some(Language::Fortran),
+            "d" | "dlang" => Some(Language::D),
+            "crystal" | "cr" => Some(Language::Crystal),
+            "v" | "vlang" => Some(Language::V),
+            "ada" => Some(Language::Ada),
+            "java" => Some(Language::Java),
+            "kotlin" => Some(Language::Kotlin),
+            "scala" => Some(Language::Scala),
+            "clojure" => Some(Language::Clojure),
+            "python" => Some(Language::Python),
+            "javascript" | "js" => Some(Language::Javascript),
+            "typescript" | "ts" => Some(Language::Typescript),
+            "bun" => Some(Language::Bun),
+            "deno" => Some(Language::Deno),
+            "node" | "nodejs" => Some(Language::Node),
+            "lua" => Some(Language::Lua),
+            "perl" => Some(Language::Perl),
+            "php" => Some(Language::Php),
+            "tcl" => Some(Language::Tcl),
+            "erlang" => Some(Language::Erlang),
+            "elixir" => Some(Language::Elixir),
+            "racket" => Some(Language::Racket),
+            "wasm" | "wat" => Some(Language::Wasm),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Rust => "rust",
+            Language::Go => "go",
+            Language::Zig => "zig",
+            Language::Asm => "asm",
+            Language::Nim => "nim",
+            Language::Pascal => "pascal",
+            Language::Ocaml => "ocaml",
+            Language::Swift => "swift",
+            Language::Haskell => "haskell",
+            Language::Csharp => "csharp",
+            Language::Fortran => "fortran",
+            Language::D => "d",
+            Language::Crystal => "crystal",
+            Language::V => "v",
+            Language::Ada => "ada",
+            Language::Java => "java",
+            Language::Kotlin => "kotlin",
+            Language::Scala => "scala",
+            Language::Clojure => "clojure",
+            Language::Python => "python",
+            Language::Javascript => "javascript",
+            Language::Typescript => "typescript",
+            Language::Bun => "bun",
+            Language::Deno => "deno",
+            Language::Node => "node",
+            Language::Lua => "lua",
+            Language::Perl => "perl",
+            Language::Php => "php",
+            Language::Tcl => "tcl",
+            Language::Erlang => "erlang",
+            Language::Elixir => "elixir",
+            Language::Racket => "racket",
+            Language::Wasm => "wasm",
+        }
+    }
+
+    pub fn source_extension(&self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Rust => "rs",
+            Language::Go => "go",
+            Language::Zig => "zig",
+            Language::Asm => "S",
+            Language::Nim => "nim",
+            Language::Pascal => "pas",
+            Language::Ocaml => "ml",
+            Language::Swift => "swift",
+            Language::Haskell => "hs",
+            Language::Csharp => "cs",
+            Language::Fortran => "f90",
+            Language::D => "d",
+            Language::Crystal => "cr",
+            Language::V => "v",
+            Language::Ada => "adb",
+            Language::Java => "java",
+            Language::Kotlin => "kt",
+            Language::Scala => "scala",
+            Language::Clojure => "clj",
+            Language::Python => "py",
+            Language::Javascript => "js",
+            Language::Typescript => "ts",
+            Language::Bun => "ts",
+            Language::Deno => "ts",
+            Language::Node => "js",
+            Language::Lua => "lua",
+            Language::Perl => "pl",
+            Language::Php => "php",
+            Language::Tcl => "tcl",
+            Language::Erlang => "erl",
+            Language::Elixir => "ex",
+            Language::Racket => "rkt",
+            Language::Wasm => "wat",
+        }
+    }
+
+    /// The language the source is written in. `bun`, `deno` and `node` are
+    /// runtimes rather than languages; runs are labelled with this plus [`Language::runtime`]
+    pub fn canonical(&self) -> &'static str {
+        match self {
+            Language::Bun | Language::Node => "javascript",
+            Language::Deno => "typescript",
+            other => other.as_str(),
+        }
+    }
+
+    /// The JavaScript runtime bundled into the binary, for the languages that have one
+    pub fn runtime(&self) -> Option<&'static str> {
+        match self {
+            Language::Javascript | Language::Typescript | Language::Bun => Some("bun"),
+            Language::Deno => Some("deno"),
+            Language::Node => Some("node"),
+            _ => None,
+        }
+    }
+
+    /// A language plus an optional runtime (`typescript` + `deno`) as the
+    /// language to compile with; `None` if the runtime can't run that language
+    pub fn resolve(language: &str, runtime: Option<&str>) -> Option<Self> {
+        let language = Language::from_str(language)?;
+        let Some(runtime) = runtime.map(str::trim).filter(|r| !r.is_empty()) else {
+            return Some(language);
+        };
+        RUNTIMES
+            .iter()
+            .find(|(name, rt, _)| *name == language.canonical() && rt.eq_ignore_ascii_case(runtime))
+            .map(|(_, _, compile_as)| *compile_as)
+    }
+
+    /// Why `name` shouldn't be used as a language any more, if it shouldn't
+    pub fn deprecation(name: &str) -> Option<String> {
+        let language = Language::from_str(name)?;
+        if !matches!(language, Language::Bun | Language::Deno | Language::Node) {
+            return None;
+        }
+        Some(format!(
+            "language '{}' is deprecated, use language '{}' with runtime '{}'",
+            name.trim(),
+            language.canonical(),
+            language.runtime()?
+        ))
+    }
+}
+
+/// (language, runtime, language to compile with); a language's first entry is its default runtime
+const RUNTIMES: &[(&str, &str, Language)] = &[
+    ("javascript", "bun", Language::Javascript),
+    ("javascript", "node", Language::Node),
+    ("javascript", "deno", Language::Deno),
+    ("typescript", "bun", Language::Typescript),
+    ("typescript", "deno", Language::Deno),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Optimization {
+    Debug,
+    #[default]
+    Release,
+    Size,
+}
+
+impl Optimization {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "debug" => Some(Optimization::Debug),
+            "release" => Some(Optimization::Release),
+            "size" => Some(Optimization::Size),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Optimization::Debug => "debug",
+            Optimization::Release => "release",
+            Optimization::Size => "size",
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Code shared between the API, the execute worker and the compile worker.
+
+pub mod binary_cache;
+pub mod binary_checks;
+pub mod binary_encoding;
+pub mod compilation;
+pub mod compile_protocol;
+pub mod core_dump;
+pub mod diagnostics;
+pub mod execution;
+pub mod file_mode;
+pub mod fixtures;
+pub mod language;
+pub mod logging;
+pub mod outputs;
+pub mod redact;
+pub mod runtime;
+pub mod sandbox_options;
+pub mod signals;
+pub mod topology;
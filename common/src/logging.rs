@@ -0,0 +1,93 @@
+//! Log output for the API and both workers.
+//!
+//! `LOG_FORMAT=json` writes one JSON object per event instead of the
+//! human-readable lines, so logs can go straight into Loki or Elasticsearch:
+//! `timestamp`, `level`, `target`, `message` and the event's own fields at the
+//! top level, and the enclosing span under `span`. The API runs every request
+//! in a `request` span (`request_id`, `method`, `path`) and the workers run
+//! every job in a `job` span (`job_id`, `user_id`), so those fields are on
+//! every line logged on their behalf.
+//!
+//! Events that repeat on every poll (a fetch failing once a second while NATS
+//! is down, a reconciliation finding nothing) go through a [`Sampler`] and
+//! only one in `LOG_SAMPLE_RATE` (default 100; `1` keeps everything) is
+//! logged, with `suppressed` counting the ones dropped in between.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_SAMPLE_RATE: u64 = 100;
+
+static SAMPLE_RATE: AtomicU64 = AtomicU64::new(DEFAULT_SAMPLE_RATE);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// `LOG_FORMAT`: `json`, or anything else for text
+    pub fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// Install the global subscriber: `RUST_LOG` plus `default_directive`
+/// (e.g. `ctf_worker=info`), in the format `LOG_FORMAT` asks for
+pub fn init<W>(default_directive: &str, writer: W)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let sample_rate = std::env::var("LOG_SAMPLE_RATE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SAMPLE_RATE);
+    SAMPLE_RATE.store(sample_rate.max(1), Ordering::Relaxed);
+
+    let filter = EnvFilter::from_default_env().add_directive(default_directive.parse().unwrap());
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer);
+    match LogFormat::from_env() {
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+        LogFormat::Text => builder.init(),
+    }
+}
+
+/// Thins out one repetitive event; keep it in a `static` next to the call site
+pub struct Sampler {
+    seen: AtomicU64,
+}
+
+impl Sampler {
+    pub const fn new() -> Self {
+        Self { seen: AtomicU64::new(0) }
+    }
+
+    /// `Some(suppressed)` when this occurrence should be logged: the first,
+    /// then every `LOG_SAMPLE_RATE`th. `suppressed` is how many were skipped
+    /// since the last one logged.
+    pub fn sample(&self) -> Option<u64> {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        let rate = SAMPLE_RATE.load(Ordering::Relaxed);
+        match seen {
+            0 => Some(0),
+            n if n % rate == 0 => Some(rate - 1),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,174 @@
+//! Files a job writes to `/work/out`.
+//!
+//! A job declares the files it is expected to produce by name. The sandbox
+//! gets an empty host temp dir bind-mounted read-write at [`OUTPUT_DIR`] and,
+//! once the container is gone, [`OutputDir::collect`] reads the declared files
+//! back. Anything that isn't a regular file, or is over
+//! [`MAX_OUTPUT_FILE_BYTES`] (or would take the job past [`MAX_OUTPUT_BYTES`]),
+//! is listed with an error instead of its content. Callers put collected
+//! files in the binary store under their content ID and list them in the
+//! execution result as [`OutputFile`]s.
+//!
+//! Only plain names directly under `/work/out` are accepted, so collecting
+//! never has to follow a directory the sandbox controlled.
+
+use crate::file_mode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use tempfile::TempDir;
+use thiserror::Error;
+
+/// Where the sandboxed binary writes its output files
+pub const OUTPUT_DIR: &str = "/work/out";
+pub const MAX_OUTPUT_FILES: usize = 16;
+pub const MAX_OUTPUT_FILE_BYTES: u64 = 1024 * 1024;
+pub const MAX_OUTPUT_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_NAME_LENGTH: usize = 255;
+
+/// A declared output file as the execution result lists it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputFile {
+    /// Name under `/work/out`
+    pub path: String,
+    pub size: u64,
+    /// Binary store ID of the content; `None` when it wasn't collected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_id: Option<String>,
+    /// Why it wasn't collected: `missing`, `not_a_file`, `too_large`,
+    /// `unreadable` or `store_failed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An output file read back from the sandbox, not stored yet
+#[derive(Debug)]
+pub struct CollectedOutput {
+    pub file: OutputFile,
+    /// Empty unless `file.binary_id` is set
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum OutputError {
+    #[error("invalid output path {path}: {reason}")]
+    Invalid { path: String, reason: String },
+
+    #[error("too many output files: {0} (max {MAX_OUTPUT_FILES})")]
+    TooManyFiles(usize),
+
+    #[error("failed to create output dir: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The file name a declared path refers to: `name` or `/work/out/name`
+pub fn file_name(path: &str) -> Option<&str> {
+    let name = path.strip_prefix("/work/out/").unwrap_or(path);
+    let valid = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.len() <= MAX_NAME_LENGTH
+        && !name.contains(['/', '\0']);
+    valid.then_some(name)
+}
+
+/// Check declared paths without touching the filesystem
+pub fn validate(paths: &[String]) -> Result<(), OutputError> {
+    if paths.len() > MAX_OUTPUT_FILES {
+        return Err(OutputError::TooManyFiles(paths.len()));
+    }
+    for (i, path) in paths.iter().enumerate() {
+        let invalid = |reason: &str| OutputError::Invalid {
+            path: path.clone(),
+            reason: reason.to_string(),
+        };
+        let name = file_name(path).ok_or_else(|| invalid("must be a file name directly under /work/out"))?;
+        if paths[..i].iter().any(|p| file_name(p) == Some(name)) {
+            return Err(invalid("duplicate path"));
+        }
+    }
+    Ok(())
+}
+
+/// Binary store ID for `data`, as content-addressed binaries are named
+pub fn content_id(data: &[u8]) -> String {
+    format!("sha256-{}", hex::encode(Sha256::digest(data)))
+}
+
+/// Host side of `/work/out`; keep it alive until the files are collected
+pub struct OutputDir {
+    dir: TempDir,
+    names: Vec<String>,
+}
+
+impl OutputDir {
+    pub fn create(paths: &[String]) -> Result<Self, OutputError> {
+        validate(paths)?;
+        let dir = tempfile::Builder::new().prefix("ctf-outputs-").tempdir()?;
+        // Writable by whichever user the binary runs as
+        file_mode::set(dir.path(), 0o777)?;
+        let names = paths.iter().filter_map(|p| file_name(p)).map(str::to_string).collect();
+        Ok(Self { dir, names })
+    }
+
+    pub fn docker_args(&self) -> Vec<String> {
+        vec!["-v".to_string(), format!("{}:{}:rw", self.dir.path().display(), OUTPUT_DIR)]
+    }
+
+    /// Read back the declared files; call once the container has exited
+    pub fn collect(&self) -> Vec<CollectedOutput> {
+        let mut budget = MAX_OUTPUT_BYTES;
+        self.names
+            .iter()
+            .map(|name| {
+                let path = self.dir.path().join(name);
+                let collected = CollectedOutput::read(name, &path, MAX_OUTPUT_FILE_BYTES.min(budget));
+                budget -= collected.data.len() as u64;
+                collected
+            })
+            .collect()
+    }
+}
+
+impl CollectedOutput {
+    /// Read `path` as `name`, leaving the content out past `max` bytes
+    pub(crate) fn read(name: &str, path: &std::path::Path, max: u64) -> Self {
+        let (size, data, error) = match read_capped(path, max) {
+            Ok(data) => (data.len() as u64, data, None),
+            Err((size, error)) => (size, Vec::new(), Some(error.to_string())),
+        };
+        let binary_id = error.is_none().then(|| content_id(&data));
+        CollectedOutput {
+            file: OutputFile {
+                path: name.to_string(),
+                size,
+                binary_id,
+                error,
+            },
+            data,
+        }
+    }
+}
+
+/// A regular file's content, or its size and why it was left out
+fn read_capped(path: &std::path::Path, max: u64) -> Result<Vec<u8>, (u64, &'static str)> {
+    // Not following symlinks: the sandbox could point one anywhere on the host
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err((0, "missing")),
+        Err(_) => return Err((0, "unreadable")),
+    };
+    if !metadata.is_file() {
+        return Err((0, "not_a_file"));
+    }
+    let size = metadata.len();
+    if size > max {
+        return Err((size, "too_large"));
+    }
+
+    let mut data = Vec::with_capacity(size as usize);
+    std::fs::File::open(path)
+        .and_then(|file| file.take(size).read_to_end(&mut data))
+        .map_err(|_| (size, "unreadable"))?;
+    Ok(data)
+}
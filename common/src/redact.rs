@@ -0,0 +1,181 @@
+//! Keeping secrets out of worker logs, errors and run output.
+//!
+//! Challenge flags reach the sandbox as `-e KEY=VALUE` arguments, so a
+//! `docker run` command line must never be logged as-is. [`command_line`]
+//! keeps every argument except environment values, which become `***`.
+//!
+//! A [`Redactor`] masks known secret values wherever they turn up in text:
+//! the worker's own sensitive env vars (see [`is_sensitive_key`]) in every
+//! log line via [`RedactingWriter`] (JSON-escaped too, for `LOG_FORMAT=json`), and a job's env values in its stored
+//! error and, for challenge jobs, its stderr.
+
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+
+pub const REDACTED: &str = "***";
+
+/// Name parts that mark an env var's value as secret
+const SENSITIVE_KEY_PARTS: &[&str] = &["FLAG", "SECRET", "TOKEN", "PASSWORD", "PASSWD", "KEY", "CREDENTIALS"];
+
+/// Shorter values are left alone; masking them would mangle ordinary output
+const MIN_SECRET_LEN: usize = 4;
+
+/// Render `program args...` with the value of every `-e`/`--env` variable masked
+pub fn command_line<'a>(program: &OsStr, args: impl IntoIterator<Item = &'a OsStr>) -> String {
+    let mut parts = vec![program.to_string_lossy().into_owned()];
+    let mut env_next = false;
+
+    for arg in args {
+        let arg = arg.to_string_lossy();
+        if env_next {
+            parts.push(mask(&arg));
+            env_next = false;
+        } else if arg == "-e" || arg == "--env" {
+            parts.push(arg.into_owned());
+            env_next = true;
+        } else if let Some(assignment) = arg.strip_prefix("--env=") {
+            parts.push(format!("--env={}", mask(assignment)));
+        } else {
+            parts.push(arg.into_owned());
+        }
+    }
+    parts.join(" ")
+}
+
+fn mask(assignment: &str) -> String {
+    match assignment.split_once('=') {
+        Some((key, _)) => format!("{}={}", key, REDACTED),
+        // `-e KEY` takes the value from the caller's environment; nothing to hide
+        None => assignment.to_string(),
+    }
+}
+
+/// `FLAG`, `CTF_FLAG`, `SANDBOX_OPTIONS_SECRET`, `API_TOKEN`... (any `_`-separated
+/// part in [`SENSITIVE_KEY_PARTS`], case-insensitive)
+pub fn is_sensitive_key(key: &str) -> bool {
+    key.to_ascii_uppercase()
+        .split('_')
+        .any(|part| SENSITIVE_KEY_PARTS.contains(&part))
+}
+
+/// A set of secret values to mask in text
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    /// Longest first, so a secret containing another is masked whole
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    /// Values of this process's sensitive env vars
+    pub fn from_process_env() -> Self {
+        let mut redactor = Self::default();
+        for (key, value) in std::env::vars() {
+            if is_sensitive_key(&key) {
+                redactor.add(value);
+            }
+        }
+        redactor
+    }
+
+    /// This redactor plus a job's env values: every value when `all` (challenge
+    /// jobs), otherwise only those under sensitive keys
+    pub fn with_env<'a>(&self, env: impl IntoIterator<Item = (&'a String, &'a String)>, all: bool) -> Self {
+        let mut redactor = self.clone();
+        for (key, value) in env {
+            if all || is_sensitive_key(key) {
+                redactor.add(value.clone());
+            }
+        }
+        redactor
+    }
+
+    pub fn add(&mut self, secret: String) {
+        if secret.len() < MIN_SECRET_LEN || self.secrets.contains(&secret) {
+            return;
+        }
+        // JSON log lines carry the value with quotes and backslashes escaped
+        if let Ok(quoted) = serde_json::to_string(&secret) {
+            let escaped = &quoted[1..quoted.len() - 1];
+            if escaped != secret {
+                self.secrets.push(escaped.to_string());
+            }
+        }
+        self.secrets.push(secret);
+        self.secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+
+    pub fn scrub(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), REDACTED);
+            }
+        }
+        text
+    }
+
+    /// [`Redactor::scrub`] for output that may not be UTF-8
+    pub fn scrub_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        for secret in &self.secrets {
+            bytes = replace_bytes(&bytes, secret.as_bytes(), REDACTED.as_bytes());
+        }
+        bytes
+    }
+}
+
+fn replace_bytes(haystack: &[u8], needle: &[u8], replacement: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(haystack.len());
+    let mut rest = haystack;
+    while let Some(pos) = rest.windows(needle.len()).position(|w| w == needle) {
+        out.extend_from_slice(&rest[..pos]);
+        out.extend_from_slice(replacement);
+        rest = &rest[pos + needle.len()..];
+    }
+    out.extend_from_slice(rest);
+    out
+}
+
+/// `tracing_subscriber` writer that scrubs each formatted event on its way to stdout
+#[derive(Clone)]
+pub struct RedactingWriter {
+    redactor: Arc<Redactor>,
+}
+
+impl RedactingWriter {
+    pub fn new(redactor: Redactor) -> Self {
+        Self {
+            redactor: Arc::new(redactor),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for RedactingWriter {
+    type Writer = RedactedStdout<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactedStdout {
+            redactor: &self.redactor,
+            stdout: io::stdout(),
+        }
+    }
+}
+
+pub struct RedactedStdout<'a> {
+    redactor: &'a Redactor,
+    stdout: io::Stdout,
+}
+
+impl Write for RedactedStdout<'_> {
+    // The fmt layer writes each event in one call, so a secret is never split
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write_all(&self.redactor.scrub_bytes(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
@@ -0,0 +1,118 @@
+//! Runtime manifests for binaries that aren't standalone executables.
+//!
+//! Most languages compile to a static x86_64 binary that QEMU runs as
+//! `/work/binary`. Interpreter-script bundles (PHP, Tcl, escripts) need an
+//! interpreter the plain sandbox image doesn't have, so their compile script
+//! writes a [`RuntimeManifest`] next to the binary. The compile worker stores
+//! it with the binary's metadata, and the execute worker runs such binaries in
+//! the sandbox image for that runtime (`RUNTIME_SANDBOX_IMAGE`, overridable per
+//! runtime with `RUNTIME_IMAGES`), with the entrypoint in front of
+//! `/work/binary`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+
+/// Runtimes whose interpreter hands the program over with `execve`, which
+/// QEMU doesn't follow: escript starts `erl`, so a BEAM program is never
+/// counted
+const UNCOUNTED_RUNTIMES: &[&str] = &["escript"];
+
+/// Longest entrypoint accepted, in arguments
+pub const MAX_ENTRYPOINT_ARGS: usize = 8;
+const MAX_ARG_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeManifest {
+    /// What the binary needs to run, e.g. `php`, `tcl`, `escript`; picks the
+    /// sandbox image
+    pub runtime: String,
+    /// Interpreter command inside that image, absolute path first;
+    /// `/work/binary` and the job's arguments follow it
+    pub entrypoint: Vec<String>,
+}
+
+impl RuntimeManifest {
+    /// Manifests come out of the compile container, so they're checked
+    /// before being stored or turned into a sandbox command line
+    pub fn validate(&self) -> Result<(), String> {
+        if self.runtime.is_empty()
+            || self.runtime.len() > 32
+            || !self
+                .runtime
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(format!("invalid runtime name '{}'", self.runtime));
+        }
+        // The path also goes into the plugin's comma-separated options
+        match self.entrypoint.first() {
+            Some(program) if program.starts_with('/') && !program.contains(',') => {}
+            _ => return Err("entrypoint must start with an absolute path".to_string()),
+        }
+        if self.entrypoint.len() > MAX_ENTRYPOINT_ARGS {
+            return Err(format!("entrypoint has more than {} arguments", MAX_ENTRYPOINT_ARGS));
+        }
+        if self
+            .entrypoint
+            .iter()
+            .any(|arg| arg.is_empty() || arg.len() > MAX_ARG_LENGTH || arg.contains(['\n', '\0']))
+        {
+            return Err("entrypoint arguments must be non-empty single lines".to_string());
+        }
+        Ok(())
+    }
+
+    /// Parse the compile script's `runtime.json`
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let manifest: Self = serde_json::from_str(raw).map_err(|e| format!("unreadable runtime manifest: {}", e))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// False when the program itself runs past what the sandbox counts, so
+    /// its runs can't be ranked
+    pub fn is_counted(&self) -> bool {
+        !UNCOUNTED_RUNTIMES.contains(&self.runtime.as_str())
+    }
+
+}
+
+/// The sandbox's `RUNTIME_ENTRYPOINT`, one argument per line. Always set, to
+/// an empty value for native binaries, so a job's own env can't supply one
+pub fn entrypoint_env(runtime: Option<&RuntimeManifest>) -> String {
+    format!(
+        "RUNTIME_ENTRYPOINT={}",
+        runtime.map(|r| r.entrypoint.join("\n")).unwrap_or_default()
+    )
+}
+
+/// Which sandbox image has which runtime: `RUNTIME_SANDBOX_IMAGE` has all of
+/// them unless `RUNTIME_IMAGES` (`runtime=image,...`) names another
+#[derive(Debug, Clone)]
+pub struct RuntimeImages {
+    default: String,
+    by_runtime: HashMap<String, String>,
+}
+
+impl RuntimeImages {
+    pub fn from_env() -> Self {
+        Self {
+            default: env::var("RUNTIME_SANDBOX_IMAGE").unwrap_or_else(|_| "sandbox-runtimes".to_string()),
+            by_runtime: env::var("RUNTIME_IMAGES")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|entry| {
+                    let (runtime, image) = entry.split_once('=')?;
+                    Some((runtime.trim().to_string(), image.trim().to_string()))
+                })
+                .filter(|(runtime, image)| !runtime.is_empty() && !image.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn image_for(&self, runtime: &str) -> &str {
+        self.by_runtime.get(runtime).unwrap_or(&self.default)
+    }
+}
+
@@ -0,0 +1,298 @@
+//! Controlled insecurity for privilege-escalation challenges.
+//!
+//! A challenge may declare [`SandboxOptions`]: extra capabilities, a
+//! vulnerable helper process started alongside the solution, and permission
+//! to ship setuid fixtures. Only challenge rows (admin-authored; there is no
+//! user-facing way to set them) carry options. The API signs them per job
+//! with `SANDBOX_OPTIONS_SECRET` when publishing to NATS and the worker
+//! refuses any job whose options don't verify, so nothing that can merely
+//! publish to the jobs stream can grant itself privileges.
+
+use crate::file_mode;
+use crate::fixtures::{self, FixtureError, FixtureSpec, Materialized, DEFAULT_RUN_AS};
+use crate::core_dump::CoreDir;
+use crate::diagnostics::ExecutionMode;
+use crate::outputs::{CollectedOutput, OutputDir, OutputError};
+use crate::runtime::RuntimeManifest;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::Write;
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+/// Capabilities a challenge may add; anything that reaches the host
+/// (SYS_ADMIN, SYS_MODULE, NET_ADMIN, ...) is never allowed.
+pub const ALLOWED_CAPABILITIES: &[&str] = &[
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "KILL",
+    "NET_BIND_SERVICE",
+    "NET_RAW",
+    "SETGID",
+    "SETUID",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+];
+
+pub const MAX_HELPER_STARTUP_MS: u64 = 5000;
+
+/// Env name prefixes a job may not set: the entrypoint's own `SANDBOX_*`
+/// settings, the dynamic loader's and QEMU's. Workers refuse jobs using them.
+pub const RESERVED_ENV_PREFIXES: &[&str] = &["SANDBOX_", "LD_", "QEMU_"];
+
+/// Where the helper settings are mounted; the entrypoint reads them only
+/// from here, never from the environment
+const HELPER_ENV_PATH: &str = "/sandbox/helper.env";
+
+pub fn is_reserved_env(name: &str) -> bool {
+    RESERVED_ENV_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SandboxOptions {
+    /// Added with `--cap-add`, e.g. `["DAC_READ_SEARCH"]`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helper: Option<HelperProcess>,
+    /// Fixture files may carry setuid/setgid bits
+    #[serde(default)]
+    pub allow_setuid: bool,
+}
+
+/// Process started in the sandbox before the solution, e.g. a vulnerable
+/// root daemon listening on localhost. Its output is discarded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelperProcess {
+    /// Absolute path plus arguments, usually a fixture file
+    pub command: Vec<String>,
+    /// `uid:gid` the helper runs as
+    #[serde(default = "default_helper_user")]
+    pub user: String,
+    /// Head start before the solution runs
+    #[serde(default = "default_startup_ms")]
+    pub startup_ms: u64,
+}
+
+fn default_helper_user() -> String {
+    "0:0".to_string()
+}
+
+fn default_startup_ms() -> u64 {
+    200
+}
+
+#[derive(Debug, Error)]
+pub enum SandboxOptionsError {
+    #[error("invalid sandbox options: {0}")]
+    Invalid(String),
+
+    #[error("sandbox options are not signed by the API")]
+    Unsigned,
+
+    #[error(transparent)]
+    Fixture(#[from] FixtureError),
+
+    #[error("failed to write helper settings: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Output(#[from] OutputError),
+}
+
+fn is_uid_gid(s: &str) -> bool {
+    s.split_once(':')
+        .is_some_and(|(u, g)| u.parse::<u32>().is_ok() && g.parse::<u32>().is_ok())
+}
+
+impl SandboxOptions {
+    pub fn validate(&self) -> Result<(), SandboxOptionsError> {
+        let invalid = |msg: String| Err(SandboxOptionsError::Invalid(msg));
+        for cap in &self.capabilities {
+            if !ALLOWED_CAPABILITIES.contains(&cap.as_str()) {
+                return invalid(format!("capability {} is not allowed", cap));
+            }
+        }
+        if let Some(helper) = &self.helper {
+            match helper.command.first() {
+                Some(path) if path.starts_with('/') => {}
+                _ => return invalid("helper command must start with an absolute path".to_string()),
+            }
+            // The entrypoint word-splits the command, so keep it shell-inert
+            let safe = |c: char| c.is_ascii_alphanumeric() || "_./:=,@+-".contains(c);
+            if let Some(arg) = helper.command.iter().find(|a| a.is_empty() || !a.chars().all(safe)) {
+                return invalid(format!("helper argument {:?} has unsupported characters", arg));
+            }
+            if !is_uid_gid(&helper.user) {
+                return invalid(format!("helper user must be uid:gid, got {}", helper.user));
+            }
+            if helper.startup_ms > MAX_HELPER_STARTUP_MS {
+                return invalid(format!("helper startup_ms exceeds {}", MAX_HELPER_STARTUP_MS));
+            }
+        }
+        Ok(())
+    }
+
+    /// HMAC over everything that shapes the sandbox for this job, so a
+    /// signature can't be lifted onto another job, binary or fixture set.
+    pub fn sign(&self, secret: &[u8], job_id: &str, binary_id: &str, fixtures: Option<&FixtureSpec>) -> String {
+        hex::encode(self.mac(secret, job_id, binary_id, fixtures).finalize().into_bytes())
+    }
+
+    pub fn verify(
+        &self,
+        secret: &[u8],
+        job_id: &str,
+        binary_id: &str,
+        fixtures: Option<&FixtureSpec>,
+        signature: &str,
+    ) -> Result<(), SandboxOptionsError> {
+        let signature = hex::decode(signature).map_err(|_| SandboxOptionsError::Unsigned)?;
+        self.mac(secret, job_id, binary_id, fixtures)
+            .verify_slice(&signature)
+            .map_err(|_| SandboxOptionsError::Unsigned)
+    }
+
+    fn mac(&self, secret: &[u8], job_id: &str, binary_id: &str, fixtures: Option<&FixtureSpec>) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+        for part in [
+            job_id.as_bytes(),
+            binary_id.as_bytes(),
+            &serde_json::to_vec(self).unwrap_or_default(),
+            &serde_json::to_vec(&fixtures).unwrap_or_default(),
+        ] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        mac
+    }
+}
+
+/// Everything beyond the default sandbox that a challenge asks for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Isolation<'a> {
+    pub fixtures: Option<&'a FixtureSpec>,
+    pub options: Option<&'a SandboxOptions>,
+    /// Files the job declared under `/work/out`, see [`crate::outputs`]
+    pub outputs: &'a [String],
+    /// Keep the core if the binary crashes, see [`crate::core_dump`]
+    pub core_dump: bool,
+    /// Which image runs the binary; doesn't change what gets prepared
+    pub mode: ExecutionMode,
+    /// Interpreter the binary needs; like `mode`, only picks the image and
+    /// the entrypoint
+    pub runtime: Option<&'a RuntimeManifest>,
+}
+
+/// What a run left behind, read back after the container exited
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub outputs: Vec<CollectedOutput>,
+    pub core_dump: Option<CollectedOutput>,
+}
+
+/// Materialized fixtures, output and core dirs plus the `docker run`
+/// arguments that apply them; keep it alive until the container exits and
+/// outputs are collected.
+pub struct Prepared {
+    _fixtures: Option<Materialized>,
+    _helper_env: Option<NamedTempFile>,
+    outputs: Option<OutputDir>,
+    core_dump: Option<CoreDir>,
+    args: Vec<String>,
+}
+
+impl Prepared {
+    pub fn docker_args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// The declared output files and any core; call once the container has exited
+    pub fn collect(&self) -> Collected {
+        Collected {
+            outputs: self.outputs.as_ref().map(OutputDir::collect).unwrap_or_default(),
+            core_dump: self.core_dump.as_ref().and_then(CoreDir::collect),
+        }
+    }
+}
+
+impl Isolation<'_> {
+    /// Validate and set up the sandbox; `None` when nothing beyond the defaults
+    /// was requested.
+    pub fn prepare(self) -> Result<Option<Prepared>, SandboxOptionsError> {
+        let fixtures = self.fixtures.filter(|f| !f.is_empty());
+        let outputs = match self.outputs {
+            [] => None,
+            paths => Some(OutputDir::create(paths)?),
+        };
+        let core_dump = self.core_dump.then(CoreDir::create).transpose()?;
+        let mut args = outputs.as_ref().map(OutputDir::docker_args).unwrap_or_default();
+        args.extend(core_dump.as_ref().map(CoreDir::docker_args).unwrap_or_default());
+        if fixtures.is_none() && self.options.is_none() {
+            if outputs.is_none() && core_dump.is_none() {
+                return Ok(None);
+            }
+            return Ok(Some(Prepared {
+                _fixtures: None,
+                _helper_env: None,
+                outputs,
+                core_dump,
+                args,
+            }));
+        }
+        let options = self.options.cloned().unwrap_or_default();
+        options.validate()?;
+
+        let materialized = fixtures
+            .map(|spec| fixtures::materialize(spec, options.allow_setuid))
+            .transpose()?;
+        let run_as = fixtures.map(|f| f.run_as()).unwrap_or(DEFAULT_RUN_AS);
+
+        args.extend(materialized.as_ref().map(|m| m.docker_args()).unwrap_or_default());
+        for cap in &options.capabilities {
+            args.push(format!("--cap-add={}", cap));
+        }
+        let helper_env = match &options.helper {
+            // Start as root so the entrypoint can launch the helper as its
+            // user, then drop to run_as for the solution
+            Some(helper) => {
+                let file = helper_env(helper, run_as)?;
+                args.extend([
+                    "--user=0:0".to_string(),
+                    "-v".to_string(),
+                    format!("{}:{}:ro", file.path().display(), HELPER_ENV_PATH),
+                ]);
+                Some(file)
+            }
+            None => {
+                args.push(format!("--user={}", run_as));
+                None
+            }
+        };
+
+        Ok(Some(Prepared {
+            _fixtures: materialized,
+            _helper_env: helper_env,
+            outputs,
+            core_dump,
+            args,
+        }))
+    }
+}
+
+/// `KEY=VALUE` lines for the entrypoint, written by the worker from options
+/// that already passed verification
+fn helper_env(helper: &HelperProcess, run_as: &str) -> Result<NamedTempFile, SandboxOptionsError> {
+    let mut file = tempfile::Builder::new().prefix("ctf-helper-").tempfile()?;
+    writeln!(file, "SANDBOX_RUN_AS={}", run_as)?;
+    writeln!(file, "SANDBOX_HELPER={}", helper.command.join(" "))?;
+    writeln!(file, "SANDBOX_HELPER_USER={}", helper.user)?;
+    writeln!(file, "SANDBOX_HELPER_STARTUP_MS={}", helper.startup_ms)?;
+    file.flush()?;
+    // Created 0600 and owned by the worker's user, not necessarily the
+    // container's root
+    file_mode::set(file.path(), 0o644)?;
+    Ok(file)
+}
@@ -0,0 +1,24 @@
+//! Telling crashes apart from ordinary non-zero exits.
+//!
+//! QEMU re-raises a fatal guest signal on itself, so the entrypoint, and with
+//! it the container, exits with 128 + the signal number the way a shell
+//! reports a child killed by a signal. Only signals that mean the program
+//! faulted count as crashes; SIGKILL and SIGTERM come from the sandbox's own
+//! limits and are reported through `limit_reached_reason` instead.
+
+const CRASH_SIGNALS: &[(i32, &str)] = &[
+    (4, "SIGILL"),
+    (5, "SIGTRAP"),
+    (6, "SIGABRT"),
+    (7, "SIGBUS"),
+    (8, "SIGFPE"),
+    (11, "SIGSEGV"),
+    (31, "SIGSYS"),
+];
+
+/// The signal a container exit code says the program crashed with, e.g.
+/// `SIGSEGV` for 139
+pub fn crash_signal(exit_code: i32) -> Option<&'static str> {
+    let signal = exit_code.checked_sub(128)?;
+    CRASH_SIGNALS.iter().find(|(n, _)| *n == signal).map(|(_, name)| *name)
+}
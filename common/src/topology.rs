@@ -0,0 +1,530 @@
+//! NATS JetStream topology shared by the API and both workers.
+//!
+//! Every stream and KV bucket is declared exactly once here. Each process
+//! calls [`Topology::ensure`] at startup, which creates anything missing and
+//! asserts that existing resources match the declared config. Streams are
+//! stamped with [`TOPOLOGY_VERSION`] in their metadata so a config change
+//! (retention, max_age, ...) is only applied when the version is bumped or
+//! when the operator explicitly opts in with `NATS_TOPOLOGY_MIGRATE=true`.
+//!
+//! For clustered NATS, `NATS_STREAM_REPLICAS`, `NATS_PLACEMENT_CLUSTER`,
+//! `NATS_PLACEMENT_TAGS` and `NATS_MAX_STREAM_BYTES` apply to every stream and
+//! bucket. They're part of the declared config: a stream already stamped
+//! with the current version that was created with other settings fails
+//! startup with the differences listed, until the operator migrates it.
+
+use async_nats::jetstream::{
+    self,
+    kv::{self, Store},
+    stream::{self, Placement, RetentionPolicy, StorageType, Stream},
+};
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Bump whenever a declared stream/bucket config changes.
+pub const TOPOLOGY_VERSION: u32 = 1;
+
+const VERSION_METADATA_KEY: &str = "ctf_topology_version";
+
+// ============ Names ============
+
+pub const JOBS_STREAM: &str = "JOBS";
+pub const JOBS_SUBJECT: &str = "jobs.submit";
+/// Jobs with nothing secret in them, which registered external workers may run too
+pub const SHARED_JOBS_STREAM: &str = "SHARED_JOBS";
+pub const SHARED_JOBS_SUBJECT: &str = "jobs.shared";
+pub const COMPILES_STREAM: &str = "COMPILES";
+pub const COMPILES_SUBJECT: &str = "compiles.submit";
+/// Runs the worker could not persist through the API, replayed once it recovers
+pub const RUNS_SPOOL_STREAM: &str = "RUNS_SPOOL";
+pub const RUNS_SPOOL_SUBJECT: &str = "runs.spool";
+/// Things that happened, for any subsystem to follow with its own consumer
+pub const EVENTS_STREAM: &str = "EVENTS";
+pub const EVENTS_SUBJECT: &str = "events.>";
+/// A leaderboard entry improved (JSON, see the API's events.rs)
+pub const LEADERBOARD_EVENTS_SUBJECT: &str = "events.leaderboard";
+
+pub const JOBS_KV: &str = "jobs";
+pub const RESULTS_KV: &str = "results";
+pub const COMPILES_KV: &str = "compiles";
+pub const BINARIES_KV: &str = "binaries";
+pub const COMPILE_CACHE_KV: &str = "compile_cache";
+
+pub const WORKER_CONSUMER: &str = "worker";
+/// Shared by in-house and external workers
+pub const SHARED_WORKER_CONSUMER: &str = "shared-worker";
+pub const COMPILE_WORKER_CONSUMER: &str = "compile-worker";
+pub const RUNS_SPOOL_CONSUMER: &str = "runs-spool";
+/// The API's notification sender on the events stream
+pub const NOTIFICATIONS_CONSUMER: &str = "notifications";
+
+/// How long spooled runs survive an API/Postgres outage.
+pub const RUNS_SPOOL_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// How long events stay around for consumers that fall behind or start later.
+pub const EVENTS_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Largest value accepted by the binaries bucket (100MB).
+pub const MAX_BINARY_VALUE_SIZE: i32 = 100 * 1024 * 1024;
+
+// ============ Errors ============
+
+#[derive(Debug, thiserror::Error)]
+pub enum TopologyError {
+    #[error("Failed to ensure {resource}: {message}")]
+    Nats { resource: String, message: String },
+
+    #[error(
+        "{resource} does not match topology v{expected} (found {}): {}. \
+         Bump TOPOLOGY_VERSION or set NATS_TOPOLOGY_MIGRATE=true to apply",
+        .found.map(|v| format!("v{}", v)).unwrap_or_else(|| "unversioned".to_string()),
+        .differences.join(", ")
+    )]
+    Mismatch {
+        resource: String,
+        expected: u32,
+        found: Option<u32>,
+        differences: Vec<String>,
+    },
+
+    #[error("Invalid NATS topology settings: {}", .0.join(", "))]
+    InvalidConfig(Vec<String>),
+
+    #[error("{resource} has immutable settings that differ from the topology: {}. Delete and recreate it", .differences.join(", "))]
+    Immutable {
+        resource: String,
+        differences: Vec<String>,
+    },
+}
+
+impl TopologyError {
+    fn nats(resource: &str, e: impl std::fmt::Display) -> Self {
+        TopologyError::Nats {
+            resource: resource.to_string(),
+            message: e.to_string(),
+        }
+    }
+}
+
+// ============ Config ============
+
+#[derive(Debug, Clone)]
+pub struct TopologyConfig {
+    /// max_age for the work-queue streams and the job/compile status buckets
+    pub job_ttl: Duration,
+    /// max_age for the binaries and compile_cache buckets
+    pub binary_ttl: Duration,
+    /// Apply config differences to streams already stamped with the current version
+    pub migrate: bool,
+    /// Copies of every stream and bucket in a cluster
+    pub replicas: usize,
+    /// Cluster and server tags streams are placed on; `None` lets NATS choose
+    pub placement: Option<Placement>,
+    /// Size cap for each stream and bucket in bytes, -1 for none
+    pub max_bytes: i64,
+    /// Settings from the environment that didn't parse, reported by [`Topology::ensure`]
+    invalid: Vec<String>,
+}
+
+/// JetStream's ceiling on replicas
+const MAX_REPLICAS: usize = 5;
+
+impl TopologyConfig {
+    pub fn new(job_ttl_seconds: u64, binary_ttl_seconds: u64) -> Self {
+        let mut invalid = Vec::new();
+
+        let replicas = match env::var("NATS_STREAM_REPLICAS") {
+            Ok(v) => match v.trim().parse::<usize>() {
+                Ok(n) if (1..=MAX_REPLICAS).contains(&n) => n,
+                _ => {
+                    invalid.push(format!("NATS_STREAM_REPLICAS must be 1-{}, got '{}'", MAX_REPLICAS, v));
+                    1
+                }
+            },
+            Err(_) => 1,
+        };
+
+        let max_bytes = match env::var("NATS_MAX_STREAM_BYTES") {
+            Ok(v) => match v.trim().parse::<i64>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    invalid.push(format!("NATS_MAX_STREAM_BYTES must be a positive byte count, got '{}'", v));
+                    -1
+                }
+            },
+            Err(_) => -1,
+        };
+
+        let cluster = env::var("NATS_PLACEMENT_CLUSTER")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        let tags: Vec<String> = env::var("NATS_PLACEMENT_TAGS")
+            .map(|v| v.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        let placement = (cluster.is_some() || !tags.is_empty()).then_some(Placement { cluster, tags });
+
+        Self {
+            job_ttl: Duration::from_secs(job_ttl_seconds),
+            binary_ttl: Duration::from_secs(binary_ttl_seconds),
+            migrate: env::var("NATS_TOPOLOGY_MIGRATE")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            replicas,
+            placement,
+            max_bytes,
+            invalid,
+        }
+    }
+}
+
+// ============ Declarations ============
+
+enum Kind {
+    Stream { subjects: Vec<String>, retention: RetentionPolicy },
+    KeyValue { bucket: &'static str },
+}
+
+/// Declared settings for one JetStream stream (KV buckets are backed by a `KV_<bucket>` stream).
+struct Spec {
+    name: String,
+    kind: Kind,
+    storage: StorageType,
+    max_age: Duration,
+    max_message_size: i32,
+}
+
+impl Spec {
+    fn stream(name: &str, subject: &str, max_age: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: Kind::Stream {
+                subjects: vec![subject.to_string()],
+                retention: RetentionPolicy::WorkQueue,
+            },
+            storage: StorageType::File,
+            max_age,
+            max_message_size: -1,
+        }
+    }
+
+    /// Kept until `max_age` rather than until acked, so every consumer sees every event
+    fn events(name: &str, subject: &str, max_age: Duration) -> Self {
+        Self {
+            kind: Kind::Stream {
+                subjects: vec![subject.to_string()],
+                retention: RetentionPolicy::Limits,
+            },
+            ..Self::stream(name, subject, max_age)
+        }
+    }
+
+    fn key_value(bucket: &'static str, storage: StorageType, max_age: Duration, max_value_size: i32) -> Self {
+        Self {
+            name: format!("KV_{}", bucket),
+            kind: Kind::KeyValue { bucket },
+            storage,
+            max_age,
+            max_message_size: max_value_size,
+        }
+    }
+
+    fn stream_config(&self, config: &TopologyConfig) -> stream::Config {
+        let Kind::Stream { subjects, retention } = &self.kind else {
+            unreachable!("stream_config called on a KV spec");
+        };
+        stream::Config {
+            name: self.name.clone(),
+            subjects: subjects.clone(),
+            retention: *retention,
+            max_age: self.max_age,
+            storage: self.storage,
+            max_message_size: self.max_message_size,
+            num_replicas: config.replicas,
+            placement: config.placement.clone(),
+            max_bytes: config.max_bytes,
+            metadata: version_metadata(HashMap::new()),
+            ..Default::default()
+        }
+    }
+
+    fn kv_config(&self, bucket: &str, config: &TopologyConfig) -> kv::Config {
+        kv::Config {
+            bucket: bucket.to_string(),
+            max_age: self.max_age,
+            max_value_size: self.max_message_size,
+            storage: self.storage,
+            num_replicas: config.replicas,
+            placement: config.placement.clone(),
+            max_bytes: config.max_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Differences that NATS cannot apply in place.
+    fn immutable_differences(&self, current: &stream::Config) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if current.storage != self.storage {
+            diffs.push(format!("storage {:?} != {:?}", current.storage, self.storage));
+        }
+        if let Kind::Stream { retention, .. } = &self.kind {
+            if current.retention != *retention {
+                diffs.push(format!("retention {:?} != {:?}", current.retention, retention));
+            }
+        }
+        diffs
+    }
+
+    fn mutable_differences(&self, current: &stream::Config, config: &TopologyConfig) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if current.num_replicas.max(1) != config.replicas {
+            diffs.push(format!("replicas {} != {}", current.num_replicas.max(1), config.replicas));
+        }
+        if normalize_placement(current.placement.as_ref()) != normalize_placement(config.placement.as_ref()) {
+            diffs.push(format!("placement {:?} != {:?}", current.placement, config.placement));
+        }
+        if normalize_bytes(current.max_bytes) != normalize_bytes(config.max_bytes) {
+            diffs.push(format!("max_bytes {} != {}", current.max_bytes, config.max_bytes));
+        }
+        if current.max_age != self.max_age {
+            diffs.push(format!("max_age {:?} != {:?}", current.max_age, self.max_age));
+        }
+        if normalize_size(current.max_message_size) != normalize_size(self.max_message_size) {
+            diffs.push(format!(
+                "max_message_size {} != {}",
+                current.max_message_size, self.max_message_size
+            ));
+        }
+        if let Kind::Stream { subjects, .. } = &self.kind {
+            if &current.subjects != subjects {
+                diffs.push(format!("subjects {:?} != {:?}", current.subjects, subjects));
+            }
+        }
+        diffs
+    }
+
+    /// Current config with the declared (mutable) settings applied.
+    fn apply(&self, current: &stream::Config, config: &TopologyConfig) -> stream::Config {
+        let mut updated = current.clone();
+        updated.max_age = self.max_age;
+        updated.max_message_size = self.max_message_size;
+        updated.num_replicas = config.replicas;
+        updated.placement = config.placement.clone();
+        updated.max_bytes = config.max_bytes;
+        if let Kind::Stream { subjects, .. } = &self.kind {
+            updated.subjects = subjects.clone();
+        }
+        updated.metadata = version_metadata(updated.metadata);
+        updated
+    }
+}
+
+fn normalize_size(size: i32) -> i32 {
+    if size <= 0 { -1 } else { size }
+}
+
+fn normalize_bytes(bytes: i64) -> i64 {
+    if bytes <= 0 { -1 } else { bytes }
+}
+
+/// (cluster, sorted tags); no placement and an empty one are the same
+fn normalize_placement(placement: Option<&Placement>) -> (Option<&str>, Vec<&str>) {
+    let Some(placement) = placement else {
+        return (None, Vec::new());
+    };
+    let mut tags: Vec<&str> = placement.tags.iter().map(String::as_str).collect();
+    tags.sort_unstable();
+    (placement.cluster.as_deref(), tags)
+}
+
+fn version_metadata(mut metadata: HashMap<String, String>) -> HashMap<String, String> {
+    metadata.insert(VERSION_METADATA_KEY.to_string(), TOPOLOGY_VERSION.to_string());
+    metadata
+}
+
+fn stored_version(config: &stream::Config) -> Option<u32> {
+    config
+        .metadata
+        .get(VERSION_METADATA_KEY)
+        .and_then(|v| v.parse().ok())
+}
+
+// ============ Topology ============
+
+/// Handles to every stream and bucket, all verified against the declared config.
+pub struct Topology {
+    pub jobs_stream: Stream,
+    pub shared_jobs_stream: Stream,
+    pub compiles_stream: Stream,
+    pub runs_spool_stream: Stream,
+    pub events_stream: Stream,
+    pub jobs_kv: Store,
+    pub results_kv: Store,
+    pub compiles_kv: Store,
+    pub binaries_kv: Store,
+    pub compile_cache_kv: Store,
+}
+
+impl Topology {
+    pub async fn ensure(jetstream: &jetstream::Context, config: &TopologyConfig) -> Result<Self, TopologyError> {
+        if !config.invalid.is_empty() {
+            return Err(TopologyError::InvalidConfig(config.invalid.clone()));
+        }
+
+        let jobs_stream = ensure_stream(jetstream, &Spec::stream(JOBS_STREAM, JOBS_SUBJECT, config.job_ttl), config).await?;
+        let shared_jobs_stream =
+            ensure_stream(jetstream, &Spec::stream(SHARED_JOBS_STREAM, SHARED_JOBS_SUBJECT, config.job_ttl), config)
+                .await?;
+        let compiles_stream =
+            ensure_stream(jetstream, &Spec::stream(COMPILES_STREAM, COMPILES_SUBJECT, config.job_ttl), config).await?;
+        let runs_spool_stream =
+            ensure_stream(jetstream, &Spec::stream(RUNS_SPOOL_STREAM, RUNS_SPOOL_SUBJECT, RUNS_SPOOL_MAX_AGE), config)
+                .await?;
+        let events_stream =
+            ensure_stream(jetstream, &Spec::events(EVENTS_STREAM, EVENTS_SUBJECT, EVENTS_MAX_AGE), config).await?;
+
+        let jobs_kv = ensure_kv(jetstream, &Spec::key_value(JOBS_KV, StorageType::File, config.job_ttl, -1), config).await?;
+        let results_kv =
+            ensure_kv(jetstream, &Spec::key_value(RESULTS_KV, StorageType::File, config.job_ttl, -1), config).await?;
+        let compiles_kv =
+            ensure_kv(jetstream, &Spec::key_value(COMPILES_KV, StorageType::File, config.job_ttl, -1), config).await?;
+        // Memory storage for faster writes (binaries are ephemeral anyway)
+        let binaries_kv = ensure_kv(
+            jetstream,
+            &Spec::key_value(BINARIES_KV, StorageType::Memory, config.binary_ttl, MAX_BINARY_VALUE_SIZE),
+            config,
+        )
+        .await?;
+        let compile_cache_kv = ensure_kv(
+            jetstream,
+            &Spec::key_value(COMPILE_CACHE_KV, StorageType::File, config.binary_ttl, -1),
+            config,
+        )
+        .await?;
+
+        info!("NATS topology v{} verified ({} replica(s))", TOPOLOGY_VERSION, config.replicas);
+
+        Ok(Self {
+            jobs_stream,
+            shared_jobs_stream,
+            compiles_stream,
+            runs_spool_stream,
+            events_stream,
+            jobs_kv,
+            results_kv,
+            compiles_kv,
+            binaries_kv,
+            compile_cache_kv,
+        })
+    }
+}
+
+async fn ensure_stream(jetstream: &jetstream::Context, spec: &Spec, config: &TopologyConfig) -> Result<Stream, TopologyError> {
+    match jetstream.get_stream(&spec.name).await {
+        Ok(stream) => {
+            reconcile(jetstream, spec, &stream.cached_info().config, config).await?;
+            jetstream
+                .get_stream(&spec.name)
+                .await
+                .map_err(|e| TopologyError::nats(&spec.name, e))
+        }
+        Err(_) => {
+            info!("Creating stream {}", spec.name);
+            jetstream
+                .create_stream(spec.stream_config(config))
+                .await
+                .map_err(|e| TopologyError::nats(&spec.name, e))
+        }
+    }
+}
+
+async fn ensure_kv(jetstream: &jetstream::Context, spec: &Spec, config: &TopologyConfig) -> Result<Store, TopologyError> {
+    let Kind::KeyValue { bucket } = spec.kind else {
+        unreachable!("ensure_kv called on a stream spec");
+    };
+
+    match jetstream.get_stream(&spec.name).await {
+        Ok(stream) => {
+            reconcile(jetstream, spec, &stream.cached_info().config, config).await?;
+        }
+        Err(_) => {
+            info!("Creating KV bucket {}", bucket);
+            jetstream
+                .create_key_value(spec.kv_config(bucket, config))
+                .await
+                .map_err(|e| TopologyError::nats(bucket, e))?;
+            // kv::Config has no metadata, so stamp the backing stream afterwards
+            let stream = jetstream
+                .get_stream(&spec.name)
+                .await
+                .map_err(|e| TopologyError::nats(bucket, e))?;
+            let mut stamped = stream.cached_info().config.clone();
+            stamped.metadata = version_metadata(stamped.metadata);
+            jetstream
+                .update_stream(stamped)
+                .await
+                .map_err(|e| TopologyError::nats(bucket, e))?;
+        }
+    }
+
+    jetstream
+        .get_key_value(bucket)
+        .await
+        .map_err(|e| TopologyError::nats(bucket, e))
+}
+
+/// Compare an existing stream with its declaration, migrating or failing as the version allows.
+async fn reconcile(
+    jetstream: &jetstream::Context,
+    spec: &Spec,
+    current: &stream::Config,
+    config: &TopologyConfig,
+) -> Result<(), TopologyError> {
+    let immutable = spec.immutable_differences(current);
+    if !immutable.is_empty() {
+        return Err(TopologyError::Immutable {
+            resource: spec.name.clone(),
+            differences: immutable,
+        });
+    }
+
+    let found = stored_version(current);
+    let differences = spec.mutable_differences(current, config);
+
+    if differences.is_empty() {
+        // Stamp resources created before versioning (or by an older release)
+        if found.is_none_or(|v| v < TOPOLOGY_VERSION) {
+            let mut stamped = current.clone();
+            stamped.metadata = version_metadata(stamped.metadata);
+            jetstream
+                .update_stream(stamped)
+                .await
+                .map_err(|e| TopologyError::nats(&spec.name, e))?;
+        }
+        return Ok(());
+    }
+
+    let outdated = found.is_none_or(|v| v < TOPOLOGY_VERSION);
+    if !outdated && !config.migrate {
+        return Err(TopologyError::Mismatch {
+            resource: spec.name.clone(),
+            expected: TOPOLOGY_VERSION,
+            found,
+            differences,
+        });
+    }
+
+    warn!(
+        "Migrating {} to topology v{}: {}",
+        spec.name,
+        TOPOLOGY_VERSION,
+        differences.join(", ")
+    );
+    jetstream
+        .update_stream(spec.apply(current, config))
+        .await
+        .map_err(|e| TopologyError::nats(&spec.name, e))?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! Persistent build caches, mounted into the compiler container at `/cache`.
+//!
+//! With `BUILD_CACHE_DIR` set, languages with a reusable toolchain cache get
+//! a directory under it: sccache for C, C++ and Rust, the Go build cache, and
+//! the Gradle/Maven caches for the JVM languages. `compile.sh` points each
+//! toolchain at `/cache` based on `BUILD_CACHE`.
+//!
+//! `BUILD_CACHE_ISOLATION=user` (the default) gives every user their own
+//! caches, anonymous compiles sharing one; `shared` pools them. Each cache is
+//! held to `BUILD_CACHE_MAX_MB`, dropping the least recently written files
+//! first once a build pushes it over.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Written by `compile.sh` after an sccache build
+const SCCACHE_STATS_FILE: &str = "build_cache_stats.json";
+
+/// Eviction trims a cache to this share of its quota, so it isn't trimmed on every build
+const EVICT_TO_PERCENT: u64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    Shared,
+    User,
+}
+
+impl Isolation {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "shared" => Some(Isolation::Shared),
+            "user" => Some(Isolation::User),
+            _ => None,
+        }
+    }
+}
+
+/// How a build used its cache; reported in `CompileResult::build_cache`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildCacheUsage {
+    /// `sccache`, `go` or `jvm`
+    pub kind: String,
+    /// The cache had entries before this build
+    pub warm: bool,
+    /// Compilations served from the cache (sccache only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hits: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub misses: Option<u64>,
+    /// Cache size after the build (and any eviction)
+    pub size_bytes: u64,
+}
+
+/// A cache directory prepared for one build
+pub struct CacheMount {
+    pub kind: &'static str,
+    pub dir: PathBuf,
+    warm: bool,
+}
+
+pub struct BuildCache {
+    root: PathBuf,
+    max_bytes: u64,
+    isolation: Isolation,
+}
+
+/// Which cache a language's toolchain can use, if any
+pub fn kind_for(language: &str) -> Option<&'static str> {
+    match language {
+        "c" | "cpp" | "rust" => Some("sccache"),
+        "go" => Some("go"),
+        "java" | "kotlin" | "scala" | "clojure" => Some("jvm"),
+        _ => None,
+    }
+}
+
+impl BuildCache {
+    pub fn new(root: PathBuf, max_mb: u64, isolation: Isolation) -> Self {
+        Self {
+            root,
+            max_bytes: max_mb * 1024 * 1024,
+            isolation,
+        }
+    }
+
+    pub fn max_mb(&self) -> u64 {
+        self.max_bytes / (1024 * 1024)
+    }
+
+    /// Create (if needed) the cache directory for a `language` build by `user_id`
+    pub async fn prepare(&self, language: &str, user_id: Option<&Uuid>) -> Option<CacheMount> {
+        let kind = kind_for(language)?;
+        let scope = match (self.isolation, user_id) {
+            (Isolation::Shared, _) => "shared".to_string(),
+            (Isolation::User, Some(user_id)) => format!("user-{}", user_id),
+            (Isolation::User, None) => "anonymous".to_string(),
+        };
+        let dir = self.root.join(scope).join(kind);
+
+        if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+            warn!(dir = %dir.display(), error = %e, "Failed to create build cache, building cold");
+            return None;
+        }
+        let warm = match tokio::fs::read_dir(&dir).await {
+            Ok(mut entries) => matches!(entries.next_entry().await, Ok(Some(_))),
+            Err(_) => false,
+        };
+        Some(CacheMount { kind, dir, warm })
+    }
+
+    /// Enforce the quota after a build and report how the cache was used
+    pub async fn finish(&self, mount: CacheMount, work_dir: &Path) -> BuildCacheUsage {
+        let (hits, misses) = if mount.kind == "sccache" {
+            read_sccache_stats(&work_dir.join(SCCACHE_STATS_FILE)).await
+        } else {
+            (None, None)
+        };
+
+        let dir = mount.dir.clone();
+        let max_bytes = self.max_bytes;
+        let size_bytes = tokio::task::spawn_blocking(move || enforce_quota(&dir, max_bytes))
+            .await
+            .unwrap_or(0);
+
+        BuildCacheUsage {
+            kind: mount.kind.to_string(),
+            warm: mount.warm,
+            hits,
+            misses,
+            size_bytes,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SccacheStats {
+    stats: SccacheCounters,
+}
+
+#[derive(Deserialize)]
+struct SccacheCounters {
+    cache_hits: SccachePerLanguage,
+    cache_misses: SccachePerLanguage,
+}
+
+#[derive(Deserialize)]
+struct SccachePerLanguage {
+    counts: std::collections::HashMap<String, u64>,
+}
+
+/// (hits, misses) from `sccache --show-stats --stats-format json`
+async fn read_sccache_stats(path: &Path) -> (Option<u64>, Option<u64>) {
+    let Ok(raw) = tokio::fs::read(path).await else {
+        return (None, None);
+    };
+    match serde_json::from_slice::<SccacheStats>(&raw) {
+        Ok(s) => (
+            Some(s.stats.cache_hits.counts.values().sum()),
+            Some(s.stats.cache_misses.counts.values().sum()),
+        ),
+        Err(e) => {
+            warn!(error = %e, "Unreadable sccache stats");
+            (None, None)
+        }
+    }
+}
+
+/// Every file under `dir` with its size and last write
+fn walk(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            walk(&entry.path(), files);
+        } else if meta.is_file() {
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((entry.path(), meta.len(), modified));
+        }
+    }
+}
+
+/// Drop the oldest files until `dir` fits its quota; returns the size left
+fn enforce_quota(dir: &Path, max_bytes: u64) -> u64 {
+    let mut files = Vec::new();
+    walk(dir, &mut files);
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if total <= max_bytes {
+        return total;
+    }
+
+    let target = max_bytes / 100 * EVICT_TO_PERCENT;
+    let before = total;
+    files.sort_by_key(|(_, _, modified)| *modified);
+    for (path, len, _) in files {
+        if total <= target {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+    info!(dir = %dir.display(), before, after = total, "Build cache trimmed to quota");
+    total
+}
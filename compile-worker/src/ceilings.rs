@@ -0,0 +1,68 @@
+//! Resource ceilings for compile containers.
+//!
+//! Every build gets `COMPILE_MEMORY_LIMIT_MB` of memory (no swap) and, with
+//! `COMPILE_CPUS` set, that many CPUs. Toolchains that need more or should
+//! get less are set per language with `COMPILE_MEMORY_LIMIT_MB_BY_LANGUAGE`
+//! and `COMPILE_CPUS_BY_LANGUAGE` (e.g. `graalvm=8192`, `rust=2`); group keys
+//! work as in slots.rs.
+//!
+//! What a build really used comes back from the compiler image in
+//! `result.json` and is passed on in `CompileResult::resources` along with
+//! the ceilings it ran under.
+
+use crate::slots;
+use std::collections::HashMap;
+
+pub struct Ceilings {
+    memory_limit_mb: u32,
+    cpus: Option<f64>,
+    memory_limit_mb_by_language: HashMap<String, u32>,
+    cpus_by_language: HashMap<String, f64>,
+}
+
+/// The limits one build runs under
+#[derive(Debug, Clone, Copy)]
+pub struct Ceiling {
+    pub memory_limit_mb: u32,
+    pub cpus: Option<f64>,
+}
+
+impl Ceilings {
+    pub fn new(
+        memory_limit_mb: u32,
+        cpus: Option<f64>,
+        memory_limit_mb_by_language: HashMap<String, u32>,
+        cpus_by_language: HashMap<String, f64>,
+    ) -> Self {
+        Self {
+            memory_limit_mb,
+            cpus: cpus.filter(|n| *n > 0.0),
+            memory_limit_mb_by_language,
+            cpus_by_language: cpus_by_language.into_iter().filter(|(_, n)| *n > 0.0).collect(),
+        }
+    }
+
+    pub fn for_language(&self, language: &str) -> Ceiling {
+        Ceiling {
+            memory_limit_mb: slots::for_language(&self.memory_limit_mb_by_language, language)
+                .map(|(_, mb)| *mb)
+                .unwrap_or(self.memory_limit_mb),
+            cpus: slots::for_language(&self.cpus_by_language, language)
+                .map(|(_, n)| *n)
+                .or(self.cpus),
+        }
+    }
+}
+
+impl Ceiling {
+    pub fn docker_args(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("--memory={}m", self.memory_limit_mb),
+            format!("--memory-swap={}m", self.memory_limit_mb),
+        ];
+        if let Some(cpus) = self.cpus {
+            args.push(format!("--cpus={}", cpus));
+        }
+        args
+    }
+}
@@ -0,0 +1,135 @@
+//! In-flight deduplication: identical jobs share one build.
+//!
+//! Before building, a worker claims the job's cache key with a
+//! `<cache key>_building` entry in the compile cache bucket. An identical job
+//! (same source, language, flags, image, ...) that finds the claim waits for
+//! it to be released and then serves the result from the cache instead of
+//! starting another container.
+//!
+//! The claim goes away when the build finishes. If it goes away without a
+//! result (the build failed), waiters build for themselves. A claim older than
+//! the compile timeout plus [`CLAIM_GRACE`] belongs to a worker that died and
+//! is taken over, and a redelivered job may retake its own claim, so a crash
+//! never leaves identical jobs waiting on nothing.
+
+use async_nats::jetstream::kv::{CreateErrorKind, Operation, Store};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Time past the compile timeout for storing the binary and cache entry
+const CLAIM_GRACE: Duration = Duration::from_secs(120);
+
+/// How often a waiting job checks the claim
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Deserialize)]
+struct Marker {
+    job_id: Uuid,
+    started_at: DateTime<Utc>,
+}
+
+impl Marker {
+    fn is_stale(&self, build_timeout: Duration) -> bool {
+        let age = (Utc::now() - self.started_at).to_std().unwrap_or_default();
+        age > build_timeout + CLAIM_GRACE
+    }
+}
+
+pub enum Claim {
+    /// This job builds; release the claim when done
+    Acquired(Building),
+    /// An identical build is running
+    Busy,
+}
+
+/// A claimed cache key
+pub struct Building {
+    key: String,
+    /// `None` when the claim couldn't be written and the job builds unclaimed
+    revision: Option<u64>,
+}
+
+fn claim_key(cache_key: &str) -> String {
+    format!("{}_building", cache_key)
+}
+
+/// Claim `cache_key` for `job_id`, unless a live identical build holds it
+pub async fn claim(store: &Store, cache_key: &str, job_id: &Uuid, build_timeout: Duration) -> Claim {
+    let key = claim_key(cache_key);
+    let marker = Marker {
+        job_id: *job_id,
+        started_at: Utc::now(),
+    };
+    let value = serde_json::to_vec(&marker).unwrap_or_default();
+
+    match store.create(&key, value.clone().into()).await {
+        Ok(revision) => {
+            return Claim::Acquired(Building {
+                key,
+                revision: Some(revision),
+            })
+        }
+        Err(e) if e.kind() == CreateErrorKind::AlreadyExists => {}
+        Err(e) => {
+            warn!(job_id = %job_id, error = %e, "Failed to claim build, building without dedup");
+            return Claim::Acquired(Building { key, revision: None });
+        }
+    }
+
+    let entry = match store.entry(&key).await {
+        Ok(Some(entry)) if entry.operation == Operation::Put => entry,
+        // Released in between; the caller looks at the cache again
+        Ok(_) => return Claim::Busy,
+        Err(e) => {
+            warn!(job_id = %job_id, error = %e, "Failed to read build claim, building without dedup");
+            return Claim::Acquired(Building { key, revision: None });
+        }
+    };
+    let takeover = match serde_json::from_slice::<Marker>(&entry.value) {
+        // A redelivery of the job that claimed it, or a worker that died mid-build
+        Ok(held) => held.job_id == *job_id || held.is_stale(build_timeout),
+        Err(_) => true,
+    };
+    if !takeover {
+        return Claim::Busy;
+    }
+    match store.update(&key, value.into(), entry.revision).await {
+        Ok(revision) => Claim::Acquired(Building {
+            key,
+            revision: Some(revision),
+        }),
+        // Someone else took it over first
+        Err(_) => Claim::Busy,
+    }
+}
+
+/// Wait until the build holding `cache_key` has released its claim (after
+/// caching its result) or gone stale
+pub async fn wait(store: &Store, cache_key: &str, build_timeout: Duration) {
+    let key = claim_key(cache_key);
+    loop {
+        match store.get(&key).await {
+            Ok(Some(value)) => match serde_json::from_slice::<Marker>(&value) {
+                Ok(held) if !held.is_stale(build_timeout) => {}
+                _ => return,
+            },
+            _ => return,
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+impl Building {
+    /// Drop the claim, unless another worker has since taken it over
+    pub async fn release(self, store: &Store) {
+        let Some(revision) = self.revision else {
+            return;
+        };
+        if let Err(e) = store.delete_expect_revision(&self.key, Some(revision)).await {
+            warn!(key = %self.key, error = %e, "Failed to release build claim");
+        }
+    }
+}
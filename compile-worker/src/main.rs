@@ -1,20 +1,51 @@
-use async_nats::jetstream::{self, consumer::PullConsumer, kv::Store};
+mod build_cache;
+mod ceilings;
+mod inflight;
+mod slots;
+
+use async_nats::jetstream::{self, consumer::PullConsumer, kv::Store, AckKind};
+use build_cache::{BuildCache, BuildCacheUsage, Isolation};
+use ceilings::Ceilings;
+use ctf_common::binary_encoding;
+use ctf_common::compile_protocol::{
+    self, CompileFailure, CompileManifest, CompileReport, Diagnostic, ResourceUsage, MANIFEST_FILE, RESULT_FILE,
+};
+use ctf_common::runtime::RuntimeManifest;
+use ctf_common::{logging, redact};
+use ctf_common::topology::{Topology, TopologyConfig, COMPILE_WORKER_CONSUMER};
 use chrono::{DateTime, Utc};
 use futures::StreamExt;
+use inflight::Claim;
+use std::future::Future;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use slots::{Slot, Slots};
 use std::env;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tempfile::TempDir;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
-use tracing::{error, info, warn};
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::{error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
-const COMPILES_STREAM: &str = "COMPILES";
-const COMPILES_KV: &str = "compiles";
-const COMPILE_CACHE_KV: &str = "compile_cache";
+/// Deliveries before JetStream gives up on a compile job
+const MAX_DELIVER: i64 = 3;
+/// How long a job deferred by its language ceiling stays in the stream
+const REQUEUE_DELAY: Duration = Duration::from_secs(10);
+/// Parked jobs tell JetStream they're still in hand this often
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+/// Assembly past this is cut off so it fits in one KV value
+const MAX_ASM_BYTES: usize = 768 * 1024;
+/// Where a job's custom linker script goes in the work directory
+const LINKER_SCRIPT_FILE: &str = "linker.ld";
+
+/// Events the poll loop would otherwise repeat every second (see logging.rs)
+static FETCH_ERRORS: logging::Sampler = logging::Sampler::new();
+static RECEIVE_ERRORS: logging::Sampler = logging::Sampler::new();
+static REQUEUES: logging::Sampler = logging::Sampler::new();
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -32,6 +63,11 @@ pub enum Language {
     Swift,
     Haskell,
     Csharp,
+    Fortran,
+    D,
+    Crystal,
+    V,
+    Ada,
     // Tier 2: JVM -> Native (GraalVM)
     Java,
     Kotlin,
@@ -70,6 +106,11 @@ impl Language {
             Language::Swift => "swift",
             Language::Haskell => "haskell",
             Language::Csharp => "csharp",
+            Language::Fortran => "fortran",
+            Language::D => "d",
+            Language::Crystal => "crystal",
+            Language::V => "v",
+            Language::Ada => "ada",
             Language::Java => "java",
             Language::Kotlin => "kotlin",
             Language::Scala => "scala",
@@ -105,6 +146,11 @@ impl Language {
             Language::Swift => "swift",
             Language::Haskell => "hs",
             Language::Csharp => "cs",
+            Language::Fortran => "f90",
+            Language::D => "d",
+            Language::Crystal => "cr",
+            Language::V => "v",
+            Language::Ada => "adb",
             Language::Java => "java",
             Language::Kotlin => "kt",
             Language::Scala => "scala",
@@ -155,9 +201,38 @@ pub struct CompileJob {
     pub optimization: Optimization,
     #[serde(default)]
     pub flags: HashMap<String, String>,
+    /// Allowlisted compiler image to build with instead of `COMPILER_IMAGE`
+    #[serde(default)]
+    pub compiler_image: Option<String>,
+    /// Also produce assembly, kept under `{id}_asm` in the compiles bucket
+    #[serde(default)]
+    pub emit_asm: bool,
+    /// Linker flags and script (asm, C and C++), validated by the API
+    #[serde(default, skip_serializing_if = "BuildOptions::is_empty")]
+    pub build_options: BuildOptions,
+    /// Non-default toolchain, validated by the API; `None` builds with the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// Skip the failed-build cache
+    #[serde(default)]
+    pub retry_failed: bool,
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linker_flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linker_script: Option<String>,
+}
+
+impl BuildOptions {
+    pub fn is_empty(&self) -> bool {
+        self.linker_flags.is_empty() && self.linker_script.is_none()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CompileStatus {
@@ -183,16 +258,40 @@ pub struct CompileResult {
     pub binary_size: usize,
     pub compile_time_ms: u64,
     pub cached: bool,
+    /// How the build used its persistent cache (`BUILD_CACHE_DIR`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_cache: Option<BuildCacheUsage>,
+    /// Warnings the compiler image reported
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    /// CPU time and peak memory of the build, with the ceilings it had
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
 }
 
 struct Config {
     nats_url: String,
     api_url: String,
     compiler_image: String,
-    memory_limit_mb: u32,
+    // Memory and CPU per build, see ceilings.rs
+    ceilings: Ceilings,
     timeout_sec: u64,
+    // How long a failed build answers identical jobs (0 = never cached)
+    failure_cache_seconds: u64,
     job_ttl_seconds: u64,
     binary_ttl_seconds: u64,
+    // Builds run at once
+    concurrency: usize,
+    // Lower ceilings per language or group (`graalvm`), see slots.rs
+    concurrency_by_language: HashMap<String, usize>,
+    // How often slot utilization is logged (0 = never)
+    slot_report_seconds: u64,
+    // Persistent toolchain caches, see build_cache.rs (None = every build is cold)
+    build_cache_dir: Option<String>,
+    build_cache_max_mb: u64,
+    build_cache_isolation: Isolation,
+    // Send binaries zstd-compressed (BINARY_UPLOAD_COMPRESSION=none to turn off)
+    upload_compression: bool,
 }
 
 impl Config {
@@ -201,11 +300,20 @@ impl Config {
             nats_url: env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string()),
             api_url: env::var("API_URL").unwrap_or_else(|_| "http://ctf-api:3000".to_string()),
             compiler_image: env::var("COMPILER_IMAGE").unwrap_or_else(|_| "compiler".to_string()),
-            memory_limit_mb: env::var("COMPILE_MEMORY_LIMIT_MB")
+            ceilings: Ceilings::new(
+                env::var("COMPILE_MEMORY_LIMIT_MB")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(4096),
+                env::var("COMPILE_CPUS").ok().and_then(|s| s.parse().ok()),
+                by_language("COMPILE_MEMORY_LIMIT_MB_BY_LANGUAGE", ""),
+                by_language("COMPILE_CPUS_BY_LANGUAGE", ""),
+            ),
+            timeout_sec: env::var("COMPILE_TIMEOUT_SEC")
                 .ok()
                 .and_then(|s| s.parse().ok())
-                .unwrap_or(4096),
-            timeout_sec: env::var("COMPILE_TIMEOUT_SEC")
+                .unwrap_or(120),
+            failure_cache_seconds: env::var("COMPILE_FAILURE_CACHE_SECONDS")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(120),
@@ -217,11 +325,50 @@ impl Config {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(86400),
+            concurrency: env::var("COMPILE_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2),
+            concurrency_by_language: by_language("COMPILE_CONCURRENCY_BY_LANGUAGE", "graalvm=1"),
+            slot_report_seconds: env::var("COMPILE_SLOT_REPORT_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            build_cache_dir: env::var("BUILD_CACHE_DIR").ok().filter(|s| !s.is_empty()),
+            build_cache_max_mb: env::var("BUILD_CACHE_MAX_MB")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2048),
+            build_cache_isolation: env::var("BUILD_CACHE_ISOLATION")
+                .ok()
+                .and_then(|s| Isolation::parse(&s))
+                .unwrap_or(Isolation::User),
+            upload_compression: env::var("BINARY_UPLOAD_COMPRESSION").map(|v| v != "none").unwrap_or(true),
         }
     }
 }
 
-fn compute_cache_key(source: &str, language: Language, optimization: Optimization, flags: &HashMap<String, String>) -> String {
+/// `language=value,...` from `var`, skipping entries that don't parse
+fn by_language<T: std::str::FromStr>(var: &str, default: &str) -> HashMap<String, T> {
+    env::var(var)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .filter_map(|entry| {
+            let (language, value) = entry.split_once('=')?;
+            Some((language.trim().to_lowercase(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn compute_cache_key(
+    source: &str,
+    language: Language,
+    optimization: Optimization,
+    flags: &HashMap<String, String>,
+    compiler_image: Option<&str>,
+    build_options: &BuildOptions,
+    toolchain: Option<&str>,
+) -> String {
     let mut hasher = Sha256::new();
     hasher.update(source.as_bytes());
     hasher.update(language.as_str().as_bytes());
@@ -235,6 +382,19 @@ fn compute_cache_key(source: &str, language: Language, optimization: Optimizatio
         hasher.update(v.as_bytes());
         hasher.update(b";");
     }
+    // Only pinned builds hash the image, so existing cache keys stay valid
+    if let Some(image) = compiler_image {
+        hasher.update(b"image=");
+        hasher.update(image.as_bytes());
+    }
+    if !build_options.is_empty() {
+        hasher.update(b"build_options=");
+        hasher.update(serde_json::to_vec(build_options).unwrap_or_default());
+    }
+    if let Some(toolchain) = toolchain {
+        hasher.update(b"toolchain=");
+        hasher.update(toolchain.as_bytes());
+    }
     hex::encode(hasher.finalize())
 }
 
@@ -247,9 +407,46 @@ struct CompileOutput {
     binary: Vec<u8>,
     compiler_version: Option<String>,
     compile_flags: Option<serde_json::Value>,
+    /// Digest of the image that ran the build
+    compiler_image: String,
+    build_cache: Option<BuildCacheUsage>,
+    /// With `emit_asm`, truncated to `MAX_ASM_BYTES`
+    asm: Option<String>,
+    /// Section and symbol sizes from `compile.sh`, for binaries binutils can read
+    size_report: Option<serde_json::Value>,
+    /// Toolchain the container built with, for languages that have a choice
+    toolchain: Option<String>,
+    /// From the image's `result.json`; empty for images that predate it
+    diagnostics: Vec<Diagnostic>,
+    /// Likewise; `None` too where the image can't read its cgroup
+    resources: Option<ResourceUsage>,
+    /// Interpreter the binary needs in the sandbox, for script bundles
+    runtime: Option<RuntimeManifest>,
+}
+
+/// `repo@sha256:...` for a pulled image, or the image ID for a local build;
+/// `None` if Docker can't inspect it
+async fn resolve_image_digest(image: &str) -> Option<String> {
+    let output = Command::new("docker")
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{if .RepoDigests}}{{index .RepoDigests 0}}{{else}}{{.Id}}{{end}}",
+            image,
+        ])
+        .output()
+        .await
+        .ok()?;
+    let digest = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !digest.is_empty()).then_some(digest)
 }
 
-async fn compile_source(job: &CompileJob, config: &Config) -> Result<CompileOutput, String> {
+async fn compile_source(
+    job: &CompileJob,
+    config: &Config,
+    build_cache: Option<&BuildCache>,
+) -> Result<CompileOutput, CompileFailure> {
     // Create temp directory for compilation
     let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
     let work_dir = temp_dir.path();
@@ -270,12 +467,11 @@ async fn compile_source(job: &CompileJob, config: &Config) -> Result<CompileOutp
     drop(file);
 
     // Build docker command
+    let ceiling = config.ceilings.for_language(job.language.as_str());
     let mut cmd = Command::new("docker");
+    cmd.args(["run", "--rm"]);
+    cmd.args(ceiling.docker_args());
     cmd.args([
-        "run",
-        "--rm",
-        &format!("--memory={}m", config.memory_limit_mb),
-        &format!("--memory-swap={}m", config.memory_limit_mb),
         // Network access needed for package managers (NuGet, Maven, Hackage, etc.)
         // Execution still runs sandboxed with --network=none
         "--tmpfs=/tmp:rw,exec,nosuid,size=512m",
@@ -291,6 +487,39 @@ async fn compile_source(job: &CompileJob, config: &Config) -> Result<CompileOutp
         "OUTPUT_FILE=output",
     ]);
 
+    let cache_mount = match build_cache {
+        Some(cache) => cache.prepare(job.language.as_str(), job.user_id.as_ref()).await,
+        None => None,
+    };
+    if let (Some(cache), Some(mount)) = (build_cache, &cache_mount) {
+        cmd.args([
+            "-v",
+            &format!("{}:/cache:rw", mount.dir.display()),
+            "-e",
+            &format!("BUILD_CACHE={}", mount.kind),
+            "-e",
+            &format!("BUILD_CACHE_MAX_MB={}", cache.max_mb()),
+        ]);
+    }
+
+    if job.emit_asm {
+        cmd.args(["-e", "EMIT_ASM=1"]);
+    }
+
+    if let Some(toolchain) = &job.toolchain {
+        cmd.args(["-e", &format!("TOOLCHAIN={}", toolchain)]);
+    }
+
+    if !job.build_options.linker_flags.is_empty() {
+        cmd.args(["-e", &format!("LINKER_FLAGS={}", job.build_options.linker_flags.join(" "))]);
+    }
+    if let Some(script) = &job.build_options.linker_script {
+        tokio::fs::write(work_dir.join(LINKER_SCRIPT_FILE), script)
+            .await
+            .map_err(|e| format!("Failed to write linker script: {}", e))?;
+        cmd.args(["-e", &format!("LINKER_SCRIPT=/work/{}", LINKER_SCRIPT_FILE)]);
+    }
+
     // Pass flags as environment variables (FLAG_<name>=<value>)
     for (key, value) in &job.flags {
         // Sanitize key: only alphanumeric and underscore
@@ -308,7 +537,23 @@ async fn compile_source(job: &CompileJob, config: &Config) -> Result<CompileOutp
         cmd.args(["-e", &format!("FLAGS_JSON={}", flags_json)]);
     }
 
-    cmd.arg(&config.compiler_image);
+    // The same inputs as a versioned manifest; the env vars above are for
+    // images that predate it
+    let mut manifest = CompileManifest::new(job.language.as_str(), job.optimization.as_str(), &source_filename);
+    manifest.flags = job.flags.clone().into_iter().collect();
+    manifest.emit_asm = job.emit_asm;
+    manifest.toolchain = job.toolchain.clone();
+    manifest.linker_flags = job.build_options.linker_flags.clone();
+    manifest.linker_script = job.build_options.linker_script.as_ref().map(|_| LINKER_SCRIPT_FILE.to_string());
+    manifest.build_cache = cache_mount.as_ref().map(|mount| mount.kind.to_string());
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    tokio::fs::write(work_dir.join(MANIFEST_FILE), manifest_json)
+        .await
+        .map_err(|e| format!("Failed to write manifest: {}", e))?;
+    let outputs = &manifest.outputs;
+
+    let image = job.compiler_image.as_deref().unwrap_or(&config.compiler_image);
+    cmd.arg(image);
 
     cmd.stdout(std::process::Stdio::piped());
     cmd.stderr(std::process::Stdio::piped());
@@ -324,33 +569,45 @@ async fn compile_source(job: &CompileJob, config: &Config) -> Result<CompileOutp
 
     let output = match result {
         Ok(Ok(output)) => output,
-        Ok(Err(e)) => return Err(format!("Compilation failed: {}", e)),
-        Err(_) => return Err(format!("Compilation timed out after {} seconds", config.timeout_sec)),
+        Ok(Err(e)) => return Err(format!("Compilation failed: {}", e).into()),
+        Err(_) => return Err(format!("Compilation timed out after {} seconds", config.timeout_sec).into()),
+    };
+
+    // Failed builds can grow the cache too, so the quota applies either way
+    let build_cache = match (build_cache, cache_mount) {
+        (Some(cache), Some(mount)) => Some(cache.finish(mount, work_dir).await),
+        _ => None,
+    };
+
+    // Images that read the manifest report back in kind; an unsupported
+    // protocol version fails the build rather than being misread
+    let report = match tokio::fs::read(work_dir.join(RESULT_FILE)).await {
+        Ok(raw) => Some(CompileReport::parse(&raw)?),
+        Err(_) => None,
     };
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "Compilation failed (exit {})\nstdout: {}\nstderr: {}",
+        return Err(CompileFailure::Rejected(compile_protocol::failure_message(
             output.status.code().unwrap_or(-1),
-            stdout,
-            stderr
-        ));
+            report.as_ref(),
+            ceiling.memory_limit_mb,
+            &String::from_utf8_lossy(&output.stdout),
+            &String::from_utf8_lossy(&output.stderr),
+        )));
     }
 
     // Read compiled binary
-    let output_path = work_dir.join("output");
+    let output_path = work_dir.join(&outputs.binary);
     let binary = tokio::fs::read(&output_path)
         .await
         .map_err(|e| format!("Failed to read compiled binary: {}", e))?;
 
     if binary.is_empty() {
-        return Err("Compilation produced empty binary".to_string());
+        return Err("Compilation produced empty binary".to_string().into());
     }
 
     // Read compiler version
-    let version_path = work_dir.join("compiler_version.txt");
+    let version_path = work_dir.join(&outputs.compiler_version);
     let compiler_version = tokio::fs::read_to_string(&version_path)
         .await
         .ok()
@@ -358,19 +615,68 @@ async fn compile_source(job: &CompileJob, config: &Config) -> Result<CompileOutp
         .filter(|s| !s.is_empty());
 
     // Read compile flags
-    let flags_path = work_dir.join("compile_flags.json");
+    let flags_path = work_dir.join(&outputs.compile_flags);
     let compile_flags = tokio::fs::read_to_string(&flags_path)
         .await
         .ok()
         .and_then(|s| serde_json::from_str(&s).ok());
 
+    let toolchain = tokio::fs::read_to_string(work_dir.join(&outputs.toolchain))
+        .await
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let size_report = tokio::fs::read_to_string(work_dir.join(&outputs.size_report))
+        .await
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok());
+
+    // An unreadable manifest is an image bug; running the bundle without its
+    // interpreter would only fail later
+    let runtime = match tokio::fs::read_to_string(work_dir.join(&outputs.runtime)).await {
+        Ok(raw) => Some(RuntimeManifest::parse(&raw).map_err(|e| format!("Bad {} from the compiler image: {}", outputs.runtime, e))?),
+        Err(_) => None,
+    };
+
+    let asm = if job.emit_asm {
+        tokio::fs::read(work_dir.join(&outputs.asm))
+            .await
+            .ok()
+            .filter(|asm| !asm.is_empty())
+            .map(|asm| truncate_asm(&asm))
+    } else {
+        None
+    };
+
     Ok(CompileOutput {
         binary,
         compiler_version,
         compile_flags,
+        compiler_image: resolve_image_digest(image).await.unwrap_or_else(|| image.to_string()),
+        build_cache,
+        asm,
+        size_report,
+        toolchain,
+        runtime,
+        resources: report.as_ref().and_then(|r| r.resources.clone()).map(|usage| ResourceUsage {
+            memory_limit_mb: Some(ceiling.memory_limit_mb),
+            cpus: ceiling.cpus,
+            ..usage
+        }),
+        diagnostics: report.map(|r| r.diagnostics).unwrap_or_default(),
     })
 }
 
+fn truncate_asm(asm: &[u8]) -> String {
+    if asm.len() <= MAX_ASM_BYTES {
+        return String::from_utf8_lossy(asm).into_owned();
+    }
+    let mut text = String::from_utf8_lossy(&asm[..MAX_ASM_BYTES]).into_owned();
+    text.push_str(&format!("\n; ... truncated ({} of {} bytes shown)\n", MAX_ASM_BYTES, asm.len()));
+    text
+}
+
 async fn update_compile_status(
     compiles_kv: &Store,
     job_id: &Uuid,
@@ -416,31 +722,43 @@ async fn update_compile_status(
 
 async fn store_compile_result(
     http_client: &reqwest::Client,
-    api_url: &str,
+    config: &Config,
     compile_cache_kv: &Store,
     cache_key: &str,
-    binary: &[u8],
+    output: &CompileOutput,
     compile_time_ms: u64,
     language: Language,
     optimization: Optimization,
-    compiler_version: Option<&str>,
-    compile_flags: Option<&serde_json::Value>,
 ) -> Result<CompileResult, String> {
+    let binary = &output.binary;
     let binary_id = compute_binary_id(binary);
     let binary_size = binary.len();
 
     // Build URL with metadata query parameters
     let mut url = format!(
-        "{}/binaries/{}?language={}&optimization={}",
-        api_url, binary_id, language.as_str(), optimization.as_str()
+        "{}/binaries/{}?language={}&optimization={}&compiler_image={}&build_key={}",
+        config.api_url,
+        binary_id,
+        language.as_str(),
+        optimization.as_str(),
+        urlencoding::encode(&output.compiler_image),
+        cache_key
     );
-    if let Some(version) = compiler_version {
+    if let Some(version) = &output.compiler_version {
         url.push_str(&format!(
             "&compiler_version={}",
             urlencoding::encode(version)
         ));
     }
-    if let Some(flags) = compile_flags {
+    if let Some(toolchain) = &output.toolchain {
+        url.push_str(&format!("&toolchain={}", urlencoding::encode(toolchain)));
+    }
+    if let Some(runtime) = &output.runtime {
+        if let Ok(runtime_json) = serde_json::to_string(runtime) {
+            url.push_str(&format!("&runtime={}", urlencoding::encode(&runtime_json)));
+        }
+    }
+    if let Some(flags) = &output.compile_flags {
         if let Ok(flags_json) = serde_json::to_string(flags) {
             url.push_str(&format!(
                 "&compile_flags={}",
@@ -449,14 +767,25 @@ async fn store_compile_result(
         }
     }
 
+    // Compressed once, then sent as-is on each attempt
+    let compressed = config
+        .upload_compression
+        .then(|| binary_encoding::compress(binary, binary_encoding::DEFAULT_LEVEL))
+        .flatten();
+
     // Store binary via HTTP API (PostgreSQL backend, more reliable than NATS KV for large files)
     let mut attempts = 0;
     const MAX_ATTEMPTS: u32 = 3;
     loop {
         attempts += 1;
-        let result = http_client
-            .put(&url)
-            .body(binary.to_vec())
+        let request = match &compressed {
+            Some(compressed) => http_client
+                .put(&url)
+                .header(reqwest::header::CONTENT_ENCODING, binary_encoding::ZSTD)
+                .body(compressed.clone()),
+            None => http_client.put(&url).body(binary.to_vec()),
+        };
+        let result = request
             .timeout(Duration::from_secs(120))
             .send()
             .await;
@@ -476,12 +805,31 @@ async fn store_compile_result(
         }
     }
 
+    // The binary is usable without its size report, so a failure here only warns
+    if let Some(report) = &output.size_report {
+        let result = http_client
+            .put(format!("{}/binaries/{}/size-report", config.api_url, binary_id))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(report.to_string())
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await;
+        match result {
+            Ok(resp) if resp.status().is_success() => {}
+            Ok(resp) => warn!(binary_id = %binary_id, "Failed to store size report: HTTP {}", resp.status()),
+            Err(e) => warn!(binary_id = %binary_id, "Failed to store size report: {}", e),
+        }
+    }
+
     // Store cache mapping in NATS (small data, no timeout issues)
     let result = CompileResult {
         binary_id: binary_id.clone(),
         binary_size,
         compile_time_ms,
         cached: false,
+        build_cache: output.build_cache.clone(),
+        diagnostics: output.diagnostics.clone(),
+        resources: output.resources.clone(),
     };
 
     compile_cache_kv
@@ -497,21 +845,364 @@ async fn store_compile_result(
     Ok(result)
 }
 
+/// Everything a compile task needs, shared by all slots
+struct Worker {
+    config: Config,
+    compiles_kv: Store,
+    compile_cache_kv: Store,
+    http_client: reqwest::Client,
+    redactor: redact::Redactor,
+    slots: Arc<Slots>,
+    build_cache: Option<BuildCache>,
+}
+
+/// Run one job on the slot it was fetched with, then ack it
+async fn handle_job(worker: Arc<Worker>, msg: jetstream::Message, job: CompileJob, slot: OwnedSemaphorePermit) {
+    let slot = match worker.slots.try_start(slot, job.language.as_str()) {
+        Ok(slot) => slot,
+        Err(slot) => {
+            // Let other languages use the slot while this one waits its turn
+            drop(slot);
+            match wait_for_slot(&worker, &msg, &job).await {
+                Some(slot) => slot,
+                None => return,
+            }
+        }
+    };
+
+    process_job(&worker, &msg, &job, slot).await;
+
+    // Acknowledge the message
+    if let Err(e) = msg.ack().await {
+        error!("Failed to ack message: {}", e);
+    }
+}
+
+/// A slot for a job whose language is at its ceiling. The job waits in a
+/// parking place if there is one (or this is its last delivery); otherwise
+/// it goes back to the stream and `None` is returned.
+async fn wait_for_slot(worker: &Worker, msg: &jetstream::Message, job: &CompileJob) -> Option<Slot> {
+    let parked = worker.slots.try_park();
+    let last_delivery = msg.info().map(|info| info.delivered >= MAX_DELIVER).unwrap_or(true);
+    if parked.is_none() && !last_delivery {
+        if let Some(suppressed) = REQUEUES.sample() {
+            info!(job_id = %job.id, language = ?job.language, suppressed, "Language at its compile ceiling, requeueing");
+        }
+        worker.slots.record_deferred();
+        let _ = msg.ack_with(AckKind::Nak(Some(REQUEUE_DELAY))).await;
+        return None;
+    }
+
+    let slot = with_progress(msg, worker.slots.start(job.language.as_str())).await;
+    drop(parked);
+    Some(slot)
+}
+
+/// Run `fut`, keeping JetStream from redelivering the job while it waits
+async fn with_progress<F: Future>(msg: &jetstream::Message, fut: F) -> F::Output {
+    tokio::pin!(fut);
+    let mut progress = tokio::time::interval(PROGRESS_INTERVAL);
+    progress.tick().await;
+    loop {
+        tokio::select! {
+            output = &mut fut => return output,
+            _ = progress.tick() => {
+                let _ = msg.ack_with(AckKind::Progress).await;
+            }
+        }
+    }
+}
+
+/// Keep a build's assembly for the job and, like the binary, for later cache hits
+async fn store_asm(worker: &Worker, job_id: &Uuid, asm_cache_key: &str, asm: &str) {
+    let asm = asm.as_bytes().to_vec();
+    if let Err(e) = worker.compiles_kv.put(format!("{}_asm", job_id), asm.clone().into()).await {
+        error!(job_id = %job_id, "Failed to store assembly: {}", e);
+    }
+    if let Err(e) = worker.compile_cache_kv.put(asm_cache_key, asm.into()).await {
+        error!(job_id = %job_id, "Failed to cache assembly: {}", e);
+    }
+}
+
+/// A build the compiler rejected, kept under `<cache key>_failed`
+#[derive(Serialize, Deserialize)]
+struct CachedFailure {
+    error: String,
+    failed_at: DateTime<Utc>,
+}
+
+/// Fail `job` straight away if an identical build failed within
+/// `COMPILE_FAILURE_CACHE_SECONDS`, unless it asked to retry
+async fn serve_cached_failure(worker: &Worker, job: &CompileJob, cache_key: &str) -> bool {
+    if worker.config.failure_cache_seconds == 0 || job.retry_failed {
+        return false;
+    }
+    let Ok(Some(entry)) = worker.compile_cache_kv.get(format!("{}_failed", cache_key)).await else {
+        return false;
+    };
+    let Ok(failure) = serde_json::from_slice::<CachedFailure>(&entry) else {
+        return false;
+    };
+    let age = (Utc::now() - failure.failed_at).num_seconds().max(0);
+    if age as u64 >= worker.config.failure_cache_seconds {
+        return false;
+    }
+
+    info!(job_id = %job.id, age_seconds = age, "Cached compile failure");
+    let error = format!(
+        "{}
+(an identical build failed {}s ago; resubmit with retry_failed=true to build again)",
+        failure.error, age
+    );
+    if let Err(e) = update_compile_status(&worker.compiles_kv, &job.id, CompileStatus::Failed, Some(error)).await {
+        error!("Failed to update compile status: {}", e);
+    }
+    true
+}
+
+async fn cache_failure(worker: &Worker, job_id: &Uuid, cache_key: &str, error: &str) {
+    if worker.config.failure_cache_seconds == 0 {
+        return;
+    }
+    let failure = CachedFailure {
+        error: error.to_string(),
+        failed_at: Utc::now(),
+    };
+    let value = serde_json::to_vec(&failure).unwrap_or_default();
+    if let Err(e) = worker.compile_cache_kv.put(format!("{}_failed", cache_key), value.into()).await {
+        warn!(job_id = %job_id, "Failed to cache compile failure: {}", e);
+    }
+}
+
+/// Complete `job` from the compile cache if an identical build is there; a
+/// job that wants assembly only hits if that was kept too
+async fn serve_cached(worker: &Worker, job: &CompileJob, cache_key: &str, asm_cache_key: &str) -> bool {
+    let cached_asm = if job.emit_asm {
+        worker.compile_cache_kv.get(asm_cache_key).await.ok().flatten()
+    } else {
+        None
+    };
+    let cached_entry = if !job.emit_asm || cached_asm.is_some() {
+        worker.compile_cache_kv.get(cache_key).await.ok().flatten()
+    } else {
+        None
+    };
+    if let Some(cached_entry) = cached_entry {
+        if let Ok(mut cached_result) = serde_json::from_slice::<CompileResult>(&cached_entry)
+        {
+            info!(
+                job_id = %job.id,
+                binary_id = %cached_result.binary_id,
+                "Cache hit"
+            );
+
+            cached_result.cached = true;
+            cached_result.build_cache = None;
+
+            // Store result for this job
+            let result_key = format!("{}_result", job.id);
+            if let Err(e) = worker.compiles_kv
+                .put(
+                    &result_key,
+                    serde_json::to_vec(&cached_result).unwrap().into(),
+                )
+                .await
+            {
+                error!("Failed to store cached result: {}", e);
+            }
+            if let Some(asm) = cached_asm {
+                if let Err(e) = worker.compiles_kv.put(format!("{}_asm", job.id), asm).await {
+                    error!("Failed to store cached assembly: {}", e);
+                }
+            }
+
+            // Update status to completed
+            if let Err(e) =
+                update_compile_status(&worker.compiles_kv, &job.id, CompileStatus::Completed, None)
+                    .await
+            {
+                error!("Failed to update compile status: {}", e);
+            }
+
+            return true;
+        }
+    }
+    false
+}
+
+async fn process_job(worker: &Worker, msg: &jetstream::Message, job: &CompileJob, mut slot: Slot) {
+    info!(
+        job_id = %job.id,
+        language = ?job.language,
+        optimization = ?job.optimization,
+        source_size = job.source_code.len(),
+        "Processing compile job"
+    );
+
+    let cache_key = compute_cache_key(
+        &job.source_code,
+        job.language,
+        job.optimization,
+        &job.flags,
+        job.compiler_image.as_deref(),
+        &job.build_options,
+        job.toolchain.as_deref(),
+    );
+    let asm_cache_key = format!("{}_asm", cache_key);
+
+    // Check the cache (and recent failures) first, then claim the build; an
+    // identical job already building means waiting for it (without the slot)
+    // and checking again
+    let build_timeout = Duration::from_secs(worker.config.timeout_sec);
+    let building = loop {
+        if serve_cached(worker, job, &cache_key, &asm_cache_key).await
+            || serve_cached_failure(worker, job, &cache_key).await
+        {
+            return;
+        }
+        match inflight::claim(&worker.compile_cache_kv, &cache_key, &job.id, build_timeout).await {
+            Claim::Acquired(building) => break building,
+            Claim::Busy => {
+                info!(job_id = %job.id, "Identical build in progress, waiting for it");
+                drop(slot);
+                with_progress(msg, inflight::wait(&worker.compile_cache_kv, &cache_key, build_timeout)).await;
+                slot = with_progress(msg, worker.slots.start(job.language.as_str())).await;
+            }
+        }
+    };
+    let start = Instant::now();
+
+    // Update status to compiling
+    if let Err(e) =
+        update_compile_status(&worker.compiles_kv, &job.id, CompileStatus::Compiling, None).await
+    {
+        error!("Failed to update compile status: {}", e);
+    }
+
+    // Compile the source
+    match compile_source(job, &worker.config, worker.build_cache.as_ref()).await {
+        Ok(output) => {
+            let compile_time_ms = start.elapsed().as_millis() as u64;
+
+            info!(
+                job_id = %job.id,
+                binary_size = output.binary.len(),
+                compiler_version = ?output.compiler_version,
+                compiler_image = %output.compiler_image,
+                build_cache = ?output.build_cache,
+                resources = ?output.resources,
+                time_ms = compile_time_ms,
+                "Compilation succeeded"
+            );
+
+            // Store binary and cache entry
+            match store_compile_result(
+                &worker.http_client,
+                &worker.config,
+                &worker.compile_cache_kv,
+                &cache_key,
+                &output,
+                compile_time_ms,
+                job.language,
+                job.optimization,
+            )
+            .await
+            {
+                Ok(result) => {
+                    // Store result for this job
+                    let result_key = format!("{}_result", job.id);
+                    if let Err(e) = worker.compiles_kv
+                        .put(&result_key, serde_json::to_vec(&result).unwrap().into())
+                        .await
+                    {
+                        error!("Failed to store result: {}", e);
+                    }
+                    if let Some(asm) = &output.asm {
+                        store_asm(worker, &job.id, &asm_cache_key, asm).await;
+                    }
+
+                    // Update status to completed
+                    if let Err(e) = update_compile_status(
+                        &worker.compiles_kv,
+                        &job.id,
+                        CompileStatus::Completed,
+                        None,
+                    )
+                    .await
+                    {
+                        error!("Failed to update compile status: {}", e);
+                    }
+                }
+                Err(e) => {
+                    error!(job_id = %job.id, error = %e, "Failed to store compile result");
+                    if let Err(e2) = update_compile_status(
+                        &worker.compiles_kv,
+                        &job.id,
+                        CompileStatus::Failed,
+                        Some(e),
+                    )
+                    .await
+                    {
+                        error!("Failed to update compile status: {}", e2);
+                    }
+                }
+            }
+        }
+        Err(failure) => {
+            // Compiler output is stored for the user and can echo anything it read
+            let e = worker.redactor.scrub(failure.message());
+            warn!(job_id = %job.id, error = %e, "Compilation failed");
+
+            // Identical jobs fail the same way for a while; infra trouble doesn't stick
+            if let CompileFailure::Rejected(_) = failure {
+                cache_failure(worker, &job.id, &cache_key, &e).await;
+            }
+
+            // Update status to failed
+            if let Err(e2) = update_compile_status(
+                &worker.compiles_kv,
+                &job.id,
+                CompileStatus::Failed,
+                Some(e),
+            )
+            .await
+            {
+                error!("Failed to update compile status: {}", e2);
+            }
+        }
+    }
+
+    // Identical jobs waiting on this build can now take the result from the
+    // cache, or build for themselves if it failed
+    building.release(&worker.compile_cache_kv).await;
+    drop(slot);
+}
+
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("compile_worker=info".parse().unwrap()),
-        )
-        .init();
+    // Initialize tracing (LOG_FORMAT=json for structured output); secrets in
+    // the worker's own env never reach the logs
+    let redactor = redact::Redactor::from_process_env();
+    logging::init("compile_worker=info", redact::RedactingWriter::new(redactor.clone()));
 
     let config = Config::from_env();
+    let slots = Slots::new(config.concurrency, &config.concurrency_by_language);
+    let build_cache = config.build_cache_dir.as_ref().map(|dir| {
+        info!(
+            dir = %dir,
+            max_mb = config.build_cache_max_mb,
+            isolation = ?config.build_cache_isolation,
+            "Persistent build caches enabled"
+        );
+        BuildCache::new(dir.into(), config.build_cache_max_mb, config.build_cache_isolation)
+    });
 
     info!(
-        "Starting Compile Worker (NATS: {}, compiler: {})",
-        config.nats_url, config.compiler_image
+        "Starting Compile Worker (NATS: {}, compiler: {}, slots: {}, language ceilings: {:?})",
+        config.nats_url,
+        config.compiler_image,
+        slots.capacity(),
+        config.concurrency_by_language
     );
 
     // Connect to NATS with longer request timeout for large binary uploads
@@ -533,29 +1224,16 @@ async fn main() {
 
     let jetstream = jetstream::new(client);
 
-    // Get or create stream
-    let stream = jetstream
-        .get_or_create_stream(jetstream::stream::Config {
-            name: COMPILES_STREAM.to_string(),
-            subjects: vec!["compiles.submit".to_string()],
-            retention: jetstream::stream::RetentionPolicy::WorkQueue,
-            max_age: Duration::from_secs(config.job_ttl_seconds),
-            storage: jetstream::stream::StorageType::File,
-            ..Default::default()
-        })
-        .await
-        .expect("Failed to create/get COMPILES stream");
-
-    // Get or create KV buckets
-    let compiles_kv = jetstream
-        .create_key_value(jetstream::kv::Config {
-            bucket: COMPILES_KV.to_string(),
-            max_age: Duration::from_secs(config.job_ttl_seconds),
-            storage: jetstream::stream::StorageType::File,
-            ..Default::default()
-        })
-        .await
-        .expect("Failed to create compiles KV");
+    // Create or verify the shared stream/KV topology
+    let topology = Topology::ensure(
+        &jetstream,
+        &TopologyConfig::new(config.job_ttl_seconds, config.binary_ttl_seconds),
+    )
+    .await
+    .expect("Failed to ensure NATS topology");
+    let stream = topology.compiles_stream;
+    let compiles_kv = topology.compiles_kv;
+    let compile_cache_kv = topology.compile_cache_kv;
 
     // HTTP client for binary storage via API (PostgreSQL backend)
     let http_client = reqwest::Client::builder()
@@ -563,24 +1241,14 @@ async fn main() {
         .build()
         .expect("Failed to create HTTP client");
 
-    let compile_cache_kv = jetstream
-        .create_key_value(jetstream::kv::Config {
-            bucket: COMPILE_CACHE_KV.to_string(),
-            max_age: Duration::from_secs(config.binary_ttl_seconds),
-            storage: jetstream::stream::StorageType::File,
-            ..Default::default()
-        })
-        .await
-        .expect("Failed to create compile_cache KV");
-
     // Create durable consumer
     let consumer: PullConsumer = stream
         .get_or_create_consumer(
-            "compile-worker",
+            COMPILE_WORKER_CONSUMER,
             jetstream::consumer::pull::Config {
-                durable_name: Some("compile-worker".to_string()),
+                durable_name: Some(COMPILE_WORKER_CONSUMER.to_string()),
                 ack_policy: jetstream::consumer::AckPolicy::Explicit,
-                max_deliver: 3,
+                max_deliver: MAX_DELIVER,
                 ack_wait: Duration::from_secs(config.timeout_sec + 60),
                 ..Default::default()
             },
@@ -588,181 +1256,60 @@ async fn main() {
         .await
         .expect("Failed to create consumer");
 
+    let worker = Arc::new(Worker {
+        config,
+        compiles_kv,
+        compile_cache_kv,
+        http_client,
+        redactor,
+        slots,
+        build_cache,
+    });
+    worker.slots.spawn_reporter(Duration::from_secs(worker.config.slot_report_seconds));
+
     info!("Compile Worker ready, waiting for jobs...");
 
     // Process messages
     loop {
+        // Only take a job when there's a slot for it; the rest wait in the stream
+        let slot = worker.slots.free_slot().await;
+
         let mut messages = match consumer.fetch().max_messages(1).messages().await {
             Ok(m) => m,
             Err(e) => {
-                error!("Failed to fetch messages: {}", e);
+                if let Some(suppressed) = FETCH_ERRORS.sample() {
+                    error!(suppressed, "Failed to fetch messages: {}", e);
+                }
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 continue;
             }
         };
 
-        while let Some(msg_result) = messages.next().await {
-            let msg = match msg_result {
-                Ok(m) => m,
-                Err(e) => {
-                    error!("Failed to receive message: {}", e);
-                    continue;
-                }
-            };
-
-            let job: CompileJob = match serde_json::from_slice(&msg.payload) {
-                Ok(j) => j,
-                Err(e) => {
-                    error!("Failed to parse compile job: {}", e);
-                    let _ = msg.ack().await;
-                    continue;
-                }
-            };
-
-            info!(
-                job_id = %job.id,
-                language = ?job.language,
-                optimization = ?job.optimization,
-                source_size = job.source_code.len(),
-                "Processing compile job"
-            );
-
-            let start = Instant::now();
-            let cache_key = compute_cache_key(&job.source_code, job.language, job.optimization, &job.flags);
-
-            // Check cache first
-            if let Ok(Some(cached_entry)) = compile_cache_kv.get(&cache_key).await {
-                if let Ok(mut cached_result) = serde_json::from_slice::<CompileResult>(&cached_entry)
-                {
-                    info!(
-                        job_id = %job.id,
-                        binary_id = %cached_result.binary_id,
-                        "Cache hit"
-                    );
-
-                    cached_result.cached = true;
-
-                    // Store result for this job
-                    let result_key = format!("{}_result", job.id);
-                    if let Err(e) = compiles_kv
-                        .put(
-                            &result_key,
-                            serde_json::to_vec(&cached_result).unwrap().into(),
-                        )
-                        .await
-                    {
-                        error!("Failed to store cached result: {}", e);
-                    }
-
-                    // Update status to completed
-                    if let Err(e) =
-                        update_compile_status(&compiles_kv, &job.id, CompileStatus::Completed, None)
-                            .await
-                    {
-                        error!("Failed to update compile status: {}", e);
-                    }
-
-                    let _ = msg.ack().await;
-                    continue;
+        let msg = match messages.next().await {
+            Some(Ok(m)) => m,
+            Some(Err(e)) => {
+                if let Some(suppressed) = RECEIVE_ERRORS.sample() {
+                    error!(suppressed, "Failed to receive message: {}", e);
                 }
+                continue;
             }
-
-            // Update status to compiling
-            if let Err(e) =
-                update_compile_status(&compiles_kv, &job.id, CompileStatus::Compiling, None).await
-            {
-                error!("Failed to update compile status: {}", e);
-            }
-
-            // Compile the source
-            match compile_source(&job, &config).await {
-                Ok(output) => {
-                    let compile_time_ms = start.elapsed().as_millis() as u64;
-
-                    info!(
-                        job_id = %job.id,
-                        binary_size = output.binary.len(),
-                        compiler_version = ?output.compiler_version,
-                        time_ms = compile_time_ms,
-                        "Compilation succeeded"
-                    );
-
-                    // Store binary and cache entry
-                    match store_compile_result(
-                        &http_client,
-                        &config.api_url,
-                        &compile_cache_kv,
-                        &cache_key,
-                        &output.binary,
-                        compile_time_ms,
-                        job.language,
-                        job.optimization,
-                        output.compiler_version.as_deref(),
-                        output.compile_flags.as_ref(),
-                    )
-                    .await
-                    {
-                        Ok(result) => {
-                            // Store result for this job
-                            let result_key = format!("{}_result", job.id);
-                            if let Err(e) = compiles_kv
-                                .put(&result_key, serde_json::to_vec(&result).unwrap().into())
-                                .await
-                            {
-                                error!("Failed to store result: {}", e);
-                            }
-
-                            // Update status to completed
-                            if let Err(e) = update_compile_status(
-                                &compiles_kv,
-                                &job.id,
-                                CompileStatus::Completed,
-                                None,
-                            )
-                            .await
-                            {
-                                error!("Failed to update compile status: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            error!(job_id = %job.id, error = %e, "Failed to store compile result");
-                            if let Err(e2) = update_compile_status(
-                                &compiles_kv,
-                                &job.id,
-                                CompileStatus::Failed,
-                                Some(e),
-                            )
-                            .await
-                            {
-                                error!("Failed to update compile status: {}", e2);
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!(job_id = %job.id, error = %e, "Compilation failed");
-
-                    // Update status to failed
-                    if let Err(e2) = update_compile_status(
-                        &compiles_kv,
-                        &job.id,
-                        CompileStatus::Failed,
-                        Some(e),
-                    )
-                    .await
-                    {
-                        error!("Failed to update compile status: {}", e2);
-                    }
-                }
+            None => {
+                // Small delay before next fetch
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
             }
+        };
 
-            // Acknowledge the message
-            if let Err(e) = msg.ack().await {
-                error!("Failed to ack message: {}", e);
+        let job: CompileJob = match serde_json::from_slice(&msg.payload) {
+            Ok(j) => j,
+            Err(e) => {
+                error!("Failed to parse compile job: {}", e);
+                let _ = msg.ack().await;
+                continue;
             }
-        }
+        };
 
-        // Small delay before next fetch
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        let span = info_span!("job", job_id = %job.id, user_id = job.user_id.as_ref().map(tracing::field::display));
+        tokio::spawn(handle_job(worker.clone(), msg, job, slot).instrument(span));
     }
 }
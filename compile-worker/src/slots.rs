@@ -0,0 +1,233 @@
+//! Compile slots: how many builds one worker runs at once.
+//!
+//! The worker only fetches a job while one of its `COMPILE_CONCURRENCY`
+//! slots is free, so the backlog stays in the stream (where other replicas
+//! can take it) instead of piling up in memory. On top of that, a language
+//! can be held to fewer concurrent builds with `COMPILE_CONCURRENCY_BY_LANGUAGE`
+//! (e.g. `graalvm=1`, since native-image wants most of the box's memory).
+//! `graalvm` covers every JVM language; a key for the language itself wins.
+//!
+//! A job whose language is at its ceiling gives its slot back and waits in
+//! one of `COMPILE_CONCURRENCY` parking places; with those full too, it goes
+//! back to the stream for a while. Utilization is logged every
+//! `COMPILE_SLOT_REPORT_SECONDS`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::info;
+
+/// Languages that share a ceiling under one key
+const GROUPS: &[(&str, &[&str])] = &[("graalvm", &["java", "kotlin", "scala", "clojure"])];
+
+/// The entry of a per-language setting that applies to `language`: its own
+/// key, else its group's
+pub fn for_language<'a, T>(settings: &'a HashMap<String, T>, language: &str) -> Option<(&'a String, &'a T)> {
+    if let Some(entry) = settings.get_key_value(language) {
+        return Some(entry);
+    }
+    GROUPS
+        .iter()
+        .find(|(_, members)| members.contains(&language))
+        .and_then(|(group, _)| settings.get_key_value(*group))
+}
+
+pub struct Slots {
+    capacity: usize,
+    slots: Arc<Semaphore>,
+    /// Keyed by language or group, see [`Slots::ceiling_key`]
+    ceilings: HashMap<String, Arc<Semaphore>>,
+    parking: Arc<Semaphore>,
+    usage: Mutex<Usage>,
+}
+
+/// Counters since the last report
+struct Usage {
+    busy: usize,
+    by_key: BTreeMap<String, usize>,
+    /// Slot-milliseconds spent compiling
+    busy_ms: u128,
+    last_change: Instant,
+    since: Instant,
+    started: u64,
+    deferred: u64,
+    max_wait_ms: u64,
+}
+
+impl Usage {
+    fn advance(&mut self) {
+        let now = Instant::now();
+        self.busy_ms += self.busy as u128 * now.duration_since(self.last_change).as_millis();
+        self.last_change = now;
+    }
+}
+
+/// A running build; frees its slot (and language permit) when dropped
+pub struct Slot {
+    slots: Arc<Slots>,
+    key: String,
+    _slot: OwnedSemaphorePermit,
+    _ceiling: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut usage = self.slots.usage.lock().unwrap();
+        usage.advance();
+        usage.busy -= 1;
+        if let Some(n) = usage.by_key.get_mut(&self.key) {
+            *n -= 1;
+        }
+    }
+}
+
+impl Slots {
+    pub fn new(capacity: usize, ceilings: &HashMap<String, usize>) -> Arc<Self> {
+        let capacity = capacity.max(1);
+        let now = Instant::now();
+        Arc::new(Self {
+            capacity,
+            slots: Arc::new(Semaphore::new(capacity)),
+            ceilings: ceilings
+                .iter()
+                .map(|(key, n)| (key.clone(), Arc::new(Semaphore::new((*n).clamp(1, capacity)))))
+                .collect(),
+            parking: Arc::new(Semaphore::new(capacity)),
+            usage: Mutex::new(Usage {
+                busy: 0,
+                by_key: BTreeMap::new(),
+                busy_ms: 0,
+                last_change: now,
+                since: now,
+                started: 0,
+                deferred: 0,
+                max_wait_ms: 0,
+            }),
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The ceiling `language` counts against, if any
+    fn ceiling_key(&self, language: &str) -> Option<&str> {
+        for_language(&self.ceilings, language).map(|(key, _)| key.as_str())
+    }
+
+    /// Wait until a slot is free; hold it while fetching the next job
+    pub async fn free_slot(&self) -> OwnedSemaphorePermit {
+        self.slots.clone().acquire_owned().await.expect("slots semaphore closed")
+    }
+
+    /// Start a `language` build on a slot from [`Slots::free_slot`], or hand
+    /// the slot back if the language is at its ceiling
+    pub fn try_start(
+        self: &Arc<Self>,
+        slot: OwnedSemaphorePermit,
+        language: &str,
+    ) -> Result<Slot, OwnedSemaphorePermit> {
+        let ceiling = match self.ceiling_key(language) {
+            Some(key) => match self.ceilings[key].clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => return Err(slot),
+            },
+            None => None,
+        };
+        Ok(self.started(language, slot, ceiling, Duration::ZERO))
+    }
+
+    /// A place to wait for a language permit without holding a slot
+    pub fn try_park(&self) -> Option<OwnedSemaphorePermit> {
+        self.parking.clone().try_acquire_owned().ok()
+    }
+
+    /// Wait for a `language` permit, then a slot
+    pub async fn start(self: &Arc<Self>, language: &str) -> Slot {
+        let waited = Instant::now();
+        let ceiling = match self.ceiling_key(language) {
+            Some(key) => Some(self.ceilings[key].clone().acquire_owned().await.expect("ceiling semaphore closed")),
+            None => None,
+        };
+        let slot = self.free_slot().await;
+        self.started(language, slot, ceiling, waited.elapsed())
+    }
+
+    /// A job went back to the stream because its language was saturated
+    pub fn record_deferred(&self) {
+        self.usage.lock().unwrap().deferred += 1;
+    }
+
+    fn started(
+        self: &Arc<Self>,
+        language: &str,
+        slot: OwnedSemaphorePermit,
+        ceiling: Option<OwnedSemaphorePermit>,
+        waited: Duration,
+    ) -> Slot {
+        let key = self.ceiling_key(language).unwrap_or(language).to_string();
+        let mut usage = self.usage.lock().unwrap();
+        usage.advance();
+        usage.busy += 1;
+        usage.started += 1;
+        usage.max_wait_ms = usage.max_wait_ms.max(waited.as_millis() as u64);
+        *usage.by_key.entry(key.clone()).or_default() += 1;
+        drop(usage);
+
+        Slot {
+            slots: self.clone(),
+            key,
+            _slot: slot,
+            _ceiling: ceiling,
+        }
+    }
+
+    /// Log utilization since the last report and start a new window
+    fn report(&self) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.advance();
+        let window_ms = usage.since.elapsed().as_millis().max(1);
+        let utilization = usage.busy_ms as f64 / (window_ms * self.capacity as u128) as f64;
+        let busy_by_language: Vec<String> = usage
+            .by_key
+            .iter()
+            .filter(|(_, n)| **n > 0)
+            .map(|(key, n)| format!("{}={}", key, n))
+            .collect();
+
+        info!(
+            slots = self.capacity,
+            busy = usage.busy,
+            busy_by_language = %busy_by_language.join(","),
+            parked = self.capacity - self.parking.available_permits(),
+            utilization = format!("{:.2}", utilization),
+            started = usage.started,
+            deferred = usage.deferred,
+            max_wait_ms = usage.max_wait_ms,
+            "Compile slots"
+        );
+
+        usage.busy_ms = 0;
+        usage.since = Instant::now();
+        usage.started = 0;
+        usage.deferred = 0;
+        usage.max_wait_ms = 0;
+    }
+
+    /// Report utilization every `interval` (no-op for a zero interval)
+    pub fn spawn_reporter(self: &Arc<Self>, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+        let slots = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                slots.report();
+            }
+        });
+    }
+}
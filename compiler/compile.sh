@@ -2,11 +2,74 @@
 set -e
 
 # Entry script that dispatches to language-specific compile scripts
+#
+# Inputs come from /work/manifest.json when the worker writes one (compile
+# protocol, see common/src/compile_protocol.rs); the environment variables
+# below are the older protocol, used only without a manifest.
 # Environment variables:
 #   LANGUAGE - the language to compile (c, rust, go, etc.)
 #   OPTIMIZATION - debug, release, or size (default: release)
 #   SOURCE_FILE - the source file name in /work
 #   OUTPUT_FILE - the output binary name in /work (default: output)
+#   BUILD_CACHE - persistent cache mounted at /cache: sccache, go or jvm (optional)
+#   BUILD_CACHE_MAX_MB - size the worker holds that cache to
+#   EMIT_ASM - 1 to also write assembly to /work/output.asm (optional)
+#   TOOLCHAIN - toolchain version for languages with several installed:
+#               rust stable|nightly, python 3.12|3.11 (optional, API-validated)
+#   LINKER_FLAGS - allowlisted ld arguments, space separated (asm, c, cpp; optional)
+#   LINKER_SCRIPT - path of a custom linker script under /work (asm, c, cpp; optional)
+#
+# Besides the binary, writes compiler_version.txt, compile_flags.json and,
+# for object files binutils can read, size_report.json to /work (or the
+# names the manifest asks for); scripts whose output needs an interpreter in
+# the sandbox write runtime.json (RUNTIME_PATH, see common/src/runtime.rs).
+# Then result.json: success, exit code, the
+# outputs written, the compiler diagnostics it could parse and the CPU time
+# and peak memory of the container's cgroup
+
+# Compile protocol version this image speaks
+PROTOCOL_VERSION=1
+MANIFEST="/work/manifest.json"
+RESULT_FILE="/work/result.json"
+
+VERSION_FILE="/work/compiler_version.txt"
+FLAGS_FILE="/work/compile_flags.json"
+TOOLCHAIN_FILE="/work/toolchain.txt"
+ASM_PATH="/work/output.asm"
+SIZE_REPORT_FILE="/work/size_report.json"
+BUILD_CACHE_STATS_FILE="/work/build_cache_stats.json"
+RUNTIME_PATH="/work/runtime.json"
+
+if [ -f "$MANIFEST" ]; then
+    MANIFEST_VERSION=$(jq -r '.schema_version // 0' "$MANIFEST")
+    if [ "$MANIFEST_VERSION" -gt "$PROTOCOL_VERSION" ]; then
+        echo "ERROR: manifest schema_version $MANIFEST_VERSION is newer than this image ($PROTOCOL_VERSION)" >&2
+        exit 1
+    fi
+    eval "$(jq -r '
+        "LANGUAGE=\(.language | @sh)",
+        "OPTIMIZATION=\(.optimization // "release" | @sh)",
+        "SOURCE_FILE=\(.source_file | @sh)",
+        "EMIT_ASM=\(if .emit_asm then "1" else "" end)",
+        "TOOLCHAIN=\(.toolchain // "" | @sh)",
+        "LINKER_FLAGS=\(.linker_flags // [] | join(" ") | @sh)",
+        "LINKER_SCRIPT=\(if .linker_script then "/work/" + .linker_script else "" end | @sh)",
+        "BUILD_CACHE=\(.build_cache // "" | @sh)",
+        (.outputs // {} | to_entries[] | "OUT_\(.key | ascii_upcase)=\(.value | @sh)"),
+        (.flags // {} | to_entries[]
+            | (.key | gsub("[^A-Za-z0-9_]"; "") | ascii_upcase) as $name
+            | select($name != "")
+            | "export FLAG_\($name)=\(.value | @sh)")
+    ' "$MANIFEST")"
+    OUTPUT_FILE="${OUT_BINARY:-output}"
+    VERSION_FILE="/work/${OUT_COMPILER_VERSION:-compiler_version.txt}"
+    FLAGS_FILE="/work/${OUT_COMPILE_FLAGS:-compile_flags.json}"
+    TOOLCHAIN_FILE="/work/${OUT_TOOLCHAIN:-toolchain.txt}"
+    ASM_PATH="/work/${OUT_ASM:-output.asm}"
+    SIZE_REPORT_FILE="/work/${OUT_SIZE_REPORT:-size_report.json}"
+    BUILD_CACHE_STATS_FILE="/work/${OUT_BUILD_CACHE_STATS:-build_cache_stats.json}"
+    RUNTIME_PATH="/work/${OUT_RUNTIME:-runtime.json}"
+fi
 
 LANGUAGE="${LANGUAGE:-}"
 OPTIMIZATION="${OPTIMIZATION:-release}"
@@ -40,11 +103,51 @@ if [ ! -f "$SCRIPT_PATH" ]; then
 fi
 
 # Export variables for child scripts
-export SOURCE_PATH OUTPUT_PATH OPTIMIZATION
+EMIT_ASM="${EMIT_ASM:-}"
+LINKER_FLAGS="${LINKER_FLAGS:-}"
+LINKER_SCRIPT="${LINKER_SCRIPT:-}"
+export SOURCE_PATH OUTPUT_PATH OPTIMIZATION EMIT_ASM ASM_PATH LINKER_FLAGS LINKER_SCRIPT RUNTIME_PATH
+
+# Pick the toolchain version and record it for the binary's metadata
+TOOLCHAIN="${TOOLCHAIN:-}"
+case "$LANGUAGE" in
+    rust)
+        export RUSTUP_TOOLCHAIN="${TOOLCHAIN:-stable}"
+        TOOLCHAIN="$RUSTUP_TOOLCHAIN"
+        ;;
+    python)
+        TOOLCHAIN="${TOOLCHAIN:-3.12}"
+        export PYTHON="python${TOOLCHAIN}"
+        ;;
+esac
+if [ -n "$TOOLCHAIN" ]; then
+    echo "$TOOLCHAIN" > "$TOOLCHAIN_FILE"
+fi
+
+# Point the toolchain at the persistent cache, if the worker mounted one
+BUILD_CACHE="${BUILD_CACHE:-}"
+if [ -n "$BUILD_CACHE" ] && [ -d /cache ]; then
+    case "$BUILD_CACHE" in
+        sccache)
+            export SCCACHE_DIR=/cache
+            export SCCACHE_CACHE_SIZE="${BUILD_CACHE_MAX_MB:-2048}M"
+            export RUSTC_WRAPPER=sccache
+            export CC_LAUNCHER=sccache
+            ;;
+        go)
+            export GOCACHE=/cache/go-build
+            export GOMODCACHE=/cache/mod
+            ;;
+        jvm)
+            export GRADLE_USER_HOME=/cache/gradle
+            # Maven and the Clojure CLI both resolve dependencies into ~/.m2
+            mkdir -p /cache/m2 && rm -rf "$HOME/.m2" && ln -s /cache/m2 "$HOME/.m2"
+            ;;
+    esac
+else
+    BUILD_CACHE=""
+fi
 
-# Output files for metadata
-VERSION_FILE="/work/compiler_version.txt"
-FLAGS_FILE="/work/compile_flags.json"
 get_version() {
     case "$LANGUAGE" in
         c|cpp)   gcc --version | head -1 ;;
@@ -57,11 +160,16 @@ get_version() {
         swift)   swift --version 2>&1 | head -1 ;;
         haskell) ghc --version ;;
         csharp)  dotnet --version ;;
+        fortran) gfortran --version | head -1 ;;
+        d)       ldc2 --version | head -1 ;;
+        crystal) crystal --version | head -1 ;;
+        v)       v version ;;
+        ada)     gnatmake --version | head -1 ;;
         java)    java --version 2>&1 | head -1 ;;
         kotlin)  kotlinc -version 2>&1 | head -1 ;;
         scala)   scala --version 2>&1 | head -1 || echo "scala3" ;;
         clojure) clojure --version 2>&1 | head -1 ;;
-        python)  python3 --version 2>&1 && python3 -m nuitka --version 2>&1 | head -1 ;;
+        python)  $PYTHON --version 2>&1 && $PYTHON -m nuitka --version 2>&1 | head -1 ;;
         javascript|typescript|bun) bun --version ;;
         deno)    deno --version | head -1 ;;
         node)    node --version ;;
@@ -90,8 +198,181 @@ get_version > "$VERSION_FILE" 2>&1 || true
         value="${!var}"
         echo ",  \"${var}\": \"$value\""
     done
+    # Build options, so a rebuild can tell they were used
+    if [ -n "$LINKER_FLAGS" ]; then
+        echo ",  \"LINKER_FLAGS\": \"$LINKER_FLAGS\""
+    fi
+    if [ -n "$LINKER_SCRIPT" ]; then
+        echo ",  \"LINKER_SCRIPT_SHA256\": \"$(sha256sum "$LINKER_SCRIPT" | cut -d' ' -f1)\""
+    fi
     echo "}"
 } > "$FLAGS_FILE" 2>/dev/null || true
 
-# Execute the language-specific script
-"$SCRIPT_PATH"
+# Diagnostics from compiler output: gcc-style `file:line:col: level: message`
+# lines, and rustc's `level: message` followed by ` --> file:line:col`
+MAX_DIAGNOSTICS=100
+parse_diagnostics() {
+    awk -v max="$MAX_DIAGNOSTICS" '
+        function esc(s) {
+            gsub(/\\/, "\\\\", s); gsub(/"/, "\\\"", s); gsub(/\t/, " ", s)
+            gsub("[\001-\037]", "", s)
+            return s
+        }
+        function emit(level, message, file, line, column) {
+            if (count >= max) return
+            printf "%s    {\"level\": \"%s\", \"message\": \"%s\"", sep, level, esc(message)
+            if (file != "") printf ", \"file\": \"%s\", \"line\": %d", esc(file), line
+            if (column != "") printf ", \"column\": %d", column
+            printf "}"
+            sep = ",\n"; count++
+        }
+        function flush() {
+            if (pending_level != "") emit(pending_level, pending_message, "", 0, "")
+            pending_level = ""
+        }
+        # Colored output
+        { gsub("\033\\[[0-9;]*[A-Za-z]", "") }
+        match($0, /^[^: ]+:[0-9]+:([0-9]+:)? *(fatal error|error|warning|note|Error|Warning):/) {
+            flush()
+            head = substr($0, 1, RLENGTH); message = substr($0, RLENGTH + 1)
+            sub(/^ +/, "", message)
+            n = split(head, parts, ":")
+            level = tolower(parts[n - 1]); sub(/^ +/, "", level); sub(/^fatal /, "", level)
+            emit(level, message, parts[1], parts[2], (n == 5 ? parts[3] : ""))
+            next
+        }
+        match($0, /^(error|warning)(\[[A-Z0-9]+\])?: /) {
+            flush()
+            pending_level = $0; sub(/[:\[].*/, "", pending_level)
+            pending_message = substr($0, RLENGTH + 1)
+            next
+        }
+        pending_level != "" && match($0, /^ *--> [^:]+:[0-9]+:[0-9]+/) {
+            location = $0; sub(/^ *--> /, "", location)
+            split(location, parts, ":")
+            emit(pending_level, pending_message, parts[1], parts[2], parts[3] + 0)
+            pending_level = ""
+            next
+        }
+        END { flush(); if (sep) printf "\n" }
+    ' "$1"
+}
+
+# CPU time, peak memory and OOM kills of this container (cgroup v2, else v1),
+# as a `"resources"` line; nothing where the cgroup isn't readable
+write_resources() {
+    local cgroup=/sys/fs/cgroup cpu_usec="" peak="" oom_kills=""
+    if [ -f "$cgroup/cpu.stat" ] && [ -f "$cgroup/memory.current" ]; then
+        cpu_usec=$(awk '$1 == "usage_usec" { print $2 }' "$cgroup/cpu.stat")
+        # memory.peak is newer (Linux 5.19) than the rest of v2
+        peak=$(cat "$cgroup/memory.peak" 2>/dev/null || cat "$cgroup/memory.current")
+        oom_kills=$(awk '$1 == "oom_kill" { print $2 }' "$cgroup/memory.events" 2>/dev/null)
+    elif [ -f "$cgroup/cpuacct/cpuacct.usage" ]; then
+        cpu_usec=$(( $(cat "$cgroup/cpuacct/cpuacct.usage") / 1000 ))
+        peak=$(cat "$cgroup/memory/memory.max_usage_in_bytes" 2>/dev/null)
+        oom_kills=$(awk '$1 == "oom_kill" { print $2 }' "$cgroup/memory/memory.oom_control" 2>/dev/null)
+    fi
+    [ -n "$cpu_usec" ] && [ -n "$peak" ] || return 0
+    awk -v usec="$cpu_usec" -v peak="$peak" -v oom="${oom_kills:-0}" 'BEGIN {
+        printf "  \"resources\": {\"cpu_seconds\": %.3f, \"peak_memory_bytes\": %.0f, \"oom_killed\": %s},\n",
+            usec / 1000000, peak, (oom > 0 ? "true" : "false")
+    }'
+}
+
+# result.json for the worker: success, exit code, outputs written, diagnostics
+# and resource usage
+write_result() {
+    local success="$1" exit_code="$2" produced="" sep="" key file
+    for key in binary compiler_version compile_flags toolchain asm size_report build_cache_stats runtime; do
+        case "$key" in
+            binary)            file="$OUTPUT_PATH" ;;
+            compiler_version)  file="$VERSION_FILE" ;;
+            compile_flags)     file="$FLAGS_FILE" ;;
+            toolchain)         file="$TOOLCHAIN_FILE" ;;
+            asm)               file="$ASM_PATH" ;;
+            size_report)       file="$SIZE_REPORT_FILE" ;;
+            build_cache_stats) file="$BUILD_CACHE_STATS_FILE" ;;
+            runtime)           file="$RUNTIME_PATH" ;;
+        esac
+        if [ -s "$file" ]; then
+            produced="$produced$sep\"$key\""
+            sep=", "
+        fi
+    done
+    {
+        echo "{"
+        echo "  \"schema_version\": $PROTOCOL_VERSION,"
+        echo "  \"success\": $success,"
+        echo "  \"exit_code\": $exit_code,"
+        echo "  \"produced\": [$produced],"
+        write_resources
+        echo "  \"diagnostics\": ["
+        parse_diagnostics "$STDERR_LOG"
+        echo "  ]"
+        echo "}"
+    } > "$RESULT_FILE" 2>/dev/null || rm -f "$RESULT_FILE"
+}
+
+# Execute the language-specific script, keeping its stderr for diagnostics
+STDERR_LOG=$(mktemp)
+set +e
+"$SCRIPT_PATH" 2> "$STDERR_LOG"
+STATUS=$?
+set -e
+cat "$STDERR_LOG" >&2
+if [ "$STATUS" -ne 0 ]; then
+    write_result false "$STATUS"
+    exit "$STATUS"
+fi
+
+# Scripts that can emit annotated assembly (C, C++, Rust) write ASM_PATH
+# themselves; everything else gets a disassembly of the binary
+if [ "$EMIT_ASM" = "1" ] && [ ! -s "$ASM_PATH" ]; then
+    case "$LANGUAGE" in
+        go) go tool objdump -s '^main\.' "$OUTPUT_PATH" > "$ASM_PATH" 2>/dev/null || true ;;
+        *)  objdump -d -C -M intel --no-show-raw-insn "$OUTPUT_PATH" > "$ASM_PATH" 2>/dev/null || true ;;
+    esac
+fi
+
+# Per-section and largest-symbol sizes; skipped for outputs binutils can't read
+# (scripts, jars, wasm)
+MAX_REPORT_SYMBOLS=100
+write_size_report() {
+    local sections symbols
+    sections=$(size -A -d "$OUTPUT_PATH" 2>/dev/null) || return 0
+    symbols=$(mktemp)
+    nm -S -t d --size-sort -C "$OUTPUT_PATH" > "$symbols" 2>/dev/null || true
+
+    {
+        echo "{"
+        echo "  \"tool\": \"binutils\","
+        echo "  \"file_bytes\": $(stat -c %s "$OUTPUT_PATH"),"
+        echo "  \"sections\": ["
+        echo "$sections" | awk 'NR > 2 && NF == 3 && $1 != "Total" && $2 > 0 {
+            printf "%s    {\"name\": \"%s\", \"size\": %d}", sep, $1, $2; sep = ",\n"
+        } END { if (sep) printf "\n" }'
+        echo "  ],"
+        echo "  \"symbol_count\": $(wc -l < "$symbols"),"
+        echo "  \"symbols\": ["
+        # --size-sort is ascending, so the largest are at the end
+        tail -n "$MAX_REPORT_SYMBOLS" "$symbols" | tac | awk '{
+            name = $0
+            sub(/^[^ ]+ +[^ ]+ +[^ ]+ +/, "", name)
+            gsub(/\\/, "\\\\", name); gsub(/"/, "\\\"", name); gsub(/\t/, " ", name)
+            printf "%s    {\"name\": \"%s\", \"size\": %d, \"type\": \"%s\"}", sep, name, $2, $3; sep = ",\n"
+        } END { if (sep) printf "\n" }'
+        echo "  ]"
+        echo "}"
+    } > "$SIZE_REPORT_FILE" 2>/dev/null || rm -f "$SIZE_REPORT_FILE"
+    rm -f "$symbols"
+}
+
+write_size_report
+
+# Hit/miss counts for the worker, then stop the server so the cache is flushed
+if [ "$BUILD_CACHE" = "sccache" ]; then
+    sccache --show-stats --stats-format json > "$BUILD_CACHE_STATS_FILE" 2>/dev/null || true
+    sccache --stop-server > /dev/null 2>&1 || true
+fi
+
+write_result true 0
@@ -0,0 +1,66 @@
+#!/bin/bash
+set -e
+
+# Ada compilation with GNAT, statically linked
+#
+# GNAT wants one unit per file, named after the unit, so the source is split
+# with gnatchop first; the main procedure is the last unit in the file.
+#
+# Supported flags (via FLAG_* environment variables):
+#   FLAG_OPT=0|1|2|3|s - Optimization level (overrides OPTIMIZATION)
+#   FLAG_CHECKS=true|false - Runtime checks (default: off for release)
+#   FLAG_STRIP=true|false - Strip symbols (default: true for release)
+
+case "$OPTIMIZATION" in
+    debug)
+        DEFAULT_OPT="0"
+        DEFAULT_CHECKS="true"
+        DEFAULT_STRIP="false"
+        FLAGS="-g"
+        ;;
+    release)
+        DEFAULT_OPT="3"
+        DEFAULT_CHECKS="false"
+        DEFAULT_STRIP="true"
+        FLAGS=""
+        ;;
+    size)
+        DEFAULT_OPT="s"
+        DEFAULT_CHECKS="false"
+        DEFAULT_STRIP="true"
+        FLAGS=""
+        ;;
+    *)
+        DEFAULT_OPT="3"
+        DEFAULT_CHECKS="false"
+        DEFAULT_STRIP="true"
+        FLAGS=""
+        ;;
+esac
+
+# Parse flags
+OPT="${FLAG_OPT:-$DEFAULT_OPT}"
+CHECKS="${FLAG_CHECKS:-$DEFAULT_CHECKS}"
+STRIP="${FLAG_STRIP:-$DEFAULT_STRIP}"
+
+FLAGS="$FLAGS -O$OPT"
+if [ "$CHECKS" = "false" ]; then
+    FLAGS="$FLAGS -gnatp"
+fi
+LINK_FLAGS="-static"
+if [ "$STRIP" = "true" ]; then
+    LINK_FLAGS="$LINK_FLAGS -s"
+fi
+
+TEMP_DIR=$(mktemp -d)
+trap "rm -rf $TEMP_DIR" EXIT
+
+# gnatchop lists the files it writes; the last one holds the main procedure
+MAIN=$(gnatchop -w "$SOURCE_PATH" "$TEMP_DIR" | grep -E '\.adb$' | tail -n 1 | awk '{print $NF}')
+if [ -z "$MAIN" ]; then
+    echo "ERROR: no Ada procedure body found" >&2
+    exit 1
+fi
+
+cd "$TEMP_DIR"
+exec gnatmake $FLAGS -o "$OUTPUT_PATH" "$(basename "$MAIN")" -largs $LINK_FLAGS
@@ -2,9 +2,12 @@
 set -e
 
 # x86_64 Assembly compilation
+#
+# LINKER_FLAGS - extra ld arguments, e.g. "--entry=main -N" (optional)
+# LINKER_SCRIPT - custom linker script, passed as -T (optional)
 
 TEMP_DIR=$(mktemp -d)
 trap "rm -rf $TEMP_DIR" EXIT
 
 as -o "$TEMP_DIR/prog.o" "$SOURCE_PATH"
-ld -o "$OUTPUT_PATH" "$TEMP_DIR/prog.o"
+ld $LINKER_FLAGS ${LINKER_SCRIPT:+-T "$LINKER_SCRIPT"} -o "$OUTPUT_PATH" "$TEMP_DIR/prog.o"
@@ -12,6 +12,8 @@ set -e
 #   FLAG_STRIP=true|false     - Strip symbols
 #   FLAG_MARCH=native|...     - Target architecture
 #   FLAG_FREESTANDING=true    - Freestanding mode (no libc)
+#
+# LINKER_FLAGS and LINKER_SCRIPT (from build_options) are passed to ld via -Wl
 
 case "$OPTIMIZATION" in
     debug)
@@ -109,4 +111,26 @@ case "$COMPILER" in
         ;;
 esac
 
-exec $CC $FLAGS -o "$OUTPUT_PATH" "$SOURCE_PATH" $LIBS
+# Linker options from the job's build_options, validated by the API
+LDFLAGS=""
+for flag in $LINKER_FLAGS; do
+    LDFLAGS="$LDFLAGS -Wl,$flag"
+done
+if [ -n "$LINKER_SCRIPT" ]; then
+    LDFLAGS="$LDFLAGS -Wl,-T,$LINKER_SCRIPT"
+fi
+
+# Annotated assembly (without LTO, which would leave only IR in the output)
+if [ "$EMIT_ASM" = "1" ]; then
+    $CC $FLAGS -fno-lto -S -fverbose-asm -o "$ASM_PATH" "$SOURCE_PATH" || true
+fi
+
+# With a build cache, compile and link separately so the object can be cached
+if [ -n "$CC_LAUNCHER" ]; then
+    $CC_LAUNCHER $CC $FLAGS -c -o "$OUTPUT_PATH.o" "$SOURCE_PATH"
+    $CC $FLAGS $LDFLAGS -o "$OUTPUT_PATH" "$OUTPUT_PATH.o" $LIBS
+    rm -f "$OUTPUT_PATH.o"
+    exit 0
+fi
+
+exec $CC $FLAGS $LDFLAGS -o "$OUTPUT_PATH" "$SOURCE_PATH" $LIBS
@@ -0,0 +1,32 @@
+#!/bin/bash
+set -e
+
+# Crystal compilation, statically linked
+#
+# Supported flags (via FLAG_* environment variables):
+#   FLAG_STRIP=true|false - Strip symbols (default: true for release)
+
+case "$OPTIMIZATION" in
+    debug)
+        DEFAULT_STRIP="false"
+        FLAGS="--debug"
+        ;;
+    release|size|*)
+        # Crystal has one optimized mode; --no-debug keeps DWARF out
+        DEFAULT_STRIP="true"
+        FLAGS="--release --no-debug"
+        ;;
+esac
+
+STRIP="${FLAG_STRIP:-$DEFAULT_STRIP}"
+
+TEMP_DIR=$(mktemp -d)
+trap "rm -rf $TEMP_DIR" EXIT
+
+# Crystal caches compiled objects under CRYSTAL_CACHE_DIR
+export CRYSTAL_CACHE_DIR="$TEMP_DIR/cache"
+crystal build $FLAGS --static -o "$OUTPUT_PATH" "$SOURCE_PATH"
+
+if [ "$STRIP" = "true" ]; then
+    strip "$OUTPUT_PATH"
+fi
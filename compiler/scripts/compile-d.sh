@@ -0,0 +1,50 @@
+#!/bin/bash
+set -e
+
+# D compilation with LDC (LLVM backend), statically linked
+#
+# Supported flags (via FLAG_* environment variables):
+#   FLAG_OPT=0|1|2|3|s|z - Optimization level (overrides OPTIMIZATION)
+#   FLAG_BETTERC=true|false - -betterC: no druntime/GC, much smaller binaries
+#   FLAG_STRIP=true|false - Strip symbols (default: true for release)
+
+case "$OPTIMIZATION" in
+    debug)
+        DEFAULT_OPT="0"
+        DEFAULT_STRIP="false"
+        FLAGS="-g"
+        ;;
+    release)
+        DEFAULT_OPT="3"
+        DEFAULT_STRIP="true"
+        FLAGS="-release"
+        ;;
+    size)
+        DEFAULT_OPT="z"
+        DEFAULT_STRIP="true"
+        FLAGS="-release"
+        ;;
+    *)
+        DEFAULT_OPT="3"
+        DEFAULT_STRIP="true"
+        FLAGS="-release"
+        ;;
+esac
+
+# Parse flags
+OPT="${FLAG_OPT:-$DEFAULT_OPT}"
+BETTERC="${FLAG_BETTERC:-false}"
+STRIP="${FLAG_STRIP:-$DEFAULT_STRIP}"
+
+FLAGS="$FLAGS -O$OPT"
+if [ "$BETTERC" = "true" ]; then
+    FLAGS="$FLAGS -betterC"
+fi
+if [ "$STRIP" = "true" ]; then
+    FLAGS="$FLAGS -L-s"
+fi
+
+TEMP_DIR=$(mktemp -d)
+trap "rm -rf $TEMP_DIR" EXIT
+
+exec ldc2 $FLAGS -static -od="$TEMP_DIR" -of="$OUTPUT_PATH" "$SOURCE_PATH"
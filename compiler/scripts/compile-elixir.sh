@@ -39,3 +39,6 @@ mix deps.get 2>&1 || true
 mix escript.build 2>&1
 
 cp "$TEMP_DIR/main" "$OUTPUT_PATH"
+
+# The sandbox runs it with escript from its runtimes image
+echo '{"runtime": "escript", "entrypoint": ["/usr/bin/escript"]}' > "$RUNTIME_PATH"
@@ -36,3 +36,6 @@ EOF
 chmod +x output
 
 cp "$TEMP_DIR/output" "$OUTPUT_PATH"
+
+# The sandbox runs it with escript from its runtimes image
+echo '{"runtime": "escript", "entrypoint": ["/usr/bin/escript"]}' > "$RUNTIME_PATH"
@@ -0,0 +1,48 @@
+#!/bin/bash
+set -e
+
+# Fortran compilation with gfortran, statically linked
+#
+# Supported flags (via FLAG_* environment variables):
+#   FLAG_OPT=0|1|2|3|s|fast - Optimization level (overrides OPTIMIZATION)
+#   FLAG_STD=f95|f2003|f2008|f2018|legacy - Language standard (default: gfortran's)
+#   FLAG_STRIP=true|false - Strip symbols (default: true for release)
+
+case "$OPTIMIZATION" in
+    debug)
+        DEFAULT_OPT="0"
+        DEFAULT_STRIP="false"
+        FLAGS="-g"
+        ;;
+    release)
+        DEFAULT_OPT="3"
+        DEFAULT_STRIP="true"
+        FLAGS=""
+        ;;
+    size)
+        DEFAULT_OPT="s"
+        DEFAULT_STRIP="true"
+        FLAGS=""
+        ;;
+    *)
+        DEFAULT_OPT="3"
+        DEFAULT_STRIP="true"
+        FLAGS=""
+        ;;
+esac
+
+# Parse flags
+OPT="${FLAG_OPT:-$DEFAULT_OPT}"
+STD="${FLAG_STD:-}"
+STRIP="${FLAG_STRIP:-$DEFAULT_STRIP}"
+
+FLAGS="$FLAGS -O$OPT"
+if [ -n "$STD" ]; then
+    FLAGS="$FLAGS -std=$STD"
+fi
+if [ "$STRIP" = "true" ]; then
+    FLAGS="$FLAGS -s"
+fi
+
+# gfortran picks free or fixed form from the extension; sources are free-form .f90
+exec gfortran $FLAGS -static -o "$OUTPUT_PATH" "$SOURCE_PATH"
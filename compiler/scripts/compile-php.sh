@@ -18,6 +18,5 @@ chmod +x "$TEMP_DIR/output"
 
 cp "$TEMP_DIR/output" "$OUTPUT_PATH"
 
-# Note: This won't work in the sandbox without PHP interpreter.
-# For true standalone PHP binaries, we'd need static-php-cli or similar.
-echo "Warning: PHP compilation creates an interpreter script, not a native binary" >&2
+# The sandbox runs it with the interpreter from its runtimes image
+echo '{"runtime": "php", "entrypoint": ["/usr/bin/php"]}' > "$RUNTIME_PATH"
@@ -2,6 +2,8 @@
 set -e
 
 # Python compilation with Nuitka
+#
+# PYTHON - interpreter for the selected toolchain (set by compile.sh)
 
 TEMP_DIR=$(mktemp -d)
 trap "rm -rf $TEMP_DIR" EXIT
@@ -10,7 +12,7 @@ cp "$SOURCE_PATH" "$TEMP_DIR/main.py"
 cd "$TEMP_DIR"
 
 # Nuitka compilation to standalone binary
-python3 -m nuitka \
+"${PYTHON:-python3}" -m nuitka \
     --standalone \
     --onefile \
     --remove-output \
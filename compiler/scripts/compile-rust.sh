@@ -92,12 +92,23 @@ if [ "$NOSTD" = "true" ]; then
     RUSTFLAGS="$RUSTFLAGS -C link-arg=-nostartfiles"
 fi
 
+# Keep the assembly rustc generates alongside the binary
+if [ "$EMIT_ASM" = "1" ]; then
+    RUSTFLAGS="$RUSTFLAGS --emit=asm,link"
+fi
+
 export RUSTFLAGS
 
 if [ "$PROFILE" = "release" ]; then
     cargo build --release --target "$TARGET" 2>&1
     cp "$TEMP_DIR/target/$TARGET/release/solution" "$OUTPUT_PATH"
+    PROFILE_DIR="release"
 else
     cargo build --target "$TARGET" 2>&1
     cp "$TEMP_DIR/target/$TARGET/debug/solution" "$OUTPUT_PATH"
+    PROFILE_DIR="debug"
+fi
+
+if [ "$EMIT_ASM" = "1" ]; then
+    cat "$TEMP_DIR/target/$TARGET/$PROFILE_DIR"/deps/solution-*.s > "$ASM_PATH" 2>/dev/null || true
 fi
@@ -18,4 +18,5 @@ chmod +x "$TEMP_DIR/output"
 
 cp "$TEMP_DIR/output" "$OUTPUT_PATH"
 
-echo "Warning: Tcl compilation creates an interpreter script, not a native binary" >&2
+# The sandbox runs it with the interpreter from its runtimes image
+echo '{"runtime": "tcl", "entrypoint": ["/usr/bin/tclsh"]}' > "$RUNTIME_PATH"
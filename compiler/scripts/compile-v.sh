@@ -0,0 +1,45 @@
+#!/bin/bash
+set -e
+
+# V compilation through C (gcc backend), statically linked
+#
+# Supported flags (via FLAG_* environment variables):
+#   FLAG_GC=boehm|none - Garbage collector (default: boehm, V's default)
+#   FLAG_STRIP=true|false - Strip symbols (default: true for release)
+
+case "$OPTIMIZATION" in
+    debug)
+        DEFAULT_STRIP="false"
+        FLAGS="-g"
+        ;;
+    release)
+        DEFAULT_STRIP="true"
+        FLAGS="-prod"
+        ;;
+    size)
+        DEFAULT_STRIP="true"
+        FLAGS="-prod -cflags -Os"
+        ;;
+    *)
+        DEFAULT_STRIP="true"
+        FLAGS="-prod"
+        ;;
+esac
+
+GC="${FLAG_GC:-}"
+STRIP="${FLAG_STRIP:-$DEFAULT_STRIP}"
+
+if [ -n "$GC" ]; then
+    FLAGS="$FLAGS -gc $GC"
+fi
+if [ "$STRIP" = "true" ]; then
+    FLAGS="$FLAGS -cflags -s"
+fi
+
+TEMP_DIR=$(mktemp -d)
+trap "rm -rf $TEMP_DIR" EXIT
+
+# V wants a .v file it can write next to, and keeps its cache in VCACHE
+cp "$SOURCE_PATH" "$TEMP_DIR/main.v"
+export VCACHE="$TEMP_DIR/cache"
+exec v $FLAGS -cc gcc -cflags -static -o "$OUTPUT_PATH" "$TEMP_DIR/main.v"
@@ -0,0 +1,134 @@
+//! End-to-end flows through the in-process queue (QUEUE_MODE=local).
+//!
+//! Needs a Docker daemon for the Postgres container:
+//! `cargo test -p integration-tests -- --ignored`
+
+use integration_tests::{job_for, TestApp};
+
+const HELLO: &str = "int main() { return 0; }\n// FAKE_STDOUT=Hello, World!\\n\n";
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn compile_execute_and_persist_run() {
+    let app = TestApp::local().await;
+
+    let (compile_id, status) = app.compile(HELLO, "c").await;
+    assert_eq!(status["status"], "completed", "{}", status);
+
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+    let binary_id = result["binary_id"].as_str().unwrap().to_string();
+    assert!(binary_id.starts_with("sha256-"));
+
+    let (job_id, status) = app.execute(&binary_id, "").await;
+    assert_eq!(status["status"], "completed", "{}", status);
+
+    let (code, result) = app.get_json(&format!("/result/{}", job_id)).await;
+    assert!(code.is_success());
+    assert!(result["instructions"].as_u64().unwrap() > 0);
+
+    let (code, run) = app.get_json(&format!("/runs/job/{}", job_id)).await;
+    assert!(code.is_success(), "run not persisted: {}", run);
+    assert_eq!(run["binary_id"], binary_id.as_str());
+    assert_eq!(run["instructions"], result["instructions"]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn identical_source_hits_compile_cache() {
+    let app = TestApp::local().await;
+
+    let (first, _) = app.compile(HELLO, "c").await;
+    let (second, _) = app.compile(HELLO, "c").await;
+
+    let (_, first) = app.get_json(&format!("/compile/result/{}", first)).await;
+    let (_, second) = app.get_json(&format!("/compile/result/{}", second)).await;
+    assert_eq!(first["binary_id"], second["binary_id"]);
+    assert_eq!(second["cached"], true);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn compile_error_is_reported() {
+    let app = TestApp::local().await;
+
+    let (compile_id, status) = app.compile("FAKE_COMPILE_ERROR\n", "c").await;
+    assert_eq!(status["status"], "failed");
+
+    let (code, body) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+    assert_eq!(code, 400);
+    assert!(body["error"].as_str().unwrap().contains("fake compile error"));
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn passing_challenge_submission_reaches_leaderboard() {
+    let app = TestApp::local().await;
+    let cookie = app.login("alice").await;
+
+    let submission = app.submit_challenge(&cookie, "hello-world", HELLO, "c").await;
+    assert_eq!(submission["status"], "passed", "{}", submission);
+    assert!(submission["instructions"].as_i64().unwrap() > 0);
+
+    assert_eq!(app.leaderboard_users("hello-world").await, vec!["alice"]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn wrong_output_stays_off_leaderboard() {
+    let app = TestApp::local().await;
+    let cookie = app.login("bob").await;
+
+    let source = "int main() { return 0; }\n// FAKE_STDOUT=Goodbye\n";
+    let submission = app.submit_challenge(&cookie, "hello-world", source, "c").await;
+    assert_eq!(submission["status"], "failed", "{}", submission);
+    assert_eq!(submission["test_results"][0]["passed"], false);
+
+    assert!(app.leaderboard_users("hello-world").await.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn crashing_worker_fails_job() {
+    let app = TestApp::local().await;
+
+    let (compile_id, _) = app.compile("FAKE_CRASH\n", "c").await;
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+
+    let (job_id, status) = app.execute(result["binary_id"].as_str().unwrap(), "").await;
+    assert_eq!(status["status"], "failed");
+    assert!(status["error"].as_str().unwrap().contains("crashed"));
+
+    // The queue keeps serving jobs after a crash
+    let (compile_id, _) = app.compile(HELLO, "c").await;
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+    let (_, status) = app.execute(result["binary_id"].as_str().unwrap(), "").await;
+    assert_eq!(status["status"], "completed");
+
+    let (code, _) = app.get_json(&format!("/runs/job/{}", job_id)).await;
+    assert_eq!(code, 404);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn submit_with_unknown_binary_is_rejected() {
+    let app = TestApp::local().await;
+
+    let form = reqwest::multipart::Form::new().text("binary_id", "sha256-does-not-exist");
+    let resp = app.client.post(app.url("/submit")).multipart(form).send().await.unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn binary_missing_at_execution_fails_job() {
+    let app = TestApp::local().await;
+    let queue = app.state.queue.as_ref().unwrap();
+
+    let job = job_for("sha256-deleted");
+    let job_id = job.id;
+    queue.submit_job(job).await.unwrap();
+
+    let status = app.wait_for_job(job_id).await;
+    assert_eq!(status["status"], "failed");
+    assert_eq!(status["error"], "Binary not found: sha256-deleted");
+}
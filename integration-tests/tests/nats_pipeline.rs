@@ -0,0 +1,216 @@
+//! End-to-end flows through NATS JetStream with in-process fake workers.
+//!
+//! Needs a Docker daemon for the Postgres and NATS containers:
+//! `cargo test -p integration-tests -- --ignored`
+
+use ctf_common::sandbox_options::SandboxOptions;
+use ctf_common::topology::{Topology, TopologyConfig, TopologyError, JOBS_STREAM, JOBS_SUBJECT};
+use ctf_sandbox_api::{queue::QueueClient, reconciler};
+use integration_tests::{job_for, TestApp, WorkerOptions};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+const HELLO: &str = "int main() { return 0; }\n// FAKE_STDOUT=Hello, World!\\n\n";
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn compile_execute_and_leaderboard_over_nats() {
+    let app = TestApp::nats().await;
+    let _workers = app.start_workers(WorkerOptions::default()).await;
+
+    let (_, health) = app.get_json("/health").await;
+    assert_eq!(health["queue_backend"], "nats");
+
+    let (compile_id, status) = app.compile(HELLO, "c").await;
+    assert_eq!(status["status"], "completed", "{}", status);
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+    let binary_id = result["binary_id"].as_str().unwrap().to_string();
+
+    let (job_id, status) = app.execute(&binary_id, "").await;
+    assert_eq!(status["status"], "completed", "{}", status);
+    let (code, run) = app.get_json(&format!("/runs/job/{}", job_id)).await;
+    assert!(code.is_success(), "run not persisted: {}", run);
+
+    let cookie = app.login("carol").await;
+    let submission = app.submit_challenge(&cookie, "hello-world", HELLO, "c").await;
+    assert_eq!(submission["status"], "passed", "{}", submission);
+    assert_eq!(app.leaderboard_users("hello-world").await, vec!["carol"]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn worker_crash_mid_job_is_redelivered() {
+    let app = TestApp::nats().await;
+    let workers = app.start_workers(WorkerOptions { crash_executions: 1, ..Default::default() }).await;
+
+    let (compile_id, _) = app.compile(HELLO, "c").await;
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+
+    let (job_id, status) = app.execute(result["binary_id"].as_str().unwrap(), "").await;
+    assert_eq!(workers.crashes_remaining.load(Ordering::SeqCst), 0);
+    assert_eq!(status["status"], "completed", "{}", status);
+
+    let (code, _) = app.get_json(&format!("/runs/job/{}", job_id)).await;
+    assert!(code.is_success());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn worker_binary_404_fails_job() {
+    let app = TestApp::nats().await;
+    let _workers = app.start_workers(WorkerOptions::default()).await;
+    let queue = app.state.queue.as_ref().unwrap();
+
+    let job = job_for("sha256-deleted");
+    let job_id = job.id;
+    queue.submit_job(job).await.unwrap();
+
+    let status = app.wait_for_job(job_id).await;
+    assert_eq!(status["status"], "failed");
+    assert_eq!(status["error"], "Binary not found: sha256-deleted");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn topology_drift_requires_migration() {
+    let app = TestApp::nats().await;
+    let client = async_nats::connect(app.nats_url.as_ref().unwrap()).await.unwrap();
+    let js = async_nats::jetstream::new(client);
+
+    let mut config = TopologyConfig::new(app.state.config.job_ttl_seconds + 60, app.state.config.binary_ttl_seconds);
+    config.migrate = false;
+    match Topology::ensure(&js, &config).await {
+        Err(TopologyError::Mismatch { resource, found, .. }) => {
+            assert!(resource.contains(JOBS_STREAM), "{}", resource);
+            assert_eq!(found, Some(ctf_common::topology::TOPOLOGY_VERSION));
+        }
+        Err(e) => panic!("expected mismatch, got {}", e),
+        Ok(_) => panic!("expected mismatch, drift was accepted"),
+    }
+
+    config.migrate = true;
+    let topology = Topology::ensure(&js, &config).await.expect("migration failed");
+    let mut jobs_stream = topology.jobs_stream;
+    let info = jobs_stream.info().await.unwrap();
+    assert_eq!(info.config.max_age, Duration::from_secs(app.state.config.job_ttl_seconds + 60));
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn lost_streams_are_recreated_on_demand() {
+    let app = TestApp::nats().await;
+    let client = async_nats::connect(app.nats_url.as_ref().unwrap()).await.unwrap();
+    let js = async_nats::jetstream::new(client);
+
+    // Simulate a NATS restart without persistence
+    js.delete_stream(JOBS_STREAM).await.unwrap();
+    js.delete_key_value(ctf_common::topology::JOBS_KV).await.unwrap();
+
+    // The API notices on the next call and re-creates the topology
+    let (code, stats) = app.get_json("/queue/stats").await;
+    assert!(code.is_success(), "{}", stats);
+    assert!(js.get_stream(JOBS_STREAM).await.is_ok());
+
+    let _workers = app.start_workers(WorkerOptions::default()).await;
+    let (compile_id, _) = app.compile(HELLO, "c").await;
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+    let (_, status) = app.execute(result["binary_id"].as_str().unwrap(), "").await;
+    assert_eq!(status["status"], "completed", "{}", status);
+
+    let (_, health) = app.get_json("/health").await;
+    assert_eq!(health["queue_healthy"], true);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn reconciler_backfills_lost_runs() {
+    let app = TestApp::nats().await;
+    let _workers = app.start_workers(WorkerOptions { skip_persist: true, ..Default::default() }).await;
+
+    let (compile_id, _) = app.compile(HELLO, "c").await;
+    let (_, result) = app.get_json(&format!("/compile/result/{}", compile_id)).await;
+    let (job_id, status) = app.execute(result["binary_id"].as_str().unwrap(), "").await;
+    assert_eq!(status["status"], "completed");
+
+    let (code, _) = app.get_json(&format!("/runs/job/{}", job_id)).await;
+    assert_eq!(code, 404);
+
+    let Some(QueueClient::Nats(queue)) = app.state.queue.as_ref() else {
+        panic!("expected NATS queue");
+    };
+    let report = reconciler::reconcile(queue, &app.db, Duration::ZERO).await.unwrap();
+    assert_eq!(report.backfilled, 1, "{:?}", report);
+
+    let (code, run) = app.get_json(&format!("/runs/job/{}", job_id)).await;
+    assert!(code.is_success());
+    assert_eq!(run["binary_id"], result["binary_id"]);
+    assert_eq!(run["language"], "c");
+
+    // Second pass finds nothing to do
+    let report = reconciler::reconcile(queue, &app.db, Duration::ZERO).await.unwrap();
+    assert_eq!(report.missing, 0);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn full_compile_queue_returns_429() {
+    let app = TestApp::nats_with(|config| {
+        config.max_compile_queue_depth = 1;
+        config.queue_retry_after_seconds = 7;
+    })
+    .await;
+
+    // No workers, so the first job stays queued
+    let form = reqwest::multipart::Form::new().text("source_code", HELLO).text("language", "c");
+    let resp = app.client.post(app.url("/compile")).multipart(form).send().await.unwrap();
+    assert!(resp.status().is_success());
+
+    let form = reqwest::multipart::Form::new().text("source_code", HELLO).text("language", "c");
+    let resp = app.client.post(app.url("/compile")).multipart(form).send().await.unwrap();
+    assert_eq!(resp.status(), 429);
+    assert_eq!(resp.headers()["retry-after"], "7");
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["queue_depth"], 1);
+    assert_eq!(body["max_queue_depth"], 1);
+
+    let (_, health) = app.get_json("/health").await;
+    assert_eq!(health["queue_saturated"], true);
+    assert_eq!(health["compile_queue_depth"], 1);
+
+    // Execution has its own limit and is unaffected
+    let form = reqwest::multipart::Form::new().text("binary_id", "sha256-missing");
+    let resp = app.client.post(app.url("/submit")).multipart(form).send().await.unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker (testcontainers)"]
+async fn sandbox_options_are_signed_for_workers() {
+    let app = TestApp::nats_with(|config| config.sandbox_options_secret = Some("s3cret".to_string())).await;
+    let queue = app.state.queue.as_ref().unwrap();
+
+    let options = SandboxOptions {
+        capabilities: vec!["DAC_READ_SEARCH".to_string()],
+        ..Default::default()
+    };
+    let mut job = job_for("sha256-privesc");
+    job.sandbox_options = Some(options.clone());
+    let job_id = job.id.to_string();
+    queue.submit_job(job).await.unwrap();
+
+    // Read back what a worker would receive
+    let client = async_nats::connect(app.nats_url.as_ref().unwrap()).await.unwrap();
+    let stream = async_nats::jetstream::new(client).get_stream(JOBS_STREAM).await.unwrap();
+    let message = stream.get_last_raw_message_by_subject(JOBS_SUBJECT).await.unwrap();
+    let published: ctf_sandbox_api::queue::Job = serde_json::from_slice(&message.payload).unwrap();
+    let signature = published.sandbox_signature.expect("options were not signed");
+
+    assert!(options.verify(b"s3cret", &job_id, "sha256-privesc", None, &signature).is_ok());
+    assert!(options.verify(b"wrong", &job_id, "sha256-privesc", None, &signature).is_err());
+    assert!(options.verify(b"s3cret", &job_id, "sha256-other", None, &signature).is_err());
+    let escalated = SandboxOptions {
+        capabilities: vec!["DAC_READ_SEARCH".to_string(), "SYS_PTRACE".to_string()],
+        ..Default::default()
+    };
+    assert!(escalated.verify(b"s3cret", &job_id, "sha256-privesc", None, &signature).is_err());
+}
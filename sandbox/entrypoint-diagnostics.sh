@@ -0,0 +1,28 @@
+#!/bin/sh
+# Diagnostics mode: run the binary natively under valgrind's memcheck, or
+# directly when it was built with -fsanitize=address, then append the tool's
+# log to stderr after a "__CTF_DIAGNOSTICS__ <tool>" line for the worker to
+# parse (ctf_common::diagnostics). No instructions are counted.
+
+REPORT_DIR=$(mktemp -d)
+
+# Same wall-clock handling as the normal entrypoint (exit code 124)
+TIMEOUT=""
+if [ -n "$TIMEOUT_MS" ]; then
+    TIMEOUT="timeout -k 1 $(awk "BEGIN { print $TIMEOUT_MS / 1000 }")"
+fi
+unset TIMEOUT_MS LIMIT
+
+if grep -q __asan_init /work/binary; then
+    TOOL=asan
+    ASAN_OPTIONS="log_path=$REPORT_DIR/report:detect_leaks=1" $TIMEOUT /work/binary "$@"
+else
+    TOOL=valgrind
+    $TIMEOUT valgrind --tool=memcheck --leak-check=full --log-file="$REPORT_DIR/report" /work/binary "$@"
+fi
+EXIT_CODE=$?
+
+printf '\n%s %s\n' "__CTF_DIAGNOSTICS__" "$TOOL" >&2
+cat "$REPORT_DIR"/report* >&2 2>/dev/null
+rm -rf "$REPORT_DIR"
+exit $EXIT_CODE
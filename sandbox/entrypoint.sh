@@ -2,30 +2,88 @@
 # Forward environment variables to QEMU guest binary
 # QEMU uses -E KEY=VALUE to pass env vars to the emulated process
 
-# Build arguments file
+# Build arguments file, NUL-separated so values with spaces or quotes reach
+# QEMU and the binary verbatim
 ARGS_FILE=$(mktemp)
 
 # Base command
-echo "-cpu" >> "$ARGS_FILE"
-echo "max" >> "$ARGS_FILE"
+printf '%s\0' "-cpu" >> "$ARGS_FILE"
+printf '%s\0' "max" >> "$ARGS_FILE"
 
 # Pass selected environment variables to QEMU
 # Only pass FLAG and other user-defined vars, not system vars
 for var in FLAG SECRET_KEY; do
     val=$(printenv "$var" 2>/dev/null)
     if [ -n "$val" ]; then
-        echo "-E" >> "$ARGS_FILE"
-        echo "$var=$val" >> "$ARGS_FILE"
+        printf '%s\0' "-E" >> "$ARGS_FILE"
+        printf '%s\0' "$var=$val" >> "$ARGS_FILE"
     fi
 done
 
+# Script bundles name their interpreter in RUNTIME_ENTRYPOINT, one argument
+# per line (common/src/runtime.rs); QEMU runs it with /work/binary after it
+PROGRAM=/work/binary
+if [ -n "$RUNTIME_ENTRYPOINT" ]; then
+    PROGRAM=$(printf '%s\n' "$RUNTIME_ENTRYPOINT" | head -n 1)
+fi
+
 # Plugin and binary
-echo "-plugin" >> "$ARGS_FILE"
-echo "/plugin/sandbox.so,limit=$LIMIT,binary=/work/binary,from_start=on" >> "$ARGS_FILE"
-echo "/work/binary" >> "$ARGS_FILE"
+printf '%s\0' "-plugin" >> "$ARGS_FILE"
+printf '%s\0' "/plugin/sandbox.so,limit=$LIMIT,binary=$PROGRAM,from_start=on" >> "$ARGS_FILE"
+if [ -n "$RUNTIME_ENTRYPOINT" ]; then
+    printf '%s\n' "$RUNTIME_ENTRYPOINT" | while IFS= read -r arg; do
+        printf '%s\0' "$arg" >> "$ARGS_FILE"
+    done
+fi
+printf '%s\0' "/work/binary" >> "$ARGS_FILE"
+unset RUNTIME_ENTRYPOINT
+
+# The job's arguments (docker run ... IMAGE ARGS) follow the binary
+for arg in "$@"; do
+    printf '%s\0' "$arg" >> "$ARGS_FILE"
+done
+
+# Privilege-escalation challenges: the container starts as root, launches
+# the helper as its own user, then drops to SANDBOX_RUN_AS for the solution.
+# The settings come only from the file the worker mounts for verified
+# sandbox options, never from the job's environment
+unset SANDBOX_HELPER SANDBOX_HELPER_USER SANDBOX_HELPER_STARTUP_MS SANDBOX_RUN_AS
+if [ -f /sandbox/helper.env ]; then
+    while IFS='=' read -r key value; do
+        case "$key" in
+            SANDBOX_HELPER|SANDBOX_HELPER_USER|SANDBOX_HELPER_STARTUP_MS|SANDBOX_RUN_AS)
+                eval "$key=\$value" ;;
+        esac
+    done < /sandbox/helper.env
+fi
+DROP=""
+if [ -n "$SANDBOX_HELPER" ]; then
+    setpriv --reuid="${SANDBOX_HELPER_USER%%:*}" --regid="${SANDBOX_HELPER_USER##*:}" --clear-groups \
+        $SANDBOX_HELPER </dev/null >/dev/null 2>&1 &
+    sleep "$(awk "BEGIN { print ${SANDBOX_HELPER_STARTUP_MS:-200} / 1000 }")"
+fi
+if [ -n "$SANDBOX_RUN_AS" ]; then
+    DROP="setpriv --reuid=${SANDBOX_RUN_AS%%:*} --regid=${SANDBOX_RUN_AS##*:} --clear-groups"
+fi
+unset SANDBOX_HELPER SANDBOX_HELPER_USER SANDBOX_HELPER_STARTUP_MS SANDBOX_RUN_AS
+
+# Wall-clock limit: SIGTERM lets QEMU run the plugin's exit hook so stats
+# are still reported (exit code 124); SIGKILL a second later if ignored
+TIMEOUT=""
+if [ -n "$TIMEOUT_MS" ]; then
+    TIMEOUT="timeout -k 1 $(awk "BEGIN { print $TIMEOUT_MS / 1000 }")"
+fi
+unset TIMEOUT_MS
+
+# Debug runs: QEMU writes the guest's core to its working directory when the
+# binary crashes, so run it from the bind-mounted core dir
+if [ -n "$CORE_DUMP" ]; then
+    cd /work/core || exit 1
+fi
+unset CORE_DUMP
 
 # Execute QEMU with arguments from file
-xargs -a "$ARGS_FILE" qemu-x86_64
+$TIMEOUT xargs -0 -a "$ARGS_FILE" $DROP qemu-x86_64
 EXIT_CODE=$?
 
 rm -f "$ARGS_FILE"
@@ -3,11 +3,14 @@
 #include <string.h>
 #include <inttypes.h>
 #include <elf.h>
+#include <pthread.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
 #include "qemu-plugin.h"
 
 QEMU_PLUGIN_EXPORT int qemu_plugin_version = QEMU_PLUGIN_VERSION;
 
-static uint64_t insn_count;
 static uint64_t insn_limit;
 static bool limit_reached;
 static uint64_t main_offset;   // main address from file (or offset for PIE)
@@ -20,12 +23,54 @@ static bool counting;
 static bool count_from_start;  // if true, count from _start instead of main
 
 // Syscall tracking
-static uint64_t syscall_count;
 static uint64_t syscall_cost;  // Virtual instruction cost per syscall (0 = disabled)
 
 // Track counts for common syscalls (x86_64 syscall numbers)
 #define MAX_TRACKED_SYSCALLS 512
-static uint64_t syscall_counts[MAX_TRACKED_SYSCALLS];
+
+// Process accounting. QEMU user-mode runs a forked guest process in a forked
+// copy of itself, plugin state included, so counters that must span the whole
+// process tree live in a MAP_SHARED page created before the first fork. Each
+// process owns a slot holding its own instruction count; the limit applies to
+// the sum. Only the root process prints stats, covering every process.
+// Threads (clone with CLONE_VM) share their process's slot and additionally
+// get a thread slot of their own for the per-thread split.
+#define MAX_PROCESSES 64
+#define MAX_THREADS 128
+
+struct proc_slot {
+    int32_t pid;
+    int32_t ppid;
+    uint64_t insns;
+    bool exited;
+    bool exec;       // replaced itself via execve; the new image runs uncounted
+};
+
+// Written only by its own thread, read by the root at exit
+struct thread_slot {
+    int32_t pid;
+    int32_t tid;
+    uint64_t insns;
+    uint64_t syscalls;
+    uint64_t syscall_counts[MAX_TRACKED_SYSCALLS];
+};
+
+struct shared_stats {
+    int32_t root_pid;
+    uint32_t nprocs;             // slots handed out (can exceed MAX_PROCESSES)
+    bool limit_reached;
+    uint64_t overflow_insns;     // processes past MAX_PROCESSES count here
+    uint64_t syscall_count;
+    uint64_t syscall_counts[MAX_TRACKED_SYSCALLS];
+    struct proc_slot procs[MAX_PROCESSES];
+    uint32_t nthreads;           // slots handed out (can exceed MAX_THREADS)
+    struct thread_slot threads[MAX_THREADS];
+};
+
+static struct shared_stats *shared;
+static struct proc_slot *self;   // NULL once past MAX_PROCESSES
+static __thread struct thread_slot *cur_thread;  // NULL once past MAX_THREADS
+static __thread bool thread_claimed;
 
 // Guest memory tracking (actual guest allocations via syscalls)
 static uint64_t guest_mmap_bytes = 0;      // Current mmap'd memory
@@ -137,6 +182,66 @@ static const char* syscall_name(int64_t num) {
     return NULL;
 }
 
+static void claim_slot(void)
+{
+    uint32_t idx = __atomic_fetch_add(&shared->nprocs, 1, __ATOMIC_SEQ_CST);
+    self = idx < MAX_PROCESSES ? &shared->procs[idx] : NULL;
+    if (self) {
+        self->pid = getpid();
+        self->ppid = getppid();
+    }
+}
+
+// Guest threads are host threads under QEMU user-mode, so each claims a
+// slot the first time it executes or makes a syscall
+static struct thread_slot *this_thread(void)
+{
+    if (!thread_claimed) {
+        thread_claimed = true;
+        uint32_t idx = __atomic_fetch_add(&shared->nthreads, 1, __ATOMIC_SEQ_CST);
+        cur_thread = idx < MAX_THREADS ? &shared->threads[idx] : NULL;
+        if (cur_thread) {
+            cur_thread->pid = getpid();
+            cur_thread->tid = (int32_t)syscall(SYS_gettid);
+        }
+    }
+    return cur_thread;
+}
+
+// Runs in the child after QEMU forks for a guest fork/vfork/clone
+static void on_fork_child(void)
+{
+    // The parent's instructions stay in the parent's slots
+    claim_slot();
+    thread_claimed = false;
+}
+
+static uint64_t total_insns(void)
+{
+    uint32_t nprocs = __atomic_load_n(&shared->nprocs, __ATOMIC_RELAXED);
+    if (nprocs == 1) return __atomic_load_n(&shared->procs[0].insns, __ATOMIC_RELAXED);
+    uint64_t total = __atomic_load_n(&shared->overflow_insns, __ATOMIC_RELAXED);
+    for (uint32_t i = 0; i < nprocs && i < MAX_PROCESSES; i++) {
+        total += __atomic_load_n(&shared->procs[i].insns, __ATOMIC_RELAXED);
+    }
+    return total;
+}
+
+static void add_insns(uint64_t n)
+{
+    struct thread_slot *t = this_thread();
+    if (t) {
+        __atomic_store_n(&t->insns, t->insns + n, __ATOMIC_RELAXED);
+    }
+    uint64_t *counter = self ? &self->insns : &shared->overflow_insns;
+    __atomic_fetch_add(counter, n, __ATOMIC_RELAXED);
+    if (insn_limit && total_insns() >= insn_limit) {
+        limit_reached = true;
+        shared->limit_reached = true;
+        exit(137);
+    }
+}
+
 static void parse_elf(const char *path)
 {
     FILE *f = fopen(path, "rb");
@@ -220,9 +325,22 @@ static void vcpu_syscall(qemu_plugin_id_t id, unsigned int vcpu_index,
     // (counting should already be true, but syscalls might fire before first TB)
     if (!counting && !count_from_start) return;
 
-    syscall_count++;
+    struct thread_slot *t = this_thread();
+    __atomic_fetch_add(&shared->syscall_count, 1, __ATOMIC_RELAXED);
+    if (t) {
+        __atomic_store_n(&t->syscalls, t->syscalls + 1, __ATOMIC_RELAXED);
+    }
     if (num >= 0 && num < MAX_TRACKED_SYSCALLS) {
-        syscall_counts[num]++;
+        __atomic_fetch_add(&shared->syscall_counts[num], 1, __ATOMIC_RELAXED);
+        if (t) {
+            __atomic_store_n(&t->syscall_counts[num], t->syscall_counts[num] + 1, __ATOMIC_RELAXED);
+        }
+    }
+
+    // execve/execveat: QEMU hands the new image to the host kernel, so it
+    // runs natively and its instructions are not counted
+    if ((num == 59 || num == 322) && self) {
+        self->exec = true;
     }
 
     // Track guest memory allocations
@@ -246,17 +364,18 @@ static void vcpu_syscall(qemu_plugin_id_t id, unsigned int vcpu_index,
 
     // Add virtual cost for syscalls if enabled
     if (syscall_cost > 0) {
-        insn_count += syscall_cost;
-        if (insn_limit && insn_count >= insn_limit) {
-            limit_reached = true;
-            exit(137);
-        }
+        add_insns(syscall_cost);
     }
 }
 
 static void vcpu_syscall_ret(qemu_plugin_id_t id, unsigned int vcpu_index,
                               int64_t num, int64_t ret)
 {
+    // A failed exec leaves the process running under QEMU
+    if ((num == 59 || num == 322) && ret < 0 && self) {
+        self->exec = false;
+    }
+
     // Track brk return values to measure heap growth
     if (num == 12 && ret > 0) {  // brk returns new brk address (or current if arg was 0)
         uint64_t new_brk = (uint64_t)ret;
@@ -270,8 +389,31 @@ static void vcpu_syscall_ret(qemu_plugin_id_t id, unsigned int vcpu_index,
     }
 }
 
+// Non-zero counts as JSON object members
+static void print_breakdown(FILE *out, uint64_t *counts)
+{
+    bool first = true;
+    for (int i = 0; i < MAX_TRACKED_SYSCALLS; i++) {
+        uint64_t count = __atomic_load_n(&counts[i], __ATOMIC_RELAXED);
+        if (count == 0) continue;
+        const char *name = syscall_name(i);
+        if (name) {
+            fprintf(out, "%s\"%s\": %" PRIu64, first ? "" : ", ", name, count);
+        } else {
+            fprintf(out, "%s\"sys_%d\": %" PRIu64, first ? "" : ", ", i, count);
+        }
+        first = false;
+    }
+}
+
 static void plugin_exit(qemu_plugin_id_t id, void *p)
 {
+    if (self) {
+        __atomic_store_n(&self->exited, true, __ATOMIC_RELEASE);
+    }
+    // Children report through their slots; the root prints for the tree
+    if (getpid() != shared->root_pid) return;
+
     uint64_t vm_peak_kb = 0;
     uint64_t vm_rss_kb = 0;
     uint64_t vm_hwm_kb = 0;
@@ -313,53 +455,69 @@ static void plugin_exit(qemu_plugin_id_t id, void *p)
         fclose(io);
     }
 
-    // Build syscall breakdown for non-zero counts
-    char syscall_breakdown[4096] = "";
-    int offset = 0;
-    bool first = true;
-    for (int i = 0; i < MAX_TRACKED_SYSCALLS && offset < 4000; i++) {
-        if (syscall_counts[i] > 0) {
-            const char *name = syscall_name(i);
-            if (name) {
-                offset += snprintf(syscall_breakdown + offset, sizeof(syscall_breakdown) - offset,
-                                   "%s\"%s\": %" PRIu64, first ? "" : ", ", name, syscall_counts[i]);
-            } else {
-                offset += snprintf(syscall_breakdown + offset, sizeof(syscall_breakdown) - offset,
-                                   "%s\"sys_%d\": %" PRIu64, first ? "" : ", ", i, syscall_counts[i]);
-            }
-            first = false;
-        }
-    }
-
     // Calculate guest heap size from brk
     uint64_t guest_heap_bytes = 0;
     if (guest_brk_initialized && guest_brk_current > guest_brk_base) {
         guest_heap_bytes = guest_brk_current - guest_brk_base;
     }
 
-    fprintf(stderr, "\n{\"instructions\": %" PRIu64 ", \"memory_peak_kb\": %" PRIu64
+    // Built in memory and written at once so the line isn't interleaved
+    // with output from processes that are still running
+    char *json = NULL;
+    size_t json_len = 0;
+    FILE *out = open_memstream(&json, &json_len);
+    if (!out) return;
+
+    fprintf(out, "\n{\"instructions\": %" PRIu64 ", \"memory_peak_kb\": %" PRIu64
             ", \"memory_rss_kb\": %" PRIu64 ", \"memory_hwm_kb\": %" PRIu64
             ", \"memory_data_kb\": %" PRIu64 ", \"memory_stack_kb\": %" PRIu64
             ", \"io_read_bytes\": %" PRIu64 ", \"io_write_bytes\": %" PRIu64
             ", \"guest_mmap_bytes\": %" PRIu64 ", \"guest_mmap_peak\": %" PRIu64
             ", \"guest_heap_bytes\": %" PRIu64
             ", \"limit_reached\": %s, \"syscalls\": %" PRIu64
-            ", \"syscall_cost\": %" PRIu64
-            ", \"syscall_breakdown\": {%s}}\n",
-            insn_count, vm_peak_kb, vm_rss_kb, vm_hwm_kb, vm_data_kb, vm_stk_kb,
+            ", \"syscall_cost\": %" PRIu64 ", \"syscall_breakdown\": {",
+            total_insns(), vm_peak_kb, vm_rss_kb, vm_hwm_kb, vm_data_kb, vm_stk_kb,
             io_read_bytes, io_write_bytes, guest_mmap_bytes, guest_mmap_peak,
-            guest_heap_bytes, limit_reached ? "true" : "false",
-            syscall_count, syscall_cost, syscall_breakdown);
+            guest_heap_bytes, (limit_reached || shared->limit_reached) ? "true" : "false",
+            __atomic_load_n(&shared->syscall_count, __ATOMIC_RELAXED), syscall_cost);
+    print_breakdown(out, shared->syscall_counts);
+
+    // Per-process instruction split, in fork order (root first)
+    uint32_t nprocs = __atomic_load_n(&shared->nprocs, __ATOMIC_ACQUIRE);
+    fprintf(out, "}, \"process_count\": %" PRIu32 ", \"processes\": [", nprocs);
+    for (uint32_t i = 0; i < nprocs && i < MAX_PROCESSES; i++) {
+        struct proc_slot *slot = &shared->procs[i];
+        fprintf(out, "%s{\"pid\": %d, \"ppid\": %d, \"instructions\": %" PRIu64
+                ", \"exited\": %s, \"exec\": %s}",
+                i ? ", " : "", slot->pid, slot->ppid,
+                __atomic_load_n(&slot->insns, __ATOMIC_RELAXED),
+                __atomic_load_n(&slot->exited, __ATOMIC_ACQUIRE) ? "true" : "false",
+                slot->exec ? "true" : "false");
+    }
+
+    // Per-thread split, in creation order across the whole tree
+    uint32_t nthreads = __atomic_load_n(&shared->nthreads, __ATOMIC_ACQUIRE);
+    fprintf(out, "], \"thread_count\": %" PRIu32 ", \"threads\": [", nthreads);
+    for (uint32_t i = 0; i < nthreads && i < MAX_THREADS; i++) {
+        struct thread_slot *slot = &shared->threads[i];
+        fprintf(out, "%s{\"pid\": %d, \"tid\": %d, \"instructions\": %" PRIu64
+                ", \"syscalls\": %" PRIu64 ", \"syscall_breakdown\": {",
+                i ? ", " : "", slot->pid, slot->tid,
+                __atomic_load_n(&slot->insns, __ATOMIC_RELAXED),
+                __atomic_load_n(&slot->syscalls, __ATOMIC_RELAXED));
+        print_breakdown(out, slot->syscall_counts);
+        fputs("}}", out);
+    }
+    fputs("]}\n", out);
+
+    fclose(out);
+    fwrite(json, 1, json_len, stderr);
+    free(json);
 }
 
 static void vcpu_tb_exec(unsigned int cpu_index, void *udata)
 {
-    uint64_t n = (uint64_t)udata;
-    insn_count += n;
-    if (insn_limit && insn_count >= insn_limit) {
-        limit_reached = true;
-        exit(137);
-    }
+    add_insns((uint64_t)udata);
 }
 
 static void vcpu_tb_trans(qemu_plugin_id_t id, struct qemu_plugin_tb *tb)
@@ -420,6 +578,16 @@ QEMU_PLUGIN_EXPORT int qemu_plugin_install(qemu_plugin_id_t id,
         start_addr = main_offset;  // Non-PIE: use address directly
     }
 
+    shared = mmap(NULL, sizeof(*shared), PROT_READ | PROT_WRITE,
+                  MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    if (shared == MAP_FAILED) {
+        fprintf(stderr, "sandbox plugin: cannot map shared stats\n");
+        return -1;
+    }
+    shared->root_pid = getpid();
+    claim_slot();
+    pthread_atfork(NULL, NULL, on_fork_child);
+
     qemu_plugin_register_vcpu_tb_trans_cb(id, vcpu_tb_trans);
     qemu_plugin_register_vcpu_syscall_cb(id, vcpu_syscall);
     qemu_plugin_register_vcpu_syscall_ret_cb(id, vcpu_syscall_ret);
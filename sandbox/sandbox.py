@@ -29,6 +29,11 @@ class Result:
     guest_mmap_bytes: int = 0
     guest_mmap_peak: int = 0
     guest_heap_bytes: int = 0
+    # Forked children are counted too; instructions is the sum over processes
+    process_count: int = 1
+    processes: list = None
+    thread_count: int = 1
+    threads: list = None
 
 
 def run(
@@ -92,6 +97,10 @@ def run(
         guest_mmap_bytes=stats.get("guest_mmap_bytes", 0),
         guest_mmap_peak=stats.get("guest_mmap_peak", 0),
         guest_heap_bytes=stats.get("guest_heap_bytes", 0),
+        process_count=stats.get("process_count", 1),
+        processes=stats.get("processes", []),
+        thread_count=stats.get("thread_count", 1),
+        threads=stats.get("threads", []),
     )
 
 
@@ -111,7 +120,16 @@ if __name__ == "__main__":
     print(f"  mmap peak: {result.guest_mmap_peak} bytes")
     print(f"  heap (brk): {result.guest_heap_bytes} bytes")
     print(f"Limit reached: {result.limit_reached}")
+    if result.process_count > 1:
+        print(f"Processes: {result.process_count}")
+        for p in result.processes:
+            note = " (exec'd, rest uncounted)" if p["exec"] else "" if p["exited"] else " (still running)"
+            print(f"  pid {p['pid']} (ppid {p['ppid']}): {p['instructions']} instructions{note}")
     print(f"Syscalls: {result.syscalls}")
+    if result.thread_count > 1:
+        print(f"Threads: {result.thread_count}")
+        for t in result.threads:
+            print(f"  tid {t['tid']} (pid {t['pid']}): {t['instructions']} instructions, {t['syscalls']} syscalls")
     if result.syscall_breakdown:
         print(f"Syscall breakdown:")
         for name, count in sorted(result.syscall_breakdown.items(), key=lambda x: -x[1]):
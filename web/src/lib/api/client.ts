@@ -2,10 +2,29 @@
 
 const API_BASE = '/api';
 
+export interface ErrorCatalogEntry {
+	code: string;
+	status: number;
+	retryable: boolean;
+	description: string;
+}
+
 export interface CompileSubmitResponse {
 	compile_job_id: string;
 	status: string;
 	position: number | null;
+	// The language as written and the runtime it's built for
+	language: string;
+	runtime?: JsRuntime;
+	// Deprecated names used in the request
+	warnings?: string[];
+}
+
+export interface ImportGistResponse extends CompileSubmitResponse {
+	gist_id: string;
+	file: string;
+	// The imported source, for loading into the editor
+	source_code: string;
 }
 
 export interface CompileStatusResponse {
@@ -23,6 +42,35 @@ export interface CompileResultResponse {
 	binary_size: number;
 	compile_time_ms: number;
 	cached: boolean;
+	build_cache?: BuildCacheUsage;
+	// Compiler warnings, for images that report them
+	diagnostics?: CompileDiagnostic[];
+	// CPU time and peak memory of the build, for images that report them
+	resources?: CompileResources;
+}
+
+export interface CompileResources {
+	cpu_seconds: number;
+	peak_memory_bytes: number;
+	oom_killed: boolean;
+	memory_limit_mb?: number;
+	cpus?: number;
+}
+
+export interface CompileDiagnostic {
+	level: 'error' | 'warning' | 'note';
+	message: string;
+	file?: string;
+	line?: number;
+	column?: number;
+}
+
+export interface BuildCacheUsage {
+	kind: 'sccache' | 'go' | 'jvm';
+	warm: boolean;
+	hits?: number;
+	misses?: number;
+	size_bytes: number;
 }
 
 export interface SubmitResponse {
@@ -41,6 +89,14 @@ export interface StatusResponse {
 	error: string | null;
 }
 
+export type LimitReason = 'instructions' | 'wall_clock' | 'memory';
+
+export const LIMIT_REASON_LABELS: Record<LimitReason, string> = {
+	instructions: 'Instruction limit reached',
+	wall_clock: 'Timed out',
+	memory: 'Out of memory'
+};
+
 export interface ExecutionResult {
 	instructions: number;
 	memory_peak_kb: number;
@@ -55,12 +111,46 @@ export interface ExecutionResult {
 	guest_mmap_peak?: number;
 	guest_heap_bytes?: number;
 	limit_reached: boolean;
+	limit_reached_reason?: LimitReason;
+	oom_killed?: boolean;
+	// Set when the program crashed on a signal, e.g. 'SIGSEGV'
+	crash_signal?: string;
+	memory_limit_mb?: number;
 	exit_code: number;
 	stdout: string; // base64 encoded
 	stderr: string; // base64 encoded
 	execution_time_ms: number;
 	syscalls: number;
 	syscall_breakdown: Record<string, number>;
+	output_files?: OutputFile[];
+	// The crash's core, when submitted with coreDump
+	core_dump?: OutputFile;
+	// valgrind/ASan findings, for mode 'diagnostics'
+	diagnostics?: DiagnosticsReport;
+}
+
+export type ExecutionMode = 'normal' | 'diagnostics';
+
+export interface MemoryError {
+	kind: string;
+	message: string;
+	frames?: string[];
+}
+
+export interface DiagnosticsReport {
+	tool: 'valgrind' | 'asan';
+	errors: MemoryError[];
+	error_count: number;
+	leaked_bytes: number;
+}
+
+// A file the job declared under /work/out
+export interface OutputFile {
+	path: string;
+	size: number;
+	binary_id?: string;
+	// missing, not_a_file, too_large, unreadable or store_failed
+	error?: string;
 }
 
 export interface BinaryMetadata {
@@ -68,6 +158,88 @@ export interface BinaryMetadata {
 	optimization?: string;
 	compiler_version?: string;
 	compile_flags?: Record<string, string>;
+	compiler_image?: string;
+	toolchain?: string;
+}
+
+export interface ToolchainInfo {
+	name: string;
+	description: string;
+	default: boolean;
+}
+
+export interface LanguageInfo {
+	id: Language;
+	// The language as written; differs from id for bun, deno and node
+	language: string;
+	runtime?: JsRuntime;
+	deprecated?: string;
+	extension: string;
+	// Empty when the compiler image has only one
+	toolchains: ToolchainInfo[];
+}
+
+// Linking control for asm, c and cpp
+// What an organization lets its members compile; a missing list allows everything
+export interface OrgPolicy {
+	languages?: string[];
+	optimizations?: Optimization[];
+	flags?: string[];
+	build_options: boolean;
+	toolchains: boolean;
+}
+
+export interface OrgMember {
+	username: string;
+	role: 'admin' | 'member';
+	created_at: string;
+}
+
+export interface Organization {
+	slug: string;
+	name: string;
+	policy: OrgPolicy;
+	members: OrgMember[];
+}
+
+export interface BuildOptions {
+	// Allowlisted ld arguments with attached values, e.g. '--entry=main', '-znoexecstack'
+	linker_flags?: string[];
+	linker_script?: string;
+}
+
+export interface SizeReport {
+	tool: string;
+	file_bytes: number;
+	sections: { name: string; size: number }[];
+	symbol_count: number;
+	// Largest first; empty for stripped binaries
+	symbols: { name: string; size: number; type: string }[];
+}
+
+// A stored binary as listed by GET /binaries (no payload)
+export interface BinarySummary {
+	id: string;
+	size: number;
+	language?: string;
+	optimization?: string;
+	compiler_version?: string;
+	compiler_image?: string;
+	toolchain?: string;
+	has_size_report: boolean;
+	// Saved runs that executed it
+	run_count: number;
+	created_at?: string;
+}
+
+export interface ListBinariesParams {
+	language?: string;
+	// 'me' (the default) or a username; other users need admin
+	user?: string;
+	// RFC 3339
+	since?: string;
+	limit?: number;
+	offset?: number;
 }
 
 export interface RunDetails {
@@ -77,8 +249,10 @@ export interface RunDetails {
 	binary_size?: number;
 	source_code?: string;
 	language?: string;
+	runtime?: JsRuntime;
 	optimization?: string;
 	compiler_version?: string;
+	toolchain?: string;
 	compile_time_ms?: number;
 	compile_cached?: boolean;
 	instructions: number;
@@ -94,30 +268,192 @@ export interface RunDetails {
 	guest_mmap_peak?: number;
 	guest_heap_bytes?: number;
 	limit_reached: boolean;
+	limit_reached_reason?: LimitReason;
 	exit_code?: number;
 	execution_time_ms?: number;
 	instruction_limit?: number;
+	memory_limit_mb?: number;
+	sandbox_image?: string;
+	args?: string[] | null;
+	// Set when a core was kept; download from getRunCoreUrl (owner only)
+	core_dump_id?: string | null;
+	mode?: ExecutionMode | null;
 	syscalls?: number;
 	syscall_breakdown?: Record<string, number>;
 	stdout?: string;
 	stderr?: string;
 	benchmark_id?: string;
+	// Benchmark version the run was checked against
+	benchmark_version?: number | null;
 	created_at: string;
 	started_at?: string;
 	completed_at?: string;
+	user_id?: string;
+	visibility: RunVisibility;
+	star_count: number;
+	// External worker that reported the run; such runs are never ranked
+	worker_id?: string | null;
 }
 
+export type RunVisibility = 'public' | 'private';
+
 export interface BenchmarkStats {
+	version: number;
 	min_instructions: Record<string, number>;
 }
 
 export interface HealthResponse {
 	status: string;
 	docker_available: boolean;
+	direct_execution: boolean;
 	nats_connected: boolean;
 	db_connected: boolean;
 }
 
+export interface ImageInfo {
+	image: string;
+	digest: string | null;
+}
+
+export interface VersionResponse {
+	api: {
+		version: string;
+		git_sha: string | null;
+		profile: 'release' | 'debug';
+		features: string[];
+	};
+	sandbox: ImageInfo;
+	compiler: ImageInfo;
+	queue_backend: 'nats' | 'local' | null;
+	nats_version: string | null;
+	postgres_version: string | null;
+	schema: {
+		api_version: string;
+		git_sha: string | null;
+		migrated_at: string;
+	} | null;
+}
+
+export interface StatusCount {
+	status: string;
+	count: number;
+}
+
+export interface ConsumerStats {
+	pending: number;
+	in_flight: number;
+	waiting_pulls: number;
+	redelivered: number;
+	last_delivery: string | null;
+}
+
+export interface PurgeRunsResponse {
+	deleted: number;
+	anonymized: number;
+	dry_run: boolean;
+}
+
+export interface RetentionReport {
+	policy: {
+		interval_seconds: number;
+		run_retention_days: number;
+		run_output_retention_days: number;
+	};
+	removed: {
+		table_name: string;
+		action: 'deleted' | 'trimmed';
+		last_pass_rows: number;
+		total_rows: number;
+		last_pass_at: string;
+	}[];
+	tables: { table: string; total_bytes: number; live_rows: number }[];
+}
+
+export type SignatureStatus = 'valid' | 'invalid' | 'unsigned' | 'other_key' | 'unverifiable';
+
+export interface ChallengeProvenanceReport {
+	// ID of the API's CHALLENGE_SIGNING_KEY, if set
+	signing_key_id: string | null;
+	challenges: {
+		id: string;
+		name: string;
+		is_active: boolean;
+		imported_by: string | null;
+		imported_at: string | null;
+		signing_key_id: string | null;
+		signature_status: SignatureStatus;
+	}[];
+}
+
+export type WorkerIsolation = 'container' | 'gvisor' | 'vm';
+
+export interface ExternalWorker {
+	id: string;
+	name: string;
+	created_by: string | null;
+	created_at: string;
+	revoked_at: string | null;
+	// Declared at registration; null until the worker first registers
+	arch: string | null;
+	isolation: WorkerIsolation | null;
+	version: string | null;
+	registered_at: string | null;
+	last_seen_at: string | null;
+	runs_reported: number;
+	// From the last heartbeat; null for workers without a binary cache
+	binary_cache: BinaryCacheStats | null;
+}
+
+export interface BinaryCacheStats {
+	entries: number;
+	bytes: number;
+	max_bytes: number;
+	hits: number;
+	misses: number;
+	evictions: number;
+	invalid: number;
+	prefetched: number;
+}
+
+export interface BinaryTransferStats {
+	transfers: number;
+	compressed: number;
+	raw_bytes: number;
+	wire_bytes: number;
+	saved_bytes: number;
+}
+
+export interface AdminOverview {
+	since: string;
+	jobs: {
+		runs: StatusCount[];
+		challenge_submissions: StatusCount[];
+	};
+	compile_failure_rates: {
+		language: string;
+		finished: number;
+		failed: number;
+		failure_rate: number;
+	}[];
+	top_submitters: { username: string; submissions: number; passed: number }[];
+	slowest_challenges: { challenge_id: string; submissions: number; avg_ms: number; p95_ms: number }[];
+	storage: { table: string; total_bytes: number; live_rows: number }[];
+	binary_storage: { binaries: number; compressed: number; raw_bytes: number; stored_bytes: number };
+	binary_transfer: { uploads: BinaryTransferStats; downloads: BinaryTransferStats };
+	queue: {
+		backend: 'nats' | 'local' | null;
+		healthy: boolean;
+		queue_depth: number | null;
+		compile_queue_depth: number | null;
+		fleet: {
+			workers: ConsumerStats | null;
+			shared_workers: ConsumerStats | null;
+			compile_workers: ConsumerStats | null;
+			spooled_runs: number | null;
+		} | null;
+	};
+}
+
 // ============ Auth Types ============
 
 export interface PublicUser {
@@ -130,10 +466,61 @@ export interface PublicUser {
 	is_verified: boolean;
 	user_type: 'human' | 'clanker';
 	created_at: string;
+	country: string | null;
+	affiliation: string | null;
+}
+
+export interface UpdateProfileRequest {
+	display_name?: string;
+	bio?: string;
+	twitter_handle?: string;
+	country?: string;
+	affiliation?: string;
+}
+
+export interface UsageDay {
+	day: string;
+	api_calls: number;
+	compiles: number;
+	compile_cache_hits: number;
+	compile_ms: number;
+	runs: number;
+	execute_ms: number;
+}
+
+export interface UsageAnalytics {
+	period_days: number;
+	since: string;
+	totals: {
+		api_calls: number;
+		compiles: number;
+		compile_cache_hits: number;
+		compile_cache_hit_rate: number | null;
+		compile_minutes: number;
+		runs: number;
+		execute_seconds: number;
+	};
+	daily: UsageDay[];
+}
+
+export interface CountryStanding {
+	rank: number;
+	country: string;
+	players: number;
+	total_score: number;
+	average_score: number;
+	top_player: PublicUser;
+}
+
+export interface InstructionLimits {
+	standing: 'unverified' | 'verified' | 'admin';
+	default: number;
+	max: number;
 }
 
 export interface AuthMeResponse {
 	user: PublicUser;
+	instruction_limit: InstructionLimits;
 }
 
 // ============ Challenge Types ============
@@ -145,6 +532,11 @@ export interface ChallengeInfo {
 	category: string;
 	difficulty: string;
 	is_active: boolean;
+	points?: number;
+	star_count: number;
+	// Availability window; not-yet-open challenges are only listed for admins
+	starts_at?: string;
+	ends_at?: string;
 }
 
 export interface ChallengeListResponse {
@@ -154,6 +546,8 @@ export interface ChallengeListResponse {
 export interface PublicTestCase {
 	description: string | null;
 	stdin: string;
+	args?: string[];
+	outputs?: string[];
 }
 
 export interface ChallengeBaseline {
@@ -175,30 +569,339 @@ export interface ChallengeDetail {
 	test_cases: PublicTestCase[];
 	verify_mode: string;
 	baselines?: ChallengeBaseline[];
+	binary_checks?: BinaryChecks;
+	languages?: LanguageRestrictions;
+	scoring?: ChallengeScoring;
+	solve_count: number;
+	points?: number;
+	star_count: number;
+	hints?: ChallengeHint[];
+	starts_at?: string;
+	ends_at?: string;
+	run_all_tests: boolean;
+}
+
+export interface ChallengeHint {
+	index: number;
+	cost: number;
+	unlocked: boolean;
+	text?: string;
+}
+
+export interface ChallengeScoring {
+	type: 'decay';
+	initial: number;
+	minimum: number;
+	decay: number;
+	curve?: 'quadratic' | 'linear';
+}
+
+export interface LanguageRestrictions {
+	allow?: Language[];
+	deny?: Language[];
+}
+
+export interface BinaryChecks {
+	max_binary_size?: number;
+	require_static: boolean;
+	forbidden_sections: string[];
+	forbidden_symbols: string[];
 }
 
 export interface ChallengeSubmitResponse {
 	submission_id: string;
 	status: string;
+	ranked: boolean;
 }
 
+export interface QueuedSubmission {
+	submission_id: string;
+	status: 'pending' | 'compiling' | 'running';
+	position: number;
+	created_at: string;
+}
+
+export interface ChallengeQueueResponse {
+	challenge_id: string;
+	in_flight: number;
+	ahead: number;
+	avg_processing_ms: number | null;
+	processed_last_day: number;
+	mine: QueuedSubmission[];
+}
+
+export interface DiffLine {
+	number: number;
+	text: string;
+	newline: boolean;
+	truncated?: boolean;
+}
+
+export interface OutputDiff {
+	line: number;
+	expected: DiffLine[];
+	actual: DiffLine[];
+}
+
+export type DiffWhitespace = 'raw' | 'escaped' | 'visible';
+
 export interface TestResult {
 	test_index: number;
 	passed: boolean;
 	expected_preview: string | null;
 	actual_preview: string | null;
+	first_mismatch?: { line: number; column: number };
+	diff?: OutputDiff;
 	error: string | null;
+	skipped?: boolean;
+	cached?: boolean;
 }
 
 export interface SubmissionStatusResponse {
 	submission_id: string;
 	status: 'pending' | 'compiling' | 'running' | 'passed' | 'failed';
+	retry_of?: string;
+	ranked: boolean;
 	test_results: TestResult[] | null;
 	instructions: number | null;
 	error_message: string | null;
 	completed_at: string | null;
 }
 
+export interface Writeup {
+	id: string;
+	user: PublicUser;
+	content: string;
+	hidden: boolean;
+	moderation_note?: string;
+	created_at: string;
+	updated_at: string;
+}
+
+export interface SavedWriteup {
+	id: string;
+	challenge_id: string;
+	user_id: string;
+	content: string;
+	hidden: boolean;
+	moderation_note: string | null;
+	created_at: string;
+	updated_at: string;
+}
+
+export interface WriteupListResponse {
+	writeups: Writeup[];
+	visible_at?: string;
+}
+
+export interface RunComment {
+	id: string;
+	user: PublicUser;
+	content: string;
+	/** Written by the run's owner */
+	is_note: boolean;
+	hidden: boolean;
+	moderation_note?: string;
+	created_at: string;
+	updated_at: string;
+}
+
+export interface SavedRunComment {
+	id: string;
+	run_id: string;
+	user_id: string;
+	content: string;
+	hidden: boolean;
+	moderation_note: string | null;
+	created_at: string;
+	updated_at: string;
+}
+
+// ============ Star Types ============
+
+export interface StarResponse {
+	starred: boolean;
+	star_count: number;
+}
+
+export interface MyStarsResponse {
+	runs: RunDetails[];
+	challenges: ChallengeInfo[];
+}
+
+// One scheduled re-run; either instructions or error is set
+export interface TrackedSolutionRun {
+	run_id: string | null;
+	benchmark_version: number;
+	compiler_version: string | null;
+	instructions: number | null;
+	error: string | null;
+	created_at: string;
+}
+
+export interface TrackedSolution {
+	benchmark_id: string;
+	language: string;
+	optimization: string | null;
+	source_code: string;
+	next_run_at: string;
+	created_at: string;
+	// Newest first; only the latest from listTrackedSolutions
+	history: TrackedSolutionRun[];
+}
+
+// One push a repo link ran; either instructions or error is set
+export interface RepoPush {
+	commit_sha: string;
+	run_id: string | null;
+	submission_id: string | null;
+	instructions: number | null;
+	error: string | null;
+	created_at: string;
+}
+
+export interface RepoLink {
+	id: string;
+	repo: string;
+	path: string;
+	// null runs pushes to the repo's default branch
+	branch: string | null;
+	language: string;
+	optimization: string | null;
+	benchmark_id: string | null;
+	challenge_id: string | null;
+	created_at: string;
+	// Newest first
+	history: RepoPush[];
+}
+
+export interface LinkRepoRequest {
+	repo: string;
+	path: string;
+	branch?: string;
+	language: string;
+	optimization?: string;
+	// Exactly one of these
+	benchmark_id?: string;
+	challenge_id?: string;
+}
+
+// ============ Public Stats Types ============
+
+export interface PublicStatsSummary {
+	total_runs: number;
+	active_users_this_week: number;
+	total_stars: number;
+	refreshed_at: string;
+}
+
+export interface PublicRunsPerDay {
+	days: { day: string; total: number; languages: Record<string, number> }[];
+	refreshed_at: string;
+}
+
+export interface PublicHelloWorld {
+	languages: Record<string, number>;
+	refreshed_at: string;
+}
+
+export interface CompositeScore {
+	language: string;
+	// Geometric mean of best/fastest across benchmarks; 1.0 is the fastest everywhere
+	score: number;
+	benchmarks: number;
+}
+
+export interface CompositeScores {
+	benchmarks: string[];
+	languages: CompositeScore[];
+	refreshed_at: string;
+}
+
+// ============ Digest Types ============
+
+export interface DigestEntry {
+	target: string;
+	language: string;
+	instructions: number;
+	previous_best: number | null;
+	username: string | null;
+}
+
+export interface SandboxImage {
+	image: string;
+	digest: string | null;
+}
+
+export interface SandboxManifest {
+	api_version: string;
+	sandbox: SandboxImage;
+	compiler: SandboxImage;
+	compiler_image_allowlist: string[];
+	docker_run_args: string[];
+	limits: {
+		default_instruction_limit: number;
+		max_instruction_limit: number;
+		memory_limit_mb: number;
+		min_memory_limit_mb: number;
+		max_memory_limit_mb: number;
+		timeout_ms: number;
+		compile_timeout_sec: number;
+		compile_memory_limit_mb: number;
+	};
+	cost_model: {
+		unit: string;
+		emulator: string;
+		plugin: string;
+		plugin_args: string;
+		syscall_cost: number;
+		description: string;
+	};
+}
+
+export interface DailyDigest {
+	day: string;
+	benchmarks: DigestEntry[];
+	challenges: DigestEntry[];
+	improvements: (DigestEntry & { kind: 'benchmark' | 'challenge'; percent: number })[];
+}
+
+// ============ Notification Types ============
+
+export type NotificationKind =
+	| 'record_beaten'
+	| 'submission_finished'
+	| 'challenge_published'
+	| 'tracked_solution';
+
+export interface Notification {
+	id: string;
+	kind: NotificationKind;
+	title: string;
+	body: string;
+	link: string | null;
+	read_at: string | null;
+	created_at: string;
+}
+
+export interface NotificationListResponse {
+	notifications: Notification[];
+	unread_count: number;
+}
+
+export interface ChannelPreference {
+	web: boolean;
+	email: boolean;
+}
+
+export interface NotificationPreferences {
+	email?: string;
+	record_beaten: ChannelPreference;
+	submission_finished: ChannelPreference;
+	challenge_published: ChannelPreference;
+	tracked_solution: ChannelPreference;
+}
+
 // ============ Leaderboard Types ============
 
 export interface LeaderboardEntry {
@@ -209,6 +912,22 @@ export interface LeaderboardEntry {
 	submitted_at: string;
 }
 
+export interface LanguageLeaderboardEntry {
+	language: string;
+	user: PublicUser;
+	instructions: number;
+	submitted_at: string;
+	entries: number;
+	delta: number;
+	ratio: number;
+}
+
+export interface LanguageLeaderboardResponse {
+	challenge_id: string;
+	best_instructions: number | null;
+	languages: LanguageLeaderboardEntry[];
+}
+
 export interface GlobalLeaderboardEntry {
 	rank: number;
 	user: PublicUser;
@@ -221,6 +940,7 @@ export interface UserStats {
 	challenges_completed: number;
 	total_entries: number;
 	first_places: number;
+	stars_received: number;
 	entries: Array<{
 		id: string;
 		challenge_id: string;
@@ -262,6 +982,11 @@ export type Language =
 	| 'swift'
 	| 'haskell'
 	| 'csharp'
+	| 'fortran'
+	| 'd'
+	| 'crystal'
+	| 'v'
+	| 'ada'
 	| 'java'
 	| 'kotlin'
 	| 'scala'
@@ -283,7 +1008,51 @@ export type Language =
 
 export type Optimization = 'debug' | 'release' | 'size';
 
+export type JsRuntime = 'bun' | 'deno' | 'node';
+
+export interface ShareLinkResponse {
+	code: string;
+	short_url: string;
+	share_url: string;
+}
+
+export interface PinResponse {
+	job_id: string;
+	until_fetched: boolean;
+	expires_at: string | null;
+	mirrored: boolean;
+}
+
+export interface AnonymousToken {
+	token: string;
+	expires_at: string;
+	max_submissions: number;
+	max_compiles: number;
+}
+
 class ApiClient {
+	// Sent on /compile and /submit as X-Anonymous-Token when set
+	private anonymousToken: string | null = null;
+
+	private anonymousHeaders(): Record<string, string> {
+		return this.anonymousToken ? { 'X-Anonymous-Token': this.anonymousToken } : {};
+	}
+
+	// For the playground without a login; captchaToken when the server asks for one
+	async requestAnonymousToken(captchaToken?: string): Promise<AnonymousToken> {
+		const token: AnonymousToken = await this.request('/tokens/anonymous', {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ captcha_token: captchaToken })
+		});
+		this.anonymousToken = token.token;
+		return token;
+	}
+
+	clearAnonymousToken() {
+		this.anonymousToken = null;
+	}
+
 	private async request<T>(path: string, options?: RequestInit): Promise<T> {
 		const response = await fetch(`${API_BASE}${path}`, {
 			...options,
@@ -304,11 +1073,66 @@ class ApiClient {
 		return this.request('/health');
 	}
 
+	async errorCatalog(): Promise<ErrorCatalogEntry[]> {
+		return this.request('/errors');
+	}
+
+	async version(): Promise<VersionResponse> {
+		return this.request('/version');
+	}
+
+	async adminOverview(hours = 24, limit = 10): Promise<AdminOverview> {
+		return this.request(`/admin/overview?hours=${hours}&limit=${limit}`, {
+			credentials: 'include'
+		});
+	}
+
+	async adminRetention(): Promise<RetentionReport> {
+		return this.request('/admin/retention', {
+			credentials: 'include'
+		});
+	}
+
+	async adminChallenges(): Promise<ChallengeProvenanceReport> {
+		return this.request('/admin/challenges', {
+			credentials: 'include'
+		});
+	}
+
+	async adminWorkers(): Promise<ExternalWorker[]> {
+		return this.request('/admin/workers', {
+			credentials: 'include'
+		});
+	}
+
+	// The token is only ever returned here
+	async createExternalWorker(name: string): Promise<{ worker: ExternalWorker; token: string }> {
+		return this.request('/admin/workers', {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			credentials: 'include',
+			body: JSON.stringify({ name })
+		});
+	}
+
+	async revokeExternalWorker(workerId: string): Promise<{ revoked: boolean }> {
+		return this.request(`/admin/workers/${workerId}`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
+	}
+
 	async compile(
 		sourceCode: string,
 		language: Language,
 		optimization: Optimization = 'release',
-		flags: Record<string, string> = {}
+		flags: Record<string, string> = {},
+		compilerImage?: string,
+		emitAsm = false,
+		buildOptions: BuildOptions = {},
+		toolchain?: string,
+		runtime?: JsRuntime,
+		retryFailed = false
 	): Promise<CompileSubmitResponse> {
 		const formData = new FormData();
 		formData.append('source_code', sourceCode);
@@ -322,13 +1146,50 @@ class ApiClient {
 		if (Object.keys(activeFlags).length > 0) {
 			formData.append('flags', JSON.stringify(activeFlags));
 		}
+		if (compilerImage) {
+			formData.append('compiler_image', compilerImage);
+		}
+		if (emitAsm) {
+			formData.append('emit_asm', 'true');
+		}
+		if (buildOptions.linker_flags?.length) {
+			formData.append('build_options', JSON.stringify({ linker_flags: buildOptions.linker_flags }));
+		}
+		if (buildOptions.linker_script) {
+			formData.append('linker_script', buildOptions.linker_script);
+		}
+		if (toolchain) {
+			formData.append('toolchain', toolchain);
+		}
+		if (runtime) {
+			formData.append('runtime', runtime);
+		}
+		if (retryFailed) {
+			formData.append('retry_failed', 'true');
+		}
 
 		return this.request('/compile', {
 			method: 'POST',
+			headers: this.anonymousHeaders(),
 			body: formData
 		});
 	}
 
+	// file picks among several sources; language overrides the one inferred from the extension
+	async importGist(
+		url: string,
+		file?: string,
+		language?: Language,
+		optimization?: Optimization,
+		runtime?: JsRuntime
+	): Promise<ImportGistResponse> {
+		return this.request('/import/gist', {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json', ...this.anonymousHeaders() },
+			body: JSON.stringify({ url, file, language, optimization, runtime })
+		});
+	}
+
 	async compileStatus(jobId: string): Promise<CompileStatusResponse> {
 		return this.request(`/compile/status/${jobId}`);
 	}
@@ -337,12 +1198,63 @@ class ApiClient {
 		return this.request(`/compile/result/${jobId}`);
 	}
 
+	// Members of an organization get only its allowed languages, plus its policy
+	async getLanguages(): Promise<{
+		languages: LanguageInfo[];
+		policy?: OrgPolicy & { organization: string };
+	}> {
+		return this.request('/languages', { credentials: 'include' });
+	}
+
+	async getOrganization(slug: string): Promise<Organization> {
+		return this.request(`/orgs/${slug}`, { credentials: 'include' });
+	}
+
+	async setOrganizationPolicy(slug: string, policy: OrgPolicy): Promise<Organization> {
+		return this.request(`/orgs/${slug}/policy`, {
+			method: 'PUT',
+			headers: { 'Content-Type': 'application/json' },
+			credentials: 'include',
+			body: JSON.stringify(policy)
+		});
+	}
+
+	async setOrganizationMember(slug: string, username: string, role: 'admin' | 'member'): Promise<Organization> {
+		return this.request(`/orgs/${slug}/members/${username}`, {
+			method: 'PUT',
+			headers: { 'Content-Type': 'application/json' },
+			credentials: 'include',
+			body: JSON.stringify({ role })
+		});
+	}
+
+	async removeOrganizationMember(slug: string, username: string): Promise<Organization> {
+		return this.request(`/orgs/${slug}/members/${username}`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
+	}
+
+	async compileAsm(jobId: string): Promise<string> {
+		const response = await fetch(`${API_BASE}/compile/${jobId}/asm`);
+		if (!response.ok) {
+			const error = await response.json().catch(() => ({ error: 'Unknown error' }));
+			throw new Error(error.error || `HTTP ${response.status}`);
+		}
+		return response.text();
+	}
+
 	async submit(
 		binaryId: string,
 		instructionLimit?: number,
 		stdin?: string,
 		benchmarkId?: string,
-		envVars?: Record<string, string>
+		envVars?: Record<string, string>,
+		visibility?: RunVisibility,
+		args?: string[],
+		outputs?: string[],
+		coreDump?: boolean,
+		mode?: ExecutionMode
 	): Promise<SubmitResponse> {
 		const formData = new FormData();
 		formData.append('binary_id', binaryId);
@@ -358,21 +1270,59 @@ class ApiClient {
 		if (envVars && Object.keys(envVars).length > 0) {
 			formData.append('env_vars', JSON.stringify(envVars));
 		}
+		if (visibility) {
+			formData.append('visibility', visibility);
+		}
+		if (args && args.length > 0) {
+			formData.append('args', JSON.stringify(args));
+		}
+		if (outputs && outputs.length > 0) {
+			formData.append('outputs', JSON.stringify(outputs));
+		}
+		if (coreDump) {
+			formData.append('core_dump', 'true');
+		}
+		if (mode && mode !== 'normal') {
+			formData.append('mode', mode);
+		}
 
 		return this.request('/submit', {
 			method: 'POST',
-			body: formData
+			headers: this.anonymousHeaders(),
+			body: formData,
+			credentials: 'include'
 		});
 	}
 
-	async status(jobId: string): Promise<StatusResponse> {
-		return this.request(`/status/${jobId}`);
+	// With waitSeconds, the server holds the request until the status moves off `seen`
+	async status(jobId: string, waitSeconds?: number, seen?: string): Promise<StatusResponse> {
+		const params = new URLSearchParams();
+		if (waitSeconds) {
+			params.set('wait', waitSeconds.toString());
+		}
+		if (seen) {
+			params.set('status', seen);
+		}
+		const query = params.toString();
+		return this.request(`/status/${jobId}${query ? `?${query}` : ''}`);
 	}
 
 	async result(jobId: string): Promise<ExecutionResult> {
 		return this.request(`/result/${jobId}`);
 	}
 
+	getResultFileUrl(jobId: string, path: string): string {
+		return `${API_BASE}/result/${jobId}/files/${encodeURIComponent(path)}`;
+	}
+
+	// Keep the status and result past the job TTL
+	async pinJob(jobId: string): Promise<PinResponse> {
+		return this.request(`/jobs/${jobId}/pin`, {
+			method: 'POST',
+			credentials: 'include'
+		});
+	}
+
 	// Helper to poll for compile completion
 	async waitForCompile(jobId: string, timeoutMs = 120000): Promise<CompileResultResponse> {
 		const startTime = Date.now();
@@ -394,12 +1344,14 @@ class ApiClient {
 		throw new Error('Compile timeout');
 	}
 
-	// Helper to poll for execution completion
+	// Helper to wait for execution completion (long-polls the status)
 	async waitForExecution(jobId: string, timeoutMs = 60000): Promise<ExecutionResult> {
 		const startTime = Date.now();
+		let seen: string | undefined;
 
 		while (Date.now() - startTime < timeoutMs) {
-			const status = await this.status(jobId);
+			const remaining = Math.ceil((timeoutMs - (Date.now() - startTime)) / 1000);
+			const status = await this.status(jobId, Math.min(30, Math.max(1, remaining)), seen);
 
 			if (status.status === 'completed') {
 				return this.result(jobId);
@@ -409,7 +1361,7 @@ class ApiClient {
 				throw new Error(status.error || 'Execution failed');
 			}
 
-			await new Promise((resolve) => setTimeout(resolve, 250));
+			seen = status.status;
 		}
 
 		throw new Error('Execution timeout');
@@ -462,33 +1414,134 @@ class ApiClient {
 		return response.text();
 	}
 
-	async getBenchmarkStats(benchmarkId: string): Promise<BenchmarkStats> {
-		return this.request(`/benchmarks/${benchmarkId}/stats`);
+	// Defaults to the benchmark's current version
+	async getBenchmarkStats(benchmarkId: string, version?: number): Promise<BenchmarkStats> {
+		const query = version !== undefined ? `?version=${version}` : '';
+		return this.request(`/benchmarks/${benchmarkId}/stats${query}`);
 	}
 
 	// Runs endpoints
 	async getRun(runId: string): Promise<RunDetails> {
-		return this.request(`/runs/${runId}`);
+		return this.request(`/runs/${runId}`, { credentials: 'include' });
+	}
+
+	async purgeMyRuns(
+		filter: { before?: string; benchmark?: string } = {},
+		dryRun = false
+	): Promise<PurgeRunsResponse> {
+		const params = new URLSearchParams();
+		if (filter.before) params.set('before', filter.before);
+		if (filter.benchmark) params.set('benchmark', filter.benchmark);
+		if (dryRun) params.set('dry_run', 'true');
+		return this.request(`/my/runs?${params}`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
+	}
+
+	async setRunVisibility(runId: string, visibility: RunVisibility): Promise<RunDetails> {
+		return this.request(`/runs/${runId}`, {
+			method: 'PATCH',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ visibility }),
+			credentials: 'include'
+		});
+	}
+
+	async getRunComments(runId: string): Promise<{ comments: RunComment[] }> {
+		return this.request(`/runs/${runId}/comments`, { credentials: 'include' });
+	}
+
+	async postRunComment(runId: string, content: string): Promise<SavedRunComment> {
+		return this.request(`/runs/${runId}/comments`, {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ content }),
+			credentials: 'include'
+		});
+	}
+
+	async updateRunComment(runId: string, commentId: string, content: string): Promise<SavedRunComment> {
+		return this.request(`/runs/${runId}/comments/${commentId}`, {
+			method: 'PATCH',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ content }),
+			credentials: 'include'
+		});
+	}
+
+	async deleteRunComment(runId: string, commentId: string): Promise<{ deleted: boolean }> {
+		return this.request(`/runs/${runId}/comments/${commentId}`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
+	}
+
+	async setRunStar(runId: string, starred: boolean): Promise<StarResponse> {
+		return this.request(`/runs/${runId}/star`, {
+			method: starred ? 'PUT' : 'DELETE',
+			credentials: 'include'
+		});
+	}
+
+	// Public runs only
+	async shareRun(runId: string): Promise<ShareLinkResponse> {
+		return this.request(`/runs/${runId}/share`, { method: 'POST' });
+	}
+
+	getRunReproUrl(runId: string): string {
+		return `${API_BASE}/runs/${runId}/repro.tar.gz`;
+	}
+
+	getRunCoreUrl(runId: string): string {
+		return `${API_BASE}/runs/${runId}/core`;
 	}
 
 	async getRunByJob(jobId: string): Promise<RunDetails> {
-		return this.request(`/runs/job/${jobId}`);
+		return this.request(`/runs/job/${jobId}`, { credentials: 'include' });
 	}
 
 	async listRuns(limit = 50, offset = 0): Promise<RunDetails[]> {
-		return this.request(`/runs?limit=${limit}&offset=${offset}`);
+		return this.request(`/runs?limit=${limit}&offset=${offset}`, { credentials: 'include' });
+	}
+
+	async listBinaries(params: ListBinariesParams = {}): Promise<BinarySummary[]> {
+		const query = new URLSearchParams();
+		for (const [key, value] of Object.entries(params)) {
+			if (value !== undefined && value !== '') {
+				query.set(key, String(value));
+			}
+		}
+		return this.request(`/binaries?${query}`, { credentials: 'include' });
 	}
 
 	async getBinaryMetadata(binaryId: string): Promise<BinaryMetadata> {
 		return this.request(`/binaries/${binaryId}/metadata`);
 	}
 
+	async getSizeReport(binaryId: string): Promise<SizeReport> {
+		return this.request(`/binaries/${binaryId}/size-report`);
+	}
+
 	// ============ Auth Endpoints ============
 
 	async getMe(): Promise<AuthMeResponse> {
 		return this.request('/auth/me', { credentials: 'include' });
 	}
 
+	async getUsageAnalytics(periodDays = 30): Promise<UsageAnalytics> {
+		return this.request(`/auth/me/analytics?period=${periodDays}d`, { credentials: 'include' });
+	}
+
+	async updateProfile(profile: UpdateProfileRequest): Promise<AuthMeResponse> {
+		return this.request('/auth/me', {
+			method: 'PATCH',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify(profile),
+			credentials: 'include'
+		});
+	}
+
 	async logout(): Promise<void> {
 		await this.request('/auth/logout', {
 			method: 'POST',
@@ -511,9 +1564,14 @@ class ApiClient {
 		});
 	}
 
-	async checkClankerVerification(): Promise<CheckClankerVerificationResponse> {
+	async checkClankerVerification(
+		code: string,
+		captchaToken?: string
+	): Promise<CheckClankerVerificationResponse> {
 		return this.request('/verification/clanker/check', {
 			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ code, captcha_token: captchaToken }),
 			credentials: 'include'
 		});
 	}
@@ -527,23 +1585,109 @@ class ApiClient {
 	// ============ Challenge Endpoints ============
 
 	async listChallenges(): Promise<ChallengeListResponse> {
-		return this.request('/challenges');
+		return this.request('/challenges', {
+			credentials: 'include'
+		});
 	}
 
 	async getChallenge(id: string): Promise<ChallengeDetail> {
-		return this.request(`/challenges/${id}`);
+		return this.request(`/challenges/${id}`, {
+			credentials: 'include'
+		});
+	}
+
+	async setChallengeWindow(
+		id: string,
+		startsAt: string | null,
+		endsAt: string | null
+	): Promise<ChallengeInfo> {
+		return this.request(`/admin/challenges/${id}/window`, {
+			method: 'PUT',
+			headers: { 'Content-Type': 'application/json' },
+			credentials: 'include',
+			body: JSON.stringify({ starts_at: startsAt, ends_at: endsAt })
+		});
+	}
+
+	async setChallengeStar(challengeId: string, starred: boolean): Promise<StarResponse> {
+		return this.request(`/challenges/${challengeId}/star`, {
+			method: starred ? 'PUT' : 'DELETE',
+			credentials: 'include'
+		});
+	}
+
+	async getMyStars(): Promise<MyStarsResponse> {
+		return this.request('/my/stars', { credentials: 'include' });
+	}
+
+	async listTrackedSolutions(): Promise<{ tracked: TrackedSolution[] }> {
+		return this.request('/my/tracked', { credentials: 'include' });
+	}
+
+	async getTrackedSolution(benchmarkId: string): Promise<TrackedSolution> {
+		return this.request(`/my/tracked/${benchmarkId}`, { credentials: 'include' });
+	}
+
+	async trackSolution(
+		benchmarkId: string,
+		sourceCode: string,
+		language: string,
+		optimization?: string
+	): Promise<TrackedSolution> {
+		return this.request(`/my/tracked/${benchmarkId}`, {
+			method: 'PUT',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ source_code: sourceCode, language, optimization }),
+			credentials: 'include'
+		});
+	}
+
+	async untrackSolution(benchmarkId: string): Promise<{ deleted: boolean }> {
+		return this.request(`/my/tracked/${benchmarkId}`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
+	}
+
+	async listRepoLinks(): Promise<{ repos: RepoLink[] }> {
+		return this.request('/my/repos', { credentials: 'include' });
+	}
+
+	async linkRepo(link: LinkRepoRequest): Promise<RepoLink> {
+		return this.request('/my/repos', {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify(link),
+			credentials: 'include'
+		});
+	}
+
+	async unlinkRepo(id: string): Promise<{ deleted: boolean }> {
+		return this.request(`/my/repos/${id}`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
 	}
 
 	async submitChallenge(
 		challengeId: string,
 		sourceCode: string,
 		language: Language,
-		optimization: Optimization = 'release'
+		optimization: Optimization = 'release',
+		prebuilt?: { binaryId: string; sourceSha256: string },
+		ranked = true
 	): Promise<ChallengeSubmitResponse> {
 		const formData = new FormData();
 		formData.append('source_code', sourceCode);
 		formData.append('language', language);
 		formData.append('optimization', optimization);
+		if (prebuilt) {
+			formData.append('binary_id', prebuilt.binaryId);
+			formData.append('source_sha256', prebuilt.sourceSha256);
+		}
+		if (!ranked) {
+			formData.append('ranked', 'false');
+		}
 
 		return this.request(`/challenges/${challengeId}/submit`, {
 			method: 'POST',
@@ -554,35 +1698,158 @@ class ApiClient {
 
 	async getChallengeSubmissionStatus(
 		challengeId: string,
-		submissionId: string
+		submissionId: string,
+		whitespace?: DiffWhitespace
 	): Promise<SubmissionStatusResponse> {
-		return this.request(`/challenges/${challengeId}/submission/${submissionId}`, {
+		const query = whitespace ? `?whitespace=${whitespace}` : '';
+		return this.request(`/challenges/${challengeId}/submission/${submissionId}${query}`, {
+			credentials: 'include'
+		});
+	}
+
+	async getChallengeQueue(challengeId: string): Promise<ChallengeQueueResponse> {
+		return this.request(`/challenges/${challengeId}/queue`, {
+			credentials: 'include'
+		});
+	}
+
+	async retryChallengeSubmission(
+		challengeId: string,
+		submissionId: string
+	): Promise<ChallengeSubmitResponse> {
+		return this.request(`/challenges/${challengeId}/submissions/${submissionId}/retry`, {
+			method: 'POST',
+			credentials: 'include'
+		});
+	}
+
+	async unlockHint(challengeId: string, index: number): Promise<ChallengeHint> {
+		return this.request(`/challenges/${challengeId}/hints/${index}/unlock`, {
+			method: 'POST',
+			credentials: 'include'
+		});
+	}
+
+	async getWriteups(challengeId: string): Promise<WriteupListResponse> {
+		return this.request(`/challenges/${challengeId}/writeups`, {
+			credentials: 'include'
+		});
+	}
+
+	async submitWriteup(challengeId: string, content: string): Promise<SavedWriteup> {
+		return this.request(`/challenges/${challengeId}/writeups`, {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ content }),
+			credentials: 'include'
+		});
+	}
+
+	async getNotifications(unread = false): Promise<NotificationListResponse> {
+		return this.request(`/notifications${unread ? '?unread=true' : ''}`, {
+			credentials: 'include'
+		});
+	}
+
+	async markNotificationRead(id: string): Promise<{ marked: number }> {
+		return this.request(`/notifications/${id}/read`, {
+			method: 'POST',
+			credentials: 'include'
+		});
+	}
+
+	async markAllNotificationsRead(): Promise<{ marked: number }> {
+		return this.request('/notifications/read-all', {
+			method: 'POST',
+			credentials: 'include'
+		});
+	}
+
+	async getNotificationPreferences(): Promise<NotificationPreferences> {
+		return this.request('/notifications/preferences', {
+			credentials: 'include'
+		});
+	}
+
+	async setNotificationPreferences(preferences: NotificationPreferences): Promise<NotificationPreferences> {
+		return this.request('/notifications/preferences', {
+			method: 'PUT',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify(preferences),
 			credentials: 'include'
 		});
 	}
 
 	async getChallengeLeaderboard(
 		challengeId: string,
-		options?: { language?: string; user_type?: string; limit?: number }
+		options?: { language?: string; user_type?: string; country?: string; limit?: number }
 	): Promise<LeaderboardEntry[]> {
 		const params = new URLSearchParams();
 		if (options?.language) params.set('language', options.language);
 		if (options?.user_type) params.set('user_type', options.user_type);
+		if (options?.country) params.set('country', options.country);
 		if (options?.limit) params.set('limit', options.limit.toString());
 		const query = params.toString();
 		return this.request(`/challenges/${challengeId}/leaderboard${query ? '?' + query : ''}`);
 	}
 
+	async getLanguageLeaderboard(
+		challengeId: string,
+		options?: { user_type?: string }
+	): Promise<LanguageLeaderboardResponse> {
+		const query = options?.user_type ? `?user_type=${encodeURIComponent(options.user_type)}` : '';
+		return this.request(`/challenges/${challengeId}/leaderboard/languages${query}`);
+	}
+
 	async getGlobalLeaderboard(
-		options?: { user_type?: string; limit?: number }
+		options?: { user_type?: string; country?: string; limit?: number }
 	): Promise<GlobalLeaderboardEntry[]> {
 		const params = new URLSearchParams();
 		if (options?.user_type) params.set('user_type', options.user_type);
+		if (options?.country) params.set('country', options.country);
 		if (options?.limit) params.set('limit', options.limit.toString());
 		const query = params.toString();
 		return this.request(`/leaderboard${query ? '?' + query : ''}`);
 	}
 
+	async getCountryLeaderboard(options?: { user_type?: string }): Promise<CountryStanding[]> {
+		const query = options?.user_type ? `?user_type=${encodeURIComponent(options.user_type)}` : '';
+		return this.request(`/leaderboard/countries${query}`);
+	}
+
+	async getPublicStats(): Promise<PublicStatsSummary> {
+		return this.request('/public/stats');
+	}
+
+	async getPublicRunsPerDay(): Promise<PublicRunsPerDay> {
+		return this.request('/public/stats/runs-per-day');
+	}
+
+	async getPublicHelloWorld(): Promise<PublicHelloWorld> {
+		return this.request('/public/stats/hello-world');
+	}
+
+	async getCompositeScores(): Promise<CompositeScores> {
+		return this.request('/benchmarks/composite');
+	}
+
+	getChallengeBadgeUrl(challengeId: string, username: string, language?: string): string {
+		const query = language ? `?language=${encodeURIComponent(language)}` : '';
+		return `${API_BASE}/badge/${challengeId}/${encodeURIComponent(username)}.svg${query}`;
+	}
+
+	async getDigest(date: string): Promise<DailyDigest> {
+		return this.request(`/digest/${date}`);
+	}
+
+	async getSandboxManifest(): Promise<SandboxManifest> {
+		return this.request('/sandbox/manifest');
+	}
+
+	getSandboxComposeUrl(language = 'c', optimization = 'release'): string {
+		return `${API_BASE}/sandbox/docker-compose.yml?language=${encodeURIComponent(language)}&optimization=${optimization}`;
+	}
+
 	// Helper to poll for challenge submission completion
 	async waitForChallengeSubmission(
 		challengeId: string,
@@ -15,6 +15,8 @@ export interface Benchmark {
 	id: string;
 	name: string;
 	description: string;
+	// Bumped when stdin, env or the expected output change
+	version: number;
 	implementations: BenchmarkImpl[];
 	env_vars?: Record<string, string>;
 	stdin?: string;
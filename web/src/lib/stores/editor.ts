@@ -157,6 +157,65 @@ main = putStrLn "Hello, World!"
 		tier: 'native',
 		monacoLanguage: 'csharp',
 		defaultCode: `Console.WriteLine("Hello, World!");
+`
+	},
+	{
+		id: 'fortran',
+		name: 'Fortran',
+		extension: 'f90',
+		tier: 'native',
+		monacoLanguage: 'plaintext',
+		defaultCode: `program hello
+  print "(a)", "Hello, World!"
+end program hello
+`
+	},
+	{
+		id: 'd',
+		name: 'D',
+		extension: 'd',
+		tier: 'native',
+		monacoLanguage: 'plaintext',
+		defaultCode: `import std.stdio;
+
+void main()
+{
+    writeln("Hello, World!");
+}
+`
+	},
+	{
+		id: 'crystal',
+		name: 'Crystal',
+		extension: 'cr',
+		tier: 'native',
+		monacoLanguage: 'ruby',
+		defaultCode: `puts "Hello, World!"
+`
+	},
+	{
+		id: 'v',
+		name: 'V',
+		extension: 'v',
+		tier: 'native',
+		monacoLanguage: 'go',
+		defaultCode: `fn main() {
+	println('Hello, World!')
+}
+`
+	},
+	{
+		id: 'ada',
+		name: 'Ada',
+		extension: 'adb',
+		tier: 'native',
+		monacoLanguage: 'plaintext',
+		defaultCode: `with Ada.Text_IO;
+
+procedure Main is
+begin
+   Ada.Text_IO.Put_Line ("Hello, World!");
+end Main;
 `
 	},
 
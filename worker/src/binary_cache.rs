@@ -0,0 +1,269 @@
+//! Fetched binaries kept on local disk.
+//!
+//! Challenge test cases and repeat runs execute the same binary job after
+//! job. With `BINARY_CACHE_DIR` set, the worker keeps each binary it fetches
+//! there, named by its ID, and skips the API the next time. Only
+//! content-addressed IDs (`sha256-<hex>`, what `/compile` and `/submit`
+//! store) are cached, and a file is checked against its ID when written and
+//! again when read, so a corrupt entry is dropped and fetched again rather
+//! than run. The cache is held to `BINARY_CACHE_MAX_MB`, evicting the least
+//! recently used binaries; files left by an earlier run are picked up at
+//! startup in the order they were last used.
+
+use ctf_common::binary_cache::BinaryCacheStats;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+/// What's on disk, with a use counter standing in for access times
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    bytes: u64,
+    clock: u64,
+}
+
+impl Index {
+    fn touch(&mut self, binary_id: &str) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(binary_id).map(|e| e.last_used = clock).is_some()
+    }
+
+    fn insert(&mut self, binary_id: String, size: u64) {
+        self.clock += 1;
+        let entry = Entry {
+            size,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.entries.insert(binary_id, entry) {
+            self.bytes -= old.size;
+        }
+        self.bytes += size;
+    }
+
+    fn remove(&mut self, binary_id: &str) -> bool {
+        match self.entries.remove(binary_id) {
+            Some(entry) => {
+                self.bytes -= entry.size;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop least recently used entries until the rest fit in `max_bytes`
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.bytes > max_bytes {
+            let Some(oldest) = self.entries.iter().min_by_key(|(_, e)| e.last_used).map(|(id, _)| id.clone()) else {
+                break;
+            };
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+pub struct BinaryCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    invalid: AtomicU64,
+    prefetched: AtomicU64,
+}
+
+impl BinaryCache {
+    /// Open the cache in `dir`, creating it if needed and indexing what's
+    /// already there
+    pub fn open(dir: PathBuf, max_mb: u64) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !meta.is_file() || digest_of(&name).is_none() {
+                // Left over from a write that didn't finish
+                let _ = std::fs::remove_file(entry.path());
+                continue;
+            }
+            files.push((name, meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+        }
+        files.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut index = Index::default();
+        for (binary_id, size, _) in files {
+            index.insert(binary_id, size);
+        }
+        let max_bytes = max_mb * 1024 * 1024;
+        for binary_id in index.evict(max_bytes) {
+            let _ = std::fs::remove_file(dir.join(binary_id));
+        }
+        info!(dir = %dir.display(), entries = index.entries.len(), bytes = index.bytes, max_mb, "Binary cache opened");
+
+        Ok(Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            invalid: AtomicU64::new(0),
+            prefetched: AtomicU64::new(0),
+        })
+    }
+
+    /// The cached binary, if there is one and it still matches its ID
+    pub async fn get(&self, binary_id: &str) -> Option<Vec<u8>> {
+        let digest = digest_of(binary_id)?;
+        if !self.index.lock().unwrap().touch(binary_id) {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let path = self.path(binary_id);
+        let read = tokio::task::spawn_blocking(move || read_verified(&path, &digest))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+        match read {
+            Ok(data) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(data)
+            }
+            Err(e) => {
+                if e.kind() == io::ErrorKind::InvalidData {
+                    self.invalid.fetch_add(1, Ordering::Relaxed);
+                }
+                warn!(binary_id, error = %e, "Dropping unreadable cached binary");
+                self.discard(binary_id).await;
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Whether `binary_id` is the kind of ID the cache keeps
+    pub fn accepts(binary_id: &str) -> bool {
+        digest_of(binary_id).is_some()
+    }
+
+    /// Whether `binary_id` is cached; not counted as a hit or miss
+    pub fn contains(&self, binary_id: &str) -> bool {
+        self.index.lock().unwrap().entries.contains_key(binary_id)
+    }
+
+    /// Keep a fetched binary, if its ID is a digest it matches; false if it
+    /// wasn't added
+    pub async fn put(&self, binary_id: &str, data: &[u8]) -> bool {
+        let Some(digest) = digest_of(binary_id) else {
+            return false;
+        };
+        let size = data.len() as u64;
+        if size > self.max_bytes || self.index.lock().unwrap().entries.contains_key(binary_id) {
+            return false;
+        }
+
+        let (dir, path, data) = (self.dir.clone(), self.path(binary_id), data.to_vec());
+        let written = tokio::task::spawn_blocking(move || write_verified(&dir, &path, &data, &digest))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+        if let Err(e) = written {
+            if e.kind() == io::ErrorKind::InvalidData {
+                self.invalid.fetch_add(1, Ordering::Relaxed);
+            }
+            warn!(binary_id, error = %e, "Failed to cache binary");
+            return false;
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(binary_id.to_string(), size);
+            index.evict(self.max_bytes)
+        };
+        for binary_id in evicted {
+            let _ = tokio::fs::remove_file(self.path(&binary_id)).await;
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+
+    pub fn count_prefetch(&self) {
+        self.prefetched.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> BinaryCacheStats {
+        let index = self.index.lock().unwrap();
+        BinaryCacheStats {
+            entries: index.entries.len() as u64,
+            bytes: index.bytes,
+            max_bytes: self.max_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+            prefetched: self.prefetched.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn discard(&self, binary_id: &str) {
+        if self.index.lock().unwrap().remove(binary_id) {
+            let _ = tokio::fs::remove_file(self.path(binary_id)).await;
+        }
+    }
+
+    fn path(&self, binary_id: &str) -> PathBuf {
+        self.dir.join(binary_id)
+    }
+}
+
+/// The SHA-256 a `sha256-<lowercase hex>` ID names; other IDs aren't cached
+fn digest_of(binary_id: &str) -> Option<[u8; 32]> {
+    let hex_digest = binary_id.strip_prefix("sha256-")?;
+    let mut digest = [0u8; 32];
+    hex::decode_to_slice(hex_digest, &mut digest).ok()?;
+    (hex::encode(digest) == hex_digest).then_some(digest)
+}
+
+fn check_digest(data: &[u8], digest: &[u8; 32]) -> io::Result<()> {
+    if Sha256::digest(data).as_slice() != digest {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "content doesn't match the binary ID"));
+    }
+    Ok(())
+}
+
+fn read_verified(path: &Path, digest: &[u8; 32]) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    check_digest(&data, digest)?;
+    // Keep the order of use across restarts
+    let _ = File::options().write(true).open(path).and_then(|f| f.set_modified(SystemTime::now()));
+    Ok(data)
+}
+
+/// Write through a temp file in `dir`, so a reader never sees half a binary
+fn write_verified(dir: &Path, path: &Path, data: &[u8], digest: &[u8; 32]) -> io::Result<()> {
+    check_digest(data, digest)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(data)?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
@@ -1,22 +1,43 @@
-use async_nats::jetstream::{self, consumer::PullConsumer, kv::Store};
+mod binary_cache;
+mod prefetch;
+
+use async_nats::jetstream::{self, consumer::PullConsumer, kv::Store, AckKind};
+use binary_cache::BinaryCache;
+use ctf_common::binary_encoding;
+use ctf_common::diagnostics::{self, DiagnosticsReport, ExecutionMode};
+use ctf_common::fixtures::FixtureSpec;
+use ctf_common::outputs::{CollectedOutput, OutputFile};
+use ctf_common::runtime::{self, RuntimeImages, RuntimeManifest};
+use ctf_common::{logging, redact, signals};
+use ctf_common::sandbox_options::{self, Collected, Isolation, SandboxOptions};
+use ctf_common::topology::{
+    Topology, TopologyConfig, RUNS_SPOOL_CONSUMER, RUNS_SPOOL_SUBJECT, SHARED_JOBS_STREAM, SHARED_WORKER_CONSUMER,
+    WORKER_CONSUMER,
+};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use chrono::{DateTime, Utc};
 use futures::StreamExt;
+use nkeys::KeyPair;
 use regex::bytes::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::os::unix::fs::PermissionsExt;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use std::time::{Duration, Instant};
 use tempfile::NamedTempFile;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
-use tracing::{error, info};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
-const JOBS_STREAM: &str = "JOBS";
-const JOBS_KV: &str = "jobs";
-const RESULTS_KV: &str = "results";
+/// Redelivery delay for spooled runs while the API is down; doubles up to the max
+const SPOOL_RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const SPOOL_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Events the poll loop would otherwise repeat every second (see logging.rs)
+static FETCH_ERRORS: logging::Sampler = logging::Sampler::new();
+static RECEIVE_ERRORS: logging::Sampler = logging::Sampler::new();
 
 static STATS_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\n(\{[^\n]+\})\n?$").unwrap());
@@ -31,11 +52,43 @@ struct Job {
     created_at: DateTime<Utc>,
     #[serde(default)]
     benchmark_id: Option<String>,
+    #[serde(default)]
+    benchmark_version: Option<u32>,
     // Challenge-specific execution options
     #[serde(default)]
     network_enabled: bool,
     #[serde(default)]
     env_vars: std::collections::HashMap<String, String>,
+    /// argv after the program name; the API bounds count and length
+    #[serde(default)]
+    args: Vec<String>,
+    /// Files to collect from `/work/out` afterwards
+    #[serde(default)]
+    outputs: Vec<String>,
+    /// Keep the core if the binary crashes
+    #[serde(default)]
+    core_dump: bool,
+    /// `diagnostics` runs in DIAGNOSTICS_IMAGE under valgrind/ASan
+    #[serde(default)]
+    mode: ExecutionMode,
+    #[serde(default)]
+    fixtures: Option<FixtureSpec>,
+    #[serde(default)]
+    sandbox_options: Option<SandboxOptions>,
+    #[serde(default)]
+    sandbox_signature: Option<String>,
+    /// Wall-clock limit; `None` means `TIMEOUT_SEC`
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    /// Container memory limit; `None` means `MEMORY_LIMIT_MB`
+    #[serde(default)]
+    memory_limit_mb: Option<u32>,
+    /// `public` or `private`; passed through to the saved run
+    #[serde(default)]
+    visibility: Option<String>,
+    /// Set for challenge test runs; env values are scrubbed from their stderr
+    #[serde(default)]
+    challenge_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -54,6 +107,24 @@ struct JobMetadata {
     started_at: Option<DateTime<Utc>>,
     completed_at: Option<DateTime<Utc>>,
     error: Option<String>,
+    // Set by the API for run reconciliation; carried through status updates
+    #[serde(default)]
+    binary_id: Option<String>,
+    #[serde(default)]
+    instruction_limit: Option<u64>,
+    #[serde(default)]
+    benchmark_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    visibility: Option<String>,
+    // Set by the API for jobs on SHARED_JOBS; carried through status updates
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    shared: bool,
+    // Marks a shared job as taken in-house, so the API refuses external
+    // workers' reports on it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    in_house: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -86,8 +157,77 @@ struct PluginStats {
     syscall_cost: u64,
     #[serde(default)]
     syscall_breakdown: std::collections::HashMap<String, u64>,
+    #[serde(default)]
+    process_count: u64,
+    #[serde(default)]
+    processes: Vec<ProcessStats>,
+    #[serde(default)]
+    thread_count: u64,
+    #[serde(default)]
+    threads: Vec<ThreadStats>,
+}
+
+/// One guest process of a run. Forked children get their own entry; threads
+/// are counted in their process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProcessStats {
+    pid: i32,
+    ppid: i32,
+    instructions: u64,
+    /// False if it was still running when the root process exited, in which
+    /// case its count stops there
+    #[serde(default)]
+    exited: bool,
+    /// Replaced itself via execve; the new program ran natively, uncounted
+    #[serde(default)]
+    exec: bool,
+}
+
+/// One guest thread (the main thread included). A thread burning instructions
+/// with next to no syscalls while others sit in `futex` is usually spinning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ThreadStats {
+    pid: i32,
+    tid: i32,
+    instructions: u64,
+    #[serde(default)]
+    syscalls: u64,
+    #[serde(default)]
+    syscall_breakdown: std::collections::HashMap<String, u64>,
+}
+
+/// Which limit stopped a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LimitReason {
+    Instructions,
+    WallClock,
+    Memory,
+    /// Not a limit: a process exec'd a program QEMU doesn't follow (or the
+    /// runtime always does), so the count is short and the run can't rank
+    Exec,
 }
 
+impl LimitReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            LimitReason::Instructions => "instructions",
+            LimitReason::WallClock => "wall_clock",
+            LimitReason::Memory => "memory",
+            LimitReason::Exec => "exec",
+        }
+    }
+}
+
+/// Past the in-container `timeout`, give up on the container
+const WALL_CLOCK_GRACE: Duration = Duration::from_secs(5);
+
+/// Exit code of coreutils `timeout` when the entrypoint's wall-clock limit fires
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Smallest container QEMU and the plugin reliably start in
+const MIN_MEMORY_LIMIT_MB: u32 = 32;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ExecutionResult {
     instructions: u64,
@@ -112,6 +252,22 @@ struct ExecutionResult {
     #[serde(default)]
     guest_heap_bytes: u64,
     limit_reached: bool,
+    /// Set whenever `limit_reached` is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    limit_reached_reason: Option<LimitReason>,
+    /// Killed by the container's memory limit
+    #[serde(default)]
+    oom_killed: bool,
+    /// `SIGSEGV`, `SIGILL`, `SIGFPE`, ... when the program crashed rather
+    /// than exiting non-zero; see `ctf_common::signals`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crash_signal: Option<String>,
+    /// Memory limit the run had, in MB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memory_limit_mb: Option<u32>,
+    /// Sandbox image the run used, pinned by digest when the image has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sandbox_image: Option<String>,
     exit_code: i32,
     stdout: String,
     stderr: String,
@@ -120,15 +276,54 @@ struct ExecutionResult {
     syscalls: u64,
     #[serde(default)]
     syscall_breakdown: std::collections::HashMap<String, u64>,
+    /// Guest processes measured; 1 unless the program forks
+    #[serde(default)]
+    process_count: u64,
+    /// Per-process split of `instructions`, root process first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    processes: Vec<ProcessStats>,
+    /// Guest threads across all processes; `threads` lists the first 128
+    #[serde(default)]
+    thread_count: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    threads: Vec<ThreadStats>,
+    /// Files the job declared under `/work/out`, in the binary store
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    output_files: Vec<OutputFile>,
+    /// The crash's core, in the binary store, when the job asked for one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    core_dump: Option<OutputFile>,
+    /// valgrind/ASan findings; only in diagnostics mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diagnostics: Option<DiagnosticsReport>,
 }
 
 struct Config {
     nats_url: String,
     api_url: String,
     sandbox_image: String,
+    diagnostics_image: String,
+    // Images for binaries that need an interpreter
+    runtime_images: RuntimeImages,
     memory_limit_mb: u32,
+    // Ceiling for a job's own memory_limit_mb
+    max_memory_limit_mb: u32,
     timeout_sec: u64,
     job_ttl_seconds: u64,
+    binary_ttl_seconds: u64,
+    // Verifies privileged sandbox options signed by the API
+    sandbox_options_secret: Option<String>,
+    // Set for workers run outside the arena; see Registration
+    worker_token: Option<String>,
+    // What an external worker declares at registration: container, gvisor or vm
+    worker_isolation: String,
+    // Send output files zstd-compressed (BINARY_UPLOAD_COMPRESSION=none to turn off)
+    upload_compression: bool,
+    // Fetched binaries kept on disk, see binary_cache.rs (None = fetch every job)
+    binary_cache_dir: Option<String>,
+    binary_cache_max_mb: u64,
+    // Queued jobs whose binaries are fetched ahead, see prefetch.rs (0 = none)
+    binary_prefetch_depth: u64,
 }
 
 impl Config {
@@ -137,10 +332,16 @@ impl Config {
             nats_url: env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string()),
             api_url: env::var("API_URL").unwrap_or_else(|_| "http://ctf-api:3000".to_string()),
             sandbox_image: env::var("SANDBOX_IMAGE").unwrap_or_else(|_| "sandbox".to_string()),
+            diagnostics_image: env::var("DIAGNOSTICS_IMAGE").unwrap_or_else(|_| "sandbox-diagnostics".to_string()),
+            runtime_images: RuntimeImages::from_env(),
             memory_limit_mb: env::var("MEMORY_LIMIT_MB")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(256),
+            max_memory_limit_mb: env::var("MAX_MEMORY_LIMIT_MB")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2048),
             timeout_sec: env::var("TIMEOUT_SEC")
                 .ok()
                 .and_then(|s| s.parse().ok())
@@ -149,11 +350,88 @@ impl Config {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(3600),
+            binary_ttl_seconds: env::var("BINARY_TTL_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(86400),
+            sandbox_options_secret: env::var("SANDBOX_OPTIONS_SECRET").ok().filter(|s| !s.is_empty()),
+            worker_token: env::var("WORKER_TOKEN").ok().filter(|s| !s.is_empty()),
+            worker_isolation: env::var("WORKER_ISOLATION").unwrap_or_else(|_| "container".to_string()),
+            upload_compression: env::var("BINARY_UPLOAD_COMPRESSION").map(|v| v != "none").unwrap_or(true),
+            binary_cache_dir: env::var("BINARY_CACHE_DIR").ok().filter(|s| !s.is_empty()),
+            binary_cache_max_mb: env::var("BINARY_CACHE_MAX_MB")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1024),
+            binary_prefetch_depth: env::var("BINARY_PREFETCH_DEPTH")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2),
         }
     }
 }
 
-async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<ExecutionResult, String> {
+/// Resolved digests by configured image name
+static IMAGE_REFERENCES: LazyLock<tokio::sync::Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+/// `repo@sha256:...` for a pulled image, the image ID for a local build, or
+/// just the configured name if Docker can't tell; resolved once per image
+/// per process
+async fn image_reference(image: &str) -> String {
+    if let Some(reference) = IMAGE_REFERENCES.lock().await.get(image) {
+        return reference.clone();
+    }
+    let output = Command::new("docker")
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{if .RepoDigests}}{{index .RepoDigests 0}}{{else}}{{.Id}}{{end}}",
+            image,
+        ])
+        .output()
+        .await;
+    let reference = match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        _ => image.to_string(),
+    };
+    IMAGE_REFERENCES.lock().await.insert(image.to_string(), reference.clone());
+    reference
+}
+
+/// `State.OOMKilled` of a stopped container; `None` if it can't be inspected
+async fn container_oom_killed(name: &str) -> Option<bool> {
+    let output = Command::new("docker")
+        .args(["inspect", "--format", "{{.State.OOMKilled}}", name])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Force-remove a sandbox container, killing it if it's still running
+async fn remove_container(name: &str) {
+    let removed = Command::new("docker")
+        .args(["rm", "-f", name])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await;
+    if !removed.is_ok_and(|s| s.success()) {
+        warn!(container = name, "Failed to remove sandbox container");
+    }
+}
+
+async fn execute_sandbox(
+    job: &Job,
+    binary: &[u8],
+    runtime: Option<&RuntimeManifest>,
+    config: &Config,
+    redactor: &redact::Redactor,
+) -> Result<(ExecutionResult, Collected), String> {
     // Write binary to temp file
     let temp_file = NamedTempFile::new().map_err(|e| format!("Failed to create temp file: {}", e))?;
     let binary_path = temp_file.path().to_path_buf();
@@ -180,16 +458,69 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
         .await
         .map_err(|e| format!("Failed to set permissions: {}", e))?;
 
+    // Names the entrypoint, the loader or QEMU act on would let the job
+    // reach past the sandbox
+    if let Some(key) = job.env_vars.keys().find(|key| sandbox_options::is_reserved_env(key)) {
+        return Err(format!("Env var {} is reserved for the sandbox", key));
+    }
+
+    // Privileged options must carry the API's signature for this exact job
+    if let Some(ref options) = job.sandbox_options {
+        let secret = config
+            .sandbox_options_secret
+            .as_ref()
+            .ok_or("Sandbox options rejected: SANDBOX_OPTIONS_SECRET not set on worker")?;
+        options
+            .verify(
+                secret.as_bytes(),
+                &job.id.to_string(),
+                &job.binary_id,
+                job.fixtures.as_ref(),
+                job.sandbox_signature.as_deref().unwrap_or_default(),
+            )
+            .map_err(|e| format!("Sandbox options rejected: {}", e))?;
+    }
+    let isolation = Isolation {
+        fixtures: job.fixtures.as_ref(),
+        options: job.sandbox_options.as_ref(),
+        outputs: &job.outputs,
+        core_dump: job.core_dump,
+        mode: job.mode,
+        runtime,
+    };
+    let image = match (job.mode, runtime) {
+        (ExecutionMode::Normal, None) => config.sandbox_image.as_str(),
+        (ExecutionMode::Normal, Some(runtime)) => config.runtime_images.image_for(&runtime.runtime),
+        (ExecutionMode::Diagnostics, None) => &config.diagnostics_image,
+        (ExecutionMode::Diagnostics, Some(runtime)) => {
+            return Err(format!("Diagnostics mode isn't available for {} programs", runtime.runtime))
+        }
+    };
+    // Kept alive until the container exits (dropping removes the files)
+    let prepared = isolation
+        .prepare()
+        .map_err(|e| format!("Failed to set up sandbox: {}", e))?;
+
     let start = Instant::now();
 
+    // The API validates memory_limit_mb; cap it again against this worker
+    let memory_limit_mb = job
+        .memory_limit_mb
+        .unwrap_or(config.memory_limit_mb)
+        .clamp(MIN_MEMORY_LIMIT_MB, config.max_memory_limit_mb.max(MIN_MEMORY_LIMIT_MB));
+
     // Build docker command
     let mut cmd = Command::new("docker");
+    // Named and removed by hand rather than --rm, so the exit state can be
+    // inspected for OOM kills and a runaway container can be killed
+    let container_name = format!("ctf-run-{}", Uuid::new_v4());
     cmd.args([
         "run",
-        "--rm",
+        "--name",
+        &container_name,
         "-i",
-        &format!("--memory={}m", config.memory_limit_mb),
-        &format!("--memory-swap={}m", config.memory_limit_mb),
+        &format!("--memory={}m", memory_limit_mb),
+        &format!("--memory-swap={}m", memory_limit_mb),
     ]);
 
     // Only disable network if not explicitly enabled
@@ -201,8 +532,6 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
         "--read-only",
         "--tmpfs=/tmp:rw,exec,nosuid,size=64m",
         "--tmpfs=/var:rw,nosuid,size=16m",
-        "-e",
-        &format!("LIMIT={}", job.instruction_limit),
     ]);
 
     // Pass environment variables from challenge
@@ -211,16 +540,42 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
         cmd.arg(format!("{}={}", key, value));
     }
 
+    // After the job's variables so they can't override the limits
+    let max_timeout_ms = config.timeout_sec * 1000;
+    let timeout_ms = job.timeout_ms.unwrap_or(max_timeout_ms).min(max_timeout_ms);
+    cmd.args([
+        "-e",
+        &format!("LIMIT={}", job.instruction_limit),
+        "-e",
+        &format!("TIMEOUT_MS={}", timeout_ms),
+        "-e",
+        &runtime::entrypoint_env(runtime),
+    ]);
+
+    if let Some(ref prepared) = prepared {
+        cmd.args(prepared.docker_args());
+    }
+
     cmd.args([
         "-v",
         &format!("{}:/work/binary:ro", binary_path.display()),
-        &config.sandbox_image,
+        image,
     ]);
+    // Handed to the entrypoint, which passes them on to the binary
+    cmd.args(&job.args);
 
     cmd.stdin(std::process::Stdio::piped());
     cmd.stdout(std::process::Stdio::piped());
     cmd.stderr(std::process::Stdio::piped());
 
+    // Env values carry challenge flags; never log them
+    let std_cmd = cmd.as_std();
+    debug!(
+        job_id = %job.id,
+        command = %redact::command_line(std_cmd.get_program(), std_cmd.get_args()),
+        "Starting sandbox container"
+    );
+
     let mut child = cmd.spawn().map_err(|e| format!("Failed to spawn docker: {}", e))?;
 
     // Write stdin if provided
@@ -232,24 +587,32 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
         drop(child.stdin.take());
     }
 
-    // Wait with timeout
-    let result = tokio::time::timeout(
-        Duration::from_secs(config.timeout_sec),
-        child.wait_with_output(),
-    )
-    .await;
+    // The entrypoint enforces timeout_ms itself so the plugin still reports
+    // stats; this only catches a container that doesn't stop
+    let host_timeout = Duration::from_millis(timeout_ms) + WALL_CLOCK_GRACE;
+    let result = tokio::time::timeout(host_timeout, child.wait_with_output()).await;
 
     let execution_time_ms = start.elapsed().as_millis() as u64;
+    let oom_killed = container_oom_killed(&container_name).await;
+    remove_container(&container_name).await;
+    let collected = prepared.as_ref().map(|p| p.collect()).unwrap_or_default();
 
     let output = match result {
         Ok(Ok(output)) => output,
         Ok(Err(e)) => return Err(format!("Docker execution failed: {}", e)),
-        Err(_) => return Err(format!("Execution timed out after {} seconds", config.timeout_sec)),
+        Err(_) => return Err(format!("Execution timed out after {} seconds", host_timeout.as_secs())),
     };
+    let exit_code = output.status.code().unwrap_or(-1);
 
     // Parse plugin stats from stderr
     let mut stderr = output.stderr;
-    let stats = if let Some(captures) = STATS_REGEX.captures(&stderr) {
+    let diagnostics = match job.mode {
+        ExecutionMode::Normal => None,
+        ExecutionMode::Diagnostics => diagnostics::take_report(&mut stderr),
+    };
+    let captures = STATS_REGEX.captures(&stderr);
+    let has_stats = captures.is_some();
+    let stats = if let Some(captures) = captures {
         let json_match = captures.get(1).unwrap();
         let stats: PluginStats = serde_json::from_slice(json_match.as_bytes()).unwrap_or(PluginStats {
             instructions: 0,
@@ -267,6 +630,10 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
             syscalls: 0,
             syscall_cost: 0,
             syscall_breakdown: std::collections::HashMap::new(),
+            process_count: 0,
+            processes: Vec::new(),
+            thread_count: 0,
+            threads: Vec::new(),
         });
         // Remove stats JSON from stderr
         stderr.truncate(json_match.start() - 1);
@@ -288,10 +655,34 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
             syscalls: 0,
             syscall_cost: 0,
             syscall_breakdown: std::collections::HashMap::new(),
+            process_count: 0,
+            processes: Vec::new(),
+            thread_count: 0,
+            threads: Vec::new(),
         }
     };
 
-    Ok(ExecutionResult {
+    // A challenge binary can echo its flag to stderr, which ends up in the saved run
+    if job.challenge_id.is_some() {
+        stderr = redactor.scrub_bytes(&stderr);
+    }
+
+    // Docker's own OOMKilled flag; if the inspect failed, a SIGKILL that
+    // stopped QEMU before its atexit hook printed stats is the best sign
+    let oom_killed = oom_killed.unwrap_or(exit_code == 137 && !has_stats);
+    let limit_reached_reason = if stats.limit_reached {
+        Some(LimitReason::Instructions)
+    } else if oom_killed {
+        Some(LimitReason::Memory)
+    } else if exit_code == TIMEOUT_EXIT_CODE && execution_time_ms >= timeout_ms {
+        Some(LimitReason::WallClock)
+    } else if stats.processes.iter().any(|p| p.exec) || runtime.is_some_and(|r| !r.is_counted()) {
+        Some(LimitReason::Exec)
+    } else {
+        None
+    };
+
+    let result = ExecutionResult {
         instructions: stats.instructions,
         memory_peak_kb: stats.memory_peak_kb,
         memory_rss_kb: stats.memory_rss_kb,
@@ -303,14 +694,123 @@ async fn execute_sandbox(job: &Job, binary: &[u8], config: &Config) -> Result<Ex
         guest_mmap_bytes: stats.guest_mmap_bytes,
         guest_mmap_peak: stats.guest_mmap_peak,
         guest_heap_bytes: stats.guest_heap_bytes,
-        limit_reached: stats.limit_reached,
-        exit_code: output.status.code().unwrap_or(-1),
+        limit_reached: limit_reached_reason.is_some(),
+        crash_signal: signals::crash_signal(exit_code)
+            .filter(|_| limit_reached_reason.is_none_or(|r| r == LimitReason::Exec))
+            .map(str::to_string),
+        limit_reached_reason,
+        oom_killed,
+        memory_limit_mb: Some(memory_limit_mb),
+        sandbox_image: Some(image_reference(image).await),
+        exit_code,
         stdout: BASE64.encode(&output.stdout),
         stderr: BASE64.encode(&stderr),
         execution_time_ms,
         syscalls: stats.syscalls,
         syscall_breakdown: stats.syscall_breakdown,
-    })
+        process_count: stats.process_count,
+        processes: stats.processes,
+        thread_count: stats.thread_count,
+        threads: stats.threads,
+        output_files: Vec::new(),
+        core_dump: None,
+        diagnostics,
+    };
+    Ok((result, collected))
+}
+
+enum FetchError {
+    NotFound(reqwest::StatusCode),
+    Failed(String),
+}
+
+/// A job's binary from the cache, or else from the API (then cached)
+async fn fetch_binary(
+    http_client: &reqwest::Client,
+    api_url: &str,
+    cache: Option<&BinaryCache>,
+    binary_id: &str,
+) -> Result<Vec<u8>, FetchError> {
+    if let Some(binary) = match cache {
+        Some(cache) => cache.get(binary_id).await,
+        None => None,
+    } {
+        debug!(binary_id, "Binary cache hit");
+        return Ok(binary);
+    }
+
+    let binary = download_binary(http_client, api_url, binary_id).await?;
+    if let Some(cache) = cache {
+        cache.put(binary_id, &binary).await;
+    }
+    Ok(binary)
+}
+
+async fn download_binary(http_client: &reqwest::Client, api_url: &str, binary_id: &str) -> Result<Vec<u8>, FetchError> {
+    let resp = http_client
+        .get(format!("{}/binaries/{}", api_url, binary_id))
+        .header(reqwest::header::ACCEPT_ENCODING, binary_encoding::ZSTD)
+        .timeout(Duration::from_secs(60))
+        .send()
+        .await
+        .map_err(|e| FetchError::Failed(e.to_string()))?;
+    if !resp.status().is_success() {
+        return Err(FetchError::NotFound(resp.status()));
+    }
+    read_binary(resp).await.map_err(FetchError::Failed)
+}
+
+/// A fetched binary's bytes, decompressed if the API sent it as zstd
+async fn read_binary(resp: reqwest::Response) -> Result<Vec<u8>, String> {
+    let zstd = resp
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(binary_encoding::ZSTD.as_bytes()));
+    let body = resp.bytes().await.map_err(|e| e.to_string())?;
+    if !zstd {
+        return Ok(body.to_vec());
+    }
+    binary_encoding::decompress(&body, binary_encoding::MAX_FETCH_SIZE).map_err(|e| format!("zstd: {}", e))
+}
+
+/// Put collected output files and the core in the binary store and list them
+/// on `result`
+async fn store_outputs(http_client: &reqwest::Client, config: &Config, result: &mut ExecutionResult, collected: Collected) {
+    for output in collected.outputs {
+        result.output_files.push(store_output(http_client, config, output).await);
+    }
+    if let Some(core_dump) = collected.core_dump {
+        result.core_dump = Some(store_output(http_client, config, core_dump).await);
+    }
+}
+
+async fn store_output(
+    http_client: &reqwest::Client,
+    config: &Config,
+    CollectedOutput { mut file, data }: CollectedOutput,
+) -> OutputFile {
+    if let Some(binary_id) = &file.binary_id {
+        let mut request = http_client.put(format!("{}/binaries/{}", config.api_url, binary_id));
+        let compressed = config
+            .upload_compression
+            .then(|| binary_encoding::compress(&data, binary_encoding::DEFAULT_LEVEL))
+            .flatten();
+        request = match compressed {
+            Some(compressed) => request.header(reqwest::header::CONTENT_ENCODING, binary_encoding::ZSTD).body(compressed),
+            None => request.body(data),
+        };
+        let stored = request
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+        if let Err(e) = stored {
+            warn!(path = %file.path, error = %e, "Failed to store output file");
+            file.binary_id = None;
+            file.error = Some("store_failed".to_string());
+        }
+    }
+    file
 }
 
 async fn update_job_status(
@@ -332,6 +832,7 @@ async fn update_job_status(
         serde_json::from_slice(&entry).map_err(|e| format!("Failed to parse metadata: {}", e))?;
 
     metadata.status = status;
+    metadata.in_house = metadata.shared;
     match status {
         JobStatus::Running => {
             metadata.started_at = Some(Utc::now());
@@ -376,15 +877,61 @@ async fn store_job_result(
     Ok(())
 }
 
-#[derive(Debug, Serialize)]
+/// Where job status and results go: straight to the KV buckets for
+/// in-house workers, through the API's /workers routes for external ones,
+/// whose NATS credentials have no KV access
+enum JobReporter {
+    Kv { jobs: Box<Store>, results: Box<Store> },
+    Api { http_client: reqwest::Client, api_url: String },
+}
+
+impl JobReporter {
+    async fn update_status(&self, job_id: &Uuid, status: JobStatus, error: Option<String>) -> Result<(), String> {
+        match self {
+            JobReporter::Kv { jobs, .. } => update_job_status(jobs, job_id, status, error).await,
+            JobReporter::Api { http_client, api_url } => {
+                let body = serde_json::json!({ "status": status, "error": error });
+                report_to_api(http_client, &format!("{}/jobs/{}/status", api_url, job_id), &body).await
+            }
+        }
+    }
+
+    async fn store_result(&self, job_id: &Uuid, result: &ExecutionResult) -> Result<(), String> {
+        match self {
+            JobReporter::Kv { results, .. } => store_job_result(results, job_id, result).await,
+            JobReporter::Api { http_client, api_url } => {
+                report_to_api(http_client, &format!("{}/jobs/{}/result", api_url, job_id), result).await
+            }
+        }
+    }
+}
+
+async fn report_to_api(http_client: &reqwest::Client, url: &str, body: &impl Serialize) -> Result<(), String> {
+    let response = http_client
+        .post(url)
+        .json(body)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach API: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("API returned HTTP {}", response.status()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct SaveRunRequest {
     job_id: Uuid,
     benchmark_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    benchmark_version: Option<u32>,
     binary_id: String,
     binary_size: Option<i64>,
     language: Option<String>,
     optimization: Option<String>,
     compiler_version: Option<String>,
+    toolchain: Option<String>,
     instructions: i64,
     memory_peak_kb: Option<i64>,
     memory_rss_kb: Option<i64>,
@@ -398,15 +945,28 @@ struct SaveRunRequest {
     guest_mmap_peak: Option<i64>,
     guest_heap_bytes: Option<i64>,
     limit_reached: bool,
+    limit_reached_reason: Option<String>,
     exit_code: Option<i32>,
     execution_time_ms: Option<i64>,
     instruction_limit: Option<i64>,
+    memory_limit_mb: Option<i32>,
+    sandbox_image: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    core_dump_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<String>,
     syscalls: Option<i64>,
     syscall_breakdown: Option<serde_json::Value>,
+    thread_breakdown: Option<serde_json::Value>,
     stdout: Option<String>,
     stderr: Option<String>,
     started_at: Option<DateTime<Utc>>,
     completed_at: Option<DateTime<Utc>>,
+    user_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    visibility: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -414,24 +974,29 @@ struct BinaryMetadata {
     language: Option<String>,
     optimization: Option<String>,
     compiler_version: Option<String>,
+    #[serde(default)]
+    toolchain: Option<String>,
+    /// Set for script bundles; picks the sandbox image
+    #[serde(default)]
+    runtime: Option<RuntimeManifest>,
 }
 
-async fn persist_run(
-    http_client: &reqwest::Client,
-    api_url: &str,
+fn run_request(
     job: &Job,
     binary_size: usize,
     metadata: Option<&BinaryMetadata>,
     result: &ExecutionResult,
-) -> Result<(), String> {
-    let req = SaveRunRequest {
+) -> SaveRunRequest {
+    SaveRunRequest {
         job_id: job.id,
         benchmark_id: job.benchmark_id.clone(),
+        benchmark_version: job.benchmark_version,
         binary_id: job.binary_id.clone(),
         binary_size: Some(binary_size as i64),
         language: metadata.and_then(|m| m.language.clone()),
         optimization: metadata.and_then(|m| m.optimization.clone()),
         compiler_version: metadata.and_then(|m| m.compiler_version.clone()),
+        toolchain: metadata.and_then(|m| m.toolchain.clone()),
         instructions: result.instructions as i64,
         memory_peak_kb: Some(result.memory_peak_kb as i64),
         memory_rss_kb: Some(result.memory_rss_kb as i64),
@@ -447,49 +1012,347 @@ async fn persist_run(
         exit_code: Some(result.exit_code),
         execution_time_ms: Some(result.execution_time_ms as i64),
         instruction_limit: Some(job.instruction_limit as i64),
+        memory_limit_mb: result.memory_limit_mb.map(|m| m as i32),
+        sandbox_image: result.sandbox_image.clone(),
+        args: job.args.clone(),
+        core_dump_id: result.core_dump.as_ref().and_then(|c| c.binary_id.clone()),
+        mode: (!job.mode.is_normal()).then(|| job.mode.as_str().to_string()),
         syscalls: Some(result.syscalls as i64),
         syscall_breakdown: Some(serde_json::to_value(&result.syscall_breakdown).unwrap_or_default()),
+        thread_breakdown: Some(serde_json::to_value(&result.threads).unwrap_or_default()),
+        limit_reached_reason: result.limit_reached_reason.map(|r| r.as_str().to_string()),
         stdout: Some(result.stdout.clone()),
         stderr: Some(result.stderr.clone()),
         started_at: None, // Could track this if needed
         completed_at: Some(Utc::now()),
-    };
+        user_id: job.user_id,
+        visibility: job.visibility.clone(),
+    }
+}
+
+enum PersistError {
+    /// API or Postgres unavailable; worth spooling and retrying
+    Retryable(String),
+    /// The API rejected the payload; retrying will not help
+    Rejected(String),
+}
 
+impl std::fmt::Display for PersistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistError::Retryable(e) | PersistError::Rejected(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+async fn persist_run(
+    http_client: &reqwest::Client,
+    api_url: &str,
+    req: &SaveRunRequest,
+) -> Result<(), PersistError> {
     let response = http_client
         .post(&format!("{}/runs", api_url))
-        .json(&req)
+        .json(req)
         .timeout(Duration::from_secs(30))
         .send()
         .await
-        .map_err(|e| format!("Failed to send persist request: {}", e))?;
+        .map_err(|e| PersistError::Retryable(format!("Failed to send persist request: {}", e)))?;
 
     if !response.status().is_success() {
         let status = response.status();
         let body = response.text().await.unwrap_or_default();
-        return Err(format!("Failed to persist run: HTTP {} - {}", status, body));
+        let message = format!("Failed to persist run: HTTP {} - {}", status, body);
+        return Err(if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            PersistError::Retryable(message)
+        } else {
+            PersistError::Rejected(message)
+        });
     }
 
     Ok(())
 }
 
+/// Park a run in the NATS spool so it survives until the API is back.
+async fn spool_run(jetstream: &jetstream::Context, req: &SaveRunRequest) -> Result<(), String> {
+    let payload = serde_json::to_vec(req).map_err(|e| format!("Failed to serialize run: {}", e))?;
+    let publish = jetstream::context::Publish::build()
+        .payload(payload.into())
+        .message_id(req.job_id.to_string());
+
+    jetstream
+        .send_publish(RUNS_SPOOL_SUBJECT, publish)
+        .await
+        .map_err(|e| format!("Failed to spool run: {}", e))?
+        .await
+        .map_err(|e| format!("Failed to confirm spooled run: {}", e))?;
+
+    Ok(())
+}
+
+/// Replay spooled runs against the API, backing off while it is still down.
+async fn flush_run_spool(consumer: PullConsumer, http_client: reqwest::Client, api_url: String) {
+    let mut delay = SPOOL_RETRY_BASE_DELAY;
+
+    loop {
+        let mut messages = match consumer.fetch().max_messages(10).messages().await {
+            Ok(m) => m,
+            Err(e) => {
+                error!("Failed to fetch spooled runs: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        let mut outage = false;
+        while let Some(Ok(msg)) = messages.next().await {
+            let req: SaveRunRequest = match serde_json::from_slice(&msg.payload) {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Dropping unreadable spooled run: {}", e);
+                    let _ = msg.ack_with(AckKind::Term).await;
+                    continue;
+                }
+            };
+
+            match persist_run(&http_client, &api_url, &req).await {
+                Ok(()) => {
+                    info!(job_id = %req.job_id, "Spooled run persisted");
+                    delay = SPOOL_RETRY_BASE_DELAY;
+                    let _ = msg.ack().await;
+                }
+                Err(PersistError::Rejected(e)) => {
+                    error!(job_id = %req.job_id, error = %e, "Dropping spooled run rejected by API");
+                    let _ = msg.ack_with(AckKind::Term).await;
+                }
+                Err(PersistError::Retryable(e)) => {
+                    warn!(job_id = %req.job_id, error = %e, retry_in = ?delay, "API still unavailable, keeping run spooled");
+                    let _ = msg.ack_with(AckKind::Nak(Some(delay))).await;
+                    outage = true;
+                }
+            }
+        }
+
+        if outage {
+            delay = (delay * 2).min(SPOOL_RETRY_MAX_DELAY);
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// What `POST /workers/register` hands an external worker
+#[derive(Debug, Deserialize)]
+struct Registration {
+    worker_id: Uuid,
+    name: String,
+    nats_url: Option<String>,
+    heartbeat_seconds: u64,
+    /// User JWT for our nkey, scoped to the shared consumer; short-lived
+    nats_jwt: String,
+    inbox_prefix: String,
+}
+
+/// An external worker's NATS identity: the nkey is made at startup and
+/// never leaves the process, the JWT is whichever the last registration
+/// issued for it. Every (re)connect authenticates with the current pair.
+struct NatsIdentity {
+    key: KeyPair,
+    jwt: std::sync::RwLock<String>,
+}
+
+impl NatsIdentity {
+    fn connect_options(self: &Arc<Self>) -> async_nats::ConnectOptions {
+        let identity = self.clone();
+        async_nats::ConnectOptions::with_auth_callback(move |nonce| {
+            let identity = identity.clone();
+            async move {
+                let mut auth = async_nats::Auth::new();
+                auth.jwt = Some(identity.jwt.read().unwrap_or_else(|e| e.into_inner()).clone());
+                auth.signature = Some(identity.key.sign(&nonce).map_err(async_nats::AuthError::new)?);
+                Ok(auth)
+            }
+        })
+    }
+}
+
+/// Register with the API as an external worker, retrying while it's
+/// unreachable; a refused token or capability ends the process
+async fn register(
+    http_client: &reqwest::Client,
+    api_url: &str,
+    isolation: &str,
+    cache: Option<&BinaryCache>,
+    identity: &NatsIdentity,
+) -> Registration {
+    let body = serde_json::json!({
+        "arch": std::env::consts::ARCH,
+        "isolation": isolation,
+        "version": env!("CARGO_PKG_VERSION"),
+        "binary_cache": cache.map(BinaryCache::stats),
+        "nats_public_key": identity.key.public_key(),
+    });
+
+    loop {
+        let response = http_client
+            .post(format!("{}/workers/register", api_url))
+            .json(&body)
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await;
+
+        match response {
+            Ok(resp) if resp.status().is_success() => match resp.json::<Registration>().await {
+                Ok(registration) => {
+                    *identity.jwt.write().unwrap_or_else(|e| e.into_inner()) = registration.nats_jwt.clone();
+                    return registration;
+                }
+                Err(e) => error!("Unreadable registration response: {}", e),
+            },
+            Ok(resp) if resp.status().is_client_error() => {
+                let status = resp.status();
+                let body = resp.text().await.unwrap_or_default();
+                error!("Registration refused: HTTP {} - {}", status, body);
+                std::process::exit(1);
+            }
+            Ok(resp) => error!("Registration failed: HTTP {}. Retrying in 5s...", resp.status()),
+            Err(e) => error!("Failed to reach API to register: {}. Retrying in 5s...", e),
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// Re-register every `heartbeat_seconds`, which is also how a revoked
+/// worker finds out and how its NATS credentials are renewed; each one
+/// carries the binary cache's stats
+async fn heartbeat(
+    http_client: reqwest::Client,
+    api_url: String,
+    isolation: String,
+    cache: Option<Arc<BinaryCache>>,
+    identity: Arc<NatsIdentity>,
+    every: Duration,
+) {
+    let mut ticker = tokio::time::interval(every);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        register(&http_client, &api_url, &isolation, cache.as_deref(), &identity).await;
+    }
+}
+
+/// The `SHARED_JOBS` stream, for external workers, which can't create it
+async fn shared_jobs_stream(jetstream: &jetstream::Context) -> jetstream::stream::Stream {
+    loop {
+        match jetstream.get_stream(SHARED_JOBS_STREAM).await {
+            Ok(stream) => return stream,
+            Err(e) => error!("Failed to get {} stream: {}. Retrying in 5s...", SHARED_JOBS_STREAM, e),
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// A consumer in-house workers created; external workers may only use it
+async fn existing_consumer(stream: &jetstream::stream::Stream, name: &str) -> PullConsumer {
+    loop {
+        match stream.get_consumer(name).await {
+            Ok(consumer) => return consumer,
+            Err(e) => error!("Consumer {} not available yet: {}. Retrying in 5s...", name, e),
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// The durable consumer execute workers share on `stream`
+async fn job_consumer(stream: &jetstream::stream::Stream, name: &str, config: &Config) -> PullConsumer {
+    stream
+        .get_or_create_consumer(
+            name,
+            jetstream::consumer::pull::Config {
+                durable_name: Some(name.to_string()),
+                ack_policy: jetstream::consumer::AckPolicy::Explicit,
+                max_deliver: 3,
+                ack_wait: Duration::from_secs(config.timeout_sec + 30),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Failed to create consumer")
+}
+
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("ctf_worker=info".parse().unwrap()),
-        )
-        .init();
+    // Initialize tracing (LOG_FORMAT=json for structured output); secrets in
+    // the worker's own env never reach the logs
+    let redactor = redact::Redactor::from_process_env();
+    logging::init("ctf_worker=info", redact::RedactingWriter::new(redactor.clone()));
+
+    let mut config = Config::from_env();
+
+    // HTTP client for fetching binaries from API; external workers send
+    // their token with every request
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(token) = &config.worker_token {
+        let mut value = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+            .expect("WORKER_TOKEN is not a valid header value");
+        value.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, value);
+    }
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .default_headers(headers)
+        .build()
+        .expect("Failed to create HTTP client");
 
-    let config = Config::from_env();
+    let binary_cache = config.binary_cache_dir.as_ref().and_then(|dir| {
+        match BinaryCache::open(dir.into(), config.binary_cache_max_mb) {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(e) => {
+                warn!(dir = %dir, error = %e, "Failed to open binary cache, fetching every binary");
+                None
+            }
+        }
+    });
+
+    // External workers only take shared jobs and only reach the API through
+    // its /workers routes
+    let external = config.worker_token.is_some();
+    let mut identity = None;
+    let mut inbox_prefix = None;
+    if external {
+        let nats_identity = Arc::new(NatsIdentity {
+            key: KeyPair::new_user(),
+            jwt: std::sync::RwLock::new(String::new()),
+        });
+        let registration =
+            register(&http_client, &config.api_url, &config.worker_isolation, binary_cache.as_deref(), &nats_identity).await;
+        info!(worker_id = %registration.worker_id, name = %registration.name, "Registered as external worker");
+        tokio::spawn(heartbeat(
+            http_client.clone(),
+            config.api_url.clone(),
+            config.worker_isolation.clone(),
+            binary_cache.clone(),
+            nats_identity.clone(),
+            Duration::from_secs(registration.heartbeat_seconds.max(10)),
+        ));
+        if let Some(nats_url) = registration.nats_url {
+            config.nats_url = nats_url;
+        }
+        config.api_url = format!("{}/workers", config.api_url);
+        identity = Some(nats_identity);
+        inbox_prefix = Some(registration.inbox_prefix);
+    }
 
     info!("Starting CTF Worker (NATS: {}, sandbox: {})", config.nats_url, config.sandbox_image);
 
     // Connect to NATS with longer request timeout for large binary operations
     let client = loop {
-        let nats_options = async_nats::ConnectOptions::new()
-            .request_timeout(Some(Duration::from_secs(120)));
+        let mut nats_options = match &identity {
+            Some(identity) => identity.connect_options(),
+            None => async_nats::ConnectOptions::new(),
+        };
+        if let Some(prefix) = &inbox_prefix {
+            nats_options = nats_options.custom_inbox_prefix(prefix);
+        }
+        let nats_options = nats_options.request_timeout(Some(Duration::from_secs(120)));
 
         match nats_options.connect(&config.nats_url).await {
             Ok(c) => {
@@ -505,69 +1368,77 @@ async fn main() {
 
     let jetstream = jetstream::new(client);
 
-    // Get or create stream
-    let stream = jetstream
-        .get_or_create_stream(jetstream::stream::Config {
-            name: JOBS_STREAM.to_string(),
-            subjects: vec!["jobs.submit".to_string()],
-            retention: jetstream::stream::RetentionPolicy::WorkQueue,
-            max_age: Duration::from_secs(config.job_ttl_seconds),
-            storage: jetstream::stream::StorageType::File,
-            ..Default::default()
-        })
-        .await
-        .expect("Failed to create/get JOBS stream");
-
-    // Get or create KV buckets
-    let jobs_kv = jetstream
-        .create_key_value(jetstream::kv::Config {
-            bucket: JOBS_KV.to_string(),
-            max_age: Duration::from_secs(config.job_ttl_seconds),
-            storage: jetstream::stream::StorageType::File,
-            ..Default::default()
-        })
-        .await
-        .expect("Failed to create jobs KV");
-
-    let results_kv = jetstream
-        .create_key_value(jetstream::kv::Config {
-            bucket: RESULTS_KV.to_string(),
-            max_age: Duration::from_secs(config.job_ttl_seconds),
-            storage: jetstream::stream::StorageType::File,
-            ..Default::default()
-        })
+    // External workers may only read the shared stream and use its existing
+    // consumer; in-house workers create or verify the whole topology
+    let (reporter, queues) = if external {
+        let reporter = JobReporter::Api {
+            http_client: http_client.clone(),
+            api_url: config.api_url.clone(),
+        };
+        (reporter, vec![(shared_jobs_stream(&jetstream).await, SHARED_WORKER_CONSUMER)])
+    } else {
+        let topology = Topology::ensure(
+            &jetstream,
+            &TopologyConfig::new(config.job_ttl_seconds, config.binary_ttl_seconds),
+        )
         .await
-        .expect("Failed to create results KV");
+        .expect("Failed to ensure NATS topology");
+
+        // Replay runs spooled during API/Postgres outages (shared by all
+        // in-house workers). External workers neither spool nor replay: a run
+        // they spooled would reach the internal /runs route as verified.
+        let spool_consumer: PullConsumer = topology
+            .runs_spool_stream
+            .get_or_create_consumer(
+                RUNS_SPOOL_CONSUMER,
+                jetstream::consumer::pull::Config {
+                    durable_name: Some(RUNS_SPOOL_CONSUMER.to_string()),
+                    ack_policy: jetstream::consumer::AckPolicy::Explicit,
+                    ack_wait: Duration::from_secs(60),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to create run spool consumer");
+        tokio::spawn(flush_run_spool(spool_consumer, http_client.clone(), config.api_url.clone()));
+
+        let reporter = JobReporter::Kv {
+            jobs: Box::new(topology.jobs_kv),
+            results: Box::new(topology.results_kv),
+        };
+        // In-house workers take turns between all jobs and the shared ones
+        let queues = vec![
+            (topology.jobs_stream, WORKER_CONSUMER),
+            (topology.shared_jobs_stream, SHARED_WORKER_CONSUMER),
+        ];
+        (reporter, queues)
+    };
 
-    // HTTP client for fetching binaries from API
-    let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(120))
-        .build()
-        .expect("Failed to create HTTP client");
+    let mut consumers = Vec::new();
+    for (stream, name) in &queues {
+        consumers.push(if external {
+            existing_consumer(stream, name).await
+        } else {
+            job_consumer(stream, name, &config).await
+        });
+    }
 
-    // Create durable consumer
-    let consumer: PullConsumer = stream
-        .get_or_create_consumer(
-            "worker",
-            jetstream::consumer::pull::Config {
-                durable_name: Some("worker".to_string()),
-                ack_policy: jetstream::consumer::AckPolicy::Explicit,
-                max_deliver: 3,
-                ack_wait: Duration::from_secs(config.timeout_sec + 30),
-                ..Default::default()
-            },
-        )
-        .await
-        .expect("Failed to create consumer");
+    // Warm the binary cache with the next jobs' binaries while one runs;
+    // external workers' credentials can't read undelivered messages
+    let prefetcher = binary_cache.clone().filter(|_| config.binary_prefetch_depth > 0 && !external).map(|cache| {
+        prefetch::spawn(queues, http_client.clone(), config.api_url.clone(), cache, config.binary_prefetch_depth)
+    });
 
     info!("Worker ready, waiting for jobs...");
 
     // Process messages
-    loop {
+    for consumer in consumers.iter().cycle() {
         let mut messages = match consumer.fetch().max_messages(1).messages().await {
             Ok(m) => m,
             Err(e) => {
-                error!("Failed to fetch messages: {}", e);
+                if let Some(suppressed) = FETCH_ERRORS.sample() {
+                    error!(suppressed, "Failed to fetch messages: {}", e);
+                }
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 continue;
             }
@@ -577,7 +1448,9 @@ async fn main() {
             let msg = match msg_result {
                 Ok(m) => m,
                 Err(e) => {
-                    error!("Failed to receive message: {}", e);
+                    if let Some(suppressed) = RECEIVE_ERRORS.sample() {
+                        error!(suppressed, "Failed to receive message: {}", e);
+                    }
                     continue;
                 }
             };
@@ -592,104 +1465,114 @@ async fn main() {
             };
 
             info!(job_id = %job.id, instruction_limit = job.instruction_limit, binary_id = %job.binary_id, "Processing job");
-
-            // Fetch binary from API
-            let binary = match http_client
-                .get(&format!("{}/binaries/{}", config.api_url, job.binary_id))
-                .timeout(Duration::from_secs(60))
-                .send()
-                .await
-            {
-                Ok(resp) if resp.status().is_success() => {
-                    match resp.bytes().await {
-                        Ok(b) => b.to_vec(),
-                        Err(e) => {
-                            error!("Failed to read binary response: {}", e);
-                            let _ = update_job_status(&jobs_kv, &job.id, JobStatus::Failed, Some(format!("Failed to fetch binary: {}", e))).await;
-                            let _ = msg.ack().await;
-                            continue;
-                        }
+            if let Some(prefetcher) = &prefetcher {
+                prefetcher.notify_one();
+            }
+            let job_redactor = redactor.with_env(&job.env_vars, job.challenge_id.is_some());
+
+            // Everything logged for this job carries its job_id and user_id
+            let span = info_span!("job", job_id = %job.id, user_id = job.user_id.as_ref().map(tracing::field::display));
+            async {
+                // Fetch binary from the local cache or the API
+                let binary = match fetch_binary(&http_client, &config.api_url, binary_cache.as_deref(), &job.binary_id).await {
+                    Ok(binary) => binary,
+                    Err(FetchError::NotFound(status)) => {
+                        error!("Binary not found: HTTP {}", status);
+                        let _ = reporter.update_status(&job.id, JobStatus::Failed, Some(format!("Binary not found: {}", job.binary_id))).await;
+                        let _ = msg.ack().await;
+                        return;
                     }
-                }
-                Ok(resp) => {
-                    error!("Binary not found: HTTP {}", resp.status());
-                    let _ = update_job_status(&jobs_kv, &job.id, JobStatus::Failed, Some(format!("Binary not found: {}", job.binary_id))).await;
-                    let _ = msg.ack().await;
-                    continue;
-                }
-                Err(e) => {
-                    error!("Failed to fetch binary: {}", e);
-                    let _ = update_job_status(&jobs_kv, &job.id, JobStatus::Failed, Some(format!("Failed to fetch binary: {}", e))).await;
-                    let _ = msg.ack().await;
-                    continue;
-                }
-            };
+                    Err(FetchError::Failed(e)) => {
+                        error!("Failed to fetch binary: {}", e);
+                        let _ = reporter.update_status(&job.id, JobStatus::Failed, Some(format!("Failed to fetch binary: {}", e))).await;
+                        let _ = msg.ack().await;
+                        return;
+                    }
+                };
+
+                info!(job_id = %job.id, binary_size = binary.len(), "Binary fetched");
+
+                // Fetch binary metadata
+                let metadata: Option<BinaryMetadata> = match http_client
+                    .get(format!("{}/binaries/{}/metadata", config.api_url, job.binary_id))
+                    .timeout(Duration::from_secs(10))
+                    .send()
+                    .await
+                {
+                    Ok(resp) if resp.status().is_success() => {
+                        resp.json().await.ok()
+                    }
+                    _ => None
+                };
 
-            info!(job_id = %job.id, binary_size = binary.len(), "Binary fetched");
-
-            // Fetch binary metadata
-            let metadata: Option<BinaryMetadata> = match http_client
-                .get(&format!("{}/binaries/{}/metadata", config.api_url, job.binary_id))
-                .timeout(Duration::from_secs(10))
-                .send()
-                .await
-            {
-                Ok(resp) if resp.status().is_success() => {
-                    resp.json().await.ok()
+                if let Some(ref m) = metadata {
+                    info!(job_id = %job.id, language = ?m.language, optimization = ?m.optimization, "Binary metadata fetched");
                 }
-                _ => None
-            };
 
-            if let Some(ref m) = metadata {
-                info!(job_id = %job.id, language = ?m.language, optimization = ?m.optimization, "Binary metadata fetched");
-            }
+                // Update status to running
+                if let Err(e) = reporter.update_status(&job.id, JobStatus::Running, None).await {
+                    error!("Failed to update job status: {}", e);
+                }
 
-            // Update status to running
-            if let Err(e) = update_job_status(&jobs_kv, &job.id, JobStatus::Running, None).await {
-                error!("Failed to update job status: {}", e);
-            }
+                // Execute the sandbox
+                match execute_sandbox(&job, &binary, metadata.as_ref().and_then(|m| m.runtime.as_ref()), &config, &job_redactor).await {
+                    Ok((mut result, collected)) => {
+                        store_outputs(&http_client, &config, &mut result, collected).await;
+                        info!(
+                            job_id = %job.id,
+                            instructions = result.instructions,
+                            exit_code = result.exit_code,
+                            time_ms = result.execution_time_ms,
+                            "Job completed"
+                        );
+
+                        // Store result in NATS KV (for fast access), through the API for external workers
+                        if let Err(e) = reporter.store_result(&job.id, &result).await {
+                            error!("Failed to store result: {}", e);
+                        }
 
-            // Execute the sandbox
-            match execute_sandbox(&job, &binary, &config).await {
-                Ok(result) => {
-                    info!(
-                        job_id = %job.id,
-                        instructions = result.instructions,
-                        exit_code = result.exit_code,
-                        time_ms = result.execution_time_ms,
-                        "Job completed"
-                    );
-
-                    // Store result in NATS KV (for fast access)
-                    if let Err(e) = store_job_result(&results_kv, &job.id, &result).await {
-                        error!("Failed to store result: {}", e);
-                    }
+                        // Persist run to PostgreSQL (permanent storage)
+                        let run = run_request(&job, binary.len(), metadata.as_ref(), &result);
+                        match persist_run(&http_client, &config.api_url, &run).await {
+                            Ok(()) => {}
+                            Err(PersistError::Retryable(e)) if external => {
+                                error!(job_id = %job.id, error = %e, "Run not reported: API unavailable");
+                            }
+                            Err(PersistError::Retryable(e)) => {
+                                // Don't fail the job - NATS KV has the result and the spool replays the run
+                                warn!(job_id = %job.id, error = %e, "API unavailable, spooling run");
+                                if let Err(e) = spool_run(&jetstream, &run).await {
+                                    error!(job_id = %job.id, error = %e, "Run lost: persist and spool both failed");
+                                }
+                            }
+                            Err(PersistError::Rejected(e)) => {
+                                error!("Failed to persist run to database: {}", e);
+                            }
+                        }
 
-                    // Persist run to PostgreSQL (permanent storage)
-                    if let Err(e) = persist_run(&http_client, &config.api_url, &job, binary.len(), metadata.as_ref(), &result).await {
-                        error!("Failed to persist run to database: {}", e);
-                        // Don't fail the job - NATS KV still has the result
+                        // Update status to completed
+                        if let Err(e) = reporter.update_status(&job.id, JobStatus::Completed, None).await {
+                            error!("Failed to update job status: {}", e);
+                        }
                     }
+                    Err(e) => {
+                        let e = job_redactor.scrub(&e);
+                        error!(job_id = %job.id, error = %e, "Job failed");
 
-                    // Update status to completed
-                    if let Err(e) = update_job_status(&jobs_kv, &job.id, JobStatus::Completed, None).await {
-                        error!("Failed to update job status: {}", e);
+                        // Update status to failed
+                        if let Err(e2) = reporter.update_status(&job.id, JobStatus::Failed, Some(e)).await {
+                            error!("Failed to update job status: {}", e2);
+                        }
                     }
                 }
-                Err(e) => {
-                    error!(job_id = %job.id, error = %e, "Job failed");
 
-                    // Update status to failed
-                    if let Err(e2) = update_job_status(&jobs_kv, &job.id, JobStatus::Failed, Some(e)).await {
-                        error!("Failed to update job status: {}", e2);
-                    }
+                // Acknowledge the message
+                if let Err(e) = msg.ack().await {
+                    error!("Failed to ack message: {}", e);
                 }
             }
-
-            // Acknowledge the message
-            if let Err(e) = msg.ack().await {
-                error!("Failed to ack message: {}", e);
-            }
+            .instrument(span)
+            .await;
         }
 
         // Small delay before next fetch
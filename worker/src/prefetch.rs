@@ -0,0 +1,87 @@
+//! Fetching the binaries of the next jobs in line while one runs.
+//!
+//! Whenever the worker starts a job it wakes the prefetcher, which reads the
+//! first `BINARY_PREFETCH_DEPTH` messages each of its consumers hasn't handed
+//! out yet, by stream sequence so nothing is taken off the queue, and puts
+//! their binaries in the binary cache. Workers share their consumers, so they
+//! all warm the same head of the queue; whichever one takes a job then finds
+//! its binary on disk. It needs `BINARY_CACHE_DIR`, and a failed peek or
+//! fetch is only logged: the job fetches its binary itself as before.
+
+use crate::binary_cache::BinaryCache;
+use crate::{download_binary, FetchError};
+use async_nats::jetstream::stream::Stream;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::debug;
+
+/// The part of a queued job the prefetcher reads
+#[derive(Deserialize)]
+struct Queued {
+    binary_id: String,
+}
+
+/// Start the prefetcher on `queues` (stream and consumer name); notify the
+/// returned handle when a job starts
+pub fn spawn(
+    queues: Vec<(Stream, &'static str)>,
+    http_client: reqwest::Client,
+    api_url: String,
+    cache: Arc<BinaryCache>,
+    depth: u64,
+) -> Arc<Notify> {
+    let wake = Arc::new(Notify::new());
+    let notified = wake.clone();
+    tokio::spawn(async move {
+        loop {
+            notified.notified().await;
+            for (stream, consumer) in &queues {
+                prefetch(stream, consumer, &http_client, &api_url, &cache, depth).await;
+            }
+        }
+    });
+    wake
+}
+
+async fn prefetch(
+    stream: &Stream,
+    consumer: &str,
+    http_client: &reqwest::Client,
+    api_url: &str,
+    cache: &BinaryCache,
+    depth: u64,
+) {
+    let info = match stream.consumer_info(consumer).await {
+        Ok(info) => info,
+        Err(e) => {
+            debug!(consumer, error = %e, "Prefetch couldn't read consumer info");
+            return;
+        }
+    };
+
+    let next = info.delivered.stream_sequence + 1;
+    for sequence in next..next + depth.min(info.num_pending) {
+        // Gone if another worker took and finished it meanwhile
+        let Ok(message) = stream.get_raw_message(sequence).await else {
+            continue;
+        };
+        let Ok(Queued { binary_id }) = serde_json::from_slice(&message.payload) else {
+            continue;
+        };
+        if !BinaryCache::accepts(&binary_id) || cache.contains(&binary_id) {
+            continue;
+        }
+
+        match download_binary(http_client, api_url, &binary_id).await {
+            Ok(binary) => {
+                if cache.put(&binary_id, &binary).await {
+                    cache.count_prefetch();
+                    debug!(binary_id, binary_size = binary.len(), "Binary prefetched");
+                }
+            }
+            Err(FetchError::NotFound(status)) => debug!(binary_id, %status, "Prefetch: binary not found"),
+            Err(FetchError::Failed(e)) => debug!(binary_id, error = %e, "Prefetch failed"),
+        }
+    }
+}